@@ -2,7 +2,9 @@
 
 use std::collections::BTreeMap;
 
-use engine_traits::{CompactExt, CompactedEvent, ManualCompactionOptions, Result};
+use engine_traits::{
+    CompactExt, CompactFilesInRangeOptions, CompactedEvent, ManualCompactionOptions, Result,
+};
 
 use crate::engine::PanicEngine;
 
@@ -23,12 +25,13 @@ impl CompactExt for PanicEngine {
         panic!()
     }
 
-    fn compact_files_in_range_cf(
+    fn compact_files_in_range_cf_opt(
         &self,
         cf: &str,
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         output_level: Option<i32>,
+        options: CompactFilesInRangeOptions,
     ) -> Result<()> {
         panic!()
     }
@@ -64,6 +67,14 @@ impl CompactedEvent for PanicCompactedEvent {
         panic!()
     }
 
+    fn start_key(&self) -> &[u8] {
+        panic!()
+    }
+
+    fn end_key(&self) -> &[u8] {
+        panic!()
+    }
+
     fn calc_ranges_declined_bytes(
         self,
         ranges: &BTreeMap<Vec<u8>, u64>,
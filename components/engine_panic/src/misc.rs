@@ -138,6 +138,18 @@ impl MiscExt for PanicEngine {
         panic!()
     }
 
+    fn get_accumulated_delete_sst_bytes() -> Result<u64> {
+        panic!()
+    }
+
+    fn get_accumulated_delete_tombstones() -> Result<u64> {
+        panic!()
+    }
+
+    fn get_accumulated_compaction_flow() -> Result<(u64, u64)> {
+        panic!()
+    }
+
     type DiskEngine = PanicEngine;
     fn get_disk_engine(&self) -> &Self::DiskEngine {
         panic!()
@@ -2,11 +2,83 @@
 
 use std::cmp;
 
-use engine_traits::{CfNamesExt, CompactExt, ManualCompactionOptions, Result};
+use engine_traits::{
+    CfNamesExt, CompactExt, CompactFilesInRangeOptions, ManualCompactionOptions, Result,
+};
 use rocksdb::{CompactOptions, CompactionOptions, DBBottommostLevelCompaction, DBCompressionType};
+use tikv_util::warn;
 
 use crate::{engine::RocksEngine, r2e, util};
 
+/// Bookkeeping of the write rate limits requested by manual compactions that
+/// are currently running with `write_rate_limit_bytes_per_sec` set.
+///
+/// The rocksdb binding does not expose a per-compaction rate limit, so the
+/// limit is enforced by temporarily lowering the rate limiter shared by the
+/// whole DB to the smallest limit requested by any running limited manual
+/// compaction, and restoring the previous rate once the last one finishes.
+/// The granularity is therefore the whole DB: background compactions (and
+/// flushes, depending on the rate limiter mode) are throttled as well while a
+/// limited manual compaction is running. The rate is never raised above the
+/// configured global one.
+#[derive(Debug, Default)]
+pub(crate) struct ManualCompactionLimits {
+    // The limits of the running limited manual compactions.
+    active: Vec<i64>,
+    // The global rate in bytes per second before the first scoped limit was
+    // applied.
+    saved: Option<i64>,
+}
+
+/// Applies a scoped write rate limit for one manual compaction, see
+/// [`ManualCompactionLimits`]. Dropping the guard restores the previous rate.
+struct ScopedCompactionRateLimit<'a> {
+    engine: &'a RocksEngine,
+    limit: i64,
+}
+
+impl<'a> ScopedCompactionRateLimit<'a> {
+    fn new(engine: &'a RocksEngine, limit: i64) -> Option<ScopedCompactionRateLimit<'a>> {
+        let mut limits = engine.manual_compaction_limits().lock().unwrap();
+        let db_opts = engine.as_inner().get_db_options();
+        let Some(limiter) = db_opts.get_rate_limiter() else {
+            // Without a configured rate limiter there is nothing to scope.
+            warn!("ignoring manual compaction rate limit, no db rate limiter is configured");
+            return None;
+        };
+        if limits.saved.is_none() {
+            limits.saved = Some(limiter.get_bytes_per_second());
+        }
+        limits.active.push(limit);
+        let effective = cmp::min(
+            limits.active.iter().copied().min().unwrap(),
+            limits.saved.unwrap(),
+        );
+        limiter.set_bytes_per_second(effective);
+        Some(ScopedCompactionRateLimit { engine, limit })
+    }
+}
+
+impl<'a> Drop for ScopedCompactionRateLimit<'a> {
+    fn drop(&mut self) {
+        let mut limits = self.engine.manual_compaction_limits().lock().unwrap();
+        let db_opts = self.engine.as_inner().get_db_options();
+        let Some(limiter) = db_opts.get_rate_limiter() else {
+            return;
+        };
+        let pos = limits.active.iter().position(|l| *l == self.limit).unwrap();
+        limits.active.swap_remove(pos);
+        let saved = limits.saved.unwrap();
+        match limits.active.iter().copied().min() {
+            Some(min) => limiter.set_bytes_per_second(cmp::min(min, saved)),
+            None => {
+                limiter.set_bytes_per_second(saved);
+                limits.saved = None;
+            }
+        }
+    }
+}
+
 impl CompactExt for RocksEngine {
     type CompactedEvent = crate::compact_listener::RocksCompactedEvent;
 
@@ -31,6 +103,9 @@ impl CompactExt for RocksEngine {
         end_key: Option<&[u8]>,
         option: ManualCompactionOptions,
     ) -> Result<()> {
+        let _rate_limit_guard = option
+            .write_rate_limit_bytes_per_sec
+            .and_then(|limit| ScopedCompactionRateLimit::new(self, limit as i64));
         let db = self.as_inner();
         let handle = util::get_cf_handle(db, cf)?;
         let mut compact_opts = CompactOptions::new();
@@ -45,24 +120,35 @@ impl CompactExt for RocksEngine {
         Ok(())
     }
 
-    fn compact_files_in_range_cf(
+    fn compact_files_in_range_cf_opt(
         &self,
         cf: &str,
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         output_level: Option<i32>,
+        options: CompactFilesInRangeOptions,
     ) -> Result<()> {
         let db = self.as_inner();
         let handle = util::get_cf_handle(db, cf)?;
         let cf_opts = db.get_options_cf(handle);
-        let output_level = output_level.unwrap_or(cf_opts.get_num_levels() as i32 - 1);
+        let bottommost_level = cf_opts.get_num_levels() as i32 - 1;
+        let output_level = if options.bottommost_only {
+            bottommost_level
+        } else {
+            output_level.unwrap_or(bottommost_level)
+        };
+        let include_output_level = options.include_output_level || options.bottommost_only;
 
         let mut input_files = Vec::new();
+        let mut files_at_output_level = 0;
         let cf_meta = db.get_column_family_meta_data(handle);
         for (i, level) in cf_meta.get_levels().iter().enumerate() {
-            if i as i32 >= output_level {
+            if i as i32 > output_level || (i as i32 == output_level && !include_output_level) {
                 break;
             }
+            if options.bottommost_only && (i as i32) < output_level {
+                continue;
+            }
             for f in level.get_files() {
                 if end.is_some() && end.unwrap() <= f.get_smallestkey() {
                     continue;
@@ -70,18 +156,29 @@ impl CompactExt for RocksEngine {
                 if start.is_some() && start.unwrap() > f.get_largestkey() {
                     continue;
                 }
+                if i as i32 == output_level {
+                    files_at_output_level += 1;
+                }
                 input_files.push(f.get_name());
             }
         }
         if input_files.is_empty() {
             return Ok(());
         }
+        // Compacting a single output-level file with itself only rewrites the
+        // file without reclaiming anything, skip it.
+        if input_files.len() == 1 && files_at_output_level == 1 && !options.rewrite_single_file {
+            return Ok(());
+        }
 
+        let max_subcompactions = options
+            .max_subcompactions
+            .unwrap_or_else(|| cmp::min(num_cpus::get(), 32) as u32);
         self.compact_files_cf(
             cf,
             input_files,
             Some(output_level),
-            cmp::min(num_cpus::get(), 32) as u32,
+            max_subcompactions,
             false,
         )
     }
@@ -131,11 +228,78 @@ impl CompactExt for RocksEngine {
 
 #[cfg(test)]
 mod tests {
-    use engine_traits::{CfNamesExt, CfOptionsExt, CompactExt, MiscExt, SyncMutable};
+    use std::sync::Arc;
+
+    use engine_traits::{
+        CfNamesExt, CfOptionsExt, CompactExt, CompactFilesInRangeOptions, ManualCompactionOptions,
+        MiscExt, SyncMutable,
+    };
+    use rocksdb::{DBRateLimiterMode, RateLimiter};
     use tempfile::Builder;
 
+    use super::ScopedCompactionRateLimit;
     use crate::{util, RocksCfOptions, RocksDbOptions};
 
+    #[test]
+    fn test_manual_compaction_rate_limit() {
+        let temp_dir = Builder::new()
+            .prefix("test_manual_compaction_rate_limit")
+            .tempdir()
+            .unwrap();
+        let global_rate: i64 = 100 << 20;
+        let mut db_opts = RocksDbOptions::default();
+        db_opts.set_rate_limiter(&Arc::new(RateLimiter::new_writeampbased_with_auto_tuned(
+            global_rate,
+            100_000, // refill period: 100ms
+            10,      // fairness
+            DBRateLimiterMode::WriteOnly,
+            false, // auto tuned
+            1,     // tune per secs
+            300,   // window size
+            30,    // recent size
+        )));
+        let db = util::new_engine_opt(
+            temp_dir.path().to_str().unwrap(),
+            db_opts,
+            vec![("default", RocksCfOptions::default())],
+        )
+        .unwrap();
+        let rate = || {
+            db.as_inner()
+                .get_db_options()
+                .get_rate_limiter()
+                .unwrap()
+                .get_bytes_per_second()
+        };
+        assert_eq!(rate(), global_rate);
+
+        // With two concurrent scoped limits the smallest one wins, and
+        // dropping one guard must not clobber the other's limit.
+        let guard1 = ScopedCompactionRateLimit::new(&db, 1 << 20).unwrap();
+        assert_eq!(rate(), 1 << 20);
+        let guard2 = ScopedCompactionRateLimit::new(&db, 4 << 20).unwrap();
+        assert_eq!(rate(), 1 << 20);
+        drop(guard1);
+        assert_eq!(rate(), 4 << 20);
+        drop(guard2);
+        assert_eq!(rate(), global_rate);
+
+        // A limited manual compaction applies the limit only for its
+        // duration and restores the global rate afterwards.
+        for i in 0..5u8 {
+            db.put(&[i], &[i]).unwrap();
+        }
+        db.flush_cf("default", true).unwrap();
+        db.compact_range_cf(
+            "default",
+            None,
+            None,
+            ManualCompactionOptions::new(false, 1, false).write_rate_limit(2 << 20),
+        )
+        .unwrap();
+        assert_eq!(rate(), global_rate);
+    }
+
     #[test]
     fn test_compact_files_in_range() {
         let temp_dir = Builder::new()
@@ -211,4 +375,68 @@ mod tests {
             assert_eq!(level_n[0].get_largestkey(), &[4]);
         }
     }
+
+    #[test]
+    fn test_compact_files_in_range_include_output_level() {
+        let temp_dir = Builder::new()
+            .prefix("test_compact_files_in_range_include_output_level")
+            .tempdir()
+            .unwrap();
+
+        let mut cf_opts = RocksCfOptions::default();
+        cf_opts.set_disable_auto_compactions(true);
+        let cfs_opts = vec![("default", cf_opts)];
+        let db = util::new_engine_opt(
+            temp_dir.path().to_str().unwrap(),
+            RocksDbOptions::default(),
+            cfs_opts,
+        )
+        .unwrap();
+
+        for i in 0..5 {
+            db.put(&[i], &[i]).unwrap();
+            db.flush_cf("default", true).unwrap();
+        }
+        // Move everything to the bottommost level.
+        db.compact_files_in_range(None, None, None).unwrap();
+
+        let bottommost = db.get_options_cf("default").unwrap().get_num_levels() - 1;
+        let cf = util::get_cf_handle(db.as_inner(), "default").unwrap();
+        let cf_meta = db.as_inner().get_column_family_meta_data(cf);
+        assert_eq!(cf_meta.get_levels()[bottommost].get_files().len(), 1);
+
+        // All files are at the bottommost level already, so the default mode
+        // collects nothing, and the single bottommost file must not be
+        // compacted with itself.
+        db.compact_files_in_range(None, None, None).unwrap();
+        db.compact_files_in_range_cf_opt(
+            "default",
+            None,
+            None,
+            None,
+            CompactFilesInRangeOptions::bottommost_only(),
+        )
+        .unwrap();
+        let cf_meta = db.as_inner().get_column_family_meta_data(cf);
+        assert_eq!(cf_meta.get_levels()[bottommost].get_files().len(), 1);
+
+        // Add a new L0 file overlapping the bottommost one. With
+        // `include_output_level` both get rewritten into one output file.
+        db.put(&[2], &[42]).unwrap();
+        db.flush_cf("default", true).unwrap();
+        db.compact_files_in_range_cf_opt(
+            "default",
+            None,
+            None,
+            None,
+            CompactFilesInRangeOptions {
+                include_output_level: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let cf_meta = db.as_inner().get_column_family_meta_data(cf);
+        assert_eq!(cf_meta.get_levels()[0].get_files().len(), 0);
+        assert_eq!(cf_meta.get_levels()[bottommost].get_files().len(), 1);
+    }
 }
@@ -155,6 +155,14 @@ impl CompactedEvent for RocksCompactedEvent {
         self.output_level.to_string()
     }
 
+    fn start_key(&self) -> &[u8] {
+        &self.start_key
+    }
+
+    fn end_key(&self) -> &[u8] {
+        &self.end_key
+    }
+
     fn calc_ranges_declined_bytes(
         self,
         ranges: &BTreeMap<Vec<u8>, u64>,
@@ -1,6 +1,9 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::{any::Any, sync::Arc};
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+};
 
 use engine_traits::{
     IterOptions, Iterable, KvEngine, Peekable, ReadOptions, Result, SnapshotContext, SyncMutable,
@@ -8,8 +11,8 @@ use engine_traits::{
 use rocksdb::{DBIterator, Writable, DB};
 
 use crate::{
-    db_vector::RocksDbVector, options::RocksReadOptions, r2e, util::get_cf_handle,
-    RocksEngineIterator, RocksSnapshot,
+    compact::ManualCompactionLimits, db_vector::RocksDbVector, options::RocksReadOptions, r2e,
+    util::get_cf_handle, RocksEngineIterator, RocksSnapshot,
 };
 
 #[cfg(feature = "trace-lifetime")]
@@ -148,6 +151,9 @@ mod trace {
 pub struct RocksEngine {
     db: Arc<DB>,
     support_multi_batch_write: bool,
+    // Bookkeeping of write rate limits requested by running manual
+    // compactions, shared by all clones of the engine.
+    manual_compaction_limits: Arc<Mutex<ManualCompactionLimits>>,
     #[cfg(feature = "trace-lifetime")]
     _id: trace::TabletTraceId,
 }
@@ -157,6 +163,7 @@ impl RocksEngine {
         let db = Arc::new(db);
         RocksEngine {
             support_multi_batch_write: db.get_db_options().is_enable_multi_batch_write(),
+            manual_compaction_limits: Arc::default(),
             #[cfg(feature = "trace-lifetime")]
             _id: trace::TabletTraceId::new(db.path(), &db),
             db,
@@ -175,6 +182,10 @@ impl RocksEngine {
         self.support_multi_batch_write
     }
 
+    pub(crate) fn manual_compaction_limits(&self) -> &Arc<Mutex<ManualCompactionLimits>> {
+        &self.manual_compaction_limits
+    }
+
     #[cfg(feature = "trace-lifetime")]
     pub fn trace(region_id: u64) -> Vec<String> {
         trace::list(region_id)
@@ -3,7 +3,7 @@
 use engine_traits::{
     CfNamesExt, DeleteStrategy, ImportExt, IterOptions, Iterable, Iterator, MiscExt, Mutable,
     Range, RangeStats, Result, SstWriter, SstWriterBuilder, WriteBatch, WriteBatchExt,
-    WriteOptions,
+    WriteOptions, ALL_CFS,
 };
 use rocksdb::{FlushOptions, Range as RocksRange};
 use tikv_util::{box_try, keybuilder::KeyBuilder};
@@ -11,7 +11,11 @@ use tikv_util::{box_try, keybuilder::KeyBuilder};
 use crate::{
     engine::RocksEngine,
     r2e,
-    rocks_metrics::{RocksStatisticsReporter, STORE_ENGINE_EVENT_COUNTER_VEC},
+    rocks_metrics::{
+        RocksStatisticsReporter, STORE_ENGINE_COMPACTION_FLOW_VEC,
+        STORE_ENGINE_DELETE_RANGE_SST_BYTES_VEC, STORE_ENGINE_DELETE_RANGE_TOMBSTONES_VEC,
+        STORE_ENGINE_EVENT_COUNTER_VEC,
+    },
     rocks_metrics_defs::*,
     sst::RocksSstWriterBuilder,
     util, RocksSstWriter,
@@ -19,6 +23,12 @@ use crate::{
 
 pub const MAX_DELETE_COUNT_BY_KEY: usize = 2048;
 
+// Cap of a single delete SST built by `DeleteStrategy::DeleteByWriter`.
+// Oversized files are ingested early and the writer restarted, so the
+// transient space an ingest needs (the file itself plus the flushes and
+// compactions it may trigger) stays bounded for huge ranges.
+const MAX_DELETE_SST_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
 impl RocksEngine {
     fn is_titan(&self) -> bool {
         self.as_inner().is_titan()
@@ -40,6 +50,8 @@ impl RocksEngine {
         let mut writer_wrapper: Option<RocksSstWriter> = None;
         let mut data: Vec<Vec<u8>> = vec![];
         let mut last_end_key: Option<Vec<u8>> = None;
+        let mut sst_seq = 0;
+        let mut current_sst_path = sst_path.clone();
         for r in ranges {
             // There may be a range overlap with next range
             if last_end_key
@@ -69,12 +81,24 @@ impl RocksEngine {
                 }
                 if let Some(writer) = writer_wrapper.as_mut() {
                     writer.delete(it.key())?;
+                    if writer.file_size() >= MAX_DELETE_SST_FILE_SIZE {
+                        let mut writer = writer_wrapper.take().unwrap();
+                        STORE_ENGINE_DELETE_RANGE_SST_BYTES_VEC
+                            .with_label_values(&[cf])
+                            .inc_by(writer.file_size());
+                        writer.finish()?;
+                        self.ingest_external_file_cf(cf, &[current_sst_path.as_str()])?;
+                        sst_seq += 1;
+                        current_sst_path = format!("{}.{}", sst_path, sst_seq);
+                        let builder = RocksSstWriterBuilder::new().set_db(self).set_cf(cf);
+                        writer_wrapper = Some(builder.build(current_sst_path.as_str())?);
+                    }
                 } else {
                     data.push(it.key().to_vec());
                 }
                 if data.len() > MAX_DELETE_COUNT_BY_KEY {
                     let builder = RocksSstWriterBuilder::new().set_db(self).set_cf(cf);
-                    let mut writer = builder.build(sst_path.as_str())?;
+                    let mut writer = builder.build(current_sst_path.as_str())?;
                     for key in data.iter() {
                         writer.delete(key)?;
                     }
@@ -85,9 +109,12 @@ impl RocksEngine {
             }
         }
 
-        if let Some(writer) = writer_wrapper {
+        if let Some(mut writer) = writer_wrapper {
+            STORE_ENGINE_DELETE_RANGE_SST_BYTES_VEC
+                .with_label_values(&[cf])
+                .inc_by(writer.file_size());
             writer.finish()?;
-            self.ingest_external_file_cf(cf, &[sst_path.as_str()])?;
+            self.ingest_external_file_cf(cf, &[current_sst_path.as_str()])?;
         } else {
             let mut wb = self.write_batch();
             for key in data.iter() {
@@ -101,6 +128,9 @@ impl RocksEngine {
                 wb.write_opt(wopts)?;
                 written = true;
             }
+            STORE_ENGINE_DELETE_RANGE_TOMBSTONES_VEC
+                .with_label_values(&[cf])
+                .inc_by(data.len() as u64);
         }
         Ok(written)
     }
@@ -122,14 +152,19 @@ impl RocksEngine {
         let mut it = self.iterator_opt(cf, opts)?;
         let mut it_valid = it.seek(range.start_key)?;
         let mut wb = self.write_batch();
+        let mut tombstones = 0;
         while it_valid {
             if wb.count() >= Self::WRITE_BATCH_MAX_KEYS {
                 wb.write_opt(wopts)?;
                 wb.clear();
             }
             wb.delete_cf(cf, it.key())?;
+            tombstones += 1;
             it_valid = it.next()?;
         }
+        STORE_ENGINE_DELETE_RANGE_TOMBSTONES_VEC
+            .with_label_values(&[cf])
+            .inc_by(tombstones);
         if wb.count() > 0 {
             wb.write_opt(wopts)?;
             if !wopts.disable_wal() {
@@ -456,6 +491,36 @@ impl MiscExt for RocksEngine {
         Ok(n)
     }
 
+    fn get_accumulated_delete_sst_bytes() -> Result<u64> {
+        let mut n = 0;
+        for cf in ALL_CFS {
+            n += STORE_ENGINE_DELETE_RANGE_SST_BYTES_VEC
+                .with_label_values(&[cf])
+                .get();
+        }
+        Ok(n)
+    }
+
+    fn get_accumulated_delete_tombstones() -> Result<u64> {
+        let mut n = 0;
+        for cf in ALL_CFS {
+            n += STORE_ENGINE_DELETE_RANGE_TOMBSTONES_VEC
+                .with_label_values(&[cf])
+                .get();
+        }
+        Ok(n)
+    }
+
+    fn get_accumulated_compaction_flow() -> Result<(u64, u64)> {
+        let read = STORE_ENGINE_COMPACTION_FLOW_VEC
+            .with_label_values(&["kv", "bytes_read"])
+            .get();
+        let written = STORE_ENGINE_COMPACTION_FLOW_VEC
+            .with_label_values(&["kv", "bytes_written"])
+            .get();
+        Ok((read, written))
+    }
+
     type DiskEngine = RocksEngine;
     fn get_disk_engine(&self) -> &Self::DiskEngine {
         self
@@ -602,6 +667,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_delete_keys_cf() {
+        let path = Builder::new()
+            .prefix("engine_delete_keys_cf")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let db = new_engine(path_str, ALL_CFS).unwrap();
+
+        let keys: Vec<Vec<u8>> = (0..100u32)
+            .map(|i| format!("key_{:03}", i).into_bytes())
+            .collect();
+        let mut wb = db.write_batch();
+        for key in &keys {
+            wb.put(key, b"value").unwrap();
+        }
+        wb.write().unwrap();
+
+        // A tiny byte budget must split the deletions into many batches: with
+        // 60 keys of 7 bytes each, at least ceil(60 * 7 / 64) batches are
+        // needed no matter the per-entry overhead.
+        let (deleted, batches) = db
+            .delete_keys_cf(&WriteOptions::default(), "default", &keys[..60], 64)
+            .unwrap();
+        assert_eq!(deleted, 60);
+        assert!(batches >= 7, "{}", batches);
+        assert!(batches <= 60, "{}", batches);
+        let kvs_left: Vec<_> = keys[60..]
+            .iter()
+            .map(|k| (k.as_slice(), b"value" as &[u8]))
+            .collect();
+        check_data(&db, &["default"], &kvs_left);
+
+        // Without a budget everything goes in one batch.
+        let (deleted, batches) = db
+            .delete_keys_cf(&WriteOptions::default(), "default", &keys[60..], 0)
+            .unwrap();
+        assert_eq!(deleted, 40);
+        assert_eq!(batches, 1);
+        check_data(&db, &["default"], &[]);
+
+        // An empty key list writes nothing.
+        let (deleted, batches) = db
+            .delete_keys_cf(&WriteOptions::default(), "default", &[], 64)
+            .unwrap();
+        assert_eq!(deleted, 0);
+        assert_eq!(batches, 0);
+    }
+
     #[test]
     fn test_delete_all_in_range_by_writer() {
         let path = Builder::new()
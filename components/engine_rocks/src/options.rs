@@ -17,6 +17,7 @@ impl From<engine_traits::ReadOptions> for RocksReadOptions {
     fn from(opts: engine_traits::ReadOptions) -> Self {
         let mut r = RawReadOptions::default();
         r.set_fill_cache(opts.fill_cache());
+        r.set_max_skippable_internal_keys(opts.max_skippable_internal_keys());
         RocksReadOptions(r)
     }
 }
@@ -1488,6 +1488,16 @@ lazy_static! {
         "Number of engine events",
         &["db", "cf", "type"]
     ).unwrap();
+    pub static ref STORE_ENGINE_DELETE_RANGE_SST_BYTES_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_engine_delete_range_sst_bytes",
+        "Bytes of delete SSTs built and ingested by DeleteByWriter range deletions",
+        &["cf"]
+    ).unwrap();
+    pub static ref STORE_ENGINE_DELETE_RANGE_TOMBSTONES_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_engine_delete_range_tombstones",
+        "Number of point tombstones written by range deletions",
+        &["cf"]
+    ).unwrap();
     pub static ref STORE_ENGINE_NUM_IMMUTABLE_MEM_TABLE_VEC: IntGaugeVec = register_int_gauge_vec!(
         "tikv_engine_num_immutable_mem_table",
         "Number of immutable mem-table",
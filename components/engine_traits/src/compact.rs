@@ -11,6 +11,11 @@ pub struct ManualCompactionOptions {
     pub exclusive_manual: bool,
     pub max_subcompactions: u32,
     pub bottommost_level_force: bool,
+    /// If set, the write rate of this manual compaction is limited to the
+    /// given number of bytes per second for its duration. How the limit is
+    /// enforced, and at which granularity, is up to the engine. `None` keeps
+    /// the engine's global settings untouched.
+    pub write_rate_limit_bytes_per_sec: Option<u64>,
 }
 
 impl ManualCompactionOptions {
@@ -23,6 +28,46 @@ impl ManualCompactionOptions {
             exclusive_manual,
             max_subcompactions,
             bottommost_level_force,
+            write_rate_limit_bytes_per_sec: None,
+        }
+    }
+
+    /// Limits the write rate of this compaction to `bytes_per_sec`.
+    #[must_use]
+    pub fn write_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.write_rate_limit_bytes_per_sec = Some(bytes_per_sec);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompactFilesInRangeOptions {
+    /// Also collect the overlapping files *at* the output level as compaction
+    /// inputs so that they get rewritten and their deleted keys and range
+    /// tombstones dropped. Without this, files already sitting at the output
+    /// level are left untouched, which makes the compaction a no-op for
+    /// ranges whose garbage is already in the output level.
+    pub include_output_level: bool,
+    /// Only collect the bottommost-level files overlapping the range. This
+    /// implies compacting to the bottommost level and is the common way to
+    /// reclaim tombstone-heavy ranges left behind by region destroy cleanup.
+    pub bottommost_only: bool,
+    /// Compact even when the only collected input is a single file already
+    /// at the output level. Rewriting a file with itself reclaims nothing,
+    /// but it gives the engine's SST partitioner a chance to cut the file,
+    /// e.g. along a region boundary introduced by a split.
+    pub rewrite_single_file: bool,
+    /// The maximum number of subcompactions. `None` lets the engine pick a
+    /// default based on the number of cores.
+    pub max_subcompactions: Option<u32>,
+}
+
+impl CompactFilesInRangeOptions {
+    pub fn bottommost_only() -> Self {
+        Self {
+            include_output_level: true,
+            bottommost_only: true,
+            ..Default::default()
         }
     }
 }
@@ -80,6 +125,25 @@ pub trait CompactExt: CfNamesExt {
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         output_level: Option<i32>,
+    ) -> Result<()> {
+        self.compact_files_in_range_cf_opt(
+            cf,
+            start,
+            end,
+            output_level,
+            CompactFilesInRangeOptions::default(),
+        )
+    }
+
+    /// Same as `compact_files_in_range_cf` but the collected input files can
+    /// be extended by `options`, see [`CompactFilesInRangeOptions`].
+    fn compact_files_in_range_cf_opt(
+        &self,
+        cf: &str,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        output_level: Option<i32>,
+        options: CompactFilesInRangeOptions,
     ) -> Result<()>;
 
     fn compact_files_cf(
@@ -102,6 +166,12 @@ pub trait CompactedEvent: Send {
 
     fn output_level_label(&self) -> String;
 
+    /// The smallest key of the compaction's input files.
+    fn start_key(&self) -> &[u8];
+
+    /// The largest key of the compaction's input files, inclusive.
+    fn end_key(&self) -> &[u8];
+
     /// This takes self by value so that engine_rocks can move keys out of the
     /// CompactedEvent
     fn calc_ranges_declined_bytes(
@@ -87,6 +87,12 @@ pub trait KvEngine:
 pub struct SnapshotContext {
     pub range: Option<CacheRange>,
     pub read_ts: u64,
+    // The apply index the read requires, filled for follower reads that have
+    // confirmed it with the leader through the read index machinery. The range
+    // cache engine snapshot can only serve the read if its data for the range
+    // has caught up to this index, otherwise the read falls back to the disk
+    // snapshot which is guaranteed to be fresh enough by the apply wait.
+    pub required_apply_index: Option<u64>,
 }
 
 impl SnapshotContext {
@@ -151,6 +151,20 @@ pub enum Error {
     EntriesCompacted,
     #[error("Iterator of RangeCacheSnapshot is only supported with boundary set")]
     BoundaryNotSet,
+    /// A read or an iterator bound fell outside the key range the snapshot is
+    /// confined to. Snapshots over the whole keyspace (e.g. a plain RocksDB
+    /// snapshot) never return it; their range checks live in the region
+    /// snapshot, which re-attributes this error to its region so clients see
+    /// the same error regardless of the underlying engine.
+    #[error(
+        "Key {} is out of the snapshot range [{}, {})",
+        log_wrappers::Value::key(.key), log_wrappers::Value::key(.start), log_wrappers::Value::key(.end)
+    )]
+    KeyOutOfSnapshotRange {
+        key: Vec<u8>,
+        start: Vec<u8>,
+        end: Vec<u8>,
+    },
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -168,6 +182,7 @@ impl ErrorCodeExt for Error {
             Error::EntriesUnavailable => error_code::engine::DATALOSS,
             Error::EntriesCompacted => error_code::engine::DATACOMPACTED,
             Error::BoundaryNotSet => error_code::engine::BOUNDARY_NOT_SET,
+            Error::KeyOutOfSnapshotRange { .. } => error_code::engine::KEY_OUT_OF_SNAPSHOT_RANGE,
         }
     }
 }
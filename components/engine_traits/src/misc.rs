@@ -7,7 +7,7 @@
 
 use crate::{
     cf_names::CfNamesExt, errors::Result, flow_control_factors::FlowControlFactorsExt,
-    range::Range, KvEngine, WriteBatchExt, WriteOptions,
+    range::Range, KvEngine, Mutable, WriteBatch, WriteBatchExt, WriteOptions,
 };
 
 #[derive(Clone, Debug)]
@@ -93,6 +93,34 @@ pub trait MiscExt: CfNamesExt + FlowControlFactorsExt + WriteBatchExt {
         Ok(written)
     }
 
+    /// Deletes the given keys in `cf` through write batches whose serialized
+    /// size is bounded by `batch_bytes` (0 disables the bound), so deleting a
+    /// large key list does not build up one oversized batch. Returns the
+    /// number of keys deleted and the number of batches written.
+    fn delete_keys_cf(
+        &self,
+        wopts: &WriteOptions,
+        cf: &str,
+        keys: &[Vec<u8>],
+        batch_bytes: usize,
+    ) -> Result<(usize, usize)> {
+        let mut wb = self.write_batch();
+        let mut batches = 0;
+        for key in keys {
+            if batch_bytes > 0 && !wb.is_empty() && wb.data_size() + key.len() > batch_bytes {
+                wb.write_opt(wopts)?;
+                batches += 1;
+                wb.clear();
+            }
+            wb.delete_cf(cf, key)?;
+        }
+        if !wb.is_empty() {
+            wb.write_opt(wopts)?;
+            batches += 1;
+        }
+        Ok((keys.len(), batches))
+    }
+
     /// Returns whether there's data written through kv interface.
     fn delete_ranges_cf(
         &self,
@@ -185,6 +213,20 @@ pub trait MiscExt: CfNamesExt + FlowControlFactorsExt + WriteBatchExt {
         Ok(n)
     }
 
+    /// Total bytes of the delete SSTs built and ingested by
+    /// `DeleteStrategy::DeleteByWriter` range deletions, accumulated
+    /// process-wide.
+    fn get_accumulated_delete_sst_bytes() -> Result<u64>;
+
+    /// Total number of point tombstones written by range deletions
+    /// (`DeleteStrategy::DeleteByKey` and the write-batch fallbacks of
+    /// `DeleteByWriter`), accumulated process-wide.
+    fn get_accumulated_delete_tombstones() -> Result<u64>;
+
+    /// Accumulated compaction flow of the engine as `(bytes read, bytes
+    /// written)`, taken from the engine statistics.
+    fn get_accumulated_compaction_flow() -> Result<(u64, u64)>;
+
     type DiskEngine: KvEngine;
     fn get_disk_engine(&self) -> &Self::DiskEngine;
 }
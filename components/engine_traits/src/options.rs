@@ -6,6 +6,11 @@ use tikv_util::keybuilder::KeyBuilder;
 #[derive(Clone)]
 pub struct ReadOptions {
     fill_cache: bool,
+    // A threshold for the number of keys that can be skipped before failing a
+    // point lookup as incomplete. The default value of 0 should be used to
+    // never fail a request as incomplete, even on skipping too many keys.
+    // It's used to avoid encountering too many tombstones when reading.
+    max_skippable_internal_keys: u64,
 }
 
 impl ReadOptions {
@@ -22,11 +27,24 @@ impl ReadOptions {
     pub fn set_fill_cache(&mut self, v: bool) {
         self.fill_cache = v;
     }
+
+    #[inline]
+    pub fn max_skippable_internal_keys(&self) -> u64 {
+        self.max_skippable_internal_keys
+    }
+
+    #[inline]
+    pub fn set_max_skippable_internal_keys(&mut self, threshold: u64) {
+        self.max_skippable_internal_keys = threshold;
+    }
 }
 
 impl Default for ReadOptions {
     fn default() -> ReadOptions {
-        ReadOptions { fill_cache: true }
+        ReadOptions {
+            fill_cache: true,
+            max_skippable_internal_keys: 0,
+        }
     }
 }
 
@@ -48,6 +48,16 @@ pub trait Peekable {
         self.get_value_cf_opt(&ReadOptions::default(), cf, key)
     }
 
+    /// Read the values of several keys from a given column family.
+    ///
+    /// The result holds one entry per key, in the same order, with `None`
+    /// marking a key that does not exist. The default implementation issues
+    /// one point read per key; engines with a native batched read should
+    /// override it.
+    fn multi_get_value_cf(&self, cf: &str, keys: &[&[u8]]) -> Result<Vec<Option<Self::DbVector>>> {
+        keys.iter().map(|key| self.get_value_cf(cf, key)).collect()
+    }
+
     /// Read a value and return it as a protobuf message.
     fn get_msg<M: protobuf::Message + Default>(&self, key: &[u8]) -> Result<Option<M>> {
         let value = self.get_value(key)?;
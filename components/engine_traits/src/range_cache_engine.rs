@@ -4,17 +4,79 @@ use std::{
     cmp,
     fmt::{self, Debug},
     result,
+    time::Duration,
 };
 
 use keys::{enc_end_key, enc_start_key};
 use kvproto::metapb;
 
-use crate::{Iterable, KvEngine, Snapshot, WriteBatchExt};
+use crate::{data_cf_offset, Iterable, KvEngine, Snapshot, WriteBatchExt, DATA_CFS};
 
-#[derive(Debug, PartialEq)]
+/// The expected reasons for a range cache snapshot not being acquired. Reads
+/// failing with one of these simply fall back to the disk engine; an error
+/// surfaced by a snapshot that has already been acquired is unexpected and is
+/// subject to [`RangeCacheEngine::strict_read_errors`].
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FailedReason {
     NotCached,
     TooOldRead,
+    // The range is cached but its boundaries do not match the region the read
+    // was routed with, which indicates the cached metadata is stale, e.g. a
+    // region split or merge event was missed.
+    EpochNotMatch,
+}
+
+impl FailedReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailedReason::NotCached => "not_cached",
+            FailedReason::TooOldRead => "too_old_read",
+            FailedReason::EpochNotMatch => "epoch_not_match",
+        }
+    }
+}
+
+impl fmt::Display for FailedReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which data cfs of a cached range hold data. Ranges are normally cached
+/// with all data cfs; a partial load can restrict caching to a subset (e.g.
+/// the write cf only for scan-heavy workloads, where short values are inlined
+/// in the write records), in which case reads of the uncached cfs must be
+/// served by the disk engine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachedCfs(u8);
+
+impl Default for CachedCfs {
+    fn default() -> CachedCfs {
+        CachedCfs::all()
+    }
+}
+
+impl CachedCfs {
+    pub fn all() -> CachedCfs {
+        CachedCfs::from_cfs(DATA_CFS)
+    }
+
+    pub fn from_cfs(cfs: &[&str]) -> CachedCfs {
+        let mut mask = 0;
+        for cf in cfs {
+            mask |= 1 << data_cf_offset(cf);
+        }
+        CachedCfs(mask)
+    }
+
+    /// Whether the given data cf is cached. Panics on a non-data cf.
+    pub fn contains(&self, cf: &str) -> bool {
+        self.0 & (1 << data_cf_offset(cf)) != 0
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == CachedCfs::all()
+    }
 }
 
 /// RangeCacheEngine works as a range cache caching some ranges (in Memory or
@@ -41,6 +103,21 @@ pub trait RangeCacheEngine:
     // return the range containing the key
     fn get_range_for_key(&self, key: &[u8]) -> Option<CacheRange>;
 
+    // Returns the freshness watermark of the cached data for the range: the
+    // max sequence number and the max apply index that have been written into
+    // the cache. None if the range is not cached or no write has recorded an
+    // apply index for it yet.
+    fn cached_watermark(&self, _range: &CacheRange) -> Option<(u64, u64)> {
+        None
+    }
+
+    // Which data cfs of the cached range containing `range` hold data. Reads
+    // of cfs not in the set must be served by the disk engine. Implementations
+    // without partial caching report all cfs.
+    fn cached_cfs(&self, _range: &CacheRange) -> CachedCfs {
+        CachedCfs::all()
+    }
+
     type RangeHintService: RangeHintService;
     fn start_hint_service(&self, range_hint_service: Self::RangeHintService);
 
@@ -48,7 +125,22 @@ pub trait RangeCacheEngine:
         false
     }
 
+    // Whether unexpected errors from reads on an acquired snapshot should
+    // fail the request instead of being masked by a fallback to the disk
+    // engine. Expected acquisition failures (`FailedReason`) are unaffected.
+    fn strict_read_errors(&self) -> bool {
+        false
+    }
+
     fn evict_range(&self, range: &CacheRange);
+
+    // Whether the eviction of the range has fully taken effect: no part of the
+    // range is cached, being loaded, or pending to be loaded, so new snapshots
+    // over the range are guaranteed to fail until it is cached again.
+    // Implementations without such bookkeeping may return true unconditionally.
+    fn range_evicted(&self, _range: &CacheRange) -> bool {
+        true
+    }
 }
 
 pub trait RangeCacheEngineExt {
@@ -57,6 +149,18 @@ pub trait RangeCacheEngineExt {
     // TODO(SpadeA): try to find a better way to reduce coupling degree of range
     // cache engine and kv engine
     fn evict_range(&self, range: &CacheRange);
+
+    // Evicts the range and waits until the eviction has fully taken effect,
+    // i.e. new range cache snapshots over the range are guaranteed to fail.
+    // Returns false if this cannot be confirmed within `timeout`; the cached
+    // data may then still be readable and the caller should report it loudly.
+    //
+    // Used before ingesting a snapshot that overlaps a cached range so that
+    // stale cached data cannot be served once the ingest starts.
+    fn evict_range_before_ingest(&self, range: &CacheRange, _timeout: Duration) -> bool {
+        self.evict_range(range);
+        true
+    }
 }
 
 /// A service that should run in the background to retrieve and apply cache
@@ -101,6 +205,11 @@ impl CacheRange {
         }
     }
 
+    /// Builds the range from the region boundaries with the same
+    /// `enc_start_key`/`enc_end_key` encoding raftstore uses for data keys.
+    /// The encoding only prepends the 'z' data prefix, so API v2 keyspace
+    /// prefixes in the region keys are preserved as-is and the range bounds
+    /// keys exactly like the disk engine does.
     pub fn from_region(region: &metapb::Region) -> Self {
         Self {
             start: enc_start_key(region),
@@ -127,4 +127,17 @@ pub trait WriteBatch: Mutable {
     /// It declares that the following consecutive write will be within this
     /// range.
     fn prepare_for_range(&mut self, _: CacheRange) {}
+
+    /// Records the apply index the writes buffered for the current range (set
+    /// by `prepare_for_range`) have been applied to. It is used by the range
+    /// cache engine to maintain a per-range freshness watermark so that
+    /// follower reads can tell whether the cache has caught up to their
+    /// required apply index.
+    fn set_range_applied_index(&mut self, _: u64) {}
+
+    /// Records the raft term the writes buffered for the current range were
+    /// applied in. Together with the apply index it lets the range cache
+    /// engine tag the entries of a consumed batch with the apply that
+    /// produced them, for debugging provenance lookups.
+    fn set_range_applied_term(&mut self, _: u64) {}
 }
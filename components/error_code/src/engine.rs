@@ -11,5 +11,6 @@ define_error_codes!(
     CODEC => ("Codec", "", ""),
     DATALOSS => ("DataLoss", "", ""),
     DATACOMPACTED => ("DataCompacted", "", ""),
-    BOUNDARY_NOT_SET => ("BoundaryNotSet", "", "")
+    BOUNDARY_NOT_SET => ("BoundaryNotSet", "", ""),
+    KEY_OUT_OF_SNAPSHOT_RANGE => ("KeyOutOfSnapshotRange", "", "")
 );
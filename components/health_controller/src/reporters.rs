@@ -90,6 +90,26 @@ impl RaftstoreReporter {
             .update_raftstore_slow_score(self.slow_score.get());
     }
 
+    /// Records the snapshot apply backlog observed over the last inspection
+    /// window. `busy_ratio` is the fraction of the window the region worker
+    /// spent inside apply passes and `max_wait` is the longest time an apply
+    /// task has been waiting. The weighted signal is fed to the slow score as
+    /// an extra timeout ratio; a zero weight (the default configuration)
+    /// makes this a no-op so the backlog stays report-only.
+    pub fn record_apply_backlog(&mut self, busy_ratio: f64, max_wait: Duration, weight: f64) {
+        if weight <= 0.0 {
+            return;
+        }
+        // An apply waiting longer than a whole window means the backlog is
+        // not draining, which is as bad as a fully busy worker.
+        let factor = if max_wait >= self.slow_score.get_inspect_interval() {
+            1.0
+        } else {
+            busy_ratio
+        };
+        self.slow_score.record_extra_ratio(factor * weight);
+    }
+
     fn is_healthy(&self) -> bool {
         self.is_healthy
     }
@@ -20,6 +20,9 @@ pub struct SlowScore {
 
     timeout_requests: usize,
     total_requests: usize,
+    // Extra timeout ratio reported by external signals (e.g. the snapshot
+    // apply backlog), accumulated until the next update.
+    extra_ratio: OrderedFloat<f64>,
 
     inspect_interval: Duration,
     // The maximal tolerated timeout ratio.
@@ -42,6 +45,7 @@ impl SlowScore {
 
             timeout_requests: 0,
             total_requests: 0,
+            extra_ratio: OrderedFloat(0.0),
 
             inspect_interval,
             ratio_thresh: OrderedFloat(0.1),
@@ -72,6 +76,14 @@ impl SlowScore {
         self.timeout_requests += 1;
     }
 
+    /// Records an extra timeout ratio from an external signal. It is added to
+    /// the timeout ratio of the inspecting requests on the next update, so a
+    /// store whose inspecting requests all succeed can still be scored slow
+    /// when e.g. its apply backlog keeps growing.
+    pub fn record_extra_ratio(&mut self, ratio: f64) {
+        self.extra_ratio = cmp::max(self.extra_ratio, OrderedFloat(ratio.clamp(0.0, 1.0)));
+    }
+
     pub fn update(&mut self) -> f64 {
         let elapsed = self.last_update_time.elapsed();
         self.update_impl(elapsed).into()
@@ -83,7 +95,7 @@ impl SlowScore {
 
     // Update the score in a AIMD way.
     fn update_impl(&mut self, elapsed: Duration) -> OrderedFloat<f64> {
-        if self.timeout_requests == 0 {
+        if self.timeout_requests == 0 && self.extra_ratio == OrderedFloat(0.0) {
             let desc = 100.0 * (elapsed.as_millis() as f64 / self.min_ttr.as_millis() as f64);
             if OrderedFloat(desc) > self.value - OrderedFloat(1.0) {
                 self.value = 1.0.into();
@@ -91,15 +103,20 @@ impl SlowScore {
                 self.value -= desc;
             }
         } else {
-            let timeout_ratio = self.timeout_requests as f64 / self.total_requests as f64;
-            let near_thresh =
-                cmp::min(OrderedFloat(timeout_ratio), self.ratio_thresh) / self.ratio_thresh;
+            let request_ratio = if self.total_requests == 0 {
+                0.0
+            } else {
+                self.timeout_requests as f64 / self.total_requests as f64
+            };
+            let timeout_ratio = OrderedFloat(request_ratio) + self.extra_ratio;
+            let near_thresh = cmp::min(timeout_ratio, self.ratio_thresh) / self.ratio_thresh;
             let value = self.value * (OrderedFloat(1.0) + near_thresh);
             self.value = cmp::min(OrderedFloat(100.0), value);
         }
 
         self.total_requests = 0;
         self.timeout_requests = 0;
+        self.extra_ratio = OrderedFloat(0.0);
         self.last_update_time = Instant::now();
         self.value
     }
@@ -207,4 +224,42 @@ mod tests {
             slow_score.update_impl(Duration::from_secs(57))
         );
     }
+
+    #[test]
+    fn test_slow_score_extra_ratio() {
+        let mut slow_score = SlowScore::new(Duration::from_millis(500));
+        // Without an extra ratio the score stays at its floor.
+        slow_score.total_requests = 100;
+        assert_eq!(
+            OrderedFloat(1.0),
+            slow_score.update_impl(Duration::from_secs(10))
+        );
+
+        // An extra ratio alone drives the score up even though none of the
+        // inspecting requests timed out.
+        slow_score.total_requests = 100;
+        slow_score.record_extra_ratio(0.5);
+        assert_eq!(
+            OrderedFloat(2.0),
+            slow_score.update_impl(Duration::from_secs(10))
+        );
+
+        // Repeated records keep the largest ratio and it is clamped to [0, 1].
+        slow_score.record_extra_ratio(2.0);
+        slow_score.record_extra_ratio(0.1);
+        assert_eq!(OrderedFloat(1.0), slow_score.extra_ratio);
+
+        // The extra ratio is consumed by the update and does not leak into
+        // the next round.
+        slow_score.total_requests = 100;
+        assert_eq!(
+            OrderedFloat(4.0),
+            slow_score.update_impl(Duration::from_secs(10))
+        );
+        slow_score.total_requests = 100;
+        assert_eq!(
+            OrderedFloat(1.0),
+            slow_score.update_impl(Duration::from_secs(10))
+        );
+    }
 }
@@ -1,6 +1,9 @@
 // Copyright 2023 TiKV Project Authors. Licensed under Apache-2.0.
 
-use engine_traits::{CompactExt, KvEngine, ManualCompactionOptions, RangeCacheEngine, Result};
+use engine_traits::{
+    CompactExt, CompactFilesInRangeOptions, KvEngine, ManualCompactionOptions, RangeCacheEngine,
+    Result,
+};
 
 use crate::engine::HybridEngine;
 
@@ -26,15 +29,16 @@ where
             .compact_range_cf(cf, start_key, end_key, compaction_option)
     }
 
-    fn compact_files_in_range_cf(
+    fn compact_files_in_range_cf_opt(
         &self,
         cf: &str,
         start: Option<&[u8]>,
         end: Option<&[u8]>,
         output_level: Option<i32>,
+        options: CompactFilesInRangeOptions,
     ) -> Result<()> {
         self.disk_engine()
-            .compact_files_in_range_cf(cf, start, end, output_level)
+            .compact_files_in_range_cf_opt(cf, start, end, output_level, options)
     }
 
     fn compact_files_in_range(
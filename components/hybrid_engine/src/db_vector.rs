@@ -53,6 +53,42 @@ where
                 db_vec: Either::Right(e),
             }))
     }
+
+    /// Same as `try_from_disk_snap` but for a batched read: one wrapper per
+    /// key, in the input order.
+    pub fn try_multi_from_disk_snap(
+        snap: &EK::Snapshot,
+        cf: &str,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Self>>> {
+        Ok(snap
+            .multi_get_value_cf(cf, keys)?
+            .into_iter()
+            .map(|v| {
+                v.map(|e| HybridDbVector {
+                    db_vec: Either::Left(e),
+                })
+            })
+            .collect())
+    }
+
+    /// Same as `try_from_cache_snap` but for a batched read: one wrapper per
+    /// key, in the input order.
+    pub fn try_multi_from_cache_snap(
+        snap: &EC::Snapshot,
+        cf: &str,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Self>>> {
+        Ok(snap
+            .multi_get_value_cf(cf, keys)?
+            .into_iter()
+            .map(|v| {
+                v.map(|e| HybridDbVector {
+                    db_vec: Either::Right(e),
+                })
+            })
+            .collect())
+    }
 }
 
 impl<EK, EC> Deref for HybridDbVector<EK, EC>
@@ -1,9 +1,10 @@
 // Copyright 2023 TiKV Project Authors. Licensed under Apache-2.0.
 
 use engine_traits::{
-    FailedReason, KvEngine, Mutable, Peekable, RangeCacheEngine, ReadOptions, Result,
+    CachedCfs, FailedReason, KvEngine, Mutable, Peekable, RangeCacheEngine, ReadOptions, Result,
     SnapshotContext, SnapshotMiscExt, SyncMutable, WriteBatch, WriteBatchExt,
 };
+use tracker::with_tls_tracker;
 
 use crate::{
     metrics::{
@@ -74,29 +75,69 @@ where
 
     fn snapshot(&self, ctx: Option<SnapshotContext>) -> Self::Snapshot {
         let disk_snap = self.disk_engine.snapshot(ctx.clone());
+        let mut range_cache_cfs = CachedCfs::all();
         let range_cache_snap = if !self.range_cache_engine.enabled() {
             None
         } else if let Some(ctx) = ctx {
-            match self.range_cache_engine.snapshot(
-                ctx.range.unwrap(),
-                ctx.read_ts,
-                disk_snap.sequence_number(),
-            ) {
-                Ok(snap) => {
-                    SNAPSHOT_TYPE_COUNT_STATIC.range_cache_engine.inc();
-                    Some(snap)
-                }
-                Err(FailedReason::TooOldRead) => {
-                    RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
-                        .too_old_read
-                        .inc();
-                    None
-                }
-                Err(FailedReason::NotCached) => {
-                    RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
-                        .not_cached
-                        .inc();
-                    None
+            let range = ctx.range.unwrap();
+            // A follower read requires the cache to have caught up to the
+            // apply index it confirmed with the leader. The disk snapshot is
+            // fresh enough by the read index machinery, but the cache may lag
+            // the disk apply by a batch, so reject the cache when its
+            // watermark is behind (or unknown).
+            let cache_fresh = ctx.required_apply_index.map_or(true, |required| {
+                self.range_cache_engine
+                    .cached_watermark(&range)
+                    .map_or(false, |(_, applied)| applied >= required)
+            });
+            if !cache_fresh {
+                RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
+                    .behind_apply_index
+                    .inc();
+                with_tls_tracker(|tracker| {
+                    tracker.metrics.range_cache_snapshot_failed_reason =
+                        Some("behind_apply_index");
+                });
+                None
+            } else {
+                match self.range_cache_engine.snapshot(
+                    range.clone(),
+                    ctx.read_ts,
+                    disk_snap.sequence_number(),
+                ) {
+                    Ok(snap) => {
+                        SNAPSHOT_TYPE_COUNT_STATIC.range_cache_engine.inc();
+                        // With a partially cached range, reads of the uncached
+                        // cfs must keep going to the disk snapshot.
+                        range_cache_cfs = self.range_cache_engine.cached_cfs(&range);
+                        Some(snap)
+                    }
+                    Err(reason) => {
+                        match reason {
+                            FailedReason::TooOldRead => {
+                                RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
+                                    .too_old_read
+                                    .inc();
+                            }
+                            FailedReason::NotCached => {
+                                RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
+                                    .not_cached
+                                    .inc();
+                            }
+                            FailedReason::EpochNotMatch => {
+                                RANGE_CACHEN_SNAPSHOT_ACQUIRE_FAILED_REASON_COUNT_STAIC
+                                    .epoch_not_match
+                                    .inc();
+                            }
+                        }
+                        // Surface the reason to the read's tracker so the slow
+                        // log can show why the range cache was not used.
+                        with_tls_tracker(|tracker| {
+                            tracker.metrics.range_cache_snapshot_failed_reason =
+                                Some(reason.as_str());
+                        });
+                        None
+                    }
                 }
             }
         } else {
@@ -108,7 +149,12 @@ where
         if range_cache_snap.is_none() {
             SNAPSHOT_TYPE_COUNT_STATIC.rocksdb.inc();
         }
-        HybridEngineSnapshot::new(disk_snap, range_cache_snap)
+        HybridEngineSnapshot::new(
+            disk_snap,
+            range_cache_snap,
+            range_cache_cfs,
+            self.range_cache_engine.strict_read_errors(),
+        )
     }
 
     fn sync(&self) -> engine_traits::Result<()> {
@@ -218,17 +264,24 @@ where
 #[cfg(test)]
 mod tests {
 
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use engine_rocks::util::new_engine;
-    use engine_traits::{CacheRange, KvEngine, SnapshotContext, CF_DEFAULT, CF_LOCK, CF_WRITE};
+    use engine_traits::{
+        CacheRange, FailedReason, KvEngine, Mutable, RangeCacheEngine, RangeCacheEngineExt,
+        SnapshotContext, SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT, CF_LOCK, CF_WRITE,
+    };
     use online_config::{ConfigChange, ConfigManager, ConfigValue};
     use range_cache_memory_engine::{
         config::RangeCacheConfigManager, RangeCacheEngineConfig, RangeCacheEngineContext,
-        RangeCacheMemoryEngine,
+        RangeCacheMemoryEngine, RangeCacheStatus,
     };
     use tempfile::Builder;
     use tikv_util::config::VersionTrack;
+    use tracker::{
+        clear_tls_tracker_token, set_tls_tracker_token, RequestInfo, Tracker, TrackerToken,
+        GLOBAL_TRACKERS,
+    };
 
     use crate::HybridEngine;
 
@@ -258,6 +311,7 @@ mod tests {
         let mut snap_ctx = SnapshotContext {
             read_ts: 15,
             range: Some(range.clone()),
+            required_apply_index: None,
         };
         let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
         assert!(s.range_cache_snapshot_available());
@@ -275,4 +329,177 @@ mod tests {
         let s = hybrid_engine.snapshot(Some(snap_ctx));
         assert!(!s.range_cache_snapshot_available());
     }
+
+    fn failed_reason(token: TrackerToken) -> Option<&'static str> {
+        GLOBAL_TRACKERS
+            .with_tracker(token, |tracker| {
+                tracker.metrics.range_cache_snapshot_failed_reason
+            })
+            .flatten()
+    }
+
+    #[test]
+    fn test_snapshot_failed_reason() {
+        let path = Builder::new().prefix("temp").tempdir().unwrap();
+        let disk_engine = new_engine(
+            path.path().to_str().unwrap(),
+            &[CF_DEFAULT, CF_LOCK, CF_WRITE],
+        )
+        .unwrap();
+        let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test()));
+        let memory_engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config));
+
+        let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        memory_engine.new_range(range.clone());
+        {
+            let mut core = memory_engine.core().write();
+            core.mut_range_manager().set_safe_point(&range, 10);
+        }
+
+        let hybrid_engine = HybridEngine::new(disk_engine, memory_engine.clone());
+        let token = GLOBAL_TRACKERS.insert(Tracker::new(RequestInfo::default()));
+        set_tls_tracker_token(token);
+
+        // The read ts is not newer than the safe point of the range.
+        let mut snap_ctx = SnapshotContext {
+            read_ts: 5,
+            range: Some(range.clone()),
+            required_apply_index: None,
+        };
+        let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
+        assert!(!s.range_cache_snapshot_available());
+        assert_eq!(failed_reason(token), Some("too_old_read"));
+
+        // The range is not cached at all.
+        snap_ctx.read_ts = 15;
+        snap_ctx.range = Some(CacheRange::new(b"k20".to_vec(), b"k30".to_vec()));
+        let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
+        assert!(!s.range_cache_snapshot_available());
+        assert_eq!(failed_reason(token), Some("not_cached"));
+
+        // The requested range overlaps the cached one without being contained
+        // by it, so the cached boundaries are stale.
+        snap_ctx.range = Some(CacheRange::new(b"k00".to_vec(), b"k15".to_vec()));
+        for _ in 0..3 {
+            let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
+            assert!(!s.range_cache_snapshot_available());
+            assert_eq!(failed_reason(token), Some("epoch_not_match"));
+        }
+        // Repeated epoch mismatches evict the stale range so it can be
+        // reloaded with the current region boundaries.
+        assert!(memory_engine.get_range_for_key(b"k05").is_none());
+
+        clear_tls_tracker_token();
+        GLOBAL_TRACKERS.remove(token);
+    }
+
+    #[test]
+    fn test_follower_read_freshness_gate() {
+        let path = Builder::new().prefix("temp").tempdir().unwrap();
+        let disk_engine = new_engine(
+            path.path().to_str().unwrap(),
+            &[CF_DEFAULT, CF_LOCK, CF_WRITE],
+        )
+        .unwrap();
+        let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test()));
+        let memory_engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config));
+
+        let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        memory_engine.new_range(range.clone());
+        {
+            let mut core = memory_engine.core().write();
+            core.mut_range_manager().set_safe_point(&range, 5);
+        }
+
+        let hybrid_engine = HybridEngine::new(disk_engine, memory_engine.clone());
+        let token = GLOBAL_TRACKERS.insert(Tracker::new(RequestInfo::default()));
+        set_tls_tracker_token(token);
+
+        // Write a batch through both engines, applied up to index 10.
+        let mut wb = hybrid_engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.cache_write_batch
+            .set_range_cache_status(RangeCacheStatus::Cached);
+        wb.put(b"k05", b"val").unwrap();
+        wb.set_range_applied_index(10);
+        wb.write().unwrap();
+
+        // The cache has caught up to the required apply index, so a follower
+        // read can be served from it.
+        let mut snap_ctx = SnapshotContext {
+            read_ts: 15,
+            range: Some(range.clone()),
+            required_apply_index: Some(10),
+        };
+        let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
+        assert!(s.range_cache_snapshot_available());
+
+        // Lag the cache behind the disk engine by one batch, as happens when
+        // the cache batch of an apply has not been consumed yet. A follower
+        // read requiring the new apply index must fall back to the disk
+        // snapshot.
+        hybrid_engine.disk_engine().put(b"k06", b"val").unwrap();
+        snap_ctx.required_apply_index = Some(11);
+        let s = hybrid_engine.snapshot(Some(snap_ctx.clone()));
+        assert!(!s.range_cache_snapshot_available());
+        assert_eq!(failed_reason(token), Some("behind_apply_index"));
+
+        // Once the cache batch is consumed the watermark advances and the
+        // follower read hits the cache again.
+        let mut wb = hybrid_engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.cache_write_batch
+            .set_range_cache_status(RangeCacheStatus::Cached);
+        wb.put(b"k06", b"val").unwrap();
+        wb.set_range_applied_index(11);
+        wb.write().unwrap();
+        let s = hybrid_engine.snapshot(Some(snap_ctx));
+        assert!(s.range_cache_snapshot_available());
+
+        clear_tls_tracker_token();
+        GLOBAL_TRACKERS.remove(token);
+    }
+
+    #[test]
+    fn test_evict_range_before_ingest() {
+        let path = Builder::new().prefix("temp").tempdir().unwrap();
+        let disk_engine = new_engine(
+            path.path().to_str().unwrap(),
+            &[CF_DEFAULT, CF_LOCK, CF_WRITE],
+        )
+        .unwrap();
+        let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test()));
+        let memory_engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config));
+
+        let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        memory_engine.new_range(range.clone());
+        {
+            let mut core = memory_engine.core().write();
+            core.mut_range_manager().set_safe_point(&range, 10);
+        }
+
+        let hybrid_engine = HybridEngine::new(disk_engine, memory_engine.clone());
+
+        // A snapshot taken before the eviction keeps its own consistency.
+        let live_snapshot = memory_engine.snapshot(range.clone(), 15, u64::MAX).unwrap();
+
+        assert!(hybrid_engine.evict_range_before_ingest(&range, Duration::from_secs(1)));
+
+        // New snapshots over the range fail both while the live snapshot still
+        // pins the evicted data and after it is dropped.
+        assert_eq!(
+            memory_engine
+                .snapshot(range.clone(), 15, u64::MAX)
+                .unwrap_err(),
+            FailedReason::NotCached
+        );
+        drop(live_snapshot);
+        assert_eq!(
+            memory_engine.snapshot(range, 15, u64::MAX).unwrap_err(),
+            FailedReason::NotCached
+        );
+    }
 }
@@ -28,4 +28,5 @@ pub mod util;
 mod write_batch;
 
 pub use engine::HybridEngine;
+pub use metrics::RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC;
 pub use snapshot::HybridEngineSnapshot;
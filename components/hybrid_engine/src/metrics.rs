@@ -18,6 +18,8 @@ make_auto_flush_static_metric! {
         no_read_ts,
         not_cached,
         too_old_read,
+        epoch_not_match,
+        behind_apply_index,
     }
 
     pub struct FailedReasonCountVec: LocalIntCounter {
@@ -39,6 +41,15 @@ lazy_static! {
             &["type"],
         )
         .unwrap();
+    // Error path only, so the plain counter vec is used without a local
+    // auto-flush wrapper.
+    pub static ref RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "tikv_range_cache_unexpected_read_error_count",
+            "Number of unexpected errors from reads on an acquired range cache snapshot",
+            &["type"],
+        )
+        .unwrap();
 }
 
 lazy_static! {
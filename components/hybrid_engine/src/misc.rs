@@ -43,6 +43,19 @@ where
             .delete_ranges_cf(wopts, cf, strategy, ranges)
     }
 
+    fn delete_keys_cf(
+        &self,
+        wopts: &engine_traits::WriteOptions,
+        cf: &str,
+        keys: &[Vec<u8>],
+        batch_bytes: usize,
+    ) -> Result<(usize, usize)> {
+        // Only used on ranges that are evicted from the cache engine through
+        // `delete_ranges_cf`, so the deletions go to the disk engine directly.
+        self.disk_engine()
+            .delete_keys_cf(wopts, cf, keys, batch_bytes)
+    }
+
     fn get_approximate_memtable_stats_cf(
         &self,
         cf: &str,
@@ -140,6 +153,18 @@ where
         EK::get_accumulated_flush_count_cf(cf)
     }
 
+    fn get_accumulated_delete_sst_bytes() -> Result<u64> {
+        EK::get_accumulated_delete_sst_bytes()
+    }
+
+    fn get_accumulated_delete_tombstones() -> Result<u64> {
+        EK::get_accumulated_delete_tombstones()
+    }
+
+    fn get_accumulated_compaction_flow() -> Result<(u64, u64)> {
+        EK::get_accumulated_compaction_flow()
+    }
+
     type DiskEngine = EK::DiskEngine;
     fn get_disk_engine(&self) -> &Self::DiskEngine {
         self.disk_engine().get_disk_engine()
@@ -1,9 +1,14 @@
 // Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
 
+use std::time::{Duration, Instant};
+
 use engine_traits::{CacheRange, KvEngine, RangeCacheEngine, RangeCacheEngineExt};
 
 use crate::HybridEngine;
 
+// How long to wait between checks of whether an eviction has taken effect.
+const EVICT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 impl<EK, EC> RangeCacheEngineExt for HybridEngine<EK, EC>
 where
     EK: KvEngine,
@@ -17,4 +22,21 @@ where
     fn evict_range(&self, range: &CacheRange) {
         self.range_cache_engine().evict_range(range);
     }
+
+    fn evict_range_before_ingest(&self, range: &CacheRange, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Evicting again on each round is idempotent for already evicted
+            // parts and cancels any load of the range started after the
+            // previous round.
+            self.range_cache_engine().evict_range(range);
+            if self.range_cache_engine().range_evicted(range) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(EVICT_POLL_INTERVAL);
+        }
+    }
 }
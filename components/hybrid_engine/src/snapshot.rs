@@ -1,13 +1,37 @@
 // Copyright 2023 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::fmt::{self, Debug, Formatter};
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use engine_traits::{
-    is_data_cf, CfNamesExt, IterOptions, Iterable, KvEngine, Peekable, RangeCacheEngine,
-    ReadOptions, Result, Snapshot, SnapshotMiscExt, CF_DEFAULT,
+    is_data_cf, CachedCfs, CfNamesExt, Error, IterOptions, Iterable, KvEngine, Peekable,
+    RangeCacheEngine, ReadOptions, Result, Snapshot, SnapshotMiscExt, CF_DEFAULT,
+};
+use range_cache_memory_engine::{LocalStatsSink, RangeCacheMemoryEngine};
+use tikv_util::{box_err, time::UnixSecs, warn};
+
+use crate::{
+    db_vector::HybridDbVector, engine_iterator::HybridEngineIterator,
+    metrics::RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC,
 };
 
-use crate::{db_vector::HybridDbVector, engine_iterator::HybridEngineIterator};
+// Rate limits the logging of unexpected range cache read errors: once they
+// start occurring they likely occur on every read, and each occurrence is
+// already counted in `RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC`.
+fn should_log_unexpected_read_error() -> bool {
+    static LAST_LOG_SECS: AtomicU64 = AtomicU64::new(0);
+    let now = UnixSecs::now().into_inner();
+    let last = LAST_LOG_SECS.load(Ordering::Relaxed);
+    last != now
+        && LAST_LOG_SECS
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+}
 
 pub struct HybridEngineSnapshot<EK, EC>
 where
@@ -16,6 +40,11 @@ where
 {
     disk_snap: EK::Snapshot,
     range_cache_snap: Option<EC::Snapshot>,
+    // Which data cfs the cached range holds. Reads of the other cfs go to
+    // the disk snapshot even when a range cache snapshot is available, e.g.
+    // default cf reads against a range loaded with the write cf only.
+    range_cache_cfs: CachedCfs,
+    strict_read_errors: bool,
 }
 
 impl<EK, EC> HybridEngineSnapshot<EK, EC>
@@ -23,10 +52,17 @@ where
     EK: KvEngine,
     EC: RangeCacheEngine,
 {
-    pub fn new(disk_snap: EK::Snapshot, range_cache_snap: Option<EC::Snapshot>) -> Self {
+    pub fn new(
+        disk_snap: EK::Snapshot,
+        range_cache_snap: Option<EC::Snapshot>,
+        range_cache_cfs: CachedCfs,
+        strict_read_errors: bool,
+    ) -> Self {
         HybridEngineSnapshot {
             disk_snap,
             range_cache_snap,
+            range_cache_cfs,
+            strict_read_errors,
         }
     }
 
@@ -41,6 +77,78 @@ where
     pub fn disk_snap(&self) -> &EK::Snapshot {
         &self.disk_snap
     }
+
+    // Records an unexpected error from a read on the range cache snapshot.
+    // The expected reasons for not serving a read from the cache
+    // (`FailedReason`) are all handled when the snapshot is acquired, so an
+    // error surfaced by an acquired snapshot indicates a bug or stale cached
+    // metadata. Returns the error to propagate in strict mode; `None` means
+    // the caller should fall back to the disk snapshot, which holds the same
+    // data.
+    fn on_unexpected_read_error(
+        &self,
+        op: &str,
+        cf: &str,
+        key: Option<&[u8]>,
+        e: Error,
+    ) -> Option<Error> {
+        RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC
+            .with_label_values(&[op])
+            .inc();
+        if should_log_unexpected_read_error() {
+            warn!(
+                "unexpected range cache engine read error";
+                "op" => op,
+                "cf" => cf,
+                "key" => key.map(log_wrappers::Value::key),
+                "err" => ?e,
+                "strict" => self.strict_read_errors,
+            );
+        }
+        if self.strict_read_errors {
+            Some(Error::Other(box_err!(
+                "range cache engine strict read error: {:?}",
+                e
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+impl<EK> HybridEngineSnapshot<EK, RangeCacheMemoryEngine>
+where
+    EK: KvEngine,
+{
+    /// Same as `Iterable::iterator_opt`, but when the read is served by the
+    /// range cache engine the iterator flushes its read counters into `sink`
+    /// on drop, so the caller can attribute per-request read stats. Reads
+    /// routed to the disk snapshot leave the sink untouched; its counters
+    /// stay zero and the caller falls back to the usual perf context
+    /// harvesting.
+    pub fn iterator_opt_with_stats(
+        &self,
+        cf: &str,
+        opts: IterOptions,
+        sink: Arc<LocalStatsSink>,
+    ) -> Result<HybridEngineIterator<EK, RangeCacheMemoryEngine>> {
+        match self.range_cache_snap() {
+            Some(range_cache_snap) if is_data_cf(cf) && self.range_cache_cfs.contains(cf) => {
+                match range_cache_snap.iterator_opt_with_stats(cf, opts.clone(), sink) {
+                    Ok(iter) => return Ok(HybridEngineIterator::range_cache_engine_iterator(iter)),
+                    Err(e) => {
+                        if let Some(e) = self.on_unexpected_read_error("iter", cf, None, e) {
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(HybridEngineIterator::disk_engine_iterator(
+            self.disk_snap.iterator_opt(cf, opts)?,
+        ))
+    }
 }
 
 impl<EK, EC> Snapshot for HybridEngineSnapshot<EK, EC>
@@ -68,14 +176,22 @@ where
     type Iterator = HybridEngineIterator<EK, EC>;
 
     fn iterator_opt(&self, cf: &str, opts: IterOptions) -> Result<Self::Iterator> {
-        Ok(match self.range_cache_snap() {
-            Some(range_cache_snap) if is_data_cf(cf) => {
-                HybridEngineIterator::range_cache_engine_iterator(
-                    range_cache_snap.iterator_opt(cf, opts)?,
-                )
+        match self.range_cache_snap() {
+            Some(range_cache_snap) if is_data_cf(cf) && self.range_cache_cfs.contains(cf) => {
+                match range_cache_snap.iterator_opt(cf, opts.clone()) {
+                    Ok(iter) => return Ok(HybridEngineIterator::range_cache_engine_iterator(iter)),
+                    Err(e) => {
+                        if let Some(e) = self.on_unexpected_read_error("iter", cf, None, e) {
+                            return Err(e);
+                        }
+                    }
+                }
             }
-            _ => HybridEngineIterator::disk_engine_iterator(self.disk_snap.iterator_opt(cf, opts)?),
-        })
+            _ => {}
+        }
+        Ok(HybridEngineIterator::disk_engine_iterator(
+            self.disk_snap.iterator_opt(cf, opts)?,
+        ))
     }
 }
 
@@ -97,12 +213,37 @@ where
         key: &[u8],
     ) -> Result<Option<Self::DbVector>> {
         match self.range_cache_snap() {
-            Some(range_cache_snap) if is_data_cf(cf) => {
-                Self::DbVector::try_from_cache_snap(range_cache_snap, opts, cf, key)
+            Some(range_cache_snap) if is_data_cf(cf) && self.range_cache_cfs.contains(cf) => {
+                match Self::DbVector::try_from_cache_snap(range_cache_snap, opts, cf, key) {
+                    Ok(v) => Ok(v),
+                    Err(e) => {
+                        if let Some(e) = self.on_unexpected_read_error("get", cf, Some(key), e) {
+                            return Err(e);
+                        }
+                        Self::DbVector::try_from_disk_snap(&self.disk_snap, opts, cf, key)
+                    }
+                }
             }
             _ => Self::DbVector::try_from_disk_snap(&self.disk_snap, opts, cf, key),
         }
     }
+
+    fn multi_get_value_cf(&self, cf: &str, keys: &[&[u8]]) -> Result<Vec<Option<Self::DbVector>>> {
+        match self.range_cache_snap() {
+            Some(range_cache_snap) if is_data_cf(cf) && self.range_cache_cfs.contains(cf) => {
+                match Self::DbVector::try_multi_from_cache_snap(range_cache_snap, cf, keys) {
+                    Ok(v) => Ok(v),
+                    Err(e) => {
+                        if let Some(e) = self.on_unexpected_read_error("multi_get", cf, None, e) {
+                            return Err(e);
+                        }
+                        Self::DbVector::try_multi_from_disk_snap(&self.disk_snap, cf, keys)
+                    }
+                }
+            }
+            _ => Self::DbVector::try_multi_from_disk_snap(&self.disk_snap, cf, keys),
+        }
+    }
 }
 
 impl<EK, EC> CfNamesExt for HybridEngineSnapshot<EK, EC>
@@ -128,11 +269,15 @@ where
 #[cfg(test)]
 mod tests {
 
+    use std::time::Duration;
+
     use engine_traits::{
-        CacheRange, IterOptions, Iterable, Iterator, KvEngine, Mutable, SnapshotContext,
-        WriteBatch, WriteBatchExt, CF_DEFAULT,
+        CacheRange, CachedCfs, IterOptions, Iterable, Iterator, KvEngine, Mutable, Peekable,
+        ReadOptions, SnapshotContext, SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT, CF_LOCK,
+        CF_WRITE,
     };
     use range_cache_memory_engine::{RangeCacheEngineConfig, RangeCacheStatus};
+    use txn_types::{Key, TimeStamp, Write, WriteType};
 
     use crate::util::hybrid_engine_for_tests;
 
@@ -172,6 +317,7 @@ mod tests {
         let ctx = SnapshotContext {
             range: Some(range.clone()),
             read_ts: 10,
+            required_apply_index: None,
         };
         let snap = hybrid_engine.snapshot(Some(ctx));
         {
@@ -183,4 +329,236 @@ mod tests {
             assert_eq!(actual_value, b"world");
         }
     }
+
+    // A range loaded with the write cf only serves write cf point reads,
+    // including the short values inlined in the write records, entirely from
+    // the cache, while default cf reads for long values keep going to disk.
+    #[test]
+    fn test_partial_cached_cfs_read_routing() {
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        let (_path, hybrid_engine) =
+            hybrid_engine_for_tests("temp", RangeCacheEngineConfig::config_for_test(), |_| {})
+                .unwrap();
+
+        // A short value is inlined in its write record; a long value lives in
+        // the default cf and is only referenced by its write record.
+        let k_short = Key::from_raw(b"k01")
+            .append_ts(TimeStamp::new(5))
+            .into_encoded();
+        let k_long = Key::from_raw(b"k02")
+            .append_ts(TimeStamp::new(5))
+            .into_encoded();
+        let short_write = Write::new(WriteType::Put, TimeStamp::new(5), Some(b"short".to_vec()))
+            .as_ref()
+            .to_bytes();
+        let long_write = Write::new(WriteType::Put, TimeStamp::new(5), None)
+            .as_ref()
+            .to_bytes();
+        let long_value = vec![b'v'; 1024];
+
+        let disk_engine = hybrid_engine.disk_engine();
+        disk_engine.put_cf(CF_WRITE, &k_short, &short_write).unwrap();
+        disk_engine.put_cf(CF_WRITE, &k_long, &long_write).unwrap();
+        disk_engine.put_cf(CF_DEFAULT, &k_long, &long_value).unwrap();
+
+        hybrid_engine
+            .range_cache_engine()
+            .load_range_with_cfs(range.clone(), CachedCfs::from_cfs(&[CF_WRITE]))
+            .unwrap();
+        // Applying a write over the range schedules the pending load.
+        let mut write_batch = hybrid_engine.write_batch();
+        write_batch.prepare_for_range(range.clone());
+        write_batch.put_cf(CF_WRITE, b"k09", b"v").unwrap();
+        write_batch.write().unwrap();
+        // wait for background load
+        std::thread::sleep(Duration::from_secs(1));
+
+        let ctx = SnapshotContext {
+            range: Some(range.clone()),
+            read_ts: 10,
+            required_apply_index: None,
+        };
+        let disk_only_snap = hybrid_engine.snapshot(None);
+        // Diverge the disk write cf after the load so that a matching read
+        // proves the cache served it without touching disk.
+        disk_engine.put_cf(CF_WRITE, &k_short, b"garbage").unwrap();
+
+        let snap = hybrid_engine.snapshot(Some(ctx));
+        assert!(snap.range_cache_snapshot_available());
+        let opts = ReadOptions::default();
+        let v = snap.get_value_cf_opt(&opts, CF_WRITE, &k_short).unwrap();
+        assert_eq!(&*v.unwrap(), short_write.as_slice());
+        let v = snap.get_value_cf_opt(&opts, CF_WRITE, &k_long).unwrap();
+        assert_eq!(&*v.unwrap(), long_write.as_slice());
+        // The default cf is not cached, so the long value read is routed to
+        // the disk snapshot rather than missing in the cache.
+        let v = snap.get_value_cf_opt(&opts, CF_DEFAULT, &k_long).unwrap();
+        assert_eq!(&*v.unwrap(), long_value.as_slice());
+        let v = disk_only_snap
+            .get_value_cf_opt(&opts, CF_DEFAULT, &k_long)
+            .unwrap();
+        assert_eq!(&*v.unwrap(), long_value.as_slice());
+    }
+
+    // A batched lock cf read is served by the range cache engine when the
+    // lock cf is cached, and by the disk snapshot otherwise.
+    #[test]
+    fn test_multi_get_lock_cf_routing() {
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        let (_path, hybrid_engine) =
+            hybrid_engine_for_tests("temp", RangeCacheEngineConfig::config_for_test(), |_| {})
+                .unwrap();
+
+        // Every other key holds a lock.
+        let keys: Vec<Vec<u8>> = (0..4)
+            .map(|i| Key::from_raw(format!("k{:02}", i).as_bytes()).into_encoded())
+            .collect();
+        let disk_engine = hybrid_engine.disk_engine();
+        for (i, key) in keys.iter().enumerate() {
+            if i % 2 == 0 {
+                disk_engine
+                    .put_cf(CF_LOCK, key, format!("lock-{}", i).as_bytes())
+                    .unwrap();
+            }
+        }
+
+        hybrid_engine
+            .range_cache_engine()
+            .load_range_with_cfs(range.clone(), CachedCfs::from_cfs(&[CF_LOCK]))
+            .unwrap();
+        // Applying a write over the range schedules the pending load.
+        let mut write_batch = hybrid_engine.write_batch();
+        write_batch.prepare_for_range(range.clone());
+        write_batch.put_cf(CF_LOCK, b"k09", b"v").unwrap();
+        write_batch.write().unwrap();
+        // wait for background load
+        std::thread::sleep(Duration::from_secs(1));
+
+        // Diverge the disk lock cf after the load so a matching batched read
+        // proves the cache served it.
+        disk_engine.put_cf(CF_LOCK, &keys[0], b"garbage").unwrap();
+
+        let ctx = SnapshotContext {
+            range: Some(range.clone()),
+            read_ts: 10,
+            required_apply_index: None,
+        };
+        let snap = hybrid_engine.snapshot(Some(ctx));
+        assert!(snap.range_cache_snapshot_available());
+        let key_refs: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+        let values = snap.multi_get_value_cf(CF_LOCK, &key_refs).unwrap();
+        for (i, v) in values.iter().enumerate() {
+            if i % 2 == 0 {
+                assert_eq!(v.as_deref().unwrap(), format!("lock-{}", i).as_bytes());
+            } else {
+                assert!(v.is_none());
+            }
+        }
+
+        // Without a cache snapshot the batch goes to disk and observes the
+        // diverged value.
+        let disk_snap = hybrid_engine.snapshot(None);
+        let values = disk_snap.multi_get_value_cf(CF_LOCK, &key_refs).unwrap();
+        assert_eq!(values[0].as_deref().unwrap(), b"garbage".as_slice());
+    }
+
+    // Keys of an API v2 keyspace carry the keyspace prefix between the 'z'
+    // data prefix and the mvcc timestamp. Gets, scans and prefix seeks over
+    // such a range must return exactly what the disk engine returns.
+    #[test]
+    fn test_api_v2_keyspace_reads() {
+        // The txn-mode keyspace [0, 0, 1].
+        let range = CacheRange::new(b"zx\x00\x00\x01".to_vec(), b"zx\x00\x00\x02".to_vec());
+        let (_path, hybrid_engine) =
+            hybrid_engine_for_tests("temp", RangeCacheEngineConfig::config_for_test(), |_| {})
+                .unwrap();
+
+        let make_key = |user: &[u8], ts: u64| {
+            let mut raw = b"x\x00\x00\x01".to_vec();
+            raw.extend_from_slice(user);
+            let mut key = b"z".to_vec();
+            key.extend(
+                Key::from_raw(&raw)
+                    .append_ts(TimeStamp::new(ts))
+                    .into_encoded(),
+            );
+            key
+        };
+        let write = Write::new(WriteType::Put, TimeStamp::new(5), Some(b"value".to_vec()))
+            .as_ref()
+            .to_bytes();
+
+        // Two versions of k2 surrounded by single versions of k1 and k3.
+        let disk_engine = hybrid_engine.disk_engine();
+        for (user, ts) in [(b"k1" as &[u8], 5), (b"k2", 5), (b"k2", 6), (b"k3", 5)] {
+            disk_engine
+                .put_cf(CF_WRITE, &make_key(user, ts), &write)
+                .unwrap();
+        }
+
+        hybrid_engine
+            .range_cache_engine()
+            .load_range(range.clone())
+            .unwrap();
+        // Applying a write over the range schedules the pending load.
+        let mut write_batch = hybrid_engine.write_batch();
+        write_batch.prepare_for_range(range.clone());
+        write_batch
+            .put_cf(CF_WRITE, &make_key(b"k4", 5), &write)
+            .unwrap();
+        write_batch.write().unwrap();
+        // wait for background load
+        std::thread::sleep(Duration::from_secs(1));
+
+        let disk_only_snap = hybrid_engine.snapshot(None);
+        let ctx = SnapshotContext {
+            range: Some(range.clone()),
+            read_ts: 10,
+            required_apply_index: None,
+        };
+        let snap = hybrid_engine.snapshot(Some(ctx));
+        assert!(snap.range_cache_snapshot_available());
+
+        fn scan<S: Iterable>(snap: &S, opts: IterOptions, seek: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+            let mut iter = snap.iterator_opt(CF_WRITE, opts).unwrap();
+            let mut valid = iter.seek(seek).unwrap();
+            let mut res = vec![];
+            while valid {
+                res.push((iter.key().to_vec(), iter.value().to_vec()));
+                valid = iter.next().unwrap();
+            }
+            res
+        }
+
+        // Point gets of the keyspace-prefixed keys.
+        let opts = ReadOptions::default();
+        for (user, ts) in [(b"k1" as &[u8], 5), (b"k2", 6), (b"k3", 5)] {
+            let v = snap
+                .get_value_cf_opt(&opts, CF_WRITE, &make_key(user, ts))
+                .unwrap();
+            assert_eq!(&*v.unwrap(), write.as_slice());
+        }
+
+        // A full scan of the range agrees with the disk engine.
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_lower_bound(&range.start, 0);
+        iter_opt.set_upper_bound(&range.end, 0);
+        let cached = scan(&snap, iter_opt.clone(), &range.start);
+        let from_disk = scan(&disk_only_snap, iter_opt.clone(), &range.start);
+        assert_eq!(cached.len(), 5);
+        assert_eq!(cached, from_disk);
+
+        // A prefix seek stops at the keyspace-prefixed user key boundary:
+        // only the two versions of k2 are returned.
+        iter_opt.set_prefix_same_as_start(true);
+        let seek = make_key(b"k2", TimeStamp::max().into_inner());
+        let cached = scan(&snap, iter_opt, &seek);
+        assert_eq!(
+            cached,
+            vec![
+                (make_key(b"k2", 6), write.clone()),
+                (make_key(b"k2", 5), write.clone()),
+            ]
+        );
+    }
 }
@@ -48,6 +48,15 @@ impl<EK: KvEngine> WriteBatch for HybridEngineWriteBatch<EK> {
             .write_callback_opt(opts, |s| {
                 if !called.fetch_or(true, Ordering::SeqCst) {
                     self.cache_write_batch.set_sequence_number(s).unwrap();
+                    // The cache entries take `[s, s + count)` in order,
+                    // mirroring the sequences RocksDB assigned to the disk
+                    // batch.
+                    debug_assert_eq!(self.cache_write_batch.sequence_range().unwrap().0, s);
+                    // Record the shadow disk checksums before the cache
+                    // consumes the entries, see the `checksum` module of the
+                    // cache engine. A no-op unless checksum verification is
+                    // enabled.
+                    self.cache_write_batch.record_disk_shadow_checksums();
                     self.cache_write_batch.write_opt(opts).unwrap();
                 }
             })
@@ -103,6 +112,14 @@ impl<EK: KvEngine> WriteBatch for HybridEngineWriteBatch<EK> {
     fn prepare_for_range(&mut self, range: CacheRange) {
         self.cache_write_batch.prepare_for_range(range);
     }
+
+    fn set_range_applied_index(&mut self, apply_index: u64) {
+        self.cache_write_batch.set_range_applied_index(apply_index);
+    }
+
+    fn set_range_applied_term(&mut self, term: u64) {
+        self.cache_write_batch.set_range_applied_term(term);
+    }
 }
 
 impl<EK: KvEngine> Mutable for HybridEngineWriteBatch<EK> {
@@ -188,6 +205,7 @@ mod tests {
         let ctx = SnapshotContext {
             range: Some(range.clone()),
             read_ts: 10,
+            required_apply_index: None,
         };
         let snap = hybrid_engine.snapshot(Some(ctx));
         let actual: &[u8] = &snap.get_value(b"hello").unwrap().unwrap();
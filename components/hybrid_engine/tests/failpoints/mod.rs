@@ -1,3 +1,4 @@
 // Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
 
+mod test_read_errors;
 mod test_write_batch;
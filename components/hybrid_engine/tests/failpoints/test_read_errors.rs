@@ -0,0 +1,106 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use engine_rocks::RocksEngine;
+use engine_traits::{
+    CacheRange, IterOptions, Iterable, Iterator, KvEngine, Mutable, Peekable, SnapshotContext,
+    SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT,
+};
+use hybrid_engine::{
+    util::hybrid_engine_for_tests, HybridEngine, RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC,
+};
+use range_cache_memory_engine::{RangeCacheEngineConfig, RangeCacheMemoryEngine};
+use tempfile::TempDir;
+
+// Builds a hybrid engine whose cache and disk engines hold different values
+// for `k05` ("cache" and "disk" respectively), so a read tells which engine
+// served it.
+fn prepared_engine(
+    strict_read_errors: bool,
+) -> (
+    TempDir,
+    HybridEngine<RocksEngine, RangeCacheMemoryEngine>,
+    CacheRange,
+) {
+    let mut config = RangeCacheEngineConfig::config_for_test();
+    config.strict_read_errors = strict_read_errors;
+    let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+    let range_clone = range.clone();
+    let (path, hybrid_engine) = hybrid_engine_for_tests("temp", config, move |memory_engine| {
+        memory_engine.new_range(range_clone.clone());
+        let mut core = memory_engine.core().write();
+        core.mut_range_manager().set_safe_point(&range_clone, 5);
+    })
+    .unwrap();
+    let mut wb = hybrid_engine.write_batch();
+    wb.prepare_for_range(range.clone());
+    wb.put(b"k05", b"cache").unwrap();
+    wb.write().unwrap();
+    hybrid_engine.disk_engine().put(b"k05", b"disk").unwrap();
+    (path, hybrid_engine, range)
+}
+
+fn snap_ctx(range: &CacheRange) -> SnapshotContext {
+    SnapshotContext {
+        range: Some(range.clone()),
+        read_ts: 10,
+        required_apply_index: None,
+    }
+}
+
+fn error_count(op: &str) -> u64 {
+    RANGE_CACHE_UNEXPECTED_READ_ERROR_COUNT_VEC
+        .with_label_values(&[op])
+        .get()
+}
+
+// The scenarios share process-global failpoints, so they run in one test to
+// avoid interfering with each other.
+#[test]
+fn test_unexpected_read_error_strictness() {
+    let (_path, hybrid_engine, range) = prepared_engine(false);
+    let snap = hybrid_engine.snapshot(Some(snap_ctx(&range)));
+    assert!(snap.range_cache_snapshot_available());
+    let mut iter_opt = IterOptions::default();
+    iter_opt.set_lower_bound(&range.start, 0);
+    iter_opt.set_upper_bound(&range.end, 0);
+
+    // Without injected errors the reads are served from the cache.
+    assert_eq!(snap.get_value(b"k05").unwrap().unwrap(), &b"cache"[..]);
+
+    // By default an unexpected cache read error is counted and the read falls
+    // back to the disk snapshot.
+    let get_errors = error_count("get");
+    fail::cfg("on_range_cache_get_value", "return").unwrap();
+    assert_eq!(snap.get_value(b"k05").unwrap().unwrap(), &b"disk"[..]);
+    assert_eq!(error_count("get"), get_errors + 1);
+
+    let iter_errors = error_count("iter");
+    fail::cfg("on_range_cache_iterator_opt", "return").unwrap();
+    let mut iter = snap.iterator_opt(CF_DEFAULT, iter_opt.clone()).unwrap();
+    assert!(iter.seek_to_first().unwrap());
+    assert_eq!(iter.key(), b"k05");
+    assert_eq!(iter.value(), b"disk");
+    assert_eq!(error_count("iter"), iter_errors + 1);
+
+    // In strict mode the same injected errors fail the request instead.
+    let (_path, hybrid_engine, range) = prepared_engine(true);
+    let snap = hybrid_engine.snapshot(Some(snap_ctx(&range)));
+    assert!(snap.range_cache_snapshot_available());
+    let err = snap.get_value(b"k05").unwrap_err();
+    assert!(
+        format!("{}", err).contains("strict read error"),
+        "{:?}",
+        err
+    );
+    let err = snap.iterator_opt(CF_DEFAULT, iter_opt).unwrap_err();
+    assert!(
+        format!("{}", err).contains("strict read error"),
+        "{:?}",
+        err
+    );
+
+    // Once the errors stop, strict mode reads are served from the cache again.
+    fail::remove("on_range_cache_get_value");
+    fail::remove("on_range_cache_iterator_opt");
+    assert_eq!(snap.get_value(b"k05").unwrap().unwrap(), &b"cache"[..]);
+}
@@ -53,6 +53,7 @@ pub const RAFT_LOG_SUFFIX: u8 = 0x01;
 pub const RAFT_STATE_SUFFIX: u8 = 0x02;
 pub const APPLY_STATE_SUFFIX: u8 = 0x03;
 pub const SNAPSHOT_RAFT_STATE_SUFFIX: u8 = 0x04;
+pub const SNAPSHOT_APPLY_PROGRESS_SUFFIX: u8 = 0x05;
 
 // For region meta
 pub const REGION_STATE_SUFFIX: u8 = 0x01;
@@ -104,6 +105,10 @@ pub fn apply_state_key(region_id: u64) -> [u8; 11] {
     make_region_prefix(region_id, APPLY_STATE_SUFFIX)
 }
 
+pub fn snapshot_apply_progress_key(region_id: u64) -> [u8; 11] {
+    make_region_prefix(region_id, SNAPSHOT_APPLY_PROGRESS_SUFFIX)
+}
+
 /// Get the log index from raft log key generated by `raft_log_key`.
 pub fn raft_log_index(key: &[u8]) -> Result<u64> {
     let expect_key_len = REGION_RAFT_PREFIX_KEY.len()
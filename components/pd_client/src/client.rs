@@ -607,6 +607,8 @@ impl PdClient for RpcClient {
         req.set_approximate_size(region_stat.approximate_size);
         req.set_approximate_keys(region_stat.approximate_keys);
         req.set_cpu_usage(region_stat.cpu_usage);
+        // TODO: attach `region_stat.pending_admin` once the heartbeat proto
+        // has a field for it.
         if let Some(s) = replication_status {
             req.set_replication_status(s);
         }
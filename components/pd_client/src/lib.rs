@@ -21,6 +21,7 @@ use futures::future::BoxFuture;
 use kvproto::{
     metapb,
     pdpb::{self, UpdateServiceGcSafePointRequest, UpdateServiceGcSafePointResponse},
+    raft_cmdpb::AdminCmdType,
     replication_modepb::{RegionReplicationStatus, ReplicationStatus, StoreDrAutoSyncStatus},
     resource_manager::TokenBucketsRequest,
 };
@@ -58,6 +59,11 @@ pub struct RegionStat {
     // cpu_usage is the CPU time usage of the leader region since the last heartbeat,
     // which is calculated by cpu_time_delta/heartbeat_reported_interval.
     pub cpu_usage: u64,
+    // The admin command (e.g. a split waiting for its pre-flush) in progress
+    // on the region, so the scheduler can avoid generating conflicting
+    // operators for it. Not on the wire yet: the heartbeat field is pending
+    // in kvproto.
+    pub pending_admin: Option<AdminCmdType>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -14,7 +14,7 @@ use batch_system::{
     BasicMailbox, BatchRouter, BatchSystem, HandleResult, HandlerBuilder, PollHandler,
 };
 use causal_ts::CausalTsProviderImpl;
-use collections::HashMap;
+use collections::{HashMap, HashSet};
 use concurrency_manager::ConcurrencyManager;
 use crossbeam::channel::TrySendError;
 use encryption_export::DataKeyManager;
@@ -59,7 +59,9 @@ use time::Timespec;
 use crate::{
     fsm::{PeerFsm, PeerFsmDelegate, SenderFsmPair, StoreFsm, StoreFsmDelegate, StoreMeta},
     operation::{
-        ReplayWatch, SharedReadTablet, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX, SPLIT_PREFIX,
+        AdminResultSubscriber, AdminResultSubscribers, MergeCatchUpLimiter,
+        PdReportBatchSplitSubscriber, PurgeHintAggregator, ReplayWatch, SharedReadTablet,
+        MAX_PURGE_HINTS_PER_BATCH, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX, SPLIT_PREFIX,
     },
     raft::Storage,
     router::{PeerMsg, PeerTick, StoreMsg},
@@ -76,6 +78,9 @@ pub struct StoreContext<EK: KvEngine, ER: RaftEngine, T> {
     pub logger: Logger,
     pub store_id: u64,
     pub coprocessor_host: CoprocessorHost<EK>,
+    /// Subscribers that are notified synchronously after admin command
+    /// results have been applied.
+    pub admin_result_subscribers: AdminResultSubscribers,
     /// The transport for sending messages to peers on other stores.
     pub trans: T,
     pub has_ready: bool,
@@ -115,6 +120,16 @@ pub struct StoreContext<EK: KvEngine, ER: RaftEngine, T> {
     pub sst_importer: Arc<SstImporter<EK>>,
     pub key_manager: Option<Arc<DataKeyManager>>,
 
+    /// Store level limiter for concurrent merge catch-up-logs operations.
+    pub merge_catch_up_limiter: MergeCatchUpLimiter,
+
+    /// Store level deduplication of externally requested memtable flushes.
+    pub flush_dedup: tablet::FlushDedup,
+
+    /// Store level aggregator of raft log purge hints, consumed by the purge
+    /// worker.
+    pub purge_hints: PurgeHintAggregator,
+
     /// Inspector for latency inspecting
     pub pending_latency_inspect: Vec<LatencyInspector>,
 }
@@ -369,6 +384,14 @@ struct StorePollerBuilder<EK: KvEngine, ER: RaftEngine, T> {
     sst_importer: Arc<SstImporter<EK>>,
     key_manager: Option<Arc<DataKeyManager>>,
     node_start_time: Timespec, // monotonic_raw_now
+    // Shared by all pollers so that the limit is enforced store wide.
+    merge_catch_up_limiter: MergeCatchUpLimiter,
+    // Shared by all pollers so that duplicate flush requests are collapsed
+    // store wide.
+    flush_dedup: tablet::FlushDedup,
+    admin_result_subscribers: AdminResultSubscribers,
+    // Shared with the purge worker which consumes the hints.
+    purge_hints: PurgeHintAggregator,
 }
 
 impl<EK: KvEngine, ER: RaftEngine, T> StorePollerBuilder<EK, ER, T> {
@@ -389,6 +412,8 @@ impl<EK: KvEngine, ER: RaftEngine, T> StorePollerBuilder<EK, ER, T> {
         sst_importer: Arc<SstImporter<EK>>,
         key_manager: Option<Arc<DataKeyManager>>,
         node_start_time: Timespec, // monotonic_raw_now
+        admin_result_subscribers: AdminResultSubscribers,
+        purge_hints: PurgeHintAggregator,
     ) -> Self {
         let pool_size = cfg.value().apply_batch_system.pool_size;
         let max_pool_size = std::cmp::max(
@@ -420,6 +445,10 @@ impl<EK: KvEngine, ER: RaftEngine, T> StorePollerBuilder<EK, ER, T> {
             sst_importer,
             key_manager,
             node_start_time,
+            merge_catch_up_limiter: MergeCatchUpLimiter::default(),
+            flush_dedup: tablet::FlushDedup::default(),
+            admin_result_subscribers,
+            purge_hints,
         }
     }
 
@@ -577,6 +606,10 @@ where
             store_stat: self.global_stat.local(),
             sst_importer: self.sst_importer.clone(),
             key_manager: self.key_manager.clone(),
+            merge_catch_up_limiter: self.merge_catch_up_limiter.clone(),
+            flush_dedup: self.flush_dedup.clone(),
+            admin_result_subscribers: self.admin_result_subscribers.clone(),
+            purge_hints: self.purge_hints.clone(),
             pending_latency_inspect: vec![],
         };
         poll_ctx.update_ticks_timeout();
@@ -677,9 +710,22 @@ pub struct StoreSystem<EK: KvEngine, ER: RaftEngine> {
     logger: Logger,
     shutdown: Arc<AtomicBool>,
     node_start_time: Timespec, // monotonic_raw_now
+    admin_result_subscribers: Vec<Arc<dyn AdminResultSubscriber>>,
+    purge_hints: PurgeHintAggregator,
 }
 
 impl<EK: KvEngine, ER: RaftEngine> StoreSystem<EK, ER> {
+    /// Registers a subscriber that is notified synchronously after admin
+    /// command results have been applied. Must be called before `start`;
+    /// registrations afterwards have no effect.
+    pub fn register_admin_result_subscriber(&mut self, subscriber: Arc<dyn AdminResultSubscriber>) {
+        self.admin_result_subscribers.push(subscriber);
+    }
+
+    /// The store level aggregator of raft log purge hints.
+    pub fn purge_hints(&self) -> &PurgeHintAggregator {
+        &self.purge_hints
+    }
     pub fn start<T, C>(
         &mut self,
         store_id: u64,
@@ -723,6 +769,7 @@ impl<EK: KvEngine, ER: RaftEngine> StoreSystem<EK, ER> {
             let logger = self.logger.clone();
             let router = router.clone();
             let registry = tablet_registry.clone();
+            let purge_hints = self.purge_hints.clone();
             let base_max_rate = cfg
                 .value()
                 .max_manual_flush_rate
@@ -761,8 +808,18 @@ impl<EK: KvEngine, ER: RaftEngine> StoreSystem<EK, ER> {
                 let mut to_flush = (rate * MAX_MANUAL_FLUSH_PERIOD.as_secs_f64()) as usize;
                 // Skip tablets that are flushed elsewhere.
                 let threshold = std::time::SystemTime::now() - MAX_MANUAL_FLUSH_PERIOD;
+                // Regions that recently applied a `CompactLog` have already
+                // truncated their logs; force-compacting them again in the
+                // same tick only duplicates work.
+                let hinted: HashSet<u64> = purge_hints
+                    .consume(MAX_PURGE_HINTS_PER_BATCH)
+                    .into_iter()
+                    .map(|(region_id, _)| region_id)
+                    .collect();
                 for r in &regions {
-                    let _ = router.send(*r, PeerMsg::ForceCompactLog);
+                    if !hinted.contains(r) {
+                        let _ = router.send(*r, PeerMsg::ForceCompactLog);
+                    }
                 }
                 let registry = registry.clone();
                 let logger = logger.clone();
@@ -859,6 +916,15 @@ impl<EK: KvEngine, ER: RaftEngine> StoreSystem<EK, ER> {
             refresh_config: refresh_config_scheduler,
         };
 
+        // The pd split report only needs the final region metadata, so it is
+        // wired as a built-in subscriber after the registered ones.
+        let mut admin_result_subscribers = std::mem::take(&mut self.admin_result_subscribers);
+        admin_result_subscribers.push(Arc::new(PdReportBatchSplitSubscriber::new(
+            schedulers.pd.clone(),
+            self.logger.clone(),
+        )));
+        let admin_result_subscribers = AdminResultSubscribers::new(admin_result_subscribers);
+
         let builder = StorePollerBuilder::new(
             cfg.clone(),
             store_id,
@@ -876,6 +942,8 @@ impl<EK: KvEngine, ER: RaftEngine> StoreSystem<EK, ER> {
             sst_importer,
             key_manager,
             self.node_start_time,
+            admin_result_subscribers,
+            self.purge_hints.clone(),
         );
 
         self.schedulers = Some(schedulers);
@@ -1057,6 +1125,8 @@ where
         logger: logger.clone(),
         shutdown: Arc::new(AtomicBool::new(false)),
         node_start_time: monotonic_raw_now(),
+        admin_result_subscribers: Vec::new(),
+        purge_hints: PurgeHintAggregator::default(),
     };
     (StoreRouter { router, logger }, system)
 }
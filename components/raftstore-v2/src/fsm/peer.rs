@@ -300,6 +300,10 @@ impl<'a, EK: KvEngine, ER: RaftEngine, T: Transport> PeerFsmDelegate<'a, EK, ER,
                     self.fsm.peer_mut().on_snapshot_generated(snap_res)
                 }
                 PeerMsg::QueryDebugInfo(ch) => self.fsm.peer_mut().on_query_debug_info(ch),
+                PeerMsg::WarmUpEntryCache { up_to_index, ch } => self
+                    .fsm
+                    .peer_mut()
+                    .on_warm_up_entry_cache(self.store_ctx, up_to_index, ch),
                 PeerMsg::DataFlushed {
                     cf,
                     tablet_index,
@@ -40,7 +40,10 @@ pub(crate) use batch::StoreContext;
 pub use batch::{create_store_batch_system, StoreRouter, StoreSystem};
 pub use bootstrap::Bootstrap;
 pub use fsm::StoreMeta;
-pub use operation::{write_initial_states, SimpleWriteBinary, SimpleWriteEncoder, StateStorage};
+pub use operation::{
+    write_initial_states, AdminResultSubscriber, PurgeHintAggregator, SimpleWriteBinary,
+    SimpleWriteEncoder, StateStorage, MAX_PURGE_HINTS_PER_BATCH,
+};
 pub use raftstore::{store::Config, Error, Result};
 pub use worker::{
     cleanup::CompactTask,
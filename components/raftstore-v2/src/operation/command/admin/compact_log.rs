@@ -17,18 +17,20 @@ use std::{
     path::PathBuf,
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex,
     },
 };
 
+use collections::HashMap;
 use engine_traits::{KvEngine, RaftEngine, RaftLogBatch};
 use kvproto::raft_cmdpb::{AdminCmdType, AdminRequest, AdminResponse, RaftCmdRequest};
 use protobuf::Message;
 use raftstore::{
     store::{
-        entry_storage::MAX_WARMED_UP_CACHE_KEEP_TIME, fsm::new_admin_request,
-        metrics::REGION_MAX_LOG_LAG, needs_evict_entry_cache, Transport, WriteTask,
-        RAFT_INIT_LOG_INDEX,
+        entry_storage::MAX_WARMED_UP_CACHE_KEEP_TIME,
+        fsm::new_admin_request,
+        metrics::{RAFT_LOG_PURGEABLE_BYTES_GAUGE, REGION_MAX_LOG_LAG},
+        needs_evict_entry_cache, Transport, WriteTask, RAFT_INIT_LOG_INDEX,
     },
     Result,
 };
@@ -106,6 +108,80 @@ impl CompactLogContext {
     }
 }
 
+/// Soft cap on how many purge hints the purge trigger consumes in one tick.
+///
+/// Keeps a single pass of the purge worker bounded when a mass ingest makes
+/// many regions compact their logs at once; the remainder is picked up by
+/// later ticks.
+pub const MAX_PURGE_HINTS_PER_BATCH: usize = 4096;
+
+/// Aggregates purge hints from applied `CompactLog` commands, store wide.
+///
+/// When many regions apply `CompactLog` in the same tick (typically right
+/// after a mass ingest), having every apply nudge the raft engine on its own
+/// floods the purge worker with a burst of work whose rewrite IO competes
+/// with the kv-db. Instead, the apply result path parks a `(region_id, new
+/// first index)` hint here and the purge trigger consumes them in batches at
+/// its own pace. A hint is only recorded after the truncated state and the
+/// log gc have been staged into the peer's extra write, so consuming a hint
+/// never compacts a region's logs ahead of the admin result being durably
+/// applied.
+///
+/// The sum of the purgeable bytes estimates of all parked hints is exported
+/// through `tikv_raftstore_raft_log_purgeable_bytes` so operators can see
+/// the reclamation lag.
+#[derive(Clone, Default)]
+pub struct PurgeHintAggregator {
+    inner: Arc<Mutex<PurgeHintAggregatorInner>>,
+}
+
+#[derive(Default)]
+struct PurgeHintAggregatorInner {
+    // region_id -> (new first index, estimated purgeable bytes).
+    hints: HashMap<u64, (u64, u64)>,
+    // Sum of the estimates of all parked hints.
+    total_bytes: u64,
+}
+
+impl PurgeHintAggregator {
+    /// Parks a hint that logs of `region_id` before `new_first_index` have
+    /// been truncated and roughly `purgeable_bytes` of them await
+    /// reclamation. Hints of the same region are merged.
+    pub fn record(&self, region_id: u64, new_first_index: u64, purgeable_bytes: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let hint = inner.hints.entry(region_id).or_insert((0, 0));
+        hint.0 = std::cmp::max(hint.0, new_first_index);
+        hint.1 += purgeable_bytes;
+        inner.total_bytes += purgeable_bytes;
+        RAFT_LOG_PURGEABLE_BYTES_GAUGE.set(inner.total_bytes as i64);
+    }
+
+    /// Takes up to `max_hints` parked hints as `(region_id, new first
+    /// index)` pairs. The rest is left for the next consumption.
+    pub fn consume(&self, max_hints: usize) -> Vec<(u64, u64)> {
+        let mut inner = self.inner.lock().unwrap();
+        let region_ids: Vec<_> = inner.hints.keys().take(max_hints).copied().collect();
+        let mut consumed = Vec::with_capacity(region_ids.len());
+        for region_id in region_ids {
+            let (first_index, bytes) = inner.hints.remove(&region_id).unwrap();
+            inner.total_bytes -= bytes;
+            consumed.push((region_id, first_index));
+        }
+        RAFT_LOG_PURGEABLE_BYTES_GAUGE.set(inner.total_bytes as i64);
+        consumed
+    }
+
+    /// Number of regions with a parked hint.
+    pub fn pending_hints(&self) -> usize {
+        self.inner.lock().unwrap().hints.len()
+    }
+
+    /// Current sum of the purgeable bytes estimates of all parked hints.
+    pub fn purgeable_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().total_bytes
+    }
+}
+
 impl<'a, EK: KvEngine, ER: RaftEngine, T: Transport> PeerFsmDelegate<'a, EK, ER, T> {
     pub fn on_compact_log_tick(&mut self, force: bool) {
         // Might read raft logs.
@@ -542,8 +618,23 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         let applied = context.last_applying_index;
         let total_cnt = applied - prev_first_index;
         let remain_cnt = applied - res.compact_index;
+        let prev_size = context.approximate_log_size;
         context.approximate_log_size =
-            (context.approximate_log_size as f64 * (remain_cnt as f64 / total_cnt as f64)) as u64;
+            (prev_size as f64 * (remain_cnt as f64 / total_cnt as f64)) as u64;
+
+        // Park a purge hint for the purge trigger instead of nudging the raft
+        // engine here. See `PurgeHintAggregator`.
+        store_ctx.purge_hints.record(
+            region_id,
+            res.compact_index + 1,
+            prev_size - context.approximate_log_size,
+        );
+
+        store_ctx.admin_result_subscribers.notify_compact_log(
+            &self.logger,
+            region_id,
+            res.compact_index,
+        );
     }
 
     /// Called when apply index is persisted.
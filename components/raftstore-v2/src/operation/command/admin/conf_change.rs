@@ -207,6 +207,8 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             RegionChangeEvent::Update(RegionChangeReason::ChangePeer),
             self.raft_group().raft.state,
         );
+        ctx.admin_result_subscribers
+            .notify_conf_change(&self.logger, self.region());
         if remove_self {
             // When self is destroyed, all metas will be cleaned in `start_destroy`.
             self.mark_for_destroy(None);
@@ -51,10 +51,14 @@
 
 use std::{
     any::Any,
-    cmp, fs, io,
+    cmp,
+    collections::VecDeque,
+    fs, io,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use collections::HashSet;
 use crossbeam::channel::SendError;
 use engine_traits::{KvEngine, RaftEngine, RaftLogBatch, TabletContext, TabletRegistry};
 use futures::channel::oneshot;
@@ -68,7 +72,12 @@ use raft::{GetEntriesContext, Storage, INVALID_ID, NO_LIMIT};
 use raftstore::{
     coprocessor::RegionChangeReason,
     store::{
-        fsm::new_admin_request, metrics::PEER_ADMIN_CMD_COUNTER, util, ProposalContext, Transport,
+        fsm::new_admin_request,
+        metrics::{
+            MERGE_CATCH_UP_QUEUE_GAUGE, MERGE_CATCH_UP_WAIT_DURATION_HISTOGRAM,
+            PEER_ADMIN_CMD_COUNTER,
+        },
+        util, ProposalContext, Transport,
     },
     Result,
 };
@@ -149,6 +158,83 @@ fn commit_of_merge(r: &CommitMergeRequest) -> u64 {
     r.get_source_state().get_merge_state().get_commit()
 }
 
+fn source_of_ask_commit_merge(req: &RaftCmdRequest) -> u64 {
+    req.get_admin_request()
+        .get_commit_merge()
+        .get_source_state()
+        .get_region()
+        .get_id()
+}
+
+/// Limits the number of concurrent catch-up-logs operations on a store.
+///
+/// Catching up the source region logs for `CommitMerge` can replay a large
+/// number of entries in the apply flow. When PD schedules many merges at once,
+/// running all the catch ups concurrently starves foreground writes of apply
+/// resources. `AskCommitMerge` requests exceeding
+/// `max_concurrent_merge_catch_up` are therefore queued here with their
+/// `CommitMerge` proposal deferred, and re-sent to the target peer one by one
+/// as running merges finish. A re-sent request goes through the full
+/// `on_ask_commit_merge` validation again, so region epoch changes that
+/// happened while it was waiting are respected.
+#[derive(Clone, Default)]
+pub struct MergeCatchUpLimiter {
+    inner: Arc<Mutex<MergeCatchUpLimiterInner>>,
+}
+
+#[derive(Default)]
+struct MergeCatchUpLimiterInner {
+    // Source region ids of the merges that hold a permit.
+    running: HashSet<u64>,
+    // Deferred `AskCommitMerge` requests in FIFO order, with the time they
+    // were queued.
+    queue: VecDeque<(RaftCmdRequest, Instant)>,
+}
+
+impl MergeCatchUpLimiter {
+    /// Tries to acquire a permit for the merge of `req`'s source region.
+    /// Returns false when the limit is reached, in which case the request is
+    /// queued and re-sent when a running merge finishes. A merge that already
+    /// holds a permit is admitted again so that a retried `AskCommitMerge`
+    /// can re-propose `CommitMerge`.
+    fn try_acquire(&self, limit: usize, req: &RaftCmdRequest) -> bool {
+        let source_id = source_of_ask_commit_merge(req);
+        let mut inner = self.inner.lock().unwrap();
+        if inner.running.contains(&source_id) {
+            return true;
+        }
+        if limit == 0 || inner.running.len() < limit {
+            inner.running.insert(source_id);
+            return true;
+        }
+        // The source peer retries `AskCommitMerge` periodically, don't queue
+        // the same merge twice.
+        if !inner
+            .queue
+            .iter()
+            .any(|(r, _)| source_of_ask_commit_merge(r) == source_id)
+        {
+            inner.queue.push_back((req.clone(), Instant::now_coarse()));
+            MERGE_CATCH_UP_QUEUE_GAUGE.set(inner.queue.len() as i64);
+        }
+        false
+    }
+
+    /// Releases the permit held by the merge of source region `source_id`, if
+    /// any, and returns the longest waiting deferred request.
+    fn release(&self, source_id: u64) -> Option<RaftCmdRequest> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.running.remove(&source_id) {
+            return None;
+        }
+        let (req, queued_at) = inner.queue.pop_front()?;
+        MERGE_CATCH_UP_QUEUE_GAUGE.set(inner.queue.len() as i64);
+        MERGE_CATCH_UP_WAIT_DURATION_HISTOGRAM
+            .observe(queued_at.saturating_elapsed().as_secs_f64());
+        Some(req)
+    }
+}
+
 // Source peer initiates commit merge on target peer.
 impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
     // Called after applying `PrepareMerge`.
@@ -236,6 +322,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             "commit_merge",
             true,
             &target.clone(),
+            None,
             Box::new(move || {
                 // If target peer is destroyed, life.rs is responsible for telling us to
                 // rollback.
@@ -341,6 +428,21 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             region
         );
         assert!(!self.storage().has_dirty_data());
+        // Replaying the source region logs can occupy an apply thread for a
+        // long time. When too many catch ups are running, defer the proposal:
+        // the limiter re-sends the request when a permit frees up and it is
+        // validated from scratch then.
+        if !store_ctx
+            .merge_catch_up_limiter
+            .try_acquire(store_ctx.cfg.max_concurrent_merge_catch_up, &req)
+        {
+            info!(
+                self.logger,
+                "defer commit merge due to too many concurrent catch ups";
+                "source" => source_id,
+            );
+            return;
+        }
         let (ch, res) = CmdResChannel::pair();
         self.on_admin_command(store_ctx, req, ch);
         if let Some(res) = res.take_result()
@@ -352,6 +454,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                 "source" => source_id,
                 "res" => ?res,
             );
+            self.release_merge_catch_up_permit(store_ctx, source_id);
             fail::fail_point!(
                 "on_propose_commit_merge_fail_store_1",
                 store_ctx.store_id == 1,
@@ -373,6 +476,33 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         let data = req.write_to_bytes().unwrap();
         self.propose_with_ctx(store_ctx, data, proposal_ctx)
     }
+
+    // Releases the catch up permit held by the merge of source region
+    // `source_id` and re-sends the longest waiting deferred `AskCommitMerge`,
+    // if any, so that it is validated and proposed with the current state.
+    fn release_merge_catch_up_permit<T>(
+        &self,
+        store_ctx: &mut StoreContext<EK, ER, T>,
+        source_id: u64,
+    ) {
+        let Some(req) = store_ctx.merge_catch_up_limiter.release(source_id) else {
+            return;
+        };
+        let target_id = req.get_header().get_region_id();
+        match store_ctx
+            .router
+            .force_send(target_id, PeerMsg::AskCommitMerge(req))
+        {
+            Ok(_) => (),
+            Err(SendError(PeerMsg::AskCommitMerge(msg))) => {
+                // The source peer retries the merge if this is lost.
+                let _ = store_ctx
+                    .router
+                    .force_send_control(StoreMsg::AskCommitMerge(msg));
+            }
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
@@ -800,6 +930,10 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         }
         self.record_tombstone_tablet_path(store_ctx, res.source_path, res.index);
 
+        // The catch up of the source region is over, admit the longest
+        // waiting deferred merge, if any.
+        self.release_merge_catch_up_permit(store_ctx, res.source.get_id());
+
         // make approximate size and keys updated in time.
         // the reason why follower need to update is that there is a issue that after
         // merge and then transfer leader, the new leader may have stale size and keys.
@@ -827,6 +961,10 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             self.add_pending_tick(PeerTick::SplitRegionCheck);
             self.maybe_schedule_gc_peer_tick();
         }
+
+        store_ctx
+            .admin_result_subscribers
+            .notify_merge(&self.logger, &res.source, self.region());
     }
 
     // Called on source peer.
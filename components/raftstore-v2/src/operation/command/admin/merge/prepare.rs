@@ -278,6 +278,21 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                 target_region.get_peers()
             ));
         }
+        // A merge moves the source tablet into the target, so every store
+        // must hold full data for both regions. A witness has no tablet to
+        // contribute and cannot ingest one; it must be switched out of the
+        // witness role before the regions can merge.
+        if let Some(p) = region
+            .get_peers()
+            .iter()
+            .chain(target_region.get_peers().iter())
+            .find(|p| p.is_witness)
+        {
+            return Err(box_err!(
+                "witness peer {:?} cannot provide data for merge, reject merge",
+                p
+            ));
+        }
         Ok(())
     }
 
@@ -454,8 +469,12 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                     }
                 };
                 let mut req = req.take().unwrap();
-                req.mut_header()
-                    .set_flags(WriteBatchFlags::PRE_FLUSH_FINISHED.bits());
+                // Keep the other flags (e.g. the trace id marker) intact for
+                // the re-entry.
+                let flags =
+                    req.get_header().get_flags() | WriteBatchFlags::PRE_FLUSH_FINISHED.bits();
+                req.mut_header().set_flags(flags);
+                let trace_id = util::admin_trace_id(req.get_header());
                 let logger = self.logger.clone();
                 let on_flush_finish = move || {
                     let (ch, _) = CmdResChannel::pair();
@@ -471,11 +490,15 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                         // clean this up.
                     }
                 };
+                // Hint PD through the region heartbeat that a merge is
+                // underway while the tablet is flushed.
+                self.set_pending_pre_flush_admin(Some(AdminCmdType::PrepareMerge));
                 self.start_pre_flush(
                     store_ctx,
                     "prepare_merge",
                     false,
                     &self.region().clone(),
+                    trace_id,
                     Box::new(on_flush_finish),
                 );
             }
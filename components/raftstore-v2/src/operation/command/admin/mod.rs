@@ -1,19 +1,22 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
+use std::collections::VecDeque;
+
 mod compact_log;
 mod conf_change;
 mod flashback;
 mod merge;
 mod split;
+mod subscriber;
 mod transfer_leader;
 
-pub use compact_log::CompactLogContext;
+pub use compact_log::{CompactLogContext, PurgeHintAggregator, MAX_PURGE_HINTS_PER_BATCH};
 use compact_log::CompactLogResult;
 use conf_change::{ConfChangeResult, UpdateGcPeersResult};
 use engine_traits::{KvEngine, RaftEngine};
 use kvproto::{
     kvrpcpb::DiskFullOpt,
-    metapb::{PeerRole, Region},
+    metapb::{self, PeerRole, Region},
     raft_cmdpb::{AdminCmdType, RaftCmdRequest},
     raft_serverpb::{ExtraMessageType, FlushMemtable, RaftMessage},
 };
@@ -21,24 +24,34 @@ use merge::{
     commit::CommitMergeResult, prepare::PrepareMergeResult, rollback::RollbackMergeResult,
 };
 pub use merge::{
-    commit::{CatchUpLogs, MERGE_IN_PROGRESS_PREFIX},
+    commit::{CatchUpLogs, MergeCatchUpLimiter, MERGE_IN_PROGRESS_PREFIX},
     merge_source_path, MergeContext, MERGE_SOURCE_PREFIX,
 };
 use protobuf::Message;
+use raft::{eraftpb::ConfChangeType, ProgressState, INVALID_INDEX};
 use raftstore::{
     store::{
         cmd_resp,
         fsm::{apply, apply::validate_batch_split},
+        metrics::{
+            ADMIN_RESOURCE_GATE_REJECTED_COUNTER, PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER,
+            WITNESS_ADMIN_CMD_REJECTED_COUNTER,
+        },
         msg::ErrorCallback,
+        region_meta::AdminCmdHistoryEntry,
+        util::admin_trace_id,
         ProposalContext, Transport,
     },
-    Error,
+    Error, Result,
 };
-use slog::{debug, error, info};
+use slog::{debug, error, info, o};
 use split::SplitResult;
 pub use split::{
     report_split_init_finish, temp_split_path, RequestHalfSplit, RequestSplit, SplitFlowControl,
-    SplitInit, SplitPendingAppend, SPLIT_PREFIX,
+    SplitInit, SplitLatencyInspector, SplitPendingAppend, SPLIT_PREFIX,
+};
+pub use subscriber::{
+    AdminResultSubscriber, AdminResultSubscribers, PdReportBatchSplitSubscriber,
 };
 use tikv_util::{box_err, log::SlogFormat, slog_panic, sys::disk::DiskUsage};
 use txn_types::WriteBatchFlags;
@@ -50,6 +63,33 @@ use crate::{
     router::{CmdResChannel, PeerMsg, RaftRequest},
 };
 
+/// Capacity of the per-peer history of applied admin commands.
+const ADMIN_CMD_HISTORY_CAP: usize = 32;
+
+/// A bounded record of the admin commands a peer has applied, kept for
+/// post-mortem analysis. The entries are collected on the apply path and
+/// exposed through the debug info query. Once the capacity is reached, the
+/// oldest entry is dropped for every new one, so the memory usage stays
+/// constant.
+#[derive(Debug, Default)]
+pub struct AdminCmdHistory {
+    entries: VecDeque<AdminCmdHistoryEntry>,
+}
+
+impl AdminCmdHistory {
+    pub fn push(&mut self, entry: AdminCmdHistoryEntry) {
+        if self.entries.len() >= ADMIN_CMD_HISTORY_CAP {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the recorded entries, oldest first.
+    pub fn entries(&self) -> Vec<AdminCmdHistoryEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
 #[derive(Debug)]
 pub enum AdminCmdResult {
     // No side effect produced by the command
@@ -65,6 +105,62 @@ pub enum AdminCmdResult {
     RollbackMerge(RollbackMergeResult),
 }
 
+/// Whether the admin command shrinks disk usage rather than enlarging it.
+/// Such commands pass the store resource gate unconditionally, even when
+/// listed in `admin_resource_gate_cmds`.
+fn admin_cmd_frees_resources(req: &RaftCmdRequest, cmd_type: AdminCmdType) -> bool {
+    match cmd_type {
+        AdminCmdType::CompactLog | AdminCmdType::RollbackMerge => true,
+        AdminCmdType::ChangePeer => {
+            req.get_admin_request().get_change_peer().get_change_type()
+                == ConfChangeType::RemoveNode
+        }
+        // An empty change list is a leave-joint request, which must not be
+        // blocked either.
+        AdminCmdType::ChangePeerV2 => req
+            .get_admin_request()
+            .get_change_peer_v2()
+            .get_changes()
+            .iter()
+            .all(|c| c.get_change_type() == ConfChangeType::RemoveNode),
+        _ => false,
+    }
+}
+
+/// Whether a witness peer may propose the admin command. A witness keeps no
+/// tablet data, so only commands that stay on the raft side are let through:
+/// log compaction, gc peer bookkeeping and the conf changes that add a witness
+/// or switch the witness role of an existing peer. Everything else either
+/// reads the tablet or, like a batch split, flushes one that does not exist.
+fn witness_admin_cmd_allowed(
+    region: &Region,
+    req: &RaftCmdRequest,
+    cmd_type: AdminCmdType,
+) -> bool {
+    let switches_witness = |peer: &metapb::Peer| {
+        region
+            .get_peers()
+            .iter()
+            .find(|p| p.get_id() == peer.get_id())
+            .map_or(peer.get_is_witness(), |p| {
+                p.get_is_witness() != peer.get_is_witness()
+            })
+    };
+    match cmd_type {
+        AdminCmdType::CompactLog | AdminCmdType::UpdateGcPeer => true,
+        AdminCmdType::ChangePeer => {
+            switches_witness(req.get_admin_request().get_change_peer().get_peer())
+        }
+        AdminCmdType::ChangePeerV2 => req
+            .get_admin_request()
+            .get_change_peer_v2()
+            .get_changes()
+            .iter()
+            .any(|c| switches_witness(c.get_peer())),
+        _ => false,
+    }
+}
+
 impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
     #[inline]
     pub fn on_admin_command<T: Transport>(
@@ -92,20 +188,79 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             return;
         }
         let cmd_type = req.get_admin_request().get_cmd_type();
-        if let Err(e) =
-            self.validate_command(req.get_header(), Some(cmd_type), &mut ctx.raft_metrics)
+        // A witness keeps no tablet data, so most admin commands cannot run
+        // on it and the pre-flush/tablet paths below must stay unreachable.
+        // Reject everything except the raft-side commands and the conf
+        // changes that manage the witness role, see
+        // `witness_admin_cmd_allowed`.
+        if self.peer().is_witness && !witness_admin_cmd_allowed(self.region(), &req, cmd_type) {
+            WITNESS_ADMIN_CMD_REJECTED_COUNTER
+                .with_label_values(&[format!("{:?}", cmd_type).as_str()])
+                .inc();
+            let resp = cmd_resp::new_error(Error::WitnessAdminCmd(self.region_id(), cmd_type));
+            ch.report_error(resp);
+            return;
+        }
+        // A PRE_FLUSH_FINISHED re-entry means the pre-flush phase is over.
+        // Clear the heartbeat hint no matter how the proposal below turns
+        // out; from here on `proposal_control` tracks the command.
+        let pre_flush_finished = WriteBatchFlags::from_bits_truncate(req.get_header().get_flags())
+            .contains(WriteBatchFlags::PRE_FLUSH_FINISHED);
+        if pre_flush_finished {
+            self.set_pending_pre_flush_admin(None);
+        }
+        let mut validation =
+            self.validate_command(req.get_header(), Some(cmd_type), &mut ctx.raft_metrics);
+        if matches!(&validation, Err(Error::EpochNotMatch(..)))
+            && pre_flush_finished
+            && cmd_type == AdminCmdType::BatchSplit
         {
+            // The re-entry revalidates against a region that may have changed
+            // while the tablet was flushed. A conf change applied meanwhile
+            // only bumps the conf version and does not invalidate the split
+            // keys, so refresh the stale header epoch and revalidate instead
+            // of bouncing a confusing epoch-not-match back to the client.
+            validation = self.refresh_epoch_after_pre_flush(&mut req).and_then(|_| {
+                self.validate_command(req.get_header(), Some(cmd_type), &mut ctx.raft_metrics)
+            });
+        }
+        if let Err(e) = validation {
             let resp = cmd_resp::new_error(e);
             ch.report_error(resp);
             return;
         }
 
+        // The command may carry a trace id in its header (see
+        // `encode_trace_id_into_flag_data`). Scope it to the logs of this
+        // command so the propose path can be correlated with the client's
+        // trace. Re-entries of the command -- the delayed channel of a
+        // conflict and the PRE_FLUSH_FINISHED resend -- carry the same
+        // header, so they rebuild the same context.
+        let trace_id = admin_trace_id(req.get_header());
+        let logger = match trace_id {
+            Some(id) => self.logger.new(o!("trace_id" => id)),
+            None => self.logger.clone(),
+        };
+
         let is_transfer_leader = cmd_type == AdminCmdType::TransferLeader;
         let pre_transfer_leader = cmd_type == AdminCmdType::TransferLeader
             && !WriteBatchFlags::from_bits_truncate(req.get_header().get_flags())
                 .contains(WriteBatchFlags::TRANSFER_LEADER_PROPOSAL);
         let is_conf_change = apply::is_conf_change_cmd(&req);
 
+        // Destructive admin commands temporarily enlarge disk usage: a batch
+        // split checkpoints the tablet and a commit merge may ingest the
+        // source tablet. Refuse to start them while the store is fighting
+        // for disk space or the peer is far behind on applying raft logs;
+        // the error is retriable, so the client proposes again once the
+        // store recovers.
+        if let Err(e) = self.check_admin_resource_gate(ctx, &req, cmd_type) {
+            let resp = cmd_resp::new_error(e);
+            ch.report_error(resp);
+            self.post_propose_fail(cmd_type);
+            return;
+        }
+
         // Check whether the admin request can be proposed when disk full.
         let can_skip_check = is_transfer_leader || pre_transfer_leader || is_conf_change;
         if !can_skip_check
@@ -134,6 +289,16 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             ch.report_error(resp);
             return;
         }
+        // A dry run only previews the split derivation against the latest
+        // applied region; nothing is proposed, so the conflict and merge
+        // checks below do not apply.
+        if cmd_type == AdminCmdType::BatchSplit
+            && WriteBatchFlags::from_bits_truncate(req.get_header().get_flags())
+                .contains(WriteBatchFlags::SPLIT_DRY_RUN)
+        {
+            self.on_batch_split_dry_run(req.get_admin_request(), ch);
+            return;
+        }
         // Do not check conflict for transfer leader, otherwise we may not
         // transfer leadership out of busy nodes in time.
         if !is_transfer_leader
@@ -179,9 +344,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                         //
                         // 2. When the task finishes, it will propose a batch split with
                         // `PRE_FLUSH_FINISHED` flag.
-                        if !WriteBatchFlags::from_bits_truncate(req.get_header().get_flags())
-                            .contains(WriteBatchFlags::PRE_FLUSH_FINISHED)
-                        {
+                        if !pre_flush_finished {
                             let mailbox = match ctx.router.mailbox(self.region_id()) {
                                 Some(mailbox) => mailbox,
                                 None => {
@@ -193,10 +356,14 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                                     return;
                                 }
                             };
-                            req.mut_header()
-                                .set_flags(WriteBatchFlags::PRE_FLUSH_FINISHED.bits());
-                            let logger = self.logger.clone();
+                            // Keep the other flags (e.g. the trace id marker)
+                            // intact for the re-entry.
+                            let flags = req.get_header().get_flags()
+                                | WriteBatchFlags::PRE_FLUSH_FINISHED.bits();
+                            req.mut_header().set_flags(flags);
+                            let logger = logger.clone();
                             let on_flush_finish = move || {
+                                fail::fail_point!("before_resend_batch_split");
                                 if let Err(e) = mailbox
                                     .try_send(PeerMsg::AdminCommand(RaftRequest::new(req, ch)))
                                 {
@@ -207,20 +374,26 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                                     );
                                 }
                             };
+                            // Hint PD through the region heartbeat that a
+                            // split is underway while the tablet is flushed.
+                            self.set_pending_pre_flush_admin(Some(AdminCmdType::BatchSplit));
+                            self.split_latency_inspector_mut().record_pre_flush_start();
                             self.start_pre_flush(
                                 ctx,
                                 "split",
                                 false,
                                 &self.region().clone(),
+                                trace_id,
                                 Box::new(on_flush_finish),
                             );
                             return;
                         }
 
                         info!(
-                            self.logger,
+                            logger,
                             "Propose split";
                         );
+                        self.split_latency_inspector_mut().record_pre_flush_finished();
                         self.propose_split(ctx, req)
                     }
                 }
@@ -265,12 +438,12 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                 self.proposal_control_mut()
                     .record_proposed_admin(cmd_type, *index);
                 if self.proposal_control_mut().has_uncommitted_admin() {
-                    self.raft_group_mut().skip_bcast_commit(false);
+                    self.set_force_bcast_commit(true);
                 }
             }
             Err(e) => {
                 info!(
-                    self.logger,
+                    logger,
                     "failed to propose admin command";
                     "cmd_type" => ?cmd_type,
                     "error" => ?e,
@@ -280,6 +453,91 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         self.post_propose_command(ctx, res, vec![ch], true);
     }
 
+    /// The store resource gate. Admin commands covered by
+    /// `admin_resource_gate_cmds` are rejected with a retriable error when
+    /// the store disk has reached the critical threshold or when the applied
+    /// index lags too far behind the committed index, e.g. right after a
+    /// restart or during heavy ingestion. Commands that free resources pass
+    /// unconditionally, see [`admin_cmd_frees_resources`].
+    fn check_admin_resource_gate<T>(
+        &self,
+        ctx: &StoreContext<EK, ER, T>,
+        req: &RaftCmdRequest,
+        cmd_type: AdminCmdType,
+    ) -> Result<()> {
+        if !ctx.cfg.admin_resource_gated(cmd_type) || admin_cmd_frees_resources(req, cmd_type) {
+            return Ok(());
+        }
+        let disk_gated = match ctx.self_disk_usage {
+            DiskUsage::Normal => false,
+            DiskUsage::AlmostFull => ctx.cfg.admin_resource_gate_on_almost_full,
+            DiskUsage::AlreadyFull => true,
+        };
+        if disk_gated {
+            ADMIN_RESOURCE_GATE_REJECTED_COUNTER
+                .with_label_values(&[format!("{:?}", cmd_type).as_str(), "disk-full"])
+                .inc();
+            return Err(Error::DiskFull(
+                vec![ctx.store_id],
+                format!(
+                    "{:?} is rejected by the resource gate: store disk usage is {:?}",
+                    cmd_type, ctx.self_disk_usage
+                ),
+            ));
+        }
+        let lag_limit = ctx.cfg.admin_resource_gate_max_apply_lag;
+        if lag_limit > 0 {
+            let committed = self.storage().entry_storage().commit_index();
+            let applied = self.storage().entry_storage().applied_index();
+            if committed >= applied + lag_limit {
+                ADMIN_RESOURCE_GATE_REJECTED_COUNTER
+                    .with_label_values(&[format!("{:?}", cmd_type).as_str(), "apply-lag"])
+                    .inc();
+                return Err(Error::ServerIsBusy(format!(
+                    "{:?} is rejected by the resource gate: peer is busy applying raft logs, \
+                     committed {}, applied {}",
+                    cmd_type, committed, applied
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Repairs the header epoch of a `BatchSplit` re-entry whose epoch check
+    /// failed after the pre-flush phase.
+    ///
+    /// The header still carries the epoch from when the request was first
+    /// validated, before the tablet flush ran. If only the conf version
+    /// differs, a conf change was applied in between; the split keys are
+    /// still valid, so the header is refreshed from the current region and
+    /// the split proceeds. If the version differs, another split or merge
+    /// rewrote the range first and the request fails with an error that
+    /// spells out the race instead of a plain epoch-not-match.
+    fn refresh_epoch_after_pre_flush(&self, req: &mut RaftCmdRequest) -> Result<()> {
+        let header_epoch = req.get_header().get_region_epoch().clone();
+        let current_epoch = self.region().get_region_epoch().clone();
+        if header_epoch.get_version() != current_epoch.get_version() {
+            return Err(Error::EpochNotMatch(
+                format!(
+                    "{} range changed while pre-flushing for batch split: another split or \
+                     merge won the race, request epoch {:?}, current epoch {:?}",
+                    SlogFormat(&self.logger),
+                    header_epoch,
+                    current_epoch,
+                ),
+                vec![self.region().clone()],
+            ));
+        }
+        info!(
+            self.logger,
+            "refresh stale epoch of batch split after pre-flush";
+            "request_epoch" => ?header_epoch,
+            "current_epoch" => ?current_epoch,
+        );
+        req.mut_header().set_region_epoch(current_epoch);
+        Ok(())
+    }
+
     fn on_prepare_merge<T: Transport>(
         &mut self,
         cmd_type: AdminCmdType,
@@ -322,33 +580,75 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         reason: &'static str,
         high_priority: bool,
         target: &Region,
+        trace_id: Option<u64>,
         on_local_flushed: Box<dyn FnOnce() + Send>,
     ) {
+        // A witness has no tablet to flush; `on_admin_command` rejects every
+        // tablet-touching command on one before reaching here.
+        debug_assert!(!self.peer().is_witness, "{}", SlogFormat(&self.logger));
         let target_id = target.get_id();
         info!(
             self.logger,
             "Start pre flush tablet";
             "target" => target_id,
             "reason" => reason,
+            "trace_id" => trace_id,
         );
-        if let Err(e) = ctx.schedulers.tablet.schedule(crate::TabletTask::Flush {
+        let mut task = Some(crate::TabletTask::Flush {
             region_id: target_id,
             reason,
             high_priority,
             threshold: Some(std::time::Duration::from_secs(10)),
             cb: Some(on_local_flushed),
-        }) {
+        });
+        // Simulates the tablet scheduler rejecting the flush task.
+        fail::fail_point!("pre_flush_schedule_fail", |_| {
+            if let Some(crate::TabletTask::Flush { cb: Some(cb), .. }) = task.take() {
+                cb();
+            }
+        });
+        if let Err(e) = ctx.schedulers.tablet.schedule(task.take().unwrap()) {
             error!(
                 self.logger,
-                "Fail to schedule flush task";
+                "Fail to schedule flush task, proceed without pre-flush";
                 "err" => ?e,
-            )
+            );
+            // The scheduler hands the task back on failure. Invoke the
+            // callback directly so the pending operation proceeds (only
+            // without the pre-flush optimization) instead of being silently
+            // dropped, which would leave the client request hanging forever.
+            if let crate::TabletTask::Flush { cb: Some(cb), .. } = e.into_inner() {
+                cb();
+            }
+            return;
         }
         // Notify followers to flush their relevant memtables
         for p in target.get_peers() {
             if p == self.peer() || p.get_role() != PeerRole::Voter || p.is_witness {
                 continue;
             }
+            // A follower that is being sent a snapshot is about to have its
+            // tablet replaced wholesale: flushing its memtables is useless
+            // and may race with the tablet replacement on its side.
+            if self
+                .raft_group()
+                .raft
+                .prs()
+                .get(p.get_id())
+                .map_or(false, |pr| {
+                    pr.state == ProgressState::Snapshot
+                        || pr.pending_request_snapshot != INVALID_INDEX
+                })
+            {
+                debug!(
+                    self.logger,
+                    "skip notifying follower to pre flush due to pending snapshot";
+                    "peer_id" => p.get_id(),
+                    "reason" => reason,
+                );
+                PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER.inc();
+                continue;
+            }
             let mut msg = RaftMessage::default();
             msg.set_region_id(target_id);
             msg.set_from_peer(self.peer().clone());
@@ -25,7 +25,7 @@
 //!   created by the store, and here init it using the data sent from the parent
 //!   peer.
 
-use std::{any::Any, borrow::Cow, cmp, path::PathBuf};
+use std::{any::Any, borrow::Cow, cmp, path::PathBuf, time::Duration};
 
 use collections::HashSet;
 use crossbeam::channel::SendError;
@@ -38,7 +38,10 @@ use kvproto::{
     kvrpcpb::DiskFullOpt,
     metapb::{self, Region, RegionEpoch},
     pdpb::CheckPolicy,
-    raft_cmdpb::{AdminRequest, AdminResponse, RaftCmdRequest, SplitRequest},
+    raft_cmdpb::{
+        AdminCmdType, AdminRequest, AdminResponse, BatchSplitRequest, RaftCmdRequest,
+        RaftCmdResponse, SplitRequest,
+    },
     raft_serverpb::{RaftMessage, RaftSnapshotData},
 };
 use protobuf::Message;
@@ -48,7 +51,8 @@ use raftstore::{
     store::{
         cmd_resp,
         fsm::{apply::validate_batch_split, ApplyMetrics},
-        metrics::PEER_ADMIN_CMD_COUNTER,
+        metrics::{PEER_ADMIN_CMD_COUNTER, PEER_BATCH_SPLIT_DERIVATION_MISMATCH_COUNTER},
+        region_meta::SplitLatencyBreakdown,
         snap::TABLET_SNAPSHOT_VERSION,
         util::{self, KeysInfoFormatter},
         PeerPessimisticLocks, SplitCheckTask, Transport, RAFT_INIT_LOG_INDEX, RAFT_INIT_LOG_TERM,
@@ -83,6 +87,12 @@ pub struct SplitResult {
     // event that performance is not critical. And using `Any` can avoid polluting
     // all existing code.
     tablet: Box<dyn Any + Send + Sync>,
+    /// When the apply of the split started, captured on the apply thread so
+    /// the leader can complete its latency breakdown, see
+    /// `SplitLatencyInspector`.
+    pub apply_start: Instant,
+    /// How long the tablet checkpoint inside the apply took.
+    pub checkpoint_duration: Duration,
 }
 
 #[derive(Debug)]
@@ -187,6 +197,120 @@ impl SplitFlowControl {
     }
 }
 
+/// Records the wall-clock instants of the stages a batch split goes through
+/// on the leader -- the pre-flush wait, propose, commit, the apply with its
+/// tablet checkpoint, and the initialization of the child peers -- and
+/// assembles them into a [`SplitLatencyBreakdown`].
+///
+/// The breakdown of the last split is exposed through the debug info query,
+/// so a slow split can be attributed to a concrete stage after the fact. Only
+/// one split is tracked at a time: a peer cannot have two splits in flight
+/// (`ProposalControl` parks the second one), but if a new split is proposed
+/// while the children of the previous one are still initializing, the
+/// previous breakdown keeps an absent init stage.
+#[derive(Default, Debug)]
+pub struct SplitLatencyInspector {
+    pre_flush_start: Option<Instant>,
+    pre_flush_wait: Option<Duration>,
+    proposed: Option<(u64, Instant)>,
+    committed: Option<Instant>,
+    /// The tablet index of the applied split whose children are still
+    /// initializing, with the instant the apply result was handled.
+    init_start: Option<(u64, Instant)>,
+    last_breakdown: Option<SplitLatencyBreakdown>,
+}
+
+impl SplitLatencyInspector {
+    /// Called when the pre-flush phase of a batch split starts.
+    pub fn record_pre_flush_start(&mut self) {
+        *self = SplitLatencyInspector {
+            pre_flush_start: Some(Instant::now()),
+            last_breakdown: self.last_breakdown,
+            ..Default::default()
+        };
+    }
+
+    /// Called when the split re-enters the propose path with the pre-flush
+    /// phase finished. A request that skipped the phase leaves the wait
+    /// absent.
+    pub fn record_pre_flush_finished(&mut self) {
+        self.pre_flush_wait = self
+            .pre_flush_start
+            .take()
+            .map(|start| start.saturating_elapsed());
+    }
+
+    /// Called when the split is proposed at `index`.
+    pub fn record_proposed(&mut self, index: u64) {
+        self.proposed = Some((index, Instant::now()));
+        self.committed = None;
+    }
+
+    /// Whether a proposed split is still waiting for its commit instant.
+    #[inline]
+    pub fn awaiting_commit(&self) -> bool {
+        self.proposed.is_some() && self.committed.is_none()
+    }
+
+    /// Called for every committed entry while `awaiting_commit`; snaps the
+    /// commit instant of the tracked proposal.
+    #[inline]
+    pub fn record_committed(&mut self, index: u64) {
+        if let Some((proposed_index, _)) = self.proposed
+            && proposed_index <= index
+            && self.committed.is_none()
+        {
+            self.committed = Some(Instant::now());
+        }
+    }
+
+    /// Called when the apply result of the split is handled on the leader.
+    /// `apply_start` and `checkpoint_duration` are captured on the apply
+    /// thread, see `SplitResult`. Returns the assembled breakdown, which
+    /// still lacks the init stage.
+    pub fn on_apply_res(
+        &mut self,
+        tablet_index: u64,
+        apply_start: Instant,
+        checkpoint_duration: Duration,
+    ) -> Option<SplitLatencyBreakdown> {
+        let (_, proposed_at) = self.proposed.take()?;
+        let committed_at = self.committed.take()?;
+        let propose_to_commit = committed_at.saturating_duration_since(proposed_at);
+        let commit_to_apply = apply_start.saturating_duration_since(committed_at);
+        let breakdown = SplitLatencyBreakdown {
+            pre_flush_wait_micros: self.pre_flush_wait.take().map(|d| d.as_micros() as u64),
+            propose_to_commit_micros: propose_to_commit.as_micros() as u64,
+            commit_to_apply_micros: commit_to_apply.as_micros() as u64,
+            checkpoint_micros: checkpoint_duration.as_micros() as u64,
+            post_apply_init_micros: None,
+        };
+        self.init_start = Some((tablet_index, Instant::now()));
+        self.last_breakdown = Some(breakdown);
+        Some(breakdown)
+    }
+
+    /// Called when every split up to `tablet_index` has all its children
+    /// initialized; completes the init stage of the tracked breakdown.
+    pub fn on_split_init_finished(&mut self, tablet_index: u64) {
+        if let Some((index, start)) = self.init_start
+            && index <= tablet_index
+        {
+            self.init_start = None;
+            if let Some(breakdown) = &mut self.last_breakdown {
+                breakdown.post_apply_init_micros =
+                    Some(start.saturating_elapsed().as_micros() as u64);
+            }
+        }
+    }
+
+    /// The breakdown of the last split the peer led, if any.
+    #[inline]
+    pub fn last_breakdown(&self) -> Option<SplitLatencyBreakdown> {
+        self.last_breakdown
+    }
+}
+
 pub struct SplitPendingAppend {
     append_msg: Option<(Box<RaftMessage>, Instant)>,
     range_overlapped: bool,
@@ -431,10 +555,199 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         // We rely on ConflictChecker to detect conflicts, so no need to set proposal
         // context.
         let data = req.write_to_bytes().unwrap();
-        self.propose(store_ctx, data)
+        let res = self.propose(store_ctx, data);
+        if let Ok(index) = res {
+            self.split_latency_inspector_mut().record_proposed(index);
+        }
+        res
+    }
+
+    /// Serves a BatchSplit request carrying the `SPLIT_DRY_RUN` flag: the
+    /// request is validated and the child regions the split would derive are
+    /// returned in the response, without proposing anything to raft or
+    /// touching the tablet.
+    ///
+    /// The caller has already checked that this peer is the leader and has
+    /// applied to the current term, so the derivation runs against the latest
+    /// applied region. The region and peer ids in the response are taken
+    /// verbatim from the request; callers previewing a plan are expected to
+    /// fill in placeholders instead of allocating real ids from PD.
+    pub fn on_batch_split_dry_run(&self, req: &AdminRequest, ch: CmdResChannel) {
+        if let Err(e) = validate_batch_split(req, self.region()) {
+            ch.set_result(cmd_resp::new_error(e));
+            return;
+        }
+        let (regions, _) = derive_split_regions(self.region(), req.get_splits());
+        let mut resp = RaftCmdResponse::default();
+        cmd_resp::bind_term(&mut resp, self.term());
+        resp.mut_admin_response()
+            .set_cmd_type(AdminCmdType::BatchSplit);
+        resp.mut_admin_response()
+            .mut_splits()
+            .set_regions(regions.into());
+        ch.set_result(resp);
     }
 }
 
+/// Checks that the regions derived by `apply_batch_split` are consistent with
+/// the parent region and the split requests: they cover exactly the parent's
+/// range with adjacent regions sharing one boundary key, all epochs are bumped
+/// by the number of split keys, and every region keeps the parent's peer
+/// distribution.
+///
+/// `validate_batch_split` already rejects malformed requests at propose time,
+/// but a bug in the derivation itself (or a divergent replay on one replica)
+/// would silently corrupt routing, so the apply path double checks the output
+/// and panics on any violation.
+fn check_derived_regions(
+    parent: &Region,
+    split_reqs: &[SplitRequest],
+    derived_regions: &[Region],
+) -> std::result::Result<(), String> {
+    if derived_regions.len() != split_reqs.len() + 1 {
+        return Err(format!(
+            "expect {} derived regions, got {}",
+            split_reqs.len() + 1,
+            derived_regions.len()
+        ));
+    }
+    if derived_regions[0].get_start_key() != parent.get_start_key() {
+        return Err(format!(
+            "first derived region starts at {} while parent starts at {}",
+            log_wrappers::Value::key(derived_regions[0].get_start_key()),
+            log_wrappers::Value::key(parent.get_start_key())
+        ));
+    }
+    if derived_regions.last().unwrap().get_end_key() != parent.get_end_key() {
+        return Err(format!(
+            "last derived region ends at {} while parent ends at {}",
+            log_wrappers::Value::key(derived_regions.last().unwrap().get_end_key()),
+            log_wrappers::Value::key(parent.get_end_key())
+        ));
+    }
+    for pair in derived_regions.windows(2) {
+        if pair[0].get_end_key() != pair[1].get_start_key() {
+            return Err(format!(
+                "region {} ends at {} but region {} starts at {}",
+                pair[0].get_id(),
+                log_wrappers::Value::key(pair[0].get_end_key()),
+                pair[1].get_id(),
+                log_wrappers::Value::key(pair[1].get_start_key())
+            ));
+        }
+        // All regions but the last have a finite end key, so an inverted or
+        // empty range always shows up as end <= start here.
+        if pair[0].get_end_key() <= pair[0].get_start_key() {
+            return Err(format!(
+                "region {} has an empty or inverted range [{}, {})",
+                pair[0].get_id(),
+                log_wrappers::Value::key(pair[0].get_start_key()),
+                log_wrappers::Value::key(pair[0].get_end_key())
+            ));
+        }
+    }
+    let expected_version = parent.get_region_epoch().get_version() + split_reqs.len() as u64;
+    for region in derived_regions {
+        let epoch = region.get_region_epoch();
+        if epoch.get_version() != expected_version
+            || epoch.get_conf_ver() != parent.get_region_epoch().get_conf_ver()
+        {
+            return Err(format!(
+                "region {} has epoch {:?}, expect version {} conf_ver {}",
+                region.get_id(),
+                epoch,
+                expected_version,
+                parent.get_region_epoch().get_conf_ver()
+            ));
+        }
+        if region.get_peers().len() != parent.get_peers().len() {
+            return Err(format!(
+                "region {} has {} peers while parent has {}",
+                region.get_id(),
+                region.get_peers().len(),
+                parent.get_peers().len()
+            ));
+        }
+        for (peer, parent_peer) in region.get_peers().iter().zip(parent.get_peers()) {
+            if peer.get_store_id() != parent_peer.get_store_id()
+                || peer.get_role() != parent_peer.get_role()
+            {
+                return Err(format!(
+                    "region {} peer {:?} does not match parent peer {:?}",
+                    region.get_id(),
+                    peer,
+                    parent_peer
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Derives the regions a batch split of `region` produces: the boundaries come
+/// from the parent range and the split keys, every child inherits the parent's
+/// peers rewritten with the requested peer ids, and all epoch versions are
+/// bumped by the number of split keys. Returns the regions ordered by range
+/// together with the index of the derived (parent) region, which keeps the
+/// parent id and sits at the right end iff `right_derive` is set.
+///
+/// The request must have passed `validate_batch_split`. This is shared by the
+/// apply path and the dry-run preview (`on_batch_split_dry_run`), so it must
+/// stay free of side effects.
+pub fn derive_split_regions(
+    region: &Region,
+    split_reqs: &BatchSplitRequest,
+) -> (Vec<Region>, usize) {
+    let mut boundaries: Vec<&[u8]> = Vec::default();
+    boundaries.push(region.get_start_key());
+    for req in split_reqs.get_requests() {
+        boundaries.push(req.get_split_key());
+    }
+    boundaries.push(region.get_end_key());
+
+    let new_region_cnt = split_reqs.get_requests().len();
+    let new_version = region.get_region_epoch().get_version() + new_region_cnt as u64;
+
+    let mut derived_req = SplitRequest::default();
+    derived_req.new_region_id = region.id;
+    let derived_req = &[derived_req];
+
+    let right_derive = split_reqs.get_right_derive();
+    let reqs = if right_derive {
+        split_reqs.get_requests().iter().chain(derived_req)
+    } else {
+        derived_req.iter().chain(split_reqs.get_requests())
+    };
+
+    let regions: Vec<_> = boundaries
+        .array_windows::<2>()
+        .zip(reqs)
+        .map(|([start_key, end_key], req)| {
+            let mut new_region = Region::default();
+            new_region.set_id(req.get_new_region_id());
+            new_region.set_region_epoch(region.get_region_epoch().to_owned());
+            new_region.mut_region_epoch().set_version(new_version);
+            new_region.set_start_key(start_key.to_vec());
+            new_region.set_end_key(end_key.to_vec());
+            new_region.set_peers(region.get_peers().to_vec().into());
+            // If the `req` is the `derived_req`, the peers are already set correctly and
+            // the following loop will not be executed due to the empty `new_peer_ids` in
+            // the `derived_req`
+            for (peer, peer_id) in new_region
+                .mut_peers()
+                .iter_mut()
+                .zip(req.get_new_peer_ids())
+            {
+                peer.set_id(*peer_id);
+            }
+            new_region
+        })
+        .collect();
+
+    let derived_index = if right_derive { regions.len() - 1 } else { 0 };
+    (regions, derived_index)
+}
+
 impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
     pub async fn apply_split(
         &mut self,
@@ -473,6 +786,7 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
             |_| { unreachable!() }
         );
         PEER_ADMIN_CMD_COUNTER.batch_split.all.inc();
+        let apply_start = Instant::now();
 
         let region = self.region();
         let region_id = region.get_id();
@@ -494,47 +808,19 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
         );
 
         let split_reqs = req.get_splits();
-        let new_region_cnt = split_reqs.get_requests().len();
-        let new_version = region.get_region_epoch().get_version() + new_region_cnt as u64;
-
-        let mut derived_req = SplitRequest::default();
-        derived_req.new_region_id = region.id;
-        let derived_req = &[derived_req];
-
-        let right_derive = split_reqs.get_right_derive();
         let share_source_region_size = split_reqs.get_share_source_region_size();
-        let reqs = if right_derive {
-            split_reqs.get_requests().iter().chain(derived_req)
-        } else {
-            derived_req.iter().chain(split_reqs.get_requests())
-        };
+        let (regions, derived_index) = derive_split_regions(region, split_reqs);
 
-        let regions: Vec<_> = boundaries
-            .array_windows::<2>()
-            .zip(reqs)
-            .map(|([start_key, end_key], req)| {
-                let mut new_region = Region::default();
-                new_region.set_id(req.get_new_region_id());
-                new_region.set_region_epoch(region.get_region_epoch().to_owned());
-                new_region.mut_region_epoch().set_version(new_version);
-                new_region.set_start_key(start_key.to_vec());
-                new_region.set_end_key(end_key.to_vec());
-                new_region.set_peers(region.get_peers().to_vec().into());
-                // If the `req` is the `derived_req`, the peers are already set correctly and
-                // the following loop will not be executed due to the empty `new_peer_ids` in
-                // the `derived_req`
-                for (peer, peer_id) in new_region
-                    .mut_peers()
-                    .iter_mut()
-                    .zip(req.get_new_peer_ids())
-                {
-                    peer.set_id(*peer_id);
-                }
-                new_region
-            })
-            .collect();
-
-        let derived_index = if right_derive { regions.len() - 1 } else { 0 };
+        if let Err(e) = check_derived_regions(region, split_reqs.get_requests(), &regions) {
+            PEER_BATCH_SPLIT_DERIVATION_MISMATCH_COUNTER.inc();
+            slog_panic!(
+                self.logger,
+                "batch split derived inconsistent regions";
+                "error" => %e,
+                "region" => ?region,
+                "derived_regions" => ?regions,
+            );
+        }
 
         // We will create checkpoint of the current tablet for both derived region and
         // split regions. Before the creation, we should flush the writes and remove the
@@ -554,6 +840,7 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
         self.high_priority_pool()
             .spawn(async move {
                 let checkpoint_start = Instant::now();
+                fail_point!("before_split_checkpoint");
                 let mut checkpointer = tablet.new_checkpointer().unwrap_or_else(|e| {
                     slog_panic!(
                         logger,
@@ -630,6 +917,10 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
 
         let mut resp = AdminResponse::default();
         resp.mut_splits().set_regions(regions.clone().into());
+        // TODO: attach the stage breakdown to the split response once kvproto
+        // grows a field for it, gated by a request flag so old clients are
+        // unaffected. Until then it is exposed through the debug info query,
+        // see `SplitLatencyInspector`.
         PEER_ADMIN_CMD_COUNTER.batch_split.success.inc();
 
         Ok((
@@ -640,6 +931,8 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
                 tablet_index: log_index,
                 tablet: Box::new(tablet),
                 share_source_region_size,
+                apply_start,
+                checkpoint_duration,
             }),
         ))
     }
@@ -693,6 +986,36 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         let share_source_region_size = res.share_source_region_size;
         let region_id = derived.get_id();
 
+        // The inspector is only armed on the peer that proposed the split,
+        // so followers applying it record nothing.
+        if let Some(breakdown) = self.split_latency_inspector_mut().on_apply_res(
+            res.tablet_index,
+            res.apply_start,
+            res.checkpoint_duration,
+        ) {
+            info!(
+                self.logger,
+                "batch split latency breakdown";
+                "pre_flush_wait_micros" => ?breakdown.pre_flush_wait_micros,
+                "propose_to_commit_micros" => breakdown.propose_to_commit_micros,
+                "commit_to_apply_micros" => breakdown.commit_to_apply_micros,
+                "checkpoint_micros" => breakdown.checkpoint_micros,
+            );
+        }
+
+        // Capture the final metadata for the subscribers before `res.regions`
+        // is consumed by the initialization of the new peers.
+        let subscriber_regions = (!store_ctx.admin_result_subscribers.is_empty()).then(|| {
+            let children: Vec<_> = res
+                .regions
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != res.derived_index)
+                .map(|(_, r)| r.clone())
+                .collect();
+            (derived.clone(), children)
+        });
+
         let region_locks = self.txn_context().split(&res.regions, derived);
         fail_point!("on_split_invalidate_locks");
 
@@ -734,15 +1057,6 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
 
         if self.is_leader() {
             self.region_heartbeat_pd(store_ctx);
-            // Notify pd immediately to let it update the region meta.
-            info!(
-                self.logger,
-                "notify pd with split";
-                "split_count" => res.regions.len(),
-            );
-            // Now pd only uses ReportBatchSplit for history operation show,
-            // so we send it independently here.
-            self.report_batch_split_pd(store_ctx, res.regions.to_vec());
             // After split, the peer may need to update its metrics.
             let control = self.split_flow_control_mut();
             control.may_skip_split_check = false;
@@ -837,6 +1151,15 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             .put_dirty_mark(region_id, res.tablet_index, true)
             .unwrap();
         self.set_has_extra_write();
+
+        if let Some((parent, children)) = subscriber_regions {
+            store_ctx.admin_result_subscribers.notify_split(
+                &self.logger,
+                &parent,
+                &children,
+                self.is_leader(),
+            );
+        }
     }
 
     pub fn on_split_init<T>(
@@ -974,6 +1297,8 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             // There should be very few elements in the vector.
             split_trace.drain(..off);
             assert_ne!(admin_flushed, 0);
+            self.split_latency_inspector_mut()
+                .on_split_init_finished(admin_flushed);
             self.storage_mut()
                 .apply_trace_mut()
                 .on_admin_flush(admin_flushed);
@@ -1018,6 +1343,7 @@ mod test {
         coprocessor::CoprocessorHost,
         store::{cmd_resp::new_error, Config},
     };
+    use rand::Rng;
     use slog::o;
     use tempfile::TempDir;
     use tikv_util::{
@@ -1040,6 +1366,105 @@ mod test {
         req
     }
 
+    // Runs the production derivation to produce inputs for
+    // `check_derived_regions`.
+    fn derive_regions(
+        parent: &Region,
+        split_reqs: &[SplitRequest],
+        right_derive: bool,
+    ) -> Vec<Region> {
+        let mut batch = BatchSplitRequest::default();
+        batch.set_right_derive(right_derive);
+        batch.set_requests(split_reqs.to_vec().into());
+        derive_split_regions(parent, &batch).0
+    }
+
+    #[test]
+    fn test_check_derived_regions() {
+        let mut parent = Region::default();
+        parent.set_id(1);
+        parent.set_end_key(b"k10".to_vec());
+        parent.mut_region_epoch().set_version(3);
+        parent.mut_region_epoch().set_conf_ver(2);
+        parent.set_peers(vec![new_peer(2, 3), new_peer(4, 5), new_learner_peer(6, 7)].into());
+
+        // Splitting at the first possible key of the parent range.
+        let reqs = vec![new_split_req(&[0], 10, vec![11, 12, 13])];
+        let regions = derive_regions(&parent, &reqs, true);
+        check_derived_regions(&parent, &reqs, &regions).unwrap();
+
+        // Maximal number of split keys in one batch.
+        let reqs: Vec<_> = (0..255u64)
+            .map(|i| {
+                new_split_req(
+                    format!("k0.{:03}", i).as_bytes(),
+                    10 + i,
+                    vec![1000 + 3 * i, 1001 + 3 * i, 1002 + 3 * i],
+                )
+            })
+            .collect();
+        for right_derive in [false, true] {
+            let regions = derive_regions(&parent, &reqs, right_derive);
+            check_derived_regions(&parent, &reqs, &regions).unwrap();
+        }
+
+        // Randomized split key sets: the derivation must always pass the
+        // check, and any single corruption of the result must fail it.
+        let mut whole_range_parent = parent.clone();
+        whole_range_parent.set_end_key(vec![]);
+        let mut rng = rand::thread_rng();
+        for _ in 0..64 {
+            let mut split_keys: Vec<Vec<u8>> = (0..rng.gen_range(1..=16))
+                .map(|_| {
+                    (0..rng.gen_range(1..=8))
+                        .map(|_| rng.gen_range(b'a'..=b'z'))
+                        .collect()
+                })
+                .collect();
+            split_keys.sort();
+            split_keys.dedup();
+            let reqs: Vec<_> = split_keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| {
+                    let i = i as u64;
+                    new_split_req(key, 100 + i, vec![1000 + 3 * i, 1001 + 3 * i, 1002 + 3 * i])
+                })
+                .collect();
+            let regions = derive_regions(&whole_range_parent, &reqs, rng.gen_bool(0.5));
+            check_derived_regions(&whole_range_parent, &reqs, &regions).unwrap();
+
+            let mut corrupted = regions.clone();
+            let i = rng.gen_range(0..corrupted.len());
+            match rng.gen_range(0..5) {
+                // A region is lost.
+                0 => {
+                    corrupted.remove(i);
+                }
+                // A boundary is shifted, creating a gap or an overlap.
+                1 => {
+                    let mut end = corrupted[i].get_end_key().to_vec();
+                    end.push(0);
+                    corrupted[i].set_end_key(end);
+                }
+                // The epoch is not bumped consistently.
+                2 => {
+                    let version = corrupted[i].get_region_epoch().get_version();
+                    corrupted[i].mut_region_epoch().set_version(version - 1);
+                }
+                // A peer is lost.
+                3 => {
+                    corrupted[i].mut_peers().pop();
+                }
+                // A peer is placed on the wrong store.
+                _ => {
+                    corrupted[i].mut_peers()[0].set_store_id(100);
+                }
+            }
+            check_derived_regions(&whole_range_parent, &reqs, &corrupted).unwrap_err();
+        }
+    }
+
     fn assert_split(
         apply: &mut Apply<KvTestEngine, MockReporter>,
         parent_id: u64,
@@ -0,0 +1,140 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! In-process subscription of applied admin results.
+//!
+//! Components that need to learn about admin commands once their results are
+//! final (split, merge, conf change, log compaction) can implement
+//! [`AdminResultSubscriber`] and register it on the store system before it is
+//! started. The callbacks are invoked synchronously on the poller thread
+//! right after the corresponding [`AdminCmdResult`] has been integrated into
+//! the peer's state, so the region metadata passed to them is final.
+//! Subscribers must never block; work that may take time has to be handed
+//! over to a worker. A panic in a subscriber is caught and reported with a
+//! metric so that one misbehaving subscriber cannot bring the poller down.
+//!
+//! [`AdminCmdResult`]: super::AdminCmdResult
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use kvproto::metapb::Region;
+use raftstore::store::metrics::ADMIN_RESULT_SUBSCRIBER_PANIC_COUNTER;
+use slog::{error, info, Logger};
+use tikv_util::worker::Scheduler;
+
+use crate::worker::pd;
+
+/// Callbacks for applied admin results. All methods default to no-ops so
+/// implementors only need to override the events they care about.
+pub trait AdminResultSubscriber: Send + Sync {
+    /// Called after a batch split has been applied. `parent` is the region
+    /// that was split, carrying its post-split range and epoch, and
+    /// `children` are the newly created regions. `is_leader` tells whether
+    /// the local peer leads the parent region.
+    fn on_split(&self, _parent: &Region, _children: &[Region], _is_leader: bool) {}
+
+    /// Called on the target peer after a merge has been committed. `source`
+    /// is the region that was merged away and `target` carries the merged
+    /// range.
+    fn on_merge(&self, _source: &Region, _target: &Region) {}
+
+    /// Called after a conf change has been applied, with the final region
+    /// metadata.
+    fn on_conf_change(&self, _region: &Region) {}
+
+    /// Called after a compact log result has advanced the truncated state of
+    /// the region to `compact_index`.
+    fn on_compact_log(&self, _region_id: u64, _compact_index: u64) {}
+}
+
+/// The set of registered subscribers, shared by all pollers of a store.
+#[derive(Clone, Default)]
+pub struct AdminResultSubscribers {
+    subscribers: Arc<Vec<Arc<dyn AdminResultSubscriber>>>,
+}
+
+impl AdminResultSubscribers {
+    pub fn new(subscribers: Vec<Arc<dyn AdminResultSubscriber>>) -> Self {
+        Self {
+            subscribers: Arc::new(subscribers),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    fn for_each(&self, logger: &Logger, f: impl Fn(&dyn AdminResultSubscriber)) {
+        for subscriber in self.subscribers.iter() {
+            if panic::catch_unwind(AssertUnwindSafe(|| f(subscriber.as_ref()))).is_err() {
+                ADMIN_RESULT_SUBSCRIBER_PANIC_COUNTER.inc();
+                error!(logger, "admin result subscriber panicked");
+            }
+        }
+    }
+
+    pub fn notify_split(
+        &self,
+        logger: &Logger,
+        parent: &Region,
+        children: &[Region],
+        is_leader: bool,
+    ) {
+        self.for_each(logger, |s| s.on_split(parent, children, is_leader));
+    }
+
+    pub fn notify_merge(&self, logger: &Logger, source: &Region, target: &Region) {
+        self.for_each(logger, |s| s.on_merge(source, target));
+    }
+
+    pub fn notify_conf_change(&self, logger: &Logger, region: &Region) {
+        self.for_each(logger, |s| s.on_conf_change(region));
+    }
+
+    pub fn notify_compact_log(&self, logger: &Logger, region_id: u64, compact_index: u64) {
+        self.for_each(logger, |s| s.on_compact_log(region_id, compact_index));
+    }
+}
+
+/// Reports applied batch splits to the pd worker. Pd only uses the report for
+/// the history operation show, so it is sent independently of the region
+/// heartbeat. This used to be inlined in the split apply-result handler; it
+/// only needs the final region metadata, so it is registered by the store
+/// system as a built-in subscriber.
+pub struct PdReportBatchSplitSubscriber {
+    scheduler: Scheduler<pd::Task>,
+    logger: Logger,
+}
+
+impl PdReportBatchSplitSubscriber {
+    pub(crate) fn new(scheduler: Scheduler<pd::Task>, logger: Logger) -> Self {
+        Self { scheduler, logger }
+    }
+}
+
+impl AdminResultSubscriber for PdReportBatchSplitSubscriber {
+    fn on_split(&self, parent: &Region, children: &[Region], is_leader: bool) {
+        if !is_leader {
+            return;
+        }
+        // Notify pd immediately to let it update the region meta.
+        info!(
+            self.logger,
+            "notify pd with split";
+            "region_id" => parent.get_id(),
+            "split_count" => children.len() + 1,
+        );
+        let mut regions = Vec::with_capacity(children.len() + 1);
+        regions.push(parent.clone());
+        regions.extend_from_slice(children);
+        if let Err(e) = self.scheduler.schedule(pd::Task::ReportBatchSplit { regions }) {
+            error!(
+                self.logger,
+                "failed to notify pd with ReportBatchSplit";
+                "err" => %e,
+            );
+        }
+    }
+}
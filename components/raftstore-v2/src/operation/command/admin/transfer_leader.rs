@@ -15,7 +15,7 @@ use raft::{eraftpb, ProgressState, Storage};
 use raftstore::{
     store::{
         fsm::new_admin_request, make_transfer_leader_response, metrics::PEER_ADMIN_CMD_COUNTER,
-        Transport, TRANSFER_LEADER_COMMAND_REPLY_CTX,
+        region_meta::AdminCmdHistoryEntry, Transport, TRANSFER_LEADER_COMMAND_REPLY_CTX,
     },
     Result,
 };
@@ -28,7 +28,7 @@ use crate::{
     batch::StoreContext,
     fsm::ApplyResReporter,
     raft::{Apply, Peer},
-    router::{CmdResChannel, PeerMsg},
+    router::{CmdResChannel, EntryCacheWarmupOutcome, PeerMsg, WarmUpEntryCacheChannel},
 };
 
 fn transfer_leader_cmd(msg: &RaftCmdRequest) -> Option<&TransferLeaderRequest> {
@@ -215,6 +215,22 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                     );
                     self.raft_group_mut().transfer_leader(from.get_id());
                     self.refresh_leader_transferee();
+                    // A plain transfer leader is executed directly without
+                    // going through the raft log (only one carrying
+                    // pessimistic locks is proposed), so it can't be recorded
+                    // on the apply path. Record it in the history here.
+                    let epoch = self.region().get_region_epoch().clone();
+                    let index = self.storage().last_index().unwrap_or_default();
+                    let term = self.term();
+                    let peer_id = self.peer_id();
+                    self.admin_cmd_history_mut().push(AdminCmdHistoryEntry::new(
+                        AdminCmdType::TransferLeader,
+                        index,
+                        term,
+                        &epoch,
+                        &epoch,
+                        peer_id,
+                    ));
                 }
             }
         }
@@ -311,6 +327,104 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         }
     }
 
+    /// Handles an explicit entry cache warmup trigger, see
+    /// [`PeerMsg::WarmUpEntryCache`].
+    ///
+    /// Unlike the warmup during leader transfer, the trigger is not tied to
+    /// a pending TransferLeaderMsg: it can be used on a leader whose cache
+    /// went cold after log compaction as well as on a follower that is about
+    /// to become a transfer target. When the cache already covers the
+    /// requested range the trigger is a no-op and the channel resolves with
+    /// `AlreadyWarm` right away, otherwise it resolves when the async fetch
+    /// completes or times out.
+    pub fn on_warm_up_entry_cache<T>(
+        &mut self,
+        ctx: &mut StoreContext<EK, ER, T>,
+        up_to_index: Option<u64>,
+        ch: WarmUpEntryCacheChannel,
+    ) {
+        let last_index = self.entry_storage().last_index();
+        let truncated_index = self
+            .entry_storage()
+            .apply_state()
+            .get_truncated_state()
+            .get_index();
+        let mut low = up_to_index.unwrap_or(truncated_index + 1);
+        if low < self.compact_log_context().last_compacted_idx() {
+            low = self.compact_log_context().last_compacted_idx();
+        }
+        if low == 0 || low > last_index {
+            // Nothing to warm up.
+            ch.set_result(EntryCacheWarmupOutcome::AlreadyWarm);
+            return;
+        }
+        if let Some(first_index) = self.entry_storage().entry_cache_first_index() {
+            if low >= first_index {
+                ch.set_result(EntryCacheWarmupOutcome::AlreadyWarm);
+                return;
+            }
+        }
+        // A warmup (e.g. one started by a transfer leader message) may
+        // already be in flight: wait on it instead of re-triggering.
+        let in_flight_timeout = self
+            .storage_mut()
+            .entry_storage_mut()
+            .entry_cache_warmup_state_mut()
+            .as_mut()
+            .map(|state| state.check_task_timeout(ctx.cfg.max_entry_cache_warmup_duration.0));
+        match in_flight_timeout {
+            Some(true) => {
+                ch.set_result(EntryCacheWarmupOutcome::Timeout);
+                // The earlier waiters of the timeout warmup are done as well.
+                self.finish_warmup_entry_cache_wait();
+            }
+            Some(false) => self.warmup_entry_cache_waiters_mut().push(ch),
+            None => {
+                if self
+                    .storage_mut()
+                    .entry_storage_mut()
+                    .async_warm_up_entry_cache(low)
+                    .is_some()
+                {
+                    self.warmup_entry_cache_waiters_mut().push(ch);
+                } else {
+                    // No task is triggered either when a concurrent fetch
+                    // made the cache cover the range in the meantime or when
+                    // fetching hit an unexpected error.
+                    let warmed = self
+                        .entry_storage()
+                        .entry_cache_first_index()
+                        .map_or(false, |first| low >= first);
+                    ch.set_result(if warmed {
+                        EntryCacheWarmupOutcome::AlreadyWarm
+                    } else {
+                        EntryCacheWarmupOutcome::Failed
+                    });
+                }
+            }
+        }
+    }
+
+    /// Resolves the channels waiting on an explicitly triggered warmup.
+    /// Called when the warmup fetch result arrives or the warmup task is
+    /// found timeout.
+    pub fn finish_warmup_entry_cache_wait(&mut self) {
+        if self.warmup_entry_cache_waiters_mut().is_empty() {
+            return;
+        }
+        let outcome = match self.entry_storage().entry_cache_warmup_state() {
+            Some(state) if state.is_task_timeout() => EntryCacheWarmupOutcome::Timeout,
+            Some(state) if state.is_finished() => EntryCacheWarmupOutcome::Finished,
+            Some(_) => EntryCacheWarmupOutcome::Failed,
+            // The state is gone, e.g. the cache has been compacted in the
+            // meantime; report failure so callers can retry.
+            None => EntryCacheWarmupOutcome::Failed,
+        };
+        for ch in std::mem::take(self.warmup_entry_cache_waiters_mut()) {
+            ch.set_result(outcome);
+        }
+    }
+
     pub fn ack_transfer_leader_msg(
         &mut self,
         reply_cmd: bool, // whether it is a reply to a TransferLeader command
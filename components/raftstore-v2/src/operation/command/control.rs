@@ -191,6 +191,16 @@ impl ProposalControl {
         !self.proposed_admin_cmd.is_empty() && !self.proposed_admin_cmd.back().unwrap().committed
     }
 
+    /// Returns the type of the latest proposed admin command that is still
+    /// uncommitted, if any.
+    #[inline]
+    pub fn uncommitted_admin_type(&self) -> Option<AdminCmdType> {
+        self.proposed_admin_cmd
+            .back()
+            .filter(|c| !c.committed)
+            .map(|c| c.cmd_type)
+    }
+
     pub fn advance_apply(&mut self, index: u64, term: u64, region: &metapb::Region) {
         while !self.proposed_admin_cmd.is_empty() {
             let cmd = self.proposed_admin_cmd.front_mut().unwrap();
@@ -242,6 +252,18 @@ impl ProposalControl {
         self.applied_prepare_merge_index != 0
     }
 
+    #[inline]
+    pub fn applied_prepare_merge_index(&self) -> u64 {
+        self.applied_prepare_merge_index
+    }
+
+    /// Types of the admin commands that are proposed but not yet applied,
+    /// oldest first. Used to expose the state for debugging purpose.
+    #[inline]
+    pub fn proposed_admin_cmd_types(&self) -> Vec<AdminCmdType> {
+        self.proposed_admin_cmd.iter().map(|c| c.cmd_type).collect()
+    }
+
     /// Check if there is an on-going split command on current term.
     ///
     /// The answer is reliable only when the peer is leader.
@@ -298,6 +320,10 @@ mod tests {
         );
         control.record_proposed_admin(AdminCmdType::BatchSplit, 5);
         assert_eq!(control.proposed_admin_cmd.len(), 1);
+        assert_eq!(
+            control.uncommitted_admin_type(),
+            Some(AdminCmdType::BatchSplit)
+        );
 
         // Both conflict with the split admin cmd
         let conflict = control.check_conflict(None).unwrap();
@@ -340,6 +366,13 @@ mod tests {
         control.commit_to(5, |c| commit_split = c.cmd_type == AdminCmdType::BatchSplit);
         assert!(commit_split);
         assert!(control.is_splitting());
+        // The change peer admin cmd at index 6 is still uncommitted.
+        assert_eq!(
+            control.uncommitted_admin_type(),
+            Some(AdminCmdType::ChangePeerV2)
+        );
+        control.commit_to(6, |_| {});
+        assert_eq!(control.uncommitted_admin_type(), None);
 
         control.advance_apply(4, 10, &region);
         // Have no effect on `proposed_admin_cmd`
@@ -42,12 +42,13 @@ use raftstore::{
             APPLY_TASK_WAIT_TIME_HISTOGRAM, APPLY_TIME_HISTOGRAM, STORE_APPLY_LOG_HISTOGRAM,
         },
         msg::ErrorCallback,
+        region_meta::AdminCmdHistoryEntry,
         util::{self, check_flashback_state},
         Config, ProposalContext, Transport, WriteCallback,
     },
     Error, Result,
 };
-use slog::{debug, error, warn};
+use slog::{debug, error, info, warn};
 use tikv_util::{
     box_err,
     log::SlogFormat,
@@ -67,9 +68,12 @@ mod control;
 mod write;
 
 pub use admin::{
-    merge_source_path, report_split_init_finish, temp_split_path, AdminCmdResult, CatchUpLogs,
-    CompactLogContext, MergeContext, RequestHalfSplit, RequestSplit, SplitFlowControl, SplitInit,
-    SplitPendingAppend, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX, SPLIT_PREFIX,
+    merge_source_path, report_split_init_finish, temp_split_path, AdminCmdHistory, AdminCmdResult,
+    AdminResultSubscriber, AdminResultSubscribers, CatchUpLogs, CompactLogContext,
+    MergeCatchUpLimiter, MergeContext, PdReportBatchSplitSubscriber, PurgeHintAggregator,
+    RequestHalfSplit, RequestSplit, SplitFlowControl, SplitInit, SplitLatencyInspector,
+    SplitPendingAppend, MAX_PURGE_HINTS_PER_BATCH, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX,
+    SPLIT_PREFIX,
 };
 pub use control::ProposalControl;
 use pd_client::{BucketMeta, BucketStat};
@@ -355,6 +359,13 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         } else {
             entry_and_proposals = committed_entries.into_iter().map(|e| (e, vec![])).collect();
         }
+        // Snap the commit instant of an in-flight batch split, see
+        // `SplitLatencyInspector`.
+        if self.split_latency_inspector().awaiting_commit()
+            && let Some((e, _)) = entry_and_proposals.last()
+        {
+            self.split_latency_inspector_mut().record_committed(e.index);
+        }
         self.report_store_time_duration(ctx, &mut entry_and_proposals);
         // Unlike v1, v2 doesn't need to persist commit index and commit term. The
         // point of persist commit index/term of raft apply state is to recover commit
@@ -432,6 +443,9 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                 AdminCmdResult::RollbackMerge(res) => self.on_apply_res_rollback_merge(ctx, res),
             }
         }
+        for entry in apply_res.admin_cmd_history {
+            self.admin_cmd_history_mut().push(entry);
+        }
         self.region_buckets_info_mut()
             .add_bucket_flow(&apply_res.bucket_stat);
         self.update_split_flow_control(
@@ -729,6 +743,21 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
         )?;
         if req.has_admin_request() {
             let admin_req = req.get_admin_request();
+            // The epoch may be changed by the command below, snapshot it first so
+            // the history entry can record the transition.
+            let epoch_before = self.region().get_region_epoch().clone();
+            // Surface the trace context of the command on the apply path as
+            // well, so a traced admin operation can be followed from propose
+            // to apply in the logs.
+            if let Some(trace_id) = util::admin_trace_id(header) {
+                info!(
+                    self.logger,
+                    "apply admin command";
+                    "cmd_type" => ?admin_req.get_cmd_type(),
+                    "index" => log_index,
+                    "trace_id" => trace_id,
+                );
+            }
             let (admin_resp, admin_result) = match req.get_admin_request().get_cmd_type() {
                 AdminCmdType::CompactLog => self.apply_compact_log(admin_req, log_index)?,
                 AdminCmdType::Split => self.apply_split(admin_req, log_index).await?,
@@ -759,6 +788,15 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
                 }
             };
 
+            self.push_admin_cmd_history(AdminCmdHistoryEntry::new(
+                admin_type.unwrap(),
+                log_index,
+                entry.get_term(),
+                &epoch_before,
+                self.region().get_region_epoch(),
+                req.get_header().get_peer().get_id(),
+            ));
+
             match admin_result {
                 AdminCmdResult::None => (),
                 _ => self.push_admin_result(admin_result),
@@ -876,6 +914,7 @@ impl<EK: KvEngine, R: ApplyResReporter> Apply<EK, R> {
         apply_res.applied_index = index;
         apply_res.applied_term = term;
         apply_res.admin_result = self.take_admin_result().into_boxed_slice();
+        apply_res.admin_cmd_history = self.take_admin_cmd_history();
         apply_res.modifications = *self.modifications_mut();
         apply_res.metrics = mem::take(&mut self.metrics);
         apply_res.bucket_stat = self.buckets.clone();
@@ -1271,6 +1271,9 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             apply::notify_req_region_removed(region_id, cb);
         }
 
+        // Keep the forcing-regions gauge accurate if the peer is destroyed
+        // while an admin command was still forcing immediate broadcasts.
+        self.set_force_bcast_commit(false);
         self.clear_apply_scheduler();
     }
 }
@@ -12,10 +12,12 @@ mod txn_ext;
 mod unsafe_recovery;
 
 pub use command::{
-    merge_source_path, AdminCmdResult, ApplyFlowControl, CatchUpLogs, CommittedEntries,
-    CompactLogContext, MergeContext, ProposalControl, RequestHalfSplit, RequestSplit,
-    SimpleWriteBinary, SimpleWriteEncoder, SimpleWriteReqDecoder, SimpleWriteReqEncoder,
-    SplitFlowControl, SplitPendingAppend, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX,
+    merge_source_path, AdminCmdHistory, AdminCmdResult, AdminResultSubscriber,
+    AdminResultSubscribers, ApplyFlowControl, CatchUpLogs, CommittedEntries, CompactLogContext,
+    MergeCatchUpLimiter, MergeContext, PdReportBatchSplitSubscriber, ProposalControl,
+    PurgeHintAggregator, RequestHalfSplit, RequestSplit, SimpleWriteBinary, SimpleWriteEncoder,
+    SimpleWriteReqDecoder, SimpleWriteReqEncoder, SplitFlowControl, SplitLatencyInspector,
+    SplitPendingAppend, MAX_PURGE_HINTS_PER_BATCH, MERGE_IN_PROGRESS_PREFIX, MERGE_SOURCE_PREFIX,
     SPLIT_PREFIX,
 };
 pub use disk_snapshot_backup::UnimplementedHandle as DiskSnapBackupHandle;
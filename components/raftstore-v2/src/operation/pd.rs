@@ -6,7 +6,7 @@ use std::sync::atomic::Ordering;
 
 use engine_traits::{KvEngine, RaftEngine};
 use fail::fail_point;
-use kvproto::{metapb, pdpb};
+use kvproto::{metapb, pdpb, raft_cmdpb::AdminCmdType};
 use raftstore::store::{metrics::STORE_SNAPSHOT_TRAFFIC_GAUGE_VEC, Transport};
 use slog::{debug, error};
 use tikv_util::{slog_panic, time::Instant};
@@ -111,6 +111,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             approximate_size: self.split_flow_control_mut().approximate_size(),
             approximate_keys: self.split_flow_control_mut().approximate_keys(),
             wait_data_peers: Vec::new(),
+            pending_admin: self.pending_admin_hint(),
         });
         if let Err(e) = ctx.schedulers.pd.schedule(task) {
             error!(
@@ -123,6 +124,26 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         fail_point!("schedule_check_split");
     }
 
+    /// Returns the admin command in progress on the region that PD should
+    /// know about, either one still in its pre-flush phase or a split/merge
+    /// proposal that is not committed yet. The scheduler can use it to avoid
+    /// piling conflicting operators on the region.
+    fn pending_admin_hint(&self) -> Option<AdminCmdType> {
+        self.pending_pre_flush_admin().or_else(|| {
+            self.proposal_control()
+                .uncommitted_admin_type()
+                .filter(|t| {
+                    matches!(
+                        t,
+                        AdminCmdType::BatchSplit
+                            | AdminCmdType::PrepareMerge
+                            | AdminCmdType::CommitMerge
+                            | AdminCmdType::RollbackMerge
+                    )
+                })
+        })
+    }
+
     /// Collects all pending peers and update `peers_start_pending_time`.
     fn collect_pending_peers<T>(&mut self, ctx: &StoreContext<EK, ER, T>) -> Vec<metapb::Peer> {
         let mut pending_peers = Vec::with_capacity(self.region().get_peers().len());
@@ -234,20 +255,4 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             );
         }
     }
-
-    #[inline]
-    pub fn report_batch_split_pd<T>(
-        &self,
-        ctx: &StoreContext<EK, ER, T>,
-        regions: Vec<metapb::Region>,
-    ) {
-        let task = pd::Task::ReportBatchSplit { regions };
-        if let Err(e) = ctx.schedulers.pd.schedule(task) {
-            error!(
-                self.logger,
-                "failed to notify pd with ReportBatchSplit";
-                "err" => %e,
-            );
-        }
-    }
 }
@@ -23,9 +23,14 @@ use raft::{Ready, StateRole};
 use raftstore::{
     errors::RAFTSTORE_IS_BUSY,
     store::{
-        cmd_resp, local_metrics::RaftMetrics, metrics::RAFT_READ_INDEX_PENDING_COUNT,
-        msg::ErrorCallback, region_meta::RegionMeta, util, util::LeaseState, GroupState,
-        ReadIndexContext, ReadProgress, RequestPolicy,
+        cmd_resp,
+        local_metrics::RaftMetrics,
+        metrics::RAFT_READ_INDEX_PENDING_COUNT,
+        msg::ErrorCallback,
+        region_meta::{EntryCacheWarmupState, ProposalControlState, RegionMeta},
+        util,
+        util::LeaseState,
+        GroupState, ReadIndexContext, ReadProgress, RequestPolicy,
     },
     Error, Result,
 };
@@ -438,6 +443,30 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         if let Some(bucket_stats) = self.region_buckets_info().bucket_stat() {
             meta.bucket_keys = bucket_stats.meta.keys.clone();
         }
+        meta.admin_cmd_history = self.admin_cmd_history().entries();
+        if let Some(state) = entry_storage.entry_cache_warmup_state() {
+            let (range_start, range_end) = state.range();
+            meta.entry_cache_warmup = Some(EntryCacheWarmupState {
+                range_start,
+                range_end,
+                elapsed_ms: state.elapsed().as_millis() as u64,
+                is_finished: state.is_finished(),
+                is_timeout: state.is_task_timeout(),
+                is_stale: state.is_stale(),
+            });
+        }
+        let control = self.proposal_control();
+        meta.proposal_control = Some(ProposalControlState {
+            proposed_admin_cmds: control
+                .proposed_admin_cmd_types()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            has_pending_prepare_merge: control.has_pending_prepare_merge(),
+            applied_prepare_merge_index: control.applied_prepare_merge_index(),
+            force_bcast_commit: self.forcing_bcast_commit(),
+        });
+        meta.last_split_latency = self.split_latency_inspector().last_breakdown();
         debug!(self.logger, "on query debug info";
             "tick" => self.raft_group().raft.election_elapsed,
             "election_timeout" => self.raft_group().raft.randomized_election_timeout(),
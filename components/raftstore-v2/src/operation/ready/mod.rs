@@ -326,6 +326,31 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                     if util::is_epoch_stale(region_epoch, self.region().get_region_epoch()) {
                         return;
                     }
+                    // The tablet is about to be replaced wholesale by a
+                    // snapshot, flushing it now is useless and may race with
+                    // the replacement.
+                    if self.is_handling_snapshot() || self.has_pending_snapshot() {
+                        debug!(
+                            self.logger,
+                            "ignore flush memtable message while applying snapshot";
+                            "from_peer_id" => msg.get_from_peer().get_id(),
+                        );
+                        return;
+                    }
+                    // Multiple leaders (or a retried pre-flush) can ask for a
+                    // flush of the same tablet almost simultaneously; one
+                    // flush serves them all.
+                    if ctx
+                        .flush_dedup
+                        .check_and_record(self.region().get_id(), self.storage().tablet_index())
+                    {
+                        debug!(
+                            self.logger,
+                            "deduplicate flush memtable message";
+                            "from_peer_id" => msg.get_from_peer().get_id(),
+                        );
+                        return;
+                    }
                     let _ = ctx
                         .schedulers
                         .tablet
@@ -495,10 +520,21 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         if self.term() != logs.term {
             self.entry_storage_mut().clean_async_fetch_res(low);
         } else if self.entry_storage().entry_cache_warmup_state().is_some() {
+            // Only a result whose low index matches the warmup range is the
+            // warmup fetch; results of other fetches must not resolve the
+            // explicit warmup waiters.
+            let is_warmup_fetch = self
+                .entry_storage()
+                .entry_cache_warmup_state()
+                .as_ref()
+                .map_or(false, |state| state.range().0 == low);
             if self.entry_storage_mut().maybe_warm_up_entry_cache(*logs) {
                 self.ack_transfer_leader_msg(false);
                 self.set_has_ready();
             }
+            if is_warmup_fetch {
+                self.finish_warmup_entry_cache_wait();
+            }
             self.entry_storage_mut().clean_async_fetch_res(low);
             return;
         } else {
@@ -725,7 +761,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             && commit_to_current_term
             && !self.proposal_control().has_uncommitted_admin()
         {
-            self.raft_group_mut().skip_bcast_commit(true);
+            self.set_force_bcast_commit(false);
         }
     }
 
@@ -1073,7 +1109,7 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                     // quorum's heartbeat response after that, it may wait for applying to
                     // current term to apply the read. So broadcast eagerly to avoid unexpected
                     // latency.
-                    self.raft_group_mut().skip_bcast_commit(false);
+                    self.set_force_bcast_commit(true);
                     self.update_last_sent_snapshot_index(
                         self.raft_group().raft.raft_log.last_index(),
                     );
@@ -1112,6 +1148,9 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
                     self.txn_context()
                         .on_became_follower(self.term(), self.region());
                     self.update_merge_progress_on_became_follower();
+                    // Only a leader broadcasts commit indexes, so a peer that
+                    // stepped down no longer counts as forcing them.
+                    self.set_force_bcast_commit(false);
                 }
                 _ => {}
             }
@@ -11,6 +11,7 @@ use raftstore::{
     coprocessor::{Cmd, CmdObserveInfo, CoprocessorHost, ObserveLevel},
     store::{
         fsm::{apply::DEFAULT_APPLY_WB_SIZE, ApplyMetrics},
+        region_meta::AdminCmdHistoryEntry,
         Config, ReadTask,
     },
 };
@@ -62,6 +63,8 @@ pub struct Apply<EK: KvEngine, R> {
     /// persisted (flushed).
     modifications: DataTrace,
     admin_cmd_result: Vec<AdminCmdResult>,
+    /// Applied admin commands that have not been reported to the peer yet.
+    admin_cmd_history: Vec<AdminCmdHistoryEntry>,
     flush_state: Arc<FlushState>,
     sst_apply_state: SstApplyState,
     sst_applied_index: Vec<SstApplyIndex>,
@@ -132,6 +135,7 @@ impl<EK: KvEngine, R> Apply<EK, R> {
             applied_index: flush_state.applied_index(),
             modifications: [0; DATA_CFS_LEN],
             admin_cmd_result: vec![],
+            admin_cmd_history: vec![],
             region_state,
             tablet_registry,
             read_scheduler,
@@ -287,6 +291,16 @@ impl<EK: KvEngine, R> Apply<EK, R> {
         mem::take(&mut self.admin_cmd_result)
     }
 
+    #[inline]
+    pub fn push_admin_cmd_history(&mut self, entry: AdminCmdHistoryEntry) {
+        self.admin_cmd_history.push(entry);
+    }
+
+    #[inline]
+    pub fn take_admin_cmd_history(&mut self) -> Vec<AdminCmdHistoryEntry> {
+        mem::take(&mut self.admin_cmd_history)
+    }
+
     #[inline]
     pub fn release_memory(&mut self) {
         mem::take(&mut self.key_buffer);
@@ -14,6 +14,7 @@ use engine_traits::{
 use kvproto::{
     metapb::{self, PeerRole},
     pdpb,
+    raft_cmdpb::AdminCmdType,
     raft_serverpb::RaftMessage,
 };
 use raft::{eraftpb, RawNode, StateRole};
@@ -21,7 +22,10 @@ use raftstore::{
     coprocessor::{CoprocessorHost, RegionChangeEvent, RegionChangeReason},
     store::{
         fsm::ApplyMetrics,
-        metrics::RAFT_PEER_PENDING_DURATION,
+        metrics::{
+            BCAST_COMMIT_TRANSITION_COUNTER_VEC, FORCE_BCAST_COMMIT_GAUGE,
+            RAFT_PEER_PENDING_DURATION,
+        },
         util::{Lease, RegionReadProgress},
         BucketStatsInfo, Config, EntryStorage, ForceLeaderState, PeerStat, ProposalQueue,
         ReadDelegate, ReadIndexQueue, ReadProgress, TabletSnapManager, UnsafeRecoveryState,
@@ -36,11 +40,11 @@ use crate::{
     batch::StoreContext,
     fsm::ApplyScheduler,
     operation::{
-        AbnormalPeerContext, AsyncWriter, CompactLogContext, DestroyProgress, GcPeerContext,
-        MergeContext, ProposalControl, ReplayWatch, SimpleWriteReqEncoder, SplitFlowControl,
-        SplitPendingAppend, TxnContext,
+        AbnormalPeerContext, AdminCmdHistory, AsyncWriter, CompactLogContext, DestroyProgress,
+        GcPeerContext, MergeContext, ProposalControl, ReplayWatch, SimpleWriteReqEncoder,
+        SplitFlowControl, SplitLatencyInspector, SplitPendingAppend, TxnContext,
     },
-    router::{ApplyTask, CmdResChannel, PeerTick, QueryResChannel},
+    router::{ApplyTask, CmdResChannel, PeerTick, QueryResChannel, WarmUpEntryCacheChannel},
     Result,
 };
 
@@ -98,9 +102,25 @@ pub struct Peer<EK: KvEngine, ER: RaftEngine> {
     /// Check whether this proposal can be proposed based on its epoch.
     proposal_control: ProposalControl,
 
+    /// Whether commit broadcasts are currently forced to be immediate instead
+    /// of lazily piggybacked on the next regular message, see
+    /// `set_force_bcast_commit`.
+    force_bcast_commit: bool,
+
+    /// The last applied admin commands, kept for post-mortem analysis.
+    admin_cmd_history: AdminCmdHistory,
+
+    /// The admin command whose pre-flush phase is in progress. During that
+    /// phase nothing is recorded in `proposal_control` yet, so it's tracked
+    /// separately to hint PD through the region heartbeat.
+    pending_pre_flush_admin: Option<AdminCmdType>,
+
     // Trace which peers have not finished split.
     split_trace: Vec<(u64, HashSet<u64>)>,
     split_flow_control: SplitFlowControl,
+    /// Stage timestamps of the batch split the peer is leading, see
+    /// `SplitLatencyInspector`.
+    split_latency_inspector: SplitLatencyInspector,
     /// `MsgAppend` messages from newly split leader should be step after peer
     /// steps snapshot from split, otherwise leader may send an unnecessary
     /// snapshot. So the messages are recorded temporarily and will be handled
@@ -118,6 +138,11 @@ pub struct Peer<EK: KvEngine, ER: RaftEngine> {
     /// lead_transferee if this peer(leader) is in a leadership transferring.
     leader_transferee: u64,
 
+    /// Channels waiting on an explicitly triggered entry cache warmup, see
+    /// `PeerMsg::WarmUpEntryCache`. Resolved when the warmup fetch completes
+    /// or times out.
+    warmup_entry_cache_waiters: Vec<WarmUpEntryCacheChannel>,
+
     long_uncommitted_threshold: u64,
 
     /// Pending messages to be sent on handle ready. We should avoid sending
@@ -218,6 +243,11 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             region_buckets_info: BucketStatsInfo::default(),
             txn_context: TxnContext::default(),
             proposal_control: ProposalControl::new(0),
+            // `new_raft_config` enables commit broadcast skipping, so the
+            // peer starts out not forcing immediate broadcasts.
+            force_bcast_commit: false,
+            admin_cmd_history: AdminCmdHistory::default(),
+            pending_pre_flush_admin: None,
             pending_ticks: Vec::new(),
             split_trace: vec![],
             split_pending_append: SplitPendingAppend::default(),
@@ -225,7 +255,9 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
             flush_state,
             sst_apply_state,
             split_flow_control: SplitFlowControl::default(),
+            split_latency_inspector: SplitLatencyInspector::default(),
             leader_transferee: raft::INVALID_ID,
+            warmup_entry_cache_waiters: vec![],
             long_uncommitted_threshold: cmp::max(
                 cfg.long_uncommitted_base_threshold.0.as_secs(),
                 1,
@@ -810,6 +842,62 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         &self.proposal_control
     }
 
+    /// Whether this peer is currently forcing immediate commit broadcasts,
+    /// see `set_force_bcast_commit`.
+    #[inline]
+    pub fn forcing_bcast_commit(&self) -> bool {
+        self.force_bcast_commit
+    }
+
+    /// The sole owner of the raft `skip_bcast_commit` flag.
+    ///
+    /// `new_raft_config` enables commit broadcast skipping, so in the steady
+    /// state followers learn the commit index piggybacked on the next regular
+    /// message. Immediate broadcasts are forced while an uncommitted admin
+    /// command is in flight (its side effects must not be delayed on the
+    /// followers) and right after becoming leader, and restored once the
+    /// blocking condition clears. Funneling every toggle through this helper
+    /// keeps the transition counters and the forcing-regions gauge accurate;
+    /// don't call `skip_bcast_commit` on the raft group directly.
+    pub fn set_force_bcast_commit(&mut self, force: bool) {
+        if self.force_bcast_commit == force {
+            return;
+        }
+        self.force_bcast_commit = force;
+        self.raft_group.skip_bcast_commit(!force);
+        if force {
+            BCAST_COMMIT_TRANSITION_COUNTER_VEC
+                .with_label_values(&["force"])
+                .inc();
+            FORCE_BCAST_COMMIT_GAUGE.inc();
+        } else {
+            BCAST_COMMIT_TRANSITION_COUNTER_VEC
+                .with_label_values(&["skip"])
+                .inc();
+            FORCE_BCAST_COMMIT_GAUGE.dec();
+        }
+    }
+
+    #[inline]
+    pub fn admin_cmd_history_mut(&mut self) -> &mut AdminCmdHistory {
+        &mut self.admin_cmd_history
+    }
+
+    #[inline]
+    pub fn admin_cmd_history(&self) -> &AdminCmdHistory {
+        &self.admin_cmd_history
+    }
+
+    #[inline]
+    pub fn pending_pre_flush_admin(&self) -> Option<AdminCmdType> {
+        self.pending_pre_flush_admin
+    }
+
+    #[inline]
+    pub fn set_pending_pre_flush_admin(&mut self, cmd_type: Option<AdminCmdType>) {
+        self.pending_pre_flush_admin = cmd_type;
+    }
+
     #[inline]
     pub fn proposal_control_advance_apply(&mut self, apply_index: u64) {
         let region = self.raft_group.store().region();
@@ -871,6 +959,16 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         &mut self.split_flow_control
     }
 
+    #[inline]
+    pub fn split_latency_inspector(&self) -> &SplitLatencyInspector {
+        &self.split_latency_inspector
+    }
+
+    #[inline]
+    pub fn split_latency_inspector_mut(&mut self) -> &mut SplitLatencyInspector {
+        &mut self.split_latency_inspector
+    }
+
     #[inline]
     pub fn refresh_leader_transferee(&mut self) -> u64 {
         mem::replace(
@@ -887,6 +985,11 @@ impl<EK: KvEngine, ER: RaftEngine> Peer<EK, ER> {
         self.leader_transferee
     }
 
+    #[inline]
+    pub fn warmup_entry_cache_waiters_mut(&mut self) -> &mut Vec<WarmUpEntryCacheChannel> {
+        &mut self.warmup_entry_cache_waiters
+    }
+
     #[inline]
     pub fn leader_transferring(&self) -> bool {
         self.leader_transferee != raft::INVALID_ID
@@ -1,7 +1,7 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
 use pd_client::{BucketMeta, BucketStat};
-use raftstore::store::fsm::ApplyMetrics;
+use raftstore::store::{fsm::ApplyMetrics, region_meta::AdminCmdHistoryEntry};
 
 use super::message::CaptureChange;
 use crate::operation::{AdminCmdResult, CommittedEntries, DataTrace, GenSnapTask};
@@ -22,6 +22,7 @@ pub struct ApplyRes {
     pub applied_index: u64,
     pub applied_term: u64,
     pub admin_result: Box<[AdminCmdResult]>,
+    pub admin_cmd_history: Vec<AdminCmdHistoryEntry>,
     pub modifications: DataTrace,
     pub metrics: ApplyMetrics,
     pub bucket_stat: Option<BucketStat>,
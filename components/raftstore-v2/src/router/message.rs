@@ -24,7 +24,7 @@ use tikv_util::time::Instant;
 
 use super::response_channel::{
     AnyResChannel, CmdResChannel, CmdResSubscriber, DebugInfoChannel, QueryResChannel,
-    QueryResSubscriber,
+    QueryResSubscriber, WarmUpEntryCacheChannel, WarmUpEntryCacheSubscriber,
 };
 use crate::{
     operation::{CatchUpLogs, ReplayWatch, RequestHalfSplit, RequestSplit, SplitInit},
@@ -188,6 +188,17 @@ pub enum PeerMsg {
         ready_number: u64,
     },
     QueryDebugInfo(DebugInfoChannel),
+    /// Trigger an entry cache warmup explicitly and report the outcome once
+    /// the async fetch completes. Warming up a follower is also valid (it is
+    /// what a leader transfer target does), so the message works on both
+    /// leaders and followers.
+    WarmUpEntryCache {
+        /// Warm the cache so that it covers the entries back to this index.
+        /// `None` means everything still in the raft log, i.e. back to the
+        /// entry right after the truncated index.
+        up_to_index: Option<u64>,
+        ch: WarmUpEntryCacheChannel,
+    },
     DataFlushed {
         cf: &'static str,
         tablet_index: u64,
@@ -320,6 +331,11 @@ impl PeerMsg {
         )
     }
 
+    pub fn warm_up_entry_cache(up_to_index: Option<u64>) -> (Self, WarmUpEntryCacheSubscriber) {
+        let (ch, sub) = WarmUpEntryCacheChannel::pair();
+        (PeerMsg::WarmUpEntryCache { up_to_index, ch }, sub)
+    }
+
     pub fn unsafe_write(data: SimpleWriteBinary) -> Self {
         PeerMsg::UnsafeWrite(UnsafeWrite {
             send_time: Instant::now(),
@@ -17,7 +17,8 @@ pub use self::{
     response_channel::{
         build_any_channel, AnyResChannel, AnyResSubscriber, BaseSubscriber, CmdResChannel,
         CmdResChannelBuilder, CmdResEvent, CmdResStream, CmdResSubscriber, DebugInfoChannel,
-        DebugInfoSubscriber, QueryResChannel, QueryResult, ReadResponse,
+        DebugInfoSubscriber, EntryCacheWarmupOutcome, QueryResChannel, QueryResult, ReadResponse,
+        WarmUpEntryCacheChannel, WarmUpEntryCacheSubscriber,
     },
 };
 pub use super::operation::DiskSnapBackupHandle;
@@ -701,6 +701,36 @@ impl Debug for DebugInfoChannel {
     }
 }
 
+/// The outcome of an explicitly triggered entry cache warmup, see
+/// `PeerMsg::WarmUpEntryCache`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryCacheWarmupOutcome {
+    /// The cache already covered the requested range, nothing was fetched.
+    AlreadyWarm,
+    /// The fetched entries have been loaded into the cache.
+    Finished,
+    /// The fetch did not complete within `max_entry_cache_warmup_duration`.
+    Timeout,
+    /// The fetch failed or its result could not be used.
+    Failed,
+}
+
+pub type WarmUpEntryCacheChannel = BaseChannel<EntryCacheWarmupOutcome>;
+pub type WarmUpEntryCacheSubscriber = BaseSubscriber<EntryCacheWarmupOutcome>;
+
+impl WarmUpEntryCacheChannel {
+    #[inline]
+    pub fn pair() -> (Self, WarmUpEntryCacheSubscriber) {
+        pair()
+    }
+}
+
+impl Debug for WarmUpEntryCacheChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "WarmUpEntryCacheChannel")
+    }
+}
+
 #[cfg(feature = "testexport")]
 mod flush_channel {
     use super::*;
@@ -4,8 +4,8 @@ use std::{sync::Arc, time::Duration};
 
 use collections::HashMap;
 use engine_traits::{KvEngine, RaftEngine};
-use kvproto::{metapb, pdpb};
-use pd_client::{metrics::PD_HEARTBEAT_COUNTER_VEC, BucketStat, PdClient, RegionStat};
+use kvproto::{metapb, pdpb, raft_cmdpb::AdminCmdType};
+use pd_client::{metrics::PD_HEARTBEAT_COUNTER_VEC, BucketStat, Feature, PdClient, RegionStat};
 use raftstore::store::{ReadStats, WriteStats};
 use resource_metering::RawRecords;
 use slog::{debug, error, info};
@@ -17,6 +17,10 @@ use crate::{
     router::{CmdResChannel, PeerMsg},
 };
 
+/// PDs new enough to understand the pending admin hint in region heartbeats.
+/// Older ones simply never receive it.
+const PENDING_ADMIN_HINT_FEATURE: Feature = Feature::require(8, 2, 0);
+
 pub struct RegionHeartbeatTask {
     pub term: u64,
     pub region: metapb::Region,
@@ -28,6 +32,9 @@ pub struct RegionHeartbeatTask {
     pub approximate_size: Option<u64>,
     pub approximate_keys: Option<u64>,
     pub wait_data_peers: Vec<u64>,
+    /// The admin command in progress on the region, e.g. a split waiting for
+    /// its pre-flush, so PD can avoid scheduling conflicting operators.
+    pub pending_admin: Option<AdminCmdType>,
     // TODO: RegionReplicationStatus
 }
 
@@ -152,6 +159,18 @@ where
             }
         };
 
+        // Only attach the pending admin hint when every PD in the cluster
+        // understands it.
+        let pending_admin = if self
+            .pd_client
+            .feature_gate()
+            .can_enable(PENDING_ADMIN_HINT_FEATURE)
+        {
+            task.pending_admin
+        } else {
+            None
+        };
+
         let region_stat = RegionStat {
             down_peers: task.down_peers,
             pending_peers: task.pending_peers,
@@ -164,6 +183,7 @@ where
             approximate_keys,
             last_report_ts,
             cpu_usage,
+            pending_admin,
         };
         self.store_stat
             .region_bytes_written
@@ -3,7 +3,7 @@
 use std::{
     fmt::{self, Display, Formatter},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -14,7 +14,9 @@ use engine_traits::{
 };
 use fail::fail_point;
 use kvproto::{import_sstpb::SstMeta, metapb::Region};
-use raftstore::store::{TabletSnapKey, TabletSnapManager};
+use raftstore::store::{
+    metrics::FLUSH_MEMTABLE_DEDUPPED_COUNTER, TabletSnapKey, TabletSnapManager,
+};
 use slog::{debug, error, info, warn, Logger};
 use sst_importer::SstImporter;
 use tikv_util::{
@@ -29,6 +31,51 @@ use tikv_util::{
 const DEFAULT_HIGH_PRI_POOL_SIZE: usize = 2;
 const DEFAULT_LOW_PRI_POOL_SIZE: usize = 6;
 
+/// How long a recorded flush suppresses duplicate flush requests for the same
+/// tablet.
+const FLUSH_DEDUP_TTL: Duration = Duration::from_secs(5);
+/// Upper bound on the number of records kept for deduplication.
+const FLUSH_DEDUP_CAPACITY: usize = 4096;
+
+/// Deduplicates externally requested memtable flushes on the receiving store.
+///
+/// During cluster-wide split activity a store can receive `MsgFlushMemtable`
+/// for the same region from several leaders within a second -- leader
+/// retries and duplicate sends along the pre-flush retry path. One flush of a
+/// tablet serves all of them, so once a flush for a (region, tablet index)
+/// pair has been scheduled, further requests are dropped until the record
+/// expires. The map is bounded: when it is full, expired records are pruned
+/// first, and if none can be pruned, new requests simply pass through
+/// undeduplicated instead of evicting newer records.
+#[derive(Clone, Default)]
+pub struct FlushDedup {
+    // (region_id, tablet_index) -> when the flush was scheduled.
+    inner: Arc<Mutex<HashMap<(u64, u64), Instant>>>,
+}
+
+impl FlushDedup {
+    /// Returns true if a flush for this tablet has already been scheduled
+    /// within the TTL, in which case the caller should not schedule another
+    /// one. Otherwise the flush is recorded and the caller should proceed.
+    pub fn check_and_record(&self, region_id: u64, tablet_index: u64) -> bool {
+        let now = Instant::now_coarse();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(scheduled_at) = inner.get(&(region_id, tablet_index))
+            && now.saturating_duration_since(*scheduled_at) < FLUSH_DEDUP_TTL
+        {
+            FLUSH_MEMTABLE_DEDUPPED_COUNTER.inc();
+            return true;
+        }
+        if inner.len() >= FLUSH_DEDUP_CAPACITY {
+            inner.retain(|_, t| now.saturating_duration_since(*t) < FLUSH_DEDUP_TTL);
+        }
+        if inner.len() < FLUSH_DEDUP_CAPACITY {
+            inner.insert((region_id, tablet_index), now);
+        }
+        false
+    }
+}
+
 pub enum Task<EK> {
     Trim {
         tablet: EK,
@@ -682,6 +729,29 @@ mod tests {
     use super::*;
     use crate::operation::test_util::create_tmp_importer;
 
+    #[test]
+    fn test_flush_dedup() {
+        let dedup = FlushDedup::default();
+        let before = FLUSH_MEMTABLE_DEDUPPED_COUNTER.get();
+        // Five requests for the same tablet collapse to one flush.
+        assert!(!dedup.check_and_record(1, 5));
+        for _ in 0..4 {
+            assert!(dedup.check_and_record(1, 5));
+        }
+        assert_eq!(FLUSH_MEMTABLE_DEDUPPED_COUNTER.get(), before + 4);
+        // A new tablet of the same region is a different flush.
+        assert!(!dedup.check_and_record(1, 6));
+        assert!(dedup.check_and_record(1, 6));
+
+        // When the map is full and nothing has expired, requests pass
+        // through undeduplicated instead of evicting newer records.
+        for i in 0..FLUSH_DEDUP_CAPACITY as u64 {
+            dedup.check_and_record(2, i);
+        }
+        assert!(!dedup.check_and_record(3, 0));
+        assert!(!dedup.check_and_record(3, 0));
+    }
+
     #[test]
     fn test_race_between_destroy_and_trim() {
         let dir = Builder::new()
@@ -8,9 +8,12 @@
 #[allow(dead_code)]
 #[path = "../integrations/cluster.rs"]
 mod cluster;
+mod scenario;
+mod test_admin_resource_gate;
 mod test_basic_write;
 mod test_bootstrap;
 mod test_bucket;
+mod test_entry_cache_warmup;
 mod test_life;
 mod test_merge;
 mod test_pd_heartbeat;
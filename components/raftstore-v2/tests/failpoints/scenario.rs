@@ -0,0 +1,423 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A scriptable runner for admin command interleaving tests.
+//!
+//! Interleaving tests all follow the same shape: park one stage of an admin
+//! command at a failpoint, slip another command in, resume, and check the
+//! outcome of both. Written by hand that shape drowns in subscriber plumbing
+//! and polling loops, so [`Scenario`] scripts it instead: a test builds an
+//! ordered list of steps -- pause or resume a failpoint, submit an admin
+//! command under a tag, await the tagged response, assert on the proposal
+//! control state of a peer -- and `run` drives the cluster through them.
+//! Failpoints that are still installed when the script ends are removed even
+//! if an assertion fails halfway, so a broken scenario can't park the next
+//! one.
+
+use std::{
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use collections::HashMap;
+use futures::executor::block_on;
+use kvproto::{
+    metapb, pdpb,
+    raft_cmdpb::{AdminCmdType, RaftCmdRequest, RaftCmdResponse},
+};
+use raft::prelude::ConfChangeType;
+use raftstore::store::region_meta::ProposalControlState;
+use raftstore_v2::router::{CmdResSubscriber, PeerMsg};
+use txn_types::WriteBatchFlags;
+
+use crate::cluster::{split_helper::new_batch_split_region_request, Cluster};
+
+/// Failpoints commonly scripted by interleaving scenarios, grouped around
+/// the tablet flush, propose and apply stages of an admin command.
+#[allow(dead_code)]
+pub mod fp {
+    /// Parks a batch split after its tablet pre-flush finished, right before
+    /// the command is sent back to the peer for the actual proposal.
+    pub const BEFORE_RESEND_BATCH_SPLIT: &str = "before_resend_batch_split";
+    /// Fails scheduling the pre-flush task of a batch split.
+    pub const PRE_FLUSH_SCHEDULE_FAIL: &str = "pre_flush_schedule_fail";
+    /// Hit after pending writes are proposed, before the admin command.
+    pub const AFTER_PROPOSE_PENDING_WRITES: &str = "after_propose_pending_writes";
+    /// Parks the apply pool before committed entries are applied.
+    pub const APPLY_COMMITTED_ENTRIES: &str = "APPLY_COMMITTED_ENTRIES";
+    /// Parks the target peer before `CommitMerge` is applied.
+    pub const APPLY_COMMIT_MERGE: &str = "apply_commit_merge";
+    /// Hit when a source peer applies `RollbackMerge`.
+    pub const APPLY_ROLLBACK_MERGE: &str = "apply_rollback_merge";
+    /// Hit when the target peer starts to schedule `CommitMerge`.
+    pub const START_COMMIT_MERGE: &str = "start_commit_merge";
+}
+
+/// Fills the admin request of a header-prepared `RaftCmdRequest`. The header
+/// is built with `TestRouter::new_request_for` when the submit step runs, so
+/// the request always carries the epoch of that moment.
+pub type AdminFill = Box<dyn FnOnce(&mut RaftCmdRequest)>;
+
+pub fn batch_split(split_key: &[u8], new_region_id: u64, new_peer_id: u64) -> AdminFill {
+    let split_key = split_key.to_vec();
+    Box::new(move |req| {
+        let mut split_id = pdpb::SplitId::new();
+        split_id.new_region_id = new_region_id;
+        split_id.new_peer_ids = vec![new_peer_id];
+        let admin_req = new_batch_split_region_request(vec![split_key], vec![split_id], false);
+        req.set_admin_request(admin_req);
+    })
+}
+
+pub fn add_learner(peer: metapb::Peer) -> AdminFill {
+    Box::new(move |req| {
+        let admin_req = req.mut_admin_request();
+        admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+        admin_req
+            .mut_change_peer()
+            .set_change_type(ConfChangeType::AddLearnerNode);
+        admin_req.mut_change_peer().set_peer(peer);
+    })
+}
+
+pub fn remove_peer(peer: metapb::Peer) -> AdminFill {
+    Box::new(move |req| {
+        let admin_req = req.mut_admin_request();
+        admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+        admin_req
+            .mut_change_peer()
+            .set_change_type(ConfChangeType::RemoveNode);
+        admin_req.mut_change_peer().set_peer(peer);
+    })
+}
+
+pub fn transfer_leader(peer: metapb::Peer) -> AdminFill {
+    Box::new(move |req| {
+        let admin_req = req.mut_admin_request();
+        admin_req.set_cmd_type(AdminCmdType::TransferLeader);
+        admin_req.mut_transfer_leader().set_peer(peer);
+    })
+}
+
+pub fn prepare_merge(target: metapb::Region) -> AdminFill {
+    Box::new(move |req| {
+        let admin_req = req.mut_admin_request();
+        admin_req.set_cmd_type(AdminCmdType::PrepareMerge);
+        admin_req.mut_prepare_merge().set_target(target);
+    })
+}
+
+/// Marks the request as having finished the pre-flush phase already, so a
+/// batch split skips the flush and is proposed right away.
+pub fn pre_flushed(fill: AdminFill) -> AdminFill {
+    Box::new(move |req| {
+        let flags = req.get_header().get_flags() | WriteBatchFlags::PRE_FLUSH_FINISHED.bits();
+        req.mut_header().set_flags(flags);
+        fill(req);
+    })
+}
+
+enum Step {
+    Pause(&'static str),
+    Resume(&'static str),
+    NotifyOn(&'static str),
+    AwaitNotify(&'static str, Duration),
+    SubmitAdmin {
+        tag: &'static str,
+        offset: usize,
+        region_id: u64,
+        fill: AdminFill,
+    },
+    ExpectOk(&'static str),
+    ExpectErr(&'static str, &'static str),
+    ExpectAny(&'static str),
+    WaitEpoch {
+        offset: usize,
+        region_id: u64,
+        timeout: Duration,
+        check: Box<dyn Fn(&metapb::RegionEpoch) -> bool>,
+    },
+    AssertControl {
+        offset: usize,
+        region_id: u64,
+        check: Box<dyn Fn(&ProposalControlState) -> bool>,
+    },
+    Call(Box<dyn FnOnce(&Cluster)>),
+}
+
+/// Removes the failpoints a script left behind, pause callbacks included.
+/// Kept separate from the step loop so a panicking assertion still unparks
+/// everything, like `fail::FailGuard` does for a single failpoint.
+struct FailpointCleanup(Vec<&'static str>);
+
+impl Drop for FailpointCleanup {
+    fn drop(&mut self) {
+        for fp in self.0.drain(..) {
+            fail::remove(fp);
+        }
+    }
+}
+
+pub struct Scenario<'a> {
+    cluster: &'a Cluster,
+    steps: Vec<Step>,
+}
+
+impl<'a> Scenario<'a> {
+    pub fn new(cluster: &'a Cluster) -> Self {
+        Scenario {
+            cluster,
+            steps: vec![],
+        }
+    }
+
+    /// Pauses the named failpoint until it is resumed or the script ends.
+    #[must_use]
+    pub fn pause(mut self, fp: &'static str) -> Self {
+        self.steps.push(Step::Pause(fp));
+        self
+    }
+
+    #[must_use]
+    pub fn resume(mut self, fp: &'static str) -> Self {
+        self.steps.push(Step::Resume(fp));
+        self
+    }
+
+    /// Installs a callback on the named failpoint that signals every time it
+    /// is hit, to be consumed by `await_notify`.
+    #[must_use]
+    pub fn notify_on(mut self, fp: &'static str) -> Self {
+        self.steps.push(Step::NotifyOn(fp));
+        self
+    }
+
+    /// Blocks until the failpoint registered with `notify_on` is hit.
+    #[must_use]
+    pub fn await_notify(mut self, fp: &'static str, timeout: Duration) -> Self {
+        self.steps.push(Step::AwaitNotify(fp, timeout));
+        self
+    }
+
+    /// Submits an admin command on the region without waiting for the
+    /// response. The response is kept under `tag` for a later expect step.
+    #[must_use]
+    pub fn submit_admin(
+        mut self,
+        tag: &'static str,
+        offset: usize,
+        region_id: u64,
+        fill: AdminFill,
+    ) -> Self {
+        self.steps.push(Step::SubmitAdmin {
+            tag,
+            offset,
+            region_id,
+            fill,
+        });
+        self
+    }
+
+    /// Waits for the tagged response and asserts it carries no error.
+    #[must_use]
+    pub fn expect_ok(mut self, tag: &'static str) -> Self {
+        self.steps.push(Step::ExpectOk(tag));
+        self
+    }
+
+    /// Waits for the tagged response and asserts its error message contains
+    /// `pattern`.
+    #[must_use]
+    pub fn expect_err(mut self, tag: &'static str, pattern: &'static str) -> Self {
+        self.steps.push(Step::ExpectErr(tag, pattern));
+        self
+    }
+
+    /// Waits for the tagged response without checking its content.
+    #[must_use]
+    pub fn expect_any(mut self, tag: &'static str) -> Self {
+        self.steps.push(Step::ExpectAny(tag));
+        self
+    }
+
+    /// Polls the epoch of the region until `check` passes.
+    #[must_use]
+    pub fn wait_epoch(
+        mut self,
+        offset: usize,
+        region_id: u64,
+        timeout: Duration,
+        check: impl Fn(&metapb::RegionEpoch) -> bool + 'static,
+    ) -> Self {
+        self.steps.push(Step::WaitEpoch {
+            offset,
+            region_id,
+            timeout,
+            check: Box::new(check),
+        });
+        self
+    }
+
+    /// Polls the proposal control state of the peer until `check` passes.
+    #[must_use]
+    pub fn assert_control(
+        mut self,
+        offset: usize,
+        region_id: u64,
+        check: impl Fn(&ProposalControlState) -> bool + 'static,
+    ) -> Self {
+        self.steps.push(Step::AssertControl {
+            offset,
+            region_id,
+            check: Box::new(check),
+        });
+        self
+    }
+
+    /// An escape hatch for setup that has no dedicated step, e.g. driving a
+    /// merge through its helper or dispatching buffered raft messages.
+    #[must_use]
+    pub fn call(mut self, f: impl FnOnce(&Cluster) + 'static) -> Self {
+        self.steps.push(Step::Call(Box::new(f)));
+        self
+    }
+
+    /// Delivers buffered raft messages of the region, see
+    /// `Cluster::dispatch`.
+    #[must_use]
+    pub fn dispatch(self, region_id: u64) -> Self {
+        self.call(move |cluster| cluster.dispatch(region_id, vec![]))
+    }
+
+    pub fn run(self) {
+        let mut cleanup = FailpointCleanup(vec![]);
+        let mut pending: HashMap<&'static str, CmdResSubscriber> = HashMap::default();
+        let mut notified: HashMap<&'static str, mpsc::Receiver<()>> = HashMap::default();
+        for step in self.steps {
+            match step {
+                Step::Pause(fp) => {
+                    fail::cfg(fp, "pause").unwrap();
+                    cleanup.0.push(fp);
+                }
+                Step::Resume(fp) => {
+                    fail::remove(fp);
+                    cleanup.0.retain(|p| *p != fp);
+                }
+                Step::NotifyOn(fp) => {
+                    let (tx, rx) = mpsc::channel();
+                    let tx = Mutex::new(tx);
+                    fail::cfg_callback(fp, move || {
+                        let _ = tx.lock().unwrap().send(());
+                    })
+                    .unwrap();
+                    cleanup.0.push(fp);
+                    notified.insert(fp, rx);
+                }
+                Step::AwaitNotify(fp, timeout) => {
+                    let rx = notified
+                        .get(fp)
+                        .unwrap_or_else(|| panic!("no notify_on for failpoint {}", fp));
+                    rx.recv_timeout(timeout)
+                        .unwrap_or_else(|e| panic!("failpoint {} is not hit: {:?}", fp, e));
+                }
+                Step::SubmitAdmin {
+                    tag,
+                    offset,
+                    region_id,
+                    fill,
+                } => {
+                    let router = &self.cluster.routers[offset];
+                    let mut req = router.new_request_for(region_id);
+                    fill(&mut req);
+                    let (msg, sub) = PeerMsg::admin_command(req);
+                    router.send(region_id, msg).unwrap();
+                    assert!(
+                        pending.insert(tag, sub).is_none(),
+                        "duplicated submission tag {}",
+                        tag
+                    );
+                }
+                Step::ExpectOk(tag) => {
+                    let resp = Self::wait_resp(&mut pending, tag);
+                    assert!(!resp.get_header().has_error(), "{}: {:?}", tag, resp);
+                }
+                Step::ExpectErr(tag, pattern) => {
+                    let resp = Self::wait_resp(&mut pending, tag);
+                    assert!(resp.get_header().has_error(), "{}: {:?}", tag, resp);
+                    assert!(
+                        resp.get_header().get_error().get_message().contains(pattern),
+                        "{}: {:?}",
+                        tag,
+                        resp
+                    );
+                }
+                Step::ExpectAny(tag) => {
+                    Self::wait_resp(&mut pending, tag);
+                }
+                Step::WaitEpoch {
+                    offset,
+                    region_id,
+                    timeout,
+                    check,
+                } => {
+                    let router = &self.cluster.routers[offset];
+                    let timer = Instant::now();
+                    loop {
+                        let epoch = router
+                            .new_request_for(region_id)
+                            .take_header()
+                            .take_region_epoch();
+                        if check(&epoch) {
+                            break;
+                        }
+                        assert!(
+                            timer.elapsed() < timeout,
+                            "epoch of region {} did not pass the check: {:?}",
+                            region_id,
+                            epoch
+                        );
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Step::AssertControl {
+                    offset,
+                    region_id,
+                    check,
+                } => {
+                    let router = &self.cluster.routers[offset];
+                    let timer = Instant::now();
+                    loop {
+                        let state = router
+                            .must_query_debug_info(region_id, Duration::from_secs(1))
+                            .unwrap()
+                            .proposal_control
+                            .unwrap();
+                        if check(&state) {
+                            break;
+                        }
+                        assert!(
+                            timer.elapsed() < Duration::from_secs(3),
+                            "proposal control of region {} did not pass the check: {:?}",
+                            region_id,
+                            state
+                        );
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Step::Call(f) => f(self.cluster),
+            }
+        }
+        let unawaited: Vec<_> = pending.keys().collect();
+        assert!(unawaited.is_empty(), "responses never awaited: {:?}", unawaited);
+    }
+
+    fn wait_resp(
+        pending: &mut HashMap<&'static str, CmdResSubscriber>,
+        tag: &'static str,
+    ) -> RaftCmdResponse {
+        let sub = pending
+            .remove(tag)
+            .unwrap_or_else(|| panic!("no submission tagged {}", tag));
+        block_on(sub.result()).unwrap()
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::time::Duration;
+
+use tikv_util::store::new_learner_peer;
+
+use crate::{
+    cluster::Cluster,
+    scenario::{add_learner, batch_split, remove_peer, Scenario},
+};
+
+/// The store resource gate refuses a batch split while the store disk is
+/// full, but conf changes keep passing: one that removes a peer shrinks
+/// disk usage and must stay available to free space. Once the disk
+/// recovers, the same split succeeds.
+#[test]
+fn test_admin_resource_gate_disk_full() {
+    let cluster = Cluster::with_node_count(2, None);
+    let region_id = 2;
+    cluster.routers[0].wait_applied_to_current_term(region_id, Duration::from_secs(3));
+    let learner = new_learner_peer(cluster.node(1).id(), 10);
+
+    // The leader store reports its disk as already full.
+    let fp = format!("disk_already_full_peer_{}", cluster.node(0).id());
+    fail::cfg(fp.as_str(), "return").unwrap();
+
+    Scenario::new(&cluster)
+        .submit_admin("gated-split", 0, region_id, batch_split(b"k11", 1000, 1001))
+        .expect_err("gated-split", "rejected by the resource gate")
+        .submit_admin("add-learner", 0, region_id, add_learner(learner.clone()))
+        .expect_ok("add-learner")
+        .submit_admin("remove-peer", 0, region_id, remove_peer(learner))
+        .expect_ok("remove-peer")
+        // Clear the disk status; the split goes through now.
+        .call(move |_| fail::remove(fp))
+        .submit_admin("split", 0, region_id, batch_split(b"k11", 1000, 1001))
+        .expect_ok("split")
+        .run();
+
+    let derived = cluster.routers[0].region_detail(region_id);
+    assert_eq!(derived.get_start_key(), b"k11");
+    let new_region = cluster.routers[0].region_detail(1000);
+    assert_eq!(new_region.get_end_key(), b"k11");
+}
@@ -0,0 +1,88 @@
+// Copyright 2025 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{thread, time::Duration};
+
+use engine_traits::CF_DEFAULT;
+use futures::executor::block_on;
+use raftstore_v2::{
+    router::{EntryCacheWarmupOutcome, PeerMsg},
+    SimpleWriteEncoder,
+};
+use tikv_util::config::ReadableDuration;
+
+use crate::cluster::{v2_default_config, Cluster};
+
+fn put_some_data(cluster: &Cluster) {
+    let router = &cluster.routers[0];
+    router.wait_applied_to_current_term(2, Duration::from_secs(3));
+    for i in 0..5 {
+        let header = Box::new(router.new_request_for(2).take_header());
+        let mut put = SimpleWriteEncoder::with_capacity(64);
+        put.put(CF_DEFAULT, format!("key{}", i).as_bytes(), b"value");
+        let resp = router.simple_write(2, header, put).unwrap();
+        assert!(!resp.get_header().has_error(), "{:?}", resp);
+    }
+    // Wait until the apply results are handled so that the entry cache is
+    // compacted and the warmup below has something to fetch.
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn test_explicit_entry_cache_warmup() {
+    let cluster = Cluster::default();
+    put_some_data(&cluster);
+    let router = &cluster.routers[0];
+
+    let (msg, sub) = PeerMsg::warm_up_entry_cache(None);
+    router.send(2, msg).unwrap();
+    assert_eq!(
+        block_on(sub.result()).unwrap(),
+        EntryCacheWarmupOutcome::Finished
+    );
+
+    // The outcome is also visible in the debug dump.
+    let meta = router
+        .must_query_debug_info(2, Duration::from_secs(3))
+        .unwrap();
+    let state = meta.entry_cache_warmup.unwrap();
+    assert!(state.is_finished);
+    assert!(!state.is_timeout);
+
+    // The cache covers the range now, so a second trigger is a no-op.
+    let (msg, sub) = PeerMsg::warm_up_entry_cache(None);
+    router.send(2, msg).unwrap();
+    assert_eq!(
+        block_on(sub.result()).unwrap(),
+        EntryCacheWarmupOutcome::AlreadyWarm
+    );
+}
+
+#[test]
+fn test_entry_cache_warmup_timeout() {
+    let mut config = v2_default_config();
+    config.max_entry_cache_warmup_duration = ReadableDuration::millis(20);
+    let cluster = Cluster::with_config(config);
+    put_some_data(&cluster);
+    let router = &cluster.routers[0];
+
+    // Keep the fetched entries from being delivered so the warmup cannot
+    // finish in time.
+    fail::cfg("worker_async_fetch_raft_log", "pause").unwrap();
+    let (msg, sub1) = PeerMsg::warm_up_entry_cache(None);
+    router.send(2, msg).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // A second trigger while the first one is in flight detects the timeout
+    // and resolves both waiters.
+    let (msg, sub2) = PeerMsg::warm_up_entry_cache(None);
+    router.send(2, msg).unwrap();
+    assert_eq!(
+        block_on(sub2.result()).unwrap(),
+        EntryCacheWarmupOutcome::Timeout
+    );
+    assert_eq!(
+        block_on(sub1.result()).unwrap(),
+        EntryCacheWarmupOutcome::Timeout
+    );
+    fail::remove("worker_async_fetch_raft_log");
+}
@@ -6,14 +6,18 @@ use std::{
 };
 
 use engine_traits::Peekable;
+use raftstore::store::metrics::MERGE_CATCH_UP_QUEUE_GAUGE;
 use raftstore_v2::router::{PeerMsg, PeerTick};
 use tikv_util::{config::ReadableDuration, info, store::new_peer};
 
-use crate::cluster::{
-    life_helper::assert_peer_not_exist,
-    merge_helper::merge_region,
-    split_helper::{put, split_region},
-    Cluster,
+use crate::{
+    cluster::{
+        life_helper::assert_peer_not_exist,
+        merge_helper::merge_region,
+        split_helper::{put, split_region},
+        v2_default_config, Cluster,
+    },
+    scenario::{fp, prepare_merge, transfer_leader, Scenario},
 };
 
 #[test]
@@ -220,29 +224,31 @@ fn test_merge_conflict_0() {
     );
     info!("regions: {:?}, {:?}, {:?}", region_1, region_2, region_3);
 
-    // pause merge progress of 2+3.
-    let fp = fail::FailGuard::new("apply_commit_merge", "pause");
-    merge_region(
-        &cluster,
-        0,
-        region_2.clone(),
-        peer_2,
-        region_3.clone(),
-        false,
-    );
-    // start merging 1+2. it should be aborted.
-    let (tx, rx) = mpsc::channel();
-    let tx = Mutex::new(tx);
-    fail::cfg_callback("apply_rollback_merge", move || {
-        tx.lock().unwrap().send(()).unwrap();
-    })
-    .unwrap();
-    let region_2 = cluster.routers[0].region_detail(region_2.get_id());
-    merge_region(&cluster, 0, region_1, peer_1, region_2, false);
-    drop(fp);
-    // wait for rollback.
-    rx.recv_timeout(std::time::Duration::from_secs(1)).unwrap();
-    fail::remove("apply_rollback_merge");
+    let region_2_id = region_2.get_id();
+    Scenario::new(&cluster)
+        // pause merge progress of 2+3.
+        .pause(fp::APPLY_COMMIT_MERGE)
+        .call({
+            let region_2 = region_2.clone();
+            let region_3 = region_3.clone();
+            move |cluster| {
+                merge_region(cluster, 0, region_2, peer_2, region_3, false);
+            }
+        })
+        // Region 2 has applied `PrepareMerge` and is parked on the target.
+        .assert_control(0, region_2_id, |control| {
+            control.applied_prepare_merge_index > 0
+        })
+        .notify_on(fp::APPLY_ROLLBACK_MERGE)
+        // start merging 1+2. it should be aborted.
+        .call(move |cluster| {
+            let region_2 = cluster.routers[0].region_detail(region_2_id);
+            merge_region(cluster, 0, region_1, peer_1, region_2, false);
+        })
+        .resume(fp::APPLY_COMMIT_MERGE)
+        // wait for rollback.
+        .await_notify(fp::APPLY_ROLLBACK_MERGE, Duration::from_secs(1))
+        .run();
 
     // Check region 1 is not merged and can serve writes.
     let mut resp = Default::default();
@@ -356,3 +362,165 @@ fn test_merge_conflict_1() {
     }
     assert!(!resp.get_header().has_error(), "{:?}", resp);
 }
+
+// A merging source must reject transfer leader until the merge is over,
+// otherwise the new leader could lose track of the merge progress.
+#[test]
+fn test_transfer_leader_rejected_during_merge() {
+    let mut cluster = Cluster::default();
+    let store_id = cluster.node(0).id();
+    let router = &mut cluster.routers[0];
+
+    let region_1 = router.region_detail(2);
+    let peer_1 = region_1.get_peers()[0].clone();
+    router.wait_applied_to_current_term(2, Duration::from_secs(3));
+    let peer_2 = new_peer(store_id, peer_1.get_id() + 1);
+    let region_1_id = region_1.get_id();
+    let region_2_id = region_1_id + 1;
+    let (region_1, region_2) = split_region(
+        router,
+        region_1,
+        peer_1.clone(),
+        region_2_id,
+        peer_2,
+        Some(format!("k{}k", region_1_id).as_bytes()),
+        Some(format!("k{}k", region_2_id).as_bytes()),
+        format!("k{}", region_2_id).as_bytes(),
+        format!("k{}", region_2_id).as_bytes(),
+        false,
+    );
+
+    Scenario::new(&cluster)
+        // Park the target before it applies `CommitMerge`, so the source
+        // stays in merging state after applying `PrepareMerge`.
+        .pause(fp::APPLY_COMMIT_MERGE)
+        .submit_admin("merge", 0, region_1_id, prepare_merge(region_2.clone()))
+        .dispatch(region_1_id)
+        .assert_control(0, region_1_id, |control| {
+            control.applied_prepare_merge_index > 0
+        })
+        // A transfer leader must bounce instead of breaking the merge. The
+        // target peer is never inspected, the command is rejected up front.
+        .submit_admin(
+            "transfer",
+            0,
+            region_1_id,
+            transfer_leader(new_peer(store_id + 1, peer_1.get_id() + 10)),
+        )
+        .expect_err("transfer", "merging mode")
+        .expect_any("merge")
+        .resume(fp::APPLY_COMMIT_MERGE)
+        .run();
+
+    // The merge completes once the target is unparked.
+    let timer = std::time::Instant::now();
+    loop {
+        let target = cluster.routers[0].region_detail(region_2_id);
+        if target.get_start_key() == region_1.get_start_key() {
+            break;
+        }
+        assert!(
+            timer.elapsed() < Duration::from_secs(5),
+            "merge not done: {:?}",
+            target
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Merges beyond `max_concurrent_merge_catch_up` must wait in the queue and
+// drain one by one while foreground writes keep working.
+#[test]
+fn test_merge_catch_up_concurrency_limit() {
+    let mut config = v2_default_config();
+    config.max_concurrent_merge_catch_up = 1;
+    let mut cluster = Cluster::with_config(config);
+    let store_id = cluster.node(0).id();
+    let router = &mut cluster.routers[0];
+
+    let mut region = router.region_detail(2);
+    let mut peer = region.get_peers()[0].clone();
+    router.wait_applied_to_current_term(2, Duration::from_secs(3));
+
+    // Split into 9 regions: 4 pairs to merge and a spare region that
+    // observes foreground writes.
+    let mut regions = vec![];
+    for _ in 0..8 {
+        let rid = region.get_id();
+        let new_peer = new_peer(store_id, peer.get_id() + 1);
+        let (lhs, rhs) = split_region(
+            router,
+            region,
+            peer.clone(),
+            rid + 1,
+            new_peer.clone(),
+            Some(format!("k{}k", rid).as_bytes()),
+            Some(format!("k{}k", rid + 1).as_bytes()),
+            format!("k{}", rid + 1).as_bytes(),
+            format!("k{}", rid + 1).as_bytes(),
+            false,
+        );
+        regions.push(lhs);
+        region = rhs;
+        peer = new_peer;
+    }
+    regions.push(region);
+
+    // Park CommitMerge applies so that the first merge keeps holding its
+    // catch up permit; with a limit of one the other merges must queue up.
+    fail::cfg("apply_commit_merge", "pause").unwrap();
+    for i in [0, 2, 4, 6] {
+        let source = regions[i].clone();
+        let source_peer = source.get_peers()[0].clone();
+        let target = regions[i + 1].clone();
+        merge_region(&cluster, 0, source, source_peer, target, false);
+    }
+
+    // Three merges are waiting for the permit of the parked one.
+    let mut queued = 0;
+    for _ in 0..100 {
+        queued = MERGE_CATCH_UP_QUEUE_GAUGE.get();
+        if queued == 3 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(queued, 3);
+    // No target has grown yet.
+    for i in [0, 2, 4, 6] {
+        let target = cluster.routers[0].region_detail(regions[i + 1].get_id());
+        assert_eq!(target.get_start_key(), regions[i + 1].get_start_key());
+    }
+    // Foreground writes to an unrelated region are not blocked.
+    let spare_id = regions[8].get_id();
+    let mut resp = Default::default();
+    for _ in 0..10 {
+        resp = put(
+            &cluster.routers[0],
+            spare_id,
+            format!("k{}k2", spare_id).as_bytes(),
+        );
+        if !resp.get_header().has_error() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+
+    // Unpark. All merges drain through the queue and complete.
+    fail::remove("apply_commit_merge");
+    for i in [0, 2, 4, 6] {
+        let target_id = regions[i + 1].get_id();
+        let mut merged = false;
+        for _ in 0..100 {
+            let target = cluster.routers[0].region_detail(target_id);
+            if target.get_start_key() == regions[i].get_start_key() {
+                merged = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert!(merged, "merge of region {} not done", regions[i].get_id());
+    }
+    assert_eq!(MERGE_CATCH_UP_QUEUE_GAUGE.get(), 0);
+}
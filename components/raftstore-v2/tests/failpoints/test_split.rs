@@ -7,10 +7,129 @@ use std::{
 
 use engine_traits::{RaftEngineReadOnly, CF_DEFAULT};
 use futures::executor::block_on;
-use raftstore::store::RAFT_INIT_LOG_INDEX;
+use raftstore::store::{region_meta::AdminCmdKind, RAFT_INIT_LOG_INDEX};
 use raftstore_v2::{router::PeerMsg, SimpleWriteEncoder};
+use tikv_util::store::{new_learner_peer, new_peer};
 
-use crate::cluster::{split_helper::split_region, Cluster};
+use crate::{
+    cluster::{split_helper::split_region, Cluster},
+    scenario::{add_learner, batch_split, fp, pre_flushed, prepare_merge, Scenario},
+};
+
+/// A conf change applied while the pre-flush of a batch split runs bumps the
+/// conf version, so the re-proposed split no longer matches its header epoch.
+/// The split keys are still valid though, so the leader must refresh the
+/// header epoch and proceed instead of bouncing an epoch-not-match back to
+/// the client.
+#[test]
+fn test_conf_change_during_pre_flush() {
+    let cluster = Cluster::with_node_count(2, None);
+    let region_id = 2;
+    cluster.routers[0].wait_applied_to_current_term(region_id, Duration::from_secs(3));
+    let old_epoch = cluster.routers[0]
+        .new_request_for(region_id)
+        .take_header()
+        .take_region_epoch();
+    let old_conf_ver = old_epoch.get_conf_ver();
+    let store_id = cluster.node(1).id();
+
+    Scenario::new(&cluster)
+        // Park the split between the pre-flush phase and the re-proposal.
+        .pause(fp::BEFORE_RESEND_BATCH_SPLIT)
+        .submit_admin("split", 0, region_id, batch_split(b"k11", 1000, 1001))
+        // Add a learner while the split is parked to bump the conf version.
+        .submit_admin(
+            "conf-change",
+            0,
+            region_id,
+            add_learner(new_learner_peer(store_id, 10)),
+        )
+        .expect_ok("conf-change")
+        .wait_epoch(0, region_id, Duration::from_secs(3), move |epoch| {
+            epoch.get_conf_ver() > old_conf_ver
+        })
+        .resume(fp::BEFORE_RESEND_BATCH_SPLIT)
+        .expect_ok("split")
+        .run();
+
+    let derived = cluster.routers[0].region_detail(region_id);
+    assert_eq!(derived.get_start_key(), b"k11");
+    let new_region = cluster.routers[0].region_detail(1000);
+    assert_eq!(new_region.get_end_key(), b"k11");
+    // Both halves carry the bumped conf version.
+    assert_eq!(derived.get_region_epoch().get_conf_ver(), old_conf_ver + 1);
+    assert_eq!(
+        new_region.get_region_epoch().get_conf_ver(),
+        old_conf_ver + 1
+    );
+}
+
+/// If another split wins the race while the pre-flush runs, the range of the
+/// parked split is gone, so it must fail with an error that explains the race
+/// rather than being re-proposed with a refreshed epoch.
+#[test]
+fn test_split_race_during_pre_flush() {
+    let cluster = Cluster::default();
+    let region_id = 2;
+    cluster.routers[0].wait_applied_to_current_term(region_id, Duration::from_secs(3));
+    let old_epoch = cluster.routers[0]
+        .new_request_for(region_id)
+        .take_header()
+        .take_region_epoch();
+    let old_version = old_epoch.get_version();
+
+    Scenario::new(&cluster)
+        // Park the first split between the pre-flush phase and the
+        // re-proposal.
+        .pause(fp::BEFORE_RESEND_BATCH_SPLIT)
+        .submit_admin("parked-split", 0, region_id, batch_split(b"k22", 1000, 1001))
+        // A competing split wins the race while the first one is parked. It
+        // skips the (parked) pre-flush phase and is proposed right away.
+        .submit_admin(
+            "winner-split",
+            0,
+            region_id,
+            pre_flushed(batch_split(b"k11", 1002, 1003)),
+        )
+        .expect_ok("winner-split")
+        .wait_epoch(0, region_id, Duration::from_secs(3), move |epoch| {
+            epoch.get_version() > old_version
+        })
+        .resume(fp::BEFORE_RESEND_BATCH_SPLIT)
+        .expect_err("parked-split", "another split or merge won the race")
+        .run();
+}
+
+/// A `PrepareMerge` that conflicts with a proposed but not yet applied split
+/// must be parked on the conflict and bounced with an epoch-not-match once
+/// the split applies, instead of breaking the split.
+#[test]
+fn test_merge_delayed_by_split_in_flight() {
+    let cluster = Cluster::default();
+    let region_id = 2;
+    cluster.routers[0].wait_applied_to_current_term(region_id, Duration::from_secs(3));
+    let target = cluster.routers[0].region_detail(region_id);
+
+    Scenario::new(&cluster)
+        // Park the apply pool so the split stays proposed but not applied.
+        .pause(fp::APPLY_COMMITTED_ENTRIES)
+        .submit_admin(
+            "split",
+            0,
+            region_id,
+            pre_flushed(batch_split(b"k11", 1000, 1001)),
+        )
+        .assert_control(0, region_id, |control| {
+            control.proposed_admin_cmds.contains(&AdminCmdKind::BatchSplit)
+        })
+        // The merge conflicts with the in-flight split and is parked on it;
+        // its target is never inspected before the conflict resolves.
+        .submit_admin("merge", 0, region_id, prepare_merge(target))
+        .resume(fp::APPLY_COMMITTED_ENTRIES)
+        .expect_ok("split")
+        .expect_err("merge", "current epoch of region")
+        .run();
+}
 
 /// If a node is restarted after metadata is persisted before tablet is not
 /// installed, it should resume install the tablet.
@@ -107,3 +226,75 @@ fn test_restart_resume() {
         assert!(!resp.get_header().has_error(), "{:?}", resp);
     }
 }
+
+/// The latency breakdown of a batch split must attribute time to the stage
+/// that actually spent it. Inflate two stages with artificial delays -- the
+/// pre-flush wait and the tablet checkpoint -- and check that the breakdown
+/// charges the delay to them and only them.
+#[test]
+fn test_split_latency_breakdown() {
+    let cluster = Cluster::default();
+    let region_id = 2;
+    let store_id = cluster.node(0).id();
+    let router = &cluster.routers[0];
+    router.wait_applied_to_current_term(region_id, Duration::from_secs(3));
+
+    let delay = Duration::from_secs(1);
+    let _fp1 = fail::FailGuard::new(fp::BEFORE_RESEND_BATCH_SPLIT, "sleep(1000)");
+    let _fp2 = fail::FailGuard::new("before_split_checkpoint", "sleep(1000)");
+    let region = router.region_detail(region_id);
+    let peer = region.get_peers()[0].clone();
+    split_region(
+        router,
+        region,
+        peer,
+        1000,
+        new_peer(store_id, 10),
+        Some(b"k11"),
+        Some(b"k33"),
+        b"k22",
+        b"k22",
+        false,
+    );
+
+    // The init stage completes asynchronously after the response, poll the
+    // debug info until the breakdown is fully assembled.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let breakdown = loop {
+        let meta = router
+            .must_query_debug_info(region_id, Duration::from_secs(1))
+            .unwrap();
+        if let Some(b) = meta.last_split_latency {
+            if b.post_apply_init_micros.is_some() {
+                break b;
+            }
+        }
+        assert!(
+            Instant::now() < deadline,
+            "split latency breakdown not assembled"
+        );
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let delay_micros = delay.as_micros() as u64;
+    // The delayed stages must be charged with their sleep...
+    let pre_flush_wait = breakdown.pre_flush_wait_micros.unwrap();
+    assert!(pre_flush_wait >= delay_micros, "{:?}", breakdown);
+    assert!(breakdown.checkpoint_micros >= delay_micros, "{:?}", breakdown);
+    // ...while the undelayed ones stay well below it.
+    assert!(
+        breakdown.propose_to_commit_micros < delay_micros,
+        "{:?}",
+        breakdown
+    );
+    assert!(
+        breakdown.commit_to_apply_micros < delay_micros,
+        "{:?}",
+        breakdown
+    );
+    assert!(
+        breakdown.post_apply_init_micros.unwrap() < delay_micros,
+        "{:?}",
+        breakdown
+    );
+}
@@ -42,7 +42,7 @@ use raftstore::{
 use raftstore_v2::{
     create_store_batch_system,
     router::{DebugInfoChannel, FlushChannel, PeerMsg, QueryResult, RaftRouter, StoreMsg},
-    Bootstrap, SimpleWriteEncoder, StateStorage, StoreSystem,
+    AdminResultSubscriber, Bootstrap, SimpleWriteEncoder, StateStorage, StoreSystem,
 };
 use resource_control::{ResourceController, ResourceGroupManager};
 use resource_metering::CollectorRegHandle;
@@ -267,6 +267,7 @@ impl RunningState {
         causal_ts_provider: Option<Arc<CausalTsProviderImpl>>,
         logger: &Logger,
         resource_ctl: Arc<ResourceController>,
+        admin_subscribers: &[Arc<dyn AdminResultSubscriber>],
     ) -> (TestRouter, Self) {
         let encryption_cfg = test_util::new_file_security_config(path);
         let key_manager = Some(Arc::new(
@@ -292,6 +293,9 @@ impl RunningState {
             logger.clone(),
             Some(resource_ctl.clone()),
         );
+        for subscriber in admin_subscribers {
+            system.register_admin_result_subscriber(subscriber.clone());
+        }
         let cf_opts = DATA_CFS
             .iter()
             .copied()
@@ -392,6 +396,7 @@ pub struct TestNode {
     running_state: Option<RunningState>,
     logger: Logger,
     resource_manager: Arc<ResourceGroupManager>,
+    admin_subscribers: Vec<Arc<dyn AdminResultSubscriber>>,
 }
 
 impl TestNode {
@@ -404,6 +409,7 @@ impl TestNode {
             running_state: None,
             logger,
             resource_manager: Arc::new(ResourceGroupManager::default()),
+            admin_subscribers: vec![],
         }
     }
 
@@ -426,6 +432,7 @@ impl TestNode {
             None,
             &self.logger,
             resource_ctl,
+            &self.admin_subscribers,
         );
         self.running_state = Some(state);
         router
@@ -566,11 +573,28 @@ impl Cluster {
         Cluster::with_configs(1, config, Some(coprocessor_cfg), |_| {})
     }
 
+    pub fn with_admin_result_subscriber(
+        count: usize,
+        subscriber: Arc<dyn AdminResultSubscriber>,
+    ) -> Cluster {
+        Cluster::with_configs_and_subscribers(count, None, None, |_| {}, vec![subscriber])
+    }
+
     pub fn with_configs(
+        count: usize,
+        config: Option<Config>,
+        cop_cfg: Option<CopConfig>,
+        extra_setting: impl FnMut(&mut Config),
+    ) -> Self {
+        Cluster::with_configs_and_subscribers(count, config, cop_cfg, extra_setting, vec![])
+    }
+
+    pub fn with_configs_and_subscribers(
         count: usize,
         config: Option<Config>,
         cop_cfg: Option<CopConfig>,
         mut extra_setting: impl FnMut(&mut Config),
+        admin_subscribers: Vec<Arc<dyn AdminResultSubscriber>>,
     ) -> Self {
         let pd_server = test_pd::Server::new(1);
         let logger = slog_global::borrow_global().new(o!());
@@ -591,6 +615,7 @@ impl Cluster {
         let cop_cfg = cop_cfg.unwrap_or_default();
         for _ in 1..=count {
             let mut node = TestNode::with_pd(&cluster.pd_server, cluster.logger.clone());
+            node.admin_subscribers = admin_subscribers.clone();
             let (tx, rx) = new_test_transport();
             let router = node.start(
                 Arc::new(VersionTrack::new(cfg.clone())),
@@ -9,13 +9,18 @@
 
 #[allow(dead_code)]
 mod cluster;
+mod test_admin_history;
+mod test_admin_subscriber;
 mod test_basic_write;
+mod test_bcast_commit;
 mod test_conf_change;
 mod test_life;
 mod test_merge;
 mod test_pd_heartbeat;
+mod test_purge_hints;
 mod test_read;
 mod test_split;
 mod test_status;
 mod test_trace_apply;
 mod test_transfer_leader;
+mod test_witness;
@@ -0,0 +1,115 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::time::Duration;
+
+use engine_traits::CF_DEFAULT;
+use kvproto::raft_cmdpb::AdminCmdType;
+use raft::prelude::ConfChangeType;
+use raftstore::store::region_meta::AdminCmdKind;
+use raftstore_v2::{router::PeerMsg, SimpleWriteEncoder};
+use tikv_util::store::new_peer;
+
+use crate::{
+    cluster::{split_helper::split_region, Cluster},
+    test_transfer_leader::must_transfer_leader,
+};
+
+fn write_kv(cluster: &Cluster, region_id: u64, key: &[u8], val: &[u8]) {
+    let header = Box::new(cluster.routers[0].new_request_for(region_id).take_header());
+    let mut put = SimpleWriteEncoder::with_capacity(64);
+    put.put(CF_DEFAULT, key, val);
+    let (msg, _) = PeerMsg::simple_write(header, put.encode());
+    cluster.routers[0].send(region_id, msg).unwrap();
+    std::thread::sleep(Duration::from_millis(1000));
+    cluster.dispatch(region_id, vec![]);
+}
+
+/// Performs a split, a conf change and a transfer leader on one region and
+/// checks that the admin command history returned by the debug query records
+/// them in order with the right epoch transitions.
+#[test]
+fn test_admin_cmd_history() {
+    let mut cluster = Cluster::with_node_count(3, None);
+    let region_id = 2;
+    let store_id = cluster.node(0).id();
+    let router = &mut cluster.routers[0];
+    let region = router.region_detail(region_id);
+    let peer = region.get_peers()[0].clone();
+    router.wait_applied_to_current_term(region_id, Duration::from_secs(3));
+
+    // Region 2 ["", ""] -> Region 2 ["", "k22"], Region 1000 ["k22", ""].
+    split_region(
+        router,
+        region,
+        peer.clone(),
+        1000,
+        new_peer(store_id, 10),
+        Some(b"k11"),
+        Some(b"k33"),
+        b"k22",
+        b"k22",
+        false,
+    );
+
+    // Add a voter on the second store.
+    let router0 = &cluster.routers[0];
+    let mut req = router0.new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::AddNode);
+    let peer1 = new_peer(cluster.node(1).id(), 20);
+    admin_req.mut_change_peer().set_peer(peer1.clone());
+    let resp = router0.admin_command(region_id, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    // Heartbeat will create the peer and trigger a snapshot.
+    cluster.dispatch(region_id, vec![]);
+    std::thread::sleep(Duration::from_millis(100));
+    cluster.dispatch(region_id, vec![]);
+
+    // Make sure the new voter is caught up before transferring leadership.
+    write_kv(&cluster, region_id, b"k01", b"value");
+
+    must_transfer_leader(&cluster, region_id, 0, 1, peer1);
+
+    // Wait for the outstanding apply results to reach the old leader peer.
+    std::thread::sleep(Duration::from_millis(100));
+    let meta = cluster.routers[0]
+        .must_query_debug_info(region_id, Duration::from_secs(3))
+        .unwrap();
+    // Background commands like CompactLog may be interleaved, only look at the
+    // three commands issued above.
+    let hist: Vec<_> = meta
+        .admin_cmd_history
+        .iter()
+        .filter(|e| {
+            matches!(
+                e.cmd_type,
+                AdminCmdKind::BatchSplit | AdminCmdKind::ChangePeer | AdminCmdKind::TransferLeader
+            )
+        })
+        .collect();
+    assert_eq!(hist.len(), 3, "{:?}", meta.admin_cmd_history);
+    let (split, change, transfer) = (hist[0], hist[1], hist[2]);
+    assert_eq!(split.cmd_type, AdminCmdKind::BatchSplit);
+    assert_eq!(change.cmd_type, AdminCmdKind::ChangePeer);
+    assert_eq!(transfer.cmd_type, AdminCmdKind::TransferLeader);
+    assert!(split.index < change.index);
+    assert!(change.index < transfer.index);
+    assert!(split.apply_time_ms > 0);
+    assert!(split.apply_time_ms <= change.apply_time_ms);
+    assert!(change.apply_time_ms <= transfer.apply_time_ms);
+    // The split bumps the version, the conf change bumps the conf ver and the
+    // transfer leader changes neither.
+    assert_eq!(split.epoch_after.version, split.epoch_before.version + 1);
+    assert_eq!(split.epoch_after.conf_ver, split.epoch_before.conf_ver);
+    assert_eq!(change.epoch_before, split.epoch_after);
+    assert_eq!(change.epoch_after.conf_ver, change.epoch_before.conf_ver + 1);
+    assert_eq!(change.epoch_after.version, change.epoch_before.version);
+    assert_eq!(transfer.epoch_before, change.epoch_after);
+    assert_eq!(transfer.epoch_after, transfer.epoch_before);
+    // Split and conf change are initiated by the leader peer.
+    assert_eq!(split.peer_id, peer.get_id());
+    assert_eq!(change.peer_id, peer.get_id());
+}
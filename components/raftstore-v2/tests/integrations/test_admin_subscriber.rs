@@ -0,0 +1,169 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use engine_traits::CF_DEFAULT;
+use kvproto::{metapb::Region, raft_cmdpb::AdminCmdType};
+use raft::prelude::ConfChangeType;
+use raftstore_v2::{router::PeerMsg, AdminResultSubscriber, SimpleWriteEncoder};
+use tikv_util::store::new_peer;
+
+use crate::cluster::{split_helper::split_region, Cluster};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Split {
+        parent: u64,
+        children: Vec<u64>,
+        is_leader: bool,
+    },
+    ConfChange {
+        region: u64,
+        peers: usize,
+    },
+    CompactLog {
+        region: u64,
+        index: u64,
+    },
+}
+
+/// Records every callback so the test can assert on count, arguments and
+/// ordering.
+#[derive(Default)]
+struct RecordingSubscriber {
+    events: Mutex<Vec<Event>>,
+}
+
+impl RecordingSubscriber {
+    fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl AdminResultSubscriber for RecordingSubscriber {
+    fn on_split(&self, parent: &Region, children: &[Region], is_leader: bool) {
+        self.events.lock().unwrap().push(Event::Split {
+            parent: parent.get_id(),
+            children: children.iter().map(|r| r.get_id()).collect(),
+            is_leader,
+        });
+    }
+
+    fn on_conf_change(&self, region: &Region) {
+        self.events.lock().unwrap().push(Event::ConfChange {
+            region: region.get_id(),
+            peers: region.get_peers().len(),
+        });
+    }
+
+    fn on_compact_log(&self, region_id: u64, compact_index: u64) {
+        self.events.lock().unwrap().push(Event::CompactLog {
+            region: region_id,
+            index: compact_index,
+        });
+    }
+}
+
+fn write_kv(cluster: &Cluster, region_id: u64, key: &[u8], val: &[u8]) {
+    let header = Box::new(cluster.routers[0].new_request_for(region_id).take_header());
+    let mut put = SimpleWriteEncoder::with_capacity(64);
+    put.put(CF_DEFAULT, key, val);
+    let (msg, _) = PeerMsg::simple_write(header, put.encode());
+    cluster.routers[0].send(region_id, msg).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    cluster.dispatch(region_id, vec![]);
+}
+
+/// Runs a split, a log compaction and a conf change on one region and checks
+/// that the registered subscriber sees exactly one callback per command, in
+/// apply order, with the final region metadata.
+#[test]
+fn test_admin_result_subscriber() {
+    let subscriber = Arc::new(RecordingSubscriber::default());
+    let mut cluster = Cluster::with_admin_result_subscriber(1, subscriber.clone());
+    let region_id = 2;
+    let store_id = cluster.node(0).id();
+    let router = &mut cluster.routers[0];
+    let region = router.region_detail(region_id);
+    let peer = region.get_peers()[0].clone();
+    router.wait_applied_to_current_term(region_id, Duration::from_secs(3));
+
+    // Region 2 ["", ""] -> Region 2 ["", "k22"], Region 1000 ["k22", ""].
+    split_region(
+        router,
+        region,
+        peer.clone(),
+        1000,
+        new_peer(store_id, 10),
+        Some(b"k11"),
+        Some(b"k33"),
+        b"k22",
+        b"k22",
+        false,
+    );
+
+    // Append a few entries so a forced compaction has something to truncate,
+    // then trigger it. The compaction is proposed and applied asynchronously.
+    write_kv(&cluster, region_id, b"k01", b"v1");
+    write_kv(&cluster, region_id, b"k02", b"v2");
+    write_kv(&cluster, region_id, b"k03", b"v3");
+    cluster.routers[0]
+        .send(region_id, PeerMsg::ForceCompactLog)
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while subscriber.events().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+        cluster.dispatch(region_id, vec![]);
+    }
+
+    // Add a learner on another store. The peer is never created, which is
+    // fine: the conf change still commits and applies on the leader.
+    let router0 = &cluster.routers[0];
+    let mut req = router0.new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::AddLearnerNode);
+    admin_req
+        .mut_change_peer()
+        .set_peer(new_peer(store_id + 100, 20));
+    let resp = router0.admin_command(region_id, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while subscriber.events().len() < 3 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+        cluster.dispatch(region_id, vec![]);
+    }
+
+    let events = subscriber.events();
+    assert_eq!(events.len(), 3, "{:?}", events);
+    assert_eq!(
+        events[0],
+        Event::Split {
+            parent: region_id,
+            children: vec![1000],
+            is_leader: true,
+        },
+        "{:?}",
+        events
+    );
+    let Event::CompactLog { region, index } = &events[1] else {
+        panic!("expect compact log as the second event: {:?}", events);
+    };
+    assert_eq!(*region, region_id);
+    assert!(*index > 0, "{:?}", events);
+    assert_eq!(
+        events[2],
+        Event::ConfChange {
+            region: region_id,
+            peers: 2,
+        },
+        "{:?}",
+        events
+    );
+}
@@ -0,0 +1,119 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::time::Duration;
+
+use futures::executor::block_on;
+use kvproto::raft_cmdpb::AdminCmdType;
+use raft::prelude::{ConfChangeType, MessageType};
+use raftstore::store::metrics::BCAST_COMMIT_TRANSITION_COUNTER_VEC;
+use raftstore_v2::router::PeerMsg;
+use tikv_util::store::new_peer;
+
+use crate::{
+    cluster::Cluster,
+    test_conf_change::{add_learner, write_kv},
+};
+
+/// An uncommitted admin command forces the leader to broadcast commit indexes
+/// immediately; once the command is applied, the default lazy broadcasting is
+/// restored and the transitions show up in the metrics.
+#[test]
+fn test_force_bcast_commit_round_trip() {
+    let cluster = Cluster::with_node_count(2, None);
+    let (region_id, peer_id, offset_id) = (2, 10, 1);
+    cluster.routers[0].wait_applied_to_current_term(region_id, Duration::from_secs(3));
+
+    // Build a two-voter region, so that an admin command cannot commit while
+    // the follower's responses are held back by the test transport.
+    add_learner(&cluster, offset_id, region_id, peer_id);
+    write_kv(&cluster, region_id, b"key", b"value");
+    let store_id = cluster.node(offset_id).id();
+    let mut req = cluster.routers[0].new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::AddNode);
+    admin_req.mut_change_peer().set_peer(new_peer(store_id, peer_id));
+    let resp = cluster.routers[0].admin_command(region_id, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    cluster.dispatch(region_id, vec![]);
+    let meta = cluster.routers[0]
+        .must_query_debug_info(region_id, Duration::from_secs(3))
+        .unwrap();
+    assert!(
+        !meta.proposal_control.as_ref().unwrap().force_bcast_commit,
+        "{:?}",
+        meta.proposal_control,
+    );
+
+    let force_before = BCAST_COMMIT_TRANSITION_COUNTER_VEC
+        .with_label_values(&["force"])
+        .get();
+    let skip_before = BCAST_COMMIT_TRANSITION_COUNTER_VEC
+        .with_label_values(&["skip"])
+        .get();
+
+    // Propose the removal of the other voter without dispatching any message,
+    // so the command stays uncommitted.
+    while cluster.receiver(0).try_recv().is_ok() {}
+    let mut req = cluster.routers[0].new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::RemoveNode);
+    admin_req.mut_change_peer().set_peer(new_peer(store_id, peer_id));
+    let (msg, sub) = PeerMsg::admin_command(req);
+    cluster.routers[0].send(region_id, msg).unwrap();
+
+    // The debug query goes through the same mailbox as the proposal, so by
+    // the time it returns the flag transition has happened.
+    let meta = cluster.routers[0]
+        .must_query_debug_info(region_id, Duration::from_secs(3))
+        .unwrap();
+    let control = meta.proposal_control.as_ref().unwrap();
+    assert!(control.force_bcast_commit, "{:?}", control);
+    assert!(!control.proposed_admin_cmds.is_empty(), "{:?}", control);
+
+    // The entry is broadcast to the follower right away.
+    std::thread::sleep(Duration::from_millis(100));
+    let msgs: Vec<_> = cluster.receiver(0).try_iter().map(Box::new).collect();
+    assert!(
+        msgs.iter()
+            .any(|m| m.get_message().get_msg_type() == MessageType::MsgAppend),
+        "{:?}",
+        msgs,
+    );
+
+    // Let the messages flow; the command commits and applies, after which the
+    // leader goes back to skipping commit broadcasts.
+    cluster.dispatch(region_id, msgs);
+    let resp = block_on(sub.result()).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    cluster.dispatch(region_id, vec![]);
+    let meta = cluster.routers[0]
+        .must_query_debug_info(region_id, Duration::from_secs(3))
+        .unwrap();
+    assert!(
+        !meta.proposal_control.as_ref().unwrap().force_bcast_commit,
+        "{:?}",
+        meta.proposal_control,
+    );
+
+    // The counters are process-wide, so concurrently running tests may also
+    // move them; the round trip above accounts for at least one transition
+    // each way.
+    assert!(
+        BCAST_COMMIT_TRANSITION_COUNTER_VEC
+            .with_label_values(&["force"])
+            .get()
+            >= force_before + 1
+    );
+    assert!(
+        BCAST_COMMIT_TRANSITION_COUNTER_VEC
+            .with_label_values(&["skip"])
+            .get()
+            >= skip_before + 1
+    );
+}
@@ -114,7 +114,7 @@ fn test_remove_by_conf_change() {
     assert_eq!(raft_engine.get_raft_state(region_id).unwrap(), None);
 }
 
-fn add_learner(
+pub fn add_learner(
     cluster: &Cluster,
     offset_id: usize,
     region_id: u64,
@@ -159,7 +159,7 @@ fn add_learner(
     req
 }
 
-fn write_kv(cluster: &Cluster, region_id: u64, key: &[u8], val: &[u8]) {
+pub fn write_kv(cluster: &Cluster, region_id: u64, key: &[u8], val: &[u8]) {
     let header = Box::new(cluster.routers[0].new_request_for(region_id).take_header());
     let mut put = SimpleWriteEncoder::with_capacity(64);
     put.put(CF_DEFAULT, key, val);
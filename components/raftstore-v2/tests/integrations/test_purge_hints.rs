@@ -0,0 +1,111 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use engine_traits::CF_DEFAULT;
+use raftstore::store::metrics::RAFT_LOG_PURGEABLE_BYTES_GAUGE;
+use raftstore_v2::{router::PeerMsg, SimpleWriteEncoder, MAX_PURGE_HINTS_PER_BATCH};
+use tikv_util::store::new_peer;
+
+use crate::cluster::{split_helper::split_region, Cluster};
+
+fn write_kv(cluster: &Cluster, region_id: u64, key: &[u8], val: &[u8]) {
+    let header = Box::new(cluster.routers[0].new_request_for(region_id).take_header());
+    let mut put = SimpleWriteEncoder::with_capacity(64);
+    put.put(CF_DEFAULT, key, val);
+    let (msg, _) = PeerMsg::simple_write(header, put.encode());
+    cluster.routers[0].send(region_id, msg).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    cluster.dispatch(region_id, vec![]);
+}
+
+/// Compacts logs of several regions in a burst and checks that the applies
+/// park their purge hints in the store level aggregator: the backlog is
+/// visible through the purgeable bytes gauge, a single consumption takes all
+/// hints in one batch (the test plays the role of the purge trigger), and
+/// consuming drains the backlog.
+#[test]
+fn test_purge_hints_aggregated_across_regions() {
+    let cluster = Cluster::default();
+    let store_id = cluster.node(0).id();
+    let purge_hints = cluster
+        .node(0)
+        .running_state()
+        .unwrap()
+        .system
+        .purge_hints()
+        .clone();
+    let router = &cluster.routers[0];
+    router.wait_applied_to_current_term(2, Duration::from_secs(3));
+
+    // Region 2 ["", ""] -> Region 2 ["", "k22"], Region 1000 ["k22", ""].
+    let region = router.region_detail(2);
+    let peer = region.get_peers()[0].clone();
+    let (_, right) = split_region(
+        router,
+        region,
+        peer,
+        1000,
+        new_peer(store_id, 10),
+        Some(b"k11"),
+        Some(b"k33"),
+        b"k22",
+        b"k22",
+        false,
+    );
+    // Region 1000 ["k22", ""] -> Region 1000 ["k22", "k55"], Region 1001
+    // ["k55", ""].
+    let peer = right.get_peers()[0].clone();
+    split_region(
+        router,
+        right,
+        peer,
+        1001,
+        new_peer(store_id, 11),
+        Some(b"k33"),
+        Some(b"k66"),
+        b"k55",
+        b"k55",
+        false,
+    );
+
+    // Append a few entries to every region so a forced compaction has
+    // something to truncate, then compact them all in a burst.
+    for (region_id, prefix) in [(2, b"k0"), (1000, b"k3"), (1001, b"k6")] {
+        for i in 0..3u8 {
+            let key = [prefix[0], prefix[1], b'1' + i];
+            write_kv(&cluster, region_id, &key, b"value");
+        }
+        cluster.routers[0]
+            .send(region_id, PeerMsg::ForceCompactLog)
+            .unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while purge_hints.pending_hints() < 3 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+        for region_id in [2, 1000, 1001] {
+            cluster.dispatch(region_id, vec![]);
+        }
+    }
+    assert_eq!(purge_hints.pending_hints(), 3);
+    let backlog = purge_hints.purgeable_bytes();
+    assert!(backlog > 0);
+    assert_eq!(RAFT_LOG_PURGEABLE_BYTES_GAUGE.get() as u64, backlog);
+
+    // One consumption batch covers the whole burst.
+    let batch = purge_hints.consume(MAX_PURGE_HINTS_PER_BATCH);
+    assert_eq!(batch.len(), 3, "{:?}", batch);
+    let regions: HashSet<u64> = batch.iter().map(|(region_id, _)| *region_id).collect();
+    assert_eq!(regions, HashSet::from([2, 1000, 1001]));
+    // The hinted first index is past the initial log index of each region.
+    assert!(batch.iter().all(|(_, first_index)| *first_index > 1));
+
+    // Consuming the hints drains the backlog and the gauge.
+    assert_eq!(purge_hints.pending_hints(), 0);
+    assert_eq!(purge_hints.purgeable_bytes(), 0);
+    assert_eq!(RAFT_LOG_PURGEABLE_BYTES_GAUGE.get(), 0);
+}
@@ -1,13 +1,26 @@
 // Copyright 2022 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use engine_traits::{Peekable, RaftEngineReadOnly, CF_RAFT};
-use raftstore::store::{INIT_EPOCH_VER, RAFT_INIT_LOG_INDEX};
+use futures::executor::block_on;
+use kvproto::{
+    pdpb,
+    raft_cmdpb::AdminCmdType,
+    raft_serverpb::{ExtraMessageType, RaftMessage},
+};
+use raft::prelude::{ConfChangeType, MessageType};
+use raftstore::store::{
+    metrics::PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER, INIT_EPOCH_VER, RAFT_INIT_LOG_INDEX,
+};
+use raftstore_v2::router::{PeerMsg, PeerTick};
 use tikv_util::store::new_peer;
-use txn_types::{Key, TimeStamp};
+use txn_types::{Key, TimeStamp, WriteBatchFlags};
 
-use crate::cluster::{split_helper::split_region, Cluster};
+use crate::cluster::{
+    split_helper::{new_batch_split_region_request, split_region},
+    Cluster,
+};
 
 #[test]
 fn test_split() {
@@ -190,6 +203,191 @@ fn test_split() {
     }
 }
 
+/// A BatchSplit request carrying the `SPLIT_DRY_RUN` flag must return the
+/// derived regions without proposing anything, and the plan must match what a
+/// real split with the same input subsequently produces.
+#[test]
+fn test_batch_split_dry_run() {
+    let mut cluster = Cluster::default();
+    let store_id = cluster.node(0).id();
+    let router = &mut cluster.routers[0];
+
+    let region_2 = 2;
+    let region = router.region_detail(region_2);
+    let peer = region.get_peers()[0].clone();
+    router.wait_applied_to_current_term(region_2, Duration::from_secs(3));
+
+    // Preview a split at k22. The ids are placeholders from the caller's point
+    // of view; nothing is proposed, so the region must stay intact.
+    let mut split_id = pdpb::SplitId::new();
+    split_id.new_region_id = 1000;
+    split_id.new_peer_ids = vec![10];
+    let admin_req = new_batch_split_region_request(vec![b"k22".to_vec()], vec![split_id], false);
+    let mut req = router.new_request_for(region_2);
+    req.set_admin_request(admin_req);
+    let flags = req.get_header().get_flags() | WriteBatchFlags::SPLIT_DRY_RUN.bits();
+    req.mut_header().set_flags(flags);
+    let resp = router.admin_command(region_2, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    let plan = resp.get_admin_response().get_splits().get_regions().to_vec();
+    assert_eq!(plan.len(), 2, "{:?}", plan);
+
+    let unchanged = router.region_detail(region_2);
+    assert_eq!(unchanged.get_region_epoch(), region.get_region_epoch());
+    assert_eq!(unchanged.get_end_key(), region.get_end_key());
+
+    // A real split with the same input must produce exactly the planned
+    // regions.
+    let (left, right) = split_region(
+        router,
+        region,
+        peer,
+        1000,
+        new_peer(store_id, 10),
+        Some(b"k11"),
+        Some(b"k33"),
+        b"k22",
+        b"k22",
+        false,
+    );
+    for (planned, real) in plan.iter().zip([&left, &right]) {
+        assert_eq!(planned.get_id(), real.get_id());
+        assert_eq!(planned.get_start_key(), real.get_start_key());
+        assert_eq!(planned.get_end_key(), real.get_end_key());
+        assert_eq!(planned.get_region_epoch(), real.get_region_epoch());
+        let planned_peers: Vec<_> = planned.get_peers().iter().map(|p| p.get_id()).collect();
+        let real_peers: Vec<_> = real.get_peers().iter().map(|p| p.get_id()).collect();
+        assert_eq!(planned_peers, real_peers, "{:?}", planned);
+    }
+
+    // A dry run with a key outside the (now bounded) region fails validation
+    // and still changes nothing.
+    let mut split_id = pdpb::SplitId::new();
+    split_id.new_region_id = 1001;
+    split_id.new_peer_ids = vec![11];
+    let admin_req = new_batch_split_region_request(vec![b"k33".to_vec()], vec![split_id], false);
+    let mut req = router.new_request_for(region_2);
+    req.set_admin_request(admin_req);
+    let flags = req.get_header().get_flags() | WriteBatchFlags::SPLIT_DRY_RUN.bits();
+    req.mut_header().set_flags(flags);
+    let resp = router.admin_command(region_2, req).unwrap();
+    assert!(
+        resp.get_header().get_error().has_key_not_in_region(),
+        "{:?}",
+        resp
+    );
+    assert_eq!(
+        router.region_detail(region_2).get_region_epoch(),
+        left.get_region_epoch()
+    );
+}
+
+/// A follower that is waiting for a snapshot must not be asked to pre-flush
+/// its memtables before a split: its tablet is about to be replaced wholesale.
+/// The leader should skip it in the flush broadcast and the split must still
+/// complete once the snapshot is delivered.
+#[test]
+fn test_split_skip_pre_flush_for_snapshot_follower() {
+    let cluster = Cluster::with_node_count(2, None);
+    let region_id = 2;
+    let peer_id = 10;
+    let store_id_2 = cluster.node(1).id();
+    let router0 = &cluster.routers[0];
+    router0.wait_applied_to_current_term(region_id, Duration::from_secs(3));
+
+    // Add a voter on store-2. The conf change commits under the old
+    // single-voter quorum, after which the new peer needs a snapshot to
+    // catch up.
+    let mut req = router0.new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::AddNode);
+    admin_req
+        .mut_change_peer()
+        .set_peer(new_peer(store_id_2, peer_id));
+    let resp = router0.admin_command(region_id, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+
+    // Pump messages by hand, withholding the snapshot so the leader keeps
+    // the new voter in `ProgressState::Snapshot`.
+    router0
+        .send(region_id, PeerMsg::Tick(PeerTick::Raft))
+        .unwrap();
+    let mut withheld: Vec<RaftMessage> = vec![];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while withheld.is_empty() {
+        assert!(
+            Instant::now() < deadline,
+            "no snapshot sent to the new voter"
+        );
+        for offset in 0..2 {
+            while let Ok(msg) = cluster.receiver(offset).try_recv() {
+                if msg.get_message().get_msg_type() == MessageType::MsgSnapshot {
+                    withheld.push(msg);
+                } else {
+                    let to = (msg.get_to_peer().get_store_id() == store_id_2) as usize;
+                    let _ = cluster.routers[to].send_raft_message(Box::new(msg));
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // Propose a batch split. The leader notifies followers to pre-flush
+    // their memtables, but must skip the one waiting for a snapshot.
+    let skipped = PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER.get();
+    let mut req = router0.new_request_for(region_id);
+    let mut split_id = pdpb::SplitId::new();
+    split_id.new_region_id = 1000;
+    split_id.new_peer_ids = vec![11, 12];
+    req.set_admin_request(new_batch_split_region_request(
+        vec![b"k50".to_vec()],
+        vec![split_id],
+        false,
+    ));
+    let (msg, sub) = PeerMsg::admin_command(req);
+    router0.send(region_id, msg).unwrap();
+
+    // The broadcast runs once the local pre-flush finishes; wait for the
+    // skip to be recorded.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER.get() == skipped {
+        assert!(
+            Instant::now() < deadline,
+            "snapshot follower not skipped in pre-flush broadcast"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    while let Ok(msg) = cluster.receiver(0).try_recv() {
+        withheld.push(msg);
+    }
+    assert!(
+        withheld
+            .iter()
+            .all(|m| m.get_extra_msg().get_type() != ExtraMessageType::MsgFlushMemtable),
+        "snapshot follower must not be asked to flush memtables"
+    );
+
+    // Deliver everything, snapshot included. The split must still complete.
+    cluster.dispatch(region_id, withheld.into_iter().map(Box::new).collect());
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let meta = router0
+            .must_query_debug_info(region_id, Duration::from_secs(3))
+            .unwrap();
+        if meta.region_state.epoch.version > INIT_EPOCH_VER {
+            break;
+        }
+        assert!(Instant::now() < deadline, "split did not complete");
+        std::thread::sleep(Duration::from_millis(100));
+        cluster.dispatch(region_id, vec![]);
+    }
+    let resp = block_on(sub.result()).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+}
+
 // TODO: test split race with
 // - created peer
 // - created peer with pending snapshot
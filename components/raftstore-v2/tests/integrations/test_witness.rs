@@ -0,0 +1,136 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::time::Duration;
+
+use kvproto::{
+    metapb,
+    raft_cmdpb::{AdminCmdType, RaftCmdRequest},
+};
+use raft::prelude::ConfChangeType;
+use raftstore_v2::router::{PeerMsg, PeerTick};
+use tikv_util::store::new_learner_peer;
+
+use crate::cluster::Cluster;
+
+/// Adds a witness learner on the store at `offset_id` and waits until the
+/// peer is created there.
+fn add_witness_learner(
+    cluster: &Cluster,
+    offset_id: usize,
+    region_id: u64,
+    peer_id: u64,
+) -> metapb::Peer {
+    let store_id = cluster.node(offset_id).id();
+    let mut witness = new_learner_peer(store_id, peer_id);
+    witness.set_is_witness(true);
+    let mut req = cluster.routers[0].new_request_for(region_id);
+    let admin_req = req.mut_admin_request();
+    admin_req.set_cmd_type(AdminCmdType::ChangePeer);
+    admin_req
+        .mut_change_peer()
+        .set_change_type(ConfChangeType::AddLearnerNode);
+    admin_req.mut_change_peer().set_peer(witness.clone());
+    let resp = cluster.routers[0].admin_command(region_id, req).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+
+    // A heartbeat will create the witness peer on the other store.
+    cluster.dispatch(region_id, vec![]);
+    cluster.routers[0]
+        .send(region_id, PeerMsg::Tick(PeerTick::Raft))
+        .unwrap();
+    let meta = cluster.routers[offset_id]
+        .must_query_debug_info(region_id, Duration::from_secs(3))
+        .unwrap();
+    assert_eq!(meta.raft_status.id, peer_id, "{:?}", meta);
+    std::thread::sleep(Duration::from_millis(100));
+    cluster.dispatch(region_id, vec![]);
+    witness
+}
+
+/// Builds an admin request addressed to the witness peer, so the rejection
+/// observed below comes from the witness gate and not from a peer id
+/// mismatch.
+fn admin_req_for_witness(
+    cluster: &Cluster,
+    region_id: u64,
+    witness: &metapb::Peer,
+    cmd_type: AdminCmdType,
+) -> RaftCmdRequest {
+    let mut req = cluster.routers[0].new_request_for(region_id);
+    req.mut_header().set_peer(witness.clone());
+    req.mut_admin_request().set_cmd_type(cmd_type);
+    req
+}
+
+#[test]
+fn test_witness_rejects_admin_commands() {
+    let cluster = Cluster::with_node_count(2, None);
+    let (region_id, peer_id, offset_id) = (2, 10, 1);
+    let witness = add_witness_learner(&cluster, offset_id, region_id, peer_id);
+
+    // Everything that touches the tablet is refused with the dedicated
+    // witness error before any other validation runs.
+    for cmd_type in [
+        AdminCmdType::Split,
+        AdminCmdType::BatchSplit,
+        AdminCmdType::TransferLeader,
+        AdminCmdType::PrepareMerge,
+        AdminCmdType::CommitMerge,
+        AdminCmdType::RollbackMerge,
+        AdminCmdType::PrepareFlashback,
+        AdminCmdType::FinishFlashback,
+    ] {
+        let req = admin_req_for_witness(&cluster, region_id, &witness, cmd_type);
+        let resp = cluster.routers[offset_id]
+            .admin_command(region_id, req)
+            .unwrap();
+        let err = resp.get_header().get_error();
+        assert!(err.has_is_witness(), "{:?}: {:?}", cmd_type, resp);
+        assert!(
+            err.get_message()
+                .contains("witness cannot execute this admin command"),
+            "{:?}: {:?}",
+            cmd_type,
+            resp
+        );
+    }
+
+    // A conf change that does not touch the witness role is refused as well.
+    let mut req = admin_req_for_witness(&cluster, region_id, &witness, AdminCmdType::ChangePeer);
+    let change = req.mut_admin_request().mut_change_peer();
+    change.set_change_type(ConfChangeType::AddLearnerNode);
+    change.set_peer(new_learner_peer(100, 100));
+    let resp = cluster.routers[offset_id]
+        .admin_command(region_id, req)
+        .unwrap();
+    assert!(resp.get_header().get_error().has_is_witness(), "{:?}", resp);
+
+    // The raft-side commands and the conf change switching the peer out of
+    // the witness role pass the gate; proposing on a follower then fails
+    // with not-leader instead of the witness error.
+    let mut non_witness = witness.clone();
+    non_witness.set_is_witness(false);
+    let mut switch_req =
+        admin_req_for_witness(&cluster, region_id, &witness, AdminCmdType::ChangePeer);
+    let change = switch_req.mut_admin_request().mut_change_peer();
+    change.set_change_type(ConfChangeType::AddLearnerNode);
+    change.set_peer(non_witness);
+    for (cmd_type, req) in [
+        (
+            AdminCmdType::CompactLog,
+            admin_req_for_witness(&cluster, region_id, &witness, AdminCmdType::CompactLog),
+        ),
+        (
+            AdminCmdType::UpdateGcPeer,
+            admin_req_for_witness(&cluster, region_id, &witness, AdminCmdType::UpdateGcPeer),
+        ),
+        (AdminCmdType::ChangePeer, switch_req),
+    ] {
+        let resp = cluster.routers[offset_id]
+            .admin_command(region_id, req)
+            .unwrap();
+        let err = resp.get_header().get_error();
+        assert!(!err.has_is_witness(), "{:?}: {:?}", cmd_type, resp);
+        assert!(err.has_not_leader(), "{:?}: {:?}", cmd_type, resp);
+    }
+}
@@ -3,7 +3,11 @@
 use engine_traits::{perf_level_serde, PerfLevel};
 use online_config::{ConfigChange, ConfigManager, OnlineConfig};
 use serde::{Deserialize, Serialize};
-use tikv_util::{box_err, config::ReadableSize, worker::Scheduler};
+use tikv_util::{
+    box_err,
+    config::{ReadableDuration, ReadableSize},
+    worker::Scheduler,
+};
 
 use super::Result;
 use crate::store::SplitCheckTask;
@@ -56,6 +60,17 @@ pub struct Config {
     // The region_bucket_merge_size_ratio * region_bucket_size is threshold to merge with its left
     // neighbor bucket
     pub region_bucket_merge_size_ratio: f64,
+
+    /// Time budget for one apply-snapshot observer hook. The hooks run on
+    /// the region worker thread with no client deadline to inherit, so the
+    /// budget bounds them instead. An observer that overruns it is counted
+    /// and logged but not killed.
+    pub apply_snapshot_hook_budget: ReadableDuration,
+    /// Hard cap on the combined pre-apply-snapshot hook time of one task,
+    /// as a multiple of `apply_snapshot_hook_budget`. Once the cap is
+    /// reached, the remaining observers are skipped (the hooks are
+    /// best-effort) and the apply proceeds.
+    pub apply_snapshot_hook_hard_cap_multiple: u64,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -84,6 +99,12 @@ pub const DEFAULT_BUCKET_SIZE: ReadableSize = ReadableSize::mb(50);
 
 pub const DEFAULT_REGION_BUCKET_MERGE_SIZE_RATIO: f64 = 0.33;
 
+/// Default time budget for one apply-snapshot observer hook.
+pub const DEFAULT_APPLY_SNAPSHOT_HOOK_BUDGET: ReadableDuration = ReadableDuration::millis(500);
+
+/// Default hard cap multiple for the combined pre-apply-snapshot hook time.
+pub const DEFAULT_APPLY_SNAPSHOT_HOOK_HARD_CAP_MULTIPLE: u64 = 4;
+
 impl Default for Config {
     fn default() -> Config {
         Config {
@@ -100,6 +121,8 @@ impl Default for Config {
             region_size_threshold_for_approximate: DEFAULT_BUCKET_SIZE * BATCH_SPLIT_LIMIT / 2 * 3,
             region_bucket_merge_size_ratio: DEFAULT_REGION_BUCKET_MERGE_SIZE_RATIO,
             prefer_approximate_bucket: true,
+            apply_snapshot_hook_budget: DEFAULT_APPLY_SNAPSHOT_HOOK_BUDGET,
+            apply_snapshot_hook_hard_cap_multiple: DEFAULT_APPLY_SNAPSHOT_HOOK_HARD_CAP_MULTIPLE,
         }
     }
 }
@@ -198,6 +221,12 @@ impl Config {
             }
             None => self.region_max_keys = Some(self.region_split_keys() / 2 * 3),
         }
+        if self.apply_snapshot_hook_hard_cap_multiple == 0 {
+            return Err(box_err!(
+                "apply-snapshot-hook-hard-cap-multiple must be at least 1"
+            ));
+        }
+
         let res = self.validate_bucket_size();
         // If it's OK to enable bucket, we will prefer to enable it if useful for
         // raftstore-v2.
@@ -12,9 +12,9 @@ use kvproto::{
 };
 use protobuf::Message;
 use raft::eraftpb;
-use tikv_util::box_try;
+use tikv_util::{box_try, time::Instant, warn};
 
-use super::{split_observer::SplitObserver, *};
+use super::{metrics::*, split_observer::SplitObserver, *};
 use crate::store::BucketRange;
 
 /// A handle for coprocessor to schedule some command back to raftstore.
@@ -672,15 +672,51 @@ impl<E: KvEngine> CoprocessorHost<E> {
         peer_id: u64,
         snap_key: &crate::store::SnapKey,
         snap: Option<&crate::store::Snapshot>,
+        cleanup: Option<&ApplyCleanupInfo>,
     ) {
-        loop_ob!(
-            region,
-            &self.registry.apply_snapshot_observers,
-            pre_apply_snapshot,
-            peer_id,
-            snap_key,
-            snap,
-        );
+        let budget = self.cfg.apply_snapshot_hook_budget.0;
+        let hard_cap = budget * self.cfg.apply_snapshot_hook_hard_cap_multiple as u32;
+        let combined = Instant::now();
+        let mut ctx = ObserverContext::new(region);
+        let observers = &self.registry.apply_snapshot_observers;
+        for (pos, observer) in observers.iter().enumerate() {
+            // The hooks are best-effort; once one task has burnt the hard
+            // cap on them, finishing the apply matters more than the
+            // remaining observers.
+            if combined.saturating_elapsed() >= hard_cap {
+                let skipped = observers.len() - pos;
+                APPLY_SNAPSHOT_HOOK_SKIPPED_COUNTER.inc_by(skipped as u64);
+                warn!(
+                    "skip remaining pre-apply-snapshot observers, combined hook time exceeds \
+                    the hard cap";
+                    "region_id" => region.get_id(),
+                    "peer_id" => peer_id,
+                    "elapsed" => ?combined.saturating_elapsed(),
+                    "hard_cap" => ?hard_cap,
+                    "skipped" => skipped,
+                );
+                break;
+            }
+            let checker = DeadlineChecker::new(budget);
+            ctx.set_deadline(Some(checker.clone()));
+            let observer = observer.observer.inner();
+            observer.pre_apply_snapshot(&mut ctx, peer_id, snap_key, snap, cleanup);
+            if checker.exceeded() {
+                APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER
+                    .with_label_values(&["pre_apply"])
+                    .inc();
+                warn!(
+                    "pre-apply-snapshot observer ran past its time budget";
+                    "region_id" => region.get_id(),
+                    "peer_id" => peer_id,
+                    "elapsed" => ?checker.elapsed(),
+                    "budget" => ?budget,
+                );
+            }
+            if ctx.bypass {
+                break;
+            }
+        }
     }
 
     pub fn pre_transfer_leader(&self, r: &Region, tr: &TransferLeaderRequest) -> Result<()> {
@@ -693,11 +729,27 @@ impl<E: KvEngine> CoprocessorHost<E> {
         peer_id: u64,
         snap_key: &crate::store::SnapKey,
         snap: Option<&crate::store::Snapshot>,
+        cleanup: Option<&ApplyCleanupInfo>,
     ) {
+        let budget = self.cfg.apply_snapshot_hook_budget.0;
         let mut ctx = ObserverContext::new(region);
         for observer in &self.registry.apply_snapshot_observers {
+            let checker = DeadlineChecker::new(budget);
+            ctx.set_deadline(Some(checker.clone()));
             let observer = observer.observer.inner();
-            observer.post_apply_snapshot(&mut ctx, peer_id, snap_key, snap);
+            observer.post_apply_snapshot(&mut ctx, peer_id, snap_key, snap, cleanup);
+            if checker.exceeded() {
+                APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER
+                    .with_label_values(&["post_apply"])
+                    .inc();
+                warn!(
+                    "post-apply-snapshot observer ran past its time budget";
+                    "region_id" => region.get_id(),
+                    "peer_id" => peer_id,
+                    "elapsed" => ?checker.elapsed(),
+                    "budget" => ?budget,
+                );
+            }
         }
     }
 
@@ -1144,6 +1196,7 @@ mod tests {
             _: u64,
             _: &SnapKey,
             _: Option<&Snapshot>,
+            _: Option<&ApplyCleanupInfo>,
         ) {
             self.called
                 .fetch_add(ObserverIndex::PreApplySnapshot as usize, Ordering::SeqCst);
@@ -1156,6 +1209,7 @@ mod tests {
             _: u64,
             _: &crate::store::SnapKey,
             _: Option<&Snapshot>,
+            _: Option<&ApplyCleanupInfo>,
         ) {
             self.called
                 .fetch_add(ObserverIndex::PostApplySnapshot as usize, Ordering::SeqCst);
@@ -1341,11 +1395,11 @@ mod tests {
         assert_all!([&ob.called], &[index]);
 
         let key = SnapKey::new(region.get_id(), 1, 1);
-        host.pre_apply_snapshot(&region, 0, &key, None);
+        host.pre_apply_snapshot(&region, 0, &key, None, None);
         index += ObserverIndex::PreApplySnapshot as usize;
         assert_all!([&ob.called], &[index]);
 
-        host.post_apply_snapshot(&region, 0, &key, None);
+        host.post_apply_snapshot(&region, 0, &key, None, None);
         index += ObserverIndex::PostApplySnapshot as usize;
         assert_all!([&ob.called], &[index]);
 
@@ -2,7 +2,8 @@
 
 use lazy_static::lazy_static;
 use prometheus::{
-    exponential_buckets, register_histogram, register_int_gauge_vec, Histogram, IntGaugeVec,
+    exponential_buckets, register_histogram, register_int_counter, register_int_counter_vec,
+    register_int_gauge_vec, Histogram, IntCounter, IntCounterVec, IntGaugeVec,
 };
 
 lazy_static! {
@@ -24,4 +25,18 @@ lazy_static! {
         "Number of regions collected in region_collector",
         &["type"]
     ).unwrap();
+
+    pub static ref APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER: IntCounterVec =
+    register_int_counter_vec!(
+        "tikv_raftstore_apply_snapshot_hook_overage_total",
+        "Total number of apply-snapshot observer hooks that ran past their time budget.",
+        &["hook"]
+    ).unwrap();
+
+    pub static ref APPLY_SNAPSHOT_HOOK_SKIPPED_COUNTER: IntCounter =
+    register_int_counter!(
+        "tikv_raftstore_apply_snapshot_hook_skipped_total",
+        "Total number of apply-snapshot observers skipped because the combined pre-apply \
+        hook time of a task exceeded the hard cap."
+    ).unwrap();
 }
@@ -6,6 +6,7 @@ use std::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
+    time::Duration,
     vec::IntoIter,
 };
 
@@ -21,12 +22,13 @@ use kvproto::{
 };
 use pd_client::RegionStat;
 use raft::{eraftpb, StateRole};
+use tikv_util::time::Instant;
 
 pub mod config;
 mod consistency_check;
 pub mod dispatcher;
 mod error;
-mod metrics;
+pub mod metrics;
 pub mod region_info_accessor;
 mod split_check;
 pub mod split_observer;
@@ -61,11 +63,50 @@ pub trait Coprocessor: Send {
     fn stop(&self) {}
 }
 
+/// A lightweight cancellation token for hooks that run on shared worker
+/// threads. The dispatcher arms it with the time budget of the hook; a
+/// long-running observer polls [`DeadlineChecker::exceeded`] at convenient
+/// points and winds down early. The deadline is advisory: nothing kills a
+/// hook that overruns it, but the dispatcher counts and logs the overage.
+#[derive(Clone, Debug)]
+pub struct DeadlineChecker {
+    start: Instant,
+    budget: Duration,
+}
+
+impl DeadlineChecker {
+    pub fn new(budget: Duration) -> DeadlineChecker {
+        DeadlineChecker {
+            start: Instant::now(),
+            budget,
+        }
+    }
+
+    /// Whether the budget is used up.
+    pub fn exceeded(&self) -> bool {
+        self.start.saturating_elapsed() > self.budget
+    }
+
+    /// The time left before the deadline, zero once it is exceeded.
+    pub fn remaining(&self) -> Duration {
+        self.budget.saturating_sub(self.start.saturating_elapsed())
+    }
+
+    /// The time spent since the checker was armed.
+    pub fn elapsed(&self) -> Duration {
+        self.start.saturating_elapsed()
+    }
+}
+
 /// Context of observer.
 pub struct ObserverContext<'a> {
     region: &'a Region,
     /// Whether to bypass following observer hook.
     pub bypass: bool,
+    /// The deadline of the current hook, armed by the dispatcher for hooks
+    /// with a time budget. Only the apply-snapshot hook family sets it for
+    /// now.
+    deadline: Option<DeadlineChecker>,
 }
 
 impl<'a> ObserverContext<'a> {
@@ -73,12 +114,22 @@ impl<'a> ObserverContext<'a> {
         ObserverContext {
             region,
             bypass: false,
+            deadline: None,
         }
     }
 
     pub fn region(&self) -> &Region {
         self.region
     }
+
+    /// The deadline of the current hook, if the dispatcher armed one.
+    pub fn deadline(&self) -> Option<&DeadlineChecker> {
+        self.deadline.as_ref()
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Option<DeadlineChecker>) {
+        self.deadline = deadline;
+    }
 }
 
 /// Context of a region provided for observers.
@@ -184,6 +235,28 @@ pub trait QueryObserver: Coprocessor {
     }
 }
 
+/// The destructive cleanup the region worker runs before ingesting a
+/// snapshot, handed to `ApplySnapshotObserver::pre_apply_snapshot` and
+/// `post_apply_snapshot`. Observers that track their own progress per key
+/// range (e.g. backup-stream) can reconcile it with the range that was
+/// actually cleaned, which can be wider than the region when overlapping
+/// pending delete ranges were drained into the cleanup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApplyCleanupInfo {
+    /// The start of the cleaned range. It covers at least the region's data
+    /// range.
+    pub cleaned_start: Vec<u8>,
+    /// The end of the cleaned range.
+    pub cleaned_end: Vec<u8>,
+    /// The pending delete ranges drained into the cleanup, as `(region_id,
+    /// start_key, end_key)` of the destroyed peers that registered them.
+    pub drained_ranges: Vec<(u64, Vec<u8>, Vec<u8>)>,
+    /// Labels of the deletion strategies that were used, e.g. `delete_files`
+    /// or `delete_by_writer`. Empty in the pre-apply preview, where the
+    /// strategies are not decided yet.
+    pub strategies_used: Vec<String>,
+}
+
 pub trait ApplySnapshotObserver: Coprocessor {
     /// Hook to call after applying key from plain file.
     /// This may be invoked multiple times for each plain file, and each time a
@@ -198,23 +271,31 @@ pub trait ApplySnapshotObserver: Coprocessor {
     /// Should pass valid snapshot, the option is only for testing.
     /// Notice that we can call `pre_apply_snapshot` to multiple snapshots at
     /// the same time.
+    /// `cleanup` is a best-effort preview of the cleanup the apply will run,
+    /// based on the pending delete ranges registered when the task is
+    /// queued; the cleanup that actually executed is delivered in
+    /// `post_apply_snapshot`.
     fn pre_apply_snapshot(
         &self,
         _: &mut ObserverContext<'_>,
         _peer_id: u64,
         _: &crate::store::SnapKey,
         _: Option<&crate::store::Snapshot>,
+        _cleanup: Option<&ApplyCleanupInfo>,
     ) {
     }
 
     /// Hook when the whole snapshot is applied.
     /// Should pass valid snapshot, the option is only for testing.
+    /// `cleanup` describes the cleanup the apply ran; `None` when a resumed
+    /// apply attempt skipped it because a previous attempt already ran it.
     fn post_apply_snapshot(
         &self,
         _: &mut ObserverContext<'_>,
         _: u64,
         _: &crate::store::SnapKey,
         _snapshot: Option<&crate::store::Snapshot>,
+        _cleanup: Option<&ApplyCleanupInfo>,
     ) {
     }
 
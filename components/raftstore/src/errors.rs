@@ -4,7 +4,7 @@ use std::{error::Error as StdError, io, net, result};
 
 use crossbeam::channel::TrySendError;
 use error_code::{self, ErrorCode, ErrorCodeExt};
-use kvproto::{errorpb, metapb, raft_serverpb};
+use kvproto::{errorpb, metapb, raft_cmdpb::AdminCmdType, raft_serverpb};
 use protobuf::ProtobufError;
 use thiserror::Error;
 use tikv_util::{
@@ -147,11 +147,20 @@ pub enum Error {
     #[error("peer is a witness of region {0}")]
     IsWitness(u64),
 
+    #[error("witness cannot execute this admin command {1:?}, region {0}")]
+    WitnessAdminCmd(u64, AdminCmdType),
+
     #[error("mismatch peer id {} != {}", .request_peer_id, .store_peer_id)]
     MismatchPeerId {
         request_peer_id: u64,
         store_peer_id: u64,
     },
+
+    #[error("in-flight pre-transfer-leader operations reached the cap {0}")]
+    PreTransferLeaderThrottled(usize),
+
+    #[error("server is busy, reason: {0}")]
+    ServerIsBusy(String),
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -231,6 +240,16 @@ impl From<Error> for errorpb::Error {
                 server_is_busy_err.set_reason(RAFTSTORE_IS_BUSY.to_owned());
                 errorpb.set_server_is_busy(server_is_busy_err);
             }
+            Error::PreTransferLeaderThrottled(_) => {
+                let mut server_is_busy_err = errorpb::ServerIsBusy::default();
+                server_is_busy_err.set_reason(RAFTSTORE_IS_BUSY.to_owned());
+                errorpb.set_server_is_busy(server_is_busy_err);
+            }
+            Error::ServerIsBusy(reason) => {
+                let mut server_is_busy_err = errorpb::ServerIsBusy::default();
+                server_is_busy_err.set_reason(reason);
+                errorpb.set_server_is_busy(server_is_busy_err);
+            }
             Error::Engine(engine_traits::Error::NotInRange {
                 key,
                 region_id,
@@ -281,6 +300,13 @@ impl From<Error> for errorpb::Error {
                 e.set_region_id(region_id);
                 errorpb.set_is_witness(e);
             }
+            // Clients back off the same way as for `IsWitness`; the message
+            // carries the rejected command type.
+            Error::WitnessAdminCmd(region_id, _) => {
+                let mut e = errorpb::IsWitness::default();
+                e.set_region_id(region_id);
+                errorpb.set_is_witness(e);
+            }
             Error::MismatchPeerId {
                 request_peer_id,
                 store_peer_id,
@@ -359,7 +385,10 @@ impl ErrorCodeExt for Error {
             Error::DeadlineExceeded => error_code::raftstore::DEADLINE_EXCEEDED,
             Error::PendingPrepareMerge => error_code::raftstore::PENDING_PREPARE_MERGE,
             Error::IsWitness(..) => error_code::raftstore::IS_WITNESS,
+            Error::WitnessAdminCmd(..) => error_code::raftstore::IS_WITNESS,
             Error::MismatchPeerId { .. } => error_code::raftstore::MISMATCH_PEER_ID,
+            Error::PreTransferLeaderThrottled(..) => error_code::raftstore::SERVER_IS_BUSY,
+            Error::ServerIsBusy(..) => error_code::raftstore::SERVER_IS_BUSY,
 
             Error::Other(_) | Error::RegionNotRegistered { .. } => error_code::raftstore::UNKNOWN,
         }
@@ -4,9 +4,11 @@ use std::{cmp::min, collections::HashMap, sync::Arc, time::Duration, u64};
 
 use batch_system::Config as BatchSystemConfig;
 use engine_traits::{perf_level_serde, PerfLevel};
+use kvproto::raft_cmdpb::AdminCmdType;
 use lazy_static::lazy_static;
 use online_config::{ConfigChange, ConfigManager, ConfigValue, OnlineConfig};
 use prometheus::register_gauge_vec;
+use protobuf::ProtobufEnum;
 use serde::{Deserialize, Serialize};
 use serde_with::with_prefix;
 use tikv_util::{
@@ -40,6 +42,15 @@ pub const DEFAULT_SNAP_MAX_BYTES_PER_SEC: u64 = 100 * 1024 * 1024;
 const DEFAULT_SNAP_WAIT_SPLIT_DURATION: ReadableDuration =
     ReadableDuration::secs(RAFTSTORE_V2_SPLIT_SIZE.0 / DEFAULT_SNAP_MAX_BYTES_PER_SEC / 3);
 
+/// Normalizes an admin command name from the configuration so that e.g.
+/// `batch-split`, `batch_split` and `BatchSplit` all name the same command.
+fn normalize_admin_cmd_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
 with_prefix!(prefix_apply "apply-");
 with_prefix!(prefix_store "store-");
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, OnlineConfig)]
@@ -188,9 +199,16 @@ pub struct Config {
     #[online_config(hidden)]
     pub leader_transfer_max_log_lag: u64,
 
-    #[online_config(skip)]
+    /// Write batch size used when replaying plain cf files of a snapshot.
+    /// Refreshing it online takes effect for newly started applies.
     pub snap_apply_batch_size: ReadableSize,
 
+    /// Write batch size used when replaying the lock cf file of a snapshot.
+    /// The lock cf is applied key by key through write batches rather than
+    /// ingested, so a larger batch usually helps. Defaults to
+    /// `snap_apply_batch_size`.
+    pub snap_apply_batch_size_lock: Option<ReadableSize>,
+
     /// When applying a Region snapshot, its SST files can be modified by TiKV
     /// itself. However those files could be read-only, for example, a TiKV
     /// [agent](cmd/tikv-agent) is started based on a read-only remains. So
@@ -199,6 +217,56 @@ pub struct Config {
     #[online_config(skip)]
     pub snap_apply_copy_symlink: bool,
 
+    /// The maximum number of SST ingestions for different column families
+    /// that are allowed to run concurrently when applying a Region snapshot.
+    /// The default value 1 keeps the ingestions serial.
+    #[online_config(skip)]
+    pub snap_apply_ingest_concurrency: usize,
+
+    /// Verify the checksum of each snapshot cf file on a dedicated thread,
+    /// overlapped with the ingestion of the preceding cf file when applying a
+    /// Region snapshot. Disable to force the fully serial verify-then-ingest
+    /// order for debugging.
+    #[online_config(skip)]
+    pub snap_apply_pipelined_verification: bool,
+
+    /// The maximum number of snapshot-applied notifications that the region
+    /// worker coalesces into one message to the store. When a write stall
+    /// clears, hundreds of applies can finish in a short window and flood the
+    /// router with one message per region, delaying raft message processing.
+    /// Set to 0 to disable batching and send one message per finished apply.
+    #[online_config(skip)]
+    pub snap_applied_notify_batch: usize,
+
+    /// The wall-time budget of one pending-apply pass of the region worker.
+    /// Once the budget is exceeded the remaining queued applies are left for
+    /// the next pass, so destroy and stale-cleanup tasks sharing the worker
+    /// thread are not starved behind a long apply backlog on a slow disk.
+    /// The first apply of a pass never waits for the budget. Set to 0 to
+    /// disable the budget.
+    #[online_config(skip)]
+    pub snap_apply_time_budget: ReadableDuration,
+
+    /// The pending-apply backlog a snapshot receiver may report above which
+    /// snapshot generation targeting that store is delayed, so that stores
+    /// that are ready to absorb snapshots are served first. Set to 0 to
+    /// disable the backpressure.
+    pub snap_receiver_backlog_threshold: ReadableSize,
+
+    /// Let urgent snapshot applies (unsafe recovery, regions without a
+    /// serving leader) proceed even when the engine vetoes ingestions, e.g.
+    /// because of accumulated level-0 files. Restoring quorum earlier is
+    /// then considered worth the write stall the ingestion may cause.
+    pub snap_apply_urgent_bypass_engine_check: bool,
+
+    /// The high watermark on the disk space taken by the snapshot directory.
+    /// When the usage exceeds it, queued snapshot applies that have not
+    /// started yet are cancelled largest-first and their snapshot files are
+    /// deleted to reclaim the space; the affected peers re-request their
+    /// snapshots from the leaders later. Applies that already started are
+    /// never cancelled. Set to 0 to disable the reclamation.
+    pub snap_apply_pressure_high_watermark: ReadableSize,
+
     // used to periodically check whether schedule pending applies in region runner
     #[doc(hidden)]
     #[online_config(skip)]
@@ -253,10 +321,29 @@ pub struct Config {
     pub merge_max_log_gap: u64,
     /// Interval to re-propose merge.
     pub merge_check_tick_interval: ReadableDuration,
+    /// The maximum number of catch-up-logs operations triggered by
+    /// `CommitMerge` that are allowed to run concurrently on a store. Excess
+    /// merges wait in a FIFO queue with their `CommitMerge` proposal deferred.
+    /// Set to 0 to disable the limit. Only effective for partitioned-raft-kv.
+    pub max_concurrent_merge_catch_up: usize,
 
     #[online_config(hidden)]
     pub use_delete_range: bool,
 
+    /// Directory to stage the temporary SSTs built by range cleanups when
+    /// `use-delete-range` is disabled, e.g. on a separate scratch disk so big
+    /// cleanups don't compete for space with the data disk. An empty value
+    /// stages them in the snapshot directory.
+    #[online_config(skip)]
+    pub cleanup_ingest_temp_dir: String,
+
+    /// Minimum available space of the data disk required to delete ranges by
+    /// building and ingesting delete SSTs. Below this watermark range
+    /// cleanups fall back to deleting by key, which needs no scratch space.
+    /// Set to 0 to disable the check.
+    #[online_config(skip)]
+    pub cleanup_ingest_min_free_space: ReadableSize,
+
     pub snap_generator_pool_size: usize,
 
     pub cleanup_import_sst_interval: ReadableDuration,
@@ -366,6 +453,11 @@ pub struct Config {
     pub slow_trend_unsensitive_result: f64,
     // The sensitiveness of slowness on network-io.
     pub slow_trend_network_io_factor: f64,
+    /// Weight of the snapshot apply backlog signals collected by the region
+    /// worker in the slow score. Zero (the default) keeps the signals
+    /// report-only: they are exported as metrics but do not affect the score.
+    #[doc(hidden)]
+    pub apply_backlog_slow_score_weight: f64,
 
     /// Interval to check whether to reactivate in-memory pessimistic lock after
     /// being disabled before transferring leader.
@@ -386,6 +478,30 @@ pub struct Config {
     /// Set it to 0 to disable warmup.
     pub max_entry_cache_warmup_duration: ReadableDuration,
 
+    /// Max number of in-flight pre-transfer-leader operations on the store.
+    /// Transfer leader requests beyond the cap are rejected with a busy error
+    /// so that PD retries them later instead of queueing more warmups.
+    /// Set it to 0 to disable the limit.
+    pub max_inflight_pre_transfer_leader: usize,
+
+    /// Admin commands refused by the store resource gate before they are
+    /// proposed, while the store disk is critically full or the peer lags
+    /// far behind on applying raft logs. Only commands that temporarily
+    /// enlarge disk usage belong here; commands that free resources (log
+    /// compaction, merge rollback and conf changes that only remove peers)
+    /// always pass the gate. Names match `AdminCmdType` variants,
+    /// case-insensitive, `-` and `_` are ignored.
+    /// Only takes effect with the partitioned-raft-kv engine.
+    #[online_config(skip)]
+    pub admin_resource_gate_cmds: Vec<String>,
+    /// Makes the resource gate refuse the commands above already when the
+    /// disk is almost full instead of only when it is already full.
+    pub admin_resource_gate_on_almost_full: bool,
+    /// The resource gate refuses the commands above when the applied index
+    /// lags behind the committed index by at least this many raft log
+    /// entries. Set it to 0 to disable the apply lag check.
+    pub admin_resource_gate_max_apply_lag: u64,
+
     #[doc(hidden)]
     pub max_snapshot_file_raw_size: ReadableSize,
 
@@ -422,6 +538,13 @@ pub struct Config {
     /// `default` column family
     #[doc(hidden)]
     pub skip_manual_compaction_in_clean_up_worker: bool,
+
+    /// Whether to compact the SST files straddling the new region boundaries
+    /// right after a split is applied, so that the compaction guard cuts them
+    /// along the new boundaries promptly instead of waiting for background
+    /// compactions to pick the files up. Only the files overlapping a new
+    /// boundary key are compacted.
+    pub compact_split_boundaries: bool,
 }
 
 impl Default for Config {
@@ -479,7 +602,15 @@ impl Default for Config {
             peer_stale_state_check_interval: ReadableDuration::minutes(5),
             leader_transfer_max_log_lag: 128,
             snap_apply_batch_size: ReadableSize::mb(10),
+            snap_apply_batch_size_lock: None,
             snap_apply_copy_symlink: false,
+            snap_apply_ingest_concurrency: 1,
+            snap_apply_pipelined_verification: true,
+            snap_applied_notify_batch: 128,
+            snap_apply_time_budget: ReadableDuration::secs(2),
+            snap_receiver_backlog_threshold: ReadableSize::gb(1),
+            snap_apply_urgent_bypass_engine_check: false,
+            snap_apply_pressure_high_watermark: ReadableSize(0),
             region_worker_tick_interval: if cfg!(feature = "test") {
                 ReadableDuration::millis(200)
             } else {
@@ -498,7 +629,10 @@ impl Default for Config {
             allow_remove_leader: false,
             merge_max_log_gap: 10,
             merge_check_tick_interval: ReadableDuration::secs(2),
+            max_concurrent_merge_catch_up: 2,
             use_delete_range: false,
+            cleanup_ingest_temp_dir: String::new(),
+            cleanup_ingest_min_free_space: ReadableSize::gb(1),
             snap_generator_pool_size: 2,
             cleanup_import_sst_interval: ReadableDuration::minutes(10),
             local_read_batch_size: 1024,
@@ -531,6 +665,10 @@ impl Default for Config {
             // this config :)
             long_uncommitted_base_threshold: ReadableDuration::secs(20),
             max_entry_cache_warmup_duration: ReadableDuration::secs(1),
+            max_inflight_pre_transfer_leader: 0,
+            admin_resource_gate_cmds: vec!["batch-split".to_owned(), "commit-merge".to_owned()],
+            admin_resource_gate_on_almost_full: true,
+            admin_resource_gate_max_apply_lag: 10000,
 
             // They are preserved for compatibility check.
             region_max_size: ReadableSize(0),
@@ -547,6 +685,7 @@ impl Default for Config {
             slow_trend_unsensitive_cause: 10.0,
             slow_trend_unsensitive_result: 0.5,
             slow_trend_network_io_factor: 0.0,
+            apply_backlog_slow_score_weight: 0.0,
             check_leader_lease_interval: ReadableDuration::secs(0),
             renew_leader_lease_advance_duration: ReadableDuration::secs(0),
             allow_unsafe_vote_after_start: false,
@@ -562,6 +701,7 @@ impl Default for Config {
             unsafe_disable_check_quorum: false,
             min_pending_apply_region_count: 10,
             skip_manual_compaction_in_clean_up_worker: false,
+            compact_split_boundaries: false,
         }
     }
 }
@@ -634,6 +774,20 @@ impl Config {
         self.max_entry_cache_warmup_duration.0 != Duration::from_secs(0)
     }
 
+    /// Whether `cmd_type` is in the set of admin commands covered by the
+    /// store resource gate, see `admin_resource_gate_cmds`.
+    pub fn admin_resource_gated(&self, cmd_type: AdminCmdType) -> bool {
+        let name = normalize_admin_cmd_name(&format!("{:?}", cmd_type));
+        self.admin_resource_gate_cmds
+            .iter()
+            .any(|c| normalize_admin_cmd_name(c) == name)
+    }
+
+    pub fn snap_apply_batch_size_lock(&self) -> ReadableSize {
+        self.snap_apply_batch_size_lock
+            .unwrap_or(self.snap_apply_batch_size)
+    }
+
     pub fn region_split_check_diff(&self) -> ReadableSize {
         self.region_split_check_diff.unwrap()
     }
@@ -787,6 +941,19 @@ impl Config {
             ));
         }
 
+        for name in &self.admin_resource_gate_cmds {
+            let norm = normalize_admin_cmd_name(name);
+            if !AdminCmdType::values()
+                .iter()
+                .any(|t| normalize_admin_cmd_name(&format!("{:?}", t)) == norm)
+            {
+                return Err(box_err!(
+                    "raftstore.admin-resource-gate-cmds contains unknown admin command {:?}",
+                    name
+                ));
+            }
+        }
+
         let abnormal_leader_missing = self.abnormal_leader_missing_duration.as_millis();
         if abnormal_leader_missing < stale_state_check {
             return Err(box_err!(
@@ -897,6 +1064,12 @@ impl Config {
             ));
         }
 
+        if self.snap_apply_ingest_concurrency == 0 {
+            return Err(box_err!(
+                "snap-apply-ingest-concurrency should be greater than 0."
+            ));
+        }
+
         if self.check_leader_lease_interval.as_millis() == 0 {
             self.check_leader_lease_interval = self.raft_store_max_leader_lease / 4;
         }
@@ -1117,6 +1290,9 @@ impl Config {
         CONFIG_RAFTSTORE_GAUGE
             .with_label_values(&["snap_apply_batch_size"])
             .set(self.snap_apply_batch_size.0 as f64);
+        CONFIG_RAFTSTORE_GAUGE
+            .with_label_values(&["snap_apply_batch_size_lock"])
+            .set(self.snap_apply_batch_size_lock().0 as f64);
 
         CONFIG_RAFTSTORE_GAUGE
             .with_label_values(&["consistency_check_interval_seconds"])
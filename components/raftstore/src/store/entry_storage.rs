@@ -579,6 +579,7 @@ pub fn init_applied_term<ER: RaftEngine>(
 #[derive(Clone, Debug)]
 pub struct CacheWarmupState {
     range: (u64, u64),
+    is_finished: bool,
     is_task_timeout: bool,
     is_stale: bool,
     started_at: Instant,
@@ -592,6 +593,7 @@ impl CacheWarmupState {
     pub fn new_with_range(low: u64, high: u64) -> Self {
         CacheWarmupState {
             range: (low, high),
+            is_finished: false,
             is_task_timeout: false,
             is_stale: false,
             started_at: Instant::now(),
@@ -607,6 +609,16 @@ impl CacheWarmupState {
         self.started_at.saturating_elapsed()
     }
 
+    /// Whether the entries of the warmup range have been loaded into the
+    /// cache.
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    pub fn mark_finished(&mut self) {
+        self.is_finished = true;
+    }
+
     /// Whether the warmup task is already timeout.
     pub fn is_task_timeout(&self) -> bool {
         self.is_task_timeout
@@ -624,6 +636,11 @@ impl CacheWarmupState {
         self.is_task_timeout
     }
 
+    /// Whether this state has been marked stale.
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+
     /// Check whether this state is stale.
     pub fn check_stale(&mut self, duration: Duration) -> bool {
         fail_point!("entry_cache_warmed_up_state_is_stale", |_| true);
@@ -1208,6 +1225,9 @@ impl<EK: KvEngine, ER: RaftEngine> EntryStorage<EK, ER> {
                         }
                         entries.truncate((range.1 - range.0) as usize);
                         self.cache.prepend(entries);
+                        if let Some(state) = self.cache_warmup_state.as_mut() {
+                            state.mark_finished();
+                        }
                         WARM_UP_ENTRY_CACHE_COUNTER.finished.inc();
                         fail_point!("on_entry_cache_warmed_up");
                         return !is_task_timeout;
@@ -1894,6 +1914,13 @@ pub mod tests {
         store.maybe_warm_up_entry_cache(res);
         // Cache should be warmed up.
         assert_eq!(store.entry_cache_first_index().unwrap(), 5);
+        assert!(
+            store
+                .entry_cache_warmup_state()
+                .as_ref()
+                .unwrap()
+                .is_finished()
+        );
     }
 
     #[test]
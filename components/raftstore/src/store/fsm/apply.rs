@@ -559,6 +559,12 @@ where
     }
 
     fn commit_opt(&mut self, delegate: &mut ApplyDelegate<EK>, persistent: bool) {
+        // Record the apply index the buffered writes of the delegate's range
+        // correspond to, so the range cache engine can maintain its per-range
+        // freshness watermark.
+        self.kv_wb
+            .set_range_applied_index(delegate.apply_state.get_applied_index());
+        self.kv_wb.set_range_applied_term(delegate.applied_term);
         delegate.update_metrics(self);
         if persistent {
             if let (_, Some(seqno)) = self.write_to_db() {
@@ -19,7 +19,8 @@ use std::{
 use batch_system::{BasicMailbox, Fsm};
 use collections::{HashMap, HashSet};
 use engine_traits::{
-    Engines, KvEngine, RaftEngine, RaftLogBatch, SstMetaInfo, WriteBatchExt, CF_LOCK, CF_RAFT,
+    Engines, KvEngine, RaftEngine, RaftLogBatch, SstMetaInfo, WriteBatchExt, CF_DEFAULT, CF_LOCK,
+    CF_RAFT, CF_WRITE,
 };
 use error_code::ErrorCodeExt;
 use fail::fail_point;
@@ -103,8 +104,8 @@ use crate::{
         },
         util::{self, compare_region_epoch, KeysInfoFormatter, LeaseState},
         worker::{
-            Bucket, BucketRange, CleanupTask, ConsistencyCheckTask, GcSnapshotTask, RaftlogGcTask,
-            ReadDelegate, ReadProgress, RegionTask, SplitCheckTask,
+            ApplyPriority, Bucket, BucketRange, CleanupTask, CompactTask, ConsistencyCheckTask,
+            GcSnapshotTask, RaftlogGcTask, ReadDelegate, ReadProgress, RegionTask, SplitCheckTask,
         },
         CasualMessage, Config, LocksStatus, MergeResultKind, PdTask, PeerMsg, PeerTick,
         ProposalContext, RaftCmdExtraOpts, RaftCommand, RaftlogFetchResult, ReadCallback,
@@ -396,7 +397,16 @@ where
     }
 
     pub fn schedule_applying_snapshot(&mut self) {
-        self.peer.mut_store().schedule_applying_snapshot();
+        // Re-scheduled applies found at store start share the urgency rules
+        // of freshly persisted snapshots, see `Peer::on_persist_snapshot`.
+        let priority = if self.peer.unsafe_recovery_state.is_some()
+            || self.peer.leader_id() == raft::INVALID_ID
+        {
+            ApplyPriority::Urgent
+        } else {
+            ApplyPriority::Normal
+        };
+        self.peer.mut_store().schedule_applying_snapshot(priority);
     }
 
     pub fn reset_hibernate_state(&mut self, state: GroupState) {
@@ -1269,7 +1279,11 @@ where
                 let raft_msg = self.fsm.peer.build_raft_messages(self.ctx, vec![msg]);
                 self.fsm.peer.send_raft_messages(self.ctx, raft_msg);
             }
-            CasualMessage::SnapshotApplied { peer_id, tombstone } => {
+            CasualMessage::SnapshotApplied {
+                peer_id,
+                tombstone,
+                failure_count,
+            } => {
                 self.fsm.has_ready = true;
                 // If failed on applying snapshot, it should record the peer as an invalid peer.
                 if tombstone && self.fsm.peer.peer_id() == peer_id && !self.fsm.peer.is_leader() {
@@ -1277,6 +1291,7 @@ where
                         "mark the region damaged on applying snapshot";
                         "region_id" => self.region_id(),
                         "peer_id" => peer_id,
+                        "failure_count" => failure_count,
                     );
                     let mut meta = self.ctx.store_meta.lock().unwrap();
                     meta.damaged_regions.insert(self.region_id());
@@ -1285,6 +1300,24 @@ where
                     self.maybe_destroy();
                 }
             }
+            CasualMessage::ApplyingSnapshotLost => {
+                warn!(
+                    "applying snapshot is lost, requesting a new snapshot";
+                    "region_id" => self.region_id(),
+                    "peer_id" => self.fsm.peer_id(),
+                );
+                self.fsm.has_ready = true;
+                if !self.fsm.peer.is_leader() {
+                    if let Err(e) = self.fsm.peer.raft_group.request_snapshot() {
+                        error!(
+                            "failed to request snapshot";
+                            "region_id" => self.region_id(),
+                            "peer_id" => self.fsm.peer_id(),
+                            "err" => %e,
+                        );
+                    }
+                }
+            }
             CasualMessage::Campaign => {
                 let _ = self.fsm.peer.raft_group.campaign();
                 self.fsm.has_ready = true;
@@ -3051,15 +3084,24 @@ where
             ExtraMessageType::MsgRefreshBuckets => self.on_msg_refresh_buckets(msg),
             ExtraMessageType::MsgSnapGenPrecheckRequest => {
                 let passed = self.ctx.snap_mgr.recv_snap_precheck(msg.region_id);
+                let pending_apply_bytes = self.ctx.snap_mgr.pending_apply_bytes();
                 self.fsm.peer.send_snap_gen_precheck_response(
                     self.ctx,
                     &msg.from_peer.unwrap(),
                     passed,
+                    pending_apply_bytes,
                 )
             }
             ExtraMessageType::MsgSnapGenPrecheckResponse => {
                 let passed = msg.get_extra_msg().get_snap_gen_precheck_passed();
                 fail_point!("snap_gen_precheck_failed", !passed, |_| {});
+                // The `index` field of a precheck response carries the
+                // pending-apply backlog in bytes of the responding store.
+                self.ctx.snap_mgr.record_recv_store_backlog(
+                    msg.get_from_peer().get_store_id(),
+                    msg.get_extra_msg().get_index(),
+                    !passed,
+                );
                 info!(
                     "snap gen precheck response: {}", passed;
                     "region_id" => self.region_id(),
@@ -3653,6 +3695,11 @@ where
                     );
                 }
                 None => {
+                    // The pre-transfer phase is over: the real transfer is
+                    // initiated below, so release the store-level slot.
+                    self.ctx
+                        .pre_transfer_leader_tracker
+                        .finish(self.fsm.region_id());
                     self.propose_pending_batch_raft_command();
                     if self.propose_locks_before_transfer_leader(msg) {
                         // If some pessimistic locks are just proposed, we propose another
@@ -4396,6 +4443,20 @@ where
         if meta.region_ranges.remove(&last_key).is_none() {
             panic!("{} original region should exist", self.fsm.peer.tag);
         }
+        // Every end key but the last one is a boundary introduced by this
+        // split; the last one is the parent's original end key. The SST files
+        // straddling the new boundaries are shared by the sibling regions
+        // until compactions repartition them, which optionally gets kicked
+        // off right away below.
+        let split_boundaries: Vec<Vec<u8>> = if self.ctx.cfg.compact_split_boundaries {
+            regions
+                .iter()
+                .take(regions.len() - 1)
+                .map(enc_end_key)
+                .collect()
+        } else {
+            vec![]
+        };
         for (new_region, locks) in regions.into_iter().zip(region_locks) {
             let new_region_id = new_region.get_id();
 
@@ -4544,6 +4605,28 @@ where
             }
         }
         drop(meta);
+        // Compact the SST files straddling each new boundary so that the
+        // compaction guard cuts them along the new region edges promptly.
+        // This is a per-store concern, so it runs on followers as well.
+        for boundary_key in split_boundaries {
+            let Some(task) = CompactTask::compact_boundary(
+                vec![String::from(CF_DEFAULT), String::from(CF_WRITE)],
+                boundary_key,
+            ) else {
+                continue;
+            };
+            if let Err(e) = self
+                .ctx
+                .cleanup_scheduler
+                .schedule(CleanupTask::Compact(task))
+            {
+                error!(
+                    "schedule compact boundary task failed";
+                    "region_id" => self.fsm.region_id(),
+                    "err" => ?e,
+                );
+            }
+        }
         if is_leader {
             self.on_split_region_check_tick();
         }
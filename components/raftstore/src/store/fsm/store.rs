@@ -101,13 +101,13 @@ use crate::{
         peer_storage,
         transport::Transport,
         util,
-        util::{is_initial_msg, RegionReadProgressRegistry},
+        util::{is_initial_msg, PreTransferLeaderTracker, RegionReadProgressRegistry},
         worker::{
-            AutoSplitController, CleanupRunner, CleanupSstRunner, CleanupSstTask, CleanupTask,
-            CompactRunner, CompactTask, ConsistencyCheckRunner, ConsistencyCheckTask,
-            GcSnapshotRunner, GcSnapshotTask, PdRunner, RaftlogGcRunner, RaftlogGcTask,
-            ReadDelegate, RefreshConfigRunner, RefreshConfigTask, RegionRunner, RegionTask,
-            SplitCheckTask,
+            ApplyHistoryCallback, AutoSplitController, CleanupRunner, CleanupSstRunner,
+            CleanupSstTask, CleanupTask, CompactRunner, CompactTask, ConsistencyCheckRunner,
+            ConsistencyCheckTask, GcSnapshotRunner, GcSnapshotTask, PdRunner, RaftlogGcRunner,
+            RaftlogGcTask, ReadDelegate, RefreshConfigRunner, RefreshConfigTask, RegionRunner,
+            RegionTask, SplitCheckTask,
         },
         worker_metrics::PROCESS_STAT_CPU_USAGE,
         Callback, CasualMessage, CompactThreshold, FullCompactController, GlobalReplicationState,
@@ -579,6 +579,9 @@ where
     /// 1. lock the store_meta.
     /// 2. lock the pending_create_peers.
     pub pending_create_peers: Arc<Mutex<HashMap<u64, (u64, bool)>>>,
+    /// In-flight pre-transfer-leader operations of the store, used to cap
+    /// concurrent transfer leader warmups.
+    pub pre_transfer_leader_tracker: PreTransferLeaderTracker,
     pub raft_metrics: RaftMetrics,
     pub snap_mgr: SnapManager,
     pub coprocessor_host: CoprocessorHost<EK>,
@@ -906,6 +909,10 @@ impl<'a, EK: KvEngine + 'static, ER: RaftEngine + 'static, T: Transport>
                 StoreMsg::AwakenRegions { abnormal_stores } => {
                     self.on_wake_up_regions(abnormal_stores);
                 }
+                StoreMsg::SnapshotsApplied(applied) => self.on_snapshots_applied(applied),
+                StoreMsg::QuerySnapApplyHistory { region_id, cb } => {
+                    self.on_query_snap_apply_history(region_id, cb)
+                }
             }
         }
         slow_log!(
@@ -1272,6 +1279,7 @@ pub struct RaftPollerBuilder<EK: KvEngine, ER: RaftEngine, T> {
     pub engines: Engines<EK, ER>,
     global_replication_state: Arc<Mutex<GlobalReplicationState>>,
     feature_gate: FeatureGate,
+    pre_transfer_leader_tracker: PreTransferLeaderTracker,
     write_senders: WriteSenders<EK, ER>,
     node_start_time: Timespec, // monotonic_raw_now
     safe_point: Arc<AtomicU64>,
@@ -1504,6 +1512,7 @@ where
             importer: self.importer.clone(),
             store_meta: self.store_meta.clone(),
             pending_create_peers: self.pending_create_peers.clone(),
+            pre_transfer_leader_tracker: self.pre_transfer_leader_tracker.clone(),
             raft_metrics: RaftMetrics::new(self.cfg.value().waterfall_metrics),
             snap_mgr: self.snap_mgr.clone(),
             coprocessor_host: self.coprocessor_host.clone(),
@@ -1585,6 +1594,7 @@ where
             engines: self.engines.clone(),
             global_replication_state: self.global_replication_state.clone(),
             feature_gate: self.feature_gate.clone(),
+            pre_transfer_leader_tracker: self.pre_transfer_leader_tracker.clone(),
             write_senders: self.write_senders.clone(),
             node_start_time: self.node_start_time,
             safe_point: self.safe_point.clone(),
@@ -1710,7 +1720,9 @@ impl<EK: KvEngine, ER: RaftEngine> RaftBatchSystem<EK, ER> {
             cfg.clone(),
             workers.coprocessor_host.clone(),
             self.router(),
+            Some(Box::new(self.router())),
             Some(Arc::clone(&pd_client)),
+            None,
         );
         let snap_generator_pool = region_runner.snap_generator_pool();
         let region_scheduler = workers
@@ -1784,11 +1796,19 @@ impl<EK: KvEngine, ER: RaftEngine> RaftBatchSystem<EK, ER> {
             store_meta,
             pending_create_peers: Arc::new(Mutex::new(HashMap::default())),
             feature_gate: pd_client.feature_gate().clone(),
+            pre_transfer_leader_tracker: PreTransferLeaderTracker::default(),
             write_senders: self.store_writers.senders(),
             node_start_time: self.node_start_time,
             safe_point,
         };
         let region_peers = builder.init()?;
+        // `init` has re-scheduled the applies of every applying region it
+        // recognized; sweep CF_RAFT behind them for applying regions that
+        // still ended up without an apply task. The scan is batched on the
+        // region worker, so it does not delay startup.
+        if let Err(e) = region_scheduler.schedule(RegionTask::ReconcileApplying) {
+            error!("failed to schedule applying reconciliation"; "err" => %e);
+        }
         self.start_system::<T, C>(
             workers,
             region_peers,
@@ -2485,6 +2505,20 @@ impl<'a, EK: KvEngine, ER: RaftEngine, T: Transport> StoreFsmDelegate<'a, EK, ER
     }
 
     fn on_compaction_finished(&mut self, event: EK::CompactedEvent) {
+        // Hint the region worker first: even a compaction whose size decline
+        // is trivial may have dropped the last entries of a pending delete
+        // range, letting the worker retire the entry without any deletion.
+        if let Err(e) = self
+            .ctx
+            .region_scheduler
+            .schedule(RegionTask::CompactionFinished {
+                cf: event.cf().to_owned(),
+                start_key: event.start_key().to_vec(),
+                end_key: event.end_key().to_vec(),
+            })
+        {
+            warn!("failed to schedule compaction finished hint"; "err" => %e);
+        }
         if event.is_size_declining_trivial(self.ctx.cfg.region_split_check_diff().0) {
             return;
         }
@@ -3015,6 +3049,46 @@ impl<'a, EK: KvEngine, ER: RaftEngine, T: Transport> StoreFsmDelegate<'a, EK, ER
         self.register_compact_lock_cf_tick();
     }
 
+    fn on_snapshots_applied(&self, applied: Vec<(u64, u64, bool, u64)>) {
+        for (region_id, peer_id, tombstone, failure_count) in applied {
+            // Use force_send so a full mailbox cannot silently drop a peer's
+            // Applied notification out of the batch.
+            if let Err(e) = self.ctx.router.force_send(
+                region_id,
+                PeerMsg::CasualMessage(CasualMessage::SnapshotApplied {
+                    peer_id,
+                    tombstone,
+                    failure_count,
+                }),
+            ) {
+                warn!(
+                    "failed to fan out snapshot applied message";
+                    "region_id" => region_id,
+                    "peer_id" => peer_id,
+                    "err" => ?e
+                );
+            }
+        }
+    }
+
+    fn on_query_snap_apply_history(&self, region_id: u64, cb: ApplyHistoryCallback) {
+        if let Err(e) = self
+            .ctx
+            .region_scheduler
+            .schedule(RegionTask::QueryApplyHistory { region_id, cb })
+        {
+            warn!(
+                "failed to schedule snap apply history query";
+                "region_id" => region_id,
+                "err" => %e,
+            );
+            // Still answer the caller so it does not wait for the timeout.
+            if let RegionTask::QueryApplyHistory { cb, .. } = e.into_inner() {
+                (cb.0)(Vec::new());
+            }
+        }
+    }
+
     fn on_wake_up_regions(&self, abnormal_stores: Vec<u64>) {
         info!("try to wake up all hibernated regions in this store";
             "to_all" => abnormal_stores.is_empty());
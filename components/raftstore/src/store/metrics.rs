@@ -487,6 +487,12 @@ lazy_static! {
     pub static ref PEER_ADMIN_CMD_COUNTER: AdminCmdVec =
         auto_flush_from!(PEER_ADMIN_CMD_COUNTER_VEC, AdminCmdVec);
 
+    pub static ref ADMIN_RESULT_SUBSCRIBER_PANIC_COUNTER: IntCounter =
+        register_int_counter!(
+            "tikv_raftstore_admin_result_subscriber_panic_total",
+            "Total number of panics caught from admin result subscribers."
+        ).unwrap();
+
     pub static ref PEER_WRITE_CMD_COUNTER_VEC: IntCounterVec =
         register_int_counter_vec!(
             "tikv_raftstore_write_cmd_total",
@@ -620,6 +626,26 @@ lazy_static! {
             "Total number of GC raft log."
         ).unwrap();
 
+    pub static ref PEER_BATCH_SPLIT_DERIVATION_MISMATCH_COUNTER: IntCounter =
+        register_int_counter!(
+            "tikv_raftstore_batch_split_derivation_mismatch_total",
+            "Total number of batch splits whose derived regions failed post-derivation validation."
+        ).unwrap();
+
+    pub static ref PRE_FLUSH_FOLLOWER_SKIPPED_COUNTER: IntCounter =
+        register_int_counter!(
+            "tikv_raftstore_pre_flush_follower_skipped_total",
+            "Total number of followers not notified to pre-flush their memtables because a \
+            snapshot to them was in flight."
+        ).unwrap();
+
+    pub static ref FLUSH_MEMTABLE_DEDUPPED_COUNTER: IntCounter =
+        register_int_counter!(
+            "tikv_raftstore_flush_memtable_dedupped_total",
+            "Total number of flush memtable requests dropped because a flush of the same \
+            tablet was already scheduled recently."
+        ).unwrap();
+
     pub static ref UPDATE_REGION_SIZE_BY_COMPACTION_COUNTER: IntCounter =
         register_int_counter!(
             "update_region_size_count_by_compaction",
@@ -668,6 +694,28 @@ lazy_static! {
             exponential_buckets(0.05, 2.0, 20).unwrap()
         ).unwrap();
 
+    pub static ref SNAP_APPLY_VERIFY_DURATION_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_snapshot_apply_verify_duration_secs",
+            "Bucketed histogram of the total cf file verification time of one snapshot apply.",
+            exponential_buckets(0.0005, 2.0, 20).unwrap()
+        ).unwrap();
+
+    pub static ref SNAP_APPLY_INGEST_DURATION_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_snapshot_apply_ingest_duration_secs",
+            "Bucketed histogram of the total cf file ingestion time of one snapshot apply.",
+            exponential_buckets(0.0005, 2.0, 20).unwrap()
+        ).unwrap();
+
+    pub static ref SNAP_APPLY_OVERLAP_SAVED_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_snapshot_apply_overlap_saved_duration_secs",
+            "Bucketed histogram of wall time saved per snapshot apply by overlapping cf file \
+            verification with ingestion.",
+            exponential_buckets(0.0005, 2.0, 20).unwrap()
+        ).unwrap();
+
     pub static ref SNAPSHOT_KV_COUNT_HISTOGRAM: Histogram =
         register_histogram!(
             "tikv_snapshot_kv_count",
@@ -682,6 +730,20 @@ lazy_static! {
             exponential_buckets(1024.0, 2.0, 22).unwrap() // 1024,1024*2^1,..,4G
         ).unwrap();
 
+    pub static ref SNAPSHOT_APPLY_PLAIN_CF_BATCHES_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_snapshot_apply_plain_cf_batches",
+            "Number of write batches used to apply a plain cf file of snapshot.",
+            exponential_buckets(1.0, 2.0, 20).unwrap()
+        ).unwrap();
+
+    pub static ref SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_snapshot_apply_plain_cf_batch_bytes",
+            "Bytes per write batch while applying a plain cf file of snapshot.",
+            exponential_buckets(1024.0, 2.0, 22).unwrap()
+        ).unwrap();
+
     pub static ref RAFT_ENTRY_FETCHES_VEC: IntCounterVec =
         register_int_counter_vec!(
             "tikv_raftstore_entry_fetches",
@@ -708,6 +770,29 @@ lazy_static! {
     pub static ref WARM_UP_ENTRY_CACHE_COUNTER: WarmUpEntryCacheCounter =
         auto_flush_from!(WARM_UP_ENTRY_CACHE_COUNTER_VEC, WarmUpEntryCacheCounter);
 
+    pub static ref PRE_TRANSFER_LEADER_INFLIGHT_GAUGE: IntGauge =
+        register_int_gauge!(
+            "tikv_raftstore_pre_transfer_leader_inflight",
+            "Number of in-flight pre-transfer-leader operations."
+        ).unwrap();
+    pub static ref PRE_TRANSFER_LEADER_THROTTLE_COUNTER: IntCounter =
+        register_int_counter!(
+            "tikv_raftstore_pre_transfer_leader_throttled_total",
+            "Total number of transfer leader requests rejected by the in-flight pre-transfer cap."
+        ).unwrap();
+    pub static ref ADMIN_RESOURCE_GATE_REJECTED_COUNTER: IntCounterVec =
+        register_int_counter_vec!(
+            "tikv_raftstore_admin_resource_gate_rejected_total",
+            "Total number of admin commands rejected by the store resource gate.",
+            &["type", "reason"]
+        ).unwrap();
+    pub static ref WITNESS_ADMIN_CMD_REJECTED_COUNTER: IntCounterVec =
+        register_int_counter_vec!(
+            "tikv_raftstore_witness_admin_cmd_rejected_total",
+            "Total number of admin commands rejected because the peer is a witness.",
+            &["type"]
+        ).unwrap();
+
     pub static ref LEADER_MISSING: IntGauge =
         register_int_gauge!(
             "tikv_raftstore_leader_missing",
@@ -799,6 +884,18 @@ lazy_static! {
         "The number of regions that disable apply unpersisted raft log."
     ).unwrap();
 
+    pub static ref BCAST_COMMIT_TRANSITION_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "tikv_raftstore_bcast_commit_transition_total",
+            "Total number of transitions of the raft skip-bcast-commit flag.",
+            &["type"]
+        ).unwrap();
+
+    pub static ref FORCE_BCAST_COMMIT_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_force_bcast_commit_regions",
+        "The number of regions currently forcing immediate commit broadcasts."
+    ).unwrap();
+
     pub static ref RAFT_ENTRIES_CACHES_GAUGE: IntGauge = register_int_gauge!(
         "tikv_raft_entries_caches",
         "Total memory size of raft entries caches."
@@ -853,6 +950,19 @@ lazy_static! {
     pub static ref STORE_SLOW_SCORE_GAUGE: Gauge =
     register_gauge!("tikv_raftstore_slow_score", "Slow score of the store.").unwrap();
 
+    pub static ref STORE_APPLY_BACKLOG_DELAYED_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_apply_backlog_delayed_applies",
+        "Number of delayed snapshot applies in the last inspection window."
+    ).unwrap();
+    pub static ref STORE_APPLY_BACKLOG_MAX_WAIT_GAUGE: Gauge = register_gauge!(
+        "tikv_raftstore_apply_backlog_max_wait_seconds",
+        "Longest wait of a snapshot apply observed in the last inspection window."
+    ).unwrap();
+    pub static ref STORE_APPLY_BACKLOG_BUSY_RATIO_GAUGE: Gauge = register_gauge!(
+        "tikv_raftstore_apply_backlog_busy_ratio",
+        "Fraction of the last inspection window the region worker spent applying snapshots."
+    ).unwrap();
+
     pub static ref STORE_SLOW_TREND_GAUGE: Gauge =
     register_gauge!("tikv_raftstore_slow_trend", "Slow trend changing rate.").unwrap();
 
@@ -942,6 +1052,37 @@ lazy_static! {
     )
     .unwrap();
 
+    pub static ref SNAP_GEN_BACKPRESSURE_DELAY_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_snap_gen_backpressure_delay_total",
+        "Total number of snapshot generations delayed by the receiver's pending-apply backlog",
+    )
+    .unwrap();
+
+    pub static ref SNAP_RESERVED_SPACE_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_snapshot_reserved_space_bytes",
+        "Total disk space in bytes reserved for incoming snapshots",
+    )
+    .unwrap();
+
+    pub static ref MERGE_CATCH_UP_QUEUE_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_merge_catch_up_queue_size",
+        "Number of CommitMerge operations waiting for a catch up logs permit"
+    )
+    .unwrap();
+
+    pub static ref MERGE_CATCH_UP_WAIT_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_merge_catch_up_wait_duration_secs",
+        "Bucketed histogram of the time CommitMerge operations wait for a catch up logs permit",
+        exponential_buckets(0.001, 2.0, 20).unwrap()
+    )
+    .unwrap();
+
+    pub static ref RAFT_LOG_PURGEABLE_BYTES_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_raft_log_purgeable_bytes",
+        "Estimated bytes of compacted raft logs awaiting reclamation by the raft engine purge"
+    )
+    .unwrap();
+
     pub static ref MESSAGE_RECV_BY_STORE: IntCounterVec = register_int_counter_vec!(
         "tikv_raftstore_message_recv_by_store",
         "Messages received by store",
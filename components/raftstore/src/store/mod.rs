@@ -72,8 +72,9 @@ pub use self::{
     snap::{
         check_abort, copy_snapshot,
         snap_io::{apply_sst_cf_file, build_sst_cf_file_list},
-        ApplyOptions, CfFile, Error as SnapError, SnapEntry, SnapKey, SnapManager,
-        SnapManagerBuilder, Snapshot, SnapshotStatistics, TabletSnapKey, TabletSnapManager,
+        ApplyBacklogStats, ApplyBacklogWindow, ApplyOptions, CfFile, CfFileMeta,
+        Error as SnapError, SnapEntry, SnapKey, SnapManager, SnapManagerBuilder, Snapshot,
+        SnapshotStatistics, TabletSnapKey, TabletSnapManager,
     },
     snapshot_backup::SnapshotBrWaitApplySyncer,
     transport::{CasualRouter, ProposalRouter, SignificantRouter, StoreRouter, Transport},
@@ -86,12 +87,14 @@ pub use self::{
     },
     util::{RegionReadProgress, RegionReadProgressRegistry},
     worker::{
-        metrics as worker_metrics, need_compact, AutoSplitController, BatchComponent, Bucket,
-        BucketRange, BucketStatsInfo, CachedReadDelegate, CheckLeaderRunner, CheckLeaderTask,
-        CompactThreshold, FlowStatistics, FlowStatsReporter, FullCompactController, KeyEntry,
-        LocalReadContext, LocalReader, LocalReaderCore, PdStatsMonitor, PdTask, ReadDelegate,
-        ReadExecutor, ReadExecutorProvider, ReadProgress, ReadStats, RefreshConfigTask, RegionTask,
-        SplitCheckRunner, SplitCheckTask, SplitConfig, SplitConfigManager, SplitInfo,
+        metrics as worker_metrics, need_compact, ApplyHistoryCallback, AutoSplitController,
+        BatchComponent, Bucket, BucketRange, BucketStatsInfo, CachedReadDelegate,
+        CheckLeaderRunner, CheckLeaderTask, CleanRegionCallback, CleanRegionOutcome,
+        CleanRegionRangeResult, CompactThreshold, FlowStatistics, FlowStatsReporter,
+        FullCompactController, KeyEntry, LocalReadContext, LocalReader, LocalReaderCore,
+        PdStatsMonitor, PdTask, ReadDelegate, ReadExecutor, ReadExecutorProvider, ReadProgress,
+        ReadStats, RefreshConfigTask, RegionTask, SnapApplyHistoryEntry, SplitCheckRunner,
+        SplitCheckTask, SplitConfig, SplitConfigManager, SplitInfo,
         StoreMetaDelegate, StoreStatsReporter, TrackVer, WriteStats, WriterContoller,
         BIG_REGION_CPU_OVERLOAD_THRESHOLD_RATIO, DEFAULT_BIG_REGION_BYTE_THRESHOLD,
         DEFAULT_BIG_REGION_QPS_THRESHOLD, DEFAULT_BYTE_THRESHOLD, DEFAULT_QPS_THRESHOLD,
@@ -40,7 +40,7 @@ use crate::store::{
         UnsafeRecoveryForceLeaderSyncer, UnsafeRecoveryWaitApplySyncer,
     },
     util::KeysInfoFormatter,
-    worker::{Bucket, BucketRange},
+    worker::{ApplyHistoryCallback, Bucket, BucketRange},
     SnapKey,
 };
 
@@ -645,8 +645,18 @@ pub enum CasualMessage<EK: KvEngine> {
         peer_id: u64,
         /// Whether the peer is destroyed after applying the snapshot
         tombstone: bool,
+        /// The number of consecutive apply failures of the region observed by
+        /// the region worker, 0 if this apply succeeded. The peer may use it
+        /// to escalate, e.g. requesting a fresh snapshot from another peer.
+        failure_count: u64,
     },
 
+    /// The region is recorded as `PeerState::Applying` but the region worker
+    /// holds no apply task for it and the received snapshot is gone, so the
+    /// apply can never finish on its own. Sent by the region-worker
+    /// reconciliation; the peer should request a fresh snapshot.
+    ApplyingSnapshotLost,
+
     // Trigger raft to campaign which is used after exiting force leader
     Campaign,
 }
@@ -714,11 +724,16 @@ impl<EK: KvEngine> fmt::Debug for CasualMessage<EK> {
             }
             CasualMessage::RefreshRegionBuckets { .. } => write!(fmt, "RefreshRegionBuckets"),
             CasualMessage::RenewLease => write!(fmt, "RenewLease"),
-            CasualMessage::SnapshotApplied { peer_id, tombstone } => write!(
+            CasualMessage::SnapshotApplied {
+                peer_id,
+                tombstone,
+                failure_count,
+            } => write!(
                 fmt,
-                "SnapshotApplied, peer_id={}, tombstone={}",
-                peer_id, tombstone
+                "SnapshotApplied, peer_id={}, tombstone={}, failure_count={}",
+                peer_id, tombstone, failure_count
             ),
+            CasualMessage::ApplyingSnapshotLost => write!(fmt, "ApplyingSnapshotLost"),
             CasualMessage::Campaign => write!(fmt, "Campaign"),
         }
     }
@@ -942,6 +957,18 @@ where
         abnormal_stores: Vec<u64>,
     },
 
+    /// Batched snapshot-applied notifications from the region worker, fanned
+    /// out to the owning peers as `CasualMessage::SnapshotApplied`. Entries
+    /// are `(region_id, peer_id, tombstone, failure_count)`.
+    SnapshotsApplied(Vec<(u64, u64, bool, u64)>),
+
+    /// Queries the recent snapshot-apply history of a region from the region
+    /// worker, for the status server's region detail page.
+    QuerySnapApplyHistory {
+        region_id: u64,
+        cb: ApplyHistoryCallback,
+    },
+
     /// Message only used for test.
     #[cfg(any(test, feature = "testexport"))]
     Validate(Box<dyn FnOnce(&crate::store::Config) + Send>),
@@ -977,6 +1004,12 @@ where
             }
             StoreMsg::GcSnapshotFinish => write!(fmt, "GcSnapshotFinish"),
             StoreMsg::AwakenRegions { .. } => write!(fmt, "AwakenRegions"),
+            StoreMsg::SnapshotsApplied(ref applied) => {
+                write!(fmt, "SnapshotsApplied [count: {}]", applied.len())
+            }
+            StoreMsg::QuerySnapApplyHistory { region_id, .. } => {
+                write!(fmt, "QuerySnapApplyHistory [region_id: {}]", region_id)
+            }
             #[cfg(any(test, feature = "testexport"))]
             StoreMsg::Validate(_) => write!(fmt, "Validate config"),
         }
@@ -998,8 +1031,10 @@ impl<EK: KvEngine> StoreMsg<EK> {
             StoreMsg::UnsafeRecoveryCreatePeer { .. } => 9,
             StoreMsg::GcSnapshotFinish => 10,
             StoreMsg::AwakenRegions { .. } => 11,
+            StoreMsg::SnapshotsApplied(_) => 12,
+            StoreMsg::QuerySnapApplyHistory { .. } => 13,
             #[cfg(any(test, feature = "testexport"))]
-            StoreMsg::Validate(_) => 12, // Please keep this always be the last one.
+            StoreMsg::Validate(_) => 14, // Please keep this always be the last one.
         }
     }
 }
@@ -107,8 +107,8 @@ use crate::{
         unsafe_recovery::{ForceLeaderState, UnsafeRecoveryState},
         util::{admin_cmd_epoch_lookup, RegionReadProgress},
         worker::{
-            CleanupTask, CompactTask, HeartbeatTask, RaftlogGcTask, ReadDelegate, ReadExecutor,
-            ReadProgress, RegionTask, SplitCheckTask,
+            ApplyPriority, CleanupTask, CompactTask, HeartbeatTask, RaftlogGcTask, ReadDelegate,
+            ReadExecutor, ReadProgress, RegionTask, SplitCheckTask,
         },
         Callback, Config, GlobalReplicationState, PdTask, ReadCallback, ReadIndexContext,
         ReadResponse, TxnExt, WriteCallback, RAFT_INIT_LOG_INDEX,
@@ -2690,7 +2690,18 @@ where
                 // Continuously send snap gen precheck requests to the follower
                 // until an approval is received.
                 if let Some(to_peer) = self.get_store().need_gen_snap_precheck() {
-                    self.send_snap_gen_precheck_request(ctx, &to_peer);
+                    // Hold off the precheck while the target store reports a
+                    // large pending-apply backlog, so stores that are ready to
+                    // absorb snapshots are served first. The gen task stays
+                    // queued and is retried on later ticks.
+                    if ctx.snap_mgr.snap_gen_delayed_by_backlog(
+                        to_peer.get_store_id(),
+                        ctx.cfg.snap_receiver_backlog_threshold.0,
+                    ) {
+                        SNAP_GEN_BACKPRESSURE_DELAY_COUNTER.inc();
+                    } else {
+                        self.send_snap_gen_precheck_request(ctx, &to_peer);
+                    }
                 }
             } else {
                 let gen_task = self.mut_store().take_gen_snap_task().unwrap();
@@ -2979,6 +2990,7 @@ where
                         CasualMessage::SnapshotApplied {
                             peer_id: self.peer.get_id(),
                             tombstone: false,
+                            failure_count: 0,
                         },
                     )
                     .unwrap();
@@ -3210,7 +3222,17 @@ where
         let persist_res = snap_ctx.persist_res.take().unwrap();
         // Schedule snapshot to apply
         snap_ctx.scheduled = true;
-        self.mut_store().persist_snapshot(&persist_res);
+        // A snapshot applied as part of unsafe recovery or by a region that
+        // currently has no serving leader is the only path back to quorum,
+        // so it is queued as urgent and jumps ahead of routine rebalance
+        // applies on the region worker.
+        let priority =
+            if self.unsafe_recovery_state.is_some() || self.leader_id() == raft::INVALID_ID {
+                ApplyPriority::Urgent
+            } else {
+                ApplyPriority::Normal
+            };
+        self.mut_store().persist_snapshot(&persist_res, priority);
 
         // The peer may change from learner to voter after snapshot persisted.
         let peer = self
@@ -4824,7 +4846,35 @@ where
         let transferred = if peer.id == self.peer.id {
             false
         } else {
-            self.pre_transfer_leader(peer)
+            // Account the pre-transfer phase in the store-level tracker. Once
+            // too many pre-transfers are in flight, e.g. under a PD balance
+            // storm, reject the request with a busy error so that PD retries
+            // it later instead of queueing one more warmup. Transfers that
+            // carry `TRANSFER_LEADER_PROPOSAL` skip the phase and are never
+            // throttled here.
+            let cap = ctx.cfg.max_inflight_pre_transfer_leader;
+            if !ctx
+                .pre_transfer_leader_tracker
+                .try_begin(self.region_id, cap)
+            {
+                info!(
+                    "reject transfer leader due to too many in-flight pre-transfers";
+                    "region_id" => self.region_id,
+                    "peer_id" => self.peer.get_id(),
+                    "transferee" => peer.get_id(),
+                    "cap" => cap,
+                );
+                let mut resp = RaftCmdResponse::new();
+                *resp.mut_header().mut_error() = Error::PreTransferLeaderThrottled(cap).into();
+                cb.invoke_with_response(resp);
+                return false;
+            }
+            let msg_sent = self.pre_transfer_leader(peer);
+            if !msg_sent {
+                // The pre-transfer did not start, release the slot right away.
+                ctx.pre_transfer_leader_tracker.finish(self.region_id);
+            }
+            msg_sent
         };
 
         // transfer leader command doesn't need to replicate log and apply, so we
@@ -5015,6 +5065,14 @@ where
             Some(SnapshotContext {
                 range: Some(CacheRange::from_region(&region)),
                 read_ts,
+                // Only follower reads have to validate the freshness of the
+                // range cache against the read index confirmed with the
+                // leader; the leader's cache is kept fresh by its own apply.
+                required_apply_index: if req.get_header().get_replica_read() {
+                    read_index
+                } else {
+                    None
+                },
             })
         } else {
             None
@@ -5746,10 +5804,15 @@ where
         ctx: &mut PollContext<EK, ER, T>,
         to_peer: &metapb::Peer,
         passed: bool,
+        pending_apply_bytes: u64,
     ) {
         let mut extra_msg = ExtraMessage::default();
         extra_msg.set_type(ExtraMessageType::MsgSnapGenPrecheckResponse);
         extra_msg.set_snap_gen_precheck_passed(passed);
+        // `ExtraMessage` has no dedicated field for the backlog, so the
+        // `index` field is reused to carry the pending-apply bytes of this
+        // store. It is only interpreted this way for precheck responses.
+        extra_msg.set_index(pending_apply_bytes);
         self.send_extra_message(extra_msg, &mut ctx.trans, to_peer);
     }
 
@@ -38,7 +38,11 @@ use tikv_util::{
     worker::Scheduler,
 };
 
-use super::{metrics::*, worker::RegionTask, SnapEntry, SnapKey, SnapManager};
+use super::{
+    metrics::*,
+    worker::{ApplyPriority, RegionTask},
+    SnapEntry, SnapKey, SnapManager,
+};
 use crate::{
     store::{
         async_io::{read::ReadTask, write::WriteTask},
@@ -927,7 +931,7 @@ where
         self.region().get_id()
     }
 
-    pub fn schedule_applying_snapshot(&mut self) {
+    pub fn schedule_applying_snapshot(&mut self, priority: ApplyPriority) {
         let status = Arc::new(AtomicUsize::new(JOB_STATUS_PENDING));
         self.set_snap_state(SnapState::Applying(Arc::clone(&status)));
         let task = RegionTask::Apply {
@@ -935,6 +939,10 @@ where
             status,
             peer_id: self.peer_id,
             create_time: Instant::now_coarse(),
+            priority,
+            region_state: None,
+            apply_state: None,
+            on_finish: None,
         };
 
         // Don't schedule the snapshot to region worker.
@@ -1019,7 +1027,7 @@ where
         Ok((res, write_task))
     }
 
-    pub fn persist_snapshot(&mut self, res: &PersistSnapshotResult) {
+    pub fn persist_snapshot(&mut self, res: &PersistSnapshotResult, priority: ApplyPriority) {
         // cleanup data before scheduling apply task
         if self.is_initialized() {
             if let Err(e) = self.clear_extra_data(self.region(), &res.region) {
@@ -1054,7 +1062,7 @@ where
         }
 
         if !res.for_witness {
-            self.schedule_applying_snapshot();
+            self.schedule_applying_snapshot(priority);
         } else {
             // Bypass apply snapshot process for witness as the snapshot is empty, so mark
             // status as finished directly here
@@ -1693,7 +1701,9 @@ pub mod tests {
             cfg,
             CoprocessorHost::<KvTestEngine>::default(),
             router,
+            None,
             Option::<Arc<TestPdClient>>::None,
+            None,
         );
         worker.start_with_timer(runner);
         let to_peer_id = s.peer_id;
@@ -1842,7 +1852,9 @@ pub mod tests {
             cfg,
             CoprocessorHost::<KvTestEngine>::default(),
             router,
+            None,
             Some(pd_mock),
+            None,
         );
         worker.start_with_timer(runner);
         let snap = s.snapshot(0, 1);
@@ -1903,7 +1915,9 @@ pub mod tests {
             cfg,
             CoprocessorHost::<KvTestEngine>::default(),
             router,
+            None,
             Option::<Arc<TestPdClient>>::None,
+            None,
         );
         worker.start_with_timer(runner);
 
@@ -1983,7 +1997,9 @@ pub mod tests {
             cfg,
             CoprocessorHost::<KvTestEngine>::default(),
             router,
+            None,
             Option::<Arc<TestPdClient>>::None,
+            None,
         );
         worker.start(runner);
         s1.snapshot(0, 1).unwrap_err();
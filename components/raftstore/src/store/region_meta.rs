@@ -1,9 +1,13 @@
 // Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use kvproto::{
     metapb::{self, PeerRole},
+    raft_cmdpb::AdminCmdType,
     raft_serverpb,
 };
 use raft::{Progress, ProgressState, StateRole, Status};
@@ -171,12 +175,99 @@ impl PartialEq<PeerRole> for RaftPeerRole {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Epoch {
     pub conf_ver: u64,
     pub version: u64,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AdminCmdKind {
+    InvalidAdmin,
+    ChangePeer,
+    ChangePeerV2,
+    Split,
+    BatchSplit,
+    CompactLog,
+    TransferLeader,
+    ComputeHash,
+    VerifyHash,
+    PrepareMerge,
+    CommitMerge,
+    RollbackMerge,
+    PrepareFlashback,
+    FinishFlashback,
+    BatchSwitchWitness,
+    UpdateGcPeer,
+}
+
+impl From<AdminCmdType> for AdminCmdKind {
+    fn from(ty: AdminCmdType) -> Self {
+        match ty {
+            AdminCmdType::InvalidAdmin => AdminCmdKind::InvalidAdmin,
+            AdminCmdType::ChangePeer => AdminCmdKind::ChangePeer,
+            AdminCmdType::ChangePeerV2 => AdminCmdKind::ChangePeerV2,
+            AdminCmdType::Split => AdminCmdKind::Split,
+            AdminCmdType::BatchSplit => AdminCmdKind::BatchSplit,
+            AdminCmdType::CompactLog => AdminCmdKind::CompactLog,
+            AdminCmdType::TransferLeader => AdminCmdKind::TransferLeader,
+            AdminCmdType::ComputeHash => AdminCmdKind::ComputeHash,
+            AdminCmdType::VerifyHash => AdminCmdKind::VerifyHash,
+            AdminCmdType::PrepareMerge => AdminCmdKind::PrepareMerge,
+            AdminCmdType::CommitMerge => AdminCmdKind::CommitMerge,
+            AdminCmdType::RollbackMerge => AdminCmdKind::RollbackMerge,
+            AdminCmdType::PrepareFlashback => AdminCmdKind::PrepareFlashback,
+            AdminCmdType::FinishFlashback => AdminCmdKind::FinishFlashback,
+            AdminCmdType::BatchSwitchWitness => AdminCmdKind::BatchSwitchWitness,
+            AdminCmdType::UpdateGcPeer => AdminCmdKind::UpdateGcPeer,
+        }
+    }
+}
+
+/// One applied admin command. Raftstore v2 keeps a bounded per-peer history
+/// of them for post-mortem analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminCmdHistoryEntry {
+    pub cmd_type: AdminCmdKind,
+    pub index: u64,
+    pub term: u64,
+    pub epoch_before: Epoch,
+    pub epoch_after: Epoch,
+    /// The peer that initiated the command, taken from the request header.
+    pub peer_id: u64,
+    /// Unix timestamp in milliseconds of when the command was applied.
+    pub apply_time_ms: u64,
+}
+
+impl AdminCmdHistoryEntry {
+    pub fn new(
+        cmd_type: AdminCmdType,
+        index: u64,
+        term: u64,
+        epoch_before: &metapb::RegionEpoch,
+        epoch_after: &metapb::RegionEpoch,
+        peer_id: u64,
+    ) -> Self {
+        Self {
+            cmd_type: cmd_type.into(),
+            index,
+            term,
+            epoch_before: Epoch {
+                conf_ver: epoch_before.get_conf_ver(),
+                version: epoch_before.get_version(),
+            },
+            epoch_after: Epoch {
+                conf_ver: epoch_after.get_conf_ver(),
+                version: epoch_after.get_version(),
+            },
+            peer_id,
+            apply_time_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct RegionPeer {
     pub id: u64,
@@ -238,6 +329,57 @@ pub struct RegionLocalState {
     pub tablet_index: u64,
 }
 
+/// A snapshot of the entry cache warmup state of a peer, see
+/// `CacheWarmupState`.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct EntryCacheWarmupState {
+    /// The requested warmup range `[low, high)`.
+    pub range_start: u64,
+    pub range_end: u64,
+    /// How long ago the warmup was started, in milliseconds.
+    pub elapsed_ms: u64,
+    pub is_finished: bool,
+    pub is_timeout: bool,
+    pub is_stale: bool,
+}
+
+/// Wall-clock breakdown of the last batch split a peer led, stage by stage
+/// in microseconds, see `SplitLatencyInspector` of raftstore v2. Collected so
+/// a slow split can be attributed to a concrete stage instead of a single
+/// total.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct SplitLatencyBreakdown {
+    /// Time spent flushing memtables before the split was proposed. Absent
+    /// when the request skipped the pre-flush phase.
+    pub pre_flush_wait_micros: Option<u64>,
+    /// From proposing the split to its commit in the raft group.
+    pub propose_to_commit_micros: u64,
+    /// From the commit to the moment the leader's apply picked it up.
+    pub commit_to_apply_micros: u64,
+    /// The tablet checkpoint inside the apply.
+    pub checkpoint_micros: u64,
+    /// From finishing the apply to all child peers reporting their
+    /// initialization. Absent until the last child has reported.
+    pub post_apply_init_micros: Option<u64>,
+}
+
+/// A snapshot of the proposal control state of a peer, see
+/// `ProposalControl` of raftstore v2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalControlState {
+    /// Types of the admin commands that are proposed but not yet applied,
+    /// oldest first.
+    pub proposed_admin_cmds: Vec<AdminCmdKind>,
+    pub has_pending_prepare_merge: bool,
+    /// Commit index of an applied `PrepareMerge`, 0 if the peer is not
+    /// waiting for being merged.
+    pub applied_prepare_merge_index: u64,
+    /// Whether the peer is currently forcing immediate commit broadcasts
+    /// instead of the default lazy broadcasting, e.g. because of an
+    /// uncommitted admin command.
+    pub force_bcast_commit: bool,
+}
+
 /// A serializeable struct that exposes the internal debug information of a
 /// peer. TODO: make protobuf generated code derive serde directly.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,6 +389,17 @@ pub struct RegionMeta {
     pub raft_apply: RaftApplyState,
     pub region_state: RegionLocalState,
     pub bucket_keys: Vec<Vec<u8>>,
+    /// The last applied admin commands, oldest first. Only filled by
+    /// raftstore v2.
+    pub admin_cmd_history: Vec<AdminCmdHistoryEntry>,
+    /// The entry cache warmup state of the peer, if it has entered one after
+    /// a leader transfer or an explicit trigger. Only filled by raftstore v2.
+    pub entry_cache_warmup: Option<EntryCacheWarmupState>,
+    /// The proposal control state of the peer. Only filled by raftstore v2.
+    pub proposal_control: Option<ProposalControlState>,
+    /// The latency breakdown of the last batch split the peer led. Only
+    /// filled by raftstore v2.
+    pub last_split_latency: Option<SplitLatencyBreakdown>,
 }
 
 impl RegionMeta {
@@ -310,6 +463,10 @@ impl RegionMeta {
                 tablet_index: local_state.get_tablet_index(),
             },
             bucket_keys: vec![],
+            admin_cmd_history: vec![],
+            entry_cache_warmup: None,
+            proposal_control: None,
+            last_split_latency: None,
         }
     }
 }
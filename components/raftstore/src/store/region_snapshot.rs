@@ -216,8 +216,7 @@ where
             self.region.get_id(),
             self.region.get_start_key(),
             self.region.get_end_key(),
-        )
-        .map_err(|e| EngineError::Other(box_err!(e)))?;
+        )?;
         let data_key = keys::data_key(key);
         self.snap
             .get_value_opt(opts, &data_key)
@@ -235,13 +234,34 @@ where
             self.region.get_id(),
             self.region.get_start_key(),
             self.region.get_end_key(),
-        )
-        .map_err(|e| EngineError::Other(box_err!(e)))?;
+        )?;
         let data_key = keys::data_key(key);
         self.snap
             .get_value_cf_opt(opts, cf, &data_key)
             .map_err(|e| self.handle_get_value_error(e, cf, key))
     }
+
+    fn multi_get_value_cf(
+        &self,
+        cf: &str,
+        keys: &[&[u8]],
+    ) -> EngineResult<Vec<Option<Self::DbVector>>> {
+        for key in keys {
+            check_key_in_range(
+                key,
+                self.region.get_id(),
+                self.region.get_start_key(),
+                self.region.get_end_key(),
+            )?;
+        }
+        let data_keys: Vec<Vec<u8>> = keys.iter().map(|key| keys::data_key(key)).collect();
+        let data_key_refs: Vec<&[u8]> = data_keys.iter().map(|k| k.as_slice()).collect();
+        // The engine does not report which key of the batch failed, so the
+        // error is attributed to the batch as a whole.
+        self.snap
+            .multi_get_value_cf(cf, &data_key_refs)
+            .map_err(|e| self.handle_get_value_error(e, cf, b""))
+    }
 }
 
 impl<S> RegionSnapshot<S>
@@ -250,6 +270,23 @@ where
 {
     #[inline(never)]
     fn handle_get_value_error(&self, e: EngineError, cf: &str, key: &[u8]) -> EngineError {
+        // A range-confined engine snapshot (e.g. the range cache engine)
+        // reports out-of-range reads in terms of its own range. Re-attribute
+        // them to the region so clients see the same `key_not_in_region`
+        // error no matter which engine served the read.
+        if let EngineError::KeyOutOfSnapshotRange { key: engine_key, .. } = e {
+            let origin_key = if keys::validate_data_key(&engine_key) {
+                keys::origin_key(&engine_key).to_vec()
+            } else {
+                engine_key
+            };
+            return EngineError::NotInRange {
+                key: origin_key,
+                region_id: self.region.get_id(),
+                start: self.region.get_start_key().to_vec(),
+                end: self.region.get_end_key().to_vec(),
+            };
+        }
         CRITICAL_ERROR.with_label_values(&["rocksdb get"]).inc();
         if panic_when_unexpected_key_or_data() {
             set_panic_mark();
@@ -531,6 +568,49 @@ mod tests {
         v4.unwrap_err();
     }
 
+    #[test]
+    fn test_out_of_range_error_classification() {
+        let path = Builder::new().prefix("test-raftstore").tempdir().unwrap();
+        let engines = new_temp_engine(&path);
+        let mut r = Region::default();
+        r.set_id(10);
+        r.set_start_key(b"key0".to_vec());
+        r.set_end_key(b"key4".to_vec());
+        let store = new_peer_storage(engines, &r);
+        let snap = RegionSnapshot::<KvTestSnapshot>::new(&store);
+
+        // An out-of-region get surfaces the structured range error instead of
+        // an opaque `Other`.
+        let e = snap.get_value(b"key5").unwrap_err();
+        assert!(matches!(
+            e,
+            EngineError::NotInRange { ref key, region_id, .. }
+                if key == b"key5" && region_id == 10
+        ));
+
+        // Clients see it as a `key_not_in_region` region error.
+        let header = kvproto::errorpb::Error::from(crate::Error::from(e));
+        assert!(header.has_key_not_in_region());
+        assert_eq!(header.get_key_not_in_region().get_key(), b"key5");
+        assert_eq!(header.get_key_not_in_region().get_region_id(), 10);
+
+        // A range-confined engine snapshot reporting its own range is
+        // re-attributed to the region, with the data prefix stripped, so it
+        // classifies identically.
+        let engine_err = EngineError::KeyOutOfSnapshotRange {
+            key: data_key(b"key9"),
+            start: data_key(b"key0"),
+            end: data_key(b"key4"),
+        };
+        let e = snap.handle_get_value_error(engine_err, CF_DEFAULT, b"key9");
+        assert!(matches!(
+            e,
+            EngineError::NotInRange { ref key, region_id, ref start, ref end }
+                if key == b"key9" && region_id == 10 && start == b"key0" && end == b"key4"
+        ));
+        assert!(kvproto::errorpb::Error::from(crate::Error::from(e)).has_key_not_in_region());
+    }
+
     #[allow(clippy::type_complexity)]
     #[test]
     fn test_seek_and_seek_prev() {
@@ -9,7 +9,7 @@ use std::{
     result, str,
     sync::{
         atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
-        Arc, Mutex, RwLock,
+        mpsc, Arc, Mutex, RwLock,
     },
     thread,
     time::{self, Duration},
@@ -18,7 +18,9 @@ use std::{
 
 use collections::{HashMap, HashMapEntry as Entry};
 use encryption::{create_aes_ctr_crypter, DataKeyManager, Iv};
-use engine_traits::{CfName, KvEngine, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use engine_traits::{
+    CfName, KvEngine, Mutable, Peekable, SyncMutable, CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE,
+};
 use error_code::{self, ErrorCode, ErrorCodeExt};
 use fail::fail_point;
 use file_system::{
@@ -76,6 +78,11 @@ const META_FILE_SUFFIX: &str = ".meta";
 const DELETE_RETRY_MAX_TIMES: u32 = 6;
 const DELETE_RETRY_TIME_MILLIS: u64 = 500;
 
+// How long a temporary delete SST in the alternate ingest directory must have
+// been idle before the periodic gc considers it orphaned. Building and
+// ingesting such an SST finishes well within this window.
+const INGEST_TEMP_FILE_TTL: Duration = Duration::from_secs(600);
+
 // TTL for the recv snap concurrency limiter, specified in seconds. This TTL
 // should be longer than the typical snapshot generation and transmission time.
 // If the TTL is too short, the limiter might permit more snapshots than
@@ -83,6 +90,10 @@ const DELETE_RETRY_TIME_MILLIS: u64 = 500;
 // regenerating them, which is what the concurrency limiter is designed to
 // prevent.
 const RECV_SNAP_CONCURRENCY_LIMITER_TTL_SECS: u64 = 60;
+// How long a pending-apply backlog report from a receiving store stays valid.
+// Expired feedback is ignored so that an unreachable store cannot hold off
+// snapshot generation forever.
+const SNAP_GEN_BACKLOG_FEEDBACK_TTL: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -197,8 +208,135 @@ where
     pub region: Region,
     pub abort: Arc<AtomicUsize>,
     pub write_batch_size: usize,
+    /// Write batch size used for the lock cf, which is replayed key by key
+    /// from a plain file rather than ingested.
+    pub write_batch_size_lock: usize,
     pub coprocessor_host: CoprocessorHost<EK>,
     pub ingest_copy_symlink: bool,
+    /// The maximum number of SST ingestions for different column families
+    /// that run concurrently. 1 means the ingestions are serial.
+    pub ingest_concurrency: usize,
+    /// Verify the checksum of each cf file on a dedicated thread, overlapped
+    /// with the ingestion of the preceding cf file. When disabled all files
+    /// are verified serially before any ingestion starts.
+    pub pipelined_verification: bool,
+    /// Persisted per-cf ingest progress of the apply, so a retry after a crash
+    /// can skip the column families it already ingested.
+    pub progress: Arc<SnapApplyProgress<EK>>,
+}
+
+/// Persistent progress of an in-flight snapshot apply, stored per region in
+/// `CF_RAFT` under `keys::snapshot_apply_progress_key`.
+///
+/// The record identifies the snapshot by `(term, idx)` and lists the column
+/// families whose SST files have been fully ingested, so an apply that is
+/// retried after a crash (or a re-delivered apply task for the same snapshot)
+/// does not redo completed ingestions. The record is only written after the
+/// overlap cleanup of the region has run, as skipping the cleanup on a retry
+/// is only safe if it preceded the first ingest. Plain column family files are
+/// replayed through a write batch, which is idempotent, so they are not
+/// tracked. The record is removed by the write batch that commits the final
+/// region state.
+pub struct SnapApplyProgress<EK> {
+    db: EK,
+    region_id: u64,
+    term: u64,
+    idx: u64,
+    ingested: Mutex<Vec<String>>,
+}
+
+impl<EK> SnapApplyProgress<EK>
+where
+    EK: KvEngine,
+{
+    /// Loads the progress recorded for the given snapshot. Returns the
+    /// progress handle and whether the overlap cleanup has already completed
+    /// for this exact snapshot; a record left behind by a different snapshot
+    /// is stale and ignored.
+    pub fn resume(db: EK, snap_key: &SnapKey) -> Result<(SnapApplyProgress<EK>, bool)> {
+        let value = box_try!(
+            db.get_value_cf(CF_RAFT, &keys::snapshot_apply_progress_key(snap_key.region_id))
+        );
+        let ingested = value
+            .as_deref()
+            .and_then(|v| Self::decode(v, snap_key.term, snap_key.idx));
+        let cleanup_done = ingested.is_some();
+        Ok((
+            SnapApplyProgress {
+                db,
+                region_id: snap_key.region_id,
+                term: snap_key.term,
+                idx: snap_key.idx,
+                ingested: Mutex::new(ingested.unwrap_or_default()),
+            },
+            cleanup_done,
+        ))
+    }
+
+    /// Marks the overlap cleanup of the region as completed by writing the
+    /// initial record.
+    pub fn record_cleanup(&self) -> Result<()> {
+        self.persist(&[])
+    }
+
+    pub fn is_ingested(&self, cf: CfName) -> bool {
+        self.ingested.lock().unwrap().iter().any(|c| c == cf)
+    }
+
+    /// Marks the SST files of `cf` as fully ingested.
+    pub fn record_ingested(&self, cf: CfName) -> Result<()> {
+        let mut ingested = self.ingested.lock().unwrap();
+        if !ingested.iter().any(|c| c == cf) {
+            ingested.push(cf.to_owned());
+        }
+        self.persist(&ingested)
+    }
+
+    /// Queues the removal of the record into the write batch that commits the
+    /// final region state.
+    pub fn clear(&self, wb: &mut EK::WriteBatch) -> Result<()> {
+        box_try!(wb.delete_cf(CF_RAFT, &keys::snapshot_apply_progress_key(self.region_id)));
+        Ok(())
+    }
+
+    fn persist(&self, ingested: &[String]) -> Result<()> {
+        let mut value = Vec::with_capacity(17 + ingested.len() * 8);
+        value.extend_from_slice(&self.term.to_be_bytes());
+        value.extend_from_slice(&self.idx.to_be_bytes());
+        value.push(ingested.len() as u8);
+        for cf in ingested {
+            value.push(cf.len() as u8);
+            value.extend_from_slice(cf.as_bytes());
+        }
+        box_try!(self.db.put_cf(
+            CF_RAFT,
+            &keys::snapshot_apply_progress_key(self.region_id),
+            &value
+        ));
+        Ok(())
+    }
+
+    fn decode(value: &[u8], term: u64, idx: u64) -> Option<Vec<String>> {
+        if value.len() < 17 {
+            return None;
+        }
+        let rec_term = u64::from_be_bytes(value[..8].try_into().unwrap());
+        let rec_idx = u64::from_be_bytes(value[8..16].try_into().unwrap());
+        if rec_term != term || rec_idx != idx {
+            return None;
+        }
+        let count = value[16] as usize;
+        let mut cfs = Vec::with_capacity(count);
+        let mut cursor = 17;
+        for _ in 0..count {
+            let len = *value.get(cursor)? as usize;
+            cursor += 1;
+            let cf = value.get(cursor..cursor + len)?;
+            cursor += len;
+            cfs.push(str::from_utf8(cf).ok()?.to_owned());
+        }
+        Some(cfs)
+    }
 }
 
 // A helper function to copy snapshot.
@@ -437,6 +575,24 @@ impl CfFile {
     }
 }
 
+/// Per-cf metadata of a snapshot, extracted from the already-loaded meta so
+/// callers can reason about the cfs (skip empty ones, account ingested bytes)
+/// without re-parsing the meta file or stat-ing the data files. See
+/// [`Snapshot::cf_files`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CfFileMeta {
+    pub cf: CfName,
+    /// Total bytes of the cf's data files.
+    pub size: u64,
+    /// Number of key-value pairs in the cf. Only known on the generating
+    /// side; the meta file does not carry it, so it is 0 for a received
+    /// snapshot.
+    pub kv_count: u64,
+    /// Whether the cf is stored as a plain file replayed through a write
+    /// batch instead of ingestible ssts, see [`plain_file_used`].
+    pub plain: bool,
+}
+
 #[derive(Default)]
 struct MetaFile {
     pub meta: Option<SnapshotMeta>,
@@ -777,7 +933,7 @@ impl Snapshot {
         )
     }
 
-    fn validate<F>(&self, post_check: F) -> RaftStoreResult<()>
+    fn validate<F>(&self, post_check: F, verify_checksum: bool) -> RaftStoreResult<()>
     where
         F: Fn(&CfFile, usize) -> RaftStoreResult<()>,
     {
@@ -790,12 +946,14 @@ impl Snapshot {
                     continue;
                 }
 
-                check_file_size_and_checksum(
-                    Path::new(&file_paths[i]),
-                    cf_file.size[i],
-                    cf_file.checksum[i],
-                    self.mgr.encryption_key_manager.as_ref(),
-                )?;
+                if verify_checksum {
+                    check_file_size_and_checksum(
+                        Path::new(&file_paths[i]),
+                        cf_file.size[i],
+                        cf_file.checksum[i],
+                        self.mgr.encryption_key_manager.as_ref(),
+                    )?;
+                }
                 post_check(cf_file, i)?;
             }
         }
@@ -852,7 +1010,7 @@ impl Snapshot {
     {
         fail_point!("snapshot_enter_do_build");
         if self.exists() {
-            match self.validate(|_, _| -> RaftStoreResult<()> { Ok(()) }) {
+            match self.validate(|_, _| -> RaftStoreResult<()> { Ok(()) }, true) {
                 Ok(()) => return Ok(()),
                 Err(e) => {
                     error!(?e;
@@ -1131,43 +1289,260 @@ impl Snapshot {
             Ok(())
         };
 
-        box_try!(self.validate(post_check));
+        // With pipelined verification the checksums are checked on a
+        // dedicated thread below, overlapped with the ingestion of the
+        // preceding cf files; only the ingestion clones are prepared here.
+        box_try!(self.validate(post_check, !options.pipelined_verification));
 
-        let abort_checker = ApplyAbortChecker(options.abort);
+        let abort_checker = ApplyAbortChecker(options.abort.clone());
         let coprocessor_host = options.coprocessor_host;
         let region = options.region;
         let key_mgr = self.mgr.encryption_key_manager.as_ref();
+        // Collect the per-cf work in apply order: plain cf files are replayed
+        // through a write batch first and are kept serial, then the SST files
+        // of the remaining column families are ingested, possibly
+        // concurrently, as files of different column families are
+        // independent.
+        let mut plain_replays: Vec<(CfName, String)> = vec![];
+        let mut sst_ingests: Vec<(CfName, String, Vec<String>)> = vec![];
+        // The path, expected size and checksum of every file in apply order,
+        // grouped per cf, for the pipelined verification.
+        let mut plain_verifies: Vec<(CfName, Vec<(String, u64, u32)>)> = vec![];
+        let mut sst_verifies: Vec<(CfName, Vec<(String, u64, u32)>)> = vec![];
         for cf_file in &mut self.cf_files {
             if cf_file.size.is_empty() {
                 // Skip empty cf file.
                 continue;
             }
             let cf = cf_file.cf;
-            if plain_file_used(cf_file.cf) {
-                let path = &cf_file.file_paths()[0];
-                let batch_size = options.write_batch_size;
-                let cb = |kv: &[(Vec<u8>, Vec<u8>)]| {
-                    coprocessor_host.post_apply_plain_kvs_from_snapshot(&region, cf, kv)
-                };
-                snap_io::apply_plain_cf_file(
-                    path,
-                    key_mgr,
-                    &abort_checker,
-                    &options.db,
-                    cf,
-                    batch_size,
-                    cb,
-                )?;
+            let file_paths = cf_file.file_paths();
+            let files = file_paths
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| cf_file.size[*i] > 0)
+                .map(|(i, p)| (p.clone(), cf_file.size[i], cf_file.checksum[i]))
+                .collect();
+            if plain_file_used(cf) {
+                plain_replays.push((cf, file_paths[0].clone()));
+                plain_verifies.push((cf, files));
             } else {
-                let path = cf_file.path.to_str().unwrap(); // path is not used at all
-                let clone_file_paths = cf_file.clone_file_paths();
-                let clone_files = clone_file_paths
-                    .iter()
-                    .map(|s| s.as_str())
-                    .collect::<Vec<&str>>();
-                snap_io::apply_sst_cf_file(clone_files.as_slice(), &options.db, cf)?;
-                coprocessor_host.post_apply_sst_from_snapshot(&region, cf, path);
+                // path is not used at all
+                let path = cf_file.path.to_str().unwrap().to_owned();
+                sst_ingests.push((cf, path, cf_file.clone_file_paths()));
+                sst_verifies.push((cf, files));
+            }
+        }
+
+        let apply_plain_cf = |cf: CfName, path: &str| -> Result<()> {
+            let batch_size = if cf == CF_LOCK {
+                options.write_batch_size_lock
+            } else {
+                options.write_batch_size
+            };
+            let cb = |kv: &[(Vec<u8>, Vec<u8>)]| {
+                coprocessor_host.post_apply_plain_kvs_from_snapshot(&region, cf, kv)
+            };
+            snap_io::apply_plain_cf_file(
+                path,
+                key_mgr,
+                &abort_checker,
+                &options.db,
+                cf,
+                batch_size,
+                cb,
+            )
+        };
+
+        fn ingest_sst_cf<EK: KvEngine>(
+            db: &EK,
+            coprocessor_host: &CoprocessorHost<EK>,
+            region: &Region,
+            progress: &SnapApplyProgress<EK>,
+            cf: CfName,
+            path: &str,
+            clone_file_paths: &[String],
+        ) -> Result<()> {
+            if progress.is_ingested(cf) {
+                info!(
+                    "skip ingesting cf already applied by a previous attempt";
+                    "region_id" => region.get_id(),
+                    "cf" => cf,
+                );
+                return Ok(());
             }
+            fail_point!("apply_snap_ingest_sst", cf == CF_WRITE, |_| {
+                Err(Error::Other(box_err!("injected ingest error")))
+            });
+            fail_point!("snap_apply_ingest_delay");
+            let clone_files = clone_file_paths
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>();
+            snap_io::apply_sst_cf_file(clone_files.as_slice(), db, cf)?;
+            coprocessor_host.post_apply_sst_from_snapshot(region, cf, path);
+            progress.record_ingested(cf)?;
+            Ok(())
+        }
+
+        let ingest_concurrency = cmp::max(options.ingest_concurrency, 1);
+        let run_ingestions = |verified: Option<&mpsc::Receiver<Result<()>>>,
+                              ingest_spent: &mut Duration|
+         -> Result<()> {
+            // Await the verification verdicts of the next `n` files in apply
+            // order. A file must never start ingesting before its own
+            // verification passed.
+            let await_verified = |n: usize| -> Result<()> {
+                if let Some(rx) = verified {
+                    for _ in 0..n {
+                        rx.recv().unwrap()?;
+                    }
+                }
+                Ok(())
+            };
+            for (cf, path) in &plain_replays {
+                await_verified(1)?;
+                check_abort(&options.abort)?;
+                let t = Instant::now();
+                apply_plain_cf(*cf, path)?;
+                *ingest_spent += t.saturating_elapsed();
+            }
+            if ingest_concurrency == 1 || sst_ingests.len() <= 1 {
+                for (cf, path, clone_file_paths) in &sst_ingests {
+                    await_verified(1)?;
+                    check_abort(&options.abort)?;
+                    let t = Instant::now();
+                    ingest_sst_cf(
+                        &options.db,
+                        &coprocessor_host,
+                        &region,
+                        &options.progress,
+                        *cf,
+                        path,
+                        clone_file_paths,
+                    )?;
+                    *ingest_spent += t.saturating_elapsed();
+                }
+            } else {
+                // Run the ingestions in waves of at most `ingest_concurrency`
+                // threads. The number of column families is small, so a full
+                // blown thread pool is not worth it.
+                let mut sst_ingests = sst_ingests.iter();
+                loop {
+                    let wave: Vec<_> = sst_ingests.by_ref().take(ingest_concurrency).collect();
+                    if wave.is_empty() {
+                        break;
+                    }
+                    // Every file of the wave must have been verified before
+                    // any of them starts ingesting.
+                    await_verified(wave.len())?;
+                    let t = Instant::now();
+                    let mut results = Vec::with_capacity(wave.len());
+                    thread::scope(|s| {
+                        let handles: Vec<_> = wave
+                            .into_iter()
+                            .map(|(cf, path, clone_file_paths)| {
+                                let abort = &options.abort;
+                                let db = &options.db;
+                                let progress = &options.progress;
+                                // Observers are `Send` but not necessarily `Sync`,
+                                // so each thread gets its own host.
+                                let host = coprocessor_host.clone();
+                                let region = region.clone();
+                                thread::Builder::new()
+                                    .name("snap-apply-ingest".to_owned())
+                                    .spawn_scoped(s, move || -> Result<()> {
+                                        check_abort(abort)?;
+                                        ingest_sst_cf(
+                                            db,
+                                            &host,
+                                            &region,
+                                            progress,
+                                            *cf,
+                                            path,
+                                            clone_file_paths,
+                                        )
+                                    })
+                                    .unwrap()
+                            })
+                            .collect();
+                        for h in handles {
+                            results.push(h.join().unwrap());
+                        }
+                    });
+                    *ingest_spent += t.saturating_elapsed();
+                    // Surface the error of the first cf in file order to keep the
+                    // serial error semantics.
+                    for res in results {
+                        res?;
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        let mut ingest_spent = Duration::default();
+        if options.pipelined_verification && !(plain_verifies.is_empty() && sst_verifies.is_empty())
+        {
+            // Two-stage pipeline: a single borrowed thread verifies the
+            // checksum of cf file N + 1 while this thread ingests cf file N.
+            // The bounded channel keeps the verifier at most one file ahead
+            // and delivers the verdicts in apply order.
+            let wall = Instant::now();
+            let verify_jobs: Vec<_> = plain_verifies.into_iter().chain(sst_verifies).collect();
+            let abort = &options.abort;
+            let (tx, rx) = mpsc::sync_channel::<Result<()>>(1);
+            let (res, verify_spent) = thread::scope(|s| {
+                let verify_jobs = &verify_jobs;
+                let verifier = thread::Builder::new()
+                    .name("snap-apply-verify".to_owned())
+                    .spawn_scoped(s, move || -> Duration {
+                        let mut spent = Duration::default();
+                        let mut verify_cf =
+                            |cf: CfName, files: &[(String, u64, u32)]| -> Result<()> {
+                                check_abort(abort)?;
+                                fail_point!("snap_apply_verify_cf", cf == CF_WRITE, |_| {
+                                    Err(Error::Other(box_err!("injected verify error")))
+                                });
+                                fail_point!("snap_apply_verify_delay");
+                                let t = Instant::now();
+                                for (path, size, checksum) in files {
+                                    box_try!(check_file_size_and_checksum(
+                                        Path::new(path),
+                                        *size,
+                                        *checksum,
+                                        key_mgr,
+                                    ));
+                                }
+                                spent += t.saturating_elapsed();
+                                Ok(())
+                            };
+                        for (cf, files) in verify_jobs {
+                            let res = verify_cf(*cf, files);
+                            let failed = res.is_err();
+                            if tx.send(res).is_err() || failed {
+                                // Either this verification failed, or the
+                                // ingestion stage hit an error and hung up;
+                                // the apply is over both ways.
+                                break;
+                            }
+                        }
+                        spent
+                    })
+                    .unwrap();
+                let res = run_ingestions(Some(&rx), &mut ingest_spent);
+                // Unhook the channel so a verifier blocked on a full slot
+                // notices an early ingestion error and stops promptly.
+                drop(rx);
+                (res, verifier.join().unwrap())
+            });
+            res?;
+            let wall = wall.saturating_elapsed();
+            SNAP_APPLY_VERIFY_DURATION_HISTOGRAM.observe(verify_spent.as_secs_f64());
+            SNAP_APPLY_INGEST_DURATION_HISTOGRAM.observe(ingest_spent.as_secs_f64());
+            SNAP_APPLY_OVERLAP_SAVED_HISTOGRAM
+                .observe((verify_spent + ingest_spent).saturating_sub(wall).as_secs_f64());
+        } else {
+            run_ingestions(None, &mut ingest_spent)?;
         }
         Ok(())
     }
@@ -1201,6 +1576,20 @@ impl Snapshot {
             .sum()
     }
 
+    /// Structured per-cf metadata in `SNAPSHOT_CFS` order, built from the
+    /// already-loaded meta. An empty cf is reported with a zero size.
+    pub fn cf_files(&self) -> Vec<CfFileMeta> {
+        self.cf_files
+            .iter()
+            .map(|cf_file| CfFileMeta {
+                cf: cf_file.cf,
+                size: cf_file.size.iter().sum(),
+                kv_count: cf_file.kv_count,
+                plain: plain_file_used(cf_file.cf),
+            })
+            .collect()
+    }
+
     pub fn total_count(&self) -> u64 {
         self.cf_files.iter().map(|cf| cf.kv_count).sum()
     }
@@ -1427,10 +1816,76 @@ pub struct SnapStats {
     pub stats: Vec<SnapshotStat>,
 }
 
+/// Apply backlog signals collected by the region worker and read by the store
+/// health inspector in the pd worker. The counters accumulate until the
+/// inspector takes the current window, see [`take_window`](Self::take_window).
+#[derive(Default)]
+pub struct ApplyBacklogStats {
+    // How many times an apply task was skipped or parked by a
+    // `handle_pending_applies` pass because of ingest stalls, failure backoff
+    // or the wall-time budget.
+    delayed_applies: AtomicU64,
+    // The longest time an apply task has been waiting since it was scheduled,
+    // in microseconds.
+    max_apply_wait_us: AtomicU64,
+    // Total wall time the region worker spent inside
+    // `handle_pending_applies`, in microseconds.
+    busy_time_us: AtomicU64,
+}
+
+impl ApplyBacklogStats {
+    pub fn record_delayed_apply(&self) {
+        self.delayed_applies.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_apply_wait(&self, wait: Duration) {
+        self.max_apply_wait_us
+            .fetch_max(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_busy_time(&self, time: Duration) {
+        self.busy_time_us
+            .fetch_add(time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Takes the signals collected since the last call and resets them.
+    /// `window` is the elapsed time the signals cover, used to turn the
+    /// accumulated busy time into a ratio.
+    pub fn take_window(&self, window: Duration) -> ApplyBacklogWindow {
+        let delayed_applies = self.delayed_applies.swap(0, Ordering::Relaxed);
+        let max_apply_wait =
+            Duration::from_micros(self.max_apply_wait_us.swap(0, Ordering::Relaxed));
+        let busy_time = Duration::from_micros(self.busy_time_us.swap(0, Ordering::Relaxed));
+        let busy_ratio = if window.is_zero() {
+            0.0
+        } else {
+            (busy_time.as_secs_f64() / window.as_secs_f64()).min(1.0)
+        };
+        ApplyBacklogWindow {
+            delayed_applies,
+            max_apply_wait,
+            busy_ratio,
+        }
+    }
+}
+
+/// One inspection window of apply backlog signals, see [`ApplyBacklogStats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ApplyBacklogWindow {
+    pub delayed_applies: u64,
+    pub max_apply_wait: Duration,
+    pub busy_ratio: f64,
+}
+
 #[derive(Clone)]
 struct SnapManagerCore {
     // directory to store snapfile.
     base: String,
+    // An alternate directory for the temporary delete SSTs built by range
+    // cleanups (see `get_temp_path_for_ingest`), e.g. on a separate scratch
+    // disk so big cleanups don't compete for space with the data disk. `None`
+    // means staging them in the snapshot directory.
+    ingest_temp_dir: Option<String>,
 
     registry: Arc<RwLock<HashMap<SnapKey, Vec<SnapEntry>>>>,
     limiter: Limiter,
@@ -1440,6 +1895,23 @@ struct SnapManagerCore {
     max_per_file_size: Arc<AtomicU64>,
     enable_multi_snapshot_files: Arc<AtomicBool>,
     stats: Arc<Mutex<Vec<SnapshotStat>>>,
+    // The total size in bytes of snapshots waiting to be applied on this store. It is
+    // reported back to snapshot senders in snap gen precheck responses.
+    pending_apply_bytes: Arc<AtomicU64>,
+    // The latest pending-apply backlog reported by other stores in their snap gen precheck
+    // responses, keyed by store id: (backlog bytes, busy flag, report time).
+    recv_backlog_feedback: Arc<Mutex<HashMap<u64, (u64, bool, Instant)>>>,
+    // Disk space reserved for incoming snapshots, keyed by snapshot key. A
+    // reservation is taken before a snapshot stream is accepted and held until
+    // the snapshot is applied or deleted.
+    reserved_space: Arc<Mutex<HashMap<SnapKey, u64>>>,
+    // Overrides the free disk space probed from the snapshot directory.
+    // `u64::MAX` means probing the filesystem; tests set a smaller value to
+    // simulate a nearly full disk.
+    disk_free_space_override: Arc<AtomicU64>,
+    // Apply backlog signals of the current inspection window, written by the
+    // region worker and consumed by the store health inspector.
+    apply_backlog_stats: Arc<ApplyBacklogStats>,
 }
 
 /// `SnapManagerCore` trace all current processing snapshots.
@@ -1469,7 +1941,7 @@ impl SnapManager {
         let path = Path::new(&self.core.base);
         if !path.exists() {
             file_system::create_dir_all(path)?;
-            return Ok(());
+            return self.init_ingest_temp_dir();
         }
         if !path.is_dir() {
             return Err(io::Error::new(
@@ -1488,9 +1960,87 @@ impl SnapManager {
             }
         }
 
+        self.init_ingest_temp_dir()?;
+        Ok(())
+    }
+
+    /// Prepares the alternate directory for staging temporary delete SSTs if
+    /// one is configured: creates it, removes temp files orphaned by a
+    /// previous run and fails fast when it is not writable instead of failing
+    /// cleanups later.
+    fn init_ingest_temp_dir(&self) -> io::Result<()> {
+        let dir = match &self.core.ingest_temp_dir {
+            Some(dir) => Path::new(dir),
+            None => return Ok(()),
+        };
+        if !dir.exists() {
+            file_system::create_dir_all(dir)?;
+        } else if !dir.is_dir() {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("{} should be a directory", dir.display()),
+            ));
+        } else {
+            for f in file_system::read_dir(dir)? {
+                let p = f?;
+                if p.file_type()?.is_file() {
+                    if let Some(s) = p.file_name().to_str() {
+                        if s.starts_with(DEL_RANGE_PREFIX) {
+                            file_system::remove_file(p.path())?;
+                        }
+                    }
+                }
+            }
+        }
+        let probe = dir.join(format!("{}_probe{}", DEL_RANGE_PREFIX, TMP_FILE_SUFFIX));
+        File::create(&probe)?;
+        file_system::remove_file(&probe)?;
         Ok(())
     }
 
+    /// Removes temporary delete SSTs orphaned in the alternate ingest
+    /// directory by cleanups that failed half way. Only files that have been
+    /// idle for a while are removed so an SST currently being built or
+    /// ingested is left alone. Called periodically by the snapshot gc.
+    pub fn gc_ingest_temp_files(&self) {
+        let dir = match &self.core.ingest_temp_dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let read_dir = match file_system::read_dir(Path::new(dir)) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                warn!("failed to list ingest temp dir"; "dir" => %dir, "err" => ?e);
+                return;
+            }
+        };
+        for p in read_dir.flatten() {
+            let file_name = p.file_name();
+            let name = match file_name.to_str() {
+                Some(n) => n,
+                None => continue,
+            };
+            if !name.starts_with(DEL_RANGE_PREFIX) {
+                continue;
+            }
+            let idle = p
+                .metadata()
+                .and_then(|m| m.modified())
+                .and_then(|t| t.elapsed().map_err(|e| io::Error::new(ErrorKind::Other, e)))
+                .map_or(false, |elapsed| elapsed > INGEST_TEMP_FILE_TTL);
+            if idle {
+                info!("removing orphaned ingest temp file"; "file" => %p.path().display());
+                if let Err(e) = file_system::remove_file(p.path()) {
+                    warn!(
+                        "failed to remove orphaned ingest temp file";
+                        "file" => %p.path().display(),
+                        "err" => ?e,
+                    );
+                }
+            }
+        }
+    }
+
     // [PerformanceCriticalPath]?? I/O involved API should be called in background
     // thread Return all snapshots which is idle not being used.
     pub fn list_idle_snap(&self) -> io::Result<Vec<(SnapKey, bool)>> {
@@ -1558,7 +2108,12 @@ impl SnapManager {
             "{}_{}{}{}",
             DEL_RANGE_PREFIX, sst_id, SST_FILE_SUFFIX, TMP_FILE_SUFFIX
         );
-        let path = PathBuf::from(&self.core.base).join(filename);
+        let base = self
+            .core
+            .ingest_temp_dir
+            .as_deref()
+            .unwrap_or(&self.core.base);
+        let path = PathBuf::from(base).join(filename);
         path.to_str().unwrap().to_string()
     }
 
@@ -1900,6 +2455,106 @@ impl SnapManager {
             .recv_concurrency_limiter
             .set_reserved_capacity(num_pending_applies)
     }
+
+    /// Sets the total size in bytes of snapshots waiting to be applied on this
+    /// store. It is reported back to snapshot senders in snap gen precheck
+    /// responses.
+    pub fn set_pending_apply_bytes(&self, bytes: u64) {
+        self.core.pending_apply_bytes.store(bytes, Ordering::SeqCst);
+    }
+
+    pub fn pending_apply_bytes(&self) -> u64 {
+        self.core.pending_apply_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Returns the apply backlog signals shared between the region worker and
+    /// the store health inspector.
+    pub fn apply_backlog_stats(&self) -> &ApplyBacklogStats {
+        &self.core.apply_backlog_stats
+    }
+
+    /// Reserves disk space for an incoming snapshot before it is accepted, so
+    /// that a snapshot which cannot fit on the disk is rejected up front and
+    /// the sender backs off, instead of the transfer failing halfway through.
+    /// The reservation is checked against the free space of the snapshot
+    /// directory minus the space already reserved for other snapshots and the
+    /// pending-apply backlog. Re-reserving with the same key only refreshes
+    /// the recorded size, so a re-sent snapshot keeps its reservation.
+    ///
+    /// The reservation is released when the snapshot is applied or deleted,
+    /// see `release_reserved_space`.
+    pub fn reserve_space(&self, key: &SnapKey, total_size: u64) -> RaftStoreResult<()> {
+        let mut reserved = self.core.reserved_space.lock().unwrap();
+        if let Some(size) = reserved.get_mut(key) {
+            *size = total_size;
+        } else {
+            let reserved_bytes: u64 = reserved.values().sum();
+            let pending_apply_bytes = self.pending_apply_bytes();
+            let free = self.core.disk_free_space();
+            if free
+                .saturating_sub(reserved_bytes)
+                .saturating_sub(pending_apply_bytes)
+                < total_size
+            {
+                return Err(RaftStoreError::Other(box_err!(
+                    "insufficient disk space for snapshot {}: size {}, free {}, reserved {}, \
+                     pending apply {}",
+                    key,
+                    total_size,
+                    free,
+                    reserved_bytes,
+                    pending_apply_bytes
+                )));
+            }
+            reserved.insert(key.clone(), total_size);
+        }
+        SNAP_RESERVED_SPACE_GAUGE.set(reserved.values().sum::<u64>() as i64);
+        Ok(())
+    }
+
+    /// Releases the disk space reserved for `key`, if any. Deleting the
+    /// snapshot through the manager releases the reservation automatically;
+    /// this is for the apply path, which consumes the snapshot without
+    /// deleting it right away.
+    pub fn release_reserved_space(&self, key: &SnapKey) {
+        self.core.release_reserved_space(key);
+    }
+
+    /// Returns the total disk space in bytes currently reserved for incoming
+    /// snapshots.
+    pub fn reserved_space_bytes(&self) -> u64 {
+        self.core.reserved_space.lock().unwrap().values().sum()
+    }
+
+    /// Records the pending-apply backlog a receiving store reported in its
+    /// snap gen precheck response.
+    pub fn record_recv_store_backlog(&self, store_id: u64, backlog_bytes: u64, busy: bool) {
+        self.core
+            .recv_backlog_feedback
+            .lock()
+            .unwrap()
+            .insert(store_id, (backlog_bytes, busy, Instant::now()));
+    }
+
+    /// Returns true if snapshot generation targeting the given store should be
+    /// held off because the store recently reported that it is busy or that
+    /// its pending-apply backlog exceeds `threshold_bytes`. A zero threshold
+    /// disables the check, and feedback older than
+    /// `SNAP_GEN_BACKLOG_FEEDBACK_TTL` is ignored.
+    pub fn snap_gen_delayed_by_backlog(&self, store_id: u64, threshold_bytes: u64) -> bool {
+        if threshold_bytes == 0 {
+            return false;
+        }
+        self.core
+            .recv_backlog_feedback
+            .lock()
+            .unwrap()
+            .get(&store_id)
+            .map_or(false, |(bytes, busy, reported_at)| {
+                reported_at.saturating_elapsed() < SNAP_GEN_BACKLOG_FEEDBACK_TTL
+                    && (*busy || *bytes >= threshold_bytes)
+            })
+    }
 }
 
 impl SnapManagerCore {
@@ -1926,6 +2581,33 @@ impl SnapManagerCore {
         Ok(total_size)
     }
 
+    /// Returns the free disk space in bytes of the snapshot directory, or the
+    /// overridden value if one is set.
+    fn disk_free_space(&self) -> u64 {
+        let overridden = self.disk_free_space_override.load(Ordering::SeqCst);
+        if overridden != u64::MAX {
+            return overridden;
+        }
+        match fs2::statvfs(&self.base) {
+            Ok(stats) => stats.available_space(),
+            Err(e) => {
+                warn!(
+                    "failed to probe the free space of the snapshot directory";
+                    "path" => %self.base,
+                    "err" => ?e,
+                );
+                u64::MAX
+            }
+        }
+    }
+
+    fn release_reserved_space(&self, key: &SnapKey) {
+        let mut reserved = self.reserved_space.lock().unwrap();
+        if reserved.remove(key).is_some() {
+            SNAP_RESERVED_SPACE_GAUGE.set(reserved.values().sum::<u64>() as i64);
+        }
+    }
+
     // Return true if it successfully delete the specified snapshot.
     fn delete_snapshot(&self, key: &SnapKey, snap: &Snapshot, check_entry: bool) -> bool {
         let registry = self.registry.rl();
@@ -1948,6 +2630,7 @@ impl SnapManagerCore {
             return false;
         }
         snap.delete();
+        self.release_reserved_space(key);
         true
     }
 
@@ -2100,6 +2783,7 @@ pub struct SnapManagerBuilder {
     enable_receive_tablet_snapshot: bool,
     key_manager: Option<Arc<DataKeyManager>>,
     concurrent_recv_snap_limit: usize,
+    ingest_temp_dir: String,
 }
 
 impl SnapManagerBuilder {
@@ -2137,6 +2821,13 @@ impl SnapManagerBuilder {
         self.key_manager = m;
         self
     }
+    /// Stages the temporary delete SSTs built by range cleanups in `dir`
+    /// instead of the snapshot directory. An empty string keeps the default.
+    #[must_use]
+    pub fn ingest_temp_dir(mut self, dir: &str) -> SnapManagerBuilder {
+        self.ingest_temp_dir = dir.to_owned();
+        self
+    }
     pub fn build<T: Into<String>>(self, path: T) -> SnapManager {
         let limiter = Limiter::new(if self.max_write_bytes_per_sec > 0 {
             self.max_write_bytes_per_sec as f64
@@ -2158,9 +2849,15 @@ impl SnapManagerBuilder {
             None
         };
 
+        let ingest_temp_dir = if self.ingest_temp_dir.is_empty() {
+            None
+        } else {
+            Some(self.ingest_temp_dir)
+        };
         let mut snapshot = SnapManager {
             core: SnapManagerCore {
                 base: path,
+                ingest_temp_dir,
                 registry: Default::default(),
                 limiter,
                 recv_concurrency_limiter: Arc::new(SnapRecvConcurrencyLimiter::new(
@@ -2174,6 +2871,11 @@ impl SnapManagerBuilder {
                     self.enable_multi_snapshot_files,
                 )),
                 stats: Default::default(),
+                pending_apply_bytes: Arc::new(AtomicU64::new(0)),
+                recv_backlog_feedback: Default::default(),
+                reserved_space: Default::default(),
+                disk_free_space_override: Arc::new(AtomicU64::new(u64::MAX)),
+                apply_backlog_stats: Arc::default(),
             },
             max_total_size: Arc::new(AtomicU64::new(max_total_size)),
             tablet_snap_manager,
@@ -2468,8 +3170,8 @@ pub mod tests {
     };
     use engine_traits::{
         Engines, ExternalSstFileInfo, KvEngine, RaftEngine, RaftLogBatch,
-        Snapshot as EngineSnapshot, SstExt, SstWriter, SstWriterBuilder, SyncMutable, ALL_CFS,
-        CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE,
+        Snapshot as EngineSnapshot, SstExt, SstWriter, SstWriterBuilder, SyncMutable, WriteBatch,
+        WriteBatchExt, ALL_CFS, CF_DEFAULT, CF_LOCK, CF_RAFT, CF_WRITE,
     };
     use kvproto::{
         encryptionpb::EncryptionMethod,
@@ -2479,7 +3181,7 @@ pub mod tests {
     use protobuf::Message;
     use raft::eraftpb::Entry;
     use tempfile::{Builder, TempDir};
-    use tikv_util::time::Limiter;
+    use tikv_util::{config::ReadableSize, time::Limiter};
 
     use super::*;
     // ApplyOptions, SnapEntry, SnapKey, SnapManager, SnapManagerBuilder, SnapManagerCore,
@@ -2653,6 +3355,7 @@ pub mod tests {
     fn create_manager_core(path: &str, max_per_file_size: u64) -> SnapManagerCore {
         SnapManagerCore {
             base: path.to_owned(),
+            ingest_temp_dir: None,
             registry: Default::default(),
             recv_concurrency_limiter: Arc::new(SnapRecvConcurrencyLimiter::new(
                 0,
@@ -2664,6 +3367,11 @@ pub mod tests {
             max_per_file_size: Arc::new(AtomicU64::new(max_per_file_size)),
             enable_multi_snapshot_files: Arc::new(AtomicBool::new(true)),
             stats: Default::default(),
+            pending_apply_bytes: Arc::new(AtomicU64::new(0)),
+            recv_backlog_feedback: Default::default(),
+            reserved_space: Default::default(),
+            disk_free_space_override: Arc::new(AtomicU64::new(u64::MAX)),
+            apply_backlog_stats: Arc::default(),
         }
     }
 
@@ -2856,8 +3564,14 @@ pub mod tests {
             region,
             abort: Arc::new(AtomicUsize::new(JOB_STATUS_RUNNING)),
             write_batch_size: TEST_WRITE_BATCH_SIZE,
+            write_batch_size_lock: TEST_WRITE_BATCH_SIZE,
             coprocessor_host: CoprocessorHost::<KvTestEngine>::default(),
             ingest_copy_symlink: false,
+            // Cover the concurrent ingestion path and the verification
+            // pipelined with it.
+            ingest_concurrency: 2,
+            pipelined_verification: true,
+            progress: Arc::new(SnapApplyProgress::resume(dst_db.clone(), &key).unwrap().0),
         };
         // Verify the snapshot applying is ok.
         s4.apply(options).unwrap();
@@ -2872,6 +3586,111 @@ pub mod tests {
         assert_eq_db(&db, &dst_db);
     }
 
+    #[test]
+    fn test_cf_files_metadata_and_empty_cf_skip() {
+        let region_id = 1;
+        let region = gen_test_region(region_id, 1, 1);
+        let db_dir = Builder::new()
+            .prefix("test-cf-files-meta-db")
+            .tempdir()
+            .unwrap();
+        // Only the default cf has data, so the lock and write cfs produce
+        // empty snapshot files.
+        let db: KvTestEngine = open_test_empty_db(db_dir.path(), None, None).unwrap();
+        let mut p = Peer::default();
+        p.set_store_id(TEST_STORE_ID);
+        p.set_id(1);
+        db.put_msg_cf(CF_DEFAULT, &keys::data_key(TEST_KEY), &p)
+            .unwrap();
+        let snapshot = db.snapshot(None);
+
+        let dir = Builder::new()
+            .prefix("test-cf-files-meta")
+            .tempdir()
+            .unwrap();
+        let key = SnapKey::new(region_id, 1, 1);
+        let mgr_core = create_manager_core(dir.path().to_str().unwrap(), u64::MAX);
+        let mut s1 = Snapshot::new_for_building(dir.path(), &key, &mgr_core).unwrap();
+        let mut snap_data = s1
+            .build(&db, &snapshot, &region, true, false, UnixSecs::now())
+            .unwrap();
+
+        let metas = s1.cf_files();
+        assert_eq!(
+            metas.iter().map(|m| m.cf).collect::<Vec<_>>(),
+            SNAPSHOT_CFS
+        );
+        for meta in &metas {
+            assert_eq!(meta.plain, plain_file_used(meta.cf));
+            if meta.cf == CF_DEFAULT {
+                assert_eq!(meta.kv_count, 1);
+                assert!(meta.size > 0);
+            } else {
+                assert_eq!(meta.kv_count, 0);
+                assert_eq!(meta.size, 0);
+            }
+        }
+        assert_eq!(metas.iter().map(|m| m.size).sum::<u64>(), s1.total_size());
+        // The reported sizes match the generated files on disk.
+        for cf_file in &s1.cf_files {
+            let on_disk: u64 = cf_file
+                .file_paths()
+                .iter()
+                .map(|p| file_system::metadata(p).map_or(0, |m| m.len()))
+                .sum();
+            let reported = metas.iter().find(|m| m.cf == cf_file.cf).unwrap().size;
+            assert_eq!(reported, on_disk);
+        }
+
+        // Receive the snapshot and verify the metadata rebuilt from the meta
+        // file reports the same sizes (kv counts are not carried by it).
+        let mut s2 = Snapshot::new_for_sending(dir.path(), &key, &mgr_core).unwrap();
+        let mut s3 =
+            Snapshot::new_for_receiving(dir.path(), &key, &mgr_core, snap_data.take_meta())
+                .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+        let mut s4 = Snapshot::new_for_applying(dir.path(), &key, &mgr_core).unwrap();
+        assert_eq!(
+            s4.cf_files()
+                .iter()
+                .map(|m| (m.cf, m.size, m.plain))
+                .collect::<Vec<_>>(),
+            metas
+                .iter()
+                .map(|m| (m.cf, m.size, m.plain))
+                .collect::<Vec<_>>()
+        );
+
+        let dst_db_dir = Builder::new()
+            .prefix("test-cf-files-meta-dst")
+            .tempdir()
+            .unwrap();
+        let dst_db: KvTestEngine =
+            open_test_empty_db(dst_db_dir.path(), None, None).unwrap();
+        let progress = Arc::new(SnapApplyProgress::resume(dst_db.clone(), &key).unwrap().0);
+        let options = ApplyOptions {
+            db: dst_db.clone(),
+            region,
+            abort: Arc::new(AtomicUsize::new(JOB_STATUS_RUNNING)),
+            write_batch_size: TEST_WRITE_BATCH_SIZE,
+            write_batch_size_lock: TEST_WRITE_BATCH_SIZE,
+            coprocessor_host: CoprocessorHost::<KvTestEngine>::default(),
+            ingest_copy_symlink: false,
+            ingest_concurrency: 1,
+            pipelined_verification: false,
+            progress: progress.clone(),
+        };
+        s4.apply(options).unwrap();
+
+        // Only the non-empty default cf was ingested; the empty write cf saw
+        // no ingest call at all. The empty lock cf is plain and simply has no
+        // file to replay.
+        assert!(progress.is_ingested(CF_DEFAULT));
+        assert!(!progress.is_ingested(CF_WRITE));
+        assert_eq_db(&db, &dst_db);
+    }
+
     #[test]
     fn test_empty_snap_validation() {
         test_snap_validation(open_test_empty_db, u64::MAX);
@@ -3065,16 +3884,59 @@ pub mod tests {
             .unwrap();
         let dst_db: KvTestEngine = open_test_empty_db(dst_db_dir.path(), None, None).unwrap();
         let options = ApplyOptions {
-            db: dst_db,
+            db: dst_db.clone(),
             region,
             abort: Arc::new(AtomicUsize::new(JOB_STATUS_RUNNING)),
             write_batch_size: TEST_WRITE_BATCH_SIZE,
+            write_batch_size_lock: TEST_WRITE_BATCH_SIZE,
             coprocessor_host: CoprocessorHost::<KvTestEngine>::default(),
             ingest_copy_symlink: false,
+            ingest_concurrency: 1,
+            // Corruption must already be caught by the up-front serial
+            // verification.
+            pipelined_verification: false,
+            progress: Arc::new(SnapApplyProgress::resume(dst_db, &key).unwrap().0),
         };
         s2.apply(options).unwrap_err();
     }
 
+    #[test]
+    fn test_snap_apply_progress_record() {
+        let db_dir = Builder::new()
+            .prefix("test-snap-apply-progress-db")
+            .tempdir()
+            .unwrap();
+        let db: KvTestEngine = open_test_empty_db(db_dir.path(), None, None).unwrap();
+        let key = SnapKey::new(1, 3, 7);
+
+        // Nothing recorded yet.
+        let (progress, cleanup_done) = SnapApplyProgress::resume(db.clone(), &key).unwrap();
+        assert!(!cleanup_done);
+        assert!(!progress.is_ingested(CF_DEFAULT));
+
+        progress.record_cleanup().unwrap();
+        progress.record_ingested(CF_DEFAULT).unwrap();
+
+        // A resumed apply of the same snapshot sees the progress.
+        let (progress, cleanup_done) = SnapApplyProgress::resume(db.clone(), &key).unwrap();
+        assert!(cleanup_done);
+        assert!(progress.is_ingested(CF_DEFAULT));
+        assert!(!progress.is_ingested(CF_WRITE));
+
+        // A record of a different snapshot is stale and ignored.
+        let stale_key = SnapKey::new(1, 3, 8);
+        let (_, cleanup_done) = SnapApplyProgress::resume(db.clone(), &stale_key).unwrap();
+        assert!(!cleanup_done);
+
+        // Clearing removes the record.
+        let mut wb = db.write_batch();
+        progress.clear(&mut wb).unwrap();
+        wb.write().unwrap();
+        let (progress, cleanup_done) = SnapApplyProgress::resume(db, &key).unwrap();
+        assert!(!cleanup_done);
+        assert!(!progress.is_ingested(CF_DEFAULT));
+    }
+
     #[test]
     fn test_snap_corruption_on_meta_file() {
         let region_id = 1;
@@ -3396,6 +4258,56 @@ pub mod tests {
         assert!(!file_system::file_exists(&sst_path));
     }
 
+    #[test]
+    fn test_ingest_temp_dir() {
+        let snap_dir = Builder::new()
+            .prefix("test_ingest_temp_dir_snap")
+            .tempdir()
+            .unwrap();
+        let ingest_dir = Builder::new()
+            .prefix("test_ingest_temp_dir_ingest")
+            .tempdir()
+            .unwrap();
+        let ingest_path = ingest_dir.path().join("sub");
+        let build_mgr = || {
+            SnapManagerBuilder::default()
+                .ingest_temp_dir(ingest_path.to_str().unwrap())
+                .build(snap_dir.path().to_str().unwrap())
+        };
+
+        // Init creates the alternate directory and temp paths are routed there.
+        let mgr = build_mgr();
+        mgr.init().unwrap();
+        assert!(ingest_path.is_dir());
+        let sst_path = mgr.get_temp_path_for_ingest();
+        assert!(Path::new(&sst_path).starts_with(&ingest_path));
+
+        // Orphaned delete SSTs from a previous run are removed on init, other
+        // files are left alone.
+        File::create(&sst_path).unwrap();
+        let other = ingest_path.join("other.file");
+        File::create(&other).unwrap();
+        drop(mgr);
+        let mgr = build_mgr();
+        mgr.init().unwrap();
+        assert!(!file_system::file_exists(&sst_path));
+        assert!(file_system::file_exists(&other));
+
+        // The periodic gc keeps fresh files, they may still be in use.
+        let sst_path = mgr.get_temp_path_for_ingest();
+        File::create(&sst_path).unwrap();
+        mgr.gc_ingest_temp_files();
+        assert!(file_system::file_exists(&sst_path));
+
+        // Init fails when the configured path is not a directory.
+        let file_path = ingest_dir.path().join("file");
+        File::create(&file_path).unwrap();
+        let mgr = SnapManagerBuilder::default()
+            .ingest_temp_dir(file_path.to_str().unwrap())
+            .build(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap_err();
+    }
+
     #[test]
     fn test_snapshot_stats() {
         let snap_dir = Builder::new()
@@ -3595,4 +4507,110 @@ pub mod tests {
         assert!(limiter.try_recv(1));
         assert!(limiter.timestamps.lock().unwrap().capacity() == 0);
     }
+
+    #[test]
+    fn test_snap_gen_backlog_feedback() {
+        let snap_dir = Builder::new()
+            .prefix("test_snap_gen_backlog_feedback")
+            .tempdir()
+            .unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let threshold = ReadableSize::mb(100).0;
+
+        // No feedback yet, nothing is delayed.
+        assert!(!mgr.snap_gen_delayed_by_backlog(1, threshold));
+
+        // Store 1 is backlogged, store 2 is idle and store 3 reports busy
+        // without a backlog.
+        mgr.record_recv_store_backlog(1, ReadableSize::mb(200).0, false);
+        mgr.record_recv_store_backlog(2, ReadableSize::mb(1).0, false);
+        mgr.record_recv_store_backlog(3, 0, true);
+        assert!(mgr.snap_gen_delayed_by_backlog(1, threshold));
+        assert!(!mgr.snap_gen_delayed_by_backlog(2, threshold));
+        assert!(mgr.snap_gen_delayed_by_backlog(3, threshold));
+
+        // A zero threshold disables the backpressure.
+        assert!(!mgr.snap_gen_delayed_by_backlog(1, 0));
+
+        // The backlog reported to senders follows what the region worker sets.
+        assert_eq!(mgr.pending_apply_bytes(), 0);
+        mgr.set_pending_apply_bytes(42);
+        assert_eq!(mgr.pending_apply_bytes(), 42);
+        mgr.set_pending_apply_bytes(0);
+        assert_eq!(mgr.pending_apply_bytes(), 0);
+    }
+
+    #[test]
+    fn test_reserve_space() {
+        let snap_dir = Builder::new()
+            .prefix("test_reserve_space")
+            .tempdir()
+            .unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        // Pretend the snap dir disk only has 100 bytes left.
+        mgr.core
+            .disk_free_space_override
+            .store(100, Ordering::SeqCst);
+
+        let key1 = SnapKey::new(1, 1, 1);
+        let key2 = SnapKey::new(2, 1, 1);
+
+        // The first snapshot fits and holds its reservation, so a second one
+        // that no longer fits is rejected.
+        mgr.reserve_space(&key1, 60).unwrap();
+        assert_eq!(mgr.reserved_space_bytes(), 60);
+        mgr.reserve_space(&key2, 60).unwrap_err();
+        // Re-reserving the same key is idempotent and just refreshes the size,
+        // so a re-sent snapshot is not rejected by its own reservation.
+        mgr.reserve_space(&key1, 70).unwrap();
+        assert_eq!(mgr.reserved_space_bytes(), 70);
+
+        // Pending-apply bytes count against the free space as well.
+        mgr.set_pending_apply_bytes(20);
+        mgr.reserve_space(&key2, 20).unwrap_err();
+        mgr.set_pending_apply_bytes(0);
+        mgr.reserve_space(&key2, 20).unwrap();
+        assert_eq!(mgr.reserved_space_bytes(), 90);
+
+        // Releasing frees the space for new snapshots; releasing an unknown
+        // key is a no-op.
+        mgr.release_reserved_space(&key1);
+        assert_eq!(mgr.reserved_space_bytes(), 20);
+        mgr.release_reserved_space(&key1);
+        assert_eq!(mgr.reserved_space_bytes(), 20);
+        mgr.reserve_space(&key1, 80).unwrap();
+        assert_eq!(mgr.reserved_space_bytes(), 100);
+    }
+
+    #[test]
+    fn test_apply_backlog_stats() {
+        let stats = ApplyBacklogStats::default();
+        // An empty window reports nothing.
+        let window = stats.take_window(Duration::from_secs(1));
+        assert_eq!(window, ApplyBacklogWindow::default());
+
+        stats.record_delayed_apply();
+        stats.record_delayed_apply();
+        // The max wait keeps the largest observation.
+        stats.observe_apply_wait(Duration::from_millis(100));
+        stats.observe_apply_wait(Duration::from_millis(300));
+        stats.observe_apply_wait(Duration::from_millis(200));
+        stats.add_busy_time(Duration::from_millis(200));
+        stats.add_busy_time(Duration::from_millis(300));
+
+        let window = stats.take_window(Duration::from_secs(1));
+        assert_eq!(window.delayed_applies, 2);
+        assert_eq!(window.max_apply_wait, Duration::from_millis(300));
+        assert!((window.busy_ratio - 0.5).abs() < f64::EPSILON);
+
+        // Taking the window resets the counters.
+        let window = stats.take_window(Duration::from_secs(1));
+        assert_eq!(window, ApplyBacklogWindow::default());
+
+        // The busy ratio is clamped and a zero window does not divide by zero.
+        stats.add_busy_time(Duration::from_secs(2));
+        assert_eq!(stats.take_window(Duration::from_secs(1)).busy_ratio, 1.0);
+        stats.add_busy_time(Duration::from_secs(1));
+        assert_eq!(stats.take_window(Duration::ZERO).busy_ratio, 0.0);
+    }
 }
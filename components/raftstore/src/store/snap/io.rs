@@ -23,6 +23,9 @@ use tikv_util::{
 };
 
 use super::{CfFile, Error, IO_LIMITER_CHUNK_SIZE};
+use crate::store::metrics::{
+    SNAPSHOT_APPLY_PLAIN_CF_BATCHES_HISTOGRAM, SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM,
+};
 
 /// Used to check a procedure is stale or not.
 pub trait StaleDetector {
@@ -273,6 +276,7 @@ where
     // times.
     let mut batch = Vec::with_capacity(1024);
     let mut batch_data_size = 0;
+    let mut batch_count = 0;
 
     loop {
         if stale_detector.is_stale() {
@@ -282,7 +286,10 @@ where
         if key.is_empty() {
             if !batch.is_empty() {
                 box_try!(write_to_db(&mut batch));
+                batch_count += 1;
+                SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM.observe(batch_data_size as f64);
             }
+            SNAPSHOT_APPLY_PLAIN_CF_BATCHES_HISTOGRAM.observe(batch_count as f64);
             return Ok(());
         }
         let value = box_try!(decoder.decode_compact_bytes());
@@ -290,6 +297,8 @@ where
         batch.push((key, value));
         if batch_data_size >= batch_size {
             box_try!(write_to_db(&mut batch));
+            batch_count += 1;
+            SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM.observe(batch_data_size as f64);
             batch_data_size = 0;
         }
     }
@@ -347,7 +356,7 @@ mod tests {
     use std::{collections::HashMap, path::PathBuf};
 
     use engine_test::kv::KvTestEngine;
-    use engine_traits::CF_DEFAULT;
+    use engine_traits::{CF_DEFAULT, CF_LOCK};
     use tempfile::Builder;
     use tikv_util::time::Limiter;
 
@@ -439,6 +448,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_apply_plain_cf_file_batch_size() {
+        let dir = Builder::new().prefix("test-apply-batch-db").tempdir().unwrap();
+        let db: KvTestEngine = open_test_db_with_100keys(dir.path(), None, None).unwrap();
+        let snap = db.snapshot(None);
+        let snap_cf_dir = Builder::new().prefix("test-apply-batch-cf").tempdir().unwrap();
+        let mut cf_file = CfFile {
+            cf: CF_LOCK,
+            path: PathBuf::from(snap_cf_dir.path().to_str().unwrap()),
+            file_prefix: "test_plain_sst".to_string(),
+            file_suffix: SST_FILE_SUFFIX.to_string(),
+            ..Default::default()
+        };
+        let stats = build_plain_cf_file::<KvTestEngine>(
+            &mut cf_file,
+            None,
+            &snap,
+            &keys::data_key(b"a"),
+            &keys::data_end_key(b"z"),
+        )
+        .unwrap();
+        assert_eq!(stats.key_count, 100);
+
+        let detector = TestStaleDetector {};
+        let tmp_file_path = &cf_file.tmp_file_paths()[0];
+        let apply_with_batch_size = |batch_size: usize| {
+            let dir = Builder::new()
+                .prefix("test-apply-batch-apply")
+                .tempdir()
+                .unwrap();
+            let db1: KvTestEngine = open_test_empty_db(dir.path(), None, None).unwrap();
+            let batches_before = SNAPSHOT_APPLY_PLAIN_CF_BATCHES_HISTOGRAM.get_sample_sum();
+            let flushes_before = SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM.get_sample_count();
+            apply_plain_cf_file(
+                tmp_file_path,
+                None,
+                &detector,
+                &db1,
+                CF_LOCK,
+                batch_size,
+                |_| {},
+            )
+            .unwrap();
+            let batches =
+                (SNAPSHOT_APPLY_PLAIN_CF_BATCHES_HISTOGRAM.get_sample_sum() - batches_before) as u64;
+            // Every flushed batch contributes one sample to the bytes histogram.
+            assert_eq!(
+                SNAPSHOT_APPLY_PLAIN_CF_BATCH_BYTES_HISTOGRAM.get_sample_count() - flushes_before,
+                batches
+            );
+            let mut kvs = Vec::new();
+            db1.scan(
+                CF_LOCK,
+                &keys::data_key(b"a"),
+                &keys::data_end_key(b"z"),
+                false,
+                |k, v| {
+                    kvs.push((k.to_owned(), v.to_owned()));
+                    Ok(true)
+                },
+            )
+            .unwrap();
+            (batches, kvs)
+        };
+
+        // Every key exceeds a 1 byte threshold, so each one flushes its own
+        // batch.
+        let (batches, small_kvs) = apply_with_batch_size(1);
+        assert_eq!(batches, 100);
+        assert_eq!(small_kvs.len(), 100);
+        // A threshold larger than the file leaves a single final flush.
+        let (batches, large_kvs) = apply_with_batch_size(usize::MAX);
+        assert_eq!(batches, 1);
+        assert_eq!(small_kvs, large_kvs);
+    }
+
     #[test]
     fn test_cf_build_and_apply_sst_files() {
         let db_creaters = &[open_test_empty_db, open_test_db_with_100keys];
@@ -165,6 +165,21 @@ where
     }
 }
 
+impl<EK> StoreRouter<EK> for mpsc::SyncSender<StoreMsg<EK>>
+where
+    EK: KvEngine,
+{
+    fn send(&self, msg: StoreMsg<EK>) -> Result<()> {
+        match self.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                Err(Error::Transport(DiscardReason::Disconnected))
+            }
+            Err(mpsc::TrySendError::Full(_)) => Err(Error::Transport(DiscardReason::Full)),
+        }
+    }
+}
+
 impl<S: Snapshot> ProposalRouter<S> for mpsc::SyncSender<RaftCommand<S>> {
     fn send(&self, cmd: RaftCommand<S>) -> std::result::Result<(), TrySendError<RaftCommand<S>>> {
         match self.try_send(cmd) {
@@ -42,7 +42,13 @@ use time::{Duration, Timespec};
 use tokio::sync::Notify;
 use txn_types::WriteBatchFlags;
 
-use super::{metrics::PEER_ADMIN_CMD_COUNTER_VEC, peer_storage, Config};
+use super::{
+    metrics::{
+        PEER_ADMIN_CMD_COUNTER_VEC, PRE_TRANSFER_LEADER_INFLIGHT_GAUGE,
+        PRE_TRANSFER_LEADER_THROTTLE_COUNTER,
+    },
+    peer_storage, Config,
+};
 use crate::{
     coprocessor::CoprocessorHost,
     store::{simple_write::SimpleWriteReqDecoder, snap::SNAPSHOT_VERSION},
@@ -398,6 +404,27 @@ pub fn encode_start_ts_into_flag_data(header: &mut RaftRequestHeader, start_ts:
     header.set_flag_data(data.into());
 }
 
+/// Encodes a trace id into the header of an admin command so that it survives
+/// the raft proposal and can be attached to logs on both the propose and the
+/// apply path. The id is carried in `flag_data`, which is unused for admin
+/// commands, guarded by `WriteBatchFlags::TRACE_ID_IN_FLAG_DATA`.
+pub fn encode_trace_id_into_flag_data(header: &mut RaftRequestHeader, trace_id: u64) {
+    header.set_flags(header.get_flags() | WriteBatchFlags::TRACE_ID_IN_FLAG_DATA.bits());
+    let mut data = [0u8; 8];
+    (&mut data[..]).encode_u64(trace_id).unwrap();
+    header.set_flag_data(data.into());
+}
+
+/// Extracts the trace id carried by the header of an admin command, if any.
+pub fn admin_trace_id(header: &RaftRequestHeader) -> Option<u64> {
+    if !WriteBatchFlags::from_bits_truncate(header.get_flags())
+        .contains(WriteBatchFlags::TRACE_ID_IN_FLAG_DATA)
+    {
+        return None;
+    }
+    decode_u64(&mut header.get_flag_data()).ok()
+}
+
 pub fn is_region_epoch_equal(
     from_epoch: &metapb::RegionEpoch,
     current_epoch: &metapb::RegionEpoch,
@@ -1243,6 +1270,62 @@ impl Display for MsgType<'_> {
     }
 }
 
+/// How long an in-flight pre-transfer entry is kept before it is considered
+/// abandoned and reclaimed.
+const PRE_TRANSFER_LEADER_TTL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Store-level accounting of in-flight pre-transfer-leader operations, shared
+/// by all the peers of a store through the poll context.
+///
+/// A transfer leader command does not propose the admin command right away:
+/// the leader first sends a `MsgTransferLeader` to the transferee, which may
+/// warm up its entry cache before acking, and only the ack triggers the real
+/// `TRANSFER_LEADER_PROPOSAL`. Each pending round trip pins follower
+/// resources, so under a PD balance storm a store can accumulate hundreds of
+/// them. The tracker counts the transfers that are between
+/// `propose_transfer_leader` and the final proposal so that new transfer
+/// requests can be rejected with a busy error once
+/// `Config::max_inflight_pre_transfer_leader` is reached, leaving the retry
+/// to PD instead of queueing more warmups. Entries of transfers that are
+/// abandoned without an ack are reclaimed after `PRE_TRANSFER_LEADER_TTL`.
+#[derive(Clone, Default)]
+pub struct PreTransferLeaderTracker {
+    inflight: Arc<Mutex<HashMap<u64, Instant>>>,
+}
+
+impl PreTransferLeaderTracker {
+    /// Tries to account a new pre-transfer of the region. Returns false if
+    /// the cap is reached, a zero `cap` means no limit.
+    ///
+    /// A pre-transfer of a region that is already tracked only refreshes its
+    /// entry, so a transfer command retried by PD is neither rejected nor
+    /// double counted.
+    pub fn try_begin(&self, region_id: u64, cap: usize) -> bool {
+        let mut inflight = self.inflight.lock().unwrap();
+        inflight.retain(|_, entered| entered.saturating_elapsed() < PRE_TRANSFER_LEADER_TTL);
+        if let Some(entered) = inflight.get_mut(&region_id) {
+            *entered = Instant::now_coarse();
+        } else if cap > 0 && inflight.len() >= cap {
+            PRE_TRANSFER_LEADER_INFLIGHT_GAUGE.set(inflight.len() as i64);
+            PRE_TRANSFER_LEADER_THROTTLE_COUNTER.inc();
+            return false;
+        } else {
+            inflight.insert(region_id, Instant::now_coarse());
+        }
+        PRE_TRANSFER_LEADER_INFLIGHT_GAUGE.set(inflight.len() as i64);
+        true
+    }
+
+    /// Ends the pre-transfer of the region. Called when the real transfer
+    /// leader proposal is made or the transfer is abandoned; a region that is
+    /// not tracked, e.g. because its entry already timed out, is ignored.
+    pub fn finish(&self, region_id: u64) {
+        let mut inflight = self.inflight.lock().unwrap();
+        inflight.remove(&region_id);
+        PRE_TRANSFER_LEADER_INFLIGHT_GAUGE.set(inflight.len() as i64);
+    }
+}
+
 #[derive(Clone)]
 pub struct RegionReadProgressRegistry {
     registry: Arc<Mutex<HashMap<u64, Arc<RegionReadProgress>>>>,
@@ -1889,6 +1972,46 @@ mod tests {
     use super::*;
     use crate::store::peer_storage;
 
+    #[test]
+    fn test_admin_trace_id() {
+        let mut header = RaftRequestHeader::default();
+        assert_eq!(admin_trace_id(&header), None);
+
+        header.set_flags(WriteBatchFlags::PRE_FLUSH_FINISHED.bits());
+        encode_trace_id_into_flag_data(&mut header, 42);
+        // The marker flag must not clobber the other flags.
+        assert!(
+            WriteBatchFlags::from_bits_truncate(header.get_flags())
+                .contains(WriteBatchFlags::PRE_FLUSH_FINISHED)
+        );
+        assert_eq!(admin_trace_id(&header), Some(42));
+
+        // Flag data without the marker flag is not interpreted as a trace id.
+        let mut header = RaftRequestHeader::default();
+        encode_start_ts_into_flag_data(&mut header, 42);
+        assert_eq!(admin_trace_id(&header), None);
+    }
+
+    #[test]
+    fn test_pre_transfer_leader_tracker() {
+        let tracker = PreTransferLeaderTracker::default();
+        assert!(tracker.try_begin(1, 2));
+        assert!(tracker.try_begin(2, 2));
+        // The cap is reached, a new region is rejected.
+        assert!(!tracker.try_begin(3, 2));
+        // A region that is already tracked is only refreshed.
+        assert!(tracker.try_begin(1, 2));
+        // Zero cap means no limit.
+        assert!(tracker.try_begin(3, 0));
+        tracker.finish(3);
+        // Finishing frees a slot for a new region.
+        tracker.finish(1);
+        assert!(tracker.try_begin(3, 2));
+        // Finishing an unknown region is a no-op.
+        tracker.finish(100);
+        assert!(!tracker.try_begin(4, 2));
+    }
+
     #[test]
     fn test_lease() {
         #[inline]
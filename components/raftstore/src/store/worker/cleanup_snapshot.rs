@@ -145,6 +145,7 @@ where
                         "store_id" => self.store_id,
                     );
                 }
+                self.snap_mgr.gc_ingest_temp_files();
 
                 let msg = StoreMsg::GcSnapshotFinish;
                 if let Err(e) = StoreRouter::send(&self.router, msg) {
@@ -4,13 +4,20 @@ use std::{
     collections::VecDeque,
     error::Error as StdError,
     fmt::{self, Display, Formatter},
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
     time::Duration,
 };
 
-use engine_traits::{KvEngine, ManualCompactionOptions, RangeStats, CF_LOCK, CF_WRITE};
+use collections::HashSet;
+use engine_traits::{
+    CompactFilesInRangeOptions, KvEngine, ManualCompactionOptions, RangeStats, CF_LOCK, CF_WRITE,
+};
 use fail::fail_point;
 use futures_util::compat::Future01CompatExt;
+use lazy_static::lazy_static;
 use thiserror::Error;
 use tikv_util::{
     box_try, config::Tracker, debug, error, info, time::Instant, timer::GLOBAL_TIMER_HANDLE, warn,
@@ -19,7 +26,8 @@ use tikv_util::{
 use yatp::Remote;
 
 use super::metrics::{
-    COMPACT_RANGE_CF, FULL_COMPACT, FULL_COMPACT_INCREMENTAL, FULL_COMPACT_PAUSE,
+    COMPACT_BOUNDARY_COUNTER, COMPACT_RANGE_CF, FULL_COMPACT, FULL_COMPACT_INCREMENTAL,
+    FULL_COMPACT_PAUSE,
 };
 use crate::store::Config;
 
@@ -27,6 +35,13 @@ type Key = Vec<u8>;
 
 static FULL_COMPACTION_IN_PROCESS: AtomicBool = AtomicBool::new(false);
 
+lazy_static! {
+    // Boundary keys that have a pending `CompactBoundary` task on this store.
+    // Used to dedup the tasks scheduled by back-to-back splits at the same
+    // key; an entry is removed once its compaction finishes.
+    static ref PENDING_BOUNDARY_COMPACTIONS: Mutex<HashSet<Key>> = Mutex::new(HashSet::default());
+}
+
 pub enum Task {
     PeriodicFullCompact {
         // Ranges, or empty if we wish to compact the entire store
@@ -49,6 +64,38 @@ pub enum Task {
         // The minimum RocksDB tombstones/duplicate versions a range that need compacting has
         compact_threshold: CompactThreshold,
     },
+
+    CompactBoundary {
+        // Column families to compact around the boundary.
+        cf_names: Vec<String>,
+        // A new region boundary introduced by a split. Only the SST files
+        // straddling it are compacted, so that the compaction guard cuts
+        // them along the boundary.
+        boundary_key: Key,
+    },
+}
+
+impl Task {
+    /// Creates a task to compact the SST files straddling a new region
+    /// boundary, or `None` if such a task is already pending, so that
+    /// back-to-back splits at the same key schedule at most one compaction
+    /// per store.
+    pub fn compact_boundary(cf_names: Vec<String>, boundary_key: Key) -> Option<Task> {
+        if !PENDING_BOUNDARY_COMPACTIONS
+            .lock()
+            .unwrap()
+            .insert(boundary_key.clone())
+        {
+            return None;
+        }
+        COMPACT_BOUNDARY_COUNTER
+            .with_label_values(&["scheduled"])
+            .inc();
+        Some(Task::CompactBoundary {
+            cf_names,
+            boundary_key,
+        })
+    }
 }
 
 type CompactPredicateFn = Box<dyn Fn() -> bool + Send + Sync>;
@@ -203,6 +250,14 @@ impl Display for Task {
                     &compact_threshold.redundant_rows_percent_threshold,
                 )
                 .finish(),
+            Task::CompactBoundary {
+                ref cf_names,
+                ref boundary_key,
+            } => f
+                .debug_struct("CompactBoundary")
+                .field("cf_names", cf_names)
+                .field("boundary_key", &log_wrappers::Value::key(boundary_key))
+                .finish(),
         }
     }
 }
@@ -344,6 +399,44 @@ where
         );
         Ok(())
     }
+
+    /// Compacts the SST files straddling a new region boundary so that the
+    /// compaction guard repartitions them along the boundary. Files fully on
+    /// either side of the boundary are left alone, which keeps the compaction
+    /// small, and a single subcompaction is used to keep its impact on
+    /// foreground traffic low.
+    pub fn compact_boundary(
+        &mut self,
+        cf_names: &[String],
+        boundary_key: &[u8],
+    ) -> Result<(), Error> {
+        let timer = Instant::now();
+        for cf in cf_names {
+            box_try!(self.engine.compact_files_in_range_cf_opt(
+                cf,
+                Some(boundary_key),
+                Some(boundary_key),
+                None,
+                CompactFilesInRangeOptions {
+                    // Right after a split the straddling files usually sit at
+                    // the bottommost level already, collect them as well.
+                    include_output_level: true,
+                    bottommost_only: false,
+                    // A single straddling file still needs to be rewritten to
+                    // get cut at the boundary.
+                    rewrite_single_file: true,
+                    max_subcompactions: Some(1),
+                }
+            ));
+        }
+        info!(
+            "compact boundary finished";
+            "boundary_key" => log_wrappers::Value::key(boundary_key),
+            "cf_names" => ?cf_names,
+            "time_takes" => ?timer.saturating_elapsed(),
+        );
+        Ok(())
+    }
 }
 
 impl<E> Runnable for Runner<E>
@@ -433,6 +526,26 @@ where
                 }
                 Err(e) => warn!("check ranges need reclaim failed"; "err" => %e),
             },
+            Task::CompactBoundary {
+                cf_names,
+                boundary_key,
+            } => {
+                let res = self.compact_boundary(&cf_names, &boundary_key);
+                PENDING_BOUNDARY_COMPACTIONS
+                    .lock()
+                    .unwrap()
+                    .remove(&boundary_key);
+                match res {
+                    Ok(()) => COMPACT_BOUNDARY_COUNTER
+                        .with_label_values(&["completed"])
+                        .inc(),
+                    Err(e) => error!(
+                        "execute compact boundary failed";
+                        "boundary_key" => log_wrappers::Value::key(&boundary_key),
+                        "err" => %e,
+                    ),
+                }
+            }
         }
     }
 }
@@ -506,8 +619,12 @@ fn collect_ranges_need_compact(
 
 #[cfg(test)]
 mod tests {
-    use std::{thread::sleep, time::Duration};
+    use std::{sync::Arc, thread::sleep, time::Duration};
 
+    use engine_rocks::{
+        util::new_engine_opt as new_rocks_engine_opt, RocksCfOptions, RocksDbOptions, RocksEngine,
+        RocksSstPartitionerFactory,
+    };
     use engine_test::{
         ctor::{CfOptions, DbOptions},
         kv::{new_engine, new_engine_opt, KvTestEngine},
@@ -517,11 +634,13 @@ mod tests {
         CF_RAFT, CF_WRITE,
     };
     use keys::data_key;
+    use kvproto::metapb::Region;
     use tempfile::Builder;
     use tikv_util::yatp_pool::{DefaultTicker, FuturePool, YatpPoolBuilder};
     use txn_types::{Key, TimeStamp, Write, WriteType};
 
     use super::*;
+    use crate::{coprocessor::RegionInfoProvider, store::CompactionGuardGeneratorFactory};
 
     fn make_compact_runner<E>(engine: E) -> (FuturePool, Runner<E>)
     where
@@ -645,6 +764,89 @@ mod tests {
         assert!(old_sst_files_size > new_sst_files_size);
     }
 
+    // A region info provider whose region set can be swapped, to simulate a
+    // split happening between compactions.
+    #[derive(Clone)]
+    struct SplitMockRegionInfoProvider(Arc<Mutex<Vec<Region>>>);
+
+    impl RegionInfoProvider for SplitMockRegionInfoProvider {
+        fn get_regions_in_range(&self, _: &[u8], _: &[u8]) -> crate::Result<Vec<Region>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    fn new_region(id: u64, start_key: &[u8], end_key: &[u8]) -> Region {
+        Region {
+            id,
+            start_key: start_key.to_vec(),
+            end_key: end_key.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn straddling_files(db: &RocksEngine, boundary: &[u8]) -> usize {
+        let db = db.as_inner();
+        let cf = db.cf_handle(CF_DEFAULT).unwrap();
+        db.get_column_family_meta_data(cf)
+            .get_levels()
+            .into_iter()
+            .flat_map(|level| level.get_files())
+            .filter(|f| f.get_smallestkey() < boundary && boundary <= f.get_largestkey())
+            .count()
+    }
+
+    #[test]
+    fn test_compact_boundary() {
+        let tmp_dir = Builder::new()
+            .prefix("test_compact_boundary")
+            .tempdir()
+            .unwrap();
+        // The parent region, yet to be split at "b".
+        let regions = Arc::new(Mutex::new(vec![new_region(1, b"a", b"c")]));
+
+        let mut cf_opts = RocksCfOptions::default();
+        cf_opts.set_disable_auto_compactions(true);
+        cf_opts.set_sst_partitioner_factory(RocksSstPartitionerFactory(
+            CompactionGuardGeneratorFactory::new(
+                CF_DEFAULT,
+                SplitMockRegionInfoProvider(regions.clone()),
+                1,       // min_output_file_size
+                1 << 30, // max_compaction_size
+            )
+            .unwrap(),
+        ));
+        let db = new_rocks_engine_opt(
+            tmp_dir.path().to_str().unwrap(),
+            RocksDbOptions::default(),
+            vec![(CF_DEFAULT, cf_opts)],
+        )
+        .unwrap();
+        let (_pool, mut runner) = make_compact_runner(db.clone());
+
+        // Lay all the data down in a single bottommost level file straddling
+        // what will become the new boundary, as it is before the split.
+        let value = vec![b'v'; 1024];
+        for key in [b"za1", b"za2", b"zb1", b"zb2"] {
+            db.put(key, &value).unwrap();
+        }
+        db.flush_cf(CF_DEFAULT, true).unwrap();
+        db.compact_files_in_range(None, None, None).unwrap();
+        assert_eq!(straddling_files(&db, b"zb"), 1);
+
+        // The split replaces the parent with two regions sharing the file.
+        *regions.lock().unwrap() = vec![new_region(2, b"a", b"b"), new_region(1, b"b", b"c")];
+
+        let task = Task::compact_boundary(vec![String::from(CF_DEFAULT)], b"zb".to_vec()).unwrap();
+        // Another task for the same boundary is deduped while one is pending.
+        assert!(Task::compact_boundary(vec![String::from(CF_DEFAULT)], b"zb".to_vec()).is_none());
+        runner.run(task);
+
+        // The compaction guard has cut the rewritten file at the new boundary.
+        assert_eq!(straddling_files(&db, b"zb"), 0);
+        // The boundary is no longer pending once its compaction finished.
+        assert!(Task::compact_boundary(vec![String::from(CF_DEFAULT)], b"zb".to_vec()).is_some());
+    }
+
     fn mvcc_put(db: &KvTestEngine, k: &[u8], v: &[u8], start_ts: TimeStamp, commit_ts: TimeStamp) {
         let k = Key::from_encoded(data_key(k)).append_ts(commit_ts);
         let w = Write::new(WriteType::Put, start_ts, Some(v.to_vec()));
@@ -31,6 +31,8 @@ make_auto_flush_static_metric! {
         delay,
         ignore,
         ingest_delay,
+        backoff_delay,
+        budget_delay,
     }
 
     pub struct SnapCounter: LocalIntCounter {
@@ -79,6 +81,16 @@ make_static_metric! {
     pub struct ClearOverlapRegionDuration : Histogram {
         "type" => ClearOverlapRegionType,
     }
+
+    pub label_enum SnapApplyPhase {
+        cleanup,
+        ingest,
+        write_state,
+    }
+
+    pub struct SnapApplyPhaseHistogram : Histogram {
+        "phase" => SnapApplyPhase,
+    }
 }
 
 pub struct LocalReadMetrics {
@@ -168,11 +180,53 @@ lazy_static! {
         exponential_buckets(0.00001, 2.0, 26).unwrap()
     )
     .unwrap();
+    pub static ref SNAP_APPLY_INGESTED_BYTES_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_snapshot_apply_ingested_bytes",
+        "Total bytes of snapshot cf files applied, per cf, taken from the snapshot meta.",
+        &["cf"]
+    )
+    .unwrap();
     pub static ref SNAP_PENDING_APPLIES_GAUGE: IntGauge = register_int_gauge!(
         "tikv_raftstore_snapshot_pending_applies",
         "Total number of snapshots that are waiting to be applied",
     )
     .unwrap();
+    pub static ref SNAP_APPLY_CACHE_EVICT_UNCONFIRMED_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_snapshot_apply_cache_evict_unconfirmed_total",
+        "Total number of snapshot applies that proceeded before the range cache engine \
+         confirmed the eviction of the overlapping range",
+    )
+    .unwrap();
+    pub static ref SNAP_APPLY_CALLBACK_PANIC_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_snapshot_apply_callback_panic_total",
+        "Total number of panics caught from `on_finish` callbacks of snapshot apply tasks",
+    )
+    .unwrap();
+    pub static ref SNAP_APPLY_PRESSURE_CANCELLED_COUNTER: IntCounter = register_int_counter!(
+        "tikv_raftstore_snapshot_apply_pressure_cancelled_total",
+        "Total number of queued snapshot applies cancelled because the snapshot directory \
+         usage exceeded the configured high watermark",
+    )
+    .unwrap();
+    pub static ref SNAP_APPLY_PRESSURE_FREED_BYTES: IntCounter = register_int_counter!(
+        "tikv_raftstore_snapshot_apply_pressure_freed_bytes",
+        "Total bytes of snapshot files deleted by cancelling queued applies under snapshot \
+         directory pressure",
+    )
+    .unwrap();
+    pub static ref REGION_WORKER_RECOVERED_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_region_worker_recovered_errors_total",
+        "Total number of engine errors in the region worker that were recovered by retrying or \
+         failing the affected task instead of aborting the process",
+        &["site"]
+    )
+    .unwrap();
+    pub static ref SNAP_APPLIES_PER_PASS_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_snapshot_applies_per_pass",
+        "Bucketed histogram of the number of snapshots applied in one pending-apply pass of the region worker",
+        exponential_buckets(1.0, 2.0, 10).unwrap()
+    )
+    .unwrap();
     pub static ref CHECK_SPILT_HISTOGRAM: Histogram = register_histogram!(
         "tikv_raftstore_check_split_duration_seconds",
         "Bucketed histogram of raftstore split check duration",
@@ -185,6 +239,12 @@ lazy_static! {
         &["cf"]
     )
     .unwrap();
+    pub static ref COMPACT_BOUNDARY_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_compact_boundary_total",
+        "Total number of boundary compactions after region splits.",
+        &["type"]
+    )
+    .unwrap();
     pub static ref FULL_COMPACT: Histogram = register_histogram!(
         "tikv_storage_full_compact_duration_seconds",
         "Bucketed histogram of full compaction for the storage."
@@ -221,6 +281,62 @@ lazy_static! {
         &["type"]
     )
     .unwrap();
+    pub static ref CLEAN_OVERLAP_TOTAL_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_clean_overlap_total_duration_seconds",
+        "Bucketed histogram of the end-to-end duration of cleaning overlap ranges, including the DeleteFiles pre-pass and the drain of pending ranges.",
+        exponential_buckets(0.0005, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref CLEAN_STALE_TICK_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_clean_stale_tick_duration_seconds",
+        "Bucketed histogram of the duration of a whole clean stale ranges tick.",
+        exponential_buckets(0.0005, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref CLEAN_LOCK_KEYS_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_raftstore_clean_lock_cf_keys",
+        "Bucketed histogram of lock cf keys deleted per region-worker cleanup.",
+        exponential_buckets(1.0, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref CLEAN_RANGES_PROCESSED_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_clean_ranges_processed_total",
+        "Total number of ranges processed by region-worker cleanups, by deletion phase.",
+        &["phase"]
+    )
+    .unwrap();
+    pub static ref PENDING_DELETE_ESTIMATED_BYTES_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_pending_delete_estimated_bytes",
+        "Estimated total bytes covered by pending delete ranges, summed from the size hints carried by destroy tasks."
+    )
+    .unwrap();
+    pub static ref DELETE_FILES_RECLAIMED_BYTES: IntCounter = register_int_counter!(
+        "tikv_raftstore_delete_files_reclaimed_bytes",
+        "Estimated total bytes reclaimed by the DeleteFiles passes of region-worker cleanups, from the size hints carried by destroy tasks."
+    )
+    .unwrap();
+    pub static ref WRITE_AMP_WINDOW_BYTES_VEC: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_raftstore_write_amp_window_bytes",
+        "Byte deltas of the last write-amplification reporter window, correlating region-worker ingest/cleanup activity with the engine compaction flow.",
+        &["source"]
+    )
+    .unwrap();
+    pub static ref WRITE_AMP_WINDOW_TOMBSTONES_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_write_amp_window_tombstones",
+        "Number of point tombstones written by range deletions in the last write-amplification reporter window."
+    )
+    .unwrap();
+    pub static ref RECONCILE_APPLYING_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_raftstore_reconcile_applying_total",
+        "Total number of applying regions processed by the region-worker reconciliation, by outcome.",
+        &["outcome"]
+    )
+    .unwrap();
+    pub static ref STUCK_APPLYING_REGIONS_GAUGE: IntGauge = register_int_gauge!(
+        "tikv_raftstore_stuck_applying_regions",
+        "Number of regions found in PeerState::Applying without a usable snapshot by the last reconciliation pass."
+    )
+    .unwrap();
     pub static ref LOCAL_READ_REJECT_VEC: IntCounterVec = register_int_counter_vec!(
         "tikv_raftstore_local_read_reject_total",
         "Total number of rejections from the local reader.",
@@ -294,4 +410,12 @@ lazy_static! {
         &["type"],
         exponential_buckets(0.005, 2.0, 20).unwrap()
     ).unwrap();
+
+    pub static ref SNAP_APPLY_PHASE_HISTOGRAM: SnapApplyPhaseHistogram = register_static_histogram_vec!(
+        SnapApplyPhaseHistogram,
+        "tikv_raftstore_snapshot_apply_phase_duration_seconds",
+        "Bucketed histogram of each phase duration of snapshot apply.",
+        &["phase"],
+        exponential_buckets(0.0005, 2.0, 20).unwrap()
+    ).unwrap();
 }
@@ -43,7 +43,11 @@ pub use self::{
         BatchComponent as RaftStoreBatchComponent, BatchComponent, Runner as RefreshConfigRunner,
         Task as RefreshConfigTask, WriterContoller,
     },
-    region::{Runner as RegionRunner, Task as RegionTask},
+    region::{
+        ApplyHistoryCallback, ApplyPriority, CleanRegionCallback, CleanRegionOutcome,
+        CleanRegionRangeResult, RegionStorageCleaner, Runner as RegionRunner,
+        SnapApplyHistoryEntry, TabletRegistryCleaner, Task as RegionTask,
+    },
     split_check::{
         Bucket, BucketRange, BucketStatsInfo, KeyEntry, Runner as SplitCheckRunner,
         Task as SplitCheckTask,
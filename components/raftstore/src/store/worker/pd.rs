@@ -856,6 +856,9 @@ where
 
     health_reporter: RaftstoreReporter,
     health_controller: HealthController,
+    // Weight of the apply backlog signals in the slow score, zero keeps them
+    // report-only. See `Config::apply_backlog_slow_score_weight`.
+    apply_backlog_slow_score_weight: f64,
 
     coprocessor_host: CoprocessorHost<EK>,
     causal_ts_provider: Option<Arc<CausalTsProviderImpl>>, // used for rawkv apiv2
@@ -944,6 +947,7 @@ where
             remote,
             health_reporter,
             health_controller,
+            apply_backlog_slow_score_weight: cfg.apply_backlog_slow_score_weight,
             coprocessor_host,
             causal_ts_provider,
             grpc_service_manager,
@@ -2104,6 +2108,8 @@ where
                         approximate_keys,
                         last_report_ts,
                         cpu_usage,
+                        // Raftstore v1 has no pre-flush phase to report.
+                        pending_admin: None,
                     },
                     hb_task.replication_status,
                 )
@@ -2163,6 +2169,23 @@ where
     T: PdClient + 'static,
 {
     fn on_timeout(&mut self) {
+        // Fold the apply backlog signals collected by the region worker over
+        // the last window into the health report. With a zero weight (the
+        // default) they are only exported as metrics.
+        let backlog = self
+            .snap_mgr
+            .apply_backlog_stats()
+            .take_window(self.health_reporter.get_tick_interval());
+        STORE_APPLY_BACKLOG_DELAYED_GAUGE.set(backlog.delayed_applies as i64);
+        STORE_APPLY_BACKLOG_MAX_WAIT_GAUGE
+            .set(tikv_util::time::duration_to_sec(backlog.max_apply_wait));
+        STORE_APPLY_BACKLOG_BUSY_RATIO_GAUGE.set(backlog.busy_ratio);
+        self.health_reporter.record_apply_backlog(
+            backlog.busy_ratio,
+            backlog.max_apply_wait,
+            self.apply_backlog_slow_score_weight,
+        );
+
         let slow_score_tick_result = self.health_reporter.tick(self.store_stat.maybe_busy());
         if let Some(score) = slow_score_tick_result.updated_score {
             STORE_SLOW_SCORE_GAUGE.set(score);
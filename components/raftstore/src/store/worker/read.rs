@@ -2603,6 +2603,7 @@ mod tests {
         let snap_ctx = SnapshotContext {
             read_ts: 15,
             range: None,
+            required_apply_index: None,
         };
 
         let s = get_snapshot(Some(snap_ctx.clone()), &mut reader, cmd.clone(), &rx);
@@ -2683,6 +2684,7 @@ mod tests {
         let snap_ctx = SnapshotContext {
             read_ts: 15,
             range: None,
+            required_apply_index: None,
         };
         reader.propose_raft_command(Some(snap_ctx), read_id, task.request, task.callback);
         assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
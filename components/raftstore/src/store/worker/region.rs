@@ -7,8 +7,9 @@ use std::{
         VecDeque,
     },
     fmt::{self, Display, Formatter},
+    path::PathBuf,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         mpsc::SyncSender,
         Arc, Mutex,
     },
@@ -18,18 +19,25 @@ use std::{
 
 use collections::HashMap;
 use engine_traits::{
-    CacheRange, DeleteStrategy, KvEngine, Mutable, Range, WriteBatch, WriteOptions, CF_LOCK,
-    CF_RAFT,
+    CacheRange, DeleteStrategy, KvEngine, Mutable, Range, TabletContext, TabletRegistry,
+    WriteBatch, WriteOptions, CF_LOCK, CF_RAFT,
 };
 use fail::fail_point;
 use file_system::{IoType, WithIoType};
-use kvproto::raft_serverpb::{PeerState, RaftApplyState, RegionLocalState};
+use kvproto::{
+    metapb,
+    raft_serverpb::{PeerState, RaftApplyState, RegionLocalState, StoreIdent},
+};
 use pd_client::PdClient;
+use protobuf::Message;
 use raft::eraftpb::Snapshot as RaftSnapshot;
+use serde::Serialize;
+use smallvec::SmallVec;
 use tikv_util::{
     box_err, box_try,
     config::VersionTrack,
     defer, error, info,
+    lru::LruCache,
     time::{Instant, UnixSecs},
     warn,
     worker::{Runnable, RunnableWithTimer},
@@ -38,25 +46,184 @@ use tikv_util::{
 
 use super::metrics::*;
 use crate::{
-    coprocessor::CoprocessorHost,
+    coprocessor::{ApplyCleanupInfo, CoprocessorHost},
     store::{
         self, check_abort,
         peer_storage::{
             JOB_STATUS_CANCELLED, JOB_STATUS_CANCELLING, JOB_STATUS_FAILED, JOB_STATUS_FINISHED,
             JOB_STATUS_PENDING, JOB_STATUS_RUNNING,
         },
-        snap::{plain_file_used, Error, Result, SNAPSHOT_CFS},
-        transport::CasualRouter,
-        ApplyOptions, CasualMessage, Config, SnapEntry, SnapError, SnapKey, SnapManager,
+        snap::{plain_file_used, Error, Result, SnapApplyProgress, Snapshot, SNAPSHOT_CFS},
+        transport::{CasualRouter, StoreRouter},
+        ApplyOptions, CasualMessage, Config, SnapEntry, SnapError, SnapKey, SnapManager, StoreMsg,
     },
 };
 
 const CLEANUP_MAX_REGION_COUNT: usize = 64;
+
+// Lock cf cleanup scans at most this many keys per pass and removes them with
+// one bulk deletion, yielding between passes, so a range stuffed with orphan
+// locks (e.g. left by failed large transactions) is deleted in bounded steps
+// instead of monopolizing the cleaner with one huge scan.
+const LOCK_CF_SCAN_BATCH_KEYS: usize = 1024;
+// Byte budget of one deletion write batch in the lock cf cleanup.
+const LOCK_CF_DELETE_BATCH_BYTES: usize = 64 * 1024;
+// A probed free-space value of the data disk is trusted for this long, so a
+// burst of range cleanups doesn't run one statvfs per range.
+const DISK_FREE_SPACE_PROBE_TTL: Duration = Duration::from_secs(5);
+
+// Cap of the snapshot-apply history kept per region for diagnostics, see
+// `Task::QueryApplyHistory`.
+const APPLY_HISTORY_PER_REGION: usize = 8;
+// How many regions may keep an apply history at once. The least recently
+// applied regions are evicted beyond this, which also ages out the entries of
+// destroyed peers.
+const APPLY_HISTORY_REGION_CAP: usize = 256;
 const SNAP_GENERATOR_MAX_POOL_SIZE: usize = 16;
 
+// If applying the snapshot of one region fails this many times in a row
+// within `APPLY_FAILURE_WINDOW`, its subsequent apply tasks are delayed by
+// `APPLY_FAILURE_BACKOFF` so that a region failing in a tight loop cannot
+// starve the applies of other regions.
+const APPLY_FAILURE_BACKOFF_THRESHOLD: u64 = 3;
+const APPLY_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const APPLY_FAILURE_BACKOFF: Duration = Duration::from_secs(5);
+
+// After this many consecutive urgent applies one normal apply is processed,
+// so a long burst of urgent snapshots (e.g. a whole store going through
+// unsafe recovery) cannot starve the normal queue indefinitely.
+const URGENT_APPLIES_PER_NORMAL: usize = 4;
+
+// How long apply may wait for the range cache engine to confirm that the
+// eviction of the overlapping range has taken effect before ingesting a
+// snapshot. Proceeding without confirmation risks serving stale cached data,
+// so hitting the timeout is reported loudly.
+const CACHE_EVICT_BEFORE_INGEST_TIMEOUT: Duration = Duration::from_secs(5);
+// The `on_finish` callback of an apply task runs on the region worker thread,
+// so a slow callback delays every other pending apply. Anything above this is
+// logged as a misuse.
+const APPLY_CALLBACK_SLOW_THRESHOLD: Duration = Duration::from_millis(100);
+
+// How often the write-amplification reporter publishes a window. A longer
+// window only coarsens the correlation between worker activity and the
+// compaction flow, so it doesn't have to line up with any engine interval.
+const WRITE_AMP_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+// How many region states one reconciliation batch inspects before yielding
+// back to the worker, so a store with many regions does not stall applies
+// behind one long CF_RAFT scan.
+const RECONCILE_APPLYING_BATCH_REGIONS: usize = 128;
+
 const TIFLASH: &str = "tiflash";
 const ENGINE: &str = "engine";
 
+/// One record of the snapshot-apply history of a region, see
+/// `Task::QueryApplyHistory`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SnapApplyHistoryEntry {
+    pub peer_id: u64,
+    /// Term and index identifying the applied snapshot, see `SnapKey`.
+    pub term: u64,
+    pub index: u64,
+    /// Unix timestamp in seconds of when the apply started executing.
+    pub start_unix_secs: u64,
+    /// How long the task waited in the pending-applies queue before it was
+    /// executed.
+    pub wait_duration_ms: u64,
+    /// How long the apply itself took.
+    pub apply_duration_ms: u64,
+    /// Total size of the snapshot in bytes, if it was known when the apply
+    /// was queued.
+    pub ingested_bytes: Option<u64>,
+    /// `success`, `abort`, or `fail: <error>`.
+    pub result: String,
+}
+
+/// Callback of `Task::QueryApplyHistory`, newtyped so `Task` can keep
+/// deriving `Debug`.
+pub struct ApplyHistoryCallback(pub Box<dyn FnOnce(Vec<SnapApplyHistoryEntry>) + Send>);
+
+impl fmt::Debug for ApplyHistoryCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ApplyHistoryCallback")
+    }
+}
+
+/// The outcome of one snapshot apply, handed to the `on_finish` callback of
+/// `Task::Apply`.
+#[derive(Clone, Debug)]
+pub struct ApplyOutcome {
+    pub snap_key: SnapKey,
+    /// `success`, `abort`, or `fail: <error>`, the same encoding as
+    /// `SnapApplyHistoryEntry::result`.
+    pub result: String,
+    /// How long the apply itself took, excluding the time queued.
+    pub apply_duration: Duration,
+    /// Total size of the snapshot in bytes, if it was known when the apply
+    /// was queued.
+    pub ingested_bytes: Option<u64>,
+}
+
+/// Callback of `Task::Apply`, newtyped so `Task` can keep deriving `Debug`.
+/// It runs on the region worker after the apply status has been published and
+/// before the peer is notified, so callers needing follow-up logic in that
+/// window (e.g. verifying keys right after the ingest) do not have to route
+/// through the casual message and back. It must return quickly, see
+/// `APPLY_CALLBACK_SLOW_THRESHOLD`.
+pub struct ApplyCallback(pub Box<dyn FnOnce(&ApplyOutcome) + Send>);
+
+impl fmt::Debug for ApplyCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ApplyCallback")
+    }
+}
+
+/// Why a pending range was or was not cleaned by `Task::CleanRegion`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CleanRegionOutcome {
+    /// The data of the range has been deleted and its entry removed from the
+    /// pending delete ranges.
+    Cleaned,
+    /// An engine snapshot taken before the range was registered is still
+    /// open, so a reader may still be on the range. Pass `force` to clean it
+    /// anyway; the forced cleanup deletes by key and skips the physical file
+    /// deletions, so open snapshots stay consistent.
+    NotStale,
+    /// A queued or running snapshot apply overlaps the range. Refused even
+    /// with `force`, otherwise the cleanup could remove files the apply just
+    /// ingested.
+    OverlappingApply,
+    /// Deleting the data failed. The entry stays pending and is retried by
+    /// the stale-range ticks.
+    Failed(String),
+}
+
+/// Per-range outcome of `Task::CleanRegion`.
+#[derive(Clone, Debug)]
+pub struct CleanRegionRangeResult {
+    pub start_key: Vec<u8>,
+    pub end_key: Vec<u8>,
+    pub outcome: CleanRegionOutcome,
+}
+
+/// Callback of `Task::CleanRegion`, newtyped so `Task` can keep deriving
+/// `Debug`.
+pub struct CleanRegionCallback(pub Box<dyn FnOnce(Vec<CleanRegionRangeResult>) + Send>);
+
+impl fmt::Debug for CleanRegionCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "CleanRegionCallback")
+    }
+}
+
+/// The scheduling class of a queued `Task::Apply`. Urgent applies are
+/// processed ahead of normal ones, see [`Runner::handle_pending_applies`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyPriority {
+    Normal,
+    Urgent,
+}
+
 /// Region related task
 #[derive(Debug)]
 pub enum Task<S> {
@@ -75,6 +242,21 @@ pub enum Task<S> {
         status: Arc<AtomicUsize>,
         peer_id: u64,
         create_time: Instant,
+        /// Set to `Urgent` by the peer FSM when the apply is part of unsafe
+        /// recovery or the region has no serving leader, so a snapshot that
+        /// is the only path back to quorum does not wait behind routine
+        /// rebalance applies.
+        priority: ApplyPriority,
+        /// The region local state and the raft apply state, read together in
+        /// one engine read when the region worker first processes the task.
+        /// Both the pre-apply phase and the apply itself use these copies
+        /// instead of re-reading CF_RAFT per phase. Schedulers always pass
+        /// `None`; the worker fills them in.
+        region_state: Option<RegionLocalState>,
+        apply_state: Option<RaftApplyState>,
+        /// Runs on the region worker once the apply has finished, aborted or
+        /// failed, before the peer is notified. See `ApplyCallback`.
+        on_finish: Option<ApplyCallback>,
     },
     /// Destroy data between [start_key, end_key).
     ///
@@ -84,7 +266,40 @@ pub enum Task<S> {
         region_id: u64,
         start_key: Vec<u8>,
         end_key: Vec<u8>,
+        /// The estimated size of the data in the range, if the scheduler knows
+        /// it. Only used to report the byte backlog of pending deletions.
+        size_hint: Option<u64>,
+    },
+    /// Queries the recent snapshot-apply history of a region, newest entry
+    /// last. A region without recorded applies yields an empty history.
+    QueryApplyHistory {
+        region_id: u64,
+        cb: ApplyHistoryCallback,
+    },
+    /// Immediately cleans up all pending delete ranges registered under the
+    /// given region id instead of waiting for the stale-range ticks. See
+    /// `CleanRegionOutcome` for when a range is refused.
+    CleanRegion {
+        region_id: u64,
+        force: bool,
+        cb: CleanRegionCallback,
+    },
+    /// Notifies the worker that a compaction covering `[start_key, end_key]`
+    /// of `cf` has finished, so pending delete ranges the compaction may
+    /// have emptied can be retired without issuing deletions. See
+    /// `RegionCleaner::on_compaction_finished`.
+    CompactionFinished {
+        cf: String,
+        start_key: Vec<u8>,
+        end_key: Vec<u8>,
     },
+    /// Scans CF_RAFT for regions recorded as `PeerState::Applying` without a
+    /// queued apply task, e.g. because a crash lost the task between writing
+    /// the state and applying the snapshot. Regions whose received snapshot
+    /// is still usable get the apply re-scheduled; the rest are reported to
+    /// their peers so a fresh snapshot can be requested. The scan is bounded
+    /// per timer tick, see `Runner::handle_reconcile_applying`.
+    ReconcileApplying,
 }
 
 impl<S> Task<S> {
@@ -93,6 +308,7 @@ impl<S> Task<S> {
             region_id,
             start_key,
             end_key,
+            size_hint: None,
         }
     }
 }
@@ -106,6 +322,7 @@ impl<S> Display for Task<S> {
                 region_id,
                 ref start_key,
                 ref end_key,
+                ..
             } => write!(
                 f,
                 "Destroy {} [{}, {})",
@@ -113,6 +330,24 @@ impl<S> Display for Task<S> {
                 log_wrappers::Value::key(start_key),
                 log_wrappers::Value::key(end_key)
             ),
+            Task::QueryApplyHistory { region_id, .. } => {
+                write!(f, "Query snap apply history for {}", region_id)
+            }
+            Task::CleanRegion {
+                region_id, force, ..
+            } => write!(f, "Clean region {} (force: {})", region_id, force),
+            Task::CompactionFinished {
+                ref cf,
+                ref start_key,
+                ref end_key,
+            } => write!(
+                f,
+                "Compaction finished on {} [{}, {}]",
+                cf,
+                log_wrappers::Value::key(start_key),
+                log_wrappers::Value::key(end_key)
+            ),
+            Task::ReconcileApplying => write!(f, "Reconcile applying regions"),
         }
     }
 }
@@ -127,6 +362,21 @@ struct StalePeerInfo {
     // reading on this peer anymore. So we can safely call `delete_files_in_range`,
     // which may break the consistency of snapshot, of this peer range.
     pub stale_sequence: u64,
+    // The estimated size of the data in the range, carried over from the
+    // destroy task if it provided one. Only used for metrics.
+    pub size_hint: Option<u64>,
+    // Set when a finished compaction fully covered the range and the
+    // range-properties estimate showed no remaining data, see
+    // `RegionCleaner::on_compaction_finished`. The next cleanup pass verifies
+    // the mark with a bounded seek and retires the entry without issuing any
+    // deletion.
+    pub probably_empty: bool,
+    // When the destroy was registered. The region layout may change while
+    // the entry waits (the region re-created here with other boundaries, a
+    // neighbor grown into the range), so cleanup re-validates the range
+    // against the current region metadata and uses this to report how long
+    // a dropped entry had been pending.
+    pub destroy_time: UnixSecs,
 }
 
 /// A structure records all ranges to be deleted with some delay.
@@ -135,6 +385,11 @@ struct StalePeerInfo {
 #[derive(Clone, Default)]
 struct PendingDeleteRanges {
     ranges: BTreeMap<Vec<u8>, StalePeerInfo>, // start_key -> StalePeerInfo
+    // A secondary index over `ranges` ordered by stale sequence, so that
+    // `stale_ranges` only visits the sequences that are actually stale
+    // instead of scanning the whole map on every cleanup tick. All mutations
+    // go through `insert` and `unregister` to keep the two in sync.
+    stale_index: BTreeMap<u64, SmallVec<[Vec<u8>; 2]>>, // stale_sequence -> start_keys
 }
 
 impl PendingDeleteRanges {
@@ -182,15 +437,27 @@ impl PendingDeleteRanges {
         let ranges = self.find_overlap_ranges(start_key, end_key);
 
         for (_, s_key, ..) in &ranges {
-            self.ranges.remove(s_key).unwrap();
+            self.unregister(s_key).unwrap();
         }
         ranges
     }
 
+    /// Removes the range starting at `start_key` from both the range map and
+    /// the stale-sequence index.
+    fn unregister(&mut self, start_key: &[u8]) -> Option<StalePeerInfo> {
+        let info = self.ranges.remove(start_key)?;
+        let keys = self.stale_index.get_mut(&info.stale_sequence).unwrap();
+        let pos = keys.iter().position(|k| k == start_key).unwrap();
+        keys.swap_remove(pos);
+        if keys.is_empty() {
+            self.stale_index.remove(&info.stale_sequence);
+        }
+        Some(info)
+    }
+
     /// Removes and returns the peer info with the `start_key`.
     fn remove(&mut self, start_key: &[u8]) -> Option<(u64, Vec<u8>, Vec<u8>)> {
-        self.ranges
-            .remove(start_key)
+        self.unregister(start_key)
             .map(|peer_info| (peer_info.region_id, start_key.to_owned(), peer_info.end_key))
     }
 
@@ -204,6 +471,7 @@ impl PendingDeleteRanges {
         start_key: Vec<u8>,
         end_key: Vec<u8>,
         stale_sequence: u64,
+        size_hint: Option<u64>,
     ) {
         if !self.find_overlap_ranges(&start_key, &end_key).is_empty() {
             panic!(
@@ -217,15 +485,64 @@ impl PendingDeleteRanges {
             region_id,
             end_key,
             stale_sequence,
+            size_hint,
+            probably_empty: false,
+            destroy_time: UnixSecs::now(),
         };
+        self.stale_index
+            .entry(stale_sequence)
+            .or_default()
+            .push(start_key.clone());
         self.ranges.insert(start_key, info);
     }
 
-    /// Gets all stale ranges info.
+    /// Gets all stale ranges info, ordered by start key.
     pub fn stale_ranges(&self, oldest_sequence: u64) -> impl Iterator<Item = (u64, &[u8], &[u8])> {
+        let mut start_keys: Vec<_> = self
+            .stale_index
+            .range(..oldest_sequence)
+            .flat_map(|(_, keys)| keys.iter())
+            .collect();
+        start_keys.sort_unstable();
+        start_keys.into_iter().map(move |start_key| {
+            let info = &self.ranges[start_key];
+            (
+                info.region_id,
+                start_key.as_slice(),
+                info.end_key.as_slice(),
+            )
+        })
+    }
+
+    /// Gets the ranges fully contained in `[start_key, end_key]` that are not
+    /// marked probably empty yet. The end key is inclusive, matching the
+    /// largest key of a finished compaction.
+    fn contained_unmarked_ranges(
+        &self,
+        start_key: &[u8],
+        end_key: &[u8],
+    ) -> Vec<(u64, Vec<u8>, Vec<u8>)> {
+        self.ranges
+            .range((Included(start_key.to_vec()), Unbounded))
+            .take_while(|(s, _)| s.as_slice() <= end_key)
+            .filter(|(_, info)| !info.probably_empty && info.end_key.as_slice() <= end_key)
+            .map(|(s, info)| (info.region_id, s.clone(), info.end_key.clone()))
+            .collect()
+    }
+
+    /// Sets or clears the probably-empty mark of the range starting at
+    /// `start_key`. A no-op if the range is no longer registered.
+    fn set_probably_empty(&mut self, start_key: &[u8], probably_empty: bool) {
+        if let Some(info) = self.ranges.get_mut(start_key) {
+            info.probably_empty = probably_empty;
+        }
+    }
+
+    /// Gets all ranges marked probably empty, ordered by start key.
+    fn probably_empty_ranges(&self) -> impl Iterator<Item = (u64, &[u8], &[u8])> {
         self.ranges
             .iter()
-            .filter(move |&(_, info)| info.stale_sequence < oldest_sequence)
+            .filter(|(_, info)| info.probably_empty)
             .map(|(start_key, info)| {
                 (
                     info.region_id,
@@ -235,9 +552,30 @@ impl PendingDeleteRanges {
             })
     }
 
+    /// Gets all pending ranges registered under the given region id, together
+    /// with their stale sequences.
+    pub fn region_ranges(&self, region_id: u64) -> impl Iterator<Item = (&[u8], &[u8], u64)> {
+        self.ranges
+            .iter()
+            .filter(move |&(_, info)| info.region_id == region_id)
+            .map(|(start_key, info)| {
+                (
+                    start_key.as_slice(),
+                    info.end_key.as_slice(),
+                    info.stale_sequence,
+                )
+            })
+    }
+
     pub fn len(&self) -> usize {
         self.ranges.len()
     }
+
+    /// Sums the size hints of all pending ranges. Ranges whose destroy task
+    /// carried no hint contribute zero.
+    pub fn estimated_bytes(&self) -> u64 {
+        self.ranges.values().filter_map(|info| info.size_hint).sum()
+    }
 }
 
 struct SnapGenContext<EK, R> {
@@ -342,11 +680,67 @@ where
     }
 }
 
+/// A cached probe of the available space of the disk holding `path`. Probed
+/// values are trusted for [`DISK_FREE_SPACE_PROBE_TTL`] before the filesystem
+/// is asked again.
+struct DiskFreeSpaceProbe {
+    path: String,
+    // The last probed available space and when it was probed.
+    cache: Mutex<Option<(Instant, u64)>>,
+    // `u64::MAX` means probing the filesystem; tests set a smaller value to
+    // fake the free space of the data disk.
+    free_space_override: AtomicU64,
+}
+
+impl DiskFreeSpaceProbe {
+    fn new(path: String) -> DiskFreeSpaceProbe {
+        DiskFreeSpaceProbe {
+            path,
+            cache: Mutex::new(None),
+            free_space_override: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Returns the available space in bytes of the disk holding `path`. A
+    /// failed probe returns `u64::MAX` so that an unprobeable disk is treated
+    /// as unconstrained.
+    fn free_space(&self) -> u64 {
+        let overridden = self.free_space_override.load(Ordering::SeqCst);
+        if overridden != u64::MAX {
+            return overridden;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((probed_at, free)) = *cache {
+            if probed_at.saturating_elapsed() < DISK_FREE_SPACE_PROBE_TTL {
+                return free;
+            }
+        }
+        let free = match fs2::statvfs(&self.path) {
+            Ok(stats) => stats.available_space(),
+            Err(e) => {
+                warn!(
+                    "failed to probe the free space of the data disk";
+                    "path" => %self.path,
+                    "err" => ?e,
+                );
+                u64::MAX
+            }
+        };
+        *cache = Some((Instant::now_coarse(), free));
+        free
+    }
+}
+
 struct RegionCleaner<EK>
 where
     EK: KvEngine,
 {
     use_delete_range: bool,
+    // Minimum free space of the data disk required to delete ranges via
+    // delete-SST ingestion; below it cleanups fall back to deleting by key.
+    // Zero disables the check.
+    delete_ingest_min_free_space: u64,
+    disk_free_probe: DiskFreeSpaceProbe,
     engine: EK,
     // Ranges that have been logically destroyed at a specific sequence number. We can
     // assume there will be no reader (engine snapshot) newer than that sequence number. Therefore,
@@ -357,6 +751,10 @@ where
     // The sole purpose of maintaining this list is to optimize deletion with `DeleteFiles`
     // whenever we can. Errors while processing them can be ignored.
     pending_delete_ranges: PendingDeleteRanges,
+    // Ranges of snapshot applies that are queued or running, keyed by region id. Physical
+    // deletions overlapping them are deferred to later ticks, otherwise a destroy of a stale
+    // peer scheduled on the cleanup pool could remove files a concurrent apply just ingested.
+    applying_ranges: HashMap<u64, (Vec<u8>, Vec<u8>)>,
     mgr: SnapManager,
 }
 
@@ -368,64 +766,157 @@ where
     /// bounds. These pending ranges will be removed. Returns an updated range
     /// that also includes these ranges. Caller must ensure the remaining keys
     /// in the returning range will be deleted properly.
+    /// Records the range of a snapshot apply that has been queued or is
+    /// running, so that overlapping physical deletions are deferred until the
+    /// apply is done.
+    fn register_applying_range(&mut self, region_id: u64, start_key: Vec<u8>, end_key: Vec<u8>) {
+        self.applying_ranges.insert(region_id, (start_key, end_key));
+    }
+
+    fn deregister_applying_range(&mut self, region_id: u64) {
+        self.applying_ranges.remove(&region_id);
+    }
+
+    /// Returns true if the given range overlaps the range of any queued or
+    /// running snapshot apply except the exempted region. The cleanup invoked
+    /// from an apply itself passes its own region id as `exempt_region` since
+    /// it runs before that apply's ingest.
+    fn overlaps_applying_range(
+        &self,
+        exempt_region: Option<u64>,
+        start_key: &[u8],
+        end_key: &[u8],
+    ) -> bool {
+        self.applying_ranges.iter().any(|(region_id, (s, e))| {
+            exempt_region != Some(*region_id) && s.as_slice() < end_key && start_key < e.as_slice()
+        })
+    }
+
     fn clean_overlap_ranges_roughly(
         &mut self,
         mut start_key: Vec<u8>,
         mut end_key: Vec<u8>,
-    ) -> (Vec<u8>, Vec<u8>) {
+        exempt_region: Option<u64>,
+    ) -> ApplyCleanupInfo {
         let overlap_ranges = self
             .pending_delete_ranges
             .drain_overlap_ranges(&start_key, &end_key);
         if overlap_ranges.is_empty() {
-            return (start_key, end_key);
+            return ApplyCleanupInfo {
+                cleaned_start: start_key,
+                cleaned_end: end_key,
+                ..Default::default()
+            };
         }
         CLEAN_COUNTER_VEC.with_label_values(&["overlap"]).inc();
         let oldest_sequence = self
             .engine
             .get_oldest_snapshot_sequence_number()
             .unwrap_or(u64::MAX);
-        let df_ranges: Vec<_> = overlap_ranges
-            .iter()
-            .filter_map(|(region_id, cur_start, cur_end, stale_sequence)| {
-                info!(
-                    "delete data in range because of overlap"; "region_id" => region_id,
-                    "start_key" => log_wrappers::Value::key(cur_start),
-                    "end_key" => log_wrappers::Value::key(cur_end)
-                );
-                if &start_key > cur_start {
-                    start_key = cur_start.clone();
-                }
-                if &end_key < cur_end {
-                    end_key = cur_end.clone();
-                }
-                if *stale_sequence < oldest_sequence {
-                    Some(Range::new(cur_start, cur_end))
-                } else {
-                    SNAP_COUNTER_VEC
-                        .with_label_values(&["overlap", "not_delete_files"])
-                        .inc();
-                    None
-                }
-            })
-            .collect();
-        self.engine
-            .delete_ranges_cfs(
-                &WriteOptions::default(),
-                DeleteStrategy::DeleteFiles,
-                &df_ranges,
-            )
-            .map_err(|e| {
+        let mut strategies_used = Vec::new();
+        {
+            let df_ranges: Vec<_> = overlap_ranges
+                .iter()
+                .filter_map(|(region_id, cur_start, cur_end, stale_sequence)| {
+                    info!(
+                        "delete data in range because of overlap"; "region_id" => region_id,
+                        "start_key" => log_wrappers::Value::key(cur_start),
+                        "end_key" => log_wrappers::Value::key(cur_end)
+                    );
+                    if &start_key > cur_start {
+                        start_key = cur_start.clone();
+                    }
+                    if &end_key < cur_end {
+                        end_key = cur_end.clone();
+                    }
+                    if self.overlaps_applying_range(exempt_region, cur_start, cur_end) {
+                        // The merged range is still deleted by the caller (either
+                        // right away or as a pending range on later ticks), only
+                        // the `DeleteFiles` optimization is skipped here.
+                        CLEAN_COUNTER_VEC.with_label_values(&["defer_by_apply"]).inc();
+                        None
+                    } else if *stale_sequence < oldest_sequence {
+                        Some(Range::new(cur_start, cur_end))
+                    } else {
+                        SNAP_COUNTER_VEC
+                            .with_label_values(&["overlap", "not_delete_files"])
+                            .inc();
+                        None
+                    }
+                })
+                .collect();
+            CLEAN_RANGES_PROCESSED_VEC
+                .with_label_values(&["delete_files"])
+                .inc_by(df_ranges.len() as u64);
+            if !df_ranges.is_empty() {
+                strategies_used.push(delete_strategy_label(&DeleteStrategy::DeleteFiles).to_owned());
+            }
+            if let Err(e) = self.delete_ranges_physical(DeleteStrategy::DeleteFiles, &df_ranges) {
+                // The deletions by key run by the caller still remove the data,
+                // only the space reclamation is less prompt.
                 error!("failed to delete files in range"; "err" => %e);
-            })
-            .unwrap();
-        (start_key, end_key)
+                REGION_WORKER_RECOVERED_ERRORS
+                    .with_label_values(&["overlap_delete_files"])
+                    .inc();
+            }
+        }
+        ApplyCleanupInfo {
+            cleaned_start: start_key,
+            cleaned_end: end_key,
+            drained_ranges: overlap_ranges
+                .into_iter()
+                .map(|(region_id, cur_start, cur_end, _)| (region_id, cur_start, cur_end))
+                .collect(),
+            strategies_used,
+        }
     }
 
     /// Cleans up data in the given range and all pending ranges overlapping
-    /// with it.
-    fn clean_overlap_ranges(&mut self, start_key: Vec<u8>, end_key: Vec<u8>) -> Result<()> {
-        let (start_key, end_key) = self.clean_overlap_ranges_roughly(start_key, end_key);
-        self.delete_all_in_range(&[Range::new(&start_key, &end_key)])
+    /// with it. `exempt_region` is set when invoked from an apply of that
+    /// region, which must not be deferred by its own registration. Returns
+    /// what was cleaned and how, so the apply path can hand it to the
+    /// apply-snapshot observers.
+    fn clean_overlap_ranges(
+        &mut self,
+        start_key: Vec<u8>,
+        end_key: Vec<u8>,
+        exempt_region: Option<u64>,
+    ) -> Result<ApplyCleanupInfo> {
+        let start = Instant::now();
+        let mut info = self.clean_overlap_ranges_roughly(start_key, end_key, exempt_region);
+        let res = self.delete_all_in_range(&[Range::new(&info.cleaned_start, &info.cleaned_end)]);
+        CLEAN_OVERLAP_TOTAL_DURATION_HISTOGRAM.observe(start.saturating_elapsed_secs());
+        for label in res? {
+            if !info.strategies_used.contains(&label) {
+                info.strategies_used.push(label);
+            }
+        }
+        Ok(info)
+    }
+
+    /// Builds a best-effort preview of the cleanup an apply of `[start_key,
+    /// end_key)` would run, based on the currently registered pending delete
+    /// ranges. The strategies are left empty, they are only decided when the
+    /// cleanup executes.
+    fn preview_apply_cleanup(&self, start_key: &[u8], end_key: &[u8]) -> ApplyCleanupInfo {
+        let mut info = ApplyCleanupInfo {
+            cleaned_start: start_key.to_vec(),
+            cleaned_end: end_key.to_vec(),
+            ..Default::default()
+        };
+        for (region_id, cur_start, cur_end, _) in self
+            .pending_delete_ranges
+            .find_overlap_ranges(start_key, end_key)
+        {
+            if cur_start < info.cleaned_start {
+                info.cleaned_start = cur_start.clone();
+            }
+            if cur_end > info.cleaned_end {
+                info.cleaned_end = cur_end.clone();
+            }
+            info.drained_ranges.push((region_id, cur_start, cur_end));
+        }
+        info
     }
 
     /// Inserts a new pending range, and it will be cleaned up with some delay.
@@ -434,8 +925,10 @@ where
         region_id: u64,
         start_key: Vec<u8>,
         end_key: Vec<u8>,
+        size_hint: Option<u64>,
     ) {
-        let (start_key, end_key) = self.clean_overlap_ranges_roughly(start_key, end_key);
+        let info = self.clean_overlap_ranges_roughly(start_key, end_key, None);
+        let (start_key, end_key) = (info.cleaned_start, info.cleaned_end);
         info!("register deleting data in range";
             "region_id" => region_id,
             "start_key" => log_wrappers::Value::key(&start_key),
@@ -443,12 +936,195 @@ where
         );
         let seq = self.engine.get_latest_sequence_number();
         self.pending_delete_ranges
-            .insert(region_id, start_key, end_key, seq);
+            .insert(region_id, start_key, end_key, seq, size_hint);
+        PENDING_DELETE_ESTIMATED_BYTES_GAUGE
+            .set(self.pending_delete_ranges.estimated_bytes() as i64);
+    }
+
+    /// Collects the data ranges of all regions the current region metadata
+    /// still considers live (`Normal` or `Applying`), data-key encoded like
+    /// the pending delete ranges.
+    fn live_region_ranges(&self) -> Result<Vec<(u64, Vec<u8>, Vec<u8>)>> {
+        let mut ranges = Vec::new();
+        box_try!(self.engine.scan(
+            CF_RAFT,
+            keys::REGION_META_MIN_KEY,
+            keys::REGION_META_MAX_KEY,
+            false,
+            |key, value| {
+                let (_, suffix) = box_try!(keys::decode_region_meta_key(key));
+                if suffix != keys::REGION_STATE_SUFFIX {
+                    return Ok(true);
+                }
+                let mut local_state = RegionLocalState::default();
+                local_state.merge_from_bytes(value)?;
+                if matches!(
+                    local_state.get_state(),
+                    PeerState::Normal | PeerState::Applying
+                ) {
+                    let region = local_state.get_region();
+                    ranges.push((
+                        region.get_id(),
+                        keys::enc_start_key(region),
+                        keys::enc_end_key(region),
+                    ));
+                }
+                Ok(true)
+            }
+        ));
+        Ok(ranges)
+    }
+
+    /// Drops the pending ranges among `region_ranges` that overlap a region
+    /// the current region metadata considers live. A pending entry is keyed
+    /// by its range and attributed to the region id destroyed back then; if
+    /// the layout changed while it waited (the same region id re-created
+    /// here with different boundaries, or a neighbor expanded into the
+    /// range), deleting the range would destroy live data. Such entries are
+    /// unregistered without deleting anything, counted and logged.
+    fn drop_ranges_overlapping_live_regions(
+        &mut self,
+        region_ranges: &mut Vec<(u64, Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let live_ranges = self.live_region_ranges()?;
+        region_ranges.retain(|(region_id, start_key, end_key)| {
+            let live = live_ranges.iter().find(|(_, live_start, live_end)| {
+                live_start.as_slice() < end_key.as_slice()
+                    && start_key.as_slice() < live_end.as_slice()
+            });
+            let Some((live_region_id, live_start, live_end)) = live else {
+                return true;
+            };
+            let info = self.pending_delete_ranges.unregister(start_key).unwrap();
+            warn!(
+                "skip deleting stale range that overlaps a live region";
+                "region_id" => region_id,
+                "start_key" => log_wrappers::Value::key(start_key),
+                "end_key" => log_wrappers::Value::key(end_key),
+                "live_region_id" => live_region_id,
+                "live_start_key" => log_wrappers::Value::key(live_start),
+                "live_end_key" => log_wrappers::Value::key(live_end),
+                "pending_secs" => UnixSecs::now()
+                    .into_inner()
+                    .saturating_sub(info.destroy_time.into_inner()),
+            );
+            CLEAN_COUNTER_VEC.with_label_values(&["live_overlap"]).inc();
+            false
+        });
+        Ok(())
+    }
+
+    /// Handles a finished compaction covering `[start_key, end_key]` of `cf`
+    /// (the end key is inclusive, it is the largest key of the compaction).
+    /// Pending ranges the compaction fully covered whose data the
+    /// range-properties estimate shows as gone are marked probably empty, so
+    /// the next cleanup pass can retire them without issuing deletions, see
+    /// `retire_probably_empty_ranges`.
+    fn on_compaction_finished(&mut self, cf: &str, start_key: &[u8], end_key: &[u8]) {
+        for (region_id, range_start, range_end) in self
+            .pending_delete_ranges
+            .contained_unmarked_ranges(start_key, end_key)
+        {
+            // The estimate covers the whole engine, not only the compaction
+            // output, so data remaining in files the compaction did not
+            // touch keeps the range unmarked.
+            let keys = match self.engine.get_range_approximate_keys_cf(
+                cf,
+                Range::new(&range_start, &range_end),
+                0,
+            ) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    error!("failed to estimate keys of pending delete range"; "err" => %e);
+                    REGION_WORKER_RECOVERED_ERRORS
+                        .with_label_values(&["compaction_estimate"])
+                        .inc();
+                    continue;
+                }
+            };
+            if keys > 0 {
+                continue;
+            }
+            info!(
+                "pending delete range probably emptied by compaction";
+                "region_id" => region_id,
+                "start_key" => log_wrappers::Value::key(&range_start),
+                "end_key" => log_wrappers::Value::key(&range_end),
+                "cf" => cf,
+            );
+            CLEAN_COUNTER_VEC
+                .with_label_values(&["compaction_hint"])
+                .inc();
+            self.pending_delete_ranges
+                .set_probably_empty(&range_start, true);
+        }
+    }
+
+    /// Verifies the ranges marked probably empty by `on_compaction_finished`
+    /// with one bounded seek per cf and retires those that are indeed empty,
+    /// without issuing any deletion. A range the seek finds data in had a
+    /// false hint (e.g. the estimate missed data in untouched files); its
+    /// mark is cleared and it waits for the regular stale cleanup.
+    fn retire_probably_empty_ranges(&mut self) {
+        let candidates: Vec<(u64, Vec<u8>, Vec<u8>)> = self
+            .pending_delete_ranges
+            .probably_empty_ranges()
+            .map(|(region_id, s, e)| (region_id, s.to_vec(), e.to_vec()))
+            .collect();
+        for (region_id, start_key, end_key) in candidates {
+            match self.range_has_data(&start_key, &end_key) {
+                Ok(false) => {
+                    info!(
+                        "retire pending delete range emptied by compaction";
+                        "region_id" => region_id,
+                        "start_key" => log_wrappers::Value::key(&start_key),
+                        "end_key" => log_wrappers::Value::key(&end_key),
+                    );
+                    self.pending_delete_ranges.unregister(&start_key).unwrap();
+                    CLEAN_COUNTER_VEC
+                        .with_label_values(&["compaction_retired"])
+                        .inc();
+                }
+                Ok(true) => {
+                    self.pending_delete_ranges
+                        .set_probably_empty(&start_key, false);
+                }
+                Err(e) => {
+                    // Leave the mark, the verification is retried on the
+                    // next tick.
+                    error!("failed to verify probably empty range"; "err" => %e);
+                    REGION_WORKER_RECOVERED_ERRORS
+                        .with_label_values(&["empty_range_seek"])
+                        .inc();
+                }
+            }
+        }
+    }
+
+    /// Checks with one bounded seek per cf whether any key remains in
+    /// [start_key, end_key).
+    fn range_has_data(&self, start_key: &[u8], end_key: &[u8]) -> Result<bool> {
+        for cf in self.engine.cf_names() {
+            let mut found = false;
+            box_try!(self.engine.scan(cf, start_key, end_key, false, |_, _| {
+                found = true;
+                Ok(false)
+            }));
+            if found {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     /// Cleans up stale ranges.
     fn clean_stale_ranges(&mut self) {
+        let tick_start = Instant::now();
+        defer!(CLEAN_STALE_TICK_DURATION_HISTOGRAM.observe(tick_start.saturating_elapsed_secs()));
+        self.retire_probably_empty_ranges();
         STALE_PEER_PENDING_DELETE_RANGE_GAUGE.set(self.pending_delete_ranges.len() as f64);
+        PENDING_DELETE_ESTIMATED_BYTES_GAUGE
+            .set(self.pending_delete_ranges.estimated_bytes() as i64);
         if self.ingest_maybe_stall() {
             return;
         }
@@ -459,14 +1135,39 @@ where
         let mut region_ranges: Vec<(u64, Vec<u8>, Vec<u8>)> = self
             .pending_delete_ranges
             .stale_ranges(oldest_sequence)
+            .filter(|&(region_id, s, e)| {
+                if self.overlaps_applying_range(None, s, e) {
+                    info!(
+                        "defer deleting data in range due to overlapping apply";
+                        "region_id" => region_id,
+                        "start_key" => log_wrappers::Value::key(s),
+                        "end_key" => log_wrappers::Value::key(e),
+                    );
+                    CLEAN_COUNTER_VEC.with_label_values(&["defer_by_apply"]).inc();
+                    return false;
+                }
+                true
+            })
             .map(|(region_id, s, e)| (region_id, s.to_vec(), e.to_vec()))
             .collect();
         if region_ranges.is_empty() {
             return;
         }
         CLEAN_COUNTER_VEC.with_label_values(&["destroy"]).inc_by(1);
-        region_ranges.sort_by(|a, b| a.1.cmp(&b.1));
+        // `stale_ranges` returns the ranges ordered by start key already.
         region_ranges.truncate(CLEANUP_MAX_REGION_COUNT);
+        if let Err(e) = self.drop_ranges_overlapping_live_regions(&mut region_ranges) {
+            // Without the re-validation the deletions are not provably safe;
+            // keep the entries registered and retry on the next tick.
+            error!("failed to validate stale ranges against region metadata"; "err" => %e);
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["stale_live_check"])
+                .inc();
+            return;
+        }
+        if region_ranges.is_empty() {
+            return;
+        }
         let ranges: Vec<_> = region_ranges
             .iter()
             .map(|(region_id, start, end)| {
@@ -477,38 +1178,163 @@ where
             })
             .collect();
 
-        self.engine
-            .delete_ranges_cfs(
-                &WriteOptions::default(),
-                DeleteStrategy::DeleteFiles,
-                &ranges,
-            )
-            .map_err(|e| {
-                error!("failed to delete files in range"; "err" => %e);
-            })
-            .unwrap();
+        CLEAN_RANGES_PROCESSED_VEC
+            .with_label_values(&["delete_files"])
+            .inc_by(ranges.len() as u64);
+        // On any failure below the ranges stay registered, so the whole
+        // cleanup is retried on the next stale-range tick; repeating the
+        // deletions then is harmless.
+        if let Err(e) = self.delete_ranges_physical(DeleteStrategy::DeleteFiles, &ranges) {
+            error!("failed to delete files in range"; "err" => %e);
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["stale_delete_files"])
+                .inc();
+            return;
+        }
         if let Err(e) = self.delete_all_in_range(&ranges) {
             error!("failed to cleanup stale range"; "err" => %e);
             return;
         }
-        self.engine
-            .delete_ranges_cfs(
-                &WriteOptions::default(),
-                DeleteStrategy::DeleteBlobs,
-                &ranges,
-            )
-            .map_err(|e| {
-                error!("failed to delete blobs in range"; "err" => %e);
-            })
-            .unwrap();
+        if let Err(e) = self.delete_ranges_physical(DeleteStrategy::DeleteBlobs, &ranges) {
+            error!("failed to delete blobs in range"; "err" => %e);
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["stale_delete_blobs"])
+                .inc();
+            return;
+        }
+        CLEAN_RANGES_PROCESSED_VEC
+            .with_label_values(&["delete_blobs"])
+            .inc_by(ranges.len() as u64);
 
+        let mut reclaimed = 0;
         for (_, key, _) in region_ranges {
+            let info = self.pending_delete_ranges.unregister(&key);
             assert!(
-                self.pending_delete_ranges.remove(&key).is_some(),
+                info.is_some(),
                 "cleanup pending_delete_ranges {} should exist",
                 log_wrappers::Value::key(&key)
             );
+            reclaimed += info.unwrap().size_hint.unwrap_or(0);
+        }
+        DELETE_FILES_RECLAIMED_BYTES.inc_by(reclaimed);
+        PENDING_DELETE_ESTIMATED_BYTES_GAUGE
+            .set(self.pending_delete_ranges.estimated_bytes() as i64);
+    }
+
+    /// Immediately cleans up the pending delete ranges registered under the
+    /// given region id, without waiting for the stale-range ticks. Ranges
+    /// that an open engine snapshot may still be reading are refused unless
+    /// `force` is set; a forced cleanup of such a range deletes by key and
+    /// skips the physical file deletions, so open snapshots stay consistent.
+    /// Ranges overlapping a queued or running snapshot apply are refused
+    /// even with `force`.
+    fn clean_region(&mut self, region_id: u64, force: bool) -> Vec<CleanRegionRangeResult> {
+        let oldest_sequence = self
+            .engine
+            .get_oldest_snapshot_sequence_number()
+            .unwrap_or(u64::MAX);
+        let targets: Vec<_> = self
+            .pending_delete_ranges
+            .region_ranges(region_id)
+            .map(|(s, e, stale_sequence)| (s.to_vec(), e.to_vec(), stale_sequence))
+            .collect();
+        let mut results = Vec::with_capacity(targets.len());
+        let mut cleanable = Vec::new();
+        for (start_key, end_key, stale_sequence) in targets {
+            if self.overlaps_applying_range(None, &start_key, &end_key) {
+                CLEAN_COUNTER_VEC.with_label_values(&["defer_by_apply"]).inc();
+                results.push(CleanRegionRangeResult {
+                    start_key,
+                    end_key,
+                    outcome: CleanRegionOutcome::OverlappingApply,
+                });
+            } else if !force && stale_sequence >= oldest_sequence {
+                results.push(CleanRegionRangeResult {
+                    start_key,
+                    end_key,
+                    outcome: CleanRegionOutcome::NotStale,
+                });
+            } else {
+                cleanable.push((start_key, end_key, stale_sequence));
+            }
+        }
+        if cleanable.is_empty() {
+            return results;
+        }
+        CLEAN_COUNTER_VEC.with_label_values(&["manual"]).inc();
+        for (start_key, end_key, _) in &cleanable {
+            info!("delete data in range because of manual clean";
+                "region_id" => region_id,
+                "start_key" => log_wrappers::Value::key(start_key),
+                "end_key" => log_wrappers::Value::key(end_key));
+        }
+        // `DeleteFiles` and `DeleteBlobs` may break the consistency of open
+        // snapshots, so a forced cleanup of a range that is not stale yet
+        // skips them and relies on the deletions by key below.
+        let df_ranges: Vec<_> = cleanable
+            .iter()
+            .filter(|(_, _, stale_sequence)| *stale_sequence < oldest_sequence)
+            .map(|(start_key, end_key, _)| Range::new(start_key, end_key))
+            .collect();
+        CLEAN_RANGES_PROCESSED_VEC
+            .with_label_values(&["delete_files"])
+            .inc_by(df_ranges.len() as u64);
+        if let Err(e) = self.delete_ranges_physical(DeleteStrategy::DeleteFiles, &df_ranges) {
+            // The deletions by key below still remove the data.
+            error!("failed to delete files in range"; "err" => %e);
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["clean_region_delete_files"])
+                .inc();
+        }
+        let ranges: Vec<_> = cleanable
+            .iter()
+            .map(|(start_key, end_key, _)| Range::new(start_key, end_key))
+            .collect();
+        if let Err(e) = self.delete_all_in_range(&ranges) {
+            error!("failed to clean region"; "region_id" => region_id, "err" => %e);
+            for (start_key, end_key, _) in cleanable {
+                results.push(CleanRegionRangeResult {
+                    start_key,
+                    end_key,
+                    outcome: CleanRegionOutcome::Failed(format!("{}", e)),
+                });
+            }
+            return results;
+        }
+        if let Err(e) = self.delete_ranges_physical(DeleteStrategy::DeleteBlobs, &df_ranges) {
+            // The keys are already gone; the remaining blob files are
+            // reclaimed by later compactions.
+            error!("failed to delete blobs in range"; "err" => %e);
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["clean_region_delete_blobs"])
+                .inc();
+        } else {
+            CLEAN_RANGES_PROCESSED_VEC
+                .with_label_values(&["delete_blobs"])
+                .inc_by(df_ranges.len() as u64);
+        }
+        for (start_key, end_key, stale_sequence) in cleanable {
+            let info = self.pending_delete_ranges.unregister(&start_key);
+            assert!(
+                info.is_some(),
+                "clean region {} pending_delete_ranges {} should exist",
+                region_id,
+                log_wrappers::Value::key(&start_key)
+            );
+            // Only the ranges the `DeleteFiles` pass above covered count as
+            // reclaimed; a forced cleanup of a not-yet-stale range skipped it.
+            if stale_sequence < oldest_sequence {
+                DELETE_FILES_RECLAIMED_BYTES.inc_by(info.unwrap().size_hint.unwrap_or(0));
+            }
+            results.push(CleanRegionRangeResult {
+                start_key,
+                end_key,
+                outcome: CleanRegionOutcome::Cleaned,
+            });
         }
+        PENDING_DELETE_ESTIMATED_BYTES_GAUGE
+            .set(self.pending_delete_ranges.estimated_bytes() as i64);
+        results
     }
 
     /// Checks the number of files at level 0 to avoid write stall after
@@ -526,23 +1352,390 @@ where
         false
     }
 
-    fn delete_all_in_range(&self, ranges: &[Range<'_>]) -> Result<()> {
+    /// Removes all lock cf keys in the given ranges. Unlike
+    /// `DeleteStrategy::DeleteByKey` this collects a bounded number of keys
+    /// per pass and removes them through `delete_keys_cf` with a byte budget
+    /// on each write batch, yielding between passes, so a range with many
+    /// orphan locks neither builds one oversized write batch nor occupies the
+    /// cleaner for seconds without interruption.
+    fn delete_locks_in_range(&self, ranges: &[Range<'_>]) -> Result<()> {
         let wopts = WriteOptions::default();
-        for cf in self.engine.cf_names() {
-            // CF_LOCK usually contains fewer keys than other CFs, so we delete them by key.
-            let strategy = if cf == CF_LOCK {
-                DeleteStrategy::DeleteByKey
-            } else if self.use_delete_range {
-                DeleteStrategy::DeleteByRange
-            } else {
-                DeleteStrategy::DeleteByWriter {
-                    sst_path: self.mgr.get_temp_path_for_ingest(),
+        let mut total_deleted = 0;
+        for r in ranges {
+            let mut start = r.start_key.to_vec();
+            loop {
+                let mut keys = Vec::new();
+                box_try!(
+                    self.engine
+                        .scan(CF_LOCK, &start, r.end_key, false, |key, _| {
+                            keys.push(key.to_vec());
+                            Ok(keys.len() < LOCK_CF_SCAN_BATCH_KEYS)
+                        })
+                );
+                if keys.is_empty() {
+                    break;
                 }
-            };
-            box_try!(self.engine.delete_ranges_cf(&wopts, cf, strategy, ranges));
-        }
+                let exhausted = keys.len() < LOCK_CF_SCAN_BATCH_KEYS;
+                let (deleted, _) = box_try!(self.engine.delete_keys_cf(
+                    &wopts,
+                    CF_LOCK,
+                    &keys,
+                    LOCK_CF_DELETE_BATCH_BYTES,
+                ));
+                total_deleted += deleted;
+                if exhausted {
+                    break;
+                }
+                // Resume right behind the last deleted key.
+                start = keys.pop().unwrap();
+                start.push(0);
+                fail_point!("clean_lock_cf_pass");
+                std::thread::yield_now();
+            }
+        }
+        CLEAN_LOCK_KEYS_HISTOGRAM.observe(total_deleted as f64);
+        Ok(())
+    }
+
+    /// Chooses the deletion strategy for the data cfs of one cleanup.
+    /// Deleting through the sst writer temporarily needs extra space for the
+    /// delete SSTs plus the flushes and compactions they trigger, which a
+    /// nearly full disk cannot afford, so below the configured free-space
+    /// watermark cleanups fall back to deleting by key regardless of
+    /// configuration.
+    fn delete_range_strategy(&self) -> DeleteStrategy {
+        if self.use_delete_range {
+            return DeleteStrategy::DeleteByRange;
+        }
+        if self.delete_ingest_min_free_space > 0 {
+            let free = self.disk_free_probe.free_space();
+            if free < self.delete_ingest_min_free_space {
+                warn!(
+                    "data disk is low on space, delete ranges by key instead of by ingesting sst";
+                    "free_space" => free,
+                    "min_free_space" => self.delete_ingest_min_free_space,
+                );
+                CLEAN_COUNTER_VEC
+                    .with_label_values(&["ingest_low_disk_fallback"])
+                    .inc();
+                return DeleteStrategy::DeleteByKey;
+            }
+        }
+        DeleteStrategy::DeleteByWriter {
+            sst_path: self.mgr.get_temp_path_for_ingest(),
+        }
+    }
+
+    /// Runs one physical deletion (`DeleteFiles`/`DeleteBlobs`) for the
+    /// cleanup paths. These deletions only reclaim space ahead of or after
+    /// the deletions by key, so the callers recover from a failure here by
+    /// skipping or retrying instead of taking down the process.
+    fn delete_ranges_physical(&self, strategy: DeleteStrategy, ranges: &[Range<'_>]) -> Result<()> {
+        // The failpoints are scoped to a dedicated key prefix so that tests
+        // enabling them do not disturb unrelated cleanups in the process.
+        fail_point!(
+            "region_cleaner_delete_files",
+            matches!(strategy, DeleteStrategy::DeleteFiles)
+                && ranges.iter().any(|r| r.start_key.starts_with(b"q")),
+            |_| Err(box_err!("injected delete files error"))
+        );
+        fail_point!(
+            "region_cleaner_delete_blobs",
+            matches!(strategy, DeleteStrategy::DeleteBlobs)
+                && ranges.iter().any(|r| r.start_key.starts_with(b"q")),
+            |_| Err(box_err!("injected delete blobs error"))
+        );
+        box_try!(
+            self.engine
+                .delete_ranges_cfs(&WriteOptions::default(), strategy, ranges)
+        );
         Ok(())
     }
+
+    /// Deletes all keys in the given ranges. Returns the labels of the
+    /// deletion strategies that were used, see `delete_strategy_label`.
+    fn delete_all_in_range(&self, ranges: &[Range<'_>]) -> Result<Vec<String>> {
+        CLEAN_RANGES_PROCESSED_VEC
+            .with_label_values(&["delete_keys"])
+            .inc_by(ranges.len() as u64);
+        let wopts = WriteOptions::default();
+        let mut strategies_used: Vec<String> = Vec::new();
+        let mut record = |label: &str| {
+            if !strategies_used.iter().any(|l| l == label) {
+                strategies_used.push(label.to_owned());
+            }
+        };
+        for cf in self.engine.cf_names() {
+            if cf == CF_LOCK {
+                // CF_LOCK usually contains fewer keys than the other CFs and
+                // is deleted by key; ranges full of orphan locks are handled
+                // in bounded passes, see `delete_locks_in_range`.
+                self.delete_locks_in_range(ranges)?;
+                record(delete_strategy_label(&DeleteStrategy::DeleteByKey));
+                continue;
+            }
+            let strategy = self.delete_range_strategy();
+            if let Err(e) = self
+                .engine
+                .delete_ranges_cf(&wopts, cf, strategy.clone(), ranges)
+            {
+                if !matches!(strategy, DeleteStrategy::DeleteByWriter { .. }) {
+                    return Err(box_err!(e));
+                }
+                // Building or ingesting the temporary SST may fail, e.g. when
+                // the disk holding the ingest temp dir is full. Deleting by
+                // key needs no scratch space, so the data is still removed.
+                warn!(
+                    "failed to delete range by ingesting sst, fallback to delete by key";
+                    "cf" => cf,
+                    "err" => %e,
+                );
+                box_try!(self.engine.delete_ranges_cf(
+                    &wopts,
+                    cf,
+                    DeleteStrategy::DeleteByKey,
+                    ranges
+                ));
+                record(delete_strategy_label(&DeleteStrategy::DeleteByKey));
+            } else {
+                record(delete_strategy_label(&strategy));
+            }
+        }
+        Ok(strategies_used)
+    }
+}
+
+/// The label a deletion strategy is reported under in `ApplyCleanupInfo`.
+fn delete_strategy_label(strategy: &DeleteStrategy) -> &'static str {
+    match strategy {
+        DeleteStrategy::DeleteFiles => "delete_files",
+        DeleteStrategy::DeleteBlobs => "delete_blobs",
+        DeleteStrategy::DeleteByRange => "delete_by_range",
+        DeleteStrategy::DeleteByKey => "delete_by_key",
+        DeleteStrategy::DeleteByWriter { .. } => "delete_by_writer",
+    }
+}
+
+/// An engine-specific hook deciding how the storage of a destroyed region is
+/// reclaimed. With a single shared kv engine the data has to be removed with
+/// range deletes, which is what the region worker does by default. With
+/// partitioned engines where every region owns its own tablet, destroying a
+/// region mostly amounts to removing the tablet, which is orders of magnitude
+/// cheaper; such deployments provide an implementation that unregisters and
+/// destroys the tablet instead.
+pub trait RegionStorageCleaner: Send + Sync {
+    /// Destroys the storage of the given region covering `[start_key,
+    /// end_key)`. Returns true if the storage has been fully reclaimed and no
+    /// range deletes are needed, false to fall back to the range delete flow,
+    /// e.g. when the tablet is still shared pending a split flush.
+    fn destroy_region_storage(&self, region_id: u64, start_key: &[u8], end_key: &[u8]) -> bool;
+}
+
+/// A [`RegionStorageCleaner`] backed by a tablet registry, for partitioned
+/// engines. Destroying a region unregisters its tablet and destroys the
+/// tablet directory through the tablet factory.
+pub struct TabletRegistryCleaner<EK> {
+    registry: TabletRegistry<EK>,
+}
+
+impl<EK> TabletRegistryCleaner<EK> {
+    pub fn new(registry: TabletRegistry<EK>) -> TabletRegistryCleaner<EK> {
+        TabletRegistryCleaner { registry }
+    }
+}
+
+impl<EK> RegionStorageCleaner for TabletRegistryCleaner<EK>
+where
+    EK: KvEngine,
+{
+    fn destroy_region_storage(&self, region_id: u64, _start_key: &[u8], _end_key: &[u8]) -> bool {
+        let Some(mut cached) = self.registry.get(region_id) else {
+            // The tablet was never opened through this registry, there is
+            // nothing to reclaim here.
+            return false;
+        };
+        let path = match cached.latest() {
+            Some(tablet) => PathBuf::from(tablet.path()),
+            None => {
+                self.registry.remove(region_id);
+                return true;
+            }
+        };
+        // The region is destroyed, so unregister the tablet in any case. Drop
+        // our cached handle before probing the lock, otherwise the tablet
+        // always appears to be in use.
+        self.registry.remove(region_id);
+        drop(cached);
+        match EK::locked(path.to_str().unwrap()) {
+            Ok(false) => {}
+            Ok(true) => {
+                // A checkpoint or a split child sharing the tablet files via
+                // hard links still holds the database open. Leave the
+                // directory alone; the files are reclaimed when the last
+                // holder releases them.
+                info!(
+                    "skip destroying shared tablet";
+                    "region_id" => region_id,
+                    "path" => %path.display(),
+                );
+                return false;
+            }
+            Err(e) => {
+                warn!(
+                    "failed to check whether the tablet path is locked";
+                    "region_id" => region_id,
+                    "path" => %path.display(),
+                    "err" => ?e,
+                );
+                return false;
+            }
+        }
+        let (_, id, suffix) = self
+            .registry
+            .parse_tablet_name(&path)
+            .unwrap_or(("", region_id, 0));
+        if let Err(e) = self
+            .registry
+            .tablet_factory()
+            .destroy_tablet(TabletContext::with_infinite_region(id, Some(suffix)), &path)
+        {
+            warn!(
+                "failed to destroy tablet";
+                "region_id" => region_id,
+                "path" => %path.display(),
+                "err" => ?e,
+            );
+            return false;
+        }
+        true
+    }
+}
+
+/// Tracks consecutive snapshot apply failures of one region, see
+/// [`Runner::handle_pending_applies`].
+struct ApplyFailureState {
+    consecutive_failures: u64,
+    last_failure: Instant,
+}
+
+/// A snapshot of the cumulative counters the write-amplification reporter
+/// correlates: region worker ingest/cleanup activity on one side and the
+/// engine compaction flow on the other.
+#[derive(Clone, Copy, Default)]
+struct WriteAmpCounters {
+    ingested_bytes: u64,
+    delete_sst_bytes: u64,
+    delete_files_reclaimed_bytes: u64,
+    delete_tombstones: u64,
+    compaction_read_bytes: u64,
+    compaction_written_bytes: u64,
+}
+
+impl WriteAmpCounters {
+    fn read<EK: KvEngine>() -> WriteAmpCounters {
+        let mut ingested_bytes = 0;
+        for cf in SNAPSHOT_CFS {
+            ingested_bytes += SNAP_APPLY_INGESTED_BYTES_VEC.with_label_values(&[cf]).get();
+        }
+        let (compaction_read_bytes, compaction_written_bytes) =
+            EK::get_accumulated_compaction_flow().unwrap_or_default();
+        WriteAmpCounters {
+            ingested_bytes,
+            delete_sst_bytes: EK::get_accumulated_delete_sst_bytes().unwrap_or_default(),
+            delete_files_reclaimed_bytes: DELETE_FILES_RECLAIMED_BYTES.get(),
+            delete_tombstones: EK::get_accumulated_delete_tombstones().unwrap_or_default(),
+            compaction_read_bytes,
+            compaction_written_bytes,
+        }
+    }
+
+    fn delta_since(&self, earlier: &WriteAmpCounters) -> WriteAmpCounters {
+        WriteAmpCounters {
+            ingested_bytes: self.ingested_bytes.saturating_sub(earlier.ingested_bytes),
+            delete_sst_bytes: self.delete_sst_bytes.saturating_sub(earlier.delete_sst_bytes),
+            delete_files_reclaimed_bytes: self
+                .delete_files_reclaimed_bytes
+                .saturating_sub(earlier.delete_files_reclaimed_bytes),
+            delete_tombstones: self.delete_tombstones.saturating_sub(earlier.delete_tombstones),
+            compaction_read_bytes: self
+                .compaction_read_bytes
+                .saturating_sub(earlier.compaction_read_bytes),
+            compaction_written_bytes: self
+                .compaction_written_bytes
+                .saturating_sub(earlier.compaction_written_bytes),
+        }
+    }
+}
+
+/// Periodically correlates the bytes the region worker pushed into or
+/// reclaimed from the engine with the compaction flow of the same window, so
+/// the write amplification caused by snapshot ingests and range cleanups can
+/// be read off one metric family instead of being stitched together from
+/// engine and raftstore dashboards.
+struct WriteAmpReporter {
+    last_report: Instant,
+    last_counters: WriteAmpCounters,
+}
+
+impl WriteAmpReporter {
+    fn new<EK: KvEngine>() -> WriteAmpReporter {
+        WriteAmpReporter {
+            last_report: Instant::now_coarse(),
+            last_counters: WriteAmpCounters::read::<EK>(),
+        }
+    }
+
+    fn maybe_report<EK: KvEngine>(&mut self) {
+        if self.last_report.saturating_elapsed() >= WRITE_AMP_REPORT_INTERVAL {
+            self.report::<EK>();
+        }
+    }
+
+    fn report<EK: KvEngine>(&mut self) {
+        let window_secs = self.last_report.saturating_elapsed_secs();
+        let current = WriteAmpCounters::read::<EK>();
+        let delta = current.delta_since(&self.last_counters);
+        self.last_report = Instant::now_coarse();
+        self.last_counters = current;
+
+        WRITE_AMP_WINDOW_BYTES_VEC
+            .with_label_values(&["snapshot_ingest"])
+            .set(delta.ingested_bytes as i64);
+        WRITE_AMP_WINDOW_BYTES_VEC
+            .with_label_values(&["delete_sst"])
+            .set(delta.delete_sst_bytes as i64);
+        WRITE_AMP_WINDOW_BYTES_VEC
+            .with_label_values(&["delete_files_reclaimed"])
+            .set(delta.delete_files_reclaimed_bytes as i64);
+        WRITE_AMP_WINDOW_BYTES_VEC
+            .with_label_values(&["compaction_read"])
+            .set(delta.compaction_read_bytes as i64);
+        WRITE_AMP_WINDOW_BYTES_VEC
+            .with_label_values(&["compaction_written"])
+            .set(delta.compaction_written_bytes as i64);
+        WRITE_AMP_WINDOW_TOMBSTONES_GAUGE.set(delta.delete_tombstones as i64);
+
+        let ratio = |bytes: u64| {
+            if delta.compaction_written_bytes == 0 {
+                0.0
+            } else {
+                bytes as f64 / delta.compaction_written_bytes as f64
+            }
+        };
+        info!(
+            "region worker write amplification report";
+            "window_secs" => format!("{:.1}", window_secs),
+            "snapshot_ingested_bytes" => delta.ingested_bytes,
+            "delete_sst_bytes" => delta.delete_sst_bytes,
+            "delete_files_reclaimed_bytes" => delta.delete_files_reclaimed_bytes,
+            "delete_tombstones" => delta.delete_tombstones,
+            "compaction_read_bytes" => delta.compaction_read_bytes,
+            "compaction_written_bytes" => delta.compaction_written_bytes,
+            "ingest_to_compaction_write_ratio" => format!("{:.3}", ratio(delta.ingested_bytes)),
+            "delete_sst_to_compaction_write_ratio" =>
+                format!("{:.3}", ratio(delta.delete_sst_bytes)),
+        );
+    }
 }
 
 pub struct Runner<EK, R, T>
@@ -550,25 +1743,76 @@ where
     EK: KvEngine,
     T: PdClient + 'static,
 {
-    batch_size: usize,
+    // Config is kept to read the apply batch sizes per task, so online
+    // changes take effect for new applies.
+    cfg: Arc<VersionTrack<Config>>,
     ingest_copy_symlink: bool,
+    ingest_concurrency: usize,
+    pipelined_verification: bool,
     clean_stale_tick: usize,
     clean_stale_check_interval: Duration,
     clean_stale_ranges_tick: usize,
+    write_amp_reporter: WriteAmpReporter,
 
     tiflash_stores: HashMap<u64, bool>,
     // we may delay some apply tasks if level 0 files to write stall threshold,
     // pending_applies records all delayed apply task, and will check again later
     pending_applies: VecDeque<Task<EK::Snapshot>>,
+    // apply tasks queued with `ApplyPriority::Urgent`, drained ahead of
+    // `pending_applies`. FIFO within the queue, like the normal one.
+    urgent_applies: VecDeque<Task<EK::Snapshot>>,
+    // consecutive urgent applies processed since the last normal one, to
+    // bound starvation of the normal queue. See `handle_pending_applies`.
+    urgent_streak: usize,
+    // per-region consecutive apply failure states. Once a region reaches
+    // `APPLY_FAILURE_BACKOFF_THRESHOLD`, its apply tasks are parked in
+    // `delayed_applies` until the backoff expires.
+    apply_failures: HashMap<u64, ApplyFailureState>,
+    delayed_applies: Vec<Task<EK::Snapshot>>,
+    apply_failure_backoff: Duration,
+    // The recent apply history of each region, newest entry last, for the
+    // status server and `Task::QueryApplyHistory`. Bounded per region and,
+    // through the LRU, in the number of regions.
+    apply_history: LruCache<u64, VecDeque<SnapApplyHistoryEntry>>,
+    // snapshot sizes of the queued applies, keyed by region id. The sum is reported to the
+    // snap manager so snapshot senders can be informed of the backlog.
+    pending_apply_sizes: HashMap<u64, u64>,
+    // snapshot handles opened when the apply tasks were queued, reused by
+    // `pre_apply_snapshot` and `apply_snap` so one task opens its snapshot
+    // once instead of once per phase. An entry is consumed by the apply and
+    // dropped when a phase fails, so a retry reopens the files.
+    cached_apply_snaps: HashMap<SnapKey, Box<Snapshot>>,
+    // completed applies waiting to be sent as one batched notification, as
+    // `(region_id, peer_id, tombstone, failure_count)`. Only used when
+    // `applied_notify_batch` > 0 and a store router is available.
+    applied_notifications: Vec<(u64, u64, bool, u64)>,
+    applied_notify_batch: usize,
+    // wall-time budget of one `handle_pending_applies` pass; zero disables it.
+    apply_time_budget: Duration,
+    // the resume point of an in-progress `Task::ReconcileApplying` scan,
+    // `None` when no reconciliation is running. See
+    // `handle_reconcile_applying`.
+    reconcile_applying_cursor: Option<Vec<u8>>,
+    // applying regions the current reconciliation pass found without a usable
+    // snapshot, published to `STUCK_APPLYING_REGIONS_GAUGE` when the pass
+    // ends.
+    reconcile_stuck_missing: u64,
 
     engine: EK,
     mgr: SnapManager,
     coprocessor_host: CoprocessorHost<EK>,
     router: R,
+    // When set, batched snapshot-applied notifications are sent through it to
+    // the store FSM which fans them out to the peers.
+    store_router: Option<Box<dyn StoreRouter<EK>>>,
     pd_client: Option<Arc<T>>,
     snap_gen_pool: FuturePool,
     region_cleanup_pool: FuturePool,
     region_cleaner: Arc<Mutex<RegionCleaner<EK>>>,
+    // When set, destroys reclaim region storage through it (e.g. by removing
+    // the tablet under partitioned engines) and only fall back to range
+    // deletes if it declines.
+    storage_cleaner: Option<Arc<dyn RegionStorageCleaner>>,
 }
 
 impl<EK, R, T> Runner<EK, R, T>
@@ -583,22 +1827,41 @@ where
         cfg: Arc<VersionTrack<Config>>,
         coprocessor_host: CoprocessorHost<EK>,
         router: R,
+        store_router: Option<Box<dyn StoreRouter<EK>>>,
         pd_client: Option<Arc<T>>,
+        storage_cleaner: Option<Arc<dyn RegionStorageCleaner>>,
     ) -> Runner<EK, R, T> {
         Runner {
-            batch_size: cfg.value().snap_apply_batch_size.0 as usize,
             ingest_copy_symlink: cfg.value().snap_apply_copy_symlink,
+            ingest_concurrency: cfg.value().snap_apply_ingest_concurrency,
+            pipelined_verification: cfg.value().snap_apply_pipelined_verification,
             clean_stale_tick: 0,
             clean_stale_check_interval: Duration::from_millis(
                 cfg.value().region_worker_tick_interval.as_millis(),
             ),
             clean_stale_ranges_tick: cfg.value().clean_stale_ranges_tick,
+            write_amp_reporter: WriteAmpReporter::new::<EK>(),
             tiflash_stores: HashMap::default(),
             pending_applies: VecDeque::new(),
+            urgent_applies: VecDeque::new(),
+            urgent_streak: 0,
+            apply_failures: HashMap::default(),
+            delayed_applies: Vec::new(),
+            apply_failure_backoff: APPLY_FAILURE_BACKOFF,
+            apply_history: LruCache::with_capacity(APPLY_HISTORY_REGION_CAP),
+            pending_apply_sizes: HashMap::default(),
+            cached_apply_snaps: HashMap::default(),
+            applied_notifications: Vec::new(),
+            applied_notify_batch: cfg.value().snap_applied_notify_batch,
+            apply_time_budget: cfg.value().snap_apply_time_budget.0,
+            reconcile_applying_cursor: None,
+            reconcile_stuck_missing: 0,
+            cfg: cfg.clone(),
             engine: engine.clone(),
             mgr: mgr.clone(),
             coprocessor_host,
             router,
+            store_router,
             pd_client,
             snap_gen_pool: YatpPoolBuilder::new(DefaultTicker::default())
                 .name_prefix("snap-generator")
@@ -614,10 +1877,14 @@ where
                 .build_future_pool(),
             region_cleaner: Arc::new(Mutex::new(RegionCleaner {
                 use_delete_range: cfg.value().use_delete_range,
+                delete_ingest_min_free_space: cfg.value().cleanup_ingest_min_free_space.0,
+                disk_free_probe: DiskFreeSpaceProbe::new(engine.path().to_owned()),
                 engine,
                 pending_delete_ranges: PendingDeleteRanges::default(),
+                applying_ranges: HashMap::default(),
                 mgr,
             })),
+            storage_cleaner,
         }
     }
 
@@ -640,31 +1907,67 @@ where
         Ok(region_state)
     }
 
-    fn apply_state(&self, region_id: u64) -> Result<RaftApplyState> {
-        let state_key = keys::apply_state_key(region_id);
-        let apply_state: RaftApplyState =
-            match box_try!(self.engine.get_msg_cf(CF_RAFT, &state_key)) {
-                Some(state) => state,
-                None => {
-                    return Err(box_err!(
-                        "failed to get apply_state from {}",
-                        log_wrappers::Value::key(&state_key)
-                    ));
-                }
-            };
-        Ok(apply_state)
+    /// Reads the region local state and the raft apply state of the region
+    /// with one engine read. They are fetched together when an apply task is
+    /// first processed so both phases of the apply work on the same copies
+    /// instead of issuing two point reads per phase.
+    fn read_apply_states(&self, region_id: u64) -> Result<(RegionLocalState, RaftApplyState)> {
+        let region_key = keys::region_state_key(region_id);
+        let apply_key = keys::apply_state_key(region_id);
+        let mut values = box_try!(
+            self.engine
+                .multi_get_value_cf(CF_RAFT, &[&region_key, &apply_key])
+        );
+        let apply_value = values.pop().unwrap();
+        let region_value = values.pop().unwrap();
+        let mut region_state = RegionLocalState::default();
+        match region_value {
+            Some(v) => box_try!(region_state.merge_from_bytes(&v)),
+            None => {
+                return Err(box_err!(
+                    "failed to get region_state from {}",
+                    log_wrappers::Value::key(&region_key)
+                ));
+            }
+        }
+        let mut apply_state = RaftApplyState::default();
+        match apply_value {
+            Some(v) => box_try!(apply_state.merge_from_bytes(&v)),
+            None => {
+                return Err(box_err!(
+                    "failed to get apply_state from {}",
+                    log_wrappers::Value::key(&apply_key)
+                ));
+            }
+        }
+        Ok((region_state, apply_state))
     }
 
     /// Applies snapshot data of the Region.
-    fn apply_snap(&mut self, region_id: u64, peer_id: u64, abort: Arc<AtomicUsize>) -> Result<()> {
+    fn apply_snap(
+        &mut self,
+        region_id: u64,
+        peer_id: u64,
+        region_state: Option<RegionLocalState>,
+        apply_state: Option<RaftApplyState>,
+        abort: Arc<AtomicUsize>,
+    ) -> Result<()> {
         info!("begin apply snap data"; "region_id" => region_id, "peer_id" => peer_id);
         fail_point!("region_apply_snap", |_| { Ok(()) });
         fail_point!("region_apply_snap_io_err", |_| {
             Err(SnapError::Other(box_err!("io error")))
         });
+        fail_point!("region_apply_snap_failure", region_id == 1, |_| {
+            Err(SnapError::Other(box_err!("injected apply failure")))
+        });
         check_abort(&abort)?;
 
-        let mut region_state = self.region_state(region_id)?;
+        // Use the states attached when the task was first processed; reading
+        // them again is only needed if that read failed back then.
+        let (mut region_state, apply_state) = match (region_state, apply_state) {
+            (Some(region_state), Some(apply_state)) => (region_state, apply_state),
+            _ => self.read_apply_states(region_id)?,
+        };
         let region = region_state.get_region().clone();
 
         let range = CacheRange::from_region(&region);
@@ -672,28 +1975,80 @@ where
             "evict range due to apply snap";
             "range" => ?range,
         );
-        self.engine.evict_range(&range);
+        // The eviction must have fully taken effect before the ingest starts,
+        // otherwise a read routed to the cache during or after the ingest
+        // could see the pre-snapshot data.
+        if !self
+            .engine
+            .evict_range_before_ingest(&range, CACHE_EVICT_BEFORE_INGEST_TIMEOUT)
+        {
+            error!(
+                "apply snap proceeds without evicting the overlapping cached range";
+                "region_id" => region_id,
+                "range" => ?range,
+            );
+            SNAP_APPLY_CACHE_EVICT_UNCONFIRMED_COUNTER.inc();
+        }
+
+        let term = apply_state.get_truncated_state().get_term();
+        let idx = apply_state.get_truncated_state().get_index();
+        let snap_key = SnapKey::new(region_id, term, idx);
+        let (progress, cleanup_done) = SnapApplyProgress::resume(self.engine.clone(), &snap_key)?;
+        let progress = Arc::new(progress);
 
         let start_key = keys::enc_start_key(&region);
         let end_key = keys::enc_end_key(&region);
         check_abort(&abort)?;
-        {
-            let mut region_cleaner = self.region_cleaner.lock().unwrap();
-            region_cleaner.clean_overlap_ranges(start_key, end_key)?;
+        let mut cleanup_info = None;
+        if cleanup_done {
+            // A previous attempt at applying this very snapshot already ran
+            // the overlap cleanup before its first ingest, so it is safe to
+            // skip it and resume from the recorded per-cf progress. The
+            // observers get no cleanup info in that case.
+            info!(
+                "skip overlap cleanup done by a previous apply attempt";
+                "region_id" => region_id,
+            );
+        } else {
+            let cleanup_start = Instant::now();
+            {
+                let mut region_cleaner = self.region_cleaner.lock().unwrap();
+                cleanup_info =
+                    Some(region_cleaner.clean_overlap_ranges(start_key, end_key, Some(region_id))?);
+            }
+            SNAP_APPLY_PHASE_HISTOGRAM
+                .cleanup
+                .observe(cleanup_start.saturating_elapsed_secs());
+            progress.record_cleanup()?;
         }
         check_abort(&abort)?;
         fail_point!("apply_snap_cleanup_range");
 
+        // The attached state may be well aged by the time a parked task gets
+        // its turn. The one field that can legitimately change while the task
+        // is queued is the peer state, so re-read just that and refuse to
+        // ingest into a peer that is no longer applying this snapshot.
+        let current_state = self.region_state(region_id)?;
+        if current_state.get_state() != PeerState::Applying {
+            return Err(box_err!(
+                "peer state of region {} changed to {:?} while the apply was queued",
+                region_id,
+                current_state.get_state()
+            ));
+        }
+
         // apply snapshot
-        let apply_state = self.apply_state(region_id)?;
-        let term = apply_state.get_truncated_state().get_term();
-        let idx = apply_state.get_truncated_state().get_index();
-        let snap_key = SnapKey::new(region_id, term, idx);
         self.mgr.register(snap_key.clone(), SnapEntry::Applying);
         defer!({
             self.mgr.deregister(&snap_key, &SnapEntry::Applying);
         });
-        let mut s = box_try!(self.mgr.get_snapshot_for_applying(&snap_key));
+        // Reuse the handle opened when the task was queued or pre-applied;
+        // fall back to opening it when nothing is cached, e.g. a retry after
+        // a failure invalidated the cached handle.
+        let mut s = match self.cached_apply_snaps.remove(&snap_key) {
+            Some(s) => s,
+            None => box_try!(self.mgr.get_snapshot_for_applying(&snap_key)),
+        };
         if !s.exists() {
             return Err(box_err!("missing snapshot file {}", s.path()));
         }
@@ -703,22 +2058,60 @@ where
             db: self.engine.clone(),
             region: region.clone(),
             abort: Arc::clone(&abort),
-            write_batch_size: self.batch_size,
+            write_batch_size: self.cfg.value().snap_apply_batch_size.0 as usize,
+            write_batch_size_lock: self.cfg.value().snap_apply_batch_size_lock().0 as usize,
             coprocessor_host: self.coprocessor_host.clone(),
             ingest_copy_symlink: self.ingest_copy_symlink,
+            ingest_concurrency: self.ingest_concurrency,
+            pipelined_verification: self.pipelined_verification,
+            progress: progress.clone(),
         };
         s.apply(options)?;
-        self.coprocessor_host
-            .post_apply_snapshot(&region, peer_id, &snap_key, Some(&s));
+        SNAP_APPLY_PHASE_HISTOGRAM
+            .ingest
+            .observe(timer.saturating_elapsed_secs());
+        // Per-cf bytes accounting from the snapshot meta, no file stats
+        // needed. Empty cfs contribute nothing, matching their being skipped
+        // by the apply.
+        for cf_meta in s.cf_files() {
+            SNAP_APPLY_INGESTED_BYTES_VEC
+                .with_label_values(&[cf_meta.cf])
+                .inc_by(cf_meta.size);
+        }
+        self.coprocessor_host.post_apply_snapshot(
+            &region,
+            peer_id,
+            &snap_key,
+            Some(&s),
+            cleanup_info.as_ref(),
+        );
 
         // delete snapshot state.
+        let write_state_start = Instant::now();
         let mut wb = self.engine.write_batch();
         region_state.set_state(PeerState::Normal);
         box_try!(wb.put_msg_cf(CF_RAFT, &keys::region_state_key(region_id), &region_state));
         box_try!(wb.delete_cf(CF_RAFT, &keys::snapshot_raft_state_key(region_id)));
-        wb.write().unwrap_or_else(|e| {
-            panic!("{} failed to save apply_snap result: {:?}", region_id, e);
+        progress.clear(&mut wb)?;
+        fail_point!("apply_snap_write_state", region_id == 2, |_| {
+            Err(box_err!("apply_snap_write_state"))
         });
+        // A write failure here is confined to this apply: the error arm of
+        // `handle_apply` marks it failed and the peer retries from a new
+        // snapshot, so it must not take down the whole store.
+        wb.write().map_err(|e| {
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["apply_write_state"])
+                .inc();
+            box_err!("{} failed to save apply_snap result: {:?}", region_id, e)
+        })?;
+        SNAP_APPLY_PHASE_HISTOGRAM
+            .write_state
+            .observe(write_state_start.saturating_elapsed_secs());
+        // The snapshot has been ingested; release the disk space reserved for
+        // it when it was received. Other terminations (abort, failure) keep
+        // the reservation until the snapshot file itself is deleted.
+        self.mgr.release_reserved_space(&snap_key);
         info!(
             "apply new data";
             "region_id" => region_id,
@@ -729,7 +2122,16 @@ where
 
     /// Tries to apply the snapshot of the specified Region. It calls
     /// `apply_snap` to do the actual work.
-    fn handle_apply(&mut self, region_id: u64, peer_id: u64, status: Arc<AtomicUsize>) {
+    fn handle_apply(
+        &mut self,
+        region_id: u64,
+        peer_id: u64,
+        status: Arc<AtomicUsize>,
+        create_time: Instant,
+        region_state: Option<RegionLocalState>,
+        apply_state: Option<RaftApplyState>,
+        on_finish: Option<ApplyCallback>,
+    ) {
         let _ = status.compare_exchange(
             JOB_STATUS_PENDING,
             JOB_STATUS_RUNNING,
@@ -739,12 +2141,29 @@ where
         SNAP_COUNTER.apply.start.inc();
 
         let start = Instant::now();
+        let wait_duration = create_time.saturating_elapsed();
+        let start_unix_secs = UnixSecs::now().into_inner();
+        let (term, index) = apply_state
+            .as_ref()
+            .map(|s| {
+                let truncated = s.get_truncated_state();
+                (truncated.get_term(), truncated.get_index())
+            })
+            .unwrap_or((0, 0));
+        let ingested_bytes = self.pending_apply_sizes.get(&region_id).copied();
 
-        let tombstone = match self.apply_snap(region_id, peer_id, Arc::clone(&status)) {
+        let (tombstone, result) = match self.apply_snap(
+            region_id,
+            peer_id,
+            region_state,
+            apply_state,
+            Arc::clone(&status),
+        ) {
             Ok(()) => {
                 status.swap(JOB_STATUS_FINISHED, Ordering::SeqCst);
                 SNAP_COUNTER.apply.success.inc();
-                false
+                self.apply_failures.remove(&region_id);
+                (false, "success".to_owned())
             }
             Err(Error::Abort) => {
                 warn!("applying snapshot is aborted"; "region_id" => region_id);
@@ -756,10 +2175,27 @@ where
                 );
                 SNAP_COUNTER.apply.abort.inc();
                 // The snapshot is applied abort, it's not necessary to tombstone the peer.
-                false
+                (false, "abort".to_owned())
             }
             Err(e) => {
-                warn!("failed to apply snap!!!"; "region_id" => region_id, "err" => %e);
+                let state = self
+                    .apply_failures
+                    .entry(region_id)
+                    .or_insert_with(|| ApplyFailureState {
+                        consecutive_failures: 0,
+                        last_failure: Instant::now(),
+                    });
+                if state.last_failure.saturating_elapsed() > APPLY_FAILURE_WINDOW {
+                    state.consecutive_failures = 0;
+                }
+                state.consecutive_failures += 1;
+                state.last_failure = Instant::now();
+                warn!(
+                    "failed to apply snap!!!";
+                    "region_id" => region_id,
+                    "err" => %e,
+                    "consecutive_failures" => state.consecutive_failures,
+                );
                 self.coprocessor_host
                     .cancel_apply_snapshot(region_id, peer_id);
                 status.swap(JOB_STATUS_FAILED, Ordering::SeqCst);
@@ -767,17 +2203,161 @@ where
                 // As the snapshot failed, the related peer should be marked tombstone.
                 // And as for the abnormal snapshot, it will be automatically cleaned up by
                 // the CleanupWorker later.
-                true
+                (true, format!("fail: {}", e))
             }
         };
 
         SNAP_HISTOGRAM
             .apply
             .observe(start.saturating_elapsed_secs());
-        let _ = self.router.send(
-            region_id,
-            CasualMessage::SnapshotApplied { peer_id, tombstone },
-        );
+        let entry = SnapApplyHistoryEntry {
+            peer_id,
+            term,
+            index,
+            start_unix_secs,
+            wait_duration_ms: wait_duration.as_millis() as u64,
+            apply_duration_ms: start.saturating_elapsed().as_millis() as u64,
+            ingested_bytes,
+            result: result.clone(),
+        };
+        if self.apply_history.get(&region_id).is_none() {
+            self.apply_history
+                .insert(region_id, VecDeque::with_capacity(APPLY_HISTORY_PER_REGION));
+        }
+        let history = self.apply_history.get_mut(&region_id).unwrap();
+        if history.len() >= APPLY_HISTORY_PER_REGION {
+            history.pop_front();
+        }
+        history.push_back(entry);
+        self.region_cleaner
+            .lock()
+            .unwrap()
+            .deregister_applying_range(region_id);
+        if self.pending_apply_sizes.remove(&region_id).is_some() {
+            self.mgr
+                .set_pending_apply_bytes(self.pending_apply_sizes.values().sum());
+        }
+        // An aborted or failed apply may not have consumed its cached
+        // snapshot handle; drop it so a retry reopens the files.
+        self.cached_apply_snaps
+            .remove(&SnapKey::new(region_id, term, index));
+        let failure_count = self
+            .apply_failures
+            .get(&region_id)
+            .map_or(0, |s| s.consecutive_failures);
+        if let Some(cb) = on_finish {
+            let outcome = ApplyOutcome {
+                snap_key: SnapKey::new(region_id, term, index),
+                result,
+                apply_duration: start.saturating_elapsed(),
+                ingested_bytes,
+            };
+            self.run_on_finish_callback(region_id, peer_id, cb, &outcome);
+        }
+        self.notify_snapshot_applied(region_id, peer_id, tombstone, failure_count);
+    }
+
+    /// Runs the `on_finish` callback of an apply task, shielding the region
+    /// worker from panicking and slow callbacks.
+    fn run_on_finish_callback(
+        &self,
+        region_id: u64,
+        peer_id: u64,
+        cb: ApplyCallback,
+        outcome: &ApplyOutcome,
+    ) {
+        let cb_start = Instant::now();
+        // A panicking callback must not take the whole region worker
+        // down with it; the peer is still notified by the caller.
+        if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (cb.0)(outcome))).is_err() {
+            error!(
+                "snapshot apply on_finish callback panicked";
+                "region_id" => region_id,
+                "peer_id" => peer_id,
+            );
+            SNAP_APPLY_CALLBACK_PANIC_COUNTER.inc();
+        }
+        let cb_elapsed = cb_start.saturating_elapsed();
+        if cb_elapsed > APPLY_CALLBACK_SLOW_THRESHOLD {
+            warn!(
+                "snapshot apply on_finish callback blocked the region worker too long";
+                "region_id" => region_id,
+                "peer_id" => peer_id,
+                "takes" => ?cb_elapsed,
+            );
+        }
+    }
+
+    /// Sends the completion of one apply task to the owning peer, coalesced
+    /// with the other completions of the pass when batching is enabled; see
+    /// `flush_applied_notifications`. Per-region ordering is preserved as
+    /// every region has at most one apply in flight.
+    fn notify_snapshot_applied(
+        &mut self,
+        region_id: u64,
+        peer_id: u64,
+        tombstone: bool,
+        failure_count: u64,
+    ) {
+        if self.applied_notify_batch > 0 && self.store_router.is_some() {
+            self.applied_notifications
+                .push((region_id, peer_id, tombstone, failure_count));
+            if self.applied_notifications.len() >= self.applied_notify_batch {
+                self.flush_applied_notifications();
+            }
+        } else {
+            let _ = self.router.send(
+                region_id,
+                CasualMessage::SnapshotApplied {
+                    peer_id,
+                    tombstone,
+                    failure_count,
+                },
+            );
+        }
+    }
+
+    /// Sends the collected snapshot-applied completions as one batched store
+    /// message. Completions are held back at most for the duration of one
+    /// `handle_pending_applies` pass, so under apply storms the store channel
+    /// sees a handful of batched messages instead of one message per region.
+    fn flush_applied_notifications(&mut self) {
+        if self.applied_notifications.is_empty() {
+            return;
+        }
+        let applied = std::mem::take(&mut self.applied_notifications);
+        let store_router = self.store_router.as_ref().unwrap();
+        if let Err(e) = store_router.send(StoreMsg::SnapshotsApplied(applied.clone())) {
+            warn!(
+                "failed to send batched snapshot applied message, fall back to per-region messages";
+                "count" => applied.len(),
+                "err" => %e,
+            );
+            for (region_id, peer_id, tombstone, failure_count) in applied {
+                let _ = self.router.send(
+                    region_id,
+                    CasualMessage::SnapshotApplied {
+                        peer_id,
+                        tombstone,
+                        failure_count,
+                    },
+                );
+            }
+        }
+    }
+
+    /// The number of apply tasks waiting in the urgent and normal queues.
+    fn queued_apply_count(&self) -> usize {
+        self.urgent_applies.len() + self.pending_applies.len()
+    }
+
+    /// Returns true if applies of the region should be delayed because it has
+    /// failed too many times in a row recently.
+    fn apply_in_backoff(&self, region_id: u64) -> bool {
+        self.apply_failures.get(&region_id).map_or(false, |s| {
+            s.consecutive_failures >= APPLY_FAILURE_BACKOFF_THRESHOLD
+                && s.last_failure.saturating_elapsed() < self.apply_failure_backoff
+        })
     }
 
     /// Checks the number of files at level 0 to avoid write stall after
@@ -795,97 +2375,689 @@ where
         false
     }
 
-    /// Calls observer `pre_apply_snapshot` for every task.
-    /// Multiple task can be `pre_apply_snapshot` at the same time.
-    fn pre_apply_snapshot(&self, task: &Task<EK::Snapshot>) -> Result<()> {
-        let (region_id, abort, peer_id) = match task {
+    /// Calls observer `pre_apply_snapshot` for every task, reusing the
+    /// snapshot handle and the states attached when the task was queued.
+    fn pre_apply_snapshot(&mut self, task: &Task<EK::Snapshot>) -> Result<()> {
+        let (region_id, abort, peer_id, region_state, apply_state) = match task {
             Task::Apply {
                 region_id,
                 status,
                 peer_id,
+                region_state: Some(region_state),
+                apply_state: Some(apply_state),
                 ..
-            } => (region_id, status.clone(), peer_id),
+            } => (
+                region_id,
+                status.clone(),
+                peer_id,
+                region_state,
+                apply_state,
+            ),
+            Task::Apply { region_id, .. } => {
+                return Err(box_err!(
+                    "no states attached to the apply task of region {}",
+                    region_id
+                ));
+            }
             _ => panic!("invalid apply snapshot task"),
         };
 
-        let region_state = self.region_state(*region_id)?;
-        let apply_state = self.apply_state(*region_id)?;
-
         check_abort(&abort)?;
 
         let term = apply_state.get_truncated_state().get_term();
         let idx = apply_state.get_truncated_state().get_index();
         let snap_key = SnapKey::new(*region_id, term, idx);
-        let s = box_try!(self.mgr.get_snapshot_for_applying(&snap_key));
-        if !s.exists() {
+        // A best-effort preview of the cleanup the apply will run; the
+        // pending ranges can still change while the task is queued, the
+        // authoritative info is delivered in `post_apply_snapshot`.
+        let cleanup_preview = {
+            let region_cleaner = self.region_cleaner.lock().unwrap();
+            region_cleaner.preview_apply_cleanup(
+                &keys::enc_start_key(region_state.get_region()),
+                &keys::enc_end_key(region_state.get_region()),
+            )
+        };
+        if !self.cached_apply_snaps.contains_key(&snap_key) {
+            let s = box_try!(self.mgr.get_snapshot_for_applying(&snap_key));
+            self.cached_apply_snaps.insert(snap_key.clone(), s);
+        }
+        if !self.cached_apply_snaps[&snap_key].exists() {
+            // The files may have been deleted since the handle was opened;
+            // drop the stale handle so a later attempt reopens it.
+            let s = self.cached_apply_snaps.remove(&snap_key).unwrap();
             self.coprocessor_host.pre_apply_snapshot(
                 region_state.get_region(),
                 *peer_id,
                 &snap_key,
                 None,
+                Some(&cleanup_preview),
             );
             return Err(box_err!("missing snapshot file {}", s.path()));
         }
         check_abort(&abort)?;
+        let s = &self.cached_apply_snaps[&snap_key];
         self.coprocessor_host.pre_apply_snapshot(
             region_state.get_region(),
             *peer_id,
             &snap_key,
-            Some(&s),
+            Some(s),
+            Some(&cleanup_preview),
         );
         Ok(())
     }
 
-    /// Tries to apply pending tasks if there is some.
-    fn handle_pending_applies(&mut self, is_timeout: bool) {
-        fail_point!("apply_pending_snapshot", |_| {});
-        let mut new_batch = true;
-        while !self.pending_applies.is_empty() {
-            // should not handle too many applies than the number of files that can be
-            // ingested. check level 0 every time because we can not make sure
-            // how does the number of level 0 files change.
-            if self.ingest_maybe_stall() {
-                SNAP_COUNTER.apply.ingest_delay.inc();
-                break;
+    /// Whether the snapshot of a queued apply can be fetched again once the
+    /// apply is cancelled, i.e. PD still knows the region and reports a
+    /// leader living on another peer. Without a PD client the check cannot
+    /// be made and the snapshot is assumed to be re-sendable; the store
+    /// wiring always provides one.
+    fn snapshot_resendable(&self, region: &metapb::Region, peer_id: u64) -> bool {
+        let pd_client = match self.pd_client.as_ref() {
+            Some(c) => c,
+            None => return true,
+        };
+        match pd_client.get_region_info(region.get_start_key()) {
+            Ok(info) => {
+                info.region.get_id() == region.get_id()
+                    && info.leader.as_ref().map_or(false, |l| l.get_id() != peer_id)
             }
-            if let Some(Task::Apply { region_id, .. }) = self.pending_applies.front() {
-                fail_point!("handle_new_pending_applies", |_| {});
-                if !self.engine.can_apply_snapshot(
-                    is_timeout,
-                    new_batch,
-                    *region_id,
-                    self.pending_applies.len(),
-                ) {
-                    // KvEngine can't apply snapshot for other reasons.
-                    SNAP_COUNTER.apply.ingest_delay.inc();
-                    break;
-                }
-                if let Some(Task::Apply {
-                    region_id,
-                    status,
-                    peer_id,
-                    create_time,
-                }) = self.pending_applies.pop_front()
-                {
-                    SNAP_APPLY_WAIT_DURATION_HISTOGRAM
-                        .observe(create_time.saturating_elapsed_secs());
-                    new_batch = false;
-                    self.handle_apply(region_id, peer_id, status);
-                    self.mgr.set_pending_apply_count(self.pending_applies.len());
-                }
+            Err(e) => {
+                warn!(
+                    "failed to query the region leader from PD, keep its queued apply";
+                    "region_id" => region.get_id(),
+                    "err" => %e,
+                );
+                false
             }
         }
-        SNAP_PENDING_APPLIES_GAUGE.set(self.pending_applies.len() as i64);
     }
-}
 
-impl<EK, R, T> Runnable for Runner<EK, R, T>
-where
-    EK: KvEngine,
-    R: CasualRouter<EK> + Send + Clone + 'static,
-    T: PdClient,
-{
-    type Task = Task<EK::Snapshot>;
+    /// Cancels the queued apply task of the region and deletes its snapshot
+    /// files. Returns the bytes freed, or `None` if the task is not queued
+    /// anymore or could not be cancelled, e.g. because the peer is
+    /// concurrently cancelling it itself.
+    fn cancel_queued_apply(&mut self, region_id: u64) -> Option<u64> {
+        // Flip the status while the task is still queued, so on a failed
+        // exchange the queue is left untouched. A task in the queues is by
+        // construction not running, hence a successful exchange from
+        // `JOB_STATUS_PENDING` never interrupts an apply in progress.
+        let cancel = |task: &Task<EK::Snapshot>| match task {
+            Task::Apply { status, .. } => status
+                .compare_exchange(
+                    JOB_STATUS_PENDING,
+                    JOB_STATUS_CANCELLED,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok(),
+            _ => false,
+        };
+        // Without the attached states the snapshot files cannot be located,
+        // so such a task is not a candidate.
+        let is_candidate = |task: &Task<EK::Snapshot>| {
+            matches!(
+                task,
+                Task::Apply {
+                    region_id: id,
+                    apply_state: Some(_),
+                    ..
+                } if *id == region_id
+            )
+        };
+        let task = if let Some(pos) = self.pending_applies.iter().position(|t| is_candidate(t)) {
+            if !cancel(&self.pending_applies[pos]) {
+                return None;
+            }
+            self.pending_applies.remove(pos).unwrap()
+        } else if let Some(pos) = self.delayed_applies.iter().position(|t| is_candidate(t)) {
+            if !cancel(&self.delayed_applies[pos]) {
+                return None;
+            }
+            self.delayed_applies.remove(pos)
+        } else {
+            return None;
+        };
+        let (peer_id, create_time, apply_state, on_finish) = match task {
+            Task::Apply {
+                peer_id,
+                create_time,
+                apply_state,
+                on_finish,
+                ..
+            } => (peer_id, create_time, apply_state, on_finish),
+            _ => unreachable!(),
+        };
+        warn!(
+            "cancel queued snapshot apply to reclaim snapshot directory space";
+            "region_id" => region_id,
+            "peer_id" => peer_id,
+        );
+        self.coprocessor_host
+            .cancel_apply_snapshot(region_id, peer_id);
+        SNAP_COUNTER.apply.abort.inc();
+        SNAP_APPLY_PRESSURE_CANCELLED_COUNTER.inc();
+
+        let truncated = apply_state.as_ref().unwrap().get_truncated_state();
+        let (term, index) = (truncated.get_term(), truncated.get_index());
+        let snap_key = SnapKey::new(region_id, term, index);
+        let snap_size = self.pending_apply_sizes.get(&region_id).copied();
+        let mut freed = 0;
+        let snap = match self.cached_apply_snaps.remove(&snap_key) {
+            Some(s) => Some(s),
+            None => self.mgr.get_snapshot_for_applying(&snap_key).ok(),
+        };
+        if let Some(s) = snap {
+            let size = s.total_size();
+            if self.mgr.delete_snapshot(&snap_key, &s, false) {
+                freed = size;
+                SNAP_APPLY_PRESSURE_FREED_BYTES.inc_by(size);
+            }
+        }
+
+        let entry = SnapApplyHistoryEntry {
+            peer_id,
+            term,
+            index,
+            start_unix_secs: UnixSecs::now().into_inner(),
+            wait_duration_ms: create_time.saturating_elapsed().as_millis() as u64,
+            apply_duration_ms: 0,
+            ingested_bytes: snap_size,
+            result: "pressure_cancel".to_owned(),
+        };
+        if self.apply_history.get(&region_id).is_none() {
+            self.apply_history
+                .insert(region_id, VecDeque::with_capacity(APPLY_HISTORY_PER_REGION));
+        }
+        let history = self.apply_history.get_mut(&region_id).unwrap();
+        if history.len() >= APPLY_HISTORY_PER_REGION {
+            history.pop_front();
+        }
+        history.push_back(entry);
+
+        self.region_cleaner
+            .lock()
+            .unwrap()
+            .deregister_applying_range(region_id);
+        if self.pending_apply_sizes.remove(&region_id).is_some() {
+            self.mgr
+                .set_pending_apply_bytes(self.pending_apply_sizes.values().sum());
+        }
+        self.mgr.set_pending_apply_count(self.queued_apply_count());
+        let failure_count = self
+            .apply_failures
+            .get(&region_id)
+            .map_or(0, |s| s.consecutive_failures);
+        if let Some(cb) = on_finish {
+            let outcome = ApplyOutcome {
+                snap_key,
+                result: "pressure_cancel".to_owned(),
+                apply_duration: Duration::ZERO,
+                ingested_bytes: snap_size,
+            };
+            self.run_on_finish_callback(region_id, peer_id, cb, &outcome);
+        }
+        // The peer sees the cancelled status through `check_applying_snap`
+        // and requests the snapshot from the leader again later.
+        self.notify_snapshot_applied(region_id, peer_id, false, failure_count);
+        Some(freed)
+    }
+
+    /// Reclaims snapshot directory space by cancelling queued applies when
+    /// the usage exceeds `snap_apply_pressure_high_watermark`.
+    ///
+    /// Only tasks that have not started yet are eligible: victims are taken
+    /// from the pending and delayed queues and must still be in
+    /// `JOB_STATUS_PENDING` when cancelled, so an apply in progress is never
+    /// interrupted. Urgent applies are never shed: they are the path back to
+    /// quorum and their region usually has no leader left to re-send the
+    /// snapshot. The largest snapshots go first, the oldest first on equal
+    /// sizes, and only those whose region has a live leader elsewhere to
+    /// re-send the snapshot.
+    fn shed_apply_pressure(&mut self) {
+        let watermark = self.cfg.value().snap_apply_pressure_high_watermark.0;
+        if watermark == 0
+            || (self.pending_applies.is_empty() && self.delayed_applies.is_empty())
+        {
+            return;
+        }
+        let mut usage = match self.mgr.get_total_snap_size() {
+            Ok(size) => size,
+            Err(e) => {
+                warn!("failed to get total snap size"; "err" => %e);
+                return;
+            }
+        };
+        if usage <= watermark {
+            return;
+        }
+        // Tasks whose states could not be read when they were queued have no
+        // recorded size and no snapshot files to reclaim, so they are not
+        // candidates.
+        let mut candidates: Vec<(u64, Instant, metapb::Region, u64)> = self
+            .pending_applies
+            .iter()
+            .chain(&self.delayed_applies)
+            .filter_map(|task| match task {
+                Task::Apply {
+                    region_id,
+                    peer_id,
+                    create_time,
+                    priority: ApplyPriority::Normal,
+                    region_state: Some(region_state),
+                    ..
+                } => self.pending_apply_sizes.get(region_id).map(|size| {
+                    (*size, *create_time, region_state.get_region().clone(), *peer_id)
+                }),
+                _ => None,
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        for (_, _, region, peer_id) in candidates {
+            if usage <= watermark {
+                break;
+            }
+            if !self.snapshot_resendable(&region, peer_id) {
+                continue;
+            }
+            if let Some(freed) = self.cancel_queued_apply(region.get_id()) {
+                usage = usage.saturating_sub(freed);
+            }
+        }
+    }
+
+    /// Tries to apply pending tasks if there is some.
+    fn handle_pending_applies(&mut self, is_timeout: bool) {
+        fail_point!("apply_pending_snapshot", |_| {});
+        // Move back the delayed applies whose backoff has expired, each into
+        // the queue of its own class.
+        if !self.delayed_applies.is_empty() {
+            let mut still_delayed = Vec::new();
+            for task in std::mem::take(&mut self.delayed_applies) {
+                match &task {
+                    Task::Apply { region_id, .. } if self.apply_in_backoff(*region_id) => {
+                        still_delayed.push(task);
+                    }
+                    Task::Apply {
+                        priority: ApplyPriority::Urgent,
+                        ..
+                    } => self.urgent_applies.push_back(task),
+                    _ => self.pending_applies.push_back(task),
+                }
+            }
+            self.delayed_applies = still_delayed;
+        }
+        // Under snapshot directory pressure, reclaim space from the queues
+        // before trying to process what is left of them.
+        self.shed_apply_pressure();
+        let start = Instant::now_coarse();
+        let mut applies_handled = 0;
+        let mut new_batch = true;
+        while self.queued_apply_count() > 0 {
+            // Once the pass has spent its wall-time budget, leave the rest of
+            // the queue for the next run or timeout so destroy and cleanup
+            // tasks sharing the worker thread get a chance to run. The first
+            // apply of a pass always proceeds so progress is guaranteed.
+            if applies_handled > 0
+                && !self.apply_time_budget.is_zero()
+                && start.saturating_elapsed() >= self.apply_time_budget
+            {
+                SNAP_COUNTER.apply.budget_delay.inc();
+                self.mgr.apply_backlog_stats().record_delayed_apply();
+                break;
+            }
+            // should not handle too many applies than the number of files that can be
+            // ingested. check level 0 every time because we can not make sure
+            // how does the number of level 0 files change.
+            if self.ingest_maybe_stall() {
+                SNAP_COUNTER.apply.ingest_delay.inc();
+                self.mgr.apply_backlog_stats().record_delayed_apply();
+                break;
+            }
+            // Urgent applies (unsafe recovery, leader-less regions) are
+            // drained ahead of normal ones, except that after
+            // `URGENT_APPLIES_PER_NORMAL` consecutive urgent applies one
+            // normal apply is processed, so a long urgent burst cannot
+            // starve the normal queue.
+            let from_urgent = !self.urgent_applies.is_empty()
+                && (self.pending_applies.is_empty()
+                    || self.urgent_streak < URGENT_APPLIES_PER_NORMAL);
+            let front = if from_urgent {
+                self.urgent_applies.front()
+            } else {
+                self.pending_applies.front()
+            };
+            if let Some(Task::Apply {
+                region_id, priority, ..
+            }) = front
+            {
+                let (region_id, priority) = (*region_id, *priority);
+                fail_point!("handle_new_pending_applies", |_| {});
+                if self.apply_in_backoff(region_id) {
+                    // The region has failed to apply too many times in a row,
+                    // park the task aside until the backoff expires so other
+                    // regions are not starved.
+                    SNAP_COUNTER.apply.backoff_delay.inc();
+                    self.mgr.apply_backlog_stats().record_delayed_apply();
+                    let task = if from_urgent {
+                        self.urgent_applies.pop_front().unwrap()
+                    } else {
+                        self.pending_applies.pop_front().unwrap()
+                    };
+                    self.delayed_applies.push(task);
+                    continue;
+                }
+                // An urgent apply may be configured to proceed even when the
+                // engine vetoes ingestions, since it restores quorum and the
+                // write stall it may cause is the lesser evil.
+                let bypass_engine_check = priority == ApplyPriority::Urgent
+                    && self.cfg.value().snap_apply_urgent_bypass_engine_check;
+                if !bypass_engine_check
+                    && !self.engine.can_apply_snapshot(
+                        is_timeout,
+                        new_batch,
+                        region_id,
+                        self.queued_apply_count(),
+                    )
+                {
+                    // KvEngine can't apply snapshot for other reasons.
+                    SNAP_COUNTER.apply.ingest_delay.inc();
+                    self.mgr.apply_backlog_stats().record_delayed_apply();
+                    break;
+                }
+                let task = if from_urgent {
+                    self.urgent_streak += 1;
+                    self.urgent_applies.pop_front()
+                } else {
+                    self.urgent_streak = 0;
+                    self.pending_applies.pop_front()
+                };
+                if let Some(Task::Apply {
+                    region_id,
+                    status,
+                    peer_id,
+                    create_time,
+                    priority: _,
+                    region_state,
+                    apply_state,
+                    on_finish,
+                }) = task
+                {
+                    SNAP_APPLY_WAIT_DURATION_HISTOGRAM
+                        .observe(create_time.saturating_elapsed_secs());
+                    self.mgr
+                        .apply_backlog_stats()
+                        .observe_apply_wait(create_time.saturating_elapsed());
+                    new_batch = false;
+                    self.handle_apply(
+                        region_id,
+                        peer_id,
+                        status,
+                        create_time,
+                        region_state,
+                        apply_state,
+                        on_finish,
+                    );
+                    self.mgr.set_pending_apply_count(self.queued_apply_count());
+                    applies_handled += 1;
+                }
+            }
+        }
+        if applies_handled > 0 {
+            SNAP_APPLIES_PER_PASS_HISTOGRAM.observe(applies_handled as f64);
+        }
+        let backlog_stats = self.mgr.apply_backlog_stats();
+        // Applies still waiting in the queues also push the max wait, so a
+        // fully stalled pass does not report a zero wait.
+        for task in self
+            .urgent_applies
+            .iter()
+            .chain(&self.pending_applies)
+            .chain(&self.delayed_applies)
+        {
+            if let Task::Apply { create_time, .. } = task {
+                backlog_stats.observe_apply_wait(create_time.saturating_elapsed());
+            }
+        }
+        backlog_stats.add_busy_time(start.saturating_elapsed());
+        self.flush_applied_notifications();
+        SNAP_PENDING_APPLIES_GAUGE
+            .set((self.queued_apply_count() + self.delayed_applies.len()) as i64);
+    }
+
+    /// Queues a freshly scheduled apply task into its priority class and
+    /// tries to process the queues.
+    fn queue_apply(&mut self, mut task: Task<EK::Snapshot>) {
+        if let Task::Apply {
+            region_id,
+            region_state,
+            apply_state,
+            ..
+        } = &mut task
+        {
+            // Read both states with one engine read and attach them to
+            // the task, so the pre-apply phase and the apply itself
+            // reuse the same copies instead of re-reading CF_RAFT per
+            // phase. If the read fails, the apply will fail on its own
+            // with the same error.
+            if let Ok((rs, aps)) = self.read_apply_states(*region_id) {
+                // Register the apply range before the task is queued
+                // so that destroys running on the cleanup pool defer
+                // physical deletions overlapping it.
+                self.region_cleaner.lock().unwrap().register_applying_range(
+                    *region_id,
+                    keys::enc_start_key(rs.get_region()),
+                    keys::enc_end_key(rs.get_region()),
+                );
+                // Track the byte backlog of queued applies; it is
+                // reported to snapshot senders in snap gen precheck
+                // responses.
+                let term = aps.get_truncated_state().get_term();
+                let idx = aps.get_truncated_state().get_index();
+                let snap_key = SnapKey::new(*region_id, term, idx);
+                if let Ok(s) = self.mgr.get_snapshot_for_applying(&snap_key) {
+                    self.pending_apply_sizes.insert(*region_id, s.total_size());
+                    self.mgr
+                        .set_pending_apply_bytes(self.pending_apply_sizes.values().sum());
+                    // Keep the opened handle so pre-apply and apply
+                    // don't have to open the snapshot again.
+                    self.cached_apply_snaps.insert(snap_key, s);
+                }
+                *region_state = Some(rs);
+                *apply_state = Some(aps);
+            }
+        }
+        if self.coprocessor_host.should_pre_apply_snapshot() {
+            let _ = self.pre_apply_snapshot(&task);
+        }
+        SNAP_COUNTER.apply.all.inc();
+        // to makes sure applying snapshots in order within each
+        // priority class.
+        if matches!(
+            task,
+            Task::Apply {
+                priority: ApplyPriority::Urgent,
+                ..
+            }
+        ) {
+            self.urgent_applies.push_back(task);
+        } else {
+            self.pending_applies.push_back(task);
+        }
+        self.mgr.set_pending_apply_count(self.queued_apply_count());
+        self.handle_pending_applies(false);
+        if self.queued_apply_count() > 0 {
+            // delay the apply and retry later
+            SNAP_COUNTER.apply.delay.inc()
+        }
+    }
+
+    /// Runs one bounded batch of the reconciliation started by
+    /// `Task::ReconcileApplying`. Regions recorded as `PeerState::Applying`
+    /// are cross-checked against the queued applies; a region without one
+    /// either gets its apply re-scheduled from the still-present received
+    /// snapshot, or its peer is notified that the snapshot is lost. The scan
+    /// resumes from the stored cursor on the next timer tick until CF_RAFT is
+    /// exhausted.
+    fn handle_reconcile_applying(&mut self) {
+        let cursor = match self.reconcile_applying_cursor.take() {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let mut next_cursor: Option<Vec<u8>> = None;
+        let mut applying = Vec::new();
+        let mut scanned = 0;
+        let res = self.engine.scan(
+            CF_RAFT,
+            &cursor,
+            keys::REGION_META_MAX_KEY,
+            false,
+            |key, value| {
+                if scanned >= RECONCILE_APPLYING_BATCH_REGIONS {
+                    // The start key of a scan is inclusive, so resuming from
+                    // this key processes it instead of skipping it.
+                    next_cursor = Some(key.to_vec());
+                    return Ok(false);
+                }
+                let (region_id, suffix) = box_try!(keys::decode_region_meta_key(key));
+                if suffix != keys::REGION_STATE_SUFFIX {
+                    return Ok(true);
+                }
+                scanned += 1;
+                let mut local_state = RegionLocalState::default();
+                local_state.merge_from_bytes(value)?;
+                if local_state.get_state() == PeerState::Applying {
+                    applying.push((region_id, local_state));
+                }
+                Ok(true)
+            },
+        );
+        if let Err(e) = res {
+            // Give up the pass instead of resuming; a partial gauge would be
+            // misleading.
+            error!("failed to scan region meta for applying reconciliation"; "err" => %e);
+            return;
+        }
+        for (region_id, local_state) in applying {
+            self.reconcile_applying_region(region_id, local_state);
+        }
+        if next_cursor.is_none() {
+            STUCK_APPLYING_REGIONS_GAUGE.set(self.reconcile_stuck_missing as i64);
+            info!(
+                "reconciled applying regions";
+                "stuck_without_snapshot" => self.reconcile_stuck_missing,
+            );
+        }
+        self.reconcile_applying_cursor = next_cursor;
+    }
+
+    /// Repairs one region the reconciliation scan found in
+    /// `PeerState::Applying` with no queued apply task.
+    fn reconcile_applying_region(&mut self, region_id: u64, local_state: RegionLocalState) {
+        if self.pending_apply_sizes.contains_key(&region_id) {
+            // An apply task is queued, the state is not stuck.
+            RECONCILE_APPLYING_COUNTER_VEC
+                .with_label_values(&["task_present"])
+                .inc();
+            return;
+        }
+        let peer_id = match self.own_peer_id(&local_state) {
+            Some(peer_id) => peer_id,
+            None => {
+                warn!(
+                    "applying region has no peer on this store, skip reconciliation";
+                    "region_id" => region_id,
+                );
+                RECONCILE_APPLYING_COUNTER_VEC
+                    .with_label_values(&["no_peer"])
+                    .inc();
+                return;
+            }
+        };
+        let apply_state: RaftApplyState = match self
+            .engine
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(region_id))
+        {
+            Ok(Some(state)) => state,
+            _ => {
+                warn!(
+                    "failed to read apply state of applying region";
+                    "region_id" => region_id,
+                );
+                return;
+            }
+        };
+        let term = apply_state.get_truncated_state().get_term();
+        let idx = apply_state.get_truncated_state().get_index();
+        let snap_key = SnapKey::new(region_id, term, idx);
+        let snap_usable = self
+            .mgr
+            .get_snapshot_for_applying(&snap_key)
+            .map_or(false, |s| s.exists());
+        if snap_usable {
+            info!(
+                "re-scheduling apply of region stuck in applying state";
+                "region_id" => region_id,
+                "peer_id" => peer_id,
+                "snap_key" => %snap_key,
+            );
+            RECONCILE_APPLYING_COUNTER_VEC
+                .with_label_values(&["rescheduled"])
+                .inc();
+            self.queue_apply(Task::Apply {
+                region_id,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id,
+                create_time: Instant::now_coarse(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            });
+        } else {
+            warn!(
+                "region stuck in applying state has no usable snapshot";
+                "region_id" => region_id,
+                "peer_id" => peer_id,
+                "snap_key" => %snap_key,
+            );
+            RECONCILE_APPLYING_COUNTER_VEC
+                .with_label_values(&["snapshot_missing"])
+                .inc();
+            self.reconcile_stuck_missing += 1;
+            let _ = self
+                .router
+                .send(region_id, CasualMessage::ApplyingSnapshotLost);
+        }
+    }
+
+    /// Returns the id of the peer of `local_state`'s region living on this
+    /// store, resolved through the store ident, or `None` if the ident is
+    /// missing or the region has no peer here.
+    fn own_peer_id(&self, local_state: &RegionLocalState) -> Option<u64> {
+        let ident = self
+            .engine
+            .get_msg::<StoreIdent>(keys::STORE_IDENT_KEY)
+            .ok()
+            .flatten()?;
+        local_state
+            .get_region()
+            .get_peers()
+            .iter()
+            .find(|p| p.get_store_id() == ident.get_store_id())
+            .map(|p| p.get_id())
+    }
+}
+
+impl<EK, R, T> Runnable for Runner<EK, R, T>
+where
+    EK: KvEngine,
+    R: CasualRouter<EK> + Send + Clone + 'static,
+    T: PdClient,
+{
+    type Task = Task<EK::Snapshot>;
 
     fn run(&mut self, task: Task<EK::Snapshot>) {
         match task {
@@ -951,38 +3123,88 @@ where
             }
             task @ Task::Apply { .. } => {
                 fail_point!("on_region_worker_apply", true, |_| {});
-                if self.coprocessor_host.should_pre_apply_snapshot() {
-                    let _ = self.pre_apply_snapshot(&task);
-                }
-                SNAP_COUNTER.apply.all.inc();
-                // to makes sure applying snapshots in order.
-                self.pending_applies.push_back(task);
-                self.mgr.set_pending_apply_count(self.pending_applies.len());
-                self.handle_pending_applies(false);
-                if !self.pending_applies.is_empty() {
-                    // delay the apply and retry later
-                    SNAP_COUNTER.apply.delay.inc()
-                }
+                self.queue_apply(task);
+            }
+            Task::QueryApplyHistory { region_id, cb } => {
+                // Queries must not disturb the recency order, otherwise
+                // polling the status server would keep stale regions alive.
+                let history = self
+                    .apply_history
+                    .get_no_promote(&region_id)
+                    .map_or_else(Vec::new, |h| h.iter().cloned().collect());
+                (cb.0)(history);
             }
             Task::Destroy {
                 region_id,
                 start_key,
                 end_key,
+                size_hint,
             } => {
                 let region_cleaner = self.region_cleaner.clone();
+                let storage_cleaner = self.storage_cleaner.clone();
                 self.region_cleanup_pool
                     .spawn(async move {
                         fail_point!("on_region_worker_destroy", region_id == 1000, |_| {});
+                        if let Some(cleaner) = &storage_cleaner
+                            && cleaner.destroy_region_storage(region_id, &start_key, &end_key)
+                        {
+                            info!(
+                                "destroyed region storage through storage cleaner";
+                                "region_id" => region_id,
+                            );
+                            CLEAN_COUNTER_VEC.with_label_values(&["storage"]).inc();
+                            return;
+                        }
                         let mut region_cleaner = region_cleaner.lock().unwrap();
                         // try to delay the range deletion because
                         // there might be a coprocessor request related to this range
-                        region_cleaner.insert_pending_delete_range(region_id, start_key, end_key);
+                        region_cleaner.insert_pending_delete_range(
+                            region_id, start_key, end_key, size_hint,
+                        );
                         region_cleaner.clean_stale_ranges();
                     })
                     .unwrap_or_else(|e| {
                         error!("failed to destroy region"; "region_id" => region_id, "err" => ?e);
                     });
             }
+            Task::CleanRegion {
+                region_id,
+                force,
+                cb,
+            } => {
+                let region_cleaner = self.region_cleaner.clone();
+                self.region_cleanup_pool
+                    .spawn(async move {
+                        let results =
+                            region_cleaner.lock().unwrap().clean_region(region_id, force);
+                        (cb.0)(results);
+                    })
+                    .unwrap_or_else(|e| {
+                        error!("failed to clean region"; "region_id" => region_id, "err" => ?e);
+                    });
+            }
+            Task::CompactionFinished {
+                cf,
+                start_key,
+                end_key,
+            } => {
+                let region_cleaner = self.region_cleaner.clone();
+                self.region_cleanup_pool
+                    .spawn(async move {
+                        region_cleaner
+                            .lock()
+                            .unwrap()
+                            .on_compaction_finished(&cf, &start_key, &end_key);
+                    })
+                    .unwrap_or_else(|e| {
+                        error!("failed to handle compaction finished event"; "err" => ?e);
+                    });
+            }
+            Task::ReconcileApplying => {
+                self.reconcile_applying_cursor = Some(keys::REGION_META_MIN_KEY.to_vec());
+                self.reconcile_stuck_missing = 0;
+                self.handle_reconcile_applying();
+            }
         }
     }
 }
@@ -1000,6 +3222,10 @@ where
             self.region_cleaner.lock().unwrap().clean_stale_ranges();
             self.clean_stale_tick = 0;
         }
+        self.write_amp_reporter.maybe_report::<EK>();
+        if self.reconcile_applying_cursor.is_some() {
+            self.handle_reconcile_applying();
+        }
     }
 
     fn get_interval(&self) -> Duration {
@@ -1021,11 +3247,15 @@ pub(crate) mod tests {
         kv::{KvTestEngine, KvTestSnapshot},
     };
     use engine_traits::{
-        CompactExt, FlowControlFactorsExt, KvEngine, MiscExt, Mutable, Peekable,
-        RaftEngineReadOnly, SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT, CF_WRITE,
+        CfName, CompactExt, FlowControlFactorsExt, KvEngine, ManualCompactionOptions, MiscExt,
+        Mutable, Peekable, RaftEngineReadOnly, SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT,
+        CF_WRITE,
     };
     use keys::data_key;
-    use kvproto::raft_serverpb::{PeerState, RaftApplyState, RaftSnapshotData, RegionLocalState};
+    use kvproto::{
+        metapb,
+        raft_serverpb::{PeerState, RaftApplyState, RaftSnapshotData, RegionLocalState},
+    };
     use pd_client::RpcClient;
     use protobuf::Message;
     use tempfile::Builder;
@@ -1037,12 +3267,13 @@ pub(crate) mod tests {
     use super::*;
     use crate::{
         coprocessor::{
+            metrics::{APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER, APPLY_SNAPSHOT_HOOK_SKIPPED_COUNTER},
             ApplySnapshotObserver, BoxApplySnapshotObserver, Coprocessor, CoprocessorHost,
-            ObserverContext,
+            DeadlineChecker, ObserverContext,
         },
         store::{
             peer_storage::JOB_STATUS_PENDING, snap::tests::get_test_db_for_regions,
-            worker::RegionRunner, CasualMessage, SnapKey, SnapManager,
+            worker::RegionRunner, CasualMessage, SnapKey, SnapManager, SnapManagerBuilder,
         },
     };
 
@@ -1071,9 +3302,25 @@ pub(crate) mod tests {
             s.as_bytes().to_owned(),
             e.as_bytes().to_owned(),
             stale_sequence,
+            None,
         );
     }
 
+    fn assert_stale_index_consistent(pending_delete_ranges: &PendingDeleteRanges) {
+        let indexed: usize = pending_delete_ranges
+            .stale_index
+            .values()
+            .map(|keys| keys.len())
+            .sum();
+        assert_eq!(indexed, pending_delete_ranges.ranges.len());
+        for (seq, keys) in &pending_delete_ranges.stale_index {
+            assert!(!keys.is_empty());
+            for key in keys {
+                assert_eq!(pending_delete_ranges.ranges[key].stale_sequence, *seq);
+            }
+        }
+    }
+
     #[test]
     #[allow(clippy::string_lit_as_bytes)]
     fn test_pending_delete_ranges() {
@@ -1087,6 +3334,7 @@ pub(crate) mod tests {
         insert_range(&mut pending_delete_ranges, id + 1, "f", "i", timeout1);
         insert_range(&mut pending_delete_ranges, id + 1, "p", "t", timeout1);
         assert_eq!(pending_delete_ranges.len(), 5);
+        assert_stale_index_consistent(&pending_delete_ranges);
 
         //  a____c    f____i    m____n    p____t    x____z
         //              g___________________q
@@ -1103,8 +3351,10 @@ pub(crate) mod tests {
             ]
         );
         assert_eq!(pending_delete_ranges.len(), 2);
+        assert_stale_index_consistent(&pending_delete_ranges);
         insert_range(&mut pending_delete_ranges, id + 2, "g", "q", timeout2);
         assert_eq!(pending_delete_ranges.len(), 3);
+        assert_stale_index_consistent(&pending_delete_ranges);
 
         // at t1, [a, c) and [x, z) will timeout
         {
@@ -1125,6 +3375,7 @@ pub(crate) mod tests {
                 pending_delete_ranges.remove(&start_key);
             }
             assert_eq!(pending_delete_ranges.len(), 1);
+            assert_stale_index_consistent(&pending_delete_ranges);
         }
 
         // at t2, [g, q) will timeout
@@ -1140,7 +3391,42 @@ pub(crate) mod tests {
                 pending_delete_ranges.remove(&start_key);
             }
             assert_eq!(pending_delete_ranges.len(), 0);
+            assert_stale_index_consistent(&pending_delete_ranges);
+        }
+
+        // Re-inserting a start key with a different stale sequence must move
+        // it between the index buckets.
+        let timeout3 = 20;
+        insert_range(&mut pending_delete_ranges, id, "a", "c", timeout1);
+        pending_delete_ranges.remove(b"a");
+        insert_range(&mut pending_delete_ranges, id, "a", "c", timeout3);
+        assert_stale_index_consistent(&pending_delete_ranges);
+        assert_eq!(pending_delete_ranges.stale_ranges(timeout3).count(), 0);
+        let ranges: Vec<_> = pending_delete_ranges.stale_ranges(timeout3 + 1).collect();
+        assert_eq!(ranges, [(id, "a".as_bytes(), "c".as_bytes())]);
+
+        // Draining overlap ranges must also unregister them from the index.
+        insert_range(&mut pending_delete_ranges, id + 1, "e", "h", timeout3);
+        pending_delete_ranges.drain_overlap_ranges(b"a", b"f");
+        assert_eq!(pending_delete_ranges.len(), 0);
+        assert_stale_index_consistent(&pending_delete_ranges);
+        assert_eq!(pending_delete_ranges.stale_ranges(u64::MAX).count(), 0);
+    }
+
+    #[bench]
+    fn bench_stale_ranges_nothing_stale(b: &mut test::Bencher) {
+        let mut pending_delete_ranges = PendingDeleteRanges::default();
+        for i in 0..50_000u64 {
+            let start = format!("k{:010}", i * 2).into_bytes();
+            let end = format!("k{:010}", i * 2 + 1).into_bytes();
+            pending_delete_ranges.insert(i, start, end, 100 + i, None);
         }
+        // The oldest snapshot sequence predates every registered range, so
+        // nothing is stale and the query should return without touching the
+        // range map.
+        b.iter(|| {
+            assert_eq!(pending_delete_ranges.stale_ranges(100).count(), 0);
+        });
     }
 
     #[test]
@@ -1161,7 +3447,9 @@ pub(crate) mod tests {
             cfg,
             CoprocessorHost::<KvTestEngine>::default(),
             router,
+            None,
             Option::<Arc<RpcClient>>::None,
+            None,
         );
         runner.clean_stale_check_interval = Duration::from_millis(100);
 
@@ -1176,11 +3464,13 @@ pub(crate) mod tests {
         let snap = engine.kv.snapshot(None);
         engine.kv.put(b"k2", b"v2").unwrap();
 
+        let stale_tick_samples = CLEAN_STALE_TICK_DURATION_HISTOGRAM.get_sample_count();
         sched
             .schedule(Task::Destroy {
                 region_id: 1,
                 start_key: b"k1".to_vec(),
                 end_key: b"k2".to_vec(),
+                size_hint: Some(1024),
             })
             .unwrap();
         for i in 0..9 {
@@ -1189,6 +3479,7 @@ pub(crate) mod tests {
                     region_id: i as u64 + 2,
                     start_key: ranges[i].clone(),
                     end_key: ranges[i + 1].clone(),
+                    size_hint: None,
                 })
                 .unwrap();
         }
@@ -1201,58 +3492,3093 @@ pub(crate) mod tests {
         for i in 0..9 {
             assert!(engine.kv.get_value(&ranges[i]).unwrap().is_none());
         }
+        // Each destroy runs a stale cleanup pass, so the tick histogram must
+        // have received samples.
+        assert!(CLEAN_STALE_TICK_DURATION_HISTOGRAM.get_sample_count() > stale_tick_samples);
     }
 
+    // The write-amplification reporter turns the counter deltas of one window
+    // into the window gauges. Drive a known apply and destroy through the
+    // worker and check the report reflects them. The underlying counters are
+    // process-global, so the assertions are lower bounds: parallel tests may
+    // add activity to the same window.
     #[test]
-    fn test_pending_applies() {
+    fn test_write_amp_reporter() {
         let temp_dir = Builder::new()
-            .prefix("test_pending_applies")
+            .prefix("test_write_amp_reporter")
             .tempdir()
             .unwrap();
-        let obs = MockApplySnapshotObserver::default();
-        let mut host = CoprocessorHost::<KvTestEngine>::default();
-        host.registry
-            .register_apply_snapshot_observer(1, BoxApplySnapshotObserver::new(obs.clone()));
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
 
-        let mut cf_opts = CfOptions::new();
-        cf_opts.set_level_zero_slowdown_writes_trigger(5);
-        cf_opts.set_disable_auto_compactions(true);
-        let kv_cfs_opts = vec![
-            (CF_DEFAULT, cf_opts.clone()),
-            (CF_WRITE, cf_opts.clone()),
-            (CF_LOCK, cf_opts.clone()),
-            (CF_RAFT, cf_opts.clone()),
-        ];
-        let engine = get_test_db_for_regions(
-            &temp_dir,
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let mut runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
             None,
+            Option::<Arc<RpcClient>>::None,
             None,
-            Some(kv_cfs_opts),
-            &[1, 2, 3, 4, 5, 6, 7],
-        )
-        .unwrap();
+        );
+        runner.clean_stale_check_interval = Duration::from_millis(100);
 
-        for cf_name in &["default", "write", "lock"] {
-            for i in 0..7 {
-                engine
+        // Baseline the reporter before any activity of this test.
+        let mut reporter = WriteAmpReporter::new::<KvTestEngine>();
+
+        // Some payload so the generated snapshot carries a known amount of
+        // data to ingest back.
+        for i in 0..16 {
+            let key = data_key(format!("a{:02}", i).as_bytes());
+            engine.kv.put(&key, &[b'v'; 256]).unwrap();
+        }
+        worker.start_with_timer(runner);
+
+        // Generate and receive a snapshot of region 1 and apply it back.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        let idx = apply_state.get_applied_index();
+        let entry = engine.raft.get_entry(1, idx).unwrap().unwrap();
+        sched
+            .schedule(Task::Gen {
+                region_id: 1,
+                kv_snap: engine.kv.snapshot(None),
+                last_applied_term: entry.get_term(),
+                last_applied_state: apply_state,
+                canceled: Arc::new(AtomicBool::new(false)),
+                notifier: tx,
+                for_balance: false,
+                to_store_id: 0,
+            })
+            .unwrap();
+        let s1 = rx.recv().unwrap();
+        match receiver.recv() {
+            Ok((1, CasualMessage::SnapshotGenerated)) => {}
+            msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+        }
+        let mut data = RaftSnapshotData::default();
+        data.merge_from_bytes(s1.get_data()).unwrap();
+        let key = SnapKey::from_snap(&s1).unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let mut s3 = mgr
+            .get_snapshot_for_receiving(&key, data.take_meta())
+            .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+        let mut wb = engine.kv.write_batch();
+        let mut region_state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        region_state.set_state(PeerState::Applying);
+        wb.put_msg_cf(CF_RAFT, &keys::region_state_key(1), &region_state)
+            .unwrap();
+        wb.write().unwrap();
+
+        let ingested_before: u64 = SNAPSHOT_CFS
+            .iter()
+            .map(|cf| SNAP_APPLY_INGESTED_BYTES_VEC.with_label_values(&[cf]).get())
+            .sum();
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((1, CasualMessage::SnapshotApplied { failure_count, .. })) => {
+                assert_eq!(failure_count, 0);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+        }
+        let ingested: u64 = SNAPSHOT_CFS
+            .iter()
+            .map(|cf| SNAP_APPLY_INGESTED_BYTES_VEC.with_label_values(&[cf]).get())
+            .sum();
+        let ingested_delta = ingested - ingested_before;
+        assert!(ingested_delta > 0);
+
+        // Destroy a range with a known size hint and let the stale cleanup
+        // reclaim it once the pinning snapshot is gone.
+        let reclaimed_before = DELETE_FILES_RECLAIMED_BYTES.get();
+        engine.kv.put(b"k1", b"v1").unwrap();
+        let snap = engine.kv.snapshot(None);
+        sched
+            .schedule(Task::Destroy {
+                region_id: 1,
+                start_key: b"k1".to_vec(),
+                end_key: b"k2".to_vec(),
+                size_hint: Some(2048),
+            })
+            .unwrap();
+        thread::sleep(Duration::from_millis(20));
+        drop(snap);
+        thread::sleep(Duration::from_millis(300));
+        assert!(engine.kv.get_value(b"k1").unwrap().is_none());
+        assert!(DELETE_FILES_RECLAIMED_BYTES.get() >= reclaimed_before + 2048);
+
+        // The window gauges must cover at least the activity driven above.
+        reporter.report::<KvTestEngine>();
+        assert!(
+            WRITE_AMP_WINDOW_BYTES_VEC
+                .with_label_values(&["snapshot_ingest"])
+                .get()
+                >= ingested_delta as i64
+        );
+        assert!(
+            WRITE_AMP_WINDOW_BYTES_VEC
+                .with_label_values(&["delete_files_reclaimed"])
+                .get()
+                >= 2048
+        );
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // A crash between writing `PeerState::Applying` and finishing the apply
+    // can leave a region stuck: the state survives the restart but the apply
+    // task does not. The reconciliation must re-schedule the apply when the
+    // received snapshot is still on disk, and notify the peer when it is
+    // gone.
+    #[test]
+    fn test_reconcile_applying() {
+        let temp_dir = Builder::new()
+            .prefix("test_reconcile_applying")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+
+        // The reconciliation resolves its own peer ids through the store
+        // ident; the test regions live on store 1.
+        let mut ident = StoreIdent::default();
+        ident.set_store_id(1);
+        engine.kv.put_msg(keys::STORE_IDENT_KEY, &ident).unwrap();
+        for i in 0..16 {
+            let key = data_key(format!("a{:02}", i).as_bytes());
+            engine.kv.put(&key, &[b'v'; 256]).unwrap();
+        }
+        worker.start_with_timer(runner);
+
+        // Generate and receive a snapshot of region 1, as if it had arrived
+        // from a leader right before the crash.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        let idx = apply_state.get_applied_index();
+        let entry = engine.raft.get_entry(1, idx).unwrap().unwrap();
+        sched
+            .schedule(Task::Gen {
+                region_id: 1,
+                kv_snap: engine.kv.snapshot(None),
+                last_applied_term: entry.get_term(),
+                last_applied_state: apply_state,
+                canceled: Arc::new(AtomicBool::new(false)),
+                notifier: tx,
+                for_balance: false,
+                to_store_id: 0,
+            })
+            .unwrap();
+        let s1 = rx.recv().unwrap();
+        match receiver.recv() {
+            Ok((1, CasualMessage::SnapshotGenerated)) => {}
+            msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+        }
+        let mut data = RaftSnapshotData::default();
+        data.merge_from_bytes(s1.get_data()).unwrap();
+        let key = SnapKey::from_snap(&s1).unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let mut s3 = mgr
+            .get_snapshot_for_receiving(&key, data.take_meta())
+            .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+
+        // Both regions are recorded as applying, but no apply task exists:
+        // region 1 still has its received snapshot, region 2 does not.
+        let mut wb = engine.kv.write_batch();
+        for id in [1, 2] {
+            let mut region_state: RegionLocalState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::region_state_key(id))
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &keys::region_state_key(id), &region_state)
+                .unwrap();
+        }
+        wb.write().unwrap();
+
+        let rescheduled_before = RECONCILE_APPLYING_COUNTER_VEC
+            .with_label_values(&["rescheduled"])
+            .get();
+        let missing_before = RECONCILE_APPLYING_COUNTER_VEC
+            .with_label_values(&["snapshot_missing"])
+            .get();
+        sched.schedule(Task::ReconcileApplying).unwrap();
+
+        // Region 1 gets its apply re-scheduled and completes, with the peer
+        // id resolved from the store ident.
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((
+                1,
+                CasualMessage::SnapshotApplied {
+                    peer_id: 1,
+                    tombstone: false,
+                    failure_count: 0,
+                },
+            )) => {}
+            msg => panic!("expected SnapshotApplied for region 1, but got {:?}", msg),
+        }
+        assert_eq!(
+            engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(1))
+                .unwrap()
+                .unwrap()
+                .get_state(),
+            PeerState::Normal
+        );
+        // Region 2 has no snapshot to apply, so its peer is told to request
+        // a fresh one and the region counts as stuck.
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((2, CasualMessage::ApplyingSnapshotLost)) => {}
+            msg => panic!("expected ApplyingSnapshotLost for region 2, but got {:?}", msg),
+        }
+        assert_eq!(
+            engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(2))
+                .unwrap()
+                .unwrap()
+                .get_state(),
+            PeerState::Applying
+        );
+        assert_eq!(
+            RECONCILE_APPLYING_COUNTER_VEC
+                .with_label_values(&["rescheduled"])
+                .get(),
+            rescheduled_before + 1
+        );
+        assert_eq!(
+            RECONCILE_APPLYING_COUNTER_VEC
+                .with_label_values(&["snapshot_missing"])
+                .get(),
+            missing_before + 1
+        );
+        // The gauge is published right after the notification was sent; give
+        // the worker thread a moment to get there.
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(STUCK_APPLYING_REGIONS_GAUGE.get(), 1);
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // A destroyed range can be re-occupied before its physical cleanup runs:
+    // the region may be re-created on this store with different boundaries,
+    // or a neighbor may have expanded into part of it. Stale cleanup must
+    // re-check the current region metadata and drop such entries instead of
+    // deleting live data.
+    #[test]
+    fn test_stale_range_overlapping_live_region_is_skipped() {
+        let temp_dir = Builder::new()
+            .prefix("test_stale_range_overlapping_live_region")
+            .tempdir()
+            .unwrap();
+        // No pre-created regions: the live regions of this test are written
+        // below, and the default test region would cover the whole key space.
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let (router, _) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        let region_cleaner = runner.region_cleaner.clone();
+
+        engine.kv.put(&data_key(b"k1a"), b"v1").unwrap();
+        engine.kv.put(&data_key(b"k5a"), b"v5").unwrap();
+        let mut cleaner = region_cleaner.lock().unwrap();
+        // The destroyed region 2 owned [k1, k3) and region 3 owned [k5, k7).
+        cleaner.insert_pending_delete_range(2, data_key(b"k1"), data_key(b"k3"), None);
+        cleaner.insert_pending_delete_range(3, data_key(b"k5"), data_key(b"k7"), None);
+
+        // Before the cleanup runs, region 4 is created on this store covering
+        // part of the first range.
+        let mut region = metapb::Region::default();
+        region.set_id(4);
+        region.set_start_key(b"k2".to_vec());
+        region.set_end_key(b"k4".to_vec());
+        let mut region_state = RegionLocalState::default();
+        region_state.set_region(region);
+        engine
+            .kv
+            .put_msg_cf(CF_RAFT, &keys::region_state_key(4), &region_state)
+            .unwrap();
+
+        // No snapshot is open, so both entries are stale. The first overlaps
+        // the live region 4 and must be dropped without deleting anything,
+        // the second is cleaned up as usual.
+        cleaner.clean_stale_ranges();
+        assert_eq!(
+            engine.kv.get_value(&data_key(b"k1a")).unwrap().unwrap(),
+            b"v1"
+        );
+        assert!(engine.kv.get_value(&data_key(b"k5a")).unwrap().is_none());
+        assert_eq!(cleaner.pending_delete_ranges.len(), 0);
+
+        // A tombstone does not keep a range alive: once region 4 is gone, a
+        // re-registered destroy of the same range proceeds.
+        region_state.set_state(PeerState::Tombstone);
+        engine
+            .kv
+            .put_msg_cf(CF_RAFT, &keys::region_state_key(4), &region_state)
+            .unwrap();
+        cleaner.insert_pending_delete_range(2, data_key(b"k1"), data_key(b"k3"), None);
+        cleaner.clean_stale_ranges();
+        assert!(engine.kv.get_value(&data_key(b"k1a")).unwrap().is_none());
+        assert_eq!(cleaner.pending_delete_ranges.len(), 0);
+    }
+
+    // Cleaning one region by id removes only its pending ranges and its data.
+    // A range an open engine snapshot may still read requires `force`, and
+    // even `force` must not race with a queued or running apply.
+    #[test]
+    fn test_clean_region_by_id() {
+        let temp_dir = Builder::new()
+            .prefix("test_clean_region_by_id")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, _) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        let region_cleaner = runner.region_cleaner.clone();
+        // No timer, so only the scheduled tasks touch the pending ranges.
+        worker.start(runner);
+
+        engine.kv.put(b"k1a", b"v1").unwrap();
+        engine.kv.put(b"k2a", b"v2").unwrap();
+        // Holding a snapshot keeps both registered ranges from going stale.
+        let snap = engine.kv.snapshot(None);
+        sched
+            .schedule(Task::destroy(1, b"k1".to_vec(), b"k2".to_vec()))
+            .unwrap();
+        sched
+            .schedule(Task::destroy(2, b"k2".to_vec(), b"k3".to_vec()))
+            .unwrap();
+        // Wait until both destroys have registered their pending ranges.
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            region_cleaner.lock().unwrap().pending_delete_ranges.len(),
+            2
+        );
+
+        let clean_region = |region_id, force| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            sched
+                .schedule(Task::CleanRegion {
+                    region_id,
+                    force,
+                    cb: CleanRegionCallback(Box::new(move |results| {
+                        tx.send(results).unwrap();
+                    })),
+                })
+                .unwrap();
+            rx.recv_timeout(Duration::from_secs(5)).unwrap()
+        };
+
+        // Without `force` the clean is refused while the snapshot is open.
+        let results = clean_region(1, false);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].outcome, CleanRegionOutcome::NotStale);
+        assert_eq!(engine.kv.get_value(b"k1a").unwrap().unwrap(), b"v1");
+
+        // Even `force` must not race with a queued or running apply.
+        region_cleaner
+            .lock()
+            .unwrap()
+            .register_applying_range(9, b"k1".to_vec(), b"k2".to_vec());
+        let results = clean_region(1, true);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].outcome, CleanRegionOutcome::OverlappingApply);
+        region_cleaner.lock().unwrap().deregister_applying_range(9);
+
+        // Forcing it cleans region 1 and leaves region 2 untouched.
+        let results = clean_region(1, true);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].start_key, b"k1".to_vec());
+        assert_eq!(results[0].end_key, b"k2".to_vec());
+        assert_eq!(results[0].outcome, CleanRegionOutcome::Cleaned);
+        assert!(engine.kv.get_value(b"k1a").unwrap().is_none());
+        assert_eq!(engine.kv.get_value(b"k2a").unwrap().unwrap(), b"v2");
+        {
+            let cleaner = region_cleaner.lock().unwrap();
+            assert_eq!(cleaner.pending_delete_ranges.len(), 1);
+            assert_eq!(cleaner.pending_delete_ranges.region_ranges(2).count(), 1);
+        }
+
+        // Once the snapshot is dropped the remaining region cleans without
+        // `force`.
+        drop(snap);
+        let results = clean_region(2, false);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].outcome, CleanRegionOutcome::Cleaned);
+        assert!(engine.kv.get_value(b"k2a").unwrap().is_none());
+        assert_eq!(
+            region_cleaner.lock().unwrap().pending_delete_ranges.len(),
+            0
+        );
+    }
+
+    // A background compaction can physically drop all data of a pending
+    // delete range long before its stale-sequence condition is met. The
+    // compaction-finished hint marks such ranges and the next cleanup pass
+    // retires them after a bounded verification seek, without issuing any
+    // deletion for them.
+    #[test]
+    fn test_compaction_retires_empty_pending_ranges() {
+        let temp_dir = Builder::new()
+            .prefix("test_compaction_retires_empty_pending_ranges")
+            .tempdir()
+            .unwrap();
+        // No pre-created regions, so the live-region validation does not
+        // interfere with the registered ranges.
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let (router, _) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        let mut cleaner = runner.region_cleaner.lock().unwrap();
+
+        // The data of [k1, k2) is dropped by normal writes plus a compaction,
+        // the data of [k2, k3) stays.
+        engine.kv.put(b"k1a", b"v1").unwrap();
+        engine.kv.put(b"k1b", b"v1").unwrap();
+        engine.kv.put(b"k2a", b"v2").unwrap();
+        engine.kv.flush_cfs(&[], true).unwrap();
+        engine.kv.delete(b"k1a").unwrap();
+        engine.kv.delete(b"k1b").unwrap();
+        engine.kv.flush_cfs(&[], true).unwrap();
+        engine
+            .kv
+            .compact_range_cf(
+                CF_DEFAULT,
+                Some(b"k1"),
+                Some(b"k2"),
+                ManualCompactionOptions::new(false, 1, true),
+            )
+            .unwrap();
+
+        // The open snapshot keeps both ranges from going stale, so the
+        // regular cleanup cannot remove them: an entry disappearing below
+        // can only have been retired through the compaction path, which
+        // issues no deletions.
+        let snap = engine.kv.snapshot(None);
+        cleaner.insert_pending_delete_range(1, b"k1".to_vec(), b"k2".to_vec(), None);
+        cleaner.insert_pending_delete_range(2, b"k2".to_vec(), b"k3".to_vec(), None);
+        let retired = CLEAN_COUNTER_VEC
+            .with_label_values(&["compaction_retired"])
+            .get();
+        cleaner.clean_stale_ranges();
+        assert_eq!(cleaner.pending_delete_ranges.len(), 2);
+
+        // Feed the compaction event covering both ranges. Only the emptied
+        // one is retired; [k2, k3) still holds k2a, so at most its mark is
+        // set and the verification seek clears it again.
+        cleaner.on_compaction_finished(CF_DEFAULT, b"k1", b"k3");
+        cleaner.clean_stale_ranges();
+        assert_eq!(cleaner.pending_delete_ranges.len(), 1);
+        assert!(
+            !cleaner.pending_delete_ranges.ranges[b"k2".as_slice()].probably_empty,
+            "a false hint must be cleared after verification"
+        );
+        assert_eq!(engine.kv.get_value(b"k2a").unwrap().unwrap(), b"v2");
+        assert_eq!(
+            CLEAN_COUNTER_VEC
+                .with_label_values(&["compaction_retired"])
+                .get(),
+            retired + 1
+        );
+
+        // Once the snapshot is gone the remaining range is cleaned through
+        // the regular stale path.
+        drop(snap);
+        cleaner.clean_stale_ranges();
+        assert_eq!(cleaner.pending_delete_ranges.len(), 0);
+        assert!(engine.kv.get_value(b"k2a").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_low_disk_space_delete_fallback() {
+        let temp_dir = Builder::new()
+            .prefix("test_low_disk_space_delete_fallback")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let (router, _) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        let cleaner = runner.region_cleaner.lock().unwrap();
+
+        // With ample free space ranges are deleted through the sst writer.
+        cleaner
+            .disk_free_probe
+            .free_space_override
+            .store(ReadableSize::gb(100).0, Ordering::SeqCst);
+        assert!(matches!(
+            cleaner.delete_range_strategy(),
+            DeleteStrategy::DeleteByWriter { .. }
+        ));
+
+        // Below the watermark cleanups fall back to deleting by key.
+        let fallbacks = CLEAN_COUNTER_VEC
+            .with_label_values(&["ingest_low_disk_fallback"])
+            .get();
+        cleaner
+            .disk_free_probe
+            .free_space_override
+            .store(ReadableSize::mb(1).0, Ordering::SeqCst);
+        assert!(matches!(
+            cleaner.delete_range_strategy(),
+            DeleteStrategy::DeleteByKey
+        ));
+        assert_eq!(
+            CLEAN_COUNTER_VEC
+                .with_label_values(&["ingest_low_disk_fallback"])
+                .get(),
+            fallbacks + 1
+        );
+
+        // The fallback still removes the data.
+        engine.kv.put(b"k1", b"v1").unwrap();
+        engine.kv.put(b"k3", b"v3").unwrap();
+        cleaner
+            .delete_all_in_range(&[Range::new(b"k1", b"k2")])
+            .unwrap();
+        assert!(engine.kv.get_value(b"k1").unwrap().is_none());
+        assert_eq!(engine.kv.get_value(b"k3").unwrap().unwrap(), b"v3");
+    }
+
+    #[derive(Clone, Default)]
+    struct MockStorageCleaner {
+        destroyed: Arc<Mutex<Vec<(u64, Vec<u8>, Vec<u8>)>>>,
+    }
+
+    impl RegionStorageCleaner for MockStorageCleaner {
+        fn destroy_region_storage(&self, region_id: u64, start_key: &[u8], end_key: &[u8]) -> bool {
+            self.destroyed
+                .lock()
+                .unwrap()
+                .push((region_id, start_key.to_vec(), end_key.to_vec()));
+            true
+        }
+    }
+
+    // Failures of the physical deletions (`DeleteFiles`/`DeleteBlobs`) in
+    // the cleanup paths are recovered by retrying or skipping and counted,
+    // instead of panicking the worker.
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_region_cleaner_recovers_from_delete_errors() {
+        let temp_dir = Builder::new()
+            .prefix("test_region_cleaner_recovers_from_delete_errors")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let (router, _) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        let mut cleaner = runner.region_cleaner.lock().unwrap();
+        let recovered =
+            |site: &str| REGION_WORKER_RECOVERED_ERRORS.with_label_values(&[site]).get();
+
+        engine.kv.put(b"q1a", b"v1").unwrap();
+        cleaner.insert_pending_delete_range(1, b"q1".to_vec(), b"q2".to_vec(), None);
+
+        // A failing `DeleteFiles` keeps the range registered for a retry on
+        // the next tick and the data untouched.
+        let before = recovered("stale_delete_files");
+        fail::cfg("region_cleaner_delete_files", "return").unwrap();
+        cleaner.clean_stale_ranges();
+        assert_eq!(recovered("stale_delete_files"), before + 1);
+        assert_eq!(cleaner.pending_delete_ranges.len(), 1);
+        assert_eq!(engine.kv.get_value(b"q1a").unwrap().unwrap(), b"v1");
+        fail::remove("region_cleaner_delete_files");
+
+        // A failing `DeleteBlobs` runs after the deletions by key; the range
+        // stays registered so the blob deletion is retried.
+        let before = recovered("stale_delete_blobs");
+        fail::cfg("region_cleaner_delete_blobs", "return").unwrap();
+        cleaner.clean_stale_ranges();
+        assert_eq!(recovered("stale_delete_blobs"), before + 1);
+        assert_eq!(cleaner.pending_delete_ranges.len(), 1);
+        assert!(engine.kv.get_value(b"q1a").unwrap().is_none());
+        fail::remove("region_cleaner_delete_blobs");
+
+        // With the injections removed the retried cleanup completes.
+        cleaner.clean_stale_ranges();
+        assert_eq!(cleaner.pending_delete_ranges.len(), 0);
+
+        // The overlap cleanup recovers from a failing `DeleteFiles` as well;
+        // the deletions by key still remove the data.
+        engine.kv.put(b"q2a", b"v2").unwrap();
+        cleaner.insert_pending_delete_range(2, b"q2".to_vec(), b"q3".to_vec(), None);
+        let before = recovered("overlap_delete_files");
+        fail::cfg("region_cleaner_delete_files", "return").unwrap();
+        cleaner
+            .clean_overlap_ranges(b"q2".to_vec(), b"q3".to_vec(), None)
+            .unwrap();
+        assert_eq!(recovered("overlap_delete_files"), before + 1);
+        assert!(engine.kv.get_value(b"q2a").unwrap().is_none());
+
+        // A forced region clean still reports `Cleaned` while counting the
+        // recovered physical deletion failures.
+        engine.kv.put(b"q3a", b"v3").unwrap();
+        cleaner.insert_pending_delete_range(3, b"q3".to_vec(), b"q4".to_vec(), None);
+        fail::cfg("region_cleaner_delete_blobs", "return").unwrap();
+        let before_files = recovered("clean_region_delete_files");
+        let before_blobs = recovered("clean_region_delete_blobs");
+        let results = cleaner.clean_region(3, true);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].outcome, CleanRegionOutcome::Cleaned);
+        assert_eq!(recovered("clean_region_delete_files"), before_files + 1);
+        assert_eq!(recovered("clean_region_delete_blobs"), before_blobs + 1);
+        assert!(engine.kv.get_value(b"q3a").unwrap().is_none());
+        assert_eq!(cleaner.pending_delete_ranges.len(), 0);
+        fail::remove("region_cleaner_delete_files");
+        fail::remove("region_cleaner_delete_blobs");
+    }
+
+    // A runner given a storage cleaner reclaims the storage of destroyed
+    // regions through it, e.g. by removing the tablet under partitioned
+    // engines, and issues no range deletes into the engine.
+    #[test]
+    fn test_destroy_with_storage_cleaner() {
+        let temp_dir = Builder::new()
+            .prefix("test_destroy_with_storage_cleaner")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, _) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(false);
+        let cleaner = MockStorageCleaner::default();
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            Some(Arc::new(cleaner.clone())),
+        );
+        worker.start_with_timer(runner);
+
+        engine.kv.put(b"k1", b"v1").unwrap();
+        sched
+            .schedule(Task::destroy(1, b"k1".to_vec(), b"k2".to_vec()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        // The destroy went through the cleaner...
+        assert_eq!(
+            *cleaner.destroyed.lock().unwrap(),
+            vec![(1, b"k1".to_vec(), b"k2".to_vec())]
+        );
+        // ...and no range deletes touched the engine.
+        assert_eq!(engine.kv.get_value(b"k1").unwrap().unwrap(), b"v1");
+    }
+
+    // Destroying a region with many orphan lock cf keys removes them all in
+    // bounded scan+delete passes through the bulk helper and accounts them in
+    // the per-cleanup metric.
+    #[test]
+    fn test_destroy_cleans_lock_cf_in_passes() {
+        let temp_dir = Builder::new()
+            .prefix("test_destroy_cleans_lock_cf_in_passes")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, _) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        // Several passes worth of orphan locks, more than one scan batch.
+        for i in 0..3000 {
+            let key = format!("k1{:04}", i);
+            engine.kv.put_cf(CF_LOCK, key.as_bytes(), b"lock").unwrap();
+        }
+        // A lock right outside the destroyed range must survive.
+        engine.kv.put_cf(CF_LOCK, b"k2", b"lock").unwrap();
+
+        let deleted_before = CLEAN_LOCK_KEYS_HISTOGRAM.get_sample_sum();
+        sched
+            .schedule(Task::destroy(1, b"k1".to_vec(), b"k2".to_vec()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert!(
+            engine
+                .kv
+                .get_value_cf(CF_LOCK, b"k10000")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            engine
+                .kv
+                .get_value_cf(CF_LOCK, b"k12999")
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            engine.kv.get_value_cf(CF_LOCK, b"k2").unwrap().unwrap(),
+            b"lock"
+        );
+        // All deleted locks are accounted in the per-cleanup metric.
+        assert!(CLEAN_LOCK_KEYS_HISTOGRAM.get_sample_sum() - deleted_before >= 3000.0);
+    }
+
+    // Destroying a region stages its temporary delete SST in the configured
+    // alternate directory, and the cleanup still succeeds through the
+    // delete-by-key fallback when that directory becomes unusable.
+    #[test]
+    fn test_destroy_with_ingest_temp_dir() {
+        let temp_dir = Builder::new()
+            .prefix("test_destroy_with_ingest_temp_dir")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let ingest_dir = Builder::new().prefix("ingest_dir").tempdir().unwrap();
+        let ingest_path = ingest_dir.path().join("staging");
+        let mgr = SnapManagerBuilder::default()
+            .ingest_temp_dir(ingest_path.to_str().unwrap())
+            .build(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("region-worker");
+        let mut worker: LazyWorker<Task<KvTestSnapshot>> = bg_worker.lazy_build("region-worker");
+        let sched = worker.scheduler();
+        let (router, _) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(false);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        // Enough keys to make the cleanup build an SST instead of using a
+        // write batch.
+        let fill = |prefix: &str| {
+            for i in 0..2100 {
+                let key = format!("{}{:04}", prefix, i);
+                engine.kv.put(key.as_bytes(), b"v1").unwrap();
+            }
+        };
+        fill("k1");
+        sched
+            .schedule(Task::destroy(1, b"k1".to_vec(), b"k2".to_vec()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert!(engine.kv.get_value(b"k10000").unwrap().is_none());
+        assert!(engine.kv.get_value(b"k12099").unwrap().is_none());
+        // The SST was moved away by the ingestion, nothing lingers in the
+        // staging directory.
+        assert_eq!(std::fs::read_dir(&ingest_path).unwrap().count(), 0);
+
+        // With the staging directory gone building the SST fails, but the
+        // data is still removed by the fallback.
+        std::fs::remove_dir_all(&ingest_path).unwrap();
+        fill("k3");
+        sched
+            .schedule(Task::destroy(1, b"k3".to_vec(), b"k4".to_vec()))
+            .unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert!(engine.kv.get_value(b"k30000").unwrap().is_none());
+        assert!(engine.kv.get_value(b"k32099").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pending_applies() {
+        let temp_dir = Builder::new()
+            .prefix("test_pending_applies")
+            .tempdir()
+            .unwrap();
+        let obs = MockApplySnapshotObserver::default();
+        let mut host = CoprocessorHost::<KvTestEngine>::default();
+        host.registry
+            .register_apply_snapshot_observer(1, BoxApplySnapshotObserver::new(obs.clone()));
+
+        let mut cf_opts = CfOptions::new();
+        cf_opts.set_level_zero_slowdown_writes_trigger(5);
+        cf_opts.set_disable_auto_compactions(true);
+        let kv_cfs_opts = vec![
+            (CF_DEFAULT, cf_opts.clone()),
+            (CF_WRITE, cf_opts.clone()),
+            (CF_LOCK, cf_opts.clone()),
+            (CF_RAFT, cf_opts.clone()),
+        ];
+        let engine = get_test_db_for_regions(
+            &temp_dir,
+            None,
+            None,
+            Some(kv_cfs_opts),
+            &[1, 2, 3, 4, 5, 6, 7],
+        )
+        .unwrap();
+
+        for cf_name in &["default", "write", "lock"] {
+            for i in 0..7 {
+                engine
+                    .kv
+                    .put_cf(cf_name, &data_key(i.to_string().as_bytes()), &[i])
+                    .unwrap();
+                engine
+                    .kv
+                    .put_cf(cf_name, &data_key((i + 1).to_string().as_bytes()), &[i + 1])
+                    .unwrap();
+                engine.kv.flush_cf(cf_name, true).unwrap();
+                // check level 0 files
+                assert_eq!(
+                    engine
+                        .kv
+                        .get_cf_num_files_at_level(cf_name, 0)
+                        .unwrap()
+                        .unwrap(),
+                    u64::from(i) + 1
+                );
+            }
+        }
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr.clone(),
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let gen_and_apply_snap = |id: u64| {
+            // construct snapshot
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            // set applying state
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+
+            // apply snapshot
+            let status = Arc::new(AtomicUsize::new(JOB_STATUS_PENDING));
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status,
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        };
+        let destroy_region = |id: u64| {
+            let start_key = data_key(id.to_string().as_bytes());
+            let end_key = data_key((id + 1).to_string().as_bytes());
+            // destroy region
+            sched
+                .schedule(Task::Destroy {
+                    region_id: id,
+                    start_key,
+                    end_key,
+                    size_hint: None,
+                })
+                .unwrap();
+        };
+
+        let check_region_exist = |id: u64| -> bool {
+            let key = data_key(id.to_string().as_bytes());
+            let v = engine.kv.get_value(&key).unwrap();
+            v.is_some()
+        };
+
+        let wait_apply_finish = |ids: &[u64]| {
+            for id in ids {
+                match receiver.recv_timeout(Duration::from_secs(5)) {
+                    Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                        assert_eq!(region_id, *id);
+                    }
+                    msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+                }
+                let region_key = keys::region_state_key(*id);
+                assert_eq!(
+                    engine
+                        .kv
+                        .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                        .unwrap()
+                        .unwrap()
+                        .get_state(),
+                    PeerState::Normal
+                )
+            }
+        };
+
+        let clean_overlap_samples = CLEAN_OVERLAP_TOTAL_DURATION_HISTOGRAM.get_sample_count();
+        // snapshot will not ingest cause already write stall
+        gen_and_apply_snap(1);
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            7
+        );
+
+        // compact all files to the bottomest level
+        engine.kv.compact_files_in_range(None, None, None).unwrap();
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            0
+        );
+
+        wait_apply_finish(&[1]);
+        // The apply cleans up its overlapping ranges first, which must be
+        // sampled by the end-to-end histogram.
+        assert!(CLEAN_OVERLAP_TOTAL_DURATION_HISTOGRAM.get_sample_count() > clean_overlap_samples);
+        assert_eq!(obs.pre_apply_count.load(Ordering::SeqCst), 1);
+        assert_eq!(obs.post_apply_count.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            obs.pre_apply_hash.load(Ordering::SeqCst),
+            obs.post_apply_hash.load(Ordering::SeqCst)
+        );
+        assert_eq!(obs.cancel_apply.load(Ordering::SeqCst), 0);
+
+        // the pending apply task should be finished and snapshots are ingested.
+        // note that when ingest sst, it may flush memtable if overlap,
+        // so here will two level 0 files.
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            2
+        );
+
+        // no write stall, ingest without delay
+        gen_and_apply_snap(2);
+        wait_apply_finish(&[2]);
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+
+        // snapshot will not ingest cause it may cause write stall
+        gen_and_apply_snap(3);
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+        gen_and_apply_snap(4);
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+        gen_and_apply_snap(5);
+        destroy_region(6);
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+        assert!(check_region_exist(6));
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+
+        // The stalled passes above were reported to the backlog stats shared
+        // with the store health inspector: some applies were delayed and the
+        // parked tasks pushed the max wait.
+        let window = mgr
+            .apply_backlog_stats()
+            .take_window(PENDING_APPLY_CHECK_INTERVAL);
+        assert!(window.delayed_applies > 0);
+        assert!(window.max_apply_wait > Duration::ZERO);
+
+        // compact all files to the bottomest level
+        engine.kv.compact_files_in_range(None, None, None).unwrap();
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            0
+        );
+
+        // make sure have checked pending applies
+        wait_apply_finish(&[3, 4]);
+
+        // before two pending apply tasks should be finished and snapshots are ingested
+        // and one still in pending.
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            4
+        );
+
+        // make sure have checked pending applies
+        engine.kv.compact_files_in_range(None, None, None).unwrap();
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            0
+        );
+        wait_apply_finish(&[5]);
+
+        // the last one pending task finished
+        assert_eq!(
+            engine
+                .kv
+                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .unwrap()
+                .unwrap(),
+            2
+        );
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+        assert!(!check_region_exist(6));
+
+        #[cfg(feature = "failpoints")]
+        {
+            let must_not_finish = |ids: &[u64]| {
+                for id in ids {
+                    let region_key = keys::region_state_key(*id);
+                    assert_eq!(
+                        engine
+                            .kv
+                            .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                            .unwrap()
+                            .unwrap()
+                            .get_state(),
+                        PeerState::Applying
+                    )
+                }
+            };
+
+            engine.kv.compact_files_in_range(None, None, None).unwrap();
+            fail::cfg("handle_new_pending_applies", "return").unwrap();
+            gen_and_apply_snap(7);
+            thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+            must_not_finish(&[7]);
+            fail::remove("handle_new_pending_applies");
+            thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+            wait_apply_finish(&[7]);
+        }
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // Urgent applies jump ahead of queued normal applies once the stall
+    // holding the queues clears, and after `URGENT_APPLIES_PER_NORMAL`
+    // consecutive urgent applies one normal apply is processed, so a long
+    // urgent backlog cannot starve the normal queue.
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_urgent_apply_priority() {
+        let temp_dir = Builder::new()
+            .prefix("test_urgent_apply_priority")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(
+            &temp_dir,
+            None,
+            None,
+            None,
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9],
+        )
+        .unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr.clone(),
+            cfg,
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let gen_and_apply_snap = |id: u64, priority: ApplyPriority| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        };
+
+        let wait_apply_finish = |ids: &[u64]| {
+            for id in ids {
+                match receiver.recv_timeout(Duration::from_secs(5)) {
+                    Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                        assert_eq!(region_id, *id);
+                    }
+                    msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+                }
+                let region_key = keys::region_state_key(*id);
+                assert_eq!(
+                    engine
+                        .kv
+                        .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                        .unwrap()
+                        .unwrap()
+                        .get_state(),
+                    PeerState::Normal
+                )
+            }
+        };
+
+        // Hold the queues closed while the backlog builds up, like a write
+        // stall would.
+        fail::cfg("handle_new_pending_applies", "return").unwrap();
+        for id in [1, 2] {
+            gen_and_apply_snap(id, ApplyPriority::Normal);
+        }
+        for id in [3, 4, 5, 6, 7, 8, 9] {
+            gen_and_apply_snap(id, ApplyPriority::Urgent);
+        }
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+        // Nothing was applied while the stall was active.
+        for id in [1, 2, 3, 4, 5, 6, 7, 8, 9] {
+            let region_key = keys::region_state_key(id);
+            assert_eq!(
+                engine
+                    .kv
+                    .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                    .unwrap()
+                    .unwrap()
+                    .get_state(),
+                PeerState::Applying
+            );
+        }
+
+        // Clear the stall: the urgent applies are drained first, but after
+        // `URGENT_APPLIES_PER_NORMAL` of them one normal apply is processed.
+        fail::remove("handle_new_pending_applies");
+        wait_apply_finish(&[3, 4, 5, 6, 1, 7, 8, 9, 2]);
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with
+        // timer task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // When the snapshot directory grows past the configured high watermark,
+    // queued applies are cancelled largest-first and their snapshot files
+    // deleted, while the rest of the queue and anything already running stay
+    // untouched.
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_shed_apply_pressure() {
+        let temp_dir = Builder::new()
+            .prefix("test_shed_apply_pressure")
+            .tempdir()
+            .unwrap();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2, 3]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let (router, receiver) = mpsc::sync_channel(11);
+        let cfg = make_raftstore_cfg(true);
+        let mut runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr.clone(),
+            cfg.clone(),
+            CoprocessorHost::<KvTestEngine>::default(),
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+
+        // Keep the queued applies from starting, simulating a stalled apply
+        // pipeline.
+        fail::cfg("handle_new_pending_applies", "return").unwrap();
+
+        // The test regions all cover the same range, so writing more data
+        // between the generations makes each snapshot strictly larger than
+        // the previous one. Region 3 gets by far the largest snapshot.
+        let mut statuses = HashMap::default();
+        for id in 1..=3u64 {
+            let key_count = if id < 3 { 2 } else { 200 };
+            for i in 0..key_count {
+                // The region range of the test regions is [a, z).
+                let key = data_key(format!("b{}{:04}", id, i).as_bytes());
+                // Pseudo-random values so that sst compression doesn't erase
+                // the size difference between the snapshots.
+                let mut x = id.wrapping_mul(6364136223846793005).wrapping_add(i as u64);
+                let value: Vec<u8> = (0..1024)
+                    .map(|_| {
+                        x = x
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        (x >> 33) as u8
+                    })
+                    .collect();
+                engine.kv.put(&key, &value).unwrap();
+            }
+
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            Runnable::run(
+                &mut runner,
+                Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                },
+            );
+            let s1 = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            engine.kv.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+
+            let status = Arc::new(AtomicUsize::new(JOB_STATUS_PENDING));
+            statuses.insert(id, (status.clone(), key));
+            Runnable::run(
+                &mut runner,
+                Task::Apply {
+                    region_id: id,
+                    status,
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                },
+            );
+        }
+        assert_eq!(runner.pending_applies.len(), 3);
+        let size3 = runner.pending_apply_sizes[&3];
+        assert!(size3 > runner.pending_apply_sizes[&1]);
+        assert!(size3 > runner.pending_apply_sizes[&2]);
+
+        // With the watermark above the usage nothing is cancelled.
+        let usage = mgr.get_total_snap_size().unwrap();
+        cfg.update(|cfg| -> std::result::Result<(), ()> {
+            cfg.snap_apply_pressure_high_watermark = ReadableSize(usage);
+            Ok(())
+        })
+        .unwrap();
+        runner.handle_pending_applies(true);
+        assert_eq!(runner.pending_applies.len(), 3);
+
+        // Lowering it by the size of the largest queued snapshot sheds
+        // exactly that one.
+        let cancelled_before = SNAP_APPLY_PRESSURE_CANCELLED_COUNTER.get();
+        let freed_before = SNAP_APPLY_PRESSURE_FREED_BYTES.get();
+        cfg.update(|cfg| -> std::result::Result<(), ()> {
+            cfg.snap_apply_pressure_high_watermark = ReadableSize(usage - size3);
+            Ok(())
+        })
+        .unwrap();
+        runner.handle_pending_applies(true);
+
+        let (status3, key3) = &statuses[&3];
+        assert_eq!(status3.load(Ordering::SeqCst), JOB_STATUS_CANCELLED);
+        assert_eq!(runner.pending_applies.len(), 2);
+        assert!(!runner.pending_apply_sizes.contains_key(&3));
+        // The snapshot files of the cancelled apply are gone, the others are
+        // untouched and the usage is back under the watermark.
+        mgr.get_snapshot_for_applying(key3).unwrap_err();
+        mgr.get_snapshot_for_applying(&statuses[&1].1).unwrap();
+        mgr.get_snapshot_for_applying(&statuses[&2].1).unwrap();
+        assert!(mgr.get_total_snap_size().unwrap() <= usage - size3);
+        assert_eq!(
+            SNAP_APPLY_PRESSURE_CANCELLED_COUNTER.get(),
+            cancelled_before + 1
+        );
+        assert_eq!(SNAP_APPLY_PRESSURE_FREED_BYTES.get(), freed_before + size3);
+        // The peer is notified so it re-requests the snapshot later, and the
+        // cancellation shows up in the apply history.
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((
+                3,
+                CasualMessage::SnapshotApplied {
+                    peer_id: 1,
+                    tombstone: false,
+                    ..
+                },
+            )) => {}
+            msg => panic!("expected SnapshotApplied for region 3, but got {:?}", msg),
+        }
+        let history = runner.apply_history.get(&3).unwrap();
+        assert_eq!(history.back().unwrap().result, "pressure_cancel");
+
+        // The remaining applies are intact and complete once the stall
+        // clears.
+        fail::remove("handle_new_pending_applies");
+        runner.handle_pending_applies(true);
+        for id in 1..=2u64 {
+            assert_eq!(
+                statuses[&id].0.load(Ordering::SeqCst),
+                JOB_STATUS_FINISHED
+            );
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+            }
+        }
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_failure_backoff() {
+        const TEST_BACKOFF: Duration = Duration::from_millis(500);
+
+        let temp_dir = Builder::new()
+            .prefix("test_apply_failure_backoff")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let mut runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        // Shorten the backoff so the test completes quickly.
+        runner.apply_failure_backoff = TEST_BACKOFF;
+        worker.start_with_timer(runner);
+
+        // Generates and saves the snapshot of the region and marks it as
+        // applying, but does not schedule the apply yet.
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        let schedule_apply = |id: u64| {
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        };
+        let recv_applied = |id: u64| -> u64 {
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((
+                    region_id,
+                    CasualMessage::SnapshotApplied { failure_count, .. },
+                )) => {
+                    assert_eq!(region_id, id);
+                    failure_count
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+        };
+
+        prepare_snap(1);
+        prepare_snap(2);
+
+        // All applies of region 1 fail.
+        fail::cfg("region_apply_snap_failure", "return").unwrap();
+
+        // The first failures are retried without any backoff.
+        for i in 1..=APPLY_FAILURE_BACKOFF_THRESHOLD {
+            schedule_apply(1);
+            assert_eq!(recv_applied(1), i);
+        }
+
+        // Region 1 is now in backoff, so its next apply is parked aside and
+        // region 2's apply queued behind it completes promptly.
+        let backoff_start = std::time::Instant::now();
+        schedule_apply(1);
+        schedule_apply(2);
+        assert_eq!(recv_applied(2), 0);
+        assert!(backoff_start.elapsed() < TEST_BACKOFF);
+
+        // The parked apply of region 1 is retried only after the backoff.
+        assert_eq!(recv_applied(1), APPLY_FAILURE_BACKOFF_THRESHOLD + 1);
+        assert!(backoff_start.elapsed() >= TEST_BACKOFF - Duration::from_millis(100));
+
+        // A successful apply resets the failure tracking.
+        fail::remove("region_apply_snap_failure");
+        let retry_start = std::time::Instant::now();
+        schedule_apply(1);
+        assert_eq!(recv_applied(1), 0);
+        assert!(retry_start.elapsed() >= TEST_BACKOFF - Duration::from_millis(100));
+        let region_key = keys::region_state_key(1);
+        assert_eq!(
+            engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap()
+                .get_state(),
+            PeerState::Normal
+        );
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // The region and apply states are read once when the apply task is queued
+    // and reused by both phases; the peer state is the one field re-validated
+    // right before the ingest. An apply whose peer is no longer applying by
+    // the time the parked task runs must be rejected instead of ingesting.
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_rejected_when_peer_state_changed() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_rejected_when_peer_state_changed")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        // Generate and save the snapshot and mark the region as applying.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        let idx = apply_state.get_applied_index();
+        let entry = engine.raft.get_entry(1, idx).unwrap().unwrap();
+        sched
+            .schedule(Task::Gen {
+                region_id: 1,
+                kv_snap: engine.kv.snapshot(None),
+                last_applied_term: entry.get_term(),
+                last_applied_state: apply_state,
+                canceled: Arc::new(AtomicBool::new(false)),
+                notifier: tx,
+                for_balance: false,
+                to_store_id: 0,
+            })
+            .unwrap();
+        let s1 = rx.recv().unwrap();
+        match receiver.recv() {
+            Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                assert_eq!(region_id, 1);
+            }
+            msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+        }
+        let mut data = RaftSnapshotData::default();
+        data.merge_from_bytes(s1.get_data()).unwrap();
+        let key = SnapKey::from_snap(&s1).unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let mut s3 = mgr
+            .get_snapshot_for_receiving(&key, data.take_meta())
+            .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+
+        let region_key = keys::region_state_key(1);
+        let mut region_state = engine
+            .kv
+            .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+            .unwrap()
+            .unwrap();
+        region_state.set_state(PeerState::Applying);
+        let mut wb = engine.kv.write_batch();
+        wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+        wb.write().unwrap();
+
+        // Park the queued apply so the peer state can change under it. The
+        // states are still attached when the task is queued.
+        fail::cfg("handle_new_pending_applies", "return").unwrap();
+        let (tx, rx) = mpsc::sync_channel(1);
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: Some(ApplyCallback(Box::new(move |outcome| {
+                    tx.send(outcome.clone()).unwrap();
+                }))),
+            })
+            .unwrap();
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+
+        // The peer moved on while the task was parked.
+        region_state.set_state(PeerState::Normal);
+        let mut wb = engine.kv.write_batch();
+        wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+        wb.write().unwrap();
+
+        fail::remove("handle_new_pending_applies");
+        // The revalidation right before the ingest rejects the apply.
+        let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(
+            outcome.result.contains("while the apply was queued"),
+            "unexpected result: {}",
+            outcome.result
+        );
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((
+                1,
+                CasualMessage::SnapshotApplied {
+                    tombstone: true, ..
+                },
+            )) => {}
+            msg => panic!("expected failed SnapshotApplied, but got {:?}", msg),
+        }
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[test]
+    fn test_snap_apply_history() {
+        let temp_dir = Builder::new()
+            .prefix("test_snap_apply_history")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2, 3]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let mut runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        // Shrink the LRU so its eviction can be exercised with three regions.
+        runner.apply_history = LruCache::with_capacity(2);
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        let apply_snap = |id: u64| {
+            prepare_snap(id);
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+        };
+        let query_history = |id: u64| -> Vec<SnapApplyHistoryEntry> {
+            let (tx, rx) = mpsc::sync_channel(1);
+            sched
+                .schedule(Task::QueryApplyHistory {
+                    region_id: id,
+                    cb: ApplyHistoryCallback(Box::new(move |history| tx.send(history).unwrap())),
+                })
+                .unwrap();
+            rx.recv_timeout(Duration::from_secs(5)).unwrap()
+        };
+
+        // Two applies of the same region, the second one of a snapshot taken
+        // after more writes.
+        apply_snap(1);
+        engine.kv.put(&data_key(b"1extra"), b"v").unwrap();
+        apply_snap(1);
+
+        let history = query_history(1);
+        assert_eq!(history.len(), 2);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        for entry in &history {
+            assert_eq!(entry.peer_id, 1);
+            assert_eq!(entry.term, apply_state.get_truncated_state().get_term());
+            assert_eq!(entry.index, apply_state.get_truncated_state().get_index());
+            assert_eq!(entry.result, "success");
+            assert!(entry.ingested_bytes.unwrap() > 0);
+            assert!(entry.start_unix_secs > 0);
+        }
+        assert!(history[0].start_unix_secs <= history[1].start_unix_secs);
+        assert!(query_history(2).is_empty());
+
+        // Applying snapshots of two more regions exceeds the LRU cap of two,
+        // so the history of region 1, the least recently applied, is evicted.
+        apply_snap(2);
+        apply_snap(3);
+        assert!(query_history(1).is_empty());
+        assert_eq!(query_history(2).len(), 1);
+        assert_eq!(query_history(3).len(), 1);
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_on_finish_callback() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_on_finish_callback")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2, 3]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        // Runs an apply whose callback counts its invocations and forwards
+        // the outcome, and returns the outcome once the peer notification
+        // confirmed the apply has fully finished.
+        let apply_with_callback = |id: u64, status: usize| -> ApplyOutcome {
+            prepare_snap(id);
+            let calls = Arc::new(AtomicUsize::new(0));
+            let calls_cb = calls.clone();
+            let (tx, rx) = mpsc::sync_channel(1);
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(status)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: Some(ApplyCallback(Box::new(move |outcome| {
+                        calls_cb.fetch_add(1, Ordering::SeqCst);
+                        tx.send(outcome.clone()).unwrap();
+                    }))),
+                })
+                .unwrap();
+            // The callback must have run before the casual message was sent.
+            let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let truncated = apply_state.get_truncated_state();
+            assert_eq!(
+                outcome.snap_key,
+                SnapKey::new(id, truncated.get_term(), truncated.get_index())
+            );
+            assert!(outcome.ingested_bytes.unwrap() > 0);
+            outcome
+        };
+
+        // Success path.
+        let outcome = apply_with_callback(2, JOB_STATUS_PENDING);
+        assert_eq!(outcome.result, "success");
+
+        // Abort path: the status is already cancelling when the worker picks
+        // the task up.
+        let outcome = apply_with_callback(3, JOB_STATUS_CANCELLING);
+        assert_eq!(outcome.result, "abort");
+
+        // Failure path: the failpoint fails applies of region 1.
+        fail::cfg("region_apply_snap_failure", "return").unwrap();
+        let outcome = apply_with_callback(1, JOB_STATUS_PENDING);
+        assert!(outcome.result.starts_with("fail:"), "{}", outcome.result);
+        fail::remove("region_apply_snap_failure");
+
+        // A panicking callback is caught and the peer is still notified.
+        prepare_snap(2);
+        sched
+            .schedule(Task::Apply {
+                region_id: 2,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: Some(ApplyCallback(Box::new(|_| panic!("broken consumer")))),
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                assert_eq!(region_id, 2);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+        }
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    // An engine write failure on the final region-state write fails that
+    // apply (and only that apply): the peer is notified, the counter is
+    // incremented and the worker keeps applying subsequent snapshots.
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_write_state_failure_fails_apply() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_write_state_failure_fails_apply")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[2, 3]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        let apply = |id: u64| -> ApplyOutcome {
+            prepare_snap(id);
+            let (tx, rx) = mpsc::sync_channel(1);
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: Some(ApplyCallback(Box::new(move |outcome| {
+                        tx.send(outcome.clone()).unwrap();
+                    }))),
+                })
+                .unwrap();
+            let outcome = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+            outcome
+        };
+
+        let recovered = || {
+            REGION_WORKER_RECOVERED_ERRORS
+                .with_label_values(&["apply_write_state"])
+                .get()
+        };
+        let before = recovered();
+        fail::cfg("apply_snap_write_state", "return").unwrap();
+        let outcome = apply(2);
+        assert!(outcome.result.starts_with("fail:"), "{}", outcome.result);
+        assert_eq!(recovered(), before + 1);
+        // The state write never happened, so the region is still applying.
+        let state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.get_state(), PeerState::Applying);
+        fail::remove("apply_snap_write_state");
+
+        // The worker keeps processing: the next apply goes through.
+        let outcome = apply(3);
+        assert_eq!(outcome.result, "success");
+        let state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.get_state(), PeerState::Normal);
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_concurrent_ingest_apply() {
+        let temp_dir = Builder::new()
+            .prefix("test_concurrent_ingest_apply")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2]).unwrap();
+
+        // Write data in every cf so both the default and write cf have an sst
+        // file to ingest.
+        for cf_name in &["default", "write", "lock"] {
+            engine.kv.put_cf(cf_name, &data_key(b"1k"), b"v1").unwrap();
+            engine.kv.put_cf(cf_name, &data_key(b"2k"), b"v2").unwrap();
+        }
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        cfg.update(|cfg| -> std::result::Result<(), ()> {
+            cfg.snap_apply_ingest_concurrency = 2;
+            Ok(())
+        })
+        .unwrap();
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        let schedule_apply = |id: u64| {
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        };
+        let recv_applied = |id: u64| -> u64 {
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((
+                    region_id,
+                    CasualMessage::SnapshotApplied { failure_count, .. },
+                )) => {
+                    assert_eq!(region_id, id);
+                    failure_count
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+        };
+        let region_peer_state = |id: u64| -> PeerState {
+            engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(id))
+                .unwrap()
+                .unwrap()
+                .get_state()
+        };
+
+        prepare_snap(1);
+        prepare_snap(2);
+
+        // An injected ingestion failure in one cf must fail the whole apply
+        // and the final state must not be written.
+        fail::cfg("apply_snap_ingest_sst", "return").unwrap();
+        schedule_apply(1);
+        assert_eq!(recv_applied(1), 1);
+        assert_eq!(region_peer_state(1), PeerState::Applying);
+        fail::remove("apply_snap_ingest_sst");
+
+        // With two concurrent ingestions the applies succeed and the data is
+        // complete.
+        schedule_apply(1);
+        assert_eq!(recv_applied(1), 0);
+        assert_eq!(region_peer_state(1), PeerState::Normal);
+        schedule_apply(2);
+        assert_eq!(recv_applied(2), 0);
+        assert_eq!(region_peer_state(2), PeerState::Normal);
+        for cf_name in &["default", "write", "lock"] {
+            assert_eq!(
+                engine
+                    .kv
+                    .get_value_cf(cf_name, &data_key(b"1k"))
+                    .unwrap()
+                    .unwrap(),
+                b"v1"
+            );
+            assert_eq!(
+                engine
+                    .kv
+                    .get_value_cf(cf_name, &data_key(b"2k"))
+                    .unwrap()
+                    .unwrap(),
+                b"v2"
+            );
+        }
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_pipelined_verify_apply() {
+        let temp_dir = Builder::new()
+            .prefix("test_pipelined_verify_apply")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1, 2]).unwrap();
+
+        // Write data in every cf so each apply replays a plain lock cf file
+        // and ingests two sst files, enough stages for the pipeline to show.
+        for cf_name in &["default", "write", "lock"] {
+            engine.kv.put_cf(cf_name, &data_key(b"1k"), b"v1").unwrap();
+            engine.kv.put_cf(cf_name, &data_key(b"2k"), b"v2").unwrap();
+        }
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        cfg.update(|cfg| -> std::result::Result<(), ()> {
+            cfg.snap_apply_pipelined_verification = true;
+            Ok(())
+        })
+        .unwrap();
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+        let schedule_apply = |id: u64| {
+            sched
+                .schedule(Task::Apply {
+                    region_id: id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        };
+        let recv_applied = |id: u64| -> u64 {
+            match receiver.recv_timeout(Duration::from_secs(5)) {
+                Ok((
+                    region_id,
+                    CasualMessage::SnapshotApplied { failure_count, .. },
+                )) => {
+                    assert_eq!(region_id, id);
+                    failure_count
+                }
+                msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
+            }
+        };
+        let region_peer_state = |id: u64| -> PeerState {
+            engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(id))
+                .unwrap()
+                .unwrap()
+                .get_state()
+        };
+
+        prepare_snap(1);
+        prepare_snap(2);
+
+        // An injected verification failure in the write cf, the last file of
+        // the pipeline, must fail the whole apply even though the ingestion
+        // of the default cf has already run, and the final state must not be
+        // written.
+        fail::cfg("snap_apply_verify_cf", "return").unwrap();
+        schedule_apply(1);
+        assert_eq!(recv_applied(1), 1);
+        assert_eq!(region_peer_state(1), PeerState::Applying);
+        fail::remove("snap_apply_verify_cf");
+
+        schedule_apply(1);
+        assert_eq!(recv_applied(1), 0);
+        assert_eq!(region_peer_state(1), PeerState::Normal);
+
+        // With an artificial delay in each stage the total time shows the
+        // verification of a file overlapping the ingestion of the previous
+        // one: three files spend 3 * 400ms verifying and the two ssts spend
+        // 2 * 400ms ingesting, which only fits in well under the serial 2s
+        // when the stages overlap.
+        fail::cfg("snap_apply_verify_delay", "sleep(400)").unwrap();
+        fail::cfg("snap_apply_ingest_delay", "sleep(400)").unwrap();
+        let timer = Instant::now();
+        schedule_apply(2);
+        assert_eq!(recv_applied(2), 0);
+        let elapsed = timer.saturating_elapsed();
+        fail::remove("snap_apply_verify_delay");
+        fail::remove("snap_apply_ingest_delay");
+        assert_eq!(region_peer_state(2), PeerState::Normal);
+        assert!(
+            elapsed < Duration::from_millis(1900),
+            "apply not pipelined: {:?}",
+            elapsed
+        );
+
+        for cf_name in &["default", "write", "lock"] {
+            assert_eq!(
+                engine
                     .kv
-                    .put_cf(cf_name, &data_key(i.to_string().as_bytes()), &[i])
-                    .unwrap();
+                    .get_value_cf(cf_name, &data_key(b"1k"))
+                    .unwrap()
+                    .unwrap(),
+                b"v1"
+            );
+            assert_eq!(
                 engine
                     .kv
-                    .put_cf(cf_name, &data_key((i + 1).to_string().as_bytes()), &[i + 1])
-                    .unwrap();
-                engine.kv.flush_cf(cf_name, true).unwrap();
-                // check level 0 files
-                assert_eq!(
-                    engine
-                        .kv
-                        .get_cf_num_files_at_level(cf_name, 0)
-                        .unwrap()
-                        .unwrap(),
-                    u64::from(i) + 1
-                );
+                    .get_value_cf(cf_name, &data_key(b"2k"))
+                    .unwrap()
+                    .unwrap(),
+                b"v2"
+            );
+        }
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_destroy_deferred_by_overlapping_apply() {
+        let temp_dir = Builder::new()
+            .prefix("test_destroy_deferred_by_overlapping_apply")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        for cf_name in &["default", "write", "lock"] {
+            engine.kv.put_cf(cf_name, &data_key(b"b1"), b"v1").unwrap();
+        }
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+
+        prepare_snap(1);
+        // The marker is not part of the snapshot, so it only survives as long
+        // as the destroy of its range has not been carried out.
+        engine.kv.put(&data_key(b"marker"), b"m").unwrap();
+
+        // Park the apply in the pending queue; its range is registered
+        // nevertheless.
+        fail::cfg("apply_pending_snapshot", "return").unwrap();
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        // A destroy of a stale peer overlapping the queued apply must be
+        // deferred: the data in the range stays put while the apply is
+        // pending, even across several stale cleanup ticks.
+        sched
+            .schedule(Task::Destroy {
+                region_id: 100,
+                start_key: data_key(b"a"),
+                end_key: data_key(b"z"),
+                size_hint: None,
+            })
+            .unwrap();
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+        assert_eq!(
+            engine.kv.get_value(&data_key(b"marker")).unwrap().unwrap(),
+            b"m"
+        );
+
+        // Once the apply is allowed to run, its own cleanup consumes the
+        // pending destroy before the ingest, so the destroy completes and the
+        // applied data survives.
+        fail::remove("apply_pending_snapshot");
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
+                assert_eq!(region_id, 1);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+        }
+        let region_state = engine
+            .kv
+            .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(region_state.get_state(), PeerState::Normal);
+        assert!(engine.kv.get_value(&data_key(b"marker")).unwrap().is_none());
+        for cf_name in &["default", "write", "lock"] {
+            assert_eq!(
+                engine
+                    .kv
+                    .get_value_cf(cf_name, &data_key(b"b1"))
+                    .unwrap()
+                    .unwrap(),
+                b"v1"
+            );
+        }
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_batched_applied_notifications() {
+        let temp_dir = Builder::new()
+            .prefix("test_batched_applied_notifications")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let region_ids: Vec<u64> = (1..=6).collect();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &region_ids).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(10);
+        let (store_tx, store_rx) = mpsc::sync_channel::<StoreMsg<KvTestEngine>>(10);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            Some(Box::new(store_tx)),
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
             }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+
+        for id in &region_ids {
+            prepare_snap(*id);
+        }
+
+        // Park the applies so they pile up in the pending queue and are all
+        // carried out by a single timer pass once the failpoint is lifted.
+        fail::cfg("apply_pending_snapshot", "return").unwrap();
+        for id in &region_ids {
+            sched
+                .schedule(Task::Apply {
+                    region_id: *id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+        assert!(store_rx.try_recv().is_err());
+        fail::remove("apply_pending_snapshot");
+
+        // All completions of the pass arrive as one batched store message.
+        let applied = match store_rx.recv_timeout(Duration::from_secs(5)) {
+            Ok(StoreMsg::SnapshotsApplied(applied)) => applied,
+            msg => panic!("expected SnapshotsApplied, but got {:?}", msg),
+        };
+        let mut applied_regions: Vec<u64> = applied
+            .iter()
+            .map(|&(region_id, peer_id, tombstone, failure_count)| {
+                assert_eq!(peer_id, 1);
+                assert!(!tombstone);
+                assert_eq!(failure_count, 0);
+                region_id
+            })
+            .collect();
+        applied_regions.sort_unstable();
+        assert_eq!(applied_regions, region_ids);
+        for id in &region_ids {
+            let region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(*id))
+                .unwrap()
+                .unwrap();
+            assert_eq!(region_state.get_state(), PeerState::Normal);
+        }
+        // No per-region message sneaks through the casual router and later
+        // passes with nothing applied send no batch at all.
+        assert!(receiver.try_recv().is_err());
+        assert!(store_rx.recv_timeout(PENDING_APPLY_CHECK_INTERVAL * 2).is_err());
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_resumes_from_recorded_cf_progress() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_resumes_from_recorded_cf_progress")
+            .tempdir()
+            .unwrap();
+        let obs = MockApplySnapshotObserver::default();
+        let mut host = CoprocessorHost::<KvTestEngine>::default();
+        host.registry
+            .register_apply_snapshot_observer(1, BoxApplySnapshotObserver::new(obs.clone()));
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        for cf_name in &["default", "write", "lock"] {
+            engine.kv.put_cf(cf_name, &data_key(b"a1"), b"v1").unwrap();
         }
 
         let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
@@ -1266,15 +6592,16 @@ pub(crate) mod tests {
         let runner = RegionRunner::new(
             engine.kv.clone(),
             mgr,
-            cfg,
-            host,
+            cfg.clone(),
+            host.clone(),
             router,
+            None,
             Option::<Arc<RpcClient>>::None,
+            None,
         );
         worker.start_with_timer(runner);
 
-        let gen_and_apply_snap = |id: u64| {
-            // construct snapshot
+        let prepare_snap = |id: u64| {
             let (tx, rx) = mpsc::sync_channel(1);
             let apply_state: RaftApplyState = engine
                 .kv
@@ -1313,7 +6640,6 @@ pub(crate) mod tests {
             io::copy(&mut s2, &mut s3).unwrap();
             s3.save().unwrap();
 
-            // set applying state
             let mut wb = engine.kv.write_batch();
             let region_key = keys::region_state_key(id);
             let mut region_state = engine
@@ -1324,226 +6650,640 @@ pub(crate) mod tests {
             region_state.set_state(PeerState::Applying);
             wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
             wb.write().unwrap();
-
-            // apply snapshot
-            let status = Arc::new(AtomicUsize::new(JOB_STATUS_PENDING));
-            sched
-                .schedule(Task::Apply {
-                    region_id: id,
-                    status,
-                    peer_id: 1,
-                    create_time: Instant::now(),
-                })
-                .unwrap();
-        };
-        let destroy_region = |id: u64| {
-            let start_key = data_key(id.to_string().as_bytes());
-            let end_key = data_key((id + 1).to_string().as_bytes());
-            // destroy region
-            sched
-                .schedule(Task::Destroy {
-                    region_id: id,
-                    start_key,
-                    end_key,
-                })
-                .unwrap();
         };
 
-        let check_region_exist = |id: u64| -> bool {
-            let key = data_key(id.to_string().as_bytes());
-            let v = engine.kv.get_value(&key).unwrap();
-            v.is_some()
-        };
+        prepare_snap(1);
 
-        let wait_apply_finish = |ids: &[u64]| {
-            for id in ids {
-                match receiver.recv_timeout(Duration::from_secs(5)) {
-                    Ok((region_id, CasualMessage::SnapshotApplied { .. })) => {
-                        assert_eq!(region_id, *id);
-                    }
-                    msg => panic!("expected {} SnapshotApplied, but got {:?}", id, msg),
-                }
-                let region_key = keys::region_state_key(*id);
-                assert_eq!(
-                    engine
-                        .kv
-                        .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
-                        .unwrap()
-                        .unwrap()
-                        .get_state(),
-                    PeerState::Normal
-                )
+        // The first attempt ingests the default cf and then "crashes" on the
+        // write cf.
+        fail::cfg("apply_snap_ingest_sst", "return").unwrap();
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((1, CasualMessage::SnapshotApplied { failure_count, .. })) => {
+                assert_eq!(failure_count, 1);
             }
-        };
-
-        // snapshot will not ingest cause already write stall
-        gen_and_apply_snap(1);
-        assert_eq!(
+            msg => panic!("expected failed SnapshotApplied, but got {:?}", msg),
+        }
+        fail::remove("apply_snap_ingest_sst");
+        assert_eq!(*obs.apply_sst_cfs.lock().unwrap(), vec!["default"]);
+        // The progress record survives the failed attempt.
+        assert!(
             engine
                 .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .get_value_cf(CF_RAFT, &keys::snapshot_apply_progress_key(1))
                 .unwrap()
-                .unwrap(),
-            7
+                .is_some()
         );
+        bg_worker.stop();
 
-        // compact all files to the bottomest level
-        engine.kv.compact_files_in_range(None, None, None).unwrap();
-        assert_eq!(
+        // "Restart" by building a fresh runner over the same engine and
+        // snapshot directory, and re-run the apply.
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((
+                1,
+                CasualMessage::SnapshotApplied {
+                    tombstone,
+                    failure_count,
+                    ..
+                },
+            )) => {
+                assert!(!tombstone);
+                assert_eq!(failure_count, 0);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+        }
+
+        // The default cf was not re-ingested, only the write cf was.
+        assert_eq!(*obs.apply_sst_cfs.lock().unwrap(), vec!["default", "write"]);
+        let region_state = engine
+            .kv
+            .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(region_state.get_state(), PeerState::Normal);
+        // The record is cleared together with the final region state write.
+        assert!(
             engine
                 .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .get_value_cf(CF_RAFT, &keys::snapshot_apply_progress_key(1))
                 .unwrap()
-                .unwrap(),
-            0
+                .is_none()
         );
+        for cf_name in &["default", "write", "lock"] {
+            assert_eq!(
+                engine
+                    .kv
+                    .get_value_cf(cf_name, &data_key(b"a1"))
+                    .unwrap()
+                    .unwrap(),
+                b"v1"
+            );
+        }
 
-        wait_apply_finish(&[1]);
-        assert_eq!(obs.pre_apply_count.load(Ordering::SeqCst), 1);
-        assert_eq!(obs.post_apply_count.load(Ordering::SeqCst), 1);
-        assert_eq!(
-            obs.pre_apply_hash.load(Ordering::SeqCst),
-            obs.post_apply_hash.load(Ordering::SeqCst)
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_apply_time_budget_yields_to_destroy() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_time_budget_yields_to_destroy")
+            .tempdir()
+            .unwrap();
+        let host = CoprocessorHost::<KvTestEngine>::default();
+        let region_ids: Vec<u64> = (1..=3).collect();
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &region_ids).unwrap();
+        // A marker outside the region ranges, removed by the interleaved
+        // destroy.
+        engine.kv.put(&data_key(b"z10"), b"m").unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(10);
+        let mut store_cfg = Config::default();
+        store_cfg.snap_apply_batch_size = ReadableSize(0);
+        store_cfg.region_worker_tick_interval = ReadableDuration(PENDING_APPLY_CHECK_INTERVAL);
+        store_cfg.clean_stale_ranges_tick = STALE_PEER_CHECK_TICK;
+        store_cfg.use_delete_range = true;
+        store_cfg.snap_generator_pool_size = 2;
+        // A tiny budget so every pass applies exactly one of the slowed-down
+        // snapshots below.
+        store_cfg.snap_apply_time_budget = ReadableDuration::millis(100);
+        let cfg = Arc::new(VersionTrack::new(store_cfg));
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
         );
-        assert_eq!(obs.cancel_apply.load(Ordering::SeqCst), 0);
+        worker.start_with_timer(runner);
+
+        let prepare_snap = |id: u64| {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let apply_state: RaftApplyState = engine
+                .kv
+                .get_msg_cf(CF_RAFT, &keys::apply_state_key(id))
+                .unwrap()
+                .unwrap();
+            let idx = apply_state.get_applied_index();
+            let entry = engine.raft.get_entry(id, idx).unwrap().unwrap();
+            sched
+                .schedule(Task::Gen {
+                    region_id: id,
+                    kv_snap: engine.kv.snapshot(None),
+                    last_applied_term: entry.get_term(),
+                    last_applied_state: apply_state,
+                    canceled: Arc::new(AtomicBool::new(false)),
+                    notifier: tx,
+                    for_balance: false,
+                    to_store_id: 0,
+                })
+                .unwrap();
+            let s1 = rx.recv().unwrap();
+            match receiver.recv() {
+                Ok((region_id, CasualMessage::SnapshotGenerated)) => {
+                    assert_eq!(region_id, id);
+                }
+                msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+            }
+            let mut data = RaftSnapshotData::default();
+            data.merge_from_bytes(s1.get_data()).unwrap();
+            let key = SnapKey::from_snap(&s1).unwrap();
+            let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+            let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+            let mut s3 = mgr
+                .get_snapshot_for_receiving(&key, data.take_meta())
+                .unwrap();
+            io::copy(&mut s2, &mut s3).unwrap();
+            s3.save().unwrap();
+
+            let mut wb = engine.kv.write_batch();
+            let region_key = keys::region_state_key(id);
+            let mut region_state = engine
+                .kv
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
+                .unwrap()
+                .unwrap();
+            region_state.set_state(PeerState::Applying);
+            wb.put_msg_cf(CF_RAFT, &region_key, &region_state).unwrap();
+            wb.write().unwrap();
+        };
+
+        for id in &region_ids {
+            prepare_snap(*id);
+        }
+
+        // Park the applies so they pile up in the pending queue, and slow
+        // down every SST ingestion well past the budget.
+        fail::cfg("apply_pending_snapshot", "return").unwrap();
+        for id in &region_ids {
+            sched
+                .schedule(Task::Apply {
+                    region_id: *id,
+                    status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                    peer_id: 1,
+                    create_time: Instant::now(),
+                    priority: ApplyPriority::Normal,
+                    region_state: None,
+                    apply_state: None,
+                    on_finish: None,
+                })
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+        fail::cfg("apply_snap_ingest_sst", "sleep(500)").unwrap();
+        fail::remove("apply_pending_snapshot");
+        // Let the first pass start its first (and, by budget, only) apply,
+        // then queue the destroy behind it.
+        thread::sleep(PENDING_APPLY_CHECK_INTERVAL + Duration::from_millis(100));
+        sched
+            .schedule(Task::Destroy {
+                region_id: 100,
+                start_key: data_key(b"z1"),
+                end_key: data_key(b"z2"),
+                size_hint: None,
+            })
+            .unwrap();
 
-        // the pending apply task should be finished and snapshots are ingested.
-        // note that when ingest sst, it may flush memtable if overlap,
-        // so here will two level 0 files.
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            2
-        );
+        // The destroy must run between the apply batches: the marker is gone
+        // long before the remaining applies finish. Without the budget the
+        // single pass would keep the worker busy for all three applies first.
+        let deadline = std::time::Instant::now() + Duration::from_millis(2500);
+        while engine.kv.get_value(&data_key(b"z10")).unwrap().is_some() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "destroy did not execute between apply batches"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut applied = 0;
+        while receiver.try_recv().is_ok() {
+            applied += 1;
+        }
+        assert!(applied < 3, "all applies finished before the destroy");
 
-        // no write stall, ingest without delay
-        gen_and_apply_snap(2);
-        wait_apply_finish(&[2]);
-        assert_eq!(
-            engine
+        // The remaining applies still complete on the following passes.
+        for _ in applied..3 {
+            match receiver.recv_timeout(Duration::from_secs(10)) {
+                Ok((
+                    _,
+                    CasualMessage::SnapshotApplied {
+                        tombstone,
+                        failure_count,
+                        ..
+                    },
+                )) => {
+                    assert!(!tombstone);
+                    assert_eq!(failure_count, 0);
+                }
+                msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+            }
+        }
+        fail::remove("apply_snap_ingest_sst");
+        for id in &region_ids {
+            let region_state = engine
                 .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+                .get_msg_cf::<RegionLocalState>(CF_RAFT, &keys::region_state_key(*id))
                 .unwrap()
-                .unwrap(),
-            4
-        );
+                .unwrap();
+            assert_eq!(region_state.get_state(), PeerState::Normal);
+        }
 
-        // snapshot will not ingest cause it may cause write stall
-        gen_and_apply_snap(3);
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            4
-        );
-        gen_and_apply_snap(4);
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            4
-        );
-        gen_and_apply_snap(5);
-        destroy_region(6);
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
         thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
-        assert!(check_region_exist(6));
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            4
-        );
+    }
 
-        // compact all files to the bottomest level
-        engine.kv.compact_files_in_range(None, None, None).unwrap();
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            0
+    #[test]
+    fn test_apply_cleanup_info_delivered_to_observer() {
+        let temp_dir = Builder::new()
+            .prefix("test_apply_cleanup_info_delivered_to_observer")
+            .tempdir()
+            .unwrap();
+        let obs = MockApplySnapshotObserver::default();
+        let mut host = CoprocessorHost::<KvTestEngine>::default();
+        host.registry
+            .register_apply_snapshot_observer(1, BoxApplySnapshotObserver::new(obs.clone()));
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
+
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
         );
+        let region_cleaner = runner.region_cleaner.clone();
+        worker.start_with_timer(runner);
 
-        // make sure have checked pending applies
-        wait_apply_finish(&[3, 4]);
+        let region_state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        let region_start = keys::enc_start_key(region_state.get_region());
+        let region_end = keys::enc_end_key(region_state.get_region());
 
-        // before two pending apply tasks should be finished and snapshots are ingested
-        // and one still in pending.
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
-                .unwrap()
-                .unwrap(),
-            4
-        );
+        // Generate and receive a snapshot of region 1 and mark it applying.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        let idx = apply_state.get_applied_index();
+        let entry = engine.raft.get_entry(1, idx).unwrap().unwrap();
+        sched
+            .schedule(Task::Gen {
+                region_id: 1,
+                kv_snap: engine.kv.snapshot(None),
+                last_applied_term: entry.get_term(),
+                last_applied_state: apply_state,
+                canceled: Arc::new(AtomicBool::new(false)),
+                notifier: tx,
+                for_balance: false,
+                to_store_id: 0,
+            })
+            .unwrap();
+        let s1 = rx.recv().unwrap();
+        match receiver.recv() {
+            Ok((1, CasualMessage::SnapshotGenerated)) => {}
+            msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+        }
+        let mut data = RaftSnapshotData::default();
+        data.merge_from_bytes(s1.get_data()).unwrap();
+        let key = SnapKey::from_snap(&s1).unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let mut s3 = mgr
+            .get_snapshot_for_receiving(&key, data.take_meta())
+            .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+        let mut wb = engine.kv.write_batch();
+        let mut region_state = region_state;
+        region_state.set_state(PeerState::Applying);
+        wb.put_msg_cf(CF_RAFT, &keys::region_state_key(1), &region_state)
+            .unwrap();
+        wb.write().unwrap();
 
-        // make sure have checked pending applies
-        engine.kv.compact_files_in_range(None, None, None).unwrap();
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+        // An open snapshot keeps the range registered below non-stale, so
+        // neither the destroy itself nor the cleanup ticks remove it before
+        // the apply drains it, and the `DeleteFiles` pass is skipped for it.
+        let snap = engine.kv.snapshot(None);
+        sched
+            .schedule(Task::Destroy {
+                region_id: 100,
+                start_key: data_key(b"k1"),
+                end_key: data_key(b"k2"),
+                size_hint: None,
+            })
+            .unwrap();
+        let drained = vec![(100, data_key(b"k1"), data_key(b"k2"))];
+        for _ in 0..100 {
+            let registered = region_cleaner
+                .lock()
                 .unwrap()
-                .unwrap(),
-            0
-        );
-        wait_apply_finish(&[5]);
+                .preview_apply_cleanup(&region_start, &region_end)
+                .drained_ranges
+                == drained;
+            if registered {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
 
-        // the last one pending task finished
-        assert_eq!(
-            engine
-                .kv
-                .get_cf_num_files_at_level(CF_DEFAULT, 0)
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((1, CasualMessage::SnapshotApplied { failure_count, .. })) => {
+                assert_eq!(failure_count, 0);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
+        }
+
+        // The preview handed to `pre_apply_snapshot` already names the range
+        // registered by the destroy, with the strategies still undecided.
+        let pre = obs.pre_cleanup.lock().unwrap().take().unwrap();
+        assert_eq!(pre.drained_ranges, drained);
+        assert_eq!(pre.cleaned_start, region_start);
+        assert_eq!(pre.cleaned_end, region_end);
+        assert!(pre.strategies_used.is_empty());
+
+        // The authoritative info in `post_apply_snapshot` reports the drained
+        // range plus the strategies the cleanup actually used: with
+        // `use_delete_range` set, delete-by-range for the data cfs and
+        // delete-by-key for the lock cf, and no `DeleteFiles` because the
+        // open snapshot keeps the drained range non-stale.
+        let post = obs.post_cleanup.lock().unwrap().take().unwrap();
+        assert_eq!(post.drained_ranges, drained);
+        assert_eq!(post.cleaned_start, region_start);
+        assert_eq!(post.cleaned_end, region_end);
+        let mut strategies = post.strategies_used;
+        strategies.sort_unstable();
+        assert_eq!(strategies, vec!["delete_by_key", "delete_by_range"]);
+
+        // The drained range is gone from the pending set.
+        assert!(
+            region_cleaner
+                .lock()
                 .unwrap()
-                .unwrap(),
-            2
+                .preview_apply_cleanup(&region_start, &region_end)
+                .drained_ranges
+                .is_empty()
         );
+        drop(snap);
+
+        bg_worker.stop();
+        // Wait the timer fired. Otherwise deletion of directory may race with timer
+        // task.
         thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
-        assert!(!check_region_exist(6));
+    }
 
-        #[cfg(feature = "failpoints")]
-        {
-            let must_not_finish = |ids: &[u64]| {
-                for id in ids {
-                    let region_key = keys::region_state_key(*id);
-                    assert_eq!(
-                        engine
-                            .kv
-                            .get_msg_cf::<RegionLocalState>(CF_RAFT, &region_key)
-                            .unwrap()
-                            .unwrap()
-                            .get_state(),
-                        PeerState::Applying
-                    )
-                }
-            };
+    #[test]
+    fn test_slow_apply_snapshot_hook_budget() {
+        let temp_dir = Builder::new()
+            .prefix("test_slow_apply_snapshot_hook_budget")
+            .tempdir()
+            .unwrap();
+        let slow = SlowApplySnapshotObserver {
+            pre_sleep: Duration::from_millis(150),
+            pre_apply_count: Arc::default(),
+        };
+        let obs = MockApplySnapshotObserver::default();
+        let mut host = CoprocessorHost::<KvTestEngine>::default();
+        // The slow observer alone burns the 100ms hard cap, so the mock
+        // registered after it must be skipped in `pre_apply_snapshot`.
+        host.cfg.apply_snapshot_hook_budget = ReadableDuration::millis(50);
+        host.cfg.apply_snapshot_hook_hard_cap_multiple = 2;
+        host.registry
+            .register_apply_snapshot_observer(1, BoxApplySnapshotObserver::new(slow.clone()));
+        host.registry
+            .register_apply_snapshot_observer(2, BoxApplySnapshotObserver::new(obs.clone()));
+        let engine = get_test_db_for_regions(&temp_dir, None, None, None, &[1]).unwrap();
 
-            engine.kv.compact_files_in_range(None, None, None).unwrap();
-            fail::cfg("handle_new_pending_applies", "return").unwrap();
-            gen_and_apply_snap(7);
-            thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
-            must_not_finish(&[7]);
-            fail::remove("handle_new_pending_applies");
-            thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
-            wait_apply_finish(&[7]);
+        let snap_dir = Builder::new().prefix("snap_dir").tempdir().unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        mgr.init().unwrap();
+        let bg_worker = Worker::new("snap-manager");
+        let mut worker = bg_worker.lazy_build("snap-manager");
+        let sched = worker.scheduler();
+        let (router, receiver) = mpsc::sync_channel(1);
+        let cfg = make_raftstore_cfg(true);
+        let runner = RegionRunner::new(
+            engine.kv.clone(),
+            mgr,
+            cfg,
+            host,
+            router,
+            None,
+            Option::<Arc<RpcClient>>::None,
+            None,
+        );
+        worker.start_with_timer(runner);
+
+        // Generate and receive a snapshot of region 1 and mark it applying.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let apply_state: RaftApplyState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::apply_state_key(1))
+            .unwrap()
+            .unwrap();
+        let idx = apply_state.get_applied_index();
+        let entry = engine.raft.get_entry(1, idx).unwrap().unwrap();
+        sched
+            .schedule(Task::Gen {
+                region_id: 1,
+                kv_snap: engine.kv.snapshot(None),
+                last_applied_term: entry.get_term(),
+                last_applied_state: apply_state,
+                canceled: Arc::new(AtomicBool::new(false)),
+                notifier: tx,
+                for_balance: false,
+                to_store_id: 0,
+            })
+            .unwrap();
+        let s1 = rx.recv().unwrap();
+        match receiver.recv() {
+            Ok((1, CasualMessage::SnapshotGenerated)) => {}
+            msg => panic!("expected SnapshotGenerated, but got {:?}", msg),
+        }
+        let mut data = RaftSnapshotData::default();
+        data.merge_from_bytes(s1.get_data()).unwrap();
+        let key = SnapKey::from_snap(&s1).unwrap();
+        let mgr = SnapManager::new(snap_dir.path().to_str().unwrap());
+        let mut s2 = mgr.get_snapshot_for_sending(&key).unwrap();
+        let mut s3 = mgr
+            .get_snapshot_for_receiving(&key, data.take_meta())
+            .unwrap();
+        io::copy(&mut s2, &mut s3).unwrap();
+        s3.save().unwrap();
+        let mut wb = engine.kv.write_batch();
+        let mut region_state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        region_state.set_state(PeerState::Applying);
+        wb.put_msg_cf(CF_RAFT, &keys::region_state_key(1), &region_state)
+            .unwrap();
+        wb.write().unwrap();
+
+        let overage_before = APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER
+            .with_label_values(&["pre_apply"])
+            .get();
+        let skipped_before = APPLY_SNAPSHOT_HOOK_SKIPPED_COUNTER.get();
+
+        sched
+            .schedule(Task::Apply {
+                region_id: 1,
+                status: Arc::new(AtomicUsize::new(JOB_STATUS_PENDING)),
+                peer_id: 1,
+                create_time: Instant::now(),
+                priority: ApplyPriority::Normal,
+                region_state: None,
+                apply_state: None,
+                on_finish: None,
+            })
+            .unwrap();
+        match receiver.recv_timeout(Duration::from_secs(5)) {
+            Ok((1, CasualMessage::SnapshotApplied { failure_count, .. })) => {
+                assert_eq!(failure_count, 0);
+            }
+            msg => panic!("expected SnapshotApplied, but got {:?}", msg),
         }
+
+        // The slow observer ran, overran its budget and was counted; the
+        // mock behind it was skipped because the combined pre-apply time
+        // had already exceeded the hard cap.
+        assert_eq!(slow.pre_apply_count.load(Ordering::SeqCst), 1);
+        assert_eq!(obs.pre_apply_count.load(Ordering::SeqCst), 0);
+        assert!(
+            APPLY_SNAPSHOT_HOOK_OVERAGE_COUNTER
+                .with_label_values(&["pre_apply"])
+                .get()
+                > overage_before
+        );
+        assert!(APPLY_SNAPSHOT_HOOK_SKIPPED_COUNTER.get() > skipped_before);
+        // Post hooks are not capped, and the apply itself completed.
+        assert_eq!(obs.post_apply_count.load(Ordering::SeqCst), 1);
+        let region_state: RegionLocalState = engine
+            .kv
+            .get_msg_cf(CF_RAFT, &keys::region_state_key(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(region_state.get_state(), PeerState::Normal);
+
         bg_worker.stop();
         // Wait the timer fired. Otherwise deletion of directory may race with timer
         // task.
         thread::sleep(PENDING_APPLY_CHECK_INTERVAL * 2);
     }
 
+    /// An apply-snapshot observer that deliberately sleeps past its budget
+    /// in `pre_apply_snapshot`.
+    #[derive(Clone)]
+    struct SlowApplySnapshotObserver {
+        pre_sleep: Duration,
+        pre_apply_count: Arc<AtomicUsize>,
+    }
+
+    impl Coprocessor for SlowApplySnapshotObserver {}
+
+    impl ApplySnapshotObserver for SlowApplySnapshotObserver {
+        fn pre_apply_snapshot(
+            &self,
+            ctx: &mut ObserverContext<'_>,
+            _: u64,
+            _: &crate::store::SnapKey,
+            _: Option<&crate::store::Snapshot>,
+            _: Option<&ApplyCleanupInfo>,
+        ) {
+            let deadline: DeadlineChecker = ctx.deadline().cloned().unwrap();
+            assert!(!deadline.exceeded());
+            thread::sleep(self.pre_sleep);
+            assert!(deadline.exceeded());
+            self.pre_apply_count.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn should_pre_apply_snapshot(&self) -> bool {
+            true
+        }
+    }
+
     #[derive(Clone, Default)]
     struct MockApplySnapshotObserver {
         pub pre_apply_count: Arc<AtomicUsize>,
@@ -1551,6 +7291,9 @@ pub(crate) mod tests {
         pub pre_apply_hash: Arc<AtomicUsize>,
         pub post_apply_hash: Arc<AtomicUsize>,
         pub cancel_apply: Arc<AtomicUsize>,
+        pub apply_sst_cfs: Arc<Mutex<Vec<String>>>,
+        pub pre_cleanup: Arc<Mutex<Option<ApplyCleanupInfo>>>,
+        pub post_cleanup: Arc<Mutex<Option<ApplyCleanupInfo>>>,
     }
 
     impl Coprocessor for MockApplySnapshotObserver {}
@@ -1562,12 +7305,14 @@ pub(crate) mod tests {
             peer_id: u64,
             key: &crate::store::SnapKey,
             snapshot: Option<&crate::store::Snapshot>,
+            cleanup: Option<&ApplyCleanupInfo>,
         ) {
             let code =
                 snapshot.unwrap().total_size() + key.term + key.region_id + key.idx + peer_id;
             self.pre_apply_count.fetch_add(1, Ordering::SeqCst);
             self.pre_apply_hash
                 .fetch_add(code as usize, Ordering::SeqCst);
+            *self.pre_cleanup.lock().unwrap() = cleanup.cloned();
         }
 
         fn post_apply_snapshot(
@@ -1576,12 +7321,14 @@ pub(crate) mod tests {
             peer_id: u64,
             key: &crate::store::SnapKey,
             snapshot: Option<&crate::store::Snapshot>,
+            cleanup: Option<&ApplyCleanupInfo>,
         ) {
             let code =
                 snapshot.unwrap().total_size() + key.term + key.region_id + key.idx + peer_id;
             self.post_apply_count.fetch_add(1, Ordering::SeqCst);
             self.post_apply_hash
                 .fetch_add(code as usize, Ordering::SeqCst);
+            *self.post_cleanup.lock().unwrap() = cleanup.cloned();
         }
 
         fn should_pre_apply_snapshot(&self) -> bool {
@@ -1591,5 +7338,9 @@ pub(crate) mod tests {
         fn cancel_apply_snapshot(&self, _: u64, _: u64) {
             self.cancel_apply.fetch_add(1, Ordering::SeqCst);
         }
+
+        fn apply_sst(&self, _: &mut ObserverContext<'_>, cf: CfName, _path: &str) {
+            self.apply_sst_cfs.lock().unwrap().push(cf.to_owned());
+        }
     }
 }
@@ -0,0 +1,91 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{hint::black_box, sync::Arc};
+
+use criterion::*;
+use engine_traits::{CacheRange, Peekable, RangeCacheEngine, ReadOptions, CF_DEFAULT, CF_WRITE};
+use range_cache_memory_engine::{
+    test_util::put_data, RangeCacheEngineConfig, RangeCacheEngineContext, RangeCacheMemoryEngine,
+};
+use tikv_util::config::VersionTrack;
+use txn_types::{Key, TimeStamp};
+
+const KEY_COUNT: u64 = 1024;
+const START_TS: u64 = 2;
+const COMMIT_TS: u64 = 3;
+
+// Fills the engine with `KEY_COUNT` values of `value_size` bytes and returns
+// the engine together with the encoded default cf keys to read back.
+fn prepare_engine(value_size: usize) -> (RangeCacheMemoryEngine, Vec<Vec<u8>>) {
+    let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+        VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+    )));
+    let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+    engine.new_range(range.clone());
+
+    let mem_controller = engine.memory_controller();
+    let (default_cf, write_cf) = {
+        let core = engine.core().read();
+        let skiplist_engine = core.engine();
+        (
+            skiplist_engine.cf_handle(CF_DEFAULT),
+            skiplist_engine.cf_handle(CF_WRITE),
+        )
+    };
+
+    let value = vec![b'v'; value_size];
+    let mut keys = Vec::with_capacity(KEY_COUNT as usize);
+    for i in 0..KEY_COUNT {
+        let key = format!("k{:08}", i).into_bytes();
+        put_data(
+            &key,
+            &value,
+            START_TS,
+            COMMIT_TS,
+            i * 2 + 10,
+            false,
+            &default_cf,
+            &write_cf,
+            mem_controller.clone(),
+        );
+        keys.push(
+            Key::from_raw(&key)
+                .append_ts(TimeStamp::new(START_TS))
+                .into_encoded(),
+        );
+    }
+    (engine, keys)
+}
+
+// Point gets return the stored bytes by bumping the refcount of the
+// underlying `Bytes`, so the cost should stay flat as the value size grows.
+// Compare the two value sizes to verify no copy sneaks back in.
+fn bench_point_get(c: &mut Criterion, name: &str, value_size: usize) {
+    let (engine, keys) = prepare_engine(value_size);
+    let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+    let snapshot = engine.snapshot(range, u64::MAX, u64::MAX).unwrap();
+    let opts = ReadOptions::default();
+    let mut i = 0;
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            let k = &keys[i % keys.len()];
+            i += 1;
+            let v = snapshot
+                .get_value_cf_opt(&opts, CF_DEFAULT, k)
+                .unwrap()
+                .unwrap();
+            black_box(&v[..]);
+        })
+    });
+}
+
+fn point_get_32b(c: &mut Criterion) {
+    bench_point_get(c, "point_get_32b_value", 32);
+}
+
+fn point_get_4kb(c: &mut Criterion) {
+    bench_point_get(c, "point_get_4kb_value", 4096);
+}
+
+criterion_group!(benches, point_get_32b, point_get_4kb);
+criterion_main!(benches);
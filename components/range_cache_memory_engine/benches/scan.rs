@@ -0,0 +1,89 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{hint::black_box, sync::Arc};
+
+use criterion::*;
+use engine_traits::{
+    CacheRange, IterOptions, Iterable, Iterator, RangeCacheEngine, CF_DEFAULT, CF_WRITE,
+};
+use range_cache_memory_engine::{
+    test_util::put_data, RangeCacheEngineConfig, RangeCacheEngineContext, RangeCacheMemoryEngine,
+};
+use tikv_util::config::VersionTrack;
+
+const KEY_COUNT: u64 = 100_000;
+const START_TS: u64 = 2;
+const COMMIT_TS: u64 = 3;
+
+fn prepare_engine(iterator_prefetch_size: usize) -> RangeCacheMemoryEngine {
+    let mut config = RangeCacheEngineConfig::config_for_test();
+    config.iterator_prefetch_size = iterator_prefetch_size;
+    let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+        VersionTrack::new(config),
+    )));
+    let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+    engine.new_range(range.clone());
+
+    let mem_controller = engine.memory_controller();
+    let (default_cf, write_cf) = {
+        let core = engine.core().read();
+        let skiplist_engine = core.engine();
+        (
+            skiplist_engine.cf_handle(CF_DEFAULT),
+            skiplist_engine.cf_handle(CF_WRITE),
+        )
+    };
+
+    let value = vec![b'v'; 32];
+    for i in 0..KEY_COUNT {
+        let key = format!("k{:08}", i).into_bytes();
+        put_data(
+            &key,
+            &value,
+            START_TS,
+            COMMIT_TS,
+            i * 2 + 10,
+            false,
+            &default_cf,
+            &write_cf,
+            mem_controller.clone(),
+        );
+    }
+    engine
+}
+
+// A full forward scan over the write cf. With prefetching enabled the
+// iterator batches skiplist advances once the sequential scan is detected,
+// so the time per next() should drop compared to the unbatched scan.
+fn bench_scan(c: &mut Criterion, name: &str, iterator_prefetch_size: usize) {
+    let engine = prepare_engine(iterator_prefetch_size);
+    let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+    let snapshot = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+    let mut iter_opt = IterOptions::default();
+    iter_opt.set_upper_bound(&range.end, 0);
+    iter_opt.set_lower_bound(&range.start, 0);
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            let mut iter = snapshot.iterator_opt(CF_WRITE, iter_opt.clone()).unwrap();
+            let mut count = 0;
+            let mut valid = iter.seek_to_first().unwrap();
+            while valid {
+                black_box((iter.key(), iter.value()));
+                count += 1;
+                valid = iter.next().unwrap();
+            }
+            assert_eq!(count, KEY_COUNT);
+        })
+    });
+}
+
+fn scan_no_prefetch(c: &mut Criterion) {
+    bench_scan(c, "scan_100k_no_prefetch", 0);
+}
+
+fn scan_prefetch_32(c: &mut Criterion) {
+    bench_scan(c, "scan_100k_prefetch_32", 32);
+}
+
+criterion_group!(benches, scan_no_prefetch, scan_prefetch_32);
+criterion_main!(benches);
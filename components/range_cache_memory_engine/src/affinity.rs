@@ -0,0 +1,231 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Thread placement for the background workers. On large NUMA machines the
+//! skiplist pages land on whatever node first touches them, so a load running
+//! on the wrong socket doubles the memory latency of every later cache read.
+//! The helpers here pin the background threads to a configured core set and
+//! optionally switch their allocations to an interleaved NUMA policy. Both
+//! are strictly best effort: when the platform does not support the calls the
+//! engine keeps working with the default placement.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use slog_global::{info, warn};
+
+// Set once the interleave policy has actually been installed on at least one
+// worker thread, so the health report can show the effective policy rather
+// than the requested one.
+static INTERLEAVE_APPLIED: AtomicBool = AtomicBool::new(false);
+
+/// The placement applied to every background worker thread, derived from the
+/// config once at worker construction.
+#[derive(Clone, Default)]
+pub(crate) struct ThreadPlacement {
+    // Cores the thread is pinned to; empty disables pinning.
+    cores: Vec<usize>,
+    // Whether allocations of the thread use an interleaved NUMA policy.
+    interleave: bool,
+}
+
+impl ThreadPlacement {
+    pub(crate) fn new(cpu_set: &str, interleave: bool) -> ThreadPlacement {
+        let cores = match parse_cpu_set(cpu_set) {
+            Ok(cores) => cores,
+            Err(e) => {
+                // The config is validated at startup, so this only happens
+                // for configs bypassing validation (e.g. in tests).
+                warn!(
+                    "invalid background worker cpu set, pinning disabled";
+                    "cpu_set" => cpu_set,
+                    "err" => e,
+                );
+                Vec::new()
+            }
+        };
+        ThreadPlacement { cores, interleave }
+    }
+
+    /// Applies the placement to the calling thread, once per thread. Failures
+    /// are logged and otherwise ignored; the thread continues with whatever
+    /// placement the OS gave it.
+    pub(crate) fn ensure_current_thread(&self) {
+        thread_local! {
+            static APPLIED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+        }
+        APPLIED.with(|applied| {
+            if applied.get() {
+                return;
+            }
+            applied.set(true);
+            if !self.cores.is_empty() {
+                if pin_current_thread(&self.cores) {
+                    info!(
+                        "pinned background worker thread";
+                        "cores" => ?self.cores,
+                    );
+                } else {
+                    warn!(
+                        "failed to pin background worker thread, continuing unpinned";
+                        "cores" => ?self.cores,
+                    );
+                }
+            }
+            if self.interleave {
+                if set_interleave_mempolicy() {
+                    INTERLEAVE_APPLIED.store(true, Ordering::Relaxed);
+                    info!("interleaved NUMA allocation enabled for background worker thread");
+                } else {
+                    warn!(
+                        "interleaved NUMA allocation not supported on this platform, \
+                         continuing with the default policy"
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// The allocation policy actually in effect, for the health report.
+/// "interleave" is only reported once a worker thread has successfully
+/// installed the policy.
+pub(crate) fn effective_allocation_policy() -> &'static str {
+    if INTERLEAVE_APPLIED.load(Ordering::Relaxed) {
+        "interleave"
+    } else {
+        "default"
+    }
+}
+
+/// Parses a cpuset-style core list like "0,2,4-6". An empty string parses to
+/// an empty set, which disables pinning.
+pub(crate) fn parse_cpu_set(value: &str) -> Result<Vec<usize>, String> {
+    let mut cores = Vec::new();
+    if value.trim().is_empty() {
+        return Ok(cores);
+    }
+    for part in value.split(',') {
+        let part = part.trim();
+        if let Some((start, end)) = part.split_once('-') {
+            let start: usize = start
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid core id {:?}", start))?;
+            let end: usize = end
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid core id {:?}", end))?;
+            if start > end {
+                return Err(format!("invalid core range {:?}", part));
+            }
+            cores.extend(start..=end);
+        } else {
+            cores.push(
+                part.parse()
+                    .map_err(|_| format!("invalid core id {:?}", part))?,
+            );
+        }
+    }
+    cores.sort_unstable();
+    cores.dedup();
+    Ok(cores)
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cores: &[usize]) -> bool {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        let mut any = false;
+        for &core in cores {
+            if core < libc::CPU_SETSIZE as usize {
+                libc::CPU_SET(core, &mut set);
+                any = true;
+            }
+        }
+        any && libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cores: &[usize]) -> bool {
+    false
+}
+
+// Installs MPOL_INTERLEAVE over all online NUMA nodes for the calling thread
+// via set_mempolicy(2). Returns false when the machine has a single node or
+// the syscall is unavailable (e.g. seccomp, non-Linux), in which case the
+// default first-touch policy stays in effect.
+#[cfg(target_os = "linux")]
+fn set_interleave_mempolicy() -> bool {
+    const MPOL_INTERLEAVE: libc::c_int = 3;
+    let online = match std::fs::read_to_string("/sys/devices/system/node/online") {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let nodes = match parse_cpu_set(online.trim()) {
+        Ok(nodes) => nodes,
+        Err(_) => return false,
+    };
+    // Interleaving over a single node is a no-op, do not pretend otherwise.
+    if nodes.len() < 2 || nodes.iter().any(|&n| n >= 64) {
+        return false;
+    }
+    let mut mask: u64 = 0;
+    for &node in &nodes {
+        mask |= 1 << node;
+    }
+    unsafe {
+        libc::syscall(
+            libc::SYS_set_mempolicy,
+            MPOL_INTERLEAVE,
+            &mask as *const u64,
+            64usize,
+        ) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_interleave_mempolicy() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_set() {
+        assert_eq!(parse_cpu_set("").unwrap(), Vec::<usize>::new());
+        assert_eq!(parse_cpu_set("  ").unwrap(), Vec::<usize>::new());
+        assert_eq!(parse_cpu_set("3").unwrap(), vec![3]);
+        assert_eq!(parse_cpu_set("0,2,4-6").unwrap(), vec![0, 2, 4, 5, 6]);
+        assert_eq!(parse_cpu_set(" 1 , 3-4 ").unwrap(), vec![1, 3, 4]);
+        assert_eq!(parse_cpu_set("1,1,1-2").unwrap(), vec![1, 2]);
+        parse_cpu_set("a").unwrap_err();
+        parse_cpu_set("1-").unwrap_err();
+        parse_cpu_set("5-3").unwrap_err();
+        parse_cpu_set("1,,2").unwrap_err();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_current_thread() {
+        // Core 0 always exists, pinning to it must succeed.
+        assert!(pin_current_thread(&[0]));
+        // Cores beyond CPU_SETSIZE are skipped, leaving nothing to pin to.
+        assert!(!pin_current_thread(&[libc::CPU_SETSIZE as usize + 1]));
+    }
+
+    #[test]
+    fn test_placement_is_best_effort() {
+        // An unsatisfiable placement must not panic or abort the thread; the
+        // loads simply run with the default placement. Each placement gets a
+        // fresh thread as the application is once per thread.
+        for cpu_set in ["not-a-cpu-set", "4095"] {
+            let placement = ThreadPlacement::new(cpu_set, true);
+            std::thread::spawn(move || placement.ensure_current_thread())
+                .join()
+                .unwrap();
+        }
+    }
+}
@@ -1,6 +1,15 @@
 // Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::{collections::BTreeSet, fmt::Display, sync::Arc, thread::JoinHandle, time::Duration};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
 
 use bytes::Bytes;
 use crossbeam::{
@@ -10,39 +19,52 @@ use crossbeam::{
 use engine_rocks::{RocksEngine, RocksSnapshot};
 use engine_traits::{
     CacheRange, IterOptions, Iterable, Iterator, MiscExt, RangeHintService, SnapshotMiscExt,
-    CF_DEFAULT, CF_WRITE, DATA_CFS,
+    CF_DEFAULT, CF_LOCK, CF_WRITE,
 };
 use parking_lot::RwLock;
 use pd_client::{PdClient, RpcClient};
 use raftstore::coprocessor::RegionInfoProvider;
 use slog_global::{error, info, warn};
 use tikv_util::{
-    config::ReadableSize,
+    config::{ReadableSize, VersionTrack},
     future::block_on_timeout,
     keybuilder::KeyBuilder,
-    time::Instant,
+    time::{Instant, Limiter},
     worker::{Builder, Runnable, RunnableWithTimer, ScheduleError, Scheduler, Worker},
 };
 use txn_types::{Key, TimeStamp, WriteRef, WriteType};
 use yatp::Remote;
 
 use crate::{
-    engine::{RangeCacheMemoryEngineCore, SkiplistHandle},
+    affinity::ThreadPlacement,
+    compression::{decode_value, maybe_compress_value},
+    engine::{id_to_cf, RangeCacheMemoryEngineCore, SkiplistHandle},
+    events::RangeEventKind,
     keys::{
-        decode_key, encode_key, encode_key_for_boundary_with_mvcc, encoding_for_filter,
-        InternalBytes, InternalKey, ValueType,
+        decode_key, encode_key, encode_key_for_boundary_with_mvcc, encode_seek_for_prev_key,
+        encode_seek_key, encoding_for_filter, InternalBytes, InternalKey, ValueType,
     },
-    memory_controller::{MemoryController, MemoryUsage},
+    load_scheduler::LoadScheduler,
+    memory_controller::{MemoryController, MemoryUsage, WritePressure},
     metrics::{
-        GC_FILTERED_STATIC, RANGE_CACHE_COUNT, RANGE_CACHE_MEMORY_USAGE, RANGE_GC_TIME_HISTOGRAM,
+        GC_FILTERED_STATIC, RANGE_CACHE_CHECKSUM_MISMATCHES, RANGE_CACHE_COUNT,
+        RANGE_CACHE_DELETE_BATCH_BUDGET, RANGE_CACHE_DELETE_BATCH_SLEEP,
+        RANGE_CACHE_DELETE_PACER_SATURATED, RANGE_CACHE_MEMORY_USAGE, RANGE_CACHE_PENDING_RECLAIM,
+        RANGE_CACHE_PREWARM_BYTES, RANGE_CACHE_PREWARM_SKIPPED, RANGE_CACHE_SEQNO_GAP,
+        RANGE_CACHE_SKIPLIST_NODE_COUNT, RANGE_CACHE_SKIPLIST_SEARCH_DEPTH,
+        RANGE_CACHE_STUCK_EVICTIONS, RANGE_CACHE_WRITE_PRESSURE, RANGE_GC_FREED_BYTES,
+        RANGE_GC_TIME_HISTOGRAM, RANGE_LOAD_BYTES, RANGE_LOAD_CHUNKED_LOADS,
+        RANGE_LOAD_SKIPPED_BYTES, RANGE_LOAD_SKIPPED_ENTRIES, RANGE_LOAD_SNAPSHOT_REFRESHES,
         RANGE_LOAD_TIME_HISTOGRAM,
     },
-    range_manager::LoadFailedReason,
+    range_manager::{now_unix_millis, LoadFailedReason},
     range_stats::{RangeStatsManager, DEFAULT_EVICT_MIN_DURATION},
     region_label::{
         LabelRule, RegionLabelAddedCb, RegionLabelRulesManager, RegionLabelServiceBuilder,
     },
+    statistics::{recent_seek_latency_quantile, thread_comparator_calls},
     write_batch::RangeCacheWriteBatchEntry,
+    RangeCacheEngineConfig,
 };
 
 /// Try to extract the key and `u64` timestamp from `encoded_key`.
@@ -75,8 +97,24 @@ pub enum BackgroundTask {
     MemoryCheckAndEvict,
     DeleteRange(Vec<CacheRange>),
     TopRegionsLoadEvict,
+    CheckStuckEvictions,
+    // Refresh the per-cf skiplist structural metrics: the node count gauges
+    // and a sampled search depth histogram, see `sample_skiplist_stats`.
+    SampleSkiplistStats,
     CleanLockTombstone(u64),
     SetRocksEngine(RocksEngine),
+    // Read the freshly evicted ranges back from the disk engine with
+    // cache-filling iterators so its block cache is warm when the reads that
+    // were served from the cache start missing it. `reason` is the eviction
+    // reason, which decides whether the pass runs at all.
+    PrewarmRange {
+        ranges: Vec<CacheRange>,
+        reason: &'static str,
+    },
+    // A sentinel only used by the deterministic test mode: the delete range
+    // worker acknowledges it once every task scheduled before it has run.
+    #[cfg(test)]
+    TestCompletion(std::sync::mpsc::SyncSender<()>),
 }
 
 impl Display for BackgroundTask {
@@ -89,15 +127,34 @@ impl Display for BackgroundTask {
                 f.debug_struct("DeleteRange").field("range", r).finish()
             }
             BackgroundTask::TopRegionsLoadEvict => f.debug_struct("CheckTopRegions").finish(),
+            BackgroundTask::CheckStuckEvictions => f.debug_struct("CheckStuckEvictions").finish(),
+            BackgroundTask::SampleSkiplistStats => f.debug_struct("SampleSkiplistStats").finish(),
             BackgroundTask::CleanLockTombstone(ref r) => f
                 .debug_struct("CleanLockTombstone")
                 .field("seqno", r)
                 .finish(),
             BackgroundTask::SetRocksEngine(_) => f.debug_struct("SetDiskEngine").finish(),
+            BackgroundTask::PrewarmRange { ref ranges, reason } => f
+                .debug_struct("PrewarmRange")
+                .field("ranges", ranges)
+                .field("reason", &reason)
+                .finish(),
+            #[cfg(test)]
+            BackgroundTask::TestCompletion(_) => f.debug_struct("TestCompletion").finish(),
         }
     }
 }
 
+/// Statistics of one in-place gc pass over a cached range, returned by
+/// [`RangeCacheMemoryEngine::gc_range`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GcStats {
+    /// The number of versions physically removed from the skiplists.
+    pub versions_removed: usize,
+    /// The estimated number of bytes freed by the removals.
+    pub bytes_freed: usize,
+}
+
 #[derive(Debug)]
 pub struct GcTask {
     pub safe_point: u64,
@@ -119,8 +176,16 @@ pub struct BgWorkManager {
     worker: Worker,
     scheduler: Scheduler<BackgroundTask>,
     delete_range_scheduler: Scheduler<BackgroundTask>,
+    load_scheduler: Arc<LoadScheduler>,
     tick_stopper: Option<(JoinHandle<()>, Sender<bool>)>,
     core: Arc<RwLock<RangeCacheMemoryEngineCore>>,
+    // Tasks parked by the deterministic test mode, `None` while the mode is
+    // off. See `enable_deterministic_mode`.
+    #[cfg(test)]
+    deterministic_queue: std::sync::Mutex<Option<std::collections::VecDeque<BackgroundTask>>>,
+    // A copy of the background runner state so parked loads can run inline.
+    #[cfg(test)]
+    runner_core: BackgroundRunnerCore,
 }
 
 impl Drop for BgWorkManager {
@@ -206,19 +271,22 @@ impl BgWorkManager {
     pub fn new(
         core: Arc<RwLock<RangeCacheMemoryEngineCore>>,
         pd_client: Arc<dyn PdClient>,
-        gc_interval: Duration,
-        load_evict_interval: Duration,
-        expected_region_size: usize,
+        config: Arc<VersionTrack<RangeCacheEngineConfig>>,
         memory_controller: Arc<MemoryController>,
         region_info_provider: Option<Arc<dyn RegionInfoProvider>>,
     ) -> Self {
+        let gc_interval = config.value().gc_interval.0;
+        let load_evict_interval = config.value().load_evict_interval.0;
         let worker = Worker::new("range-cache-background-worker");
         let (runner, delete_range_scheduler) = BackgroundRunner::new(
             core.clone(),
             memory_controller,
             region_info_provider,
-            expected_region_size,
+            config,
         );
+        let load_scheduler = runner.core.load_scheduler.clone();
+        #[cfg(test)]
+        let runner_core = runner.core.clone();
         let scheduler = worker.start_with_timer("range-cache-engine-background", runner);
 
         let (h, tx) = BgWorkManager::start_tick(
@@ -232,12 +300,26 @@ impl BgWorkManager {
             worker,
             scheduler,
             delete_range_scheduler,
+            load_scheduler,
             tick_stopper: Some((h, tx)),
             core,
+            #[cfg(test)]
+            deterministic_queue: std::sync::Mutex::new(None),
+            #[cfg(test)]
+            runner_core,
         }
     }
 
+    pub fn load_scheduler(&self) -> &Arc<LoadScheduler> {
+        &self.load_scheduler
+    }
+
     pub fn schedule_task(&self, task: BackgroundTask) -> Result<(), ScheduleError<BackgroundTask>> {
+        #[cfg(test)]
+        if let Some(queue) = self.deterministic_queue.lock().unwrap().as_mut() {
+            queue.push_back(task);
+            return Ok(());
+        }
         match task {
             task @ BackgroundTask::DeleteRange(_) => {
                 self.delete_range_scheduler.schedule_force(task)
@@ -246,6 +328,55 @@ impl BgWorkManager {
         }
     }
 
+    /// Parks every task that goes through [`BgWorkManager::schedule_task`] in
+    /// a queue instead of handing it to the background workers, so tests
+    /// decide exactly when and in what order background work runs by calling
+    /// [`BgWorkManager::run_one_background_task`]. Scheduling is untouched
+    /// while the mode is off.
+    #[cfg(test)]
+    pub(crate) fn enable_deterministic_mode(&self) {
+        *self.deterministic_queue.lock().unwrap() = Some(std::collections::VecDeque::new());
+    }
+
+    /// Runs the oldest parked task and waits until its effects, including any
+    /// deletion it handed to the delete range worker, are visible. Returns
+    /// `false` if no task is parked.
+    #[cfg(test)]
+    pub(crate) fn run_one_background_task(&self) -> bool {
+        let task = self
+            .deterministic_queue
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|q| q.pop_front());
+        let Some(task) = task else {
+            return false;
+        };
+        match task {
+            task @ BackgroundTask::DeleteRange(_) => {
+                self.delete_range_scheduler.schedule_force(task).unwrap();
+            }
+            // Run the load inline so that it has finished, not merely been
+            // spawned, by the time this returns.
+            BackgroundTask::LoadRange => futures::executor::block_on(
+                self.runner_core
+                    .clone()
+                    .load_pending_ranges(self.delete_range_scheduler.clone()),
+            ),
+            // Deterministic tests only exercise loads and evictions so far;
+            // everything else keeps its production path.
+            task => self.scheduler.schedule_force(task).unwrap(),
+        }
+        // Drain the delete range worker so a deletion scheduled above, e.g.
+        // by a canceled load, is done before we return.
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        self.delete_range_scheduler
+            .schedule_force(BackgroundTask::TestCompletion(tx))
+            .unwrap();
+        rx.recv().unwrap();
+        true
+    }
+
     pub fn start_bg_hint_service(&self, range_hint_service: PdRangeHintService) {
         let core = self.core.clone();
         range_hint_service.start(self.worker.remote(), move |cache_range: &CacheRange| {
@@ -270,6 +401,14 @@ impl BgWorkManager {
         let h = std::thread::spawn(move || {
             let gc_ticker = tick(gc_interval);
             let load_evict_ticker = tick(load_evict_interval); // TODO (afeinberg): Use a real value.
+            // The stuck eviction watchdog only reports ranges that have been
+            // blocked longer than a threshold of minutes, so the gc cadence is
+            // more than frequent enough for it.
+            let stuck_check_ticker = tick(gc_interval);
+            // The structural shape of the skiplists changes slowly, and one
+            // sampling pass is bounded regardless of their size, so the
+            // load/evict cadence is frequent enough.
+            let skiplist_stats_ticker = tick(load_evict_interval);
             // 5 seconds should be long enough for getting a TSO from PD.
             let tso_timeout = std::cmp::min(gc_interval, Duration::from_secs(5));
             'LOOP: loop {
@@ -303,6 +442,22 @@ impl BgWorkManager {
                             );
                         }
                     },
+                    recv(stuck_check_ticker) -> _ => {
+                        if let Err(e) = scheduler.schedule(BackgroundTask::CheckStuckEvictions) {
+                            error!(
+                                "schedule stuck eviction check failed";
+                                "err" => ?e,
+                            );
+                        }
+                    },
+                    recv(skiplist_stats_ticker) -> _ => {
+                        if let Err(e) = scheduler.schedule(BackgroundTask::SampleSkiplistStats) {
+                            error!(
+                                "schedule skiplist stats sampling failed";
+                                "err" => ?e,
+                            );
+                        }
+                    },
                     recv(rx) -> r => {
                         if let Err(e) = r {
                             error!(
@@ -324,6 +479,18 @@ struct BackgroundRunnerCore {
     engine: Arc<RwLock<RangeCacheMemoryEngineCore>>,
     memory_controller: Arc<MemoryController>,
     range_stats_manager: Option<RangeStatsManager>,
+    load_scheduler: Arc<LoadScheduler>,
+    config: Arc<VersionTrack<RangeCacheEngineConfig>>,
+    // The safe point of the last gc round, used by gc aware loads. 0 means no
+    // gc has run yet and loads cache everything.
+    last_gc_safe_point: Arc<AtomicU64>,
+    // The disk engine handle, shared with the runner which fills it in when
+    // the `SetRocksEngine` task arrives. Used by loads to take a fresh
+    // snapshot when the one they hold gets older than
+    // `load_snapshot_max_age`.
+    disk_engine: Arc<RwLock<Option<RocksEngine>>>,
+    // Applied lazily on every worker thread that touches the skiplist.
+    placement: ThreadPlacement,
 }
 
 impl BackgroundRunnerCore {
@@ -351,103 +518,181 @@ impl BackgroundRunnerCore {
     }
 
     fn gc_range(&self, range: &CacheRange, safe_point: u64, oldest_seqno: u64) -> FilterMetrics {
-        let (skiplist_engine, safe_ts) = {
-            let mut core = self.engine.write();
-            let Some(range_meta) = core.mut_range_manager().mut_range_meta(range) else {
-                return FilterMetrics::default();
-            };
-            let min_snapshot = range_meta
-                .range_snapshot_list()
-                .min_snapshot_ts()
-                .unwrap_or(u64::MAX);
-            let safe_point = u64::min(safe_point, min_snapshot);
+        gc_range(
+            &self.engine,
+            range,
+            safe_point,
+            oldest_seqno,
+            self.config.value().value_compression.is_enabled(),
+        )
+    }
 
-            if safe_point <= range_meta.safe_point() {
-                info!(
-                    "safe point not large enough";
-                    "prev" => range_meta.safe_point(),
-                    "current" => safe_point,
-                );
-                return FilterMetrics::default();
-            }
+    fn on_gc_finished(&mut self, ranges: BTreeSet<CacheRange>) {
+        let mut core = self.engine.write();
+        core.mut_range_manager().on_gc_finished(ranges);
+    }
+}
+
+/// Runs one gc pass over the cached `range`, advancing its safe point to
+/// `safe_point` (further capped by the min timestamp of its live snapshots)
+/// and physically removing the stale versions below it. Shared by the
+/// background gc rounds and [`RangeCacheMemoryEngine::gc_range`].
+pub(crate) fn gc_range(
+    engine: &Arc<RwLock<RangeCacheMemoryEngineCore>>,
+    range: &CacheRange,
+    safe_point: u64,
+    oldest_seqno: u64,
+    values_framed: bool,
+) -> FilterMetrics {
+    let (skiplist_engine, safe_ts) = {
+        let mut core = engine.write();
+        let Some(range_meta) = core.mut_range_manager().mut_range_meta(range) else {
+            return FilterMetrics::default();
+        };
+        let min_snapshot = range_meta
+            .range_snapshot_list()
+            .min_snapshot_ts()
+            .unwrap_or(u64::MAX);
+        let safe_point = u64::min(safe_point, min_snapshot);
 
-            // todo: change it to debug!
+        if safe_point <= range_meta.safe_point() {
             info!(
-                "safe point update";
+                "safe point not large enough";
                 "prev" => range_meta.safe_point(),
                 "current" => safe_point,
-                "range" => ?range,
             );
-            range_meta.set_safe_point(safe_point);
-            (core.engine(), safe_point)
-        };
-
-        let start = Instant::now();
-        let write_cf_handle = skiplist_engine.cf_handle(CF_WRITE);
-        let default_cf_handle = skiplist_engine.cf_handle(CF_DEFAULT);
-        let mut filter = Filter::new(
-            safe_ts,
-            oldest_seqno,
-            default_cf_handle,
-            write_cf_handle.clone(),
-        );
-
-        let mut iter = write_cf_handle.iterator();
-        let guard = &epoch::pin();
-        let (start_key, end_key) = encode_key_for_boundary_with_mvcc(range);
-        iter.seek(&start_key, guard);
-        while iter.valid() && iter.key() < &end_key {
-            let k = iter.key();
-            let v = iter.value();
-            if let Err(e) = filter.filter(k.as_bytes(), v.as_bytes()) {
-                warn!(
-                    "Something Wrong in memory engine GC";
-                    "error" => ?e,
-                );
-            }
-            iter.next(guard);
+            return FilterMetrics::default();
         }
 
-        let duration = start.saturating_elapsed();
-        RANGE_GC_TIME_HISTOGRAM.observe(duration.as_secs_f64());
+        // todo: change it to debug!
         info!(
-            "range gc complete";
+            "safe point update";
+            "prev" => range_meta.safe_point(),
+            "current" => safe_point,
             "range" => ?range,
-            "gc_duration" => ?duration,
-            "total_version" => filter.metrics.total,
-            "filtered_version" => filter.metrics.filtered,
-            "below_safe_point_unique_keys" => filter.metrics.unique_key,
-            "below_safe_point_version" => filter.metrics.versions,
-            "below_safe_point_delete_version" => filter.metrics.delete_versions,
-            "current_safe_point" => safe_ts,
         );
+        range_meta.set_safe_point(safe_point);
+        (core.engine(), safe_point)
+    };
 
-        let mut metrics = std::mem::take(&mut filter.metrics);
-        if filter.cached_mvcc_delete_key.is_some() {
-            metrics.filtered += 1;
-        }
-        if filter.cached_skiplist_delete_key.is_some() {
-            metrics.filtered += 1;
+    let start = Instant::now();
+    let write_cf_handle = skiplist_engine.cf_handle(CF_WRITE);
+    let default_cf_handle = skiplist_engine.cf_handle(CF_DEFAULT);
+    let mut filter = Filter::new(
+        safe_ts,
+        oldest_seqno,
+        default_cf_handle,
+        write_cf_handle.clone(),
+        values_framed,
+    );
+
+    let mut iter = write_cf_handle.iterator();
+    let guard = &epoch::pin();
+    let (start_key, end_key) = encode_key_for_boundary_with_mvcc(range);
+    iter.seek(&start_key, guard);
+    while iter.valid() && iter.key() < &end_key {
+        let k = iter.key();
+        let v = iter.value();
+        if let Err(e) = filter.filter(k.as_bytes(), v.as_bytes()) {
+            warn!(
+                "Something Wrong in memory engine GC";
+                "error" => ?e,
+            );
         }
-        metrics
+        iter.next(guard);
     }
 
-    fn on_gc_finished(&mut self, ranges: BTreeSet<CacheRange>) {
-        let mut core = self.engine.write();
-        core.mut_range_manager().on_gc_finished(ranges);
+    let duration = start.saturating_elapsed();
+    RANGE_GC_TIME_HISTOGRAM.observe(duration.as_secs_f64());
+    info!(
+        "range gc complete";
+        "range" => ?range,
+        "gc_duration" => ?duration,
+        "total_version" => filter.metrics.total,
+        "filtered_version" => filter.metrics.filtered,
+        "below_safe_point_unique_keys" => filter.metrics.unique_key,
+        "below_safe_point_version" => filter.metrics.versions,
+        "below_safe_point_delete_version" => filter.metrics.delete_versions,
+        "current_safe_point" => safe_ts,
+    );
+
+    let mut metrics = std::mem::take(&mut filter.metrics);
+    if let Some(key) = &filter.cached_mvcc_delete_key {
+        metrics.filtered += 1;
+        metrics.filtered_bytes += key.len();
     }
+    if let Some(key) = &filter.cached_skiplist_delete_key {
+        metrics.filtered += 1;
+        metrics.filtered_bytes += key.len();
+    }
+    metrics
+}
 
-    /// Returns the first range to load with RocksDB snapshot. The `bool`
-    /// returned indicates whether the task has been canceled due to memory
-    /// issue.
+// The number of entries a load scans between checks of the age of the disk
+// snapshot backing it, see `load_snapshot_max_age`. Frequent enough that a
+// load reacts to an expired snapshot promptly, rare enough that the clock
+// read and scheduler lock stay off the per-entry path.
+const LOAD_SNAPSHOT_AGE_CHECK_INTERVAL: usize = 1024;
+
+impl BackgroundRunnerCore {
+    /// Claims the next range to load with RocksDB snapshot through the load
+    /// scheduler so that at most `load_concurrency` loads run simultaneously.
+    /// The `bool` returned indicates whether the task has been canceled due
+    /// to memory issue.
     ///
-    /// Returns `None` if there are no ranges to load.
-    fn get_range_to_load(&self) -> Option<(CacheRange, Arc<RocksSnapshot>, bool)> {
+    /// Returns `None` if there are no ranges to load or the concurrency limit
+    /// has been reached.
+    fn claim_range_to_load(&self) -> Option<(CacheRange, Arc<RocksSnapshot>, bool)> {
+        let range = self.load_scheduler.claim()?;
+        let core = self.engine.read();
+        let loading = core
+            .range_manager()
+            .pending_ranges_loading_data
+            .iter()
+            .find(|(r, ..)| *r == range)
+            .cloned();
+        if loading.is_none() {
+            // The range has been cleaned up before the load got a chance to
+            // run, release the concurrency slot directly.
+            self.load_scheduler.finish(&range);
+        }
+        loading
+    }
+
+    // Returns true if the load of `range` has been canceled, which is checked
+    // every scheduling quantum so that one huge region neither starves others
+    // nor keeps loading after it is evicted or split mid-load.
+    fn load_canceled(&self, range: &CacheRange) -> bool {
         let core = self.engine.read();
         core.range_manager()
             .pending_ranges_loading_data
-            .front()
-            .cloned()
+            .iter()
+            .find(|(r, ..)| r == range)
+            .map_or(true, |(_, _, canceled)| *canceled)
+    }
+
+    // Replaces the disk snapshot backing the load of `range` with a freshly
+    // acquired one, so that a long load does not pin obsolete WAL and SST
+    // files for its whole duration. The loading state of the range is
+    // untouched: the write path keeps buffering writes for it across the
+    // chunk boundary. Returns `None` when the disk engine is gone or the
+    // range is no longer loading; the caller then keeps the snapshot it
+    // has and relies on the cancellation check to stop the load.
+    fn refresh_load_snapshot(&self, range: &CacheRange) -> Option<Arc<RocksSnapshot>> {
+        let rocks_engine = { self.disk_engine.read().clone() }?;
+        let fresh = Arc::new(rocks_engine.snapshot(None));
+        {
+            let mut core = self.engine.write();
+            let (_, slot, _) = core
+                .mut_range_manager()
+                .pending_ranges_loading_data
+                .iter_mut()
+                .find(|(r, ..)| r == range)?;
+            *slot = fresh.clone();
+        }
+        self.load_scheduler
+            .record_snapshot(range, fresh.sequence_number());
+        Some(fresh)
     }
 
     // if `false` is returned, the load is canceled
@@ -465,14 +710,14 @@ impl BackgroundRunnerCore {
             let canceled = core
                 .range_manager()
                 .pending_ranges_loading_data
-                .front()
+                .iter()
+                .find(|(r, ..)| *r == range)
                 .unwrap()
                 .2;
             if canceled {
                 let (r, ..) = core
                     .mut_range_manager()
-                    .pending_ranges_loading_data
-                    .pop_front()
+                    .remove_pending_loading_range(&range)
                     .unwrap();
                 assert_eq!(r, range);
                 core.mut_range_manager()
@@ -480,6 +725,7 @@ impl BackgroundRunnerCore {
                     .insert(r.clone());
                 core.remove_cached_write_batch(&range);
                 drop(core);
+                self.load_scheduler.finish(&range);
                 fail::fail_point!("in_memory_engine_snapshot_load_canceled");
 
                 if let Err(e) =
@@ -504,12 +750,14 @@ impl BackgroundRunnerCore {
                 };
                 drop(core);
                 let guard = &epoch::pin();
+                let dedup_limit = self.config.value().value_dedup_table_limit;
                 for (seq, entry) in cache_batch {
                     entry
                         .write_to_memory(
                             seq,
                             &skiplist_engine,
                             self.memory_controller.clone(),
+                            dedup_limit,
                             guard,
                         )
                         .unwrap();
@@ -517,8 +765,16 @@ impl BackgroundRunnerCore {
                 fail::fail_point!("on_cached_write_batch_consumed");
             } else {
                 core.remove_cached_write_batch(&range);
-                RangeCacheMemoryEngineCore::pending_range_completes_loading(&mut core, &range);
+                // Start the range out at the disk gc safe point: reads below
+                // it may already miss versions the disk gc has pruned, so
+                // they must keep falling back to the disk engine.
+                RangeCacheMemoryEngineCore::pending_range_completes_loading(
+                    &mut core,
+                    &range,
+                    self.last_gc_safe_point.load(Ordering::SeqCst),
+                );
                 drop(core);
+                self.load_scheduler.finish(&range);
 
                 fail::fail_point!("pending_range_completes_loading");
                 break;
@@ -535,14 +791,15 @@ impl BackgroundRunnerCore {
         let mut core = self.engine.write();
         let (r, ..) = core
             .mut_range_manager()
-            .pending_ranges_loading_data
-            .pop_front()
+            .remove_pending_loading_range(&range)
             .unwrap();
         assert_eq!(r, range);
         core.remove_cached_write_batch(&range);
         core.mut_range_manager()
             .ranges_being_deleted
             .insert(r.clone());
+        drop(core);
+        self.load_scheduler.finish(&range);
 
         if let Err(e) = delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(vec![r]))
         {
@@ -554,6 +811,326 @@ impl BackgroundRunnerCore {
         }
     }
 
+    /// Loads every range the load scheduler is willing to hand out right now.
+    /// Runs on the load worker threads in production; the deterministic test
+    /// mode runs it inline so that loads finish before the caller returns.
+    async fn load_pending_ranges(mut self, delete_range_scheduler: Scheduler<BackgroundTask>) {
+        self.placement.ensure_current_thread();
+        let skiplist_engine = {
+            let core = self.engine.read();
+            core.engine().clone()
+        };
+        let events = { self.engine.read().range_manager().events().clone() };
+        while let Some((range, mut snap, mut canceled)) = self.claim_range_to_load() {
+            info!("Loading range"; "range" => ?&range);
+            events.record(range.clone(), RangeEventKind::LoadStarted);
+            let iter_opt = IterOptions::new(
+                Some(KeyBuilder::from_vec(range.start.clone(), 0, 0)),
+                Some(KeyBuilder::from_vec(range.end.clone(), 0, 0)),
+                false,
+            );
+            if self.memory_controller.reached_soft_limit() {
+                // We are running out of memory, so cancel the load.
+                canceled = true;
+                events.record(
+                    range.clone(),
+                    RangeEventKind::LoadRejected {
+                        reason: "memory-soft-limit",
+                    },
+                );
+            }
+
+            if canceled {
+                info!(
+                    "snapshot load canceled due to memory reaching soft limit";
+                    "range" => ?range,
+                );
+                self.on_snapshot_load_canceled(range, &delete_range_scheduler);
+                continue;
+            }
+
+            let cached_cfs = {
+                let core = self.engine.read();
+                core.range_manager().requested_cfs(&range)
+            };
+            let mut loaded_bytes = 0u64;
+            let value_compression = self.config.value().value_compression;
+            let compression_threshold = self.config.value().value_compression_threshold.0 as usize;
+            let dedup_limit = self.config.value().value_dedup_table_limit;
+            let mut used_refreshed_snapshot = false;
+            let mut snapshot_load = || -> bool {
+                let gc_safe_point = if self.config.value().gc_aware_load {
+                    self.last_gc_safe_point.load(Ordering::SeqCst)
+                } else {
+                    0
+                };
+                let mut load_filter = (gc_safe_point > 0).then(|| LoadFilter::new(gc_safe_point));
+                let mut bytes_in_quantum = 0;
+                let snapshot_max_age = self.config.value().load_snapshot_max_age.map(|d| d.0);
+                let mut entries_since_age_check = 0;
+                // Load the write cf before the default cf so that
+                // the filter knows which stale put versions were
+                // skipped when their values are encountered.
+                for &cf in &[CF_WRITE, CF_LOCK, CF_DEFAULT] {
+                    if !cached_cfs.contains(cf) {
+                        continue;
+                    }
+                    let handle = skiplist_engine.cf_handle(cf);
+                    // The key the scan of this cf has progressed to, so that
+                    // a chunk cut short by a snapshot refresh resumes right
+                    // after it instead of restarting the cf.
+                    let mut resume_from: Option<Vec<u8>> = None;
+                    'chunk: loop {
+                        let seq = snap.sequence_number();
+                        let guard = &epoch::pin();
+                        let mut iter = match snap.iterator_opt(cf, iter_opt.clone()) {
+                            Ok(iter) => iter,
+                            Err(e) => {
+                                error!("creating rocksdb iterator failed"; "cf" => cf, "err" => %e);
+                                return false;
+                            }
+                        };
+                        match resume_from.as_deref() {
+                            Some(key) => {
+                                iter.seek(key).unwrap();
+                                if iter.valid().unwrap() && iter.key() == key {
+                                    iter.next().unwrap();
+                                }
+                            }
+                            None => {
+                                iter.seek_to_first().unwrap();
+                            }
+                        }
+                        while iter.valid().unwrap() {
+                            if let Some(filter) = load_filter.as_mut() {
+                                let skip = match cf {
+                                    CF_WRITE => filter
+                                        .filter_write(iter.key(), iter.value())
+                                        .unwrap_or_else(|e| {
+                                            warn!(
+                                                "failed to parse write cf entry during load, cache it";
+                                                "err" => ?e,
+                                            );
+                                            false
+                                        }),
+                                    CF_DEFAULT => filter.filter_default(iter.key()),
+                                    _ => false,
+                                };
+                                if skip {
+                                    RANGE_LOAD_SKIPPED_ENTRIES.inc();
+                                    RANGE_LOAD_SKIPPED_BYTES
+                                        .inc_by((iter.key().len() + iter.value().len()) as u64);
+                                    iter.next().unwrap();
+                                    continue;
+                                }
+                            }
+                            // use the sequence number from RocksDB snapshot here as
+                            // the kv is clearly visible
+                            let mut encoded_key = encode_key(iter.key(), seq, ValueType::Value);
+                            // Frame (and maybe compress) the value the
+                            // same way the write path does, so all
+                            // stored values agree on the framing. See
+                            // the `compression` module.
+                            let mut val = if value_compression.is_enabled() {
+                                InternalBytes::from_bytes(
+                                    maybe_compress_value(iter.value(), compression_threshold).0,
+                                )
+                            } else {
+                                InternalBytes::from_vec(iter.value().to_vec())
+                            };
+
+                            let mut mem_size = RangeCacheWriteBatchEntry::calc_put_entry_size(
+                                iter.key(),
+                                val.as_bytes(),
+                            );
+                            // Share the value bytes with an identical cached
+                            // copy if there is one. See the `dedup` module.
+                            if dedup_limit > 0
+                                && let Some(interned) = skiplist_engine
+                                    .value_dedup
+                                    .intern(val.as_bytes(), dedup_limit)
+                            {
+                                if interned.shared {
+                                    // The bytes are accounted for by the
+                                    // tracked copy, only the handle overhead
+                                    // is acquired below.
+                                    mem_size -= interned.bytes.len();
+                                    val = InternalBytes::from_bytes(interned.bytes);
+                                }
+                                val.set_dedup_handle(
+                                    skiplist_engine.value_dedup.clone(),
+                                    interned.hash,
+                                );
+                            }
+
+                            // todo(SpadeA): we can batch acquire the memory size
+                            // here.
+                            if let MemoryUsage::HardLimitReached(n) =
+                                self.memory_controller.acquire(mem_size)
+                            {
+                                warn!(
+                                    "stop loading snapshot due to memory reaching hard limit";
+                                    "range" => ?range,
+                                    "memory_usage(MB)" => ReadableSize(n as u64).as_mb_f64(),
+                                );
+                                events.record(
+                                    range.clone(),
+                                    RangeEventKind::LoadRejected {
+                                        reason: "memory-hard-limit",
+                                    },
+                                );
+                                return false;
+                            }
+
+                            encoded_key.set_memory_controller(self.memory_controller.clone());
+                            val.set_memory_controller(self.memory_controller.clone());
+                            handle.insert(encoded_key, val, guard);
+                            self.load_scheduler.record_loaded_bytes(mem_size as u64);
+                            loaded_bytes += mem_size as u64;
+                            bytes_in_quantum += mem_size;
+                            if self.load_scheduler.should_yield(bytes_in_quantum) {
+                                bytes_in_quantum = 0;
+                                // Re-check cancellation every scheduling quantum
+                                // so an evicted or split region stops loading
+                                // promptly, and let other loads sharing the
+                                // worker threads make progress.
+                                if self.load_canceled(&range) {
+                                    info!(
+                                        "stop loading snapshot due to cancellation";
+                                        "range" => ?range,
+                                    );
+                                    return false;
+                                }
+                                std::thread::yield_now();
+                            }
+                            entries_since_age_check += 1;
+                            if let Some(max_age) = snapshot_max_age
+                                && entries_since_age_check >= LOAD_SNAPSHOT_AGE_CHECK_INTERVAL
+                            {
+                                entries_since_age_check = 0;
+                                if self
+                                    .load_scheduler
+                                    .snapshot_age(&range)
+                                    .map_or(false, |(age, _)| age >= max_age)
+                                {
+                                    // The snapshot has been held for too long
+                                    // and keeps obsolete WAL and SST files
+                                    // alive; swap it for a fresh one and
+                                    // resume the scan right after the current
+                                    // key. When the refresh fails (e.g. the
+                                    // range stopped loading), keep scanning
+                                    // with the old snapshot; cancellation is
+                                    // observed by the quantum check above.
+                                    if let Some(fresh) = self.refresh_load_snapshot(&range) {
+                                        info!(
+                                            "refreshed the disk snapshot of a long load";
+                                            "range" => ?range,
+                                            "old_seq" => seq,
+                                            "new_seq" => fresh.sequence_number(),
+                                        );
+                                        RANGE_LOAD_SNAPSHOT_REFRESHES.inc();
+                                        used_refreshed_snapshot = true;
+                                        resume_from = Some(iter.key().to_vec());
+                                        snap = fresh;
+                                        continue 'chunk;
+                                    }
+                                }
+                            }
+                            iter.next().unwrap();
+                        }
+                        break;
+                    }
+                }
+                true
+            };
+
+            let start = Instant::now();
+            if !snapshot_load() {
+                info!(
+                    "snapshot load failed";
+                    "range" => ?range,
+                );
+                self.on_snapshot_load_canceled(range, &delete_range_scheduler);
+                continue;
+            }
+
+            if used_refreshed_snapshot {
+                RANGE_LOAD_CHUNKED_LOADS.inc();
+            }
+            if self.on_snapshot_load_finished(range.clone(), &delete_range_scheduler) {
+                let duration = start.saturating_elapsed();
+                RANGE_LOAD_TIME_HISTOGRAM.observe(duration.as_secs_f64());
+                info!(
+                    "Loading range finished";
+                    "range" => ?range,
+                    "duration(sec)" => ?duration,
+                );
+                events.record(
+                    range.clone(),
+                    RangeEventKind::LoadFinished {
+                        bytes: loaded_bytes,
+                    },
+                );
+            } else {
+                info!("Loading range canceled";"range" => ?range);
+            }
+        }
+    }
+
+    /// Pre-warm the disk engine's block cache for the evicted `ranges` by
+    /// reading them back with cache-filling iterators, sharing one
+    /// per-eviction byte budget across the ranges. A range whose eviction is
+    /// reverted by a concurrent load aborts its pass: the cache serves its
+    /// reads again and the warming has nothing left to do.
+    fn prewarm_evicted_ranges(
+        &self,
+        disk_engine: &RocksEngine,
+        ranges: &[CacheRange],
+        reason: &'static str,
+        budget: u64,
+        rate: u64,
+    ) {
+        let limiter = Limiter::new(if rate == 0 {
+            f64::INFINITY
+        } else {
+            rate as f64
+        });
+        let mut remaining = budget;
+        let mut total = 0;
+        for range in ranges {
+            if remaining == 0 {
+                break;
+            }
+            let readmitted = || !self.engine.read().range_manager().range_evicted(range);
+            match prewarm_range(disk_engine, range, remaining, &limiter, &readmitted) {
+                Ok((bytes, aborted)) => {
+                    RANGE_CACHE_PREWARM_BYTES.inc_by(bytes);
+                    total += bytes;
+                    remaining = remaining.saturating_sub(bytes);
+                    if aborted {
+                        RANGE_CACHE_PREWARM_SKIPPED
+                            .with_label_values(&["re-admitted"])
+                            .inc();
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "pre-warm scan of evicted range failed";
+                        "range" => ?range,
+                        "err" => ?e,
+                    );
+                    break;
+                }
+            }
+        }
+        info!(
+            "pre-warmed evicted ranges on the disk engine";
+            "ranges" => ranges.len(),
+            "bytes" => total,
+            "reason" => reason,
+        );
+    }
+
     /// Eviction on soft limit reached:
     ///
     /// When soft limit is reached, collect the candidates for eviction, and
@@ -579,7 +1156,9 @@ impl BackgroundRunnerCore {
         // TODO (afeinberg, low): consider returning just an iterator and using scan
         // below for cleaner code.
         range_stats_manager.collect_candidates_for_eviction(&mut ranges_to_evict, |range| {
-            self.engine.read().range_manager().contains_range(range)
+            let core = self.engine.read();
+            let range_manager = core.range_manager();
+            range_manager.contains_range(range) && !range_manager.is_pinned(range)
         });
 
         let mut ranges_to_delete = vec![];
@@ -591,7 +1170,9 @@ impl BackgroundRunnerCore {
             }
             let evicted_range = {
                 let mut engine_wr = self.engine.write();
-                let mut ranges = engine_wr.mut_range_manager().evict_range(range);
+                let mut ranges = engine_wr
+                    .mut_range_manager()
+                    .evict_range(range, "memory-soft-limit");
                 if !ranges.is_empty() {
                     info!(
                         "evict on soft limit reached";
@@ -615,6 +1196,71 @@ impl BackgroundRunnerCore {
         }
 
         if !ranges_to_delete.is_empty() {
+            note_prewarm_skipped(&self.config.value(), "memory-soft-limit");
+            if let Err(e) =
+                delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(ranges_to_delete))
+            {
+                error!(
+                    "schedule deletet range failed";
+                    "err" => ?e,
+                );
+                assert!(tikv_util::thread_group::is_shutdown(!cfg!(test)));
+            }
+        }
+    }
+
+    /// Evict cached ranges that have not been read for longer than `ttl`.
+    ///
+    /// Ranges with ongoing snapshots are skipped; they are still serving reads
+    /// and will be revisited in a later round once the snapshots are dropped.
+    fn evict_expired_ranges(
+        &self,
+        ttl: Duration,
+        delete_range_scheduler: &Scheduler<BackgroundTask>,
+    ) {
+        let now = now_unix_millis();
+        let ttl_millis = ttl.as_millis() as u64;
+        let expired_ranges: Vec<CacheRange> = {
+            let core = self.engine.read();
+            core.range_manager()
+                .ranges()
+                .iter()
+                .filter(|(_, meta)| {
+                    !meta.is_pinned()
+                        && meta.range_snapshot_list().is_empty()
+                        && now.saturating_sub(meta.last_access()) >= ttl_millis
+                })
+                .map(|(r, _)| r.clone())
+                .collect()
+        };
+        if expired_ranges.is_empty() {
+            return;
+        }
+        let mut ranges_to_delete = vec![];
+        {
+            let mut core = self.engine.write();
+            for r in expired_ranges {
+                // The range may have been accessed or evicted between the check
+                // above and acquiring the write lock, so recheck under the lock.
+                let still_expired = core.range_manager().ranges().get(&r).is_some_and(|meta| {
+                    !meta.is_pinned()
+                        && meta.range_snapshot_list().is_empty()
+                        && now.saturating_sub(meta.last_access()) >= ttl_millis
+                });
+                if !still_expired {
+                    continue;
+                }
+                info!(
+                    "evict expired range";
+                    "range" => ?r,
+                    "ttl" => ?ttl,
+                );
+                ranges_to_delete
+                    .append(&mut core.mut_range_manager().evict_range(&r, "ttl-expired"));
+            }
+        }
+        if !ranges_to_delete.is_empty() {
+            note_prewarm_skipped(&self.config.value(), "ttl-expired");
             if let Err(e) =
                 delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(ranges_to_delete))
             {
@@ -656,7 +1302,12 @@ impl BackgroundRunnerCore {
         for evict_range in ranges_to_remove {
             if self.memory_controller.reached_soft_limit() {
                 let mut core = self.engine.write();
-                let mut ranges = core.mut_range_manager().evict_range(&evict_range);
+                if core.range_manager().is_pinned(&evict_range) {
+                    continue;
+                }
+                let mut ranges = core
+                    .mut_range_manager()
+                    .evict_range(&evict_range, "load-evict");
                 info!(
                     "load_evict: soft limit reached";
                     "range_to_evict" => ?&evict_range,
@@ -666,6 +1317,7 @@ impl BackgroundRunnerCore {
             }
         }
         if !ranges_to_delete.is_empty() {
+            note_prewarm_skipped(&self.config.value(), "load-evict");
             if let Err(e) =
                 delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(ranges_to_delete))
             {
@@ -685,28 +1337,278 @@ impl BackgroundRunnerCore {
         range_stats_manager.set_checking_top_regions(false);
         info!("load_evict complete");
     }
-}
 
-// Flush epoch and pin enough times to make the delayed operations be executed
-#[cfg(test)]
-pub(crate) fn flush_epoch() {
-    {
-        let guard = &epoch::pin();
-        guard.flush();
-    }
-    // Local epoch tries to advance the global epoch every 128 pins. When global
-    // epoch advances, the operations(here, means delete) in the older epoch can be
-    // executed.
-    for _ in 0..128 {
-        let _ = &epoch::pin();
+    /// Compares the incremental cache and shadow disk checksums of the
+    /// cached ranges, see the `checksum` module. Diverged ranges are counted
+    /// and logged, and evicted when `evict_on_checksum_mismatch` is set.
+    fn compare_range_checksums(&self, delete_range_scheduler: &Scheduler<BackgroundTask>) {
+        let (checksums, provenance, cached_ranges) = {
+            let core = self.engine.read();
+            (
+                core.checksums(),
+                core.provenance(),
+                core.range_manager()
+                    .ranges()
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let mismatched = checksums.check(&cached_ranges);
+        if mismatched.is_empty() {
+            return;
+        }
+        for r in &mismatched {
+            RANGE_CACHE_CHECKSUM_MISMATCHES.inc();
+            error!(
+                "range cache checksum mismatch";
+                "range" => ?r,
+                // The apply that wrote the range last, when
+                // `provenance_tracking` is enabled.
+                "latest_provenance" => ?provenance.latest(r),
+            );
+        }
+        if !self.config.value().evict_on_checksum_mismatch {
+            return;
+        }
+        let mut ranges_to_delete = vec![];
+        {
+            let mut core = self.engine.write();
+            for r in &mismatched {
+                ranges_to_delete
+                    .append(&mut core.mut_range_manager().evict_range(r, "checksum-mismatch"));
+            }
+        }
+        if !ranges_to_delete.is_empty() {
+            note_prewarm_skipped(&self.config.value(), "checksum-mismatch");
+            if let Err(e) =
+                delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(ranges_to_delete))
+            {
+                error!(
+                    "schedule delete range failed";
+                    "err" => ?e,
+                );
+                assert!(tikv_util::thread_group::is_shutdown(!cfg!(test)));
+            }
+        }
     }
-}
 
-pub struct BackgroundRunner {
-    core: BackgroundRunnerCore,
+    /// The watchdog for evictions stuck on leaked snapshots: an evicted range
+    /// whose snapshots are never dropped (a wedged request, a reference cycle)
+    /// stays in the historical set forever, its memory never reclaimed and the
+    /// overlapped deletions never executed. Reports every historical range
+    /// older than `stuck_eviction_threshold` and, when
+    /// `force_complete_stuck_evictions` is set, force-completes those stuck
+    /// for an additional full threshold: the leaked snapshots are poisoned so
+    /// their reads fail instead of touching deleted data, and the blocked
+    /// ranges are handed to the delete worker.
+    fn check_stuck_evictions(&self, delete_range_scheduler: &Scheduler<BackgroundTask>) {
+        let (threshold, force) = {
+            let config = self.config.value();
+            (
+                config.stuck_eviction_threshold.0,
+                config.force_complete_stuck_evictions,
+            )
+        };
+        if threshold.is_zero() {
+            return;
+        }
+        let threshold_millis = threshold.as_millis() as u64;
+        let now = now_unix_millis();
+        let stuck = {
+            let core = self.engine.read();
+            core.range_manager()
+                .stuck_historical_ranges(now, threshold_millis)
+        };
+        RANGE_CACHE_STUCK_EVICTIONS.set(stuck.len() as i64);
+        if stuck.is_empty() {
+            return;
+        }
 
-    // We have following four separate workers so that each type of task would not block each
-    // others
+        let mut ranges_to_delete = vec![];
+        for (range, stuck_for, snapshots) in stuck {
+            warn!(
+                "range cache eviction stuck on undropped snapshots";
+                "range" => ?&range,
+                "stuck_for_millis" => stuck_for,
+                "snapshots" => ?&snapshots,
+            );
+            // One more full threshold as grace period before forcing, so a
+            // slow but live reader is not poisoned right at the reporting
+            // threshold.
+            if force && stuck_for >= threshold_millis * 2 {
+                warn!(
+                    "force completing stuck range cache eviction, leaked snapshots are poisoned";
+                    "range" => ?&range,
+                    "stuck_for_millis" => stuck_for,
+                );
+                let mut core = self.engine.write();
+                ranges_to_delete
+                    .append(&mut core.mut_range_manager().force_complete_eviction(&range));
+            }
+        }
+        if !ranges_to_delete.is_empty() {
+            if let Err(e) =
+                delete_range_scheduler.schedule_force(BackgroundTask::DeleteRange(ranges_to_delete))
+            {
+                error!(
+                    "schedule deletet range failed";
+                    "err" => ?e,
+                );
+                assert!(tikv_util::thread_group::is_shutdown(!cfg!(test)));
+            }
+        }
+    }
+
+    // Refreshes the per-cf skiplist structural metrics: the node count gauges
+    // and a sampled search depth histogram. The depth of a seek is measured
+    // in comparator invocations, for a probe key drawn roughly uniformly from
+    // the cached keyspace; a healthy skiplist stays within a small factor of
+    // log2 of the node count, while a pathological key distribution (e.g.
+    // monotonically appended keys with degenerated towers) shows up as
+    // depths far above that. One pass performs at most
+    // `SKIPLIST_STATS_SAMPLE_SIZE` seeks per cf, so its cost is bounded
+    // regardless of the skiplist size, and nothing runs between passes.
+    fn sample_skiplist_stats(&self) {
+        let skiplist_engine = self.engine.read().engine();
+        for (id, skiplist) in skiplist_engine.data.iter().enumerate() {
+            let cf = id_to_cf(id);
+            let node_count = skiplist.len();
+            RANGE_CACHE_SKIPLIST_NODE_COUNT
+                .with_label_values(&[cf])
+                .set(node_count as i64);
+            if node_count == 0 {
+                continue;
+            }
+
+            let guard = &epoch::pin();
+            let mut iter = skiplist.owned_iter();
+            iter.seek_to_first(guard);
+            if !iter.valid() {
+                continue;
+            }
+            let first = decode_key(iter.key().as_slice()).user_key.to_vec();
+            iter.seek_for_prev(&encode_seek_for_prev_key(&[0xff; 16], 0), guard);
+            if !iter.valid() {
+                continue;
+            }
+            let last = decode_key(iter.key().as_slice()).user_key.to_vec();
+
+            let histogram = RANGE_CACHE_SKIPLIST_SEARCH_DEPTH.with_label_values(&[cf]);
+            let mut rng = rand::thread_rng();
+            for _ in 0..std::cmp::min(SKIPLIST_STATS_SAMPLE_SIZE, node_count) {
+                let probe = random_user_key_between(&first, &last, &mut rng);
+                let seek_key = encode_seek_key(&probe, u64::MAX);
+                let calls_before = thread_comparator_calls();
+                iter.seek(&seek_key, guard);
+                histogram.observe((thread_comparator_calls() - calls_before) as f64);
+            }
+        }
+    }
+}
+
+// The sampling budget of one structural sampling pass, per cf. Large enough
+// for a stable depth histogram, small enough that a pass costs no more than a
+// few hundred point lookups.
+const SKIPLIST_STATS_SAMPLE_SIZE: usize = 256;
+
+// Draws a user key roughly uniformly between `first` and `last` by
+// interpolating on their 16 byte prefixes. The returned key does not need to
+// exist in the skiplist: the sampled seek measures the descent to its
+// successor, which is what the depth histogram is after.
+fn random_user_key_between(first: &[u8], last: &[u8], rng: &mut impl rand::Rng) -> Vec<u8> {
+    fn prefix(key: &[u8]) -> u128 {
+        let mut buf = [0u8; 16];
+        let len = std::cmp::min(key.len(), buf.len());
+        buf[..len].copy_from_slice(&key[..len]);
+        u128::from_be_bytes(buf)
+    }
+    let (start, end) = (prefix(first), prefix(last));
+    if start >= end {
+        return first.to_vec();
+    }
+    rng.gen_range(start..=end).to_be_bytes().to_vec()
+}
+
+// Flush epoch and pin enough times to make the delayed operations be executed
+#[cfg(test)]
+pub(crate) fn flush_epoch() {
+    {
+        let guard = &epoch::pin();
+        guard.flush();
+    }
+    // Local epoch tries to advance the global epoch every 128 pins. When global
+    // epoch advances, the operations(here, means delete) in the older epoch can be
+    // executed.
+    for _ in 0..128 {
+        let _ = &epoch::pin();
+    }
+}
+
+// Eviction reasons that mean the store is short on memory. Reading the
+// evicted data back to warm the disk engine's block cache would work against
+// the reclaim, so these evictions never pre-warm.
+const PREWARM_MEMORY_PRESSURE_REASONS: &[&str] =
+    &["memory-soft-limit", "memory-hard-limit", "write-hard-limit"];
+
+// How many keys the pre-warm scan reads between checks of the abort
+// condition, which takes the engine read lock.
+const PREWARM_ABORT_CHECK_INTERVAL: usize = 256;
+
+// Counts a pre-warm pass that was skipped, but only while pre-warming is
+// enabled: with a zero budget every eviction would count and the metric
+// would be noise.
+pub(crate) fn note_prewarm_skipped(config: &RangeCacheEngineConfig, reason: &'static str) {
+    if config.evict_prewarm_budget.0 > 0 {
+        RANGE_CACHE_PREWARM_SKIPPED.with_label_values(&[reason]).inc();
+    }
+}
+
+// One cache-filling pass over `range` on the disk engine, reading at most
+// `budget` bytes under `limiter`. Returns the bytes read and whether the pass
+// was cut short by `aborted`, which is checked periodically.
+pub(crate) fn prewarm_range<E: Iterable>(
+    disk_engine: &E,
+    range: &CacheRange,
+    budget: u64,
+    limiter: &Limiter,
+    aborted: &dyn Fn() -> bool,
+) -> engine_traits::Result<(u64, bool)> {
+    let mut bytes = 0;
+    let mut keys_since_check = 0;
+    for &cf in &[CF_WRITE, CF_LOCK, CF_DEFAULT] {
+        let iter_opt = IterOptions::new(
+            Some(KeyBuilder::from_vec(range.start.clone(), 0, 0)),
+            Some(KeyBuilder::from_vec(range.end.clone(), 0, 0)),
+            true,
+        );
+        let mut iter = disk_engine.iterator_opt(cf, iter_opt)?;
+        let mut valid = iter.seek_to_first()?;
+        while valid {
+            let entry_size = iter.key().len() + iter.value().len();
+            limiter.blocking_consume(entry_size);
+            bytes += entry_size as u64;
+            if bytes >= budget {
+                return Ok((bytes, false));
+            }
+            keys_since_check += 1;
+            if keys_since_check >= PREWARM_ABORT_CHECK_INTERVAL {
+                keys_since_check = 0;
+                if aborted() {
+                    return Ok((bytes, true));
+                }
+            }
+            valid = iter.next()?;
+        }
+    }
+    Ok((bytes, false))
+}
+
+pub struct BackgroundRunner {
+    core: BackgroundRunnerCore,
+
+    // We have following four separate workers so that each type of task would not block each
+    // others
     range_load_remote: Remote<yatp::task::future::TaskCell>,
     range_load_worker: Worker,
 
@@ -724,6 +1626,11 @@ pub struct BackgroundRunner {
     lock_cleanup_remote: Remote<yatp::task::future::TaskCell>,
     lock_cleanup_worker: Worker,
 
+    // Block cache pre-warm scans for evicted ranges. Separate so that a
+    // rate-limited scan never delays loads or deletions.
+    prewarm_remote: Remote<yatp::task::future::TaskCell>,
+    prewarm_worker: Worker,
+
     // The last sequence number for the lock cf tombstone cleanup
     last_seqno: u64,
     // RocksEngine is used to get the oldest snapshot sequence number.
@@ -737,6 +1644,7 @@ impl Drop for BackgroundRunner {
         self.gc_range_worker.stop();
         self.load_evict_worker.stop();
         self.lock_cleanup_worker.stop();
+        self.prewarm_worker.stop();
     }
 }
 
@@ -745,17 +1653,30 @@ impl BackgroundRunner {
         engine: Arc<RwLock<RangeCacheMemoryEngineCore>>,
         memory_controller: Arc<MemoryController>,
         region_info_provider: Option<Arc<dyn RegionInfoProvider>>,
-        expected_region_size: usize,
+        config: Arc<VersionTrack<RangeCacheEngineConfig>>,
     ) -> (Self, Scheduler<BackgroundTask>) {
+        let expected_region_size = config.value().expected_region_size();
+        let load_concurrency = config.value().load_concurrency();
+        let placement = ThreadPlacement::new(
+            &config.value().background_worker_cpu_set,
+            config.value().numa_interleave_allocations,
+        );
+        let load_scheduler = Arc::new(LoadScheduler::new(load_concurrency));
         let range_load_worker = Builder::new("background-range-load-worker")
-            // Range load now is implemented sequentially, so we must use exactly one thread to handle it.
-            // todo(SpadeA): if the load speed is a bottleneck, we may consider to use multiple threads to load ranges.
-            .thread_count(1)
+            // The number of loads that run simultaneously is bounded by the load scheduler, so
+            // one worker thread per concurrency slot is enough.
+            .thread_count(usize::max(load_concurrency, 1))
             .create();
         let range_load_remote = range_load_worker.remote();
 
         let delete_range_worker = Worker::new("background-delete-range-worker");
-        let delete_range_runner = DeleteRangeRunner::new(engine.clone());
+        let delete_range_runner = DeleteRangeRunner::new(
+            engine.clone(),
+            memory_controller.clone(),
+            load_scheduler.clone(),
+            placement.clone(),
+            config.clone(),
+        );
         let delete_range_scheduler =
             delete_range_worker.start_with_timer("delete-range-runner", delete_range_runner);
 
@@ -771,6 +1692,9 @@ impl BackgroundRunner {
         let load_evict_worker = Worker::new("background-region-load-evict-worker");
         let load_evict_remote = load_evict_worker.remote();
 
+        let prewarm_worker = Worker::new("background-evict-prewarm-worker");
+        let prewarm_remote = prewarm_worker.remote();
+
         let num_regions_to_cache = memory_controller.soft_limit_threshold() / expected_region_size;
         let range_stats_manager = region_info_provider.map(|region_info_provider| {
             RangeStatsManager::new(
@@ -786,6 +1710,11 @@ impl BackgroundRunner {
                     engine,
                     memory_controller,
                     range_stats_manager,
+                    load_scheduler,
+                    config,
+                    last_gc_safe_point: Arc::new(AtomicU64::new(0)),
+                    disk_engine: Arc::new(RwLock::new(None)),
+                    placement,
                 },
                 range_load_worker,
                 range_load_remote,
@@ -797,6 +1726,8 @@ impl BackgroundRunner {
                 load_evict_remote,
                 lock_cleanup_remote,
                 lock_cleanup_worker,
+                prewarm_remote,
+                prewarm_worker,
                 last_seqno: 0,
                 rocks_engine: None,
             },
@@ -811,10 +1742,16 @@ impl Runnable for BackgroundRunner {
     fn run(&mut self, task: Self::Task) {
         match task {
             BackgroundTask::SetRocksEngine(rocks_engine) => {
-                self.rocks_engine = Some(rocks_engine);
+                self.rocks_engine = Some(rocks_engine.clone());
+                *self.core.disk_engine.write() = Some(rocks_engine);
                 fail::fail_point!("in_memory_engine_set_rocks_engine");
             }
             BackgroundTask::Gc(t) => {
+                // Record it even if this gc round aborts below so that gc
+                // aware loads can filter with an up-to-date safe point.
+                self.core
+                    .last_gc_safe_point
+                    .fetch_max(t.safe_point, Ordering::SeqCst);
                 let seqno = (|| {
                     fail::fail_point!("in_memory_engine_gc_oldest_seqno", |t| {
                         Some(t.unwrap().parse::<u64>().unwrap())
@@ -843,6 +1780,7 @@ impl Runnable for BackgroundRunner {
                 let mut core = self.core.clone();
                 if let Some(ranges) = core.ranges_for_gc() {
                     let f = async move {
+                        core.placement.ensure_current_thread();
                         let mut metrics = FilterMetrics::default();
                         for range in &ranges {
                             let m = core.gc_range(range, t.safe_point, seqno);
@@ -856,112 +1794,10 @@ impl Runnable for BackgroundRunner {
                 }
             }
             BackgroundTask::LoadRange => {
-                let mut core = self.core.clone();
+                let core = self.core.clone();
                 let delete_range_scheduler = self.delete_range_scheduler.clone();
-                let f = async move {
-                    let skiplist_engine = {
-                        let core = core.engine.read();
-                        core.engine().clone()
-                    };
-                    while let Some((range, snap, mut canceled)) = core.get_range_to_load() {
-                        info!("Loading range"; "range" => ?&range);
-                        let iter_opt = IterOptions::new(
-                            Some(KeyBuilder::from_vec(range.start.clone(), 0, 0)),
-                            Some(KeyBuilder::from_vec(range.end.clone(), 0, 0)),
-                            false,
-                        );
-                        if core.memory_controller.reached_soft_limit() {
-                            // We are running out of memory, so cancel the load.
-                            canceled = true;
-                        }
-
-                        if canceled {
-                            info!(
-                                "snapshot load canceled due to memory reaching soft limit";
-                                "range" => ?range,
-                            );
-                            core.on_snapshot_load_canceled(range, &delete_range_scheduler);
-                            continue;
-                        }
-
-                        let snapshot_load = || -> bool {
-                            for &cf in DATA_CFS {
-                                let handle = skiplist_engine.cf_handle(cf);
-                                let seq = snap.sequence_number();
-                                let guard = &epoch::pin();
-                                match snap.iterator_opt(cf, iter_opt.clone()) {
-                                    Ok(mut iter) => {
-                                        iter.seek_to_first().unwrap();
-                                        while iter.valid().unwrap() {
-                                            // use the sequence number from RocksDB snapshot here as
-                                            // the kv is clearly visible
-                                            let mut encoded_key =
-                                                encode_key(iter.key(), seq, ValueType::Value);
-                                            let mut val =
-                                                InternalBytes::from_vec(iter.value().to_vec());
-
-                                            let mem_size =
-                                                RangeCacheWriteBatchEntry::calc_put_entry_size(
-                                                    iter.key(),
-                                                    val.as_bytes(),
-                                                );
-
-                                            // todo(SpadeA): we can batch acquire the memory size
-                                            // here.
-                                            if let MemoryUsage::HardLimitReached(n) =
-                                                core.memory_controller.acquire(mem_size)
-                                            {
-                                                warn!(
-                                                    "stop loading snapshot due to memory reaching hard limit";
-                                                    "range" => ?range,
-                                                    "memory_usage(MB)" => ReadableSize(n as u64).as_mb_f64(),
-                                                );
-                                                return false;
-                                            }
-
-                                            encoded_key.set_memory_controller(
-                                                core.memory_controller.clone(),
-                                            );
-                                            val.set_memory_controller(
-                                                core.memory_controller.clone(),
-                                            );
-                                            handle.insert(encoded_key, val, guard);
-                                            iter.next().unwrap();
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("creating rocksdb iterator failed"; "cf" => cf, "err" => %e);
-                                        return false;
-                                    }
-                                }
-                            }
-                            true
-                        };
-
-                        let start = Instant::now();
-                        if !snapshot_load() {
-                            info!(
-                                "snapshot load failed";
-                                "range" => ?range,
-                            );
-                            core.on_snapshot_load_canceled(range, &delete_range_scheduler);
-                            continue;
-                        }
-
-                        if core.on_snapshot_load_finished(range.clone(), &delete_range_scheduler) {
-                            let duration = start.saturating_elapsed();
-                            RANGE_LOAD_TIME_HISTOGRAM.observe(duration.as_secs_f64());
-                            info!(
-                                "Loading range finished";
-                                "range" => ?range,
-                                "duration(sec)" => ?duration,
-                            );
-                        } else {
-                            info!("Loading range canceled";"range" => ?range);
-                        }
-                    }
-                };
-                self.range_load_remote.spawn(f);
+                self.range_load_remote
+                    .spawn(core.load_pending_ranges(delete_range_scheduler));
             }
             BackgroundTask::MemoryCheckAndEvict => {
                 let mem_usage = self.core.memory_controller.mem_usage();
@@ -984,12 +1820,49 @@ impl Runnable for BackgroundRunner {
             // DeleteRange task is executed by `DeleteRangeRunner` with a different scheduler so
             // that the task will not be scheduled to here.
             BackgroundTask::DeleteRange(_) => unreachable!(),
+            BackgroundTask::PrewarmRange { ranges, reason } => {
+                let budget = self.core.config.value().evict_prewarm_budget.0;
+                if budget == 0 {
+                    // Pre-warming was disabled after the task was scheduled.
+                    RANGE_CACHE_PREWARM_SKIPPED
+                        .with_label_values(&["disabled"])
+                        .inc();
+                    return;
+                }
+                if PREWARM_MEMORY_PRESSURE_REASONS.contains(&reason) {
+                    RANGE_CACHE_PREWARM_SKIPPED.with_label_values(&[reason]).inc();
+                    return;
+                }
+                let Some(rocks_engine) = self.rocks_engine.clone() else {
+                    RANGE_CACHE_PREWARM_SKIPPED
+                        .with_label_values(&["no-disk-engine"])
+                        .inc();
+                    return;
+                };
+                let rate = self.core.config.value().evict_prewarm_rate_limit.0;
+                let core = self.core.clone();
+                self.prewarm_remote.spawn(async move {
+                    core.placement.ensure_current_thread();
+                    core.prewarm_evicted_ranges(&rocks_engine, &ranges, reason, budget, rate);
+                });
+            }
             BackgroundTask::TopRegionsLoadEvict => {
                 let delete_range_scheduler = self.delete_range_scheduler.clone();
                 let core = self.core.clone();
                 let task = async move { core.top_regions_load_evict(&delete_range_scheduler) };
                 self.load_evict_remote.spawn(task);
             }
+            BackgroundTask::CheckStuckEvictions => {
+                let delete_range_scheduler = self.delete_range_scheduler.clone();
+                let core = self.core.clone();
+                let task = async move { core.check_stuck_evictions(&delete_range_scheduler) };
+                self.load_evict_remote.spawn(task);
+            }
+            BackgroundTask::SampleSkiplistStats => {
+                let core = self.core.clone();
+                let task = async move { core.sample_skiplist_stats() };
+                self.load_evict_remote.spawn(task);
+            }
             BackgroundTask::CleanLockTombstone(snapshot_seqno) => {
                 if snapshot_seqno < self.last_seqno {
                     return;
@@ -1068,10 +1941,18 @@ impl Runnable for BackgroundRunner {
 
                 self.lock_cleanup_remote.spawn(f);
             }
+            // The sentinel is only ever scheduled on the delete range worker.
+            #[cfg(test)]
+            BackgroundTask::TestCompletion(_) => unreachable!(),
         }
     }
 }
 
+// The interval of the stats/maintenance tick of `BackgroundRunner`. Ttl
+// evictions are driven by it, so a `range_ttl` below it cannot be honored;
+// config validation rejects such values.
+pub(crate) const BACKGROUND_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
 impl RunnableWithTimer for BackgroundRunner {
     fn on_timeout(&mut self) {
         let mem_usage = self.core.memory_controller.mem_usage();
@@ -1082,7 +1963,16 @@ impl RunnableWithTimer for BackgroundRunner {
         let cached = core.range_manager.ranges().len();
         let loading = core.range_manager.pending_ranges_loading_data.len();
         let evictions = core.range_manager.get_and_reset_range_evictions();
+        let applied_seqno = core.max_applied_seqno();
         drop(core);
+        if applied_seqno > 0 {
+            if let Some(rocks_engine) = &self.rocks_engine {
+                let gap = rocks_engine
+                    .get_latest_sequence_number()
+                    .saturating_sub(applied_seqno);
+                RANGE_CACHE_SEQNO_GAP.set(gap as i64);
+            }
+        }
         RANGE_CACHE_COUNT
             .with_label_values(&["pending_range"])
             .set(pending as i64);
@@ -1095,34 +1985,211 @@ impl RunnableWithTimer for BackgroundRunner {
         RANGE_CACHE_COUNT
             .with_label_values(&["range_evictions"])
             .set(evictions as i64);
+        RANGE_CACHE_COUNT
+            .with_label_values(&["queued_load"])
+            .set(self.core.load_scheduler.pending_count() as i64);
+        RANGE_CACHE_COUNT
+            .with_label_values(&["inflight_load"])
+            .set(self.core.load_scheduler.in_flight_count() as i64);
+        // The loaded-bytes-per-second rate is derived from this counter.
+        RANGE_LOAD_BYTES.inc_by(self.core.load_scheduler.take_loaded_bytes());
+
+        let pressure = self.core.memory_controller.write_pressure();
+        RANGE_CACHE_WRITE_PRESSURE.set(match pressure {
+            WritePressure::None => 0,
+            WritePressure::Soft => 1,
+            WritePressure::Hard => 2,
+        });
+        // Resume the loads paused by write backpressure once the usage is
+        // back below the watermarks. The reclaim pause is owned by the
+        // delete worker and lifted there.
+        if pressure == WritePressure::None {
+            self.core.load_scheduler.resume();
+        }
+        // Kick the load worker when no pause is in effect and there is a
+        // backlog to pick up, e.g. right after a pause has been lifted.
+        if !self.core.load_scheduler.is_paused() && self.core.load_scheduler.pending_count() > 0 {
+            let core = self.core.clone();
+            self.range_load_remote
+                .spawn(core.load_pending_ranges(self.delete_range_scheduler.clone()));
+        }
+
+        // The ttl is read from the config on each tick so that it can be
+        // changed online.
+        if let Some(ttl) = self.core.config.value().range_ttl {
+            self.core
+                .evict_expired_ranges(ttl.0, &self.delete_range_scheduler);
+        }
+
+        // The comparison only walks the tracked pairs under a mutex, so it
+        // simply runs on every tick while the verification is enabled.
+        if self.core.config.value().checksum_verification {
+            self.core
+                .compare_range_checksums(&self.delete_range_scheduler);
+        }
     }
 
     fn get_interval(&self) -> Duration {
-        Duration::from_secs(1)
+        BACKGROUND_TICK_INTERVAL
+    }
+}
+
+// Bounds of the deletion pacer. The budget never drops below
+// `MIN_DELETE_BATCH_ENTRIES` and the inter-batch sleep never exceeds
+// `MAX_DELETE_BATCH_SLEEP`, so even a fully saturated controller keeps
+// deleting at least one minimum batch per maximum sleep interval and
+// evictions cannot be starved by a persistently slow reader.
+const MIN_DELETE_BATCH_ENTRIES: usize = 256;
+const MAX_DELETE_BATCH_ENTRIES: usize = 65536;
+const DELETE_BATCH_SLEEP_STEP: Duration = Duration::from_millis(5);
+const MAX_DELETE_BATCH_SLEEP: Duration = Duration::from_millis(50);
+
+// The seek latency quantile the pacer compares against the target.
+const DELETE_PACER_QUANTILE: f64 = 0.99;
+
+/// An AIMD controller pacing background range deletions against foreground
+/// read latency: deletion batches and foreground reads contend on the same
+/// skiplist nodes and the epoch collector, so instead of a fixed rate limit
+/// the batch size and inter-batch sleep follow the observed read latency.
+/// While the recent p99 seek latency (see
+/// `statistics::recent_seek_latency_quantile`) exceeds the configured
+/// target, each batch halves the entry budget and lengthens the sleep by a
+/// fixed step; once it is back under the target, the budget recovers
+/// additively and the sleep halves. The state is exported through the
+/// `delete_batch_budget`, `delete_batch_sleep` and `delete_pacer_saturated`
+/// gauges.
+struct DeletionPacer {
+    budget: usize,
+    sleep: Duration,
+}
+
+impl Default for DeletionPacer {
+    fn default() -> Self {
+        DeletionPacer {
+            budget: MAX_DELETE_BATCH_ENTRIES,
+            sleep: Duration::ZERO,
+        }
+    }
+}
+
+impl DeletionPacer {
+    /// Re-evaluates the controller against `target`, called before every
+    /// deletion batch. An empty reservoir (no recent reads to protect)
+    /// counts as under the target, so an idle store recovers the budget.
+    fn adjust(&mut self, target: Duration) {
+        let over_target =
+            recent_seek_latency_quantile(DELETE_PACER_QUANTILE).map_or(false, |q| q > target);
+        if over_target {
+            // Multiplicative decrease of the budget, additive increase of
+            // the sleep.
+            self.budget = usize::max(self.budget / 2, MIN_DELETE_BATCH_ENTRIES);
+            self.sleep =
+                Duration::min(self.sleep + DELETE_BATCH_SLEEP_STEP, MAX_DELETE_BATCH_SLEEP);
+        } else {
+            // Additive increase of the budget, multiplicative decrease of
+            // the sleep.
+            self.budget = usize::min(
+                self.budget + MIN_DELETE_BATCH_ENTRIES,
+                MAX_DELETE_BATCH_ENTRIES,
+            );
+            self.sleep /= 2;
+        }
+        RANGE_CACHE_DELETE_BATCH_BUDGET.set(self.budget as i64);
+        RANGE_CACHE_DELETE_BATCH_SLEEP.set(self.sleep.as_millis() as i64);
+        let saturated = over_target
+            && self.budget == MIN_DELETE_BATCH_ENTRIES
+            && self.sleep == MAX_DELETE_BATCH_SLEEP;
+        RANGE_CACHE_DELETE_PACER_SATURATED.set(saturated as i64);
     }
 }
 
 pub struct DeleteRangeRunner {
     engine: Arc<RwLock<RangeCacheMemoryEngineCore>>,
+    memory_controller: Arc<MemoryController>,
+    load_scheduler: Arc<LoadScheduler>,
+    // Whether this runner has paused the load scheduler because the epoch
+    // reclaim backlog exceeded `reclaim_lag_threshold`.
+    paused_loads_for_reclaim: bool,
     // It is possible that when `DeleteRangeRunner` begins to delete a range, the range is being
     // written by apply threads. In that case, we have to delay the delete range task to avoid race
     // condition between them. Periodically, these delayed ranges will be checked to see if it is
     // ready to be deleted.
     delay_ranges: Vec<CacheRange>,
+    placement: ThreadPlacement,
+    config: Arc<VersionTrack<RangeCacheEngineConfig>>,
+    pacer: DeletionPacer,
 }
 
 impl DeleteRangeRunner {
-    fn new(engine: Arc<RwLock<RangeCacheMemoryEngineCore>>) -> Self {
+    fn new(
+        engine: Arc<RwLock<RangeCacheMemoryEngineCore>>,
+        memory_controller: Arc<MemoryController>,
+        load_scheduler: Arc<LoadScheduler>,
+        placement: ThreadPlacement,
+        config: Arc<VersionTrack<RangeCacheEngineConfig>>,
+    ) -> Self {
         Self {
             engine,
+            memory_controller,
+            load_scheduler,
+            paused_loads_for_reclaim: false,
             delay_ranges: vec![],
+            placement,
+            config,
+            pacer: DeletionPacer::default(),
         }
     }
 
     fn delete_ranges(&mut self, ranges: &[CacheRange]) {
-        let skiplist_engine = self.engine.read().engine();
+        self.placement.ensure_current_thread();
+        let (skiplist_engine, provenance) = {
+            let core = self.engine.read();
+            (core.engine(), core.provenance())
+        };
+        let latency_target = self.config.value().delete_read_latency_target.map(|d| d.0);
+        let mut removed_bytes = 0;
         for r in ranges {
-            skiplist_engine.delete_range(r);
+            match latency_target {
+                None => removed_bytes += skiplist_engine.delete_range(r),
+                Some(target) => loop {
+                    self.pacer.adjust(target);
+                    let (bytes, _, done) =
+                        skiplist_engine.delete_range_with_budget(r, self.pacer.budget);
+                    removed_bytes += bytes;
+                    if done {
+                        break;
+                    }
+                    // Cancellations are refused once the deletion of the range
+                    // has started, so the flag stays clear past the first
+                    // batch; re-checking it between the paced batches turns a
+                    // violated invariant into an aborted deletion instead of
+                    // a race against a restored range.
+                    if self.engine.read().range_manager().eviction_canceled(r) {
+                        warn!(
+                            "abort range deletion due to canceled eviction";
+                            "range" => ?r,
+                        );
+                        break;
+                    }
+                    if !self.pacer.sleep.is_zero() {
+                        std::thread::sleep(self.pacer.sleep);
+                    }
+                },
+            }
+            provenance.remove_range(r);
+        }
+        if removed_bytes > 0 {
+            // The removed entries are only destroyed once the epoch collector
+            // catches up, which can lag behind arbitrarily under heavy read
+            // concurrency. Track the backlog and clear it with a deferred
+            // callback: the callback cannot run before the destructions
+            // deferred above it, so when it fires the bytes are back in the
+            // allocator.
+            self.memory_controller.add_pending_reclaim(removed_bytes);
+            let controller = self.memory_controller.clone();
+            let guard = &epoch::pin();
+            guard.defer(move || controller.sub_pending_reclaim(removed_bytes));
+            guard.flush();
         }
         self.engine
             .write()
@@ -1134,6 +2201,46 @@ impl DeleteRangeRunner {
         #[cfg(test)]
         flush_epoch();
     }
+
+    /// Drives crossbeam epoch advancement so that memory logically freed by
+    /// range deletions is actually returned to the allocator, and pauses new
+    /// range loads while the backlog exceeds `reclaim_lag_threshold`. Called
+    /// on every timer tick.
+    fn advance_epoch_for_reclaim(&mut self) {
+        // A pin/flush cycle nudges the global epoch forward and hands any
+        // locally buffered deferred functions to the collector.
+        {
+            let guard = &epoch::pin();
+            guard.flush();
+        }
+        if self.memory_controller.reclaim_lag_exceeded() {
+            if !self.paused_loads_for_reclaim {
+                warn!(
+                    "in memory engine epoch reclaim lags behind, pause range loads";
+                    "pending_reclaim" => self.memory_controller.pending_reclaim(),
+                );
+                self.load_scheduler.pause_for_reclaim();
+                self.paused_loads_for_reclaim = true;
+            }
+            // A local epoch only tries to advance the global one every 128
+            // pins, so repin aggressively. This cannot force progress past a
+            // stuck reader guard, but it makes the collector catch up as soon
+            // as the guards are dropped.
+            for _ in 0..128 {
+                let _ = &epoch::pin();
+            }
+            let guard = &epoch::pin();
+            guard.flush();
+        } else if self.paused_loads_for_reclaim {
+            info!(
+                "in memory engine epoch reclaim caught up, resume range loads";
+                "pending_reclaim" => self.memory_controller.pending_reclaim(),
+            );
+            self.load_scheduler.resume_from_reclaim();
+            self.paused_loads_for_reclaim = false;
+        }
+        RANGE_CACHE_PENDING_RECLAIM.set(self.memory_controller.pending_reclaim() as i64);
+    }
 }
 
 impl Runnable for DeleteRangeRunner {
@@ -1142,17 +2249,23 @@ impl Runnable for DeleteRangeRunner {
         match task {
             BackgroundTask::DeleteRange(ranges) => {
                 let (mut ranges_to_delay, ranges_to_delete) = {
-                    let core = self.engine.read();
+                    let mut core = self.engine.write();
+                    let range_manager = core.mut_range_manager();
                     let mut ranges_to_delay = vec![];
                     let mut ranges_to_delete = vec![];
                     for r in ranges {
                         // If the range is overlapped with ranges in `ranges_being_written`, the
                         // range has to be delayed to delete. See comment on `delay_ranges`.
-                        if core
-                            .range_manager
-                            .is_overlapped_with_ranges_being_written(&r)
-                        {
+                        if range_manager.is_overlapped_with_ranges_being_written(&r) {
                             ranges_to_delay.push(r);
+                        } else if !range_manager.start_deletion(&r) {
+                            // The eviction was canceled while the task was queued (or
+                            // delayed); the range is cached again and nothing of it may
+                            // be deleted.
+                            info!(
+                                "skip range deletion due to canceled eviction";
+                                "range" => ?r,
+                            );
                         } else {
                             ranges_to_delete.push(r);
                         }
@@ -1164,6 +2277,12 @@ impl Runnable for DeleteRangeRunner {
                     self.delete_ranges(&ranges_to_delete);
                 }
             }
+            // This worker runs tasks in order, so acknowledging the sentinel
+            // means everything scheduled before it has been executed.
+            #[cfg(test)]
+            BackgroundTask::TestCompletion(tx) => {
+                let _ = tx.send(());
+            }
             _ => unreachable!(),
         }
     }
@@ -1171,6 +2290,7 @@ impl Runnable for DeleteRangeRunner {
 
 impl RunnableWithTimer for DeleteRangeRunner {
     fn on_timeout(&mut self) {
+        self.advance_epoch_for_reclaim();
         if self.delay_ranges.is_empty() {
             return;
         }
@@ -1184,11 +2304,16 @@ impl RunnableWithTimer for DeleteRangeRunner {
 }
 
 #[derive(Default)]
-struct FilterMetrics {
+pub(crate) struct FilterMetrics {
     total: usize,
     versions: usize,
     delete_versions: usize,
-    filtered: usize,
+    pub(crate) filtered: usize,
+    // The estimated number of bytes freed by the removals, accounted as the
+    // key and value lengths of the removed entries (the value length may be
+    // unknown for entries removed by key, which then only contribute their
+    // key length).
+    pub(crate) filtered_bytes: usize,
     unique_key: usize,
     mvcc_rollback_and_locks: usize,
 }
@@ -1199,11 +2324,12 @@ impl FilterMetrics {
         self.versions += other.versions;
         self.delete_versions += other.delete_versions;
         self.filtered += other.filtered;
+        self.filtered_bytes += other.filtered_bytes;
         self.unique_key += other.unique_key;
         self.mvcc_rollback_and_locks += other.mvcc_rollback_and_locks;
     }
 
-    fn flush(&self) {
+    pub(crate) fn flush(&self) {
         GC_FILTERED_STATIC.total.inc_by(self.total as u64);
         GC_FILTERED_STATIC
             .below_safe_point_total
@@ -1212,6 +2338,80 @@ impl FilterMetrics {
         GC_FILTERED_STATIC
             .below_safe_point_unique
             .inc_by(self.unique_key as u64);
+        RANGE_GC_FREED_BYTES.inc_by(self.filtered_bytes as u64);
+    }
+}
+
+/// Filters write cf versions during range load with the same decisions as
+/// [`Filter`], so that versions the next gc round would remove anyway are not
+/// copied into the skiplist in the first place: versions at or below the safe
+/// point that are superseded by a newer committed version, delete marks, and
+/// rollback/lock records. It relies on the disk iterator yielding the
+/// versions of one user key from the newest to the oldest.
+struct LoadFilter {
+    safe_point: u64,
+    mvcc_key_prefix: Vec<u8>,
+    remove_older: bool,
+    // Default cf keys of the skipped put versions. The write cf is loaded
+    // first, so when the default cf is loaded these values can be skipped as
+    // well instead of staying orphaned until the range is evicted.
+    skipped_default_keys: HashSet<Vec<u8>>,
+}
+
+impl LoadFilter {
+    fn new(safe_point: u64) -> Self {
+        Self {
+            safe_point,
+            mvcc_key_prefix: vec![],
+            remove_older: false,
+            skipped_default_keys: HashSet::default(),
+        }
+    }
+
+    /// Returns true if the write cf `key` (a data key with mvcc timestamp but
+    /// without sequence number) does not need to be cached.
+    fn filter_write(&mut self, key: &[u8], value: &[u8]) -> Result<bool, String> {
+        let (mvcc_key_prefix, commit_ts) = split_ts(key)?;
+        if commit_ts > self.safe_point {
+            return Ok(false);
+        }
+
+        if self.mvcc_key_prefix != mvcc_key_prefix {
+            self.mvcc_key_prefix.clear();
+            self.mvcc_key_prefix.extend_from_slice(mvcc_key_prefix);
+            self.remove_older = false;
+        }
+
+        if self.remove_older {
+            let write = parse_write(value)?;
+            if write.write_type == WriteType::Put && write.short_value.is_none() {
+                self.skipped_default_keys.insert(
+                    Key::from_encoded_slice(mvcc_key_prefix)
+                        .append_ts(write.start_ts)
+                        .into_encoded(),
+                );
+            }
+            return Ok(true);
+        }
+
+        let write = parse_write(value)?;
+        match write.write_type {
+            WriteType::Rollback | WriteType::Lock => Ok(true),
+            WriteType::Put => {
+                self.remove_older = true;
+                Ok(false)
+            }
+            WriteType::Delete => {
+                self.remove_older = true;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns true if the default cf `key` belongs to a put version that has
+    /// been skipped by `filter_write`.
+    fn filter_default(&self, key: &[u8]) -> bool {
+        self.skipped_default_keys.contains(key)
     }
 }
 
@@ -1232,6 +2432,11 @@ struct Filter {
     metrics: FilterMetrics,
 
     last_user_key: Vec<u8>,
+
+    // Whether the stored values are framed by the value compression, in
+    // which case the mvcc meta must be parsed from the decoded bytes. See
+    // the `compression` module.
+    values_framed: bool,
 }
 
 impl Drop for Filter {
@@ -1255,6 +2460,7 @@ impl Filter {
         oldest_seqno: u64,
         default_cf_handle: SkiplistHandle,
         write_cf_handle: SkiplistHandle,
+        values_framed: bool,
     ) -> Self {
         Self {
             safe_point,
@@ -1267,6 +2473,7 @@ impl Filter {
             remove_older: false,
             metrics: FilterMetrics::default(),
             last_user_key: vec![],
+            values_framed,
         }
     }
 
@@ -1298,6 +2505,7 @@ impl Filter {
         if v_type == ValueType::Deletion {
             if let Some(cache_skiplist_delete_key) = self.cached_skiplist_delete_key.take() {
                 self.metrics.filtered += 1;
+                self.metrics.filtered_bytes += cache_skiplist_delete_key.len();
                 // Reaching here in two cases:
                 // 1. There are two ValueType::Deletion in the same user key.
                 // 2. Two consecutive ValueType::Deletion of different user keys.
@@ -1316,15 +2524,16 @@ impl Filter {
             let guard = &epoch::pin();
             if cache_skiplist_delete_user_key == user_key {
                 self.metrics.filtered += 1;
+                self.metrics.filtered_bytes += key.len() + value.len();
                 self.write_cf_handle
                     .remove(&InternalBytes::from_bytes(key.clone()), guard);
                 return Ok(());
             } else {
+                let cached_key = self.cached_skiplist_delete_key.take().unwrap();
                 self.metrics.filtered += 1;
-                self.write_cf_handle.remove(
-                    &InternalBytes::from_vec(self.cached_skiplist_delete_key.take().unwrap()),
-                    guard,
-                )
+                self.metrics.filtered_bytes += cached_key.len();
+                self.write_cf_handle
+                    .remove(&InternalBytes::from_vec(cached_key), guard)
             }
         }
 
@@ -1335,6 +2544,7 @@ impl Filter {
             self.last_user_key = user_key.to_vec();
         } else {
             self.metrics.filtered += 1;
+            self.metrics.filtered_bytes += key.len() + value.len();
             self.write_cf_handle
                 .remove(&InternalBytes::from_bytes(key.clone()), guard);
             return Ok(());
@@ -1348,13 +2558,20 @@ impl Filter {
             self.remove_older = false;
             if let Some(cached_delete_key) = self.cached_mvcc_delete_key.take() {
                 self.metrics.filtered += 1;
+                self.metrics.filtered_bytes += cached_delete_key.len();
                 self.write_cf_handle
                     .remove(&InternalBytes::from_vec(cached_delete_key), guard);
             }
         }
 
         let mut filtered = self.remove_older;
-        let write = parse_write(value)?;
+        let decoded;
+        let write = if self.values_framed {
+            decoded = decode_value(value.clone()).map_err(|e| e.to_string())?;
+            parse_write(&decoded)?
+        } else {
+            parse_write(value)?
+        };
         if !self.remove_older {
             match write.write_type {
                 WriteType::Rollback | WriteType::Lock => {
@@ -1378,6 +2595,7 @@ impl Filter {
             return Ok(());
         }
         self.metrics.filtered += 1;
+        self.metrics.filtered_bytes += key.len() + value.len();
         self.write_cf_handle
             .remove(&InternalBytes::from_bytes(key.clone()), guard);
         self.handle_filtered_write(write, guard)?;
@@ -1401,6 +2619,8 @@ impl Filter {
             let mut iter = self.default_cf_handle.iterator();
             iter.seek(&default_key, guard);
             while iter.valid() && iter.key().same_user_key_with(&default_key) {
+                self.metrics.filtered_bytes +=
+                    iter.key().as_bytes().len() + iter.value().as_bytes().len();
                 self.default_cf_handle.remove(iter.key(), guard);
                 iter.next(guard);
             }
@@ -1422,8 +2642,8 @@ pub mod tests {
     use crossbeam::epoch;
     use engine_rocks::util::new_engine;
     use engine_traits::{
-        CacheRange, IterOptions, Iterable, Iterator, RangeCacheEngine, SyncMutable, CF_DEFAULT,
-        CF_LOCK, CF_WRITE, DATA_CFS,
+        CacheRange, CachedCfs, FailedReason, IterOptions, Iterable, Iterator, Peekable,
+        RangeCacheEngine, SyncMutable, CF_DEFAULT, CF_LOCK, CF_WRITE, DATA_CFS,
     };
     use futures::future::ready;
     use keys::{data_key, DATA_MAX_KEY, DATA_MIN_KEY};
@@ -1446,6 +2666,8 @@ pub mod tests {
             encoding_for_filter, InternalBytes, ValueType,
         },
         memory_controller::MemoryController,
+        range_manager::{CancelError, PinFailedReason},
+        range_stats::tests::{new_region, RegionInfoSimulator},
         region_label::{
             region_label_meta_client,
             tests::{add_region_label_rule, new_region_label_rule, new_test_server_and_client},
@@ -1642,7 +2864,7 @@ pub mod tests {
         assert_eq!(7, element_count(&default));
         assert_eq!(8, element_count(&write));
 
-        let mut filter = Filter::new(50, 100, default.clone(), write.clone());
+        let mut filter = Filter::new(50, 100, default.clone(), write.clone(), false);
         let mut count = 0;
         let mut iter = write.iterator();
         let guard = &epoch::pin();
@@ -1763,7 +2985,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller.clone(),
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
         worker.core.gc_range(&range, 40, 100);
 
@@ -1837,7 +3059,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller.clone(),
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
 
         // gc should not hanlde keys with larger seqno than oldest seqno
@@ -1999,7 +3221,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller.clone(),
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
         let filter = worker.core.gc_range(&range1, 100, 100);
         assert_eq!(2, filter.filtered);
@@ -2014,7 +3236,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller.clone(),
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
         worker.core.gc_range(&range2, 100, 100);
         assert_eq!(2, filter.filtered);
@@ -2062,7 +3284,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller.clone(),
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
 
         let filter = worker.core.gc_range(&range, 20, 200);
@@ -2160,7 +3382,7 @@ pub mod tests {
             engine.core.clone(),
             memory_controller,
             None,
-            engine.expected_region_size(),
+            engine.config().clone(),
         );
         let s1 = engine.snapshot(range.clone(), 10, u64::MAX);
         let s2 = engine.snapshot(range.clone(), 11, u64::MAX);
@@ -2191,36 +3413,116 @@ pub mod tests {
         assert_eq!(3, element_count(&write));
     }
 
+    // `RangeCacheMemoryEngine::gc_range` runs a gc pass directly on the
+    // engine, reports the stats of the pass, and respects live snapshots just
+    // like the background gc rounds.
     #[test]
-    fn test_background_worker_load() {
-        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
-            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
-        ));
-        let path = Builder::new().prefix("test_load").tempdir().unwrap();
-        let path_str = path.path().to_str().unwrap();
-        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
-        engine.set_disk_engine(rocks_engine.clone());
-
-        for i in 10..20 {
-            let key = construct_key(i, 1);
-            let key = data_key(&key);
-            let value = construct_value(i, i);
-            rocks_engine
-                .put_cf(CF_DEFAULT, &key, value.as_bytes())
-                .unwrap();
-            rocks_engine
-                .put_cf(CF_WRITE, &key, value.as_bytes())
-                .unwrap();
-        }
+    fn test_gc_range_api() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
 
-        let k = format!("zk{:08}", 15).into_bytes();
-        let r1 = CacheRange::new(DATA_MIN_KEY.to_vec(), k.clone());
-        let r2 = CacheRange::new(k, DATA_MAX_KEY.to_vec());
-        {
-            let mut core = engine.core.write();
-            core.mut_range_manager().pending_ranges.push(r1.clone());
-            core.mut_range_manager().pending_ranges.push(r2.clone());
-        }
+        put_data(
+            b"key1",
+            b"value1",
+            10,
+            11,
+            10,
+            false,
+            &default,
+            &write,
+            memory_controller.clone(),
+        );
+        put_data(
+            b"key1",
+            b"value2",
+            12,
+            13,
+            12,
+            false,
+            &default,
+            &write,
+            memory_controller.clone(),
+        );
+        put_data(
+            b"key1",
+            b"value3",
+            14,
+            15,
+            14,
+            false,
+            &default,
+            &write,
+            memory_controller.clone(),
+        );
+        assert_eq!(3, element_count(&default));
+        assert_eq!(3, element_count(&write));
+
+        // The open snapshot caps the safe point at 11, so the first version
+        // is still the newest visible one and nothing is removed.
+        let snap = engine.snapshot(range.clone(), 11, u64::MAX);
+        let stats = engine.gc_range(&range, 100);
+        assert_eq!(0, stats.versions_removed);
+        assert_eq!(0, stats.bytes_freed);
+        assert_eq!(3, element_count(&default));
+        assert_eq!(3, element_count(&write));
+        let guard = &epoch::pin();
+        let key = encoding_for_filter(Key::from_raw(b"key1").as_encoded(), TimeStamp::new(11));
+        assert!(key_exist(&write, &key, guard));
+
+        // With the snapshot dropped, a second pass removes the two stale
+        // versions.
+        drop(snap);
+        let stats = engine.gc_range(&range, 100);
+        assert_eq!(2, stats.versions_removed);
+        assert!(stats.bytes_freed > 0);
+        assert_eq!(1, element_count(&default));
+        assert_eq!(1, element_count(&write));
+        assert!(!key_exist(&write, &key, guard));
+        let key = encoding_for_filter(Key::from_raw(b"key1").as_encoded(), TimeStamp::new(15));
+        assert!(key_exist(&write, &key, guard));
+    }
+
+    #[test]
+    fn test_background_worker_load() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
+        let path = Builder::new().prefix("test_load").tempdir().unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+
+        for i in 10..20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            rocks_engine
+                .put_cf(CF_DEFAULT, &key, value.as_bytes())
+                .unwrap();
+            rocks_engine
+                .put_cf(CF_WRITE, &key, value.as_bytes())
+                .unwrap();
+        }
+
+        let k = format!("zk{:08}", 15).into_bytes();
+        let r1 = CacheRange::new(DATA_MIN_KEY.to_vec(), k.clone());
+        let r2 = CacheRange::new(k, DATA_MAX_KEY.to_vec());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().pending_ranges.push(r1.clone());
+            core.mut_range_manager().pending_ranges.push(r2.clone());
+        }
         engine.prepare_for_apply(1, &r1);
         engine.prepare_for_apply(1, &r2);
 
@@ -2272,52 +3574,94 @@ pub mod tests {
         assert!(!key_exist(&default, &key20, guard));
     }
 
+    // A load restricted to the write cf populates only the write cf skiplist
+    // and the cached range remembers the subset so reads of the other cfs can
+    // be routed to the disk engine.
     #[test]
-    fn test_ranges_for_gc() {
-        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
-            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
-        )));
-        let memory_controller = engine.memory_controller();
-        let r1 = CacheRange::new(b"a".to_vec(), b"b".to_vec());
-        let r2 = CacheRange::new(b"b".to_vec(), b"c".to_vec());
-        engine.new_range(r1);
-        engine.new_range(r2);
+    fn test_load_with_cf_subset() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
+        let path = Builder::new()
+            .prefix("test_load_cf_subset")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
 
-        let (mut runner, _) = BackgroundRunner::new(
-            engine.core.clone(),
-            memory_controller,
-            None,
-            engine.expected_region_size(),
-        );
-        let ranges = runner.core.ranges_for_gc().unwrap();
-        assert_eq!(2, ranges.len());
+        for i in 10..20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            for cf in DATA_CFS {
+                rocks_engine.put_cf(cf, &key, value.as_bytes()).unwrap();
+            }
+        }
 
-        // until the previous gc finished, node ranges will be returned
-        assert!(runner.core.ranges_for_gc().is_none());
-        runner.core.on_gc_finished(ranges);
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine
+            .load_range_with_cfs(range.clone(), CachedCfs::from_cfs(&[CF_WRITE]))
+            .unwrap();
+        engine.prepare_for_apply(1, &range);
 
-        let ranges = runner.core.ranges_for_gc().unwrap();
-        assert_eq!(2, ranges.len());
+        // wait for background load
+        std::thread::sleep(Duration::from_secs(1));
+
+        let _ = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+
+        let (write, default, lock) = {
+            let core = engine.core().read();
+            let skiplist_engine = core.engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+                skiplist_engine.cf_handle(CF_LOCK),
+            )
+        };
+        let guard = &epoch::pin();
+        for i in 10..20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            let key = encode_seek_key(&key, u64::MAX);
+            assert_eq!(
+                get_value(&write, &key, guard).unwrap().as_slice(),
+                value.as_bytes()
+            );
+            assert!(!key_exist(&default, &key, guard));
+            assert!(!key_exist(&lock, &key, guard));
+        }
+
+        // The cached range reports the subset, and the requested-cf entry has
+        // been garbage collected with the load done.
+        {
+            let core = engine.core().read();
+            assert_eq!(
+                core.range_manager().cached_cfs(&range).unwrap(),
+                CachedCfs::from_cfs(&[CF_WRITE])
+            );
+            assert_eq!(
+                core.range_manager().requested_cfs(&range),
+                CachedCfs::all()
+            );
+        }
     }
 
-    // Test creating and loading cache hint using a region label rule:
-    // 1. Insert some data into rocks engine, which is set as disk engine for the
-    //    memory engine.
-    // 2. Use test pd client server to create a label rule for portion of the data.
-    // 3. Wait until data is loaded.
-    // 4. Verify that only the labeled key range has been loaded.
+    // An eviction racing with a snapshot load of the same range, replayed
+    // step by step with the deterministic mode: the load task is parked, the
+    // eviction only marks it as canceled, and running the parked load drops
+    // the range without leaking anything into the skiplists.
     #[test]
-    fn test_load_from_pd_hint_service() {
+    fn test_evict_range_during_load() {
         let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
             Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
         ));
-        let path = Builder::new()
-            .prefix("test_load_from_pd_hint_service")
-            .tempdir()
-            .unwrap();
+        let path = Builder::new().prefix("test_load").tempdir().unwrap();
         let path_str = path.path().to_str().unwrap();
         let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
         engine.set_disk_engine(rocks_engine.clone());
+        engine.enable_deterministic_background_tasks();
 
         for i in 10..20 {
             let key = construct_key(i, 1);
@@ -2326,43 +3670,145 @@ pub mod tests {
             rocks_engine
                 .put_cf(CF_DEFAULT, &key, value.as_bytes())
                 .unwrap();
+        }
+
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .pending_ranges
+            .push(range.clone());
+        engine.prepare_for_apply(1, &range);
+        // Pretend the write batch that triggered the load has been consumed
+        // without entries, otherwise the deletion below would be delayed.
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .clear_ranges_in_being_written(1, false);
+
+        // The load task is parked, so the range stays mid-load.
+        {
+            let core = engine.core.read();
+            let loading = &core.range_manager().pending_ranges_loading_data;
+            assert_eq!(loading.len(), 1);
+            assert!(!loading[0].2);
+        }
+
+        // Evicting the range while its load has not run yet only marks the
+        // load as canceled; nothing is deleted before the tasks run.
+        engine.evict_range(&range);
+        {
+            let core = engine.core.read();
+            let loading = &core.range_manager().pending_ranges_loading_data;
+            assert_eq!(loading.len(), 1);
+            assert!(loading[0].2);
+        }
+
+        // Step the parked load: it observes the cancellation and hands the
+        // range over for deletion, which `run_one_background_task` drains
+        // before returning.
+        assert!(engine.run_one_background_task());
+        {
+            let core = engine.core.read();
+            assert!(core.range_manager().pending_ranges_loading_data.is_empty());
+            assert!(core.range_manager().ranges_being_deleted.is_empty());
+            assert!(!core.range_manager().contains_range(&range));
+        }
+        // Nothing from the canceled load leaked into the skiplists.
+        let skiplist_engine = engine.core.read().engine().clone();
+        for cf in DATA_CFS {
+            assert!(skiplist_engine.cf_handle(cf).is_empty());
+        }
+        assert!(!engine.run_one_background_task());
+    }
+
+    // A load whose disk snapshot exceeds the configured age limit swaps it
+    // for a fresh one mid-scan and resumes after the last loaded key, so
+    // writes that landed on disk after the load started still make it into
+    // the cache.
+    #[test]
+    fn test_load_refreshes_aged_snapshot() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        // Any age exceeds the limit, so every age check refreshes.
+        config.load_snapshot_max_age = Some(ReadableDuration(Duration::ZERO));
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(config)),
+        ));
+        let path = Builder::new()
+            .prefix("test_load_refresh")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+        // The disk engine reaches the background runner through a task; give
+        // the worker a moment to process it before parking the queue.
+        std::thread::sleep(Duration::from_millis(500));
+        engine.enable_deterministic_background_tasks();
+
+        // Enough entries that the scan passes several age checkpoints.
+        for i in 0..1500 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
             rocks_engine
                 .put_cf(CF_WRITE, &key, value.as_bytes())
                 .unwrap();
+            rocks_engine
+                .put_cf(CF_DEFAULT, &key, value.as_bytes())
+                .unwrap();
         }
 
-        let (mut pd_server, pd_client) = new_test_server_and_client(ReadableDuration::millis(100));
-        let cluster_id = pd_client.get_cluster_id().unwrap();
-        let pd_client = Arc::new(pd_client);
-        engine.start_hint_service(PdRangeHintService::from(pd_client.clone()));
-        let meta_client = region_label_meta_client(pd_client.clone());
-        let label_rule = new_region_label_rule(
-            "cache/0",
-            &hex::encode(format!("k{:08}", 10).into_bytes()),
-            &hex::encode(format!("k{:08}", 15).into_bytes()),
-        );
-        add_region_label_rule(meta_client, cluster_id, &label_rule);
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .pending_ranges
+            .push(range.clone());
+        // Takes the disk snapshot and parks the load task.
+        engine.prepare_for_apply(1, &range);
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .clear_ranges_in_being_written(1, false);
 
-        // Wait for the watch to fire.
-        std::thread::sleep(Duration::from_millis(200));
-        let r1 = CacheRange::try_from(&label_rule.data[0]).unwrap();
-        engine.prepare_for_apply(1, &r1);
+        // These land on disk after the snapshot above was taken, so only a
+        // refreshed snapshot can see them.
+        for i in 1500..1520 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            rocks_engine
+                .put_cf(CF_WRITE, &key, value.as_bytes())
+                .unwrap();
+            rocks_engine
+                .put_cf(CF_DEFAULT, &key, value.as_bytes())
+                .unwrap();
+        }
 
-        // Wait for the range to be loaded.
-        std::thread::sleep(Duration::from_secs(1));
-        let _ = engine.snapshot(r1, u64::MAX, u64::MAX).unwrap();
+        let refreshes_before = RANGE_LOAD_SNAPSHOT_REFRESHES.get();
+        let chunked_before = RANGE_LOAD_CHUNKED_LOADS.get();
+        assert!(engine.run_one_background_task());
+        assert!(RANGE_LOAD_SNAPSHOT_REFRESHES.get() > refreshes_before);
+        assert_eq!(RANGE_LOAD_CHUNKED_LOADS.get(), chunked_before + 1);
 
+        let _ = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
         let (write, default) = {
-            let core = engine.core().write();
+            let core = engine.core().read();
             let skiplist_engine = core.engine();
             (
                 skiplist_engine.cf_handle(CF_WRITE),
                 skiplist_engine.cf_handle(CF_DEFAULT),
             )
         };
-
         let guard = &epoch::pin();
-        for i in 10..15 {
+        // The cache matches a direct disk scan at the end of the load,
+        // including the late writes.
+        for i in 0..1520 {
             let key = construct_key(i, 1);
             let key = data_key(&key);
             let value = construct_value(i, i);
@@ -2376,77 +3822,204 @@ pub mod tests {
                 value.as_bytes()
             );
         }
-        for i in 15..=20 {
-            let key = construct_key(i, 1);
-            let key = data_key(&key);
-            let key = encode_seek_key(&key, u64::MAX);
-            assert!(!key_exist(&write, &key, guard));
-            assert!(!key_exist(&default, &key, guard));
-        }
-
-        pd_server.stop();
     }
 
     #[test]
-    fn test_snapshot_load_reaching_limit() {
+    fn test_load_with_unavailable_placement() {
+        // A placement the OS will refuse (core 1023 is almost certainly
+        // offline, and interleaving needs a multi-node machine) must not
+        // break loading; the workers fall back to the default placement.
         let mut config = RangeCacheEngineConfig::config_for_test();
-        config.soft_limit_threshold = Some(ReadableSize(1000));
-        config.hard_limit_threshold = Some(ReadableSize(1500));
-        let config = Arc::new(VersionTrack::new(config));
-        let mut engine =
-            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config));
+        config.background_worker_cpu_set = "1023".to_string();
+        config.numa_interleave_allocations = true;
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(config)),
+        ));
         let path = Builder::new()
-            .prefix("test_snapshot_load_reaching_limit")
+            .prefix("test_load_with_unavailable_placement")
             .tempdir()
             .unwrap();
         let path_str = path.path().to_str().unwrap();
         let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
         engine.set_disk_engine(rocks_engine.clone());
-        let mem_controller = engine.memory_controller();
 
-        let range1 = CacheRange::new(construct_user_key(1), construct_user_key(3));
-        // Memory for one put is 17(key) + 3(val) + 8(Seqno) + 16(Memory controller in
-        // key and val) + 96(Node overhead) = 140
-        let key = construct_key(1, 10);
+        let key = construct_key(1, 1);
+        let key = data_key(&key);
         rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
         rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
 
-        let key = construct_key(2, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-        // After loading range1, the memory usage should be 140*6=840
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine.load_range(range.clone()).unwrap();
+        engine.prepare_for_apply(1, &range);
 
-        let range2 = CacheRange::new(construct_user_key(3), construct_user_key(5));
-        let key = construct_key(3, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        let mut count = 0;
+        while count < 20 {
+            {
+                let core = engine.core.read();
+                let range_manager = core.range_manager();
+                if range_manager.pending_ranges.is_empty()
+                    && range_manager.pending_ranges_loading_data.is_empty()
+                {
+                    break;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(100));
+            count += 1;
+        }
 
-        let key = construct_key(4, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-        // 840*2 > hard limit 1500, so the load will fail and the loaded keys should be
-        // removed
+        let _ = engine.snapshot(range, u64::MAX, u64::MAX).unwrap();
+        let default = {
+            let core = engine.core().write();
+            core.engine().cf_handle(CF_DEFAULT)
+        };
+        let guard = &epoch::pin();
+        let key = encode_seek_key(&key, u64::MAX);
+        assert_eq!(get_value(&default, &key, guard).unwrap().as_slice(), b"val");
+    }
 
-        let range3 = CacheRange::new(construct_user_key(5), construct_user_key(6));
-        let key = construct_key(5, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-        let key = construct_key(6, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+    #[test]
+    fn test_load_filter() {
+        let write_key = |k: &[u8], commit_ts: u64| {
+            Key::from_raw(k)
+                .append_ts(TimeStamp::new(commit_ts))
+                .into_encoded()
+        };
+        let write_value = |write_type, start_ts: u64, short: Option<&[u8]>| {
+            Write::new(write_type, TimeStamp::new(start_ts), short.map(|v| v.to_vec()))
+                .as_ref()
+                .to_bytes()
+        };
 
-        for r in [&range1, &range2, &range3] {
-            engine.load_range(r.clone()).unwrap();
-            engine.prepare_for_apply(1, r);
-        }
+        let mut filter = LoadFilter::new(100);
+        // Versions above the safe point are always cached.
+        assert!(
+            !filter
+                .filter_write(
+                    &write_key(b"k1", 110),
+                    &write_value(WriteType::Put, 105, None)
+                )
+                .unwrap()
+        );
+        // The newest version at or below the safe point is still visible.
+        assert!(
+            !filter
+                .filter_write(
+                    &write_key(b"k1", 90),
+                    &write_value(WriteType::Put, 85, None)
+                )
+                .unwrap()
+        );
+        // Older versions of the same user key are superseded.
+        assert!(
+            filter
+                .filter_write(
+                    &write_key(b"k1", 80),
+                    &write_value(WriteType::Put, 75, None)
+                )
+                .unwrap()
+        );
+        assert!(
+            filter
+                .filter_write(
+                    &write_key(b"k1", 70),
+                    &write_value(WriteType::Rollback, 65, None)
+                )
+                .unwrap()
+        );
+        // A delete mark below the safe point and everything under it are
+        // skipped.
+        assert!(
+            filter
+                .filter_write(
+                    &write_key(b"k2", 90),
+                    &write_value(WriteType::Delete, 85, None)
+                )
+                .unwrap()
+        );
+        assert!(
+            filter
+                .filter_write(
+                    &write_key(b"k2", 80),
+                    &write_value(WriteType::Put, 75, Some(b"v"))
+                )
+                .unwrap()
+        );
+        // Rollback and lock records below the safe point are skipped even if
+        // they are the newest version.
+        assert!(
+            filter
+                .filter_write(
+                    &write_key(b"k3", 50),
+                    &write_value(WriteType::Rollback, 45, None)
+                )
+                .unwrap()
+        );
 
-        // ensure all ranges are finshed
+        // Only the value of the skipped put without short value is skipped in
+        // the default cf.
+        assert!(filter.filter_default(&write_key(b"k1", 75)));
+        assert!(!filter.filter_default(&write_key(b"k1", 85)));
+        assert!(!filter.filter_default(&write_key(b"k2", 75)));
+    }
+
+    #[test]
+    fn test_gc_aware_load() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
+        let path = Builder::new()
+            .prefix("test_gc_aware_load")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+
+        let put_write = |key: &[u8], start_ts: u64, commit_ts: u64, write_type, short: Option<&[u8]>| {
+            let raw_key = Key::from_raw(key).append_ts(TimeStamp::new(commit_ts));
+            let write = Write::new(write_type, TimeStamp::new(start_ts), short.map(|v| v.to_vec()));
+            rocks_engine
+                .put_cf(
+                    CF_WRITE,
+                    &data_key(raw_key.as_encoded()),
+                    &write.as_ref().to_bytes(),
+                )
+                .unwrap();
+        };
+        let put_default = |key: &[u8], start_ts: u64, value: &[u8]| {
+            let raw_key = Key::from_raw(key).append_ts(TimeStamp::new(start_ts));
+            rocks_engine
+                .put_cf(CF_DEFAULT, &data_key(raw_key.as_encoded()), value)
+                .unwrap();
+        };
+
+        // k1 has a version above the safe point, a version below it that is
+        // still visible, and superseded versions that gc would drop.
+        put_write(b"k1", 105, 110, WriteType::Put, None);
+        put_default(b"k1", 105, b"v3");
+        put_write(b"k1", 85, 90, WriteType::Put, None);
+        put_default(b"k1", 85, b"v2");
+        put_write(b"k1", 75, 80, WriteType::Put, None);
+        put_default(b"k1", 75, b"v1");
+        put_write(b"k1", 65, 70, WriteType::Rollback, None);
+        // k2 is deleted below the safe point, so nothing of it survives.
+        put_write(b"k2", 85, 90, WriteType::Delete, None);
+        put_write(b"k2", 75, 80, WriteType::Put, Some(b"v1"));
+        // k3 is the newest version of its key and stays visible.
+        put_write(b"k3", 90, 95, WriteType::Put, Some(b"v1"));
+
+        // Record the gc safe point before the load. The gc round itself is a
+        // no-op as no range is cached yet.
+        engine
+            .bg_worker_manager()
+            .schedule_task(BackgroundTask::Gc(GcTask { safe_point: 100 }))
+            .unwrap();
+
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine.load_range(range.clone()).unwrap();
+        engine.prepare_for_apply(1, &range);
+
+        // wait for the range to be loaded
         {
             let mut count = 0;
             while count < 20 {
@@ -2464,91 +4037,77 @@ pub mod tests {
             }
         }
 
-        let verify = |range: CacheRange, exist, expect_count| {
-            if exist {
-                let snap = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
-                let mut count = 0;
-                for cf in DATA_CFS {
-                    let mut iter = IterOptions::default();
-                    iter.set_lower_bound(&range.start, 0);
-                    iter.set_upper_bound(&range.end, 0);
-                    let mut iter = snap.iterator_opt(cf, iter).unwrap();
-                    let _ = iter.seek_to_first();
-                    while iter.valid().unwrap() {
-                        let _ = iter.next();
-                        count += 1;
-                    }
-                }
-                assert_eq!(count, expect_count);
-            } else {
-                engine.snapshot(range, 10, 10).unwrap_err();
-            }
+        let snap = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        let (write, default) = {
+            let core = engine.core().write();
+            let skiplist_engine = core.engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
         };
-        verify(range1, true, 6);
-        verify(range2, false, 0);
-        verify(range3, false, 3);
-        assert_eq!(mem_controller.mem_usage(), 1540);
+        assert_eq!(element_count(&write), 3);
+        assert_eq!(element_count(&default), 2);
+
+        let guard = &epoch::pin();
+        let seek_write = |key: &[u8], commit_ts: u64| {
+            let raw_key = Key::from_raw(key).append_ts(TimeStamp::new(commit_ts));
+            encode_seek_key(&data_key(raw_key.as_encoded()), u64::MAX)
+        };
+        // Reads at valid timestamps see the same versions as on disk.
+        assert!(key_exist(&write, &seek_write(b"k1", 110), guard));
+        assert!(key_exist(&write, &seek_write(b"k1", 90), guard));
+        assert!(key_exist(&write, &seek_write(b"k3", 95), guard));
+        assert!(key_exist(&default, &seek_write(b"k1", 105), guard));
+        assert!(key_exist(&default, &seek_write(b"k1", 85), guard));
+        // The superseded versions and delete marks are not cached.
+        assert!(!key_exist(&write, &seek_write(b"k1", 80), guard));
+        assert!(!key_exist(&write, &seek_write(b"k1", 70), guard));
+        assert!(!key_exist(&write, &seek_write(b"k2", 90), guard));
+        assert!(!key_exist(&write, &seek_write(b"k2", 80), guard));
+        assert!(!key_exist(&default, &seek_write(b"k1", 75), guard));
+        drop(snap);
     }
 
     #[test]
-    fn test_soft_hard_limit_change() {
-        let mut config = RangeCacheEngineConfig::config_for_test();
-        config.soft_limit_threshold = Some(ReadableSize(1000));
-        config.hard_limit_threshold = Some(ReadableSize(1500));
-        let config = Arc::new(VersionTrack::new(config));
-        let mut engine =
-            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config.clone()));
+    fn test_load_starts_at_disk_gc_safe_point() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
         let path = Builder::new()
-            .prefix("test_snapshot_load_reaching_limit")
+            .prefix("test_load_starts_at_disk_gc_safe_point")
             .tempdir()
             .unwrap();
         let path_str = path.path().to_str().unwrap();
         let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
         engine.set_disk_engine(rocks_engine.clone());
-        let mem_controller = engine.memory_controller();
-
-        let range1 = CacheRange::new(construct_user_key(1), construct_user_key(3));
-        // Memory for one put is 17(key) + 3(val) + 8(Seqno) + 16(Memory controller in
-        // key and val) + 96(Node overhead) = 140
-        let key = construct_key(1, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-
-        let key = construct_key(2, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-        // After loading range1, the memory usage should be 140*6=840
-        engine.load_range(range1.clone()).unwrap();
-        engine.prepare_for_apply(1, &range1);
 
-        let range2 = CacheRange::new(construct_user_key(3), construct_user_key(5));
-        let key = construct_key(3, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        let raw_key = Key::from_raw(b"k1").append_ts(TimeStamp::new(110));
+        let write = Write::new(WriteType::Put, TimeStamp::new(105), None);
+        rocks_engine
+            .put_cf(
+                CF_WRITE,
+                &data_key(raw_key.as_encoded()),
+                &write.as_ref().to_bytes(),
+            )
+            .unwrap();
+        let raw_key = Key::from_raw(b"k1").append_ts(TimeStamp::new(105));
+        rocks_engine
+            .put_cf(CF_DEFAULT, &data_key(raw_key.as_encoded()), b"v")
+            .unwrap();
 
-        let key = construct_key(4, 10);
-        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
-        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
-        // 840*2 > hard limit 1500, so the load will fail and the loaded keys should be
-        // removed. However now we change the memory quota to 2000, so the range2 can be
-        // cached.
-        let mut config_manager = RangeCacheConfigManager(config.clone());
-        let mut config_change = ConfigChange::new();
-        config_change.insert(
-            String::from("hard_limit_threshold"),
-            ConfigValue::Size(2000),
-        );
-        config_manager.dispatch(config_change).unwrap();
-        assert_eq!(config.value().hard_limit_threshold(), 2000);
+        // Record the disk gc safe point before the load. The gc round itself
+        // is a no-op as no range is cached yet.
+        engine
+            .bg_worker_manager()
+            .schedule_task(BackgroundTask::Gc(GcTask { safe_point: 100 }))
+            .unwrap();
 
-        engine.load_range(range2.clone()).unwrap();
-        engine.prepare_for_apply(1, &range2);
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine.load_range(range.clone()).unwrap();
+        engine.prepare_for_apply(1, &range);
 
-        // ensure all ranges are finshed
+        // wait for the range to be loaded
         {
             let mut count = 0;
             while count < 20 {
@@ -2566,66 +4125,1328 @@ pub mod tests {
             }
         }
 
-        let verify = |range: CacheRange, exist, expect_count| {
-            if exist {
-                let snap = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
-                let mut count = 0;
-                for cf in DATA_CFS {
-                    let mut iter = IterOptions::default();
-                    iter.set_lower_bound(&range.start, 0);
-                    iter.set_upper_bound(&range.end, 0);
-                    let mut iter = snap.iterator_opt(cf, iter).unwrap();
-                    let _ = iter.seek_to_first();
-                    while iter.valid().unwrap() {
-                        let _ = iter.next();
-                        count += 1;
-                    }
-                }
-                assert_eq!(count, expect_count);
-            } else {
-                engine.snapshot(range, 10, 10).unwrap_err();
-            }
-        };
-        verify(range1, true, 6);
-        verify(range2, true, 6);
-        assert_eq!(mem_controller.mem_usage(), 1680);
+        // The loaded range starts out at the disk gc safe point, so reads at
+        // or below it must keep falling back to the disk engine.
+        assert_eq!(
+            engine.core.read().range_manager().safe_point(&range),
+            Some(100)
+        );
+        assert_eq!(
+            engine.snapshot(range.clone(), 100, u64::MAX).unwrap_err(),
+            FailedReason::TooOldRead
+        );
+        assert_eq!(
+            engine.snapshot(range.clone(), 90, u64::MAX).unwrap_err(),
+            FailedReason::TooOldRead
+        );
+        let snap = engine.snapshot(range.clone(), 150, u64::MAX).unwrap();
+        drop(snap);
     }
 
     #[test]
-    fn test_gc_use_pd_tso() {
-        struct MockPdClient {
-            tx: Mutex<Sender<()>>,
-        }
-        impl PdClient for MockPdClient {
-            fn get_tso(&self) -> pd_client::PdFuture<txn_types::TimeStamp> {
-                self.tx.lock().unwrap().send(()).unwrap();
-                Box::pin(ready(Ok(TimeStamp::compose(TimeStamp::physical_now(), 0))))
-            }
-        }
+    fn test_ranges_for_gc() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let memory_controller = engine.memory_controller();
+        let r1 = CacheRange::new(b"a".to_vec(), b"b".to_vec());
+        let r2 = CacheRange::new(b"b".to_vec(), b"c".to_vec());
+        engine.new_range(r1);
+        engine.new_range(r2);
 
-        let start_time = TimeStamp::compose(TimeStamp::physical_now(), 0);
-        let (tx, pd_client_rx) = channel();
-        let pd_client = Arc::new(MockPdClient { tx: Mutex::new(tx) });
-        let gc_interval = Duration::from_millis(100);
-        let load_evict_interval = Duration::from_millis(200);
-        let (scheduler, mut rx) = dummy_scheduler();
-        let (handle, stop) =
-            BgWorkManager::start_tick(scheduler, pd_client, gc_interval, load_evict_interval);
+        let (mut runner, _) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller,
+            None,
+            engine.config().clone(),
+        );
+        let ranges = runner.core.ranges_for_gc().unwrap();
+        assert_eq!(2, ranges.len());
 
-        let Some(BackgroundTask::Gc(GcTask { safe_point })) =
-            rx.recv_timeout(10 * gc_interval).unwrap()
-        else {
-            panic!("must be a GcTask");
-        };
-        let safe_point = TimeStamp::from(safe_point);
-        // Make sure it is a reasonable timestamp.
-        assert!(safe_point > start_time, "{safe_point}, {start_time}");
-        let now = TimeStamp::compose(TimeStamp::physical_now(), 0);
-        assert!(safe_point < now, "{safe_point}, {now}");
-        // Must get ts from PD.
-        pd_client_rx.try_recv().unwrap();
+        // until the previous gc finished, node ranges will be returned
+        assert!(runner.core.ranges_for_gc().is_none());
+        runner.core.on_gc_finished(ranges);
 
-        stop.send(true).unwrap();
-        handle.join().unwrap();
+        let ranges = runner.core.ranges_for_gc().unwrap();
+        assert_eq!(2, ranges.len());
+    }
+
+    #[test]
+    fn test_range_ttl_eviction() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.range_ttl = Some(ReadableDuration::secs(10));
+        let config = Arc::new(VersionTrack::new(config));
+        let engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config.clone()));
+        let memory_controller = engine.memory_controller();
+        let r1 = CacheRange::new(b"a".to_vec(), b"b".to_vec());
+        let r2 = CacheRange::new(b"b".to_vec(), b"c".to_vec());
+        engine.new_range(r1.clone());
+        engine.new_range(r2.clone());
+
+        let (runner, delete_range_scheduler) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller,
+            None,
+            engine.config().clone(),
+        );
+        let ttl = config.value().range_ttl.unwrap().0;
+        // Pretend the range has been idle for longer than the ttl.
+        let age = |range: &CacheRange| {
+            let core = engine.core.read();
+            core.range_manager().ranges()[range].set_last_access(now_unix_millis() - 20_000);
+        };
+
+        // Both ranges have been accessed recently, nothing is evicted.
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        assert_eq!(engine.core.read().range_manager().ranges().len(), 2);
+
+        // Reading from r1 refreshes its last access time, so only the idle r2
+        // is evicted.
+        age(&r1);
+        age(&r2);
+        drop(engine.snapshot(r1.clone(), 10, u64::MAX).unwrap());
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        {
+            let core = engine.core.read();
+            assert!(core.range_manager().ranges().contains_key(&r1));
+            assert!(!core.range_manager().ranges().contains_key(&r2));
+            let record = core.range_manager().recent_evictions().back().unwrap();
+            assert_eq!(record.reason, "ttl-expired");
+        }
+
+        // An expired range with an ongoing snapshot is still serving reads and
+        // must be skipped.
+        let snap = engine.snapshot(r1.clone(), 10, u64::MAX).unwrap();
+        age(&r1);
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        assert!(
+            engine
+                .core
+                .read()
+                .range_manager()
+                .ranges()
+                .contains_key(&r1)
+        );
+
+        // Once the snapshot is dropped, the next round evicts it.
+        drop(snap);
+        age(&r1);
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        assert!(engine.core.read().range_manager().ranges().is_empty());
+    }
+
+    #[test]
+    fn test_checksum_mismatch_eviction() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.checksum_verification = true;
+        config.evict_on_checksum_mismatch = true;
+        let config = Arc::new(VersionTrack::new(config));
+        let engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config.clone()));
+        let memory_controller = engine.memory_controller();
+        let r1 = CacheRange::new(b"a".to_vec(), b"b".to_vec());
+        let r2 = CacheRange::new(b"b".to_vec(), b"c".to_vec());
+        engine.new_range(r1.clone());
+        engine.new_range(r2.clone());
+
+        let (runner, delete_range_scheduler) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller,
+            None,
+            engine.config().clone(),
+        );
+
+        // Matching checksums keep both ranges cached.
+        runner
+            .core
+            .compare_range_checksums(&delete_range_scheduler);
+        assert_eq!(engine.core.read().range_manager().ranges().len(), 2);
+
+        // An entry the cache missed diverges r2, which is detected and
+        // evicted by the next comparison, while r1 stays.
+        engine.core.read().checksums().update_disk(&r2, 1);
+        runner
+            .core
+            .compare_range_checksums(&delete_range_scheduler);
+        {
+            let core = engine.core.read();
+            assert!(core.range_manager().ranges().contains_key(&r1));
+            assert!(!core.range_manager().ranges().contains_key(&r2));
+            let record = core.range_manager().recent_evictions().back().unwrap();
+            assert_eq!(record.reason, "checksum-mismatch");
+        }
+    }
+
+    #[test]
+    fn test_pinned_range_skips_eviction() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.soft_limit_threshold = Some(ReadableSize(500));
+        config.hard_limit_threshold = Some(ReadableSize(2000));
+        config.expected_region_size = Some(ReadableSize(200));
+        let config = Arc::new(VersionTrack::new(config));
+        let engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config.clone()));
+        let memory_controller = engine.memory_controller();
+
+        let region_1 = new_region(1, b"k01", b"k02", 0);
+        let region_2 = new_region(2, b"k03", b"k04", 0);
+        let r1 = CacheRange::from_region(&region_1);
+        let r2 = CacheRange::from_region(&region_2);
+        engine.new_range(r1.clone());
+        engine.new_range(r2.clone());
+
+        // The less active region would be the first eviction candidate, so
+        // pinning it must redirect the memory pressure eviction to the more
+        // active one.
+        engine.pin_range(&r2, true).unwrap();
+        // The pinned budget is half of the soft limit with one expected
+        // region size per pin, so a second pin is refused.
+        assert_eq!(
+            engine.pin_range(&r1, true),
+            Err(PinFailedReason::CapacityExceeded)
+        );
+
+        let sim: Arc<dyn RegionInfoProvider> = Arc::new(RegionInfoSimulator::new(vec![
+            (region_1, 100),
+            (region_2, 50),
+        ]));
+        let (runner, delete_range_scheduler) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller.clone(),
+            Some(sim),
+            engine.config().clone(),
+        );
+
+        // Exceed the soft limit so the memory pressure eviction runs; only
+        // the unpinned range is evicted.
+        memory_controller.acquire(600);
+        runner
+            .core
+            .evict_on_soft_limit_reached(&delete_range_scheduler);
+        {
+            let core = engine.core.read();
+            assert!(!core.range_manager().ranges().contains_key(&r1));
+            assert!(core.range_manager().ranges().contains_key(&r2));
+            assert!(core.range_manager().is_pinned(&r2));
+        }
+
+        // The ttl eviction skips the pinned range as well.
+        let ttl = Duration::from_secs(10);
+        engine.core.read().range_manager().ranges()[&r2]
+            .set_last_access(now_unix_millis() - 20_000);
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        assert!(engine.core.read().range_manager().ranges().contains_key(&r2));
+
+        // Unpinning makes the range evictable again.
+        engine.pin_range(&r2, false).unwrap();
+        runner
+            .core
+            .evict_expired_ranges(ttl, &delete_range_scheduler);
+        assert!(engine.core.read().range_manager().ranges().is_empty());
+    }
+
+    // Test creating and loading cache hint using a region label rule:
+    // 1. Insert some data into rocks engine, which is set as disk engine for the
+    //    memory engine.
+    // 2. Use test pd client server to create a label rule for portion of the data.
+    // 3. Wait until data is loaded.
+    // 4. Verify that only the labeled key range has been loaded.
+    #[test]
+    fn test_skiplist_structural_stats() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range);
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+
+        let calls_before = thread_comparator_calls();
+        let count = 600u64;
+        for i in 0..count {
+            let key = format!("key-{:05}", i);
+            put_data(
+                key.as_bytes(),
+                b"value",
+                10,
+                11,
+                i + 10,
+                false,
+                &default,
+                &write,
+                memory_controller.clone(),
+            );
+        }
+        assert_eq!(count, element_count(&default));
+        assert_eq!(count, element_count(&write));
+        // The comparator counter covers the insert path.
+        assert!(thread_comparator_calls() > calls_before);
+
+        let depth_write = RANGE_CACHE_SKIPLIST_SEARCH_DEPTH.with_label_values(&[CF_WRITE]);
+        let samples_before = depth_write.get_sample_count();
+        let sum_before = depth_write.get_sample_sum();
+
+        let (worker, _) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller.clone(),
+            None,
+            engine.config().clone(),
+        );
+        worker.core.sample_skiplist_stats();
+
+        for (cf, nodes) in [(CF_DEFAULT, count), (CF_LOCK, 0), (CF_WRITE, count)] {
+            assert_eq!(
+                nodes as i64,
+                RANGE_CACHE_SKIPLIST_NODE_COUNT
+                    .with_label_values(&[cf])
+                    .get()
+            );
+        }
+
+        // The pass is bounded by the sampling budget even though the skiplist
+        // holds more nodes, and every sampled seek compares at least once per
+        // visited node, so the mean depth cannot fall below one.
+        let samples = depth_write.get_sample_count() - samples_before;
+        assert_eq!(SKIPLIST_STATS_SAMPLE_SIZE as u64, samples);
+        assert!(depth_write.get_sample_sum() - sum_before >= samples as f64);
+    }
+
+    #[test]
+    fn test_load_from_pd_hint_service() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
+        let path = Builder::new()
+            .prefix("test_load_from_pd_hint_service")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+
+        for i in 10..20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            rocks_engine
+                .put_cf(CF_DEFAULT, &key, value.as_bytes())
+                .unwrap();
+            rocks_engine
+                .put_cf(CF_WRITE, &key, value.as_bytes())
+                .unwrap();
+        }
+
+        let (mut pd_server, pd_client) = new_test_server_and_client(ReadableDuration::millis(100));
+        let cluster_id = pd_client.get_cluster_id().unwrap();
+        let pd_client = Arc::new(pd_client);
+        engine.start_hint_service(PdRangeHintService::from(pd_client.clone()));
+        let meta_client = region_label_meta_client(pd_client.clone());
+        let label_rule = new_region_label_rule(
+            "cache/0",
+            &hex::encode(format!("k{:08}", 10).into_bytes()),
+            &hex::encode(format!("k{:08}", 15).into_bytes()),
+        );
+        add_region_label_rule(meta_client, cluster_id, &label_rule);
+
+        // Wait for the watch to fire.
+        std::thread::sleep(Duration::from_millis(200));
+        let r1 = CacheRange::try_from(&label_rule.data[0]).unwrap();
+        engine.prepare_for_apply(1, &r1);
+
+        // Wait for the range to be loaded.
+        std::thread::sleep(Duration::from_secs(1));
+        let _ = engine.snapshot(r1, u64::MAX, u64::MAX).unwrap();
+
+        let (write, default) = {
+            let core = engine.core().write();
+            let skiplist_engine = core.engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+
+        let guard = &epoch::pin();
+        for i in 10..15 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            let key = encode_seek_key(&key, u64::MAX);
+            assert_eq!(
+                get_value(&write, &key, guard).unwrap().as_slice(),
+                value.as_bytes()
+            );
+            assert_eq!(
+                get_value(&default, &key, guard).unwrap().as_slice(),
+                value.as_bytes()
+            );
+        }
+        for i in 15..=20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let key = encode_seek_key(&key, u64::MAX);
+            assert!(!key_exist(&write, &key, guard));
+            assert!(!key_exist(&default, &key, guard));
+        }
+
+        pd_server.stop();
+    }
+
+    #[test]
+    fn test_snapshot_load_reaching_limit() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.soft_limit_threshold = Some(ReadableSize(1000));
+        config.hard_limit_threshold = Some(ReadableSize(1500));
+        let config = Arc::new(VersionTrack::new(config));
+        let mut engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config));
+        let path = Builder::new()
+            .prefix("test_snapshot_load_reaching_limit")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+        let mem_controller = engine.memory_controller();
+
+        let range1 = CacheRange::new(construct_user_key(1), construct_user_key(3));
+        // Memory for one put is 17(key) + 3(val) + 8(Seqno) + 16(Memory controller in
+        // key and val) + 96(Node overhead) = 140
+        let key = construct_key(1, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+
+        let key = construct_key(2, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        // After loading range1, the memory usage should be 140*6=840
+
+        let range2 = CacheRange::new(construct_user_key(3), construct_user_key(5));
+        let key = construct_key(3, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+
+        let key = construct_key(4, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        // 840*2 > hard limit 1500, so the load will fail and the loaded keys should be
+        // removed
+
+        let range3 = CacheRange::new(construct_user_key(5), construct_user_key(6));
+        let key = construct_key(5, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        let key = construct_key(6, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+
+        for r in [&range1, &range2, &range3] {
+            engine.load_range(r.clone()).unwrap();
+            engine.prepare_for_apply(1, r);
+        }
+
+        // ensure all ranges are finshed
+        {
+            let mut count = 0;
+            while count < 20 {
+                {
+                    let core = engine.core.read();
+                    let range_manager = core.range_manager();
+                    if range_manager.pending_ranges.is_empty()
+                        && range_manager.pending_ranges_loading_data.is_empty()
+                    {
+                        break;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(100));
+                count += 1;
+            }
+        }
+
+        let verify = |range: CacheRange, exist, expect_count| {
+            if exist {
+                let snap = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+                let mut count = 0;
+                for cf in DATA_CFS {
+                    let mut iter = IterOptions::default();
+                    iter.set_lower_bound(&range.start, 0);
+                    iter.set_upper_bound(&range.end, 0);
+                    let mut iter = snap.iterator_opt(cf, iter).unwrap();
+                    let _ = iter.seek_to_first();
+                    while iter.valid().unwrap() {
+                        let _ = iter.next();
+                        count += 1;
+                    }
+                }
+                assert_eq!(count, expect_count);
+            } else {
+                engine.snapshot(range, 10, 10).unwrap_err();
+            }
+        };
+        verify(range1, true, 6);
+        verify(range2, false, 0);
+        verify(range3, false, 3);
+        assert_eq!(mem_controller.mem_usage(), 1540);
+    }
+
+    #[test]
+    fn test_soft_hard_limit_change() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.soft_limit_threshold = Some(ReadableSize(1000));
+        config.hard_limit_threshold = Some(ReadableSize(1500));
+        // Keep the tiny capacity valid for the online update below, which
+        // re-validates the whole config.
+        config.expected_region_size = Some(ReadableSize(500));
+        let config = Arc::new(VersionTrack::new(config));
+        let mut engine =
+            RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(config.clone()));
+        let path = Builder::new()
+            .prefix("test_snapshot_load_reaching_limit")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+        let mem_controller = engine.memory_controller();
+
+        let range1 = CacheRange::new(construct_user_key(1), construct_user_key(3));
+        // Memory for one put is 17(key) + 3(val) + 8(Seqno) + 16(Memory controller in
+        // key and val) + 96(Node overhead) = 140
+        let key = construct_key(1, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+
+        let key = construct_key(2, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        // After loading range1, the memory usage should be 140*6=840
+        engine.load_range(range1.clone()).unwrap();
+        engine.prepare_for_apply(1, &range1);
+
+        let range2 = CacheRange::new(construct_user_key(3), construct_user_key(5));
+        let key = construct_key(3, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+
+        let key = construct_key(4, 10);
+        rocks_engine.put_cf(CF_DEFAULT, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_LOCK, &key, b"val").unwrap();
+        rocks_engine.put_cf(CF_WRITE, &key, b"val").unwrap();
+        // 840*2 > hard limit 1500, so the load will fail and the loaded keys should be
+        // removed. However now we change the memory quota to 2000, so the range2 can be
+        // cached.
+        let mut config_manager = RangeCacheConfigManager(config.clone());
+        let mut config_change = ConfigChange::new();
+        config_change.insert(
+            String::from("hard_limit_threshold"),
+            ConfigValue::Size(2000),
+        );
+        config_manager.dispatch(config_change).unwrap();
+        assert_eq!(config.value().hard_limit_threshold(), 2000);
+
+        engine.load_range(range2.clone()).unwrap();
+        engine.prepare_for_apply(1, &range2);
+
+        // ensure all ranges are finshed
+        {
+            let mut count = 0;
+            while count < 20 {
+                {
+                    let core = engine.core.read();
+                    let range_manager = core.range_manager();
+                    if range_manager.pending_ranges.is_empty()
+                        && range_manager.pending_ranges_loading_data.is_empty()
+                    {
+                        break;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(100));
+                count += 1;
+            }
+        }
+
+        let verify = |range: CacheRange, exist, expect_count| {
+            if exist {
+                let snap = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+                let mut count = 0;
+                for cf in DATA_CFS {
+                    let mut iter = IterOptions::default();
+                    iter.set_lower_bound(&range.start, 0);
+                    iter.set_upper_bound(&range.end, 0);
+                    let mut iter = snap.iterator_opt(cf, iter).unwrap();
+                    let _ = iter.seek_to_first();
+                    while iter.valid().unwrap() {
+                        let _ = iter.next();
+                        count += 1;
+                    }
+                }
+                assert_eq!(count, expect_count);
+            } else {
+                engine.snapshot(range, 10, 10).unwrap_err();
+            }
+        };
+        verify(range1, true, 6);
+        verify(range2, true, 6);
+        assert_eq!(mem_controller.mem_usage(), 1680);
+    }
+
+    #[test]
+    fn test_gc_use_pd_tso() {
+        struct MockPdClient {
+            tx: Mutex<Sender<()>>,
+        }
+        impl PdClient for MockPdClient {
+            fn get_tso(&self) -> pd_client::PdFuture<txn_types::TimeStamp> {
+                self.tx.lock().unwrap().send(()).unwrap();
+                Box::pin(ready(Ok(TimeStamp::compose(TimeStamp::physical_now(), 0))))
+            }
+        }
+
+        let start_time = TimeStamp::compose(TimeStamp::physical_now(), 0);
+        let (tx, pd_client_rx) = channel();
+        let pd_client = Arc::new(MockPdClient { tx: Mutex::new(tx) });
+        let gc_interval = Duration::from_millis(100);
+        let load_evict_interval = Duration::from_millis(200);
+        let (scheduler, mut rx) = dummy_scheduler();
+        let (handle, stop) =
+            BgWorkManager::start_tick(scheduler, pd_client, gc_interval, load_evict_interval);
+
+        let Some(BackgroundTask::Gc(GcTask { safe_point })) =
+            rx.recv_timeout(10 * gc_interval).unwrap()
+        else {
+            panic!("must be a GcTask");
+        };
+        let safe_point = TimeStamp::from(safe_point);
+        // Make sure it is a reasonable timestamp.
+        assert!(safe_point > start_time, "{safe_point}, {start_time}");
+        let now = TimeStamp::compose(TimeStamp::physical_now(), 0);
+        assert!(safe_point < now, "{safe_point}, {now}");
+        // Must get ts from PD.
+        pd_client_rx.try_recv().unwrap();
+
+        stop.send(true).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_event_history() {
+        let mut engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(
+            Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test())),
+        ));
+        let path = Builder::new()
+            .prefix("test_event_history")
+            .tempdir()
+            .unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let rocks_engine = new_engine(path_str, DATA_CFS).unwrap();
+        engine.set_disk_engine(rocks_engine.clone());
+
+        for i in 10..20 {
+            let key = construct_key(i, 1);
+            let key = data_key(&key);
+            let value = construct_value(i, i);
+            rocks_engine
+                .put_cf(CF_DEFAULT, &key, value.as_bytes())
+                .unwrap();
+            rocks_engine
+                .put_cf(CF_WRITE, &key, value.as_bytes())
+                .unwrap();
+        }
+
+        let wait_for_load = |engine: &RangeCacheMemoryEngine| {
+            let mut count = 0;
+            while count < 20 {
+                {
+                    let core = engine.core.read();
+                    let range_manager = core.range_manager();
+                    if range_manager.pending_ranges.is_empty()
+                        && range_manager.pending_ranges_loading_data.is_empty()
+                    {
+                        return;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(100));
+                count += 1;
+            }
+            panic!("load did not finish in time");
+        };
+        let wait_for_delete = |engine: &RangeCacheMemoryEngine| {
+            let mut count = 0;
+            while count < 20 {
+                if engine
+                    .core
+                    .read()
+                    .range_manager()
+                    .ranges_being_deleted
+                    .is_empty()
+                {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+                count += 1;
+            }
+            panic!("delete did not finish in time");
+        };
+
+        let range = CacheRange::new(DATA_MIN_KEY.to_vec(), DATA_MAX_KEY.to_vec());
+        engine.load_range(range.clone()).unwrap();
+        engine.prepare_for_apply(1, &range);
+        wait_for_load(&engine);
+
+        engine.evict_range(&range);
+        wait_for_delete(&engine);
+
+        // Reads of the evicted range fall back to the disk engine and the
+        // failures are aggregated into one event.
+        engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap_err();
+        engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap_err();
+
+        engine.load_range(range.clone()).unwrap();
+        engine.prepare_for_apply(1, &range);
+        wait_for_load(&engine);
+
+        // Loading a cached range again is refused and recorded.
+        engine.load_range(range.clone()).unwrap_err();
+
+        let events = engine.range_events(&range);
+        let kinds: Vec<_> = events.iter().map(|e| e.kind.clone()).collect();
+        assert!(
+            matches!(
+                kinds.as_slice(),
+                [
+                    RangeEventKind::LoadScheduled,
+                    RangeEventKind::LoadStarted,
+                    RangeEventKind::LoadFinished { bytes },
+                    RangeEventKind::EvictRequested { reason: "evict-api" },
+                    RangeEventKind::EvictCompleted,
+                    RangeEventKind::SnapshotFailed {
+                        reason: FailedReason::NotCached,
+                        ..
+                    },
+                    RangeEventKind::LoadScheduled,
+                    RangeEventKind::LoadStarted,
+                    RangeEventKind::LoadFinished { .. },
+                    RangeEventKind::LoadRejected {
+                        reason: "overlapped"
+                    },
+                ] if *bytes > 0
+            ),
+            "{:?}",
+            kinds
+        );
+        // The two failures land in one aggregate unless the test happens to
+        // straddle a minute boundary.
+        let failed: u64 = kinds
+            .iter()
+            .map(|k| match k {
+                RangeEventKind::SnapshotFailed { count, .. } => *count,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(failed, 2);
+        assert_eq!(engine.dump_events().len(), kinds.len());
+    }
+
+    #[test]
+    fn test_stuck_eviction_watchdog() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.force_complete_stuck_evictions = true;
+        let threshold_millis = config.stuck_eviction_threshold.0.as_millis() as u64;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+        put_data(
+            b"key1",
+            b"value1",
+            10,
+            15,
+            10,
+            false,
+            &default,
+            &write,
+            memory_controller.clone(),
+        );
+
+        // Leak a snapshot across the eviction: the range gets parked in the
+        // historical set and its deletion is blocked for as long as the
+        // snapshot lives.
+        let snap = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        engine.evict_range(&range);
+        assert_eq!(engine.core.read().range_manager().historical_ranges_count(), 1);
+
+        let (worker, delete_range_scheduler) = BackgroundRunner::new(
+            engine.core.clone(),
+            memory_controller.clone(),
+            None,
+            engine.config().clone(),
+        );
+
+        // Not past the threshold yet: nothing to report.
+        worker.core.check_stuck_evictions(&delete_range_scheduler);
+        assert_eq!(RANGE_CACHE_STUCK_EVICTIONS.get(), 0);
+
+        // Past the threshold but within the grace period: the leak is
+        // reported but the snapshot keeps working.
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .set_historical_evicted_at(&range, now_unix_millis() - threshold_millis - 1000);
+        let stuck = engine
+            .core
+            .read()
+            .range_manager()
+            .stuck_historical_ranges(now_unix_millis(), threshold_millis);
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].2, vec![(u64::MAX, 1)]);
+        worker.core.check_stuck_evictions(&delete_range_scheduler);
+        assert_eq!(RANGE_CACHE_STUCK_EVICTIONS.get(), 1);
+        assert_eq!(engine.core.read().range_manager().historical_ranges_count(), 1);
+        let mut iter_opts = IterOptions::default();
+        iter_opts.set_lower_bound(&range.start, 0);
+        iter_opts.set_upper_bound(&range.end, 0);
+        let mut iter = snap.iterator_opt(CF_WRITE, iter_opts.clone()).unwrap();
+        assert!(iter.seek_to_first().unwrap());
+
+        // Past twice the threshold with force enabled: the eviction is
+        // completed, the memory reclaimed, and the leaked snapshot poisoned.
+        engine
+            .core
+            .write()
+            .mut_range_manager()
+            .set_historical_evicted_at(&range, now_unix_millis() - threshold_millis * 2 - 1000);
+        worker.core.check_stuck_evictions(&delete_range_scheduler);
+        assert_eq!(engine.core.read().range_manager().historical_ranges_count(), 0);
+        let mut count = 0;
+        while !engine
+            .core
+            .read()
+            .range_manager()
+            .ranges_being_deleted
+            .is_empty()
+        {
+            std::thread::sleep(Duration::from_millis(100));
+            count += 1;
+            assert!(count < 20, "delete did not finish in time");
+        }
+        {
+            let guard = &epoch::pin();
+            let mut iter = write.iterator();
+            iter.seek_to_first(guard);
+            assert!(!iter.valid());
+        }
+
+        // Reads through the leaked snapshot fail instead of touching the
+        // deleted data.
+        snap.get_value(b"key1").unwrap_err();
+        snap.iterator_opt(CF_WRITE, iter_opts).unwrap_err();
+        iter.seek_to_first().unwrap_err();
+
+        // The report clears once nothing is stuck any more, and dropping the
+        // poisoned snapshot has nothing left to unregister.
+        worker.core.check_stuck_evictions(&delete_range_scheduler);
+        assert_eq!(RANGE_CACHE_STUCK_EVICTIONS.get(), 0);
+        drop(snap);
+    }
+
+    #[test]
+    fn test_epoch_reclaim_lag_pauses_loads() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.reclaim_lag_threshold = Some(ReadableSize(1));
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+        for i in 0..10 {
+            put_data(
+                format!("key{:02}", i).as_bytes(),
+                b"value",
+                10,
+                15,
+                10 + i,
+                false,
+                &default,
+                &write,
+                memory_controller.clone(),
+            );
+        }
+        assert!(memory_controller.mem_usage() > 0);
+
+        let load_scheduler = Arc::new(LoadScheduler::new(1));
+        let mut runner = DeleteRangeRunner::new(
+            engine.core().clone(),
+            memory_controller.clone(),
+            load_scheduler.clone(),
+            ThreadPlacement::default(),
+            engine.config().clone(),
+        );
+
+        // Simulated readers: pinned guards keep this thread at its epoch, so
+        // the collector cannot advance past anything deferred below.
+        let readers: Vec<_> = (0..8).map(|_| epoch::pin()).collect();
+
+        runner.delete_ranges(&[range.clone()]);
+        let pending = memory_controller.pending_reclaim();
+        assert!(pending > 0);
+
+        // The backlog exceeds the threshold, so the timer tick pauses loads
+        // and cannot drain anything while the readers are pinned.
+        runner.on_timeout();
+        assert!(load_scheduler.is_paused());
+        assert_eq!(memory_controller.pending_reclaim(), pending);
+        assert_eq!(RANGE_CACHE_PENDING_RECLAIM.get(), pending as i64);
+
+        drop(readers);
+        // With the readers gone, timer ticks drive the epoch forward until
+        // the deferred destructions and the backlog callback have run.
+        for _ in 0..10 {
+            runner.on_timeout();
+            if memory_controller.pending_reclaim() == 0 && !load_scheduler.is_paused() {
+                break;
+            }
+        }
+        assert_eq!(memory_controller.pending_reclaim(), 0);
+        assert!(!load_scheduler.is_paused());
+        assert_eq!(memory_controller.mem_usage(), 0);
+        assert_eq!(RANGE_CACHE_PENDING_RECLAIM.get(), 0);
+    }
+
+    #[test]
+    fn test_cancel_eviction() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+        for i in 0..5 {
+            put_data(
+                format!("key{:02}", i).as_bytes(),
+                b"value",
+                10,
+                15,
+                10 + i,
+                false,
+                &default,
+                &write,
+                memory_controller.clone(),
+            );
+        }
+        let mut runner = DeleteRangeRunner::new(
+            engine.core().clone(),
+            memory_controller.clone(),
+            Arc::new(LoadScheduler::new(1)),
+            ThreadPlacement::default(),
+            engine.config().clone(),
+        );
+
+        // Nothing is evicting yet, so there is nothing to cancel.
+        assert_eq!(
+            engine.cancel_eviction(&range),
+            Err(CancelError::NotEvicting)
+        );
+
+        // Evict without running the delete worker: the range stops serving
+        // snapshots but its data is still there.
+        {
+            let mut core = engine.core().write();
+            assert_eq!(
+                core.mut_range_manager().evict_range(&range, "test"),
+                vec![range.clone()]
+            );
+        }
+        engine
+            .snapshot(range.clone(), u64::MAX, u64::MAX)
+            .unwrap_err();
+
+        // Cancel before the queued task has run: the range serves snapshots
+        // again with intact data, and the task skips it when it arrives.
+        engine.cancel_eviction(&range).unwrap();
+        let snap = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        runner.run(BackgroundTask::DeleteRange(vec![range.clone()]));
+        assert_eq!(element_count(&write), 5);
+        assert_eq!(element_count(&default), 5);
+        drop(snap);
+
+        // An eviction deferred behind an open snapshot can be rolled back the
+        // same way; once the snapshot drops there is nothing left to delete.
+        let pinned = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        {
+            let mut core = engine.core().write();
+            assert!(
+                core.mut_range_manager()
+                    .evict_range(&range, "test")
+                    .is_empty()
+            );
+        }
+        engine.cancel_eviction(&range).unwrap();
+        drop(pinned);
+        {
+            let core = engine.core().read();
+            assert_eq!(core.range_manager().historical_ranges_count(), 0);
+            assert!(core.range_manager().ranges_being_deleted.is_empty());
+        }
+        assert_eq!(element_count(&write), 5);
+
+        // Evict once more and let the delete worker take its first batch:
+        // from then on the cancellation is refused and the eviction runs to
+        // completion.
+        {
+            let mut core = engine.core().write();
+            assert_eq!(
+                core.mut_range_manager().evict_range(&range, "test"),
+                vec![range.clone()]
+            );
+            // What the worker does right before its first batch.
+            assert!(core.mut_range_manager().start_deletion(&range));
+        }
+        assert_eq!(
+            engine.cancel_eviction(&range),
+            Err(CancelError::DeletionStarted)
+        );
+        runner.delete_ranges(&[range.clone()]);
+        assert_eq!(element_count(&write), 0);
+        assert_eq!(element_count(&default), 0);
+        engine
+            .snapshot(range.clone(), u64::MAX, u64::MAX)
+            .unwrap_err();
+        assert!(
+            engine
+                .core()
+                .read()
+                .range_manager()
+                .ranges_being_deleted
+                .is_empty()
+        );
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_deletion_pacer_follows_seek_latency() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.delete_read_latency_target = Some(ReadableDuration::millis(5));
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let memory_controller = engine.memory_controller();
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        let (write, default) = {
+            let skiplist_engine = engine.core().write().engine();
+            (
+                skiplist_engine.cf_handle(CF_WRITE),
+                skiplist_engine.cf_handle(CF_DEFAULT),
+            )
+        };
+        let fill = |count: u64| {
+            for i in 0..count {
+                put_data(
+                    format!("key{:04}", i).as_bytes(),
+                    b"value",
+                    10,
+                    15,
+                    10 + i,
+                    false,
+                    &default,
+                    &write,
+                    memory_controller.clone(),
+                );
+            }
+        };
+        // Foreground readers feeding the seek-latency reservoir; while the
+        // failpoint is armed, every seek sleeps well past the target.
+        let seek = |n: usize| {
+            let snap = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+            let mut iter_opts = IterOptions::default();
+            iter_opts.set_lower_bound(&range.start, 0);
+            iter_opts.set_upper_bound(&range.end, 0);
+            let mut iter = snap.iterator_opt(CF_WRITE, iter_opts).unwrap();
+            for _ in 0..n {
+                iter.seek_to_first().unwrap();
+            }
+        };
+
+        let mut runner = DeleteRangeRunner::new(
+            engine.core().clone(),
+            memory_controller.clone(),
+            Arc::new(LoadScheduler::new(1)),
+            ThreadPlacement::default(),
+            engine.config().clone(),
+        );
+
+        // Every refill-and-delete cycle runs at least one batch and thus one
+        // controller adjustment, so under sustained slow reads the budget
+        // halves per cycle and the sleep grows until both hit their bounds.
+        fail::cfg("range_cache_on_seek", "sleep(10)").unwrap();
+        for _ in 0..12 {
+            seek(6);
+            fill(10);
+            runner.delete_ranges(&[range.clone()]);
+            // Minimum progress: even a saturated pacer drains every pass and
+            // the budget never falls below the floor.
+            assert_eq!(0, element_count(&default));
+            assert_eq!(0, element_count(&write));
+            assert!(RANGE_CACHE_DELETE_BATCH_BUDGET.get() >= MIN_DELETE_BATCH_ENTRIES as i64);
+        }
+        assert_eq!(
+            RANGE_CACHE_DELETE_BATCH_BUDGET.get(),
+            MIN_DELETE_BATCH_ENTRIES as i64
+        );
+        assert_eq!(
+            RANGE_CACHE_DELETE_BATCH_SLEEP.get(),
+            MAX_DELETE_BATCH_SLEEP.as_millis() as i64
+        );
+        assert_eq!(RANGE_CACHE_DELETE_PACER_SATURATED.get(), 1);
+
+        // Drop the injected latency and overwrite the reservoir with fast
+        // seeks: the budget recovers additively and the sleep collapses.
+        fail::remove("range_cache_on_seek");
+        seek(300);
+        for _ in 0..4 {
+            fill(10);
+            runner.delete_ranges(&[range.clone()]);
+        }
+        assert!(RANGE_CACHE_DELETE_BATCH_BUDGET.get() > MIN_DELETE_BATCH_ENTRIES as i64);
+        assert!(
+            RANGE_CACHE_DELETE_BATCH_SLEEP.get() < MAX_DELETE_BATCH_SLEEP.as_millis() as i64
+        );
+        assert_eq!(RANGE_CACHE_DELETE_PACER_SATURATED.get(), 0);
+    }
+
+    // A disk engine that records every iterator it hands out, so tests can
+    // assert what the pre-warm pass actually scanned.
+    #[derive(Clone)]
+    struct RecordingDiskEngine {
+        engine: RocksEngine,
+        // (cf, lower bound, upper bound, fill_cache) per iterator opened.
+        iters: Arc<Mutex<Vec<(String, Vec<u8>, Vec<u8>, bool)>>>,
+    }
+
+    impl Iterable for RecordingDiskEngine {
+        type Iterator = <RocksEngine as Iterable>::Iterator;
+
+        fn iterator_opt(
+            &self,
+            cf: &str,
+            opts: IterOptions,
+        ) -> engine_traits::Result<Self::Iterator> {
+            let fill_cache = opts.fill_cache();
+            let (lower, upper) = opts.clone().build_bounds();
+            self.iters.lock().unwrap().push((
+                cf.to_string(),
+                lower.unwrap_or_default(),
+                upper.unwrap_or_default(),
+                fill_cache,
+            ));
+            self.engine.iterator_opt(cf, opts)
+        }
+    }
+
+    #[test]
+    fn test_prewarm_range_scan() {
+        let path = Builder::new().prefix("test_prewarm").tempdir().unwrap();
+        let rocks_engine = new_engine(path.path().to_str().unwrap(), DATA_CFS).unwrap();
+        let range = CacheRange::new(data_key(b"k100"), data_key(b"k500"));
+        let mut in_range_bytes = 0;
+        for i in 0..600u32 {
+            let key = data_key(format!("k{:03}", i).as_bytes());
+            let value = vec![0u8; 100];
+            for cf in [CF_DEFAULT, CF_WRITE] {
+                rocks_engine.put_cf(cf, &key, &value).unwrap();
+                if (100..500).contains(&i) {
+                    in_range_bytes += (key.len() + value.len()) as u64;
+                }
+            }
+        }
+        let iters = Arc::new(Mutex::new(vec![]));
+        let disk_engine = RecordingDiskEngine {
+            engine: rocks_engine,
+            iters: iters.clone(),
+        };
+
+        // An unbounded pass reads exactly the data within the range bounds,
+        // with cache-filling iterators over all data cfs.
+        let limiter = Limiter::new(f64::INFINITY);
+        let (bytes, aborted) =
+            prewarm_range(&disk_engine, &range, u64::MAX, &limiter, &|| false).unwrap();
+        assert!(!aborted);
+        assert_eq!(bytes, in_range_bytes);
+        {
+            let mut iters = iters.lock().unwrap();
+            let cfs: Vec<_> = iters.iter().map(|(cf, ..)| cf.as_str()).collect();
+            assert_eq!(cfs, vec![CF_WRITE, CF_LOCK, CF_DEFAULT]);
+            for (_, lower, upper, fill_cache) in iters.iter() {
+                assert_eq!(lower, &range.start);
+                assert_eq!(upper, &range.end);
+                assert!(*fill_cache);
+            }
+            iters.clear();
+        }
+
+        // Every byte read goes through the rate limiter.
+        let limiter = Limiter::new(ReadableSize::mb(16).0 as f64);
+        let (bytes, aborted) =
+            prewarm_range(&disk_engine, &range, u64::MAX, &limiter, &|| false).unwrap();
+        assert!(!aborted);
+        assert_eq!(limiter.total_bytes_consumed() as u64, bytes);
+
+        // The budget caps the pass.
+        let limiter = Limiter::new(f64::INFINITY);
+        let (bytes, aborted) =
+            prewarm_range(&disk_engine, &range, 1000, &limiter, &|| false).unwrap();
+        assert!(!aborted);
+        assert!(bytes >= 1000 && bytes < in_range_bytes);
+
+        // The abort signal cuts a pass short at the next periodic check.
+        let (bytes, aborted) =
+            prewarm_range(&disk_engine, &range, u64::MAX, &limiter, &|| true).unwrap();
+        assert!(aborted);
+        assert!(bytes < in_range_bytes);
+    }
+
+    #[test]
+    fn test_evict_prewarm_policy() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.evict_prewarm_budget = ReadableSize::mb(1);
+        config.evict_prewarm_rate_limit = ReadableSize(0);
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let path = Builder::new()
+            .prefix("test_prewarm_policy")
+            .tempdir()
+            .unwrap();
+        let rocks_engine = new_engine(path.path().to_str().unwrap(), DATA_CFS).unwrap();
+        let range = CacheRange::new(data_key(b"k000"), data_key(b"k999"));
+        for i in 0..300u32 {
+            let key = data_key(format!("k{:03}", i).as_bytes());
+            rocks_engine.put_cf(CF_DEFAULT, &key, &[0u8; 100]).unwrap();
+        }
+
+        let (mut worker, _) = BackgroundRunner::new(
+            engine.core.clone(),
+            engine.memory_controller(),
+            None,
+            engine.config().clone(),
+        );
+        worker.run(BackgroundTask::SetRocksEngine(rocks_engine.clone()));
+
+        // A memory pressure eviction skips the pass without touching the
+        // disk engine.
+        let bytes_before = RANGE_CACHE_PREWARM_BYTES.get();
+        let skipped = RANGE_CACHE_PREWARM_SKIPPED.with_label_values(&["memory-soft-limit"]);
+        let skipped_before = skipped.get();
+        worker.run(BackgroundTask::PrewarmRange {
+            ranges: vec![range.clone()],
+            reason: "memory-soft-limit",
+        });
+        assert_eq!(skipped.get(), skipped_before + 1);
+        assert_eq!(RANGE_CACHE_PREWARM_BYTES.get(), bytes_before);
+
+        // An eviction that stays evicted warms the whole range within the
+        // budget.
+        worker.core.prewarm_evicted_ranges(
+            &rocks_engine,
+            &[range.clone()],
+            "evict-api",
+            ReadableSize::mb(1).0,
+            0,
+        );
+        let warmed = RANGE_CACHE_PREWARM_BYTES.get() - bytes_before;
+        assert_eq!(warmed, 300 * (data_key(b"k000").len() + 100) as u64);
+
+        // A range that is cached again by the time the pass runs aborts it.
+        let readmitted = RANGE_CACHE_PREWARM_SKIPPED.with_label_values(&["re-admitted"]);
+        let readmitted_before = readmitted.get();
+        engine.new_range(range.clone());
+        let bytes_before = RANGE_CACHE_PREWARM_BYTES.get();
+        worker.core.prewarm_evicted_ranges(
+            &rocks_engine,
+            &[range.clone()],
+            "evict-api",
+            ReadableSize::mb(1).0,
+            0,
+        );
+        assert_eq!(readmitted.get(), readmitted_before + 1);
+        assert!(RANGE_CACHE_PREWARM_BYTES.get() - bytes_before < warmed);
+    }
+
+    #[test]
+    fn test_evict_schedules_prewarm() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.evict_prewarm_budget = ReadableSize::mb(1);
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        engine.bg_worker_manager().enable_deterministic_mode();
+        let skipped = RANGE_CACHE_PREWARM_SKIPPED.with_label_values(&["no-disk-engine"]);
+        let skipped_before = skipped.get();
+        engine.evict_range(&range);
+        // The eviction parked a pre-warm task ahead of the deletion; with no
+        // disk engine set, running it resolves to a counted skip.
+        assert!(engine.bg_worker_manager().run_one_background_task());
+        assert!(engine.bg_worker_manager().run_one_background_task());
+        assert!(!engine.bg_worker_manager().run_one_background_task());
+        for _ in 0..100 {
+            if skipped.get() > skipped_before {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(skipped.get(), skipped_before + 1);
     }
 }
@@ -0,0 +1,206 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Optional transparent compression of the values stored in the cached
+//! skiplists. Typical workloads keep many values in the 1KB-64KB band, which
+//! lz4 shrinks well at a per-access cost small compared to a disk read.
+//!
+//! When `value-compression` is enabled, every value the write and load paths
+//! store is framed with a one-byte header: either [`RAW_VALUE_HEADER`]
+//! followed by the value bytes as they are, or [`LZ4_VALUE_HEADER`] followed
+//! by the uncompressed length (little endian u32) and the lz4 block. Only
+//! values of at least `value-compression-threshold` bytes whose compressed
+//! form is actually smaller are stored compressed; everything else pays the
+//! single header byte. With compression disabled (the default) values are
+//! stored bare, exactly as before.
+//!
+//! The setting takes effect at startup only: the cache starts empty, so all
+//! values stored during one process run agree on whether they are framed and
+//! the read paths never have to guess per value. The frames are still
+//! self-describing, so the threshold may vary freely. A frame that does not
+//! start with a known header is reported as an error instead of being served
+//! as value bytes, so a corrupted entry surfaces to the reader.
+//!
+//! The memory controller accounts the stored bytes, i.e. the compressed
+//! size, which is what lets compression stretch the configured memory
+//! limits. Checksum verification and the replay log both operate on the
+//! stored bytes as well, on both of their sides, so they are unaffected by
+//! the framing.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use engine_traits::Result;
+use serde::{Deserialize, Serialize};
+use tikv_util::box_err;
+
+/// The compression applied to qualifying values, see the module docs.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueCompression {
+    None,
+    Lz4,
+}
+
+impl ValueCompression {
+    /// Whether stored values are framed, see the module docs.
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, ValueCompression::None)
+    }
+}
+
+// The header bytes of framed values.
+pub(crate) const RAW_VALUE_HEADER: u8 = 0;
+pub(crate) const LZ4_VALUE_HEADER: u8 = 1;
+// The framing overhead of a compressed value: the header byte plus the
+// uncompressed length needed to size the decompression buffer.
+const LZ4_FRAME_OVERHEAD: usize = 1 + std::mem::size_of::<u32>();
+// lz4 works on i32 lengths (LZ4_MAX_INPUT_SIZE). Values anywhere near it do
+// not occur in practice; should one appear it is stored raw rather than
+// failing the write.
+const MAX_COMPRESS_INPUT_SIZE: usize = 0x7E00_0000;
+
+/// Frames `value` for storage: compressed when it is at least `threshold`
+/// bytes and compression actually saves space, raw behind the header byte
+/// otherwise. Returns the stored bytes and whether they are compressed.
+pub(crate) fn maybe_compress_value(value: &[u8], threshold: usize) -> (Bytes, bool) {
+    if value.len() >= threshold
+        && value.len() <= MAX_COMPRESS_INPUT_SIZE
+        && let Some(compressed) = lz4_compress(value)
+        && LZ4_FRAME_OVERHEAD + compressed.len() < 1 + value.len()
+    {
+        let mut buf = BytesMut::with_capacity(LZ4_FRAME_OVERHEAD + compressed.len());
+        buf.put_u8(LZ4_VALUE_HEADER);
+        buf.put_u32_le(value.len() as u32);
+        buf.put_slice(&compressed);
+        return (buf.freeze(), true);
+    }
+    let mut buf = BytesMut::with_capacity(1 + value.len());
+    buf.put_u8(RAW_VALUE_HEADER);
+    buf.put_slice(value);
+    (buf.freeze(), false)
+}
+
+/// The inverse of [`maybe_compress_value`]: unframes a stored value. Raw
+/// frames are unwrapped as a zero-copy slice of the stored bytes, so the
+/// returned `Bytes` keeps pinning the original allocation; compressed frames
+/// are decompressed into a fresh buffer. A frame that cannot be decoded is an
+/// error, never value bytes.
+pub(crate) fn decode_value(stored: Bytes) -> Result<Bytes> {
+    match stored.first() {
+        Some(&RAW_VALUE_HEADER) => Ok(stored.slice(1..)),
+        Some(&LZ4_VALUE_HEADER) => {
+            if stored.len() < LZ4_FRAME_OVERHEAD {
+                return Err(box_err!(
+                    "truncated lz4 value frame of {} bytes",
+                    stored.len()
+                ));
+            }
+            let raw_len = u32::from_le_bytes(stored[1..LZ4_FRAME_OVERHEAD].try_into().unwrap());
+            let decompressed = lz4_decompress(&stored[LZ4_FRAME_OVERHEAD..], raw_len as usize)?;
+            Ok(decompressed.into())
+        }
+        Some(header) => Err(box_err!("unknown value compression header {}", header)),
+        None => Err(box_err!("empty value frame")),
+    }
+}
+
+// Returns None when the input does not shrink (lz4 then reports an output
+// overflow), in which case the value is stored raw.
+fn lz4_compress(data: &[u8]) -> Option<Vec<u8>> {
+    unsafe {
+        let bound = lz4_sys::LZ4_compressBound(data.len() as i32);
+        if bound <= 0 {
+            return None;
+        }
+        let mut buf = Vec::with_capacity(bound as usize);
+        let compressed_len = lz4_sys::LZ4_compress_default(
+            data.as_ptr() as *const _,
+            buf.as_mut_ptr() as *mut _,
+            data.len() as i32,
+            bound,
+        );
+        if compressed_len <= 0 {
+            return None;
+        }
+        buf.set_len(compressed_len as usize);
+        Some(buf)
+    }
+}
+
+fn lz4_decompress(data: &[u8], raw_len: usize) -> Result<Vec<u8>> {
+    if raw_len > MAX_COMPRESS_INPUT_SIZE {
+        return Err(box_err!(
+            "invalid decompressed length {} in lz4 value frame",
+            raw_len
+        ));
+    }
+    unsafe {
+        let mut buf = Vec::with_capacity(raw_len);
+        let decompressed_len = lz4_sys::LZ4_decompress_safe(
+            data.as_ptr() as *const _,
+            buf.as_mut_ptr() as *mut _,
+            data.len() as i32,
+            raw_len as i32,
+        );
+        if decompressed_len < 0 || decompressed_len as usize != raw_len {
+            return Err(box_err!("corrupted lz4 value frame"));
+        }
+        buf.set_len(raw_len);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_value_framing() {
+        // A value below the threshold stays raw behind the header byte.
+        let (stored, compressed) = maybe_compress_value(b"short", 1024);
+        assert!(!compressed);
+        assert_eq!(stored[0], RAW_VALUE_HEADER);
+        assert_eq!(decode_value(stored).unwrap().as_ref(), b"short");
+
+        // A large compressible value is stored compressed and round-trips.
+        let value = vec![42u8; 8192];
+        let (stored, compressed) = maybe_compress_value(&value, 1024);
+        assert!(compressed);
+        assert_eq!(stored[0], LZ4_VALUE_HEADER);
+        assert!(stored.len() < value.len());
+        assert_eq!(decode_value(stored).unwrap().as_ref(), value.as_slice());
+
+        // An incompressible value above the threshold falls back to raw
+        // rather than growing.
+        let value: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+        let (stored, compressed) = maybe_compress_value(&value, 1024);
+        assert!(!compressed);
+        assert_eq!(stored.len(), value.len() + 1);
+        assert_eq!(decode_value(stored).unwrap().as_ref(), value.as_slice());
+
+        // Empty values are representable.
+        let (stored, compressed) = maybe_compress_value(b"", 1024);
+        assert!(!compressed);
+        assert!(decode_value(stored).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_frames_are_errors() {
+        // An unknown header byte.
+        decode_value(Bytes::from_static(&[7, 1, 2])).unwrap_err();
+        // A missing header.
+        decode_value(Bytes::new()).unwrap_err();
+        // An lz4 frame with its length field cut off.
+        decode_value(Bytes::from_static(&[LZ4_VALUE_HEADER, 1, 0])).unwrap_err();
+        // An lz4 frame whose payload is garbage.
+        let mut frame = vec![LZ4_VALUE_HEADER];
+        frame.extend_from_slice(&64u32.to_le_bytes());
+        frame.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        decode_value(Bytes::from(frame)).unwrap_err();
+        // An lz4 frame that claims an absurd decompressed length.
+        let mut frame = vec![LZ4_VALUE_HEADER];
+        frame.extend_from_slice(&u32::MAX.to_le_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        decode_value(Bytes::from(frame)).unwrap_err();
+    }
+}
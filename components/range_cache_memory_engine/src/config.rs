@@ -19,11 +19,19 @@ impl ConfigManager for RangeCacheConfigManager {
         &mut self,
         change: ConfigChange,
     ) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        {
-            let change = change.clone();
-            self.0
-                .update(move |cfg: &mut RangeCacheEngineConfig| cfg.update(change))?;
-        }
+        // Apply the change to a copy and validate the result first, so a
+        // rejected update leaves the tracked config fully in effect instead
+        // of partially overwritten. Validation may also clamp values, so the
+        // copy swapped in below is the effective config.
+        let mut new_cfg = self.0.value().clone();
+        new_cfg.update(change.clone())?;
+        new_cfg.validate()?;
+        let res: std::result::Result<(), Box<dyn std::error::Error>> =
+            self.0.update(move |cfg: &mut RangeCacheEngineConfig| {
+                *cfg = new_cfg;
+                Ok(())
+            });
+        res?;
         info!(
             "range cache config changed";
             "change" => ?change,
@@ -39,3 +47,34 @@ impl std::ops::Deref for RangeCacheConfigManager {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use online_config::ConfigValue;
+
+    use super::*;
+
+    // A rejected online update must not leave any of its fields applied,
+    // even the individually valid ones.
+    #[test]
+    fn test_rejected_update_is_not_partially_applied() {
+        let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig::config_for_test()));
+        let mut manager = RangeCacheConfigManager(config.clone());
+        let old = config.value().clone();
+
+        // One valid field and one that fails validation (the new hard limit
+        // drops below the soft limit), in the same change.
+        let mut change = ConfigChange::new();
+        change.insert(String::from("load_concurrency"), ConfigValue::Usize(4));
+        change.insert(String::from("hard_limit_threshold"), ConfigValue::Size(1));
+        let err = manager.dispatch(change).unwrap_err().to_string();
+        assert!(err.contains("hard-limit-threshold"), "{}", err);
+        assert_eq!(*config.value(), old);
+
+        // The valid field alone still goes through.
+        let mut change = ConfigChange::new();
+        change.insert(String::from("load_concurrency"), ConfigValue::Usize(4));
+        manager.dispatch(change).unwrap();
+        assert_eq!(config.value().load_concurrency, 4);
+    }
+}
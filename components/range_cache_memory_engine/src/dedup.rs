@@ -0,0 +1,202 @@
+// Copyright 2025 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Content-addressed deduplication of the values stored in the cached
+//! skiplists. Rows that are rewritten with identical content (status flags
+//! flipping back and forth) store every mvcc version's full value even when
+//! the bytes match; for such workloads a large fraction of the cache holds
+//! duplicate bytes.
+//!
+//! When `value-dedup-table-limit` is non-zero, the insert paths offer each
+//! stored value to a per-engine table keyed by a 128-bit hash of its bytes.
+//! On a hash hit the bytes are compared in full — a collision falls back to
+//! storing the value directly — and the existing `Bytes` handle is shared
+//! instead of keeping a second copy, so the memory controller accounts the
+//! value bytes once no matter how many skiplist entries reference them; each
+//! reference still accounts its own handle overhead. The table holds one
+//! reference count per tracked value, released from `InternalBytes::drop`
+//! when a referencing skiplist entry is destroyed; the last release removes
+//! the table entry. The table is capped at the configured number of distinct
+//! values and values are stored directly once it is full.
+//!
+//! Reads are unaffected: values are immutable `Bytes`, so a shared handle is
+//! indistinguishable from a private copy. Deduplication operates on the
+//! stored bytes, i.e. after value compression framing, so the two features
+//! compose.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+// Values smaller than this are never deduplicated: the table entry and the
+// hash cost more than the duplicate bytes they could save.
+const VALUE_DEDUP_MIN_SIZE: usize = 32;
+
+/// The 128-bit content hash the table is keyed by. Two independently seeded
+/// 64-bit hashes; `DefaultHasher::new` uses fixed keys, so the hash is
+/// stable across threads.
+fn value_hash(value: &[u8]) -> u128 {
+    let mut lo = DefaultHasher::new();
+    value.hash(&mut lo);
+    let mut hi = DefaultHasher::new();
+    // Domain-separate the halves so they do not collide together.
+    1u8.hash(&mut hi);
+    value.hash(&mut hi);
+    ((hi.finish() as u128) << 64) | lo.finish() as u128
+}
+
+struct SharedValue {
+    bytes: Bytes,
+    // The number of live skiplist entries referencing `bytes` through the
+    // table.
+    refs: usize,
+}
+
+/// A successfully interned value, see [`ValueDedupTable::intern`].
+pub(crate) struct Interned {
+    pub bytes: Bytes,
+    pub hash: u128,
+    // Whether an already tracked copy was reused. If so, the caller must
+    // store `bytes` instead of its own copy and only account the handle
+    // overhead, the value bytes being accounted by the existing copy.
+    pub shared: bool,
+}
+
+/// The per-engine table of shared values, see the module docs.
+#[derive(Default)]
+pub(crate) struct ValueDedupTable {
+    entries: Mutex<HashMap<u128, SharedValue>>,
+}
+
+impl fmt::Debug for ValueDedupTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueDedupTable")
+            .field("tracked", &self.len())
+            .finish()
+    }
+}
+
+impl ValueDedupTable {
+    /// Offers `stored` (the bytes exactly as they are to be stored) to the
+    /// table, taking one reference on success. Returns `None` when the value
+    /// does not qualify: it is too small, the table already tracks `limit`
+    /// distinct values, or a hash collision maps it to different bytes. The
+    /// caller then stores the value directly, without a dedup handle.
+    pub(crate) fn intern(&self, stored: &Bytes, limit: usize) -> Option<Interned> {
+        if stored.len() < VALUE_DEDUP_MIN_SIZE {
+            return None;
+        }
+        let hash = value_hash(stored);
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.get_mut(&hash) {
+            // A hash hit is only trusted after a full compare.
+            if entry.bytes != *stored {
+                return None;
+            }
+            entry.refs += 1;
+            return Some(Interned {
+                bytes: entry.bytes.clone(),
+                hash,
+                shared: true,
+            });
+        }
+        if entries.len() >= limit {
+            return None;
+        }
+        entries.insert(
+            hash,
+            SharedValue {
+                bytes: stored.clone(),
+                refs: 1,
+            },
+        );
+        Some(Interned {
+            bytes: stored.clone(),
+            hash,
+            shared: false,
+        })
+    }
+
+    /// Releases one reference on the value tracked under `hash`. Returns
+    /// true when it was the last one and the entry was removed, in which
+    /// case the caller owns the bytes' memory accounting.
+    pub(crate) fn release(&self, hash: u128) -> bool {
+        let mut entries = self.entries.lock();
+        match entries.get_mut(&hash) {
+            Some(entry) if entry.refs > 1 => {
+                entry.refs -= 1;
+                false
+            }
+            Some(_) => {
+                entries.remove(&hash);
+                true
+            }
+            None => {
+                debug_assert!(false, "released a value the dedup table does not track");
+                true
+            }
+        }
+    }
+
+    /// The number of distinct values currently tracked.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_and_release() {
+        let table = ValueDedupTable::default();
+        let value = Bytes::from(vec![7u8; 64]);
+
+        // The first copy is tracked but not shared.
+        let first = table.intern(&value, 16).unwrap();
+        assert!(!first.shared);
+        assert_eq!(table.len(), 1);
+
+        // Further identical copies share the tracked handle.
+        let second = table.intern(&Bytes::from(vec![7u8; 64]), 16).unwrap();
+        assert!(second.shared);
+        assert_eq!(second.hash, first.hash);
+        assert_eq!(second.bytes, value);
+        assert_eq!(table.len(), 1);
+
+        // A different value gets its own entry.
+        let other = table.intern(&Bytes::from(vec![8u8; 64]), 16).unwrap();
+        assert!(!other.shared);
+        assert_eq!(table.len(), 2);
+
+        // Only the last release removes the entry.
+        assert!(!table.release(first.hash));
+        assert!(table.release(first.hash));
+        assert!(table.release(other.hash));
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn test_small_values_and_full_table() {
+        let table = ValueDedupTable::default();
+        // Below the minimum size nothing is tracked.
+        assert!(table.intern(&Bytes::from_static(b"tiny"), 16).is_none());
+
+        // Once the table is full, new values are refused but tracked ones
+        // keep sharing.
+        let tracked = table.intern(&Bytes::from(vec![1u8; 64]), 1).unwrap();
+        assert!(table.intern(&Bytes::from(vec![2u8; 64]), 1).is_none());
+        assert!(table.intern(&Bytes::from(vec![1u8; 64]), 1).unwrap().shared);
+        assert_eq!(table.len(), 1);
+
+        // Draining the references makes room again.
+        assert!(!table.release(tracked.hash));
+        assert!(table.release(tracked.hash));
+        assert!(table.intern(&Bytes::from(vec![2u8; 64]), 1).is_some());
+    }
+}
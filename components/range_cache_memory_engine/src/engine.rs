@@ -1,7 +1,7 @@
 // Copyright 2023 TiKV Project Authors. Licensed under Apache-2.0.
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Debug},
     ops::Bound,
     result,
@@ -9,15 +9,16 @@ use std::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use crossbeam::epoch::{self, default_collector, Guard};
 use engine_rocks::RocksEngine;
 use engine_traits::{
-    CacheRange, FailedReason, IterOptions, Iterable, KvEngine, RangeCacheEngine, Result,
-    CF_DEFAULT, CF_LOCK, CF_WRITE, DATA_CFS,
+    CacheRange, CachedCfs, FailedReason, IterOptions, Iterable, KvEngine, MiscExt,
+    RangeCacheEngine, Result, SnapshotMiscExt, CF_DEFAULT, CF_LOCK, CF_WRITE, DATA_CFS,
 };
-use parking_lot::{lock_api::RwLockUpgradableReadGuard, RwLock, RwLockWriteGuard};
+use parking_lot::{lock_api::RwLockUpgradableReadGuard, Condvar, Mutex, RwLock, RwLockWriteGuard};
 use raftstore::coprocessor::RegionInfoProvider;
 use skiplist_rs::{
     base::{Entry, OwnedIter},
@@ -27,13 +28,20 @@ use slog_global::error;
 use tikv_util::{config::VersionTrack, info};
 
 use crate::{
-    background::{BackgroundTask, BgWorkManager, PdRangeHintService},
+    background::{BackgroundTask, BgWorkManager, GcStats, PdRangeHintService},
+    checksum::RangeChecksums,
+    dedup::ValueDedupTable,
+    events::{EventHistory, RangeEvent},
     keys::{
-        encode_key_for_boundary_with_mvcc, encode_key_for_boundary_without_mvcc, InternalBytes,
+        encode_key_for_boundary_with_mvcc, encode_key_for_boundary_without_mvcc,
+        is_raw_mode_data_key, InternalBytes,
     },
+    load_scheduler::LoadPriority,
     memory_controller::MemoryController,
-    range_manager::{LoadFailedReason, RangeCacheStatus, RangeManager},
-    read::{RangeCacheIterator, RangeCacheSnapshot},
+    provenance::RangeProvenance,
+    range_manager::{CancelError, LoadFailedReason, PinFailedReason, RangeCacheStatus, RangeManager},
+    read::{MultiRangeIterator, RangeCacheIterator, RangeCacheSnapshot},
+    replay::{ReplayRecord, ReplayRecorder},
     statistics::Statistics,
     write_batch::{group_write_batch_entries, RangeCacheWriteBatchEntry},
     RangeCacheEngineConfig, RangeCacheEngineContext,
@@ -122,6 +130,9 @@ impl SkiplistHandle {
 #[derive(Clone)]
 pub struct SkiplistEngine {
     pub(crate) data: [Arc<SkipList<InternalBytes, InternalBytes>>; 3],
+    // The shared values of the optional value deduplication, see the `dedup`
+    // module. Empty unless `value-dedup-table-limit` is set.
+    pub(crate) value_dedup: Arc<ValueDedupTable>,
 }
 
 impl Default for SkiplistEngine {
@@ -139,6 +150,7 @@ impl SkiplistEngine {
                 Arc::new(SkipList::new(collector.clone())),
                 Arc::new(SkipList::new(collector)),
             ],
+            value_dedup: Arc::default(),
         }
     }
 
@@ -152,8 +164,28 @@ impl SkiplistEngine {
         count
     }
 
-    pub(crate) fn delete_range(&self, range: &CacheRange) {
-        DATA_CFS.iter().for_each(|&cf| {
+    /// Removes everything within `range` from the skiplists. Returns the
+    /// memory-controller-accounted size of the removed entries; note that
+    /// their destruction is deferred to the epoch collector, so the returned
+    /// bytes are not necessarily back in the allocator when this returns.
+    pub(crate) fn delete_range(&self, range: &CacheRange) -> usize {
+        self.delete_range_with_budget(range, usize::MAX).0
+    }
+
+    /// Like [`SkiplistEngine::delete_range`], but removes at most `budget`
+    /// entries across the data cfs. Returns the accounted bytes and the
+    /// number of entries removed, and whether the range is fully drained.
+    /// Re-running with the same range resumes where the previous batch
+    /// stopped, because removed entries are no longer visible to the fresh
+    /// iterators.
+    pub(crate) fn delete_range_with_budget(
+        &self,
+        range: &CacheRange,
+        budget: usize,
+    ) -> (usize, usize, bool) {
+        let mut removed_bytes = 0;
+        let mut removed_entries = 0;
+        for &cf in DATA_CFS {
             let (start, end) = if cf == CF_LOCK {
                 encode_key_for_boundary_without_mvcc(range)
             } else {
@@ -165,12 +197,21 @@ impl SkiplistEngine {
             let guard = &epoch::pin();
             iter.seek(&start, guard);
             while iter.valid() && iter.key() < &end {
+                if removed_entries >= budget {
+                    guard.flush();
+                    return (removed_bytes, removed_entries, false);
+                }
+                removed_bytes +=
+                    InternalBytes::memory_size_required(iter.key().as_slice().len())
+                        + InternalBytes::memory_size_required(iter.value().as_slice().len());
                 handle.remove(iter.key(), guard);
+                removed_entries += 1;
                 iter.next(guard);
             }
             // guard will buffer 8 drop methods, flush here to clear the buffer.
             guard.flush();
-        });
+        }
+        (removed_bytes, removed_entries, true)
     }
 }
 
@@ -184,6 +225,16 @@ pub struct RangeCacheMemoryEngineCore {
     pub(crate) engine: SkiplistEngine,
     pub(crate) range_manager: RangeManager,
     pub(crate) cached_write_batch: BTreeMap<CacheRange, Vec<(u64, RangeCacheWriteBatchEntry)>>,
+    // The largest sequence number that has been applied to the memory engine. It is used to
+    // report the gap between the disk engine and the memory engine.
+    pub(crate) max_applied_seqno: AtomicU64,
+    // The incremental cache-vs-disk checksums of the cached ranges, only
+    // maintained when `checksum_verification` is enabled. See the `checksum`
+    // module.
+    pub(crate) checksums: Arc<RangeChecksums>,
+    // The apply provenance records of the cached ranges, only maintained
+    // when `provenance_tracking` is enabled. See the `provenance` module.
+    pub(crate) provenance: Arc<RangeProvenance>,
 }
 
 impl Default for RangeCacheMemoryEngineCore {
@@ -198,9 +249,24 @@ impl RangeCacheMemoryEngineCore {
             engine: SkiplistEngine::new(),
             range_manager: RangeManager::default(),
             cached_write_batch: BTreeMap::default(),
+            max_applied_seqno: AtomicU64::new(0),
+            checksums: Arc::default(),
+            provenance: Arc::default(),
         }
     }
 
+    pub fn max_applied_seqno(&self) -> u64 {
+        self.max_applied_seqno.load(Ordering::Relaxed)
+    }
+
+    pub fn checksums(&self) -> Arc<RangeChecksums> {
+        self.checksums.clone()
+    }
+
+    pub fn provenance(&self) -> Arc<RangeProvenance> {
+        self.provenance.clone()
+    }
+
     pub fn engine(&self) -> SkiplistEngine {
         self.engine.clone()
     }
@@ -251,16 +317,23 @@ impl RangeCacheMemoryEngineCore {
     pub(crate) fn pending_range_completes_loading(
         core: &mut RwLockWriteGuard<'_, Self>,
         range: &CacheRange,
+        safe_point: u64,
     ) {
         assert!(!core.has_cached_write_batch(range));
         let range_manager = core.mut_range_manager();
-        let (r, _, canceled) = range_manager
-            .pending_ranges_loading_data
-            .pop_front()
-            .unwrap();
+        // Fetch before `remove_pending_loading_range` as the entry is garbage
+        // collected there once no pending range references it.
+        let cached_cfs = range_manager.requested_cfs(range);
+        let (r, _, canceled) = range_manager.remove_pending_loading_range(range).unwrap();
         assert_eq!(&r, range);
         assert!(!canceled);
-        range_manager.new_range(r);
+        range_manager.new_range_with_safe_point(r, safe_point);
+        if !cached_cfs.is_all() {
+            range_manager
+                .mut_range_meta(range)
+                .unwrap()
+                .set_cached_cfs(cached_cfs);
+        }
     }
 }
 
@@ -297,6 +370,58 @@ pub struct RangeCacheMemoryEngine {
 
     // `write_batch_id_allocator` is used to allocate id for each write batch
     write_batch_id_allocator: Arc<AtomicU64>,
+
+    // An opt-in recorder that logs all writes and range events for offline
+    // replay, see the `replay` module. `None` in production unless attached
+    // explicitly.
+    pub(crate) replay_recorder: Option<Arc<ReplayRecorder>>,
+
+    // Signalled by the write path after the per-range freshness watermarks
+    // advance, so `wait_for_sequence` callers can block without polling.
+    pub(crate) watermark_notifier: Arc<(Mutex<()>, Condvar)>,
+
+    // A clone of the range manager's event history so queries do not need the
+    // core lock, see the `events` module.
+    events: Arc<EventHistory>,
+}
+
+/// Handle returned by [`RangeCacheMemoryEngine::evict_range_bulk`], resolved
+/// once all data overlapping the evicted range has been physically removed,
+/// including the parts that were pinned by snapshots at eviction time.
+#[derive(Clone)]
+pub struct BulkEvictHandle {
+    done: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl BulkEvictHandle {
+    /// Returns whether the bulk eviction has completed.
+    pub fn is_done(&self) -> bool {
+        *self.done.0.lock()
+    }
+
+    /// Blocks until the bulk eviction completes or `timeout` elapses.
+    /// Returns false on timeout.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (lock, cvar) = &*self.done;
+        let mut done = lock.lock();
+        while !*done {
+            if cvar.wait_until(&mut done, deadline).timed_out() {
+                return *done;
+            }
+        }
+        true
+    }
+}
+
+/// Why a `wait_for_sequence` call did not observe the requested sequence.
+#[derive(Debug, PartialEq)]
+pub enum WaitError {
+    /// The range is not cached (or was evicted), so its watermark will never
+    /// reach the requested sequence.
+    NotCached,
+    /// The watermark did not reach the requested sequence within the timeout.
+    TimedOut,
 }
 
 impl RangeCacheMemoryEngine {
@@ -311,6 +436,7 @@ impl RangeCacheMemoryEngine {
         info!("init range cache memory engine";);
         let core = Arc::new(RwLock::new(RangeCacheMemoryEngineCore::new()));
         let skiplist_engine = { core.read().engine().clone() };
+        let events = { core.read().range_manager().events().clone() };
 
         let RangeCacheEngineContext {
             config,
@@ -323,9 +449,7 @@ impl RangeCacheMemoryEngine {
         let bg_work_manager = Arc::new(BgWorkManager::new(
             core.clone(),
             pd_client,
-            config.value().gc_interval.0,
-            config.value().load_evict_interval.0,
-            config.value().expected_region_size(),
+            config.clone(),
             memory_controller.clone(),
             region_info_provider,
         ));
@@ -339,14 +463,38 @@ impl RangeCacheMemoryEngine {
             config,
             lock_modification_bytes: Arc::default(),
             write_batch_id_allocator: Arc::default(),
+            replay_recorder: None,
+            watermark_notifier: Arc::default(),
+            events,
         }
     }
 
+    /// Returns the recorded events whose range overlaps `range`, oldest
+    /// first. A region's range can be built with `CacheRange::from_region`,
+    /// so "why is this region not cached" can be answered from the history
+    /// without collecting logs.
+    pub fn range_events(&self, range: &CacheRange) -> Vec<RangeEvent> {
+        self.events.events_for_range(range)
+    }
+
+    /// Returns the whole event history, oldest first.
+    pub fn dump_events(&self) -> Vec<RangeEvent> {
+        self.events.dump()
+    }
+
+    /// The (apply index, term) that produced the entry holding sequence
+    /// number `seq` of `range`. Only answered when `provenance_tracking` is
+    /// enabled and the record has not aged out, see the `provenance` module.
+    pub fn provenance(&self, range: &CacheRange, seq: u64) -> Option<(u64, u64)> {
+        self.core.read().provenance.lookup(range, seq)
+    }
+
     pub fn expected_region_size(&self) -> usize {
         self.config.value().expected_region_size()
     }
 
     pub fn new_range(&self, range: CacheRange) {
+        self.record_replay(vec![ReplayRecord::Load(range.clone())]);
         let mut core = self.core.write();
         core.range_manager.new_range(range);
     }
@@ -356,48 +504,291 @@ impl RangeCacheMemoryEngine {
     // later in `prepare_for_apply`, the range will be scheduled to load snapshot
     // data into engine.
     pub fn load_range(&self, range: CacheRange) -> result::Result<(), LoadFailedReason> {
+        self.load_range_with_cfs(range, CachedCfs::all())
+    }
+
+    /// Like [`Self::load_range`], but restricts caching to the given data
+    /// cfs. Point reads of the uncached cfs are served by the disk engine;
+    /// with the write cf cached, reads of short values inlined in the write
+    /// records never reach disk at all.
+    pub fn load_range_with_cfs(
+        &self,
+        range: CacheRange,
+        cfs: CachedCfs,
+    ) -> result::Result<(), LoadFailedReason> {
+        // Raw-mode keyspaces are kept out of the cache until raw support is
+        // validated; their reads keep going to the disk engine.
+        if is_raw_mode_data_key(&range.start) && !self.config.value().cache_raw_keyspaces {
+            return Err(LoadFailedReason::RawModeKeyspace);
+        }
+        let mut core = self.core.write();
+        core.mut_range_manager()
+            .load_range_with_cfs(range.clone(), cfs)?;
+        drop(core);
+        self.record_replay(vec![ReplayRecord::Load(range)]);
+        Ok(())
+    }
+
+    /// Pins or unpins the cached range containing `range`. A pinned range is
+    /// skipped by all the automatic eviction policies (ttl, memory pressure,
+    /// load-evict), while `evict_range` and region destroys still remove it.
+    /// A new pin is refused once the pinned ranges, each estimated as one
+    /// expected region size, would occupy more than
+    /// `pinned_range_capacity_ratio` of the soft limit.
+    pub fn pin_range(
+        &self,
+        range: &CacheRange,
+        pinned: bool,
+    ) -> result::Result<(), PinFailedReason> {
+        let config = self.config.value();
+        let budget =
+            (config.soft_limit_threshold() as f64 * config.pinned_range_capacity_ratio) as usize;
+        let expected_range_size = config.expected_region_size();
+        drop(config);
         let mut core = self.core.write();
-        core.mut_range_manager().load_range(range)
+        core.mut_range_manager()
+            .pin_range(range, pinned, budget, expected_range_size)
+    }
+
+    /// Blocks until the freshness watermark of `range` has reached the disk
+    /// sequence number `seq`, or `timeout` expires.
+    ///
+    /// Debug and consistency reads scan a region directly from the disk
+    /// engine at a known sequence number; without a barrier, comparing that
+    /// scan against the cache always shows spurious differences from batches
+    /// that are still in flight. While blocked, only the notifier mutex is
+    /// held, never the range manager lock.
+    pub fn wait_for_sequence(
+        &self,
+        range: &CacheRange,
+        seq: u64,
+        timeout: Duration,
+    ) -> result::Result<(), WaitError> {
+        let deadline = Instant::now() + timeout;
+        let (lock, cvar) = &*self.watermark_notifier;
+        let mut notified = lock.lock();
+        loop {
+            {
+                let core = self.core.read();
+                let range_manager = core.range_manager();
+                if !range_manager.contains_range(range) {
+                    return Err(WaitError::NotCached);
+                }
+                if range_manager
+                    .cached_watermark(range)
+                    .map_or(false, |(applied_seq, _)| applied_seq >= seq)
+                {
+                    return Ok(());
+                }
+            }
+            if cvar.wait_until(&mut notified, deadline).timed_out() {
+                return Err(WaitError::TimedOut);
+            }
+        }
     }
 
     /// Evict a range from the in-memory engine. After this call, the range will
     /// not be readable, but the data of the range may not be deleted
     /// immediately due to some ongoing snapshots.
     pub fn evict_range(&self, range: &CacheRange) {
+        self.record_replay(vec![ReplayRecord::Evict(range.clone())]);
         let mut core = self.core.write();
-        let ranges_to_delete = core.range_manager.evict_range(range);
+        let ranges_to_delete = core.range_manager.evict_range(range, "evict-api");
         if !ranges_to_delete.is_empty() {
             drop(core);
             // The range can be deleted directly.
-            if let Err(e) = self
-                .bg_worker_manager()
-                .schedule_task(BackgroundTask::DeleteRange(ranges_to_delete))
-            {
+            self.schedule_evicted_ranges(ranges_to_delete, "evict-api");
+        }
+    }
+
+    /// Roll back an eviction requested earlier, e.g. by an operation that
+    /// has since been aborted. `range` must be the exact evicted range. The
+    /// call only succeeds while the range awaits its background deletion and
+    /// nothing of it has been deleted yet; the range then serves snapshots
+    /// again with its data intact. Once the deletion has taken its first
+    /// batch the cancellation is refused with
+    /// [`CancelError::DeletionStarted`] and the eviction runs to completion.
+    ///
+    /// The eviction stays in the replay log: replaying it without the
+    /// cancellation only loses cache coverage, never correctness.
+    pub fn cancel_eviction(&self, range: &CacheRange) -> result::Result<(), CancelError> {
+        let mut core = self.core.write();
+        core.mut_range_manager().cancel_eviction(range)
+    }
+
+    /// Schedule the background cleanup of freshly evicted ranges: their
+    /// physical deletion and, when enabled, the block cache pre-warm pass
+    /// that reads them back on the disk engine before the cache misses
+    /// arrive.
+    pub(crate) fn schedule_evicted_ranges(
+        &self,
+        ranges_to_delete: Vec<CacheRange>,
+        reason: &'static str,
+    ) {
+        if self.config().value().evict_prewarm_budget.0 > 0 {
+            if let Err(e) = self.bg_worker_manager().schedule_task(
+                BackgroundTask::PrewarmRange {
+                    ranges: ranges_to_delete.clone(),
+                    reason,
+                },
+            ) {
                 error!(
-                    "schedule delete range failed";
+                    "schedule prewarm range failed";
                     "err" => ?e,
                 );
                 assert!(tikv_util::thread_group::is_shutdown(!cfg!(test)));
             }
         }
+        if let Err(e) = self
+            .bg_worker_manager()
+            .schedule_task(BackgroundTask::DeleteRange(ranges_to_delete))
+        {
+            error!(
+                "schedule delete range failed";
+                "err" => ?e,
+            );
+            assert!(tikv_util::thread_group::is_shutdown(!cfg!(test)));
+        }
+    }
+
+    /// Evicts every cached range overlapping `range` in one call, scheduling
+    /// a single background deletion for the parts that can be removed right
+    /// away. New admissions within `range` are denied until the bulk
+    /// eviction completes, so a load interleaving with the eviction cannot
+    /// re-admit parts of it mid-way. Ranges pinned by live snapshots are
+    /// removed as their snapshots drop, same as with [`Self::evict_range`],
+    /// but tracked under the bulk operation: the returned handle resolves
+    /// once all overlapping data has been physically removed.
+    pub fn evict_range_bulk(&self, range: &CacheRange, reason: &'static str) -> BulkEvictHandle {
+        info!(
+            "bulk evict range in range cache engine";
+            "range" => ?range,
+            "reason" => reason,
+        );
+        self.record_replay(vec![ReplayRecord::Evict(range.clone())]);
+        let done = Arc::new((Mutex::new(false), Condvar::new()));
+        let mut core = self.core.write();
+        let range_manager = core.mut_range_manager();
+        // Deny admissions before evicting so that a racing load cannot slip
+        // in between the eviction and the registration.
+        range_manager.add_denied_range(range.clone());
+        let ranges_to_delete = range_manager.evict_range(range, reason);
+        range_manager.register_bulk_eviction(range.clone(), done.clone());
+        drop(core);
+        if !ranges_to_delete.is_empty() {
+            self.schedule_evicted_ranges(ranges_to_delete, reason);
+        }
+        BulkEvictHandle { done }
+    }
+
+    /// Run one gc pass over the cached `range` right away, physically removing
+    /// the mvcc versions that are no longer visible at `safe_point` (including
+    /// delete marks) together with their default cf values. The safe point is
+    /// capped by the min timestamp of the live snapshots of the range, so a
+    /// pass taken while an old snapshot is open keeps the versions it may
+    /// still read; run another pass after the snapshot is dropped to remove
+    /// them. Returns empty stats if the pass could not run, e.g. the range is
+    /// not cached, a background gc round is in progress, or the safe point
+    /// has not advanced.
+    pub fn gc_range(&self, range: &CacheRange, safe_point: u64) -> GcStats {
+        self.record_replay(vec![ReplayRecord::SafePoint(range.clone(), safe_point)]);
+        {
+            let mut core = self.core.write();
+            if core.range_manager().has_ranges_in_gc() {
+                // A background gc round is running and will cover the range.
+                return GcStats::default();
+            }
+            let mut ranges = BTreeSet::new();
+            ranges.insert(range.clone());
+            core.mut_range_manager().set_ranges_in_gc(ranges);
+        }
+        // Entries still visible to an ongoing RocksDB snapshot must be kept,
+        // same as in the background gc rounds.
+        let oldest_seqno = self
+            .rocks_engine
+            .as_ref()
+            .map(|e| {
+                e.get_oldest_snapshot_sequence_number()
+                    .unwrap_or_else(|| e.get_latest_sequence_number())
+            })
+            .unwrap_or(u64::MAX);
+        let metrics = crate::background::gc_range(
+            &self.core,
+            range,
+            safe_point,
+            oldest_seqno,
+            self.config().value().value_compression.is_enabled(),
+        );
+        {
+            let mut core = self.core.write();
+            let mut ranges = BTreeSet::new();
+            ranges.insert(range.clone());
+            core.mut_range_manager().on_gc_finished(ranges);
+        }
+        let stats = GcStats {
+            versions_removed: metrics.filtered,
+            bytes_freed: metrics.filtered_bytes,
+        };
+        metrics.flush();
+        stats
+    }
+
+    /// Deny caching for `range`: cached ranges overlapping with it are
+    /// evicted immediately, and loads and snapshots of it are refused until
+    /// the range is removed from the deny list again. Reads of the range fall
+    /// back to the disk engine in the meantime.
+    pub fn add_denied_range(&self, range: CacheRange) {
+        info!(
+            "deny range in range cache engine";
+            "range" => ?range,
+        );
+        let mut core = self.core.write();
+        core.range_manager.add_denied_range(range.clone());
+        let ranges_to_delete = core.range_manager.evict_range(&range, "denied");
+        if !ranges_to_delete.is_empty() {
+            drop(core);
+            self.schedule_evicted_ranges(ranges_to_delete, "denied");
+        }
+    }
+
+    /// Allow caching again for ranges denied by [`Self::add_denied_range`]
+    /// that overlap with `range`.
+    pub fn remove_denied_range(&self, range: &CacheRange) {
+        info!(
+            "remove denied range in range cache engine";
+            "range" => ?range,
+        );
+        let mut core = self.core.write();
+        let range_manager = core.mut_range_manager();
+        range_manager.remove_denied_range(range);
+        // The denial evicted the cached ranges overlapping it; roll back the
+        // ones whose deletion has not started, so a denial lifted shortly
+        // after being added does not cost a reload of still intact data.
+        for r in range_manager.cancelable_evictions_overlapping(range) {
+            let _ = range_manager.cancel_eviction(&r);
+        }
     }
 
     // It handles the pending range and check whether to buffer write for this
-    // range.
+    // range. Also returns which data cfs are cached for the range so that the
+    // write batch skips mirroring writes of the uncached cfs.
     pub(crate) fn prepare_for_apply(
         &self,
         write_batch_id: u64,
         range: &CacheRange,
-    ) -> RangeCacheStatus {
+    ) -> (RangeCacheStatus, CachedCfs) {
         let mut core = self.core.write();
         let range_manager = core.mut_range_manager();
         if range_manager.pending_ranges_in_loading_contains(range) {
             range_manager.record_in_ranges_being_written(write_batch_id, range);
-            return RangeCacheStatus::Loading;
+            return (RangeCacheStatus::Loading, range_manager.requested_cfs(range));
         }
         if range_manager.contains_range(range) {
             range_manager.record_in_ranges_being_written(write_batch_id, range);
-            return RangeCacheStatus::Cached;
+            let cached_cfs = range_manager
+                .cached_cfs(range)
+                .unwrap_or_else(CachedCfs::all);
+            return (RangeCacheStatus::Cached, cached_cfs);
         }
 
         let mut overlapped = false;
@@ -429,8 +820,10 @@ impl RangeCacheMemoryEngine {
             })
         {
             if overlapped {
-                core.mut_range_manager().pending_ranges.swap_remove(idx);
-                return RangeCacheStatus::NotInCache;
+                let range_manager = core.mut_range_manager();
+                range_manager.pending_ranges.swap_remove(idx);
+                range_manager.gc_requested_cfs();
+                return (RangeCacheStatus::NotInCache, CachedCfs::all());
             }
 
             let range_manager = core.mut_range_manager();
@@ -445,6 +838,7 @@ impl RangeCacheMemoryEngine {
             let range_manager = core.mut_range_manager();
             range_manager.pending_ranges.swap_remove(idx);
             let rocks_snap = Arc::new(self.rocks_engine.as_ref().unwrap().snapshot(None));
+            let snapshot_seq = rocks_snap.sequence_number();
             // Here, we use the range in `pending_ranges` rather than the parameter range as
             // the region may be splitted.
             range_manager
@@ -463,6 +857,16 @@ impl RangeCacheMemoryEngine {
             // init cached write batch to cache the writes before loading complete
             core.init_cached_write_batch(range);
 
+            // Register the load in the load scheduler so that the background
+            // workers run it with bounded concurrency. Overlapping loads have
+            // been rejected when the range was pushed in `pending_ranges`, so
+            // the dedup here should not trigger. The snapshot bookkeeping
+            // lets the load bound how long it holds the disk snapshot, see
+            // `load_snapshot_max_age`.
+            let load_scheduler = self.bg_work_manager.load_scheduler();
+            load_scheduler.record_snapshot(range, snapshot_seq);
+            load_scheduler.enqueue(range.clone(), LoadPriority::Normal);
+
             if let Err(e) = self
                 .bg_worker_manager()
                 .schedule_task(BackgroundTask::LoadRange)
@@ -476,10 +880,13 @@ impl RangeCacheMemoryEngine {
             }
             // We have scheduled the range to loading data, so the writes of the range
             // should be buffered
-            return RangeCacheStatus::Loading;
+            return (
+                RangeCacheStatus::Loading,
+                core.range_manager().requested_cfs(range),
+            );
         }
 
-        RangeCacheStatus::NotInCache
+        (RangeCacheStatus::NotInCache, CachedCfs::all())
     }
 
     // The writes in `handle_pending_range_in_loading_buffer` indicating the ranges
@@ -518,10 +925,29 @@ impl RangeCacheMemoryEngine {
         }
     }
 
+    /// Returns an iterator over all cached data of `cf` within
+    /// `[start, end)`, stitched across the cached ranges the span overlaps.
+    ///
+    /// Unlike [`RangeCacheEngine::snapshot`], which requires the requested
+    /// range to be fully contained in one cached range, this serves internal
+    /// full-store scans (consistency checks, diagnostics dumps) that cover
+    /// many regions at once: the overlapping cached ranges are all snapshot
+    /// atomically at the max sequence, the keys come out in global order, and
+    /// the uncached parts of the span are reported as explicit gaps for the
+    /// caller to serve from the disk engine. Dropping the iterator releases
+    /// all the snapshots.
+    pub fn multi_range_iterator(&self, cf: &str, start: &[u8], end: &[u8]) -> MultiRangeIterator {
+        MultiRangeIterator::new(self, cf, start, end)
+    }
+
     pub fn bg_worker_manager(&self) -> &BgWorkManager {
         &self.bg_work_manager
     }
 
+    pub fn config(&self) -> &Arc<VersionTrack<RangeCacheEngineConfig>> {
+        &self.config
+    }
+
     pub fn memory_controller(&self) -> Arc<MemoryController> {
         self.memory_controller.clone()
     }
@@ -542,6 +968,28 @@ impl RangeCacheMemoryEngine {
     }
 }
 
+#[cfg(test)]
+impl RangeCacheMemoryEngine {
+    /// Parks background tasks instead of running them, so a test controls
+    /// when and in what order they execute. See
+    /// [`BgWorkManager::enable_deterministic_mode`].
+    pub(crate) fn enable_deterministic_background_tasks(&self) {
+        self.bg_work_manager.enable_deterministic_mode();
+    }
+
+    /// Runs the oldest parked background task to completion. Returns `false`
+    /// if no task is parked.
+    pub(crate) fn run_one_background_task(&self) -> bool {
+        self.bg_work_manager.run_one_background_task()
+    }
+
+    /// Runs parked background tasks, including the ones they park in turn,
+    /// until none is left.
+    pub(crate) fn run_background_tasks_until_idle(&self) {
+        while self.run_one_background_task() {}
+    }
+}
+
 impl Debug for RangeCacheMemoryEngine {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Range Cache Memory Engine")
@@ -586,13 +1034,34 @@ impl RangeCacheEngine for RangeCacheMemoryEngine {
         core.range_manager().get_range_for_key(key)
     }
 
+    fn cached_watermark(&self, range: &CacheRange) -> Option<(u64, u64)> {
+        let core = self.core.read();
+        core.range_manager().cached_watermark(range)
+    }
+
+    fn cached_cfs(&self, range: &CacheRange) -> CachedCfs {
+        let core = self.core.read();
+        core.range_manager()
+            .cached_cfs(range)
+            .unwrap_or_else(CachedCfs::all)
+    }
+
     fn enabled(&self) -> bool {
         self.config.value().enabled
     }
 
+    fn strict_read_errors(&self) -> bool {
+        self.config.value().strict_read_errors
+    }
+
     fn evict_range(&self, range: &CacheRange) {
         self.evict_range(range)
     }
+
+    fn range_evicted(&self, range: &CacheRange) -> bool {
+        let core = self.core.read();
+        core.range_manager().range_evicted(range)
+    }
 }
 
 impl Iterable for RangeCacheMemoryEngine {
@@ -606,16 +1075,18 @@ impl Iterable for RangeCacheMemoryEngine {
 
 #[cfg(test)]
 pub mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use crossbeam::epoch;
-    use engine_traits::{CacheRange, CF_DEFAULT, CF_LOCK, CF_WRITE};
+    use engine_traits::{CacheRange, FailedReason, RangeCacheEngine, CF_DEFAULT, CF_LOCK, CF_WRITE};
     use tikv_util::config::{ReadableSize, VersionTrack};
 
     use super::SkiplistEngine;
     use crate::{
+        engine::WaitError,
         keys::{construct_key, construct_user_key, encode_key},
         memory_controller::MemoryController,
+        range_manager::LoadFailedReason,
         InternalBytes, RangeCacheEngineConfig, RangeCacheEngineContext, RangeCacheMemoryEngine,
         ValueType,
     };
@@ -656,6 +1127,137 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_deny_range() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        engine.new_range(range.clone());
+        engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+
+        // Denying the range evicts it and refuses snapshots, so reads fall
+        // back to the disk engine.
+        engine.add_denied_range(CacheRange::new(b"k05".to_vec(), b"k15".to_vec()));
+        {
+            let core = engine.core.read();
+            assert!(core.range_manager().ranges().is_empty());
+            let record = core.range_manager().recent_evictions().back().unwrap();
+            assert_eq!(record.reason, "denied");
+        }
+        assert_eq!(
+            engine.snapshot(range.clone(), 10, u64::MAX).unwrap_err(),
+            FailedReason::NotCached
+        );
+        assert_eq!(
+            engine.load_range(range.clone()),
+            Err(LoadFailedReason::Denied)
+        );
+
+        // Wait until the background deletion of the evicted range finishes.
+        let mut tried = 0;
+        while !engine
+            .core
+            .read()
+            .range_manager()
+            .ranges_being_deleted
+            .is_empty()
+        {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            tried += 1;
+            assert!(tried < 100);
+        }
+
+        // Clearing the deny list makes the range cachable again.
+        engine.remove_denied_range(&CacheRange::new(b"k00".to_vec(), b"k20".to_vec()));
+        engine.new_range(range.clone());
+        engine.snapshot(range, 10, u64::MAX).unwrap();
+    }
+
+    #[test]
+    fn test_raw_mode_keyspace_admission() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        // The 'z' data prefix followed by the API v2 keyspace prefix.
+        let raw_range = CacheRange::new(b"zr\x00\x00\x01".to_vec(), b"zr\x00\x00\x02".to_vec());
+        let txn_range = CacheRange::new(b"zx\x00\x00\x01".to_vec(), b"zx\x00\x00\x02".to_vec());
+
+        // Raw-mode keyspaces are refused admission by default while txn-mode
+        // keyspaces load as usual.
+        assert_eq!(
+            engine.load_range(raw_range.clone()),
+            Err(LoadFailedReason::RawModeKeyspace)
+        );
+        engine.load_range(txn_range).unwrap();
+
+        // Once explicitly enabled, raw-mode keyspaces can be cached too.
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.cache_raw_keyspaces = true;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        engine.load_range(raw_range).unwrap();
+    }
+
+    #[test]
+    fn test_evict_range_bulk() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        engine.enable_deterministic_background_tasks();
+        let ranges: Vec<_> = (0..5u64)
+            .map(|i| CacheRange::new(construct_user_key(i * 10), construct_user_key(i * 10 + 10)))
+            .collect();
+        for r in &ranges {
+            engine.new_range(r.clone());
+        }
+
+        // Pin the middle range with a snapshot so its data cannot be removed
+        // until the snapshot is dropped.
+        let snap = engine.snapshot(ranges[2].clone(), 10, u64::MAX).unwrap();
+
+        let evict_range = CacheRange::new(construct_user_key(10), construct_user_key(40));
+        let handle = engine.evict_range_bulk(&evict_range, "bulk-test");
+
+        // All three overlapping ranges are evicted at once and admissions
+        // within the requested range are denied while the eviction runs.
+        for r in &ranges[1..4] {
+            assert_eq!(
+                engine.snapshot(r.clone(), 10, u64::MAX).unwrap_err(),
+                FailedReason::NotCached
+            );
+            assert_eq!(engine.load_range(r.clone()), Err(LoadFailedReason::Denied));
+        }
+
+        // A single pass of the delete worker removes the two unpinned ranges,
+        // but the handle stays unresolved while the snapshot pins the third.
+        engine.run_background_tasks_until_idle();
+        assert!(
+            engine
+                .core
+                .read()
+                .range_manager()
+                .ranges_being_deleted
+                .is_empty()
+        );
+        assert!(!handle.is_done());
+        assert!(!handle.wait(Duration::from_millis(50)));
+
+        // The ranges outside the requested one are untouched.
+        engine.snapshot(ranges[0].clone(), 10, u64::MAX).unwrap();
+        engine.snapshot(ranges[4].clone(), 10, u64::MAX).unwrap();
+
+        // Dropping the snapshot releases the pinned range. The handle
+        // resolves once its deletion finishes, which also lifts the
+        // admission block.
+        drop(snap);
+        engine.run_background_tasks_until_idle();
+        assert!(handle.wait(Duration::from_secs(5)));
+        assert!(handle.is_done());
+        engine.load_range(ranges[1].clone()).unwrap();
+    }
+
     #[test]
     fn test_delete_range() {
         let delete_range_cf = |cf| {
@@ -664,11 +1266,10 @@ pub mod tests {
 
             let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig {
                 enabled: true,
-                gc_interval: Default::default(),
-                load_evict_interval: Default::default(),
                 soft_limit_threshold: Some(ReadableSize(300)),
                 hard_limit_threshold: Some(ReadableSize(500)),
                 expected_region_size: Some(ReadableSize::mb(20)),
+                ..Default::default()
             }));
             let mem_controller = Arc::new(MemoryController::new(config.clone(), skiplist.clone()));
 
@@ -719,11 +1320,10 @@ pub mod tests {
 
         let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig {
             enabled: true,
-            gc_interval: Default::default(),
-            load_evict_interval: Default::default(),
             soft_limit_threshold: Some(ReadableSize(300)),
             hard_limit_threshold: Some(ReadableSize(500)),
             expected_region_size: Some(ReadableSize::mb(20)),
+            ..Default::default()
         }));
         let mem_controller = Arc::new(MemoryController::new(config.clone(), skiplist.clone()));
 
@@ -758,4 +1358,50 @@ pub mod tests {
         iter.next(guard);
         assert!(!iter.valid());
     }
+
+    #[test]
+    fn test_wait_for_sequence() {
+        use std::time::Duration;
+
+        use engine_traits::{Mutable, WriteBatch, WriteBatchExt};
+
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        engine.new_range(range.clone());
+
+        // Nothing has been applied yet, so the watermark cannot reach any
+        // sequence number.
+        assert_eq!(
+            engine.wait_for_sequence(&range, 5, Duration::from_millis(100)),
+            Err(WaitError::TimedOut)
+        );
+        // A range the engine does not cache can never catch up.
+        let uncached = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        assert_eq!(
+            engine.wait_for_sequence(&uncached, 5, Duration::from_millis(100)),
+            Err(WaitError::NotCached)
+        );
+
+        // A concurrent write unblocks the waiter once the watermark advances.
+        let waiter_engine = engine.clone();
+        let waiter_range = range.clone();
+        let waiter = std::thread::spawn(move || {
+            waiter_engine.wait_for_sequence(&waiter_range, 5, Duration::from_secs(5))
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.put(b"k01", b"val").unwrap();
+        wb.set_range_applied_index(10);
+        wb.set_sequence_number(5).unwrap();
+        wb.write().unwrap();
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+        // Already reached watermarks return immediately.
+        assert_eq!(
+            engine.wait_for_sequence(&range, 5, Duration::from_millis(100)),
+            Ok(())
+        );
+    }
 }
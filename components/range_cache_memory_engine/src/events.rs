@@ -0,0 +1,139 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A bounded engine-wide history of range events, answering "why is this
+//! range not cached right now" without digging through the logs of the
+//! background worker. Loads, evictions and admission refusals are recorded
+//! with timestamps from their existing code paths; the history can be
+//! queried per range or dumped wholesale.
+
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use engine_traits::{CacheRange, FailedReason};
+use parking_lot::Mutex;
+
+// The number of events kept in the engine-wide history.
+const EVENT_HISTORY_CAP: usize = 4096;
+
+// How many events `record_snapshot_failed` scans backwards looking for an
+// aggregate to merge into. Bounding the scan keeps the cost on the read
+// fallback path strictly small; in the worst case a failure starts a new
+// aggregate a bit too early.
+const SNAPSHOT_FAILED_MERGE_SCAN: usize = 128;
+
+/// What happened to the range of a [`RangeEvent`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum RangeEventKind {
+    /// The range was admitted for loading and waits for the apply path to
+    /// schedule the snapshot load.
+    LoadScheduled,
+    /// The background worker began loading the snapshot of the range.
+    LoadStarted,
+    /// The snapshot load completed; `bytes` is the loaded data size as
+    /// charged to the memory controller.
+    LoadFinished { bytes: u64 },
+    /// The range was refused admission or its load was stopped, e.g. by the
+    /// deny list, an overlapping range or the memory limit.
+    LoadRejected { reason: &'static str },
+    /// The eviction of the range was requested. Readers lose access
+    /// immediately, the data is removed later.
+    EvictRequested { reason: &'static str },
+    /// The data of the range was physically removed from the engine.
+    EvictCompleted,
+    /// The requested eviction was rolled back before any data was deleted;
+    /// the range serves reads again.
+    EvictCanceled,
+    /// `count` snapshot attempts overlapping the range failed with `reason`
+    /// in the minute starting at the event timestamp.
+    SnapshotFailed { reason: FailedReason, count: u64 },
+}
+
+/// One entry of the event history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeEvent {
+    pub range: CacheRange,
+    pub kind: RangeEventKind,
+    pub unix_secs: u64,
+}
+
+/// The ring buffer holding the events, shared between the write, read and
+/// background paths. Events are small PODs and recording is one short mutex
+/// critical section, so the overhead is negligible next to the work being
+/// recorded.
+#[derive(Default)]
+pub struct EventHistory {
+    events: Mutex<VecDeque<RangeEvent>>,
+}
+
+impl EventHistory {
+    pub(crate) fn record(&self, range: CacheRange, kind: RangeEventKind) {
+        let mut events = self.events.lock();
+        if events.len() >= EVENT_HISTORY_CAP {
+            events.pop_front();
+        }
+        events.push_back(RangeEvent {
+            range,
+            kind,
+            unix_secs: now_unix_secs(),
+        });
+    }
+
+    /// Records a failed snapshot of the range. Failures of the same range
+    /// with the same reason within the same minute are merged into one event,
+    /// so a hot range that keeps falling back to the disk engine cannot flush
+    /// the rest of the history out of the ring.
+    pub(crate) fn record_snapshot_failed(&self, range: &CacheRange, reason: FailedReason) {
+        let now = now_unix_secs();
+        let mut events = self.events.lock();
+        for e in events.iter_mut().rev().take(SNAPSHOT_FAILED_MERGE_SCAN) {
+            if e.unix_secs / 60 != now / 60 {
+                break;
+            }
+            if e.range != *range {
+                continue;
+            }
+            if let RangeEventKind::SnapshotFailed {
+                reason: recorded,
+                count,
+            } = &mut e.kind
+            {
+                if *recorded == reason {
+                    *count += 1;
+                    return;
+                }
+            }
+        }
+        if events.len() >= EVENT_HISTORY_CAP {
+            events.pop_front();
+        }
+        events.push_back(RangeEvent {
+            range: range.clone(),
+            kind: RangeEventKind::SnapshotFailed { reason, count: 1 },
+            unix_secs: now,
+        });
+    }
+
+    /// Returns the recorded events whose range overlaps `range`, oldest
+    /// first.
+    pub fn events_for_range(&self, range: &CacheRange) -> Vec<RangeEvent> {
+        self.events
+            .lock()
+            .iter()
+            .filter(|e| e.range.overlaps(range))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the whole history, oldest first.
+    pub fn dump(&self) -> Vec<RangeEvent> {
+        self.events.lock().iter().cloned().collect()
+    }
+}
+
+fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
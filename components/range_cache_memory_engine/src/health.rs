@@ -0,0 +1,212 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{range_manager::CachedRangeOverview, RangeCacheEngineConfig, RangeCacheMemoryEngine};
+
+/// A record of one range eviction kept in a small ring buffer for diagnosis,
+/// see [`HealthReport`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvictionRecord {
+    // Hex-encoded range boundaries so the record stays readable in JSON.
+    pub start: String,
+    pub end: String,
+    pub reason: String,
+    pub unix_secs: u64,
+}
+
+impl EvictionRecord {
+    pub(crate) fn new(start: &[u8], end: &[u8], reason: &'static str) -> Self {
+        EvictionRecord {
+            start: hex::encode_upper(start),
+            end: hex::encode_upper(end),
+            reason: reason.to_string(),
+            unix_secs: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+}
+
+/// A consolidated diagnostic snapshot of the range cache engine used for
+/// support bundles, so users do not have to collect logs, metrics screenshots
+/// and configs separately.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// The effective config values at the time the report is collected.
+    pub config: RangeCacheEngineConfig,
+    pub memory_usage: usize,
+    pub soft_limit_threshold: usize,
+    pub hard_limit_threshold: usize,
+    pub cached_ranges: usize,
+    /// The number of cached ranges pinned against automatic eviction.
+    pub pinned_ranges: usize,
+    pub pending_ranges: usize,
+    pub loading_ranges: usize,
+    pub ranges_being_deleted: usize,
+    pub historical_ranges: usize,
+    pub live_snapshots: usize,
+    pub min_snapshot_ts: Option<u64>,
+    pub max_snapshot_ts: Option<u64>,
+    pub queued_loads: usize,
+    pub inflight_loads: usize,
+    /// The NUMA allocation policy in effect on the background workers:
+    /// "interleave" once a worker thread has installed the interleaved
+    /// policy, otherwise "default" (e.g. not requested, single node, or
+    /// set_mempolicy unavailable).
+    pub allocation_policy: String,
+    /// Estimated bytes logically freed by range deletions but not yet
+    /// reclaimed by the crossbeam epoch collector.
+    pub pending_reclaim_bytes: usize,
+    /// `allocated` reported by the jemalloc stats probe minus the
+    /// controller-accounted usage, `None` when the allocator does not expose
+    /// stats. The process-wide number includes everything else in the
+    /// process, so only changes relative to its own baseline are meaningful;
+    /// a growing gap together with a large pending reclaim estimate points
+    /// at epoch GC lagging behind.
+    pub allocator_usage_gap: Option<i64>,
+    /// The last evictions with reasons and timestamps, newest last.
+    pub recent_evictions: Vec<EvictionRecord>,
+}
+
+impl RangeCacheMemoryEngine {
+    /// Collects a diagnostic snapshot of the engine. Only a read lock is
+    /// taken and all summaries are small clones, so this is cheap enough to
+    /// serve from the status server.
+    pub fn health_report(&self) -> HealthReport {
+        let core = self.core().read();
+        let range_manager = core.range_manager();
+        let (live_snapshots, min_snapshot_ts, max_snapshot_ts) = range_manager.snapshot_stats();
+        let load_scheduler = self.bg_worker_manager().load_scheduler();
+        HealthReport {
+            config: self.config().value().clone(),
+            memory_usage: self.memory_controller().mem_usage(),
+            soft_limit_threshold: self.memory_controller().soft_limit_threshold(),
+            hard_limit_threshold: self.config().value().hard_limit_threshold(),
+            cached_ranges: range_manager.ranges().len(),
+            pinned_ranges: range_manager.pinned_ranges_count(),
+            pending_ranges: range_manager.pending_ranges.len(),
+            loading_ranges: range_manager.pending_ranges_loading_data.len(),
+            ranges_being_deleted: range_manager.ranges_being_deleted.len(),
+            historical_ranges: range_manager.historical_ranges_count(),
+            live_snapshots,
+            min_snapshot_ts,
+            max_snapshot_ts,
+            queued_loads: load_scheduler.pending_count(),
+            inflight_loads: load_scheduler.in_flight_count(),
+            allocation_policy: crate::affinity::effective_allocation_policy().to_string(),
+            pending_reclaim_bytes: self.memory_controller().pending_reclaim(),
+            allocator_usage_gap: allocator_usage_gap(self.memory_controller().mem_usage()),
+            recent_evictions: range_manager.recent_evictions().to_vec(),
+        }
+    }
+
+    /// Collects a deterministic overview of every range the engine tracks,
+    /// see `RangeManager::ranges_overview`. Only a read lock is taken and
+    /// the entries are small clones, so this is cheap enough to serve from
+    /// the status server.
+    pub fn ranges_overview(&self) -> Vec<CachedRangeOverview> {
+        self.core().read().range_manager().ranges_overview()
+    }
+}
+
+fn allocator_usage_gap(accounted: usize) -> Option<i64> {
+    let stats = tikv_alloc::fetch_stats().ok().flatten()?;
+    let allocated = stats.iter().find(|(name, _)| *name == "allocated")?.1;
+    Some(allocated as i64 - accounted as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use engine_traits::{CacheRange, RangeCacheEngine};
+    use tikv_util::config::VersionTrack;
+
+    use super::*;
+    use crate::{
+        CachedRangeState, RangeCacheEngineConfig, RangeCacheEngineContext, RangeCacheMemoryEngine,
+    };
+
+    #[test]
+    fn test_health_report() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        let r2 = CacheRange::new(b"k10".to_vec(), b"k20".to_vec());
+        engine.new_range(r1.clone());
+        engine.new_range(r2.clone());
+        let snap = engine.snapshot(r1.clone(), 10, u64::MAX).unwrap();
+        engine.evict_range(&r2);
+
+        let report = engine.health_report();
+        assert_eq!(report.config, RangeCacheEngineConfig::config_for_test());
+        assert_eq!(report.cached_ranges, 1);
+        assert_eq!(report.live_snapshots, 1);
+        assert_eq!(report.min_snapshot_ts, Some(10));
+        assert_eq!(report.max_snapshot_ts, Some(10));
+        assert_eq!(report.recent_evictions.len(), 1);
+        assert_eq!(report.recent_evictions[0].reason, "evict-api");
+        assert_eq!(report.recent_evictions[0].start, hex::encode_upper(b"k10"));
+        assert_eq!(report.pending_reclaim_bytes, 0);
+        // Whether the allocator exposes stats depends on the build features,
+        // so only the serde round-trip below covers `allocator_usage_gap`.
+        // Whether interleaving is in effect depends on the machine and on
+        // other tests in the process, but the field is always one of the two.
+        assert!(["default", "interleave"].contains(&report.allocation_policy.as_str()));
+
+        // The report must round-trip through JSON for support bundles.
+        let json = serde_json::to_string(&report).unwrap();
+        let decoded: HealthReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report, decoded);
+        drop(snap);
+    }
+
+    #[test]
+    fn test_ranges_overview() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        let r2 = CacheRange::new(b"k10".to_vec(), b"k20".to_vec());
+        engine.new_range(r1.clone());
+        engine.new_range(r2.clone());
+        let snap1 = engine.snapshot(r1.clone(), 10, u64::MAX).unwrap();
+        // The snapshot pins the evicted range in `historical_ranges` so that
+        // the background deletion cannot race with the assertions below.
+        let snap2 = engine.snapshot(r2.clone(), 12, u64::MAX).unwrap();
+        engine.evict_range(&r2);
+
+        let overview = engine.ranges_overview();
+        assert_eq!(overview.len(), 3);
+        assert_eq!(overview[0].start, hex::encode_upper(b"k00"));
+        assert_eq!(overview[0].end, hex::encode_upper(b"k10"));
+        assert_eq!(overview[0].state, CachedRangeState::Active);
+        assert!(overview[0].id > 0);
+        assert_eq!(overview[0].snapshots, 1);
+        assert_eq!(overview[0].min_snapshot_ts, Some(10));
+        assert!(!overview[0].pinned);
+        assert!(overview[0].last_access > 0);
+        // The pending deletion keeps no meta, so its entry carries the
+        // sentinel values; the historical entry still reports the snapshot
+        // blocking it.
+        assert_eq!(overview[1].start, hex::encode_upper(b"k10"));
+        assert_eq!(overview[1].state, CachedRangeState::BeingDeleted);
+        assert_eq!(overview[1].id, 0);
+        assert_eq!(overview[1].snapshots, 0);
+        assert_eq!(overview[2].start, hex::encode_upper(b"k10"));
+        assert_eq!(overview[2].state, CachedRangeState::Historical);
+        assert_eq!(overview[2].snapshots, 1);
+        assert_eq!(overview[2].min_snapshot_ts, Some(12));
+
+        // The overview must round-trip through JSON for the status server.
+        let json = serde_json::to_string(&overview).unwrap();
+        let decoded: Vec<CachedRangeOverview> = serde_json::from_str(&json).unwrap();
+        assert_eq!(overview, decoded);
+        drop(snap1);
+        drop(snap2);
+    }
+}
@@ -6,11 +6,15 @@ use std::{
     sync::Arc,
 };
 
+use api_version::{ApiV2, KeyMode, KvFormat};
 use bytes::{BufMut, Bytes};
 use engine_traits::CacheRange;
 use txn_types::{Key, TimeStamp};
 
-use crate::{memory_controller::MemoryController, write_batch::MEM_CONTROLLER_OVERHEAD};
+use crate::{
+    dedup::ValueDedupTable, memory_controller::MemoryController,
+    statistics::record_comparator_call, write_batch::MEM_CONTROLLER_OVERHEAD,
+};
 
 /// The internal bytes used in the skiplist. See comments on
 /// `encode_internal_bytes`.
@@ -20,15 +24,25 @@ pub struct InternalBytes {
     // memory_limiter **must** be set when used as key/values being inserted
     // into skiplist as keys/values.
     memory_controller: Option<Arc<MemoryController>>,
+    // Set when `bytes` is shared through the value dedup table; the table
+    // reference is released on drop. See the `dedup` module.
+    dedup: Option<(Arc<ValueDedupTable>, u128)>,
 }
 
 impl Drop for InternalBytes {
     fn drop(&mut self) {
-        let size = InternalBytes::memory_size_required(self.bytes.len());
+        // A shared value's bytes are accounted once across all references,
+        // so only the drop that removes the table entry releases them; the
+        // others release their handle overhead only.
+        let last_shared_ref = self.dedup.take().map(|(table, hash)| table.release(hash));
         let controller = self.memory_controller.take();
         if let Some(controller) = controller {
-            // Reclaim the memory though the bytes have not been drop. This time
-            // gap should not matter.
+            let size = match last_shared_ref {
+                Some(false) => MEM_CONTROLLER_OVERHEAD,
+                // Reclaim the memory though the bytes have not been drop. This
+                // time gap should not matter.
+                _ => InternalBytes::memory_size_required(self.bytes.len()),
+            };
             controller.release(size);
         }
     }
@@ -39,6 +53,7 @@ impl InternalBytes {
         Self {
             bytes,
             memory_controller: None,
+            dedup: None,
         }
     }
 
@@ -46,6 +61,7 @@ impl InternalBytes {
         Self {
             bytes: Bytes::from(vec),
             memory_controller: None,
+            dedup: None,
         }
     }
 
@@ -57,6 +73,10 @@ impl InternalBytes {
         self.memory_controller = Some(controller);
     }
 
+    pub(crate) fn set_dedup_handle(&mut self, table: Arc<ValueDedupTable>, hash: u128) {
+        self.dedup = Some((table, hash));
+    }
+
     pub fn clone_bytes(&self) -> Bytes {
         self.bytes.clone()
     }
@@ -97,6 +117,9 @@ impl Eq for InternalBytes {}
 
 impl Ord for InternalBytes {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
+        // Every comparison the skiplists perform funnels through here, so
+        // this single counter covers the insert, seek and scan paths alike.
+        record_comparator_call();
         let k1 = &self.bytes[..self.bytes.len() - ENC_KEY_SEQ_LENGTH];
         let k2 = &other.bytes[..other.bytes.len() - ENC_KEY_SEQ_LENGTH];
         let c = k1.cmp(k2);
@@ -257,6 +280,17 @@ pub fn encode_key_for_boundary_without_mvcc(range: &CacheRange) -> (InternalByte
     (encoded_start, encoded_end)
 }
 
+/// Whether `key`, a data key as stored in the engine (with the raftstore 'z'
+/// prefix when it comes from a region boundary), belongs to an API v2
+/// raw-mode keyspace. Raw keys carry no mvcc timestamp suffix, so both the
+/// prefix extraction of prefix seeks and the admission policy must treat
+/// them differently from txn-mode keys.
+#[inline]
+pub fn is_raw_mode_data_key(key: &[u8]) -> bool {
+    let origin = key.strip_prefix(keys::DATA_PREFIX_KEY).unwrap_or(key);
+    ApiV2::parse_key_mode(origin) == KeyMode::Raw
+}
+
 // mvcc_prefix is already mem-comparison encoded.
 #[inline]
 pub fn encoding_for_filter(mvcc_prefix: &[u8], start_ts: TimeStamp) -> InternalBytes {
@@ -288,9 +322,13 @@ pub fn construct_value(i: u64, j: u64) -> String {
 #[cfg(test)]
 mod tests {
     use bytes::BufMut;
+    use proptest::prelude::*;
 
     use super::*;
-    use crate::keys::{encode_key, ValueType};
+    use crate::{
+        keys::{encode_key, ValueType},
+        read::MAX_SEQUENCE_NUMBER,
+    };
 
     fn construct_key(i: u64, mvcc: u64) -> Vec<u8> {
         let k = format!("zk{:08}", i);
@@ -347,4 +385,144 @@ mod tests {
         seq_bytes.push(0);
         assert_eq!(u64::from_le_bytes(seq_bytes.try_into().unwrap()), 7);
     }
+
+    #[test]
+    fn test_max_sequence_number_sentinel() {
+        // `u64::MAX` is accepted as a seek sentinel. It encodes to the same
+        // bytes as `MAX_SEQUENCE_NUMBER`, the largest sequence that
+        // round-trips, so a sentinel seek key sorts before every entry of the
+        // same user key.
+        let sentinel = encode_seek_key(b"k", u64::MAX);
+        let max = encode_seek_key(b"k", MAX_SEQUENCE_NUMBER);
+        assert_eq!(sentinel.as_slice(), max.as_slice());
+        assert_eq!(decode_key(sentinel.as_slice()).sequence, MAX_SEQUENCE_NUMBER);
+        for seq in [0, 1, MAX_SEQUENCE_NUMBER] {
+            for v_type in [ValueType::Deletion, ValueType::Value] {
+                assert!(sentinel <= encode_key(b"k", seq, v_type));
+            }
+        }
+
+        // An empty user key decodes back empty and sorts before any non-empty
+        // key.
+        let empty = encode_seek_key(b"", u64::MAX);
+        assert!(decode_key(empty.as_slice()).user_key.is_empty());
+        assert!(empty < sentinel);
+    }
+
+    type RawEntry = (Vec<u8>, u64, ValueType);
+
+    // Mix a tiny alphabet in so that random entries frequently share a user
+    // key, which is where the sequence and value type tie-breaking matters.
+    fn arb_user_key() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            prop::collection::vec(0u8..2, 0..3),
+            prop::collection::vec(prop::num::u8::ANY, 0..8),
+        ]
+    }
+
+    fn arb_sequence() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            0u64..4,
+            Just(MAX_SEQUENCE_NUMBER),
+            0..=MAX_SEQUENCE_NUMBER,
+        ]
+    }
+
+    fn arb_entry() -> impl Strategy<Value = RawEntry> {
+        (
+            arb_user_key(),
+            arb_sequence(),
+            prop_oneof![Just(ValueType::Deletion), Just(ValueType::Value)],
+        )
+    }
+
+    fn encode_entry((key, seq, v_type): &RawEntry) -> InternalBytes {
+        encode_key(key, *seq, *v_type)
+    }
+
+    // The order the skiplist relies on: user key ascending, then sequence
+    // descending, then `ValueType` descending, matching RocksDB's internal
+    // key comparator.
+    fn reference_cmp(a: &RawEntry, b: &RawEntry) -> Ordering {
+        a.0.cmp(&b.0)
+            .then(b.1.cmp(&a.1))
+            .then((b.2 as u8).cmp(&(a.2 as u8)))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn test_encode_decode_round_trip((key, seq, v_type) in arb_entry()) {
+            let encoded = encode_key(&key, seq, v_type);
+            let decoded = decode_key(encoded.as_slice());
+            prop_assert_eq!(decoded.user_key, key.as_slice());
+            prop_assert_eq!(decoded.sequence, seq);
+            prop_assert_eq!(decoded.v_type as u8, v_type as u8);
+        }
+
+        // Checking every pair against a reference total order implies the
+        // comparator is reflexive, antisymmetric and transitive, and that
+        // `Ordering::Equal` coincides with byte equality.
+        #[test]
+        fn test_comparator_consistent_with_reference_order(
+            entries in prop::collection::vec(arb_entry(), 2..6)
+        ) {
+            let encoded: Vec<_> = entries.iter().map(encode_entry).collect();
+            for (i, a) in entries.iter().enumerate() {
+                for (j, b) in entries.iter().enumerate() {
+                    let expected = reference_cmp(a, b);
+                    prop_assert_eq!(encoded[i].cmp(&encoded[j]), expected);
+                    prop_assert_eq!(encoded[i] == encoded[j], expected == Ordering::Equal);
+                }
+            }
+        }
+
+        // A seek key must land on the first entry of its user key whose
+        // sequence is visible, i.e. compare <= every entry of the same user
+        // key with sequence <= s and > everything before that.
+        #[test]
+        fn test_seek_key_bounds(
+            key in arb_user_key(),
+            seq in arb_sequence(),
+            entry in arb_entry(),
+        ) {
+            let seek_key = encode_seek_key(&key, seq);
+            let entry_key = encode_entry(&entry);
+            match key.cmp(&entry.0) {
+                Ordering::Less => prop_assert!(seek_key < entry_key),
+                Ordering::Greater => prop_assert!(seek_key > entry_key),
+                Ordering::Equal => {
+                    if entry.1 <= seq {
+                        prop_assert!(seek_key <= entry_key);
+                    } else {
+                        prop_assert!(seek_key > entry_key);
+                    }
+                }
+            }
+        }
+
+        // The mirror image for seek_for_prev: compare >= every entry of the
+        // same user key with sequence >= s and < everything after that.
+        #[test]
+        fn test_seek_for_prev_key_bounds(
+            key in arb_user_key(),
+            seq in arb_sequence(),
+            entry in arb_entry(),
+        ) {
+            let seek_key = encode_seek_for_prev_key(&key, seq);
+            let entry_key = encode_entry(&entry);
+            match key.cmp(&entry.0) {
+                Ordering::Less => prop_assert!(seek_key < entry_key),
+                Ordering::Greater => prop_assert!(seek_key > entry_key),
+                Ordering::Equal => {
+                    if entry.1 >= seq {
+                        prop_assert!(seek_key >= entry_key);
+                    } else {
+                        prop_assert!(seek_key < entry_key);
+                    }
+                }
+            }
+        }
+    }
 }
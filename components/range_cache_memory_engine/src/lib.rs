@@ -12,32 +12,59 @@ use online_config::OnlineConfig;
 use pd_client::PdClient;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tikv_util::config::{ReadableDuration, ReadableSize, VersionTrack};
+use tikv_util::{
+    config::{ReadableDuration, ReadableSize, VersionTrack},
+    warn,
+};
 
+mod affinity;
 mod background;
+mod checksum;
+mod compression;
 pub mod config;
+mod dedup;
 mod engine;
+mod events;
+mod health;
 mod keys;
+mod load_scheduler;
 mod memory_controller;
 mod metrics;
 mod perf_context;
+mod provenance;
 mod range_manager;
 mod range_stats;
 mod read;
 mod region_label;
+mod replay;
 mod statistics;
 pub mod test_util;
 mod write_batch;
 
-pub use background::{BackgroundRunner, BackgroundTask, GcTask};
-pub use engine::{RangeCacheMemoryEngine, SkiplistHandle};
+pub use background::{BackgroundRunner, BackgroundTask, GcStats, GcTask};
+pub use checksum::RangeChecksums;
+pub use compression::ValueCompression;
+pub use engine::{BulkEvictHandle, RangeCacheMemoryEngine, SkiplistHandle, WaitError};
+pub use events::{EventHistory, RangeEvent, RangeEventKind};
+pub use health::{EvictionRecord, HealthReport};
 pub use keys::{
     decode_key, encode_key_for_boundary_without_mvcc, encoding_for_filter, InternalBytes,
     InternalKey, ValueType,
 };
+pub use load_scheduler::{LoadPriority, LoadScheduler};
 pub use metrics::flush_range_cache_engine_statistics;
-pub use range_manager::RangeCacheStatus;
-pub use statistics::Statistics as RangeCacheMemoryEngineStatistics;
+pub use provenance::{ProvenanceRecord, RangeProvenance};
+pub use range_manager::{
+    CachedRangeOverview, CachedRangeState, CancelError, PinFailedReason, RangeCacheStatus,
+};
+pub use read::{MultiRangeItem, MultiRangeIterator};
+pub use replay::{
+    find_first_divergence, read_replay_log, replay_and_compare, replay_records,
+    wait_and_find_divergence, Divergence, ReplayRecord, ReplayRecorder,
+};
+pub use statistics::{
+    IterReadStats, LocalStatsSink, Statistics as RangeCacheMemoryEngineStatistics,
+};
 use txn_types::TimeStamp;
 pub use write_batch::RangeCacheWriteBatch;
 
@@ -56,6 +83,129 @@ pub struct RangeCacheEngineConfig {
     pub soft_limit_threshold: Option<ReadableSize>,
     pub hard_limit_threshold: Option<ReadableSize>,
     pub expected_region_size: Option<ReadableSize>,
+    // The number of region loads that are allowed to run concurrently in the
+    // background load workers.
+    pub load_concurrency: usize,
+    // Whether to skip caching write cf versions below the last gc safe point
+    // that a disk compaction filter gc would drop anyway.
+    pub gc_aware_load: bool,
+    // If set, cached ranges that have not been read for this duration are
+    // evicted in the background to free memory for hotter ranges.
+    pub range_ttl: Option<ReadableDuration>,
+    // Bounds how long a single range load may hold one disk snapshot. A long
+    // load pins WAL and SST files through its snapshot, inflating disk usage
+    // and compaction debt on write-heavy stores; when the snapshot gets older
+    // than this, the load drops it, takes a fresh one and resumes from the
+    // last loaded key instead of restarting. Unset disables the bound.
+    pub load_snapshot_max_age: Option<ReadableDuration>,
+    // The number of raw skiplist entries an iterator prefetches in one batch
+    // once it detects a sequential forward scan. 0 disables prefetching.
+    pub iterator_prefetch_size: usize,
+    // CPU cores the background workers (range loads, gc, deletions) are
+    // pinned to, in cpuset list format, e.g. "0,2,8-11". Empty disables
+    // pinning.
+    pub background_worker_cpu_set: String,
+    // Whether the background workers allocate with an interleaved NUMA
+    // policy, spreading the skiplist pages of loaded ranges across nodes
+    // instead of concentrating them on the loading thread's node. Best
+    // effort: kept at the default first-touch policy where set_mempolicy
+    // is unavailable.
+    pub numa_interleave_allocations: bool,
+    // The max fraction of the soft limit that pinned ranges may occupy, each
+    // pin estimated as one expected region size. Further pins are refused
+    // beyond it, so the memory pressure eviction always has unpinned ranges
+    // to reclaim from.
+    pub pinned_range_capacity_ratio: f64,
+    // If set, unexpected errors from reads on an acquired range cache
+    // snapshot (e.g. decode failures or boundary violations) fail the request
+    // instead of silently falling back to the disk engine. Expected fallbacks
+    // such as a range not being cached are unaffected. Meant for correctness
+    // validation in staging clusters; keep disabled in production.
+    pub strict_read_errors: bool,
+    // An evicted range whose deletion has been blocked by undropped snapshots
+    // for this long is reported as stuck by the background worker. 0 disables
+    // the check.
+    pub stuck_eviction_threshold: ReadableDuration,
+    // If set, an eviction stuck for twice `stuck_eviction_threshold` is
+    // force-completed: the leaked snapshots are poisoned so their reads fail
+    // and the range's memory is reclaimed. Disabled by default because it
+    // turns a leak into read errors for whoever still holds the snapshot.
+    pub force_complete_stuck_evictions: bool,
+    // Byte budget for pre-warming the disk engine's block cache when a range
+    // is evicted: the background worker reads the evicted range back from the
+    // disk engine with cache-filling iterators, so the reads that stop being
+    // served from the cache do not all land on cold blocks. At most this many
+    // bytes are read per eviction; 0 disables pre-warming. Evictions caused
+    // by memory pressure skip the pass regardless, and so do evictions of
+    // ranges that have no read traffic to warm for (ttl, load-evict).
+    pub evict_prewarm_budget: ReadableSize,
+    // Read rate limit for the eviction pre-warm pass, in bytes per second,
+    // keeping it from competing with foreground disk reads. 0 means
+    // unlimited.
+    pub evict_prewarm_rate_limit: ReadableSize,
+    // Memory watermarks for write path backpressure. Above the soft
+    // watermark, writes to unpinned ranges are not buffered anymore and the
+    // ranges are evicted (so reads cannot miss the skipped writes), and
+    // pending range loads are paused until the usage drops below the
+    // watermark again. Above the hard watermark, an eviction selection pass
+    // is additionally scheduled right from the write path; it is only
+    // enqueued there so that the apply latency stays bounded. Unset means no
+    // backpressure, which keeps the reactive hard limit behavior only.
+    pub write_pressure_soft_watermark: Option<ReadableSize>,
+    pub write_pressure_hard_watermark: Option<ReadableSize>,
+    // Foreground read latency target for pacing background range deletions.
+    // Deletion batches and foreground reads contend on the same skiplist
+    // nodes, so when the recent p99 seek latency exceeds this, the delete
+    // worker shrinks its per-batch entry budget and sleeps between batches
+    // (AIMD), within bounds that keep deletions progressing; see
+    // `DeletionPacer`. Unset deletes each range in one uninterrupted pass.
+    pub delete_read_latency_target: Option<ReadableDuration>,
+    // If the estimate of bytes logically freed by range deletions but not
+    // yet reclaimed by the crossbeam epoch collector exceeds this, the
+    // delete worker aggressively drives epoch advancement and pauses new
+    // range loads until the backlog drains. Unset disables the watchdog.
+    pub reclaim_lag_threshold: Option<ReadableSize>,
+    // Whether ranges whose keys belong to an API v2 raw-mode keyspace may be
+    // admitted into the cache. Disabled by default until raw support (ttl,
+    // causal timestamps) is validated; load requests for such ranges are
+    // refused and their reads keep going to the disk engine.
+    pub cache_raw_keyspaces: bool,
+    // Whether to maintain an incremental checksum pair per cached range: the
+    // cache side is updated by the entries applied to the skiplists, the
+    // shadow disk side by the identical entries the hybrid engine hands to
+    // the disk engine. A periodic background pass compares the two sides,
+    // giving a cheap continuous verification of the mirrored write path. See
+    // the `checksum` module.
+    pub checksum_verification: bool,
+    // Whether a range whose checksums diverge is evicted in addition to
+    // being counted and logged.
+    pub evict_on_checksum_mismatch: bool,
+    // Whether consumed write batches record which raft apply (index and
+    // term) produced their entries, per cached range, so that a suspicious
+    // cached entry can be traced back to the apply that wrote it. See the
+    // `provenance` module. Off by default because the sidecar costs memory
+    // per cached range.
+    pub provenance_tracking: bool,
+    // Transparent compression for the values stored in the cached skiplists:
+    // values of at least `value-compression-threshold` bytes are compressed
+    // by the write and load paths and decompressed on access, stretching the
+    // memory limits at a per-access cpu cost. Takes effect at startup only,
+    // because the cache starts empty and all values stored during one
+    // process run must agree on the framing. See the `compression` module.
+    #[online_config(skip)]
+    pub value_compression: ValueCompression,
+    // The minimum value size for `value-compression` to kick in. Smaller
+    // values are stored uncompressed: compressing them saves little and
+    // costs cpu on every read.
+    pub value_compression_threshold: ReadableSize,
+    // Content-addressed deduplication of the values stored in the cached
+    // skiplists: a value whose bytes equal an already cached value is stored
+    // as a shared handle instead of a second copy, so workloads that rewrite
+    // rows with mostly identical content keep one copy per distinct value.
+    // The limit caps the number of distinct values tracked; once it is
+    // reached, further values are stored directly. 0 disables deduplication.
+    // See the `dedup` module.
+    pub value_dedup_table_limit: usize,
 }
 
 impl Default for RangeCacheEngineConfig {
@@ -70,10 +220,39 @@ impl Default for RangeCacheEngineConfig {
             soft_limit_threshold: None,
             hard_limit_threshold: None,
             expected_region_size: None,
+            load_concurrency: 1,
+            gc_aware_load: true,
+            range_ttl: None,
+            load_snapshot_max_age: None,
+            iterator_prefetch_size: 32,
+            background_worker_cpu_set: String::new(),
+            numa_interleave_allocations: false,
+            pinned_range_capacity_ratio: 0.5,
+            strict_read_errors: false,
+            stuck_eviction_threshold: ReadableDuration::minutes(10),
+            force_complete_stuck_evictions: false,
+            evict_prewarm_budget: ReadableSize(0),
+            evict_prewarm_rate_limit: ReadableSize::mb(64),
+            write_pressure_soft_watermark: None,
+            write_pressure_hard_watermark: None,
+            delete_read_latency_target: None,
+            reclaim_lag_threshold: None,
+            cache_raw_keyspaces: false,
+            checksum_verification: false,
+            evict_on_checksum_mismatch: false,
+            provenance_tracking: false,
+            value_compression: ValueCompression::None,
+            value_compression_threshold: ReadableSize::kb(1),
+            value_dedup_table_limit: 0,
         }
     }
 }
 
+// Floors for the clampable tuning knobs, see
+// `RangeCacheEngineConfig::sanitize`.
+const MIN_ITERATOR_PREFETCH_SIZE: usize = 4;
+const MIN_LOAD_SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(1);
+
 impl RangeCacheEngineConfig {
     pub fn validate(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if !self.enabled {
@@ -83,6 +262,12 @@ impl RangeCacheEngineConfig {
         Ok(self.sanitize()?)
     }
 
+    /// The centralized checks of the numeric and duration fields, applied at
+    /// startup and to the candidate config of every online update.
+    /// Impossible combinations are rejected naming the offending fields and
+    /// values; merely-suboptimal values are clamped with a warning, so the
+    /// tracked config (and with it the health report) always holds the
+    /// effective post-validation values.
     pub fn sanitize(&mut self) -> Result<(), Error> {
         if self.soft_limit_threshold.is_none() || self.hard_limit_threshold.is_none() {
             return Err(Error::InvalidArgument(
@@ -100,6 +285,155 @@ impl RangeCacheEngineConfig {
             )));
         }
 
+        let hard_limit = self.hard_limit_threshold.unwrap();
+        if (hard_limit.0 as usize) < self.expected_region_size() {
+            return Err(Error::InvalidArgument(format!(
+                "hard-limit-threshold {} is smaller than expected-region-size {}, the cache \
+                 could not hold a single region",
+                hard_limit,
+                ReadableSize(self.expected_region_size() as u64)
+            )));
+        }
+
+        if self.load_concurrency == 0 {
+            return Err(Error::InvalidArgument(
+                "load-concurrency is 0, at least one concurrent load is required".to_string(),
+            ));
+        }
+
+        if self.gc_interval.0.is_zero() {
+            return Err(Error::InvalidArgument(
+                "gc-interval must be positive".to_string(),
+            ));
+        }
+
+        if self.load_evict_interval.0.is_zero() {
+            return Err(Error::InvalidArgument(
+                "load-evict-interval must be positive".to_string(),
+            ));
+        }
+
+        if let Some(ttl) = self.range_ttl
+            && ttl.0 < background::BACKGROUND_TICK_INTERVAL
+        {
+            return Err(Error::InvalidArgument(format!(
+                "range-ttl {} is shorter than the background tick interval {} that enforces it",
+                ttl,
+                ReadableDuration(background::BACKGROUND_TICK_INTERVAL)
+            )));
+        }
+
+        if let Some(age) = self.load_snapshot_max_age
+            && age.0.is_zero()
+        {
+            return Err(Error::InvalidArgument(
+                "load-snapshot-max-age is 0; unset it to disable the bound instead".to_string(),
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&self.pinned_range_capacity_ratio) {
+            return Err(Error::InvalidArgument(format!(
+                "pinned-range-capacity-ratio {} not in [0.0, 1.0]",
+                self.pinned_range_capacity_ratio
+            )));
+        }
+
+        if let Err(e) = affinity::parse_cpu_set(&self.background_worker_cpu_set) {
+            return Err(Error::InvalidArgument(format!(
+                "invalid background-worker-cpu-set: {}",
+                e
+            )));
+        }
+
+        if self.force_complete_stuck_evictions && self.stuck_eviction_threshold.0.is_zero() {
+            return Err(Error::InvalidArgument(
+                "force-complete-stuck-evictions requires a non-zero stuck-eviction-threshold"
+                    .to_string(),
+            ));
+        }
+
+        if let (Some(soft), Some(hard)) = (
+            self.write_pressure_soft_watermark,
+            self.write_pressure_hard_watermark,
+        ) && soft >= hard
+        {
+            return Err(Error::InvalidArgument(format!(
+                "write-pressure-soft-watermark {:?} is larger or equal to \
+                 write-pressure-hard-watermark {:?}",
+                soft, hard
+            )));
+        }
+
+        for (name, watermark) in [
+            (
+                "write-pressure-soft-watermark",
+                self.write_pressure_soft_watermark,
+            ),
+            (
+                "write-pressure-hard-watermark",
+                self.write_pressure_hard_watermark,
+            ),
+        ] {
+            if let Some(watermark) = watermark
+                && watermark >= hard_limit
+            {
+                return Err(Error::InvalidArgument(format!(
+                    "{} {} is not below hard-limit-threshold {}, the backpressure could never \
+                     engage before the hard limit",
+                    name, watermark, hard_limit
+                )));
+            }
+        }
+
+        if let Some(target) = self.delete_read_latency_target
+            && target.0.is_zero()
+        {
+            return Err(Error::InvalidArgument(
+                "delete-read-latency-target is 0; unset it to delete without pacing instead"
+                    .to_string(),
+            ));
+        }
+
+        if let Some(lag) = self.reclaim_lag_threshold
+            && lag.0 == 0
+        {
+            return Err(Error::InvalidArgument(
+                "reclaim-lag-threshold is 0; unset it to disable the reclaim watchdog instead"
+                    .to_string(),
+            ));
+        }
+
+        if self.value_compression.is_enabled() && self.value_compression_threshold > hard_limit {
+            return Err(Error::InvalidArgument(format!(
+                "value-compression-threshold {} is larger than hard-limit-threshold {}, no \
+                 cached value could ever qualify",
+                self.value_compression_threshold, hard_limit
+            )));
+        }
+
+        // Suboptimal values below are clamped instead of rejected.
+        if self.iterator_prefetch_size > 0
+            && self.iterator_prefetch_size < MIN_ITERATOR_PREFETCH_SIZE
+        {
+            warn!(
+                "iterator-prefetch-size is below the floor, clamping";
+                "configured" => self.iterator_prefetch_size,
+                "floor" => MIN_ITERATOR_PREFETCH_SIZE,
+            );
+            self.iterator_prefetch_size = MIN_ITERATOR_PREFETCH_SIZE;
+        }
+
+        if let Some(age) = self.load_snapshot_max_age
+            && age.0 < MIN_LOAD_SNAPSHOT_MAX_AGE
+        {
+            warn!(
+                "load-snapshot-max-age is below the floor, clamping";
+                "configured" => %age,
+                "floor" => %ReadableDuration(MIN_LOAD_SNAPSHOT_MAX_AGE),
+            );
+            self.load_snapshot_max_age = Some(ReadableDuration(MIN_LOAD_SNAPSHOT_MAX_AGE));
+        }
+
         Ok(())
     }
 
@@ -111,6 +445,10 @@ impl RangeCacheEngineConfig {
         self.hard_limit_threshold.map_or(0, |r| r.0 as usize)
     }
 
+    pub fn load_concurrency(&self) -> usize {
+        usize::max(self.load_concurrency, 1)
+    }
+
     pub fn expected_region_size(&self) -> usize {
         self.expected_region_size.map_or(
             raftstore::coprocessor::config::SPLIT_SIZE.0 as usize,
@@ -118,6 +456,21 @@ impl RangeCacheEngineConfig {
         )
     }
 
+    pub fn write_pressure_soft_watermark(&self) -> usize {
+        self.write_pressure_soft_watermark
+            .map_or(usize::MAX, |r| r.0 as usize)
+    }
+
+    pub fn write_pressure_hard_watermark(&self) -> usize {
+        self.write_pressure_hard_watermark
+            .map_or(usize::MAX, |r| r.0 as usize)
+    }
+
+    pub fn reclaim_lag_threshold(&self) -> usize {
+        self.reclaim_lag_threshold
+            .map_or(usize::MAX, |r| r.0 as usize)
+    }
+
     pub fn config_for_test() -> RangeCacheEngineConfig {
         RangeCacheEngineConfig {
             enabled: true,
@@ -127,6 +480,30 @@ impl RangeCacheEngineConfig {
             soft_limit_threshold: Some(ReadableSize::gb(1)),
             hard_limit_threshold: Some(ReadableSize::gb(2)),
             expected_region_size: Some(ReadableSize::mb(20)),
+            load_concurrency: 1,
+            gc_aware_load: true,
+            range_ttl: None,
+            load_snapshot_max_age: None,
+            iterator_prefetch_size: 32,
+            background_worker_cpu_set: String::new(),
+            numa_interleave_allocations: false,
+            pinned_range_capacity_ratio: 0.5,
+            strict_read_errors: false,
+            stuck_eviction_threshold: ReadableDuration::minutes(10),
+            force_complete_stuck_evictions: false,
+            evict_prewarm_budget: ReadableSize(0),
+            evict_prewarm_rate_limit: ReadableSize::mb(64),
+            write_pressure_soft_watermark: None,
+            write_pressure_hard_watermark: None,
+            delete_read_latency_target: None,
+            reclaim_lag_threshold: None,
+            cache_raw_keyspaces: false,
+            checksum_verification: false,
+            evict_on_checksum_mismatch: false,
+            provenance_tracking: false,
+            value_compression: ValueCompression::None,
+            value_compression_threshold: ReadableSize::kb(1),
+            value_dedup_table_limit: 0,
         }
     }
 }
@@ -169,3 +546,142 @@ impl RangeCacheEngineContext {
         self.statistics.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_rejects_impossible_combinations() {
+        let cases: Vec<(&str, fn(&mut RangeCacheEngineConfig), &str)> = vec![
+            (
+                "limits not set",
+                |cfg| cfg.soft_limit_threshold = None,
+                "soft-limit-threshold or hard-limit-threshold not set",
+            ),
+            (
+                "soft limit above hard limit",
+                |cfg| cfg.soft_limit_threshold = Some(ReadableSize::gb(3)),
+                "is larger or equal to hard-limit-threshold",
+            ),
+            (
+                "capacity below one region",
+                |cfg| {
+                    cfg.soft_limit_threshold = Some(ReadableSize::mb(5));
+                    cfg.hard_limit_threshold = Some(ReadableSize::mb(10));
+                },
+                "the cache could not hold a single region",
+            ),
+            (
+                "no load concurrency",
+                |cfg| cfg.load_concurrency = 0,
+                "load-concurrency is 0",
+            ),
+            (
+                "zero gc interval",
+                |cfg| cfg.gc_interval = ReadableDuration(Duration::ZERO),
+                "gc-interval must be positive",
+            ),
+            (
+                "zero load evict interval",
+                |cfg| cfg.load_evict_interval = ReadableDuration(Duration::ZERO),
+                "load-evict-interval must be positive",
+            ),
+            (
+                "ttl below the background tick",
+                |cfg| cfg.range_ttl = Some(ReadableDuration(Duration::from_millis(500))),
+                "is shorter than the background tick interval",
+            ),
+            (
+                "zero snapshot age bound",
+                |cfg| cfg.load_snapshot_max_age = Some(ReadableDuration(Duration::ZERO)),
+                "load-snapshot-max-age is 0",
+            ),
+            (
+                "pin ratio out of range",
+                |cfg| cfg.pinned_range_capacity_ratio = 1.5,
+                "not in [0.0, 1.0]",
+            ),
+            (
+                "malformed cpu set",
+                |cfg| cfg.background_worker_cpu_set = "8-".to_string(),
+                "invalid background-worker-cpu-set",
+            ),
+            (
+                "force-complete without stuck threshold",
+                |cfg| {
+                    cfg.force_complete_stuck_evictions = true;
+                    cfg.stuck_eviction_threshold = ReadableDuration(Duration::ZERO);
+                },
+                "requires a non-zero stuck-eviction-threshold",
+            ),
+            (
+                "soft watermark above hard watermark",
+                |cfg| {
+                    cfg.write_pressure_soft_watermark = Some(ReadableSize::mb(800));
+                    cfg.write_pressure_hard_watermark = Some(ReadableSize::mb(700));
+                },
+                "write-pressure-soft-watermark",
+            ),
+            (
+                "hard watermark above capacity",
+                |cfg| cfg.write_pressure_hard_watermark = Some(ReadableSize::gb(3)),
+                "is not below hard-limit-threshold",
+            ),
+            (
+                "zero deletion latency target",
+                |cfg| cfg.delete_read_latency_target = Some(ReadableDuration(Duration::ZERO)),
+                "delete-read-latency-target is 0",
+            ),
+            (
+                "zero reclaim lag threshold",
+                |cfg| cfg.reclaim_lag_threshold = Some(ReadableSize(0)),
+                "reclaim-lag-threshold is 0",
+            ),
+            (
+                "compression threshold above capacity",
+                |cfg| {
+                    cfg.value_compression = ValueCompression::Lz4;
+                    cfg.value_compression_threshold = ReadableSize::gb(4);
+                },
+                "no cached value could ever qualify",
+            ),
+        ];
+        for (name, tweak, expected) in cases {
+            let mut cfg = RangeCacheEngineConfig::config_for_test();
+            tweak(&mut cfg);
+            let err = cfg.validate().unwrap_err().to_string();
+            assert!(
+                err.contains(expected),
+                "{}: expected {:?} in {:?}",
+                name,
+                expected,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_clamps_suboptimal_values() {
+        let mut cfg = RangeCacheEngineConfig::config_for_test();
+        cfg.iterator_prefetch_size = 1;
+        cfg.load_snapshot_max_age = Some(ReadableDuration(Duration::from_millis(10)));
+        cfg.validate().unwrap();
+        assert_eq!(cfg.iterator_prefetch_size, MIN_ITERATOR_PREFETCH_SIZE);
+        assert_eq!(
+            cfg.load_snapshot_max_age,
+            Some(ReadableDuration(MIN_LOAD_SNAPSHOT_MAX_AGE))
+        );
+
+        // 0 keeps meaning "disabled" and is not clamped up.
+        let mut cfg = RangeCacheEngineConfig::config_for_test();
+        cfg.iterator_prefetch_size = 0;
+        cfg.validate().unwrap();
+        assert_eq!(cfg.iterator_prefetch_size, 0);
+
+        // A disabled engine is not validated at all.
+        let mut cfg = RangeCacheEngineConfig::default();
+        cfg.load_concurrency = 0;
+        cfg.validate().unwrap();
+    }
+}
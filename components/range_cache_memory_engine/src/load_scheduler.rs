@@ -0,0 +1,364 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use engine_traits::CacheRange;
+use parking_lot::Mutex;
+use tikv_util::time::Instant;
+
+/// The default duration after which a `Normal` priority load is treated as
+/// `High` so that it cannot be starved by a continuous stream of newer
+/// high-priority loads.
+const DEFAULT_AGING_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// The default number of bytes a single load may write into the skiplist
+/// before it yields the scheduling quantum, so that one huge region does not
+/// starve the others sharing the load workers.
+pub(crate) const DEFAULT_LOAD_QUANTUM_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoadPriority {
+    Normal,
+    High,
+}
+
+#[derive(Debug)]
+struct PendingLoad {
+    range: CacheRange,
+    priority: LoadPriority,
+    enqueued_at: Instant,
+}
+
+impl PendingLoad {
+    // `Normal` loads that have waited longer than the aging threshold are
+    // promoted so they cannot be starved by newer high priority loads.
+    fn effective_priority(&self, aging_threshold: Duration) -> LoadPriority {
+        if self.priority == LoadPriority::Normal
+            && self.enqueued_at.saturating_elapsed() >= aging_threshold
+        {
+            LoadPriority::High
+        } else {
+            self.priority
+        }
+    }
+}
+
+/// LoadScheduler bounds the number of region loads that run concurrently in
+/// the background load workers and decides which pending load should run
+/// next.
+///
+/// Pending loads are ordered by effective priority (explicit `High` first,
+/// with aged `Normal` loads promoted, see [`PendingLoad::effective_priority`])
+/// and by enqueue time within the same priority. Loads whose ranges overlap a
+/// load that is already in flight are deduplicated at enqueue time, and an
+/// in-flight load can be canceled when its range is evicted mid-load.
+pub struct LoadScheduler {
+    inner: Mutex<LoadSchedulerInner>,
+    // Total bytes written into the skiplist by loads. The per-second rate is
+    // derived from this counter by the metrics flush.
+    loaded_bytes: AtomicU64,
+    concurrency: usize,
+    aging_threshold: Duration,
+    quantum_bytes: usize,
+    // While set, no pending load is handed out. Used by the write path
+    // backpressure to stop loads from growing the memory usage further;
+    // pending loads stay queued and run once the scheduler is resumed.
+    paused: AtomicBool,
+    // Like `paused`, but owned by the epoch reclaim watchdog in the delete
+    // worker. The two reasons are tracked separately so that resuming one
+    // does not undo the other.
+    paused_for_reclaim: AtomicBool,
+}
+
+#[derive(Default)]
+struct LoadSchedulerInner {
+    pending: VecDeque<PendingLoad>,
+    in_flight: Vec<CacheRange>,
+    // The creation time and sequence number of the disk snapshot currently
+    // backing each load, refreshed when the load swaps it for a fresh one
+    // mid-load. See `load_snapshot_max_age`.
+    snapshots: Vec<(CacheRange, Instant, u64)>,
+}
+
+impl LoadScheduler {
+    pub fn new(concurrency: usize) -> Self {
+        Self::with_threshold(
+            concurrency,
+            DEFAULT_AGING_THRESHOLD,
+            DEFAULT_LOAD_QUANTUM_BYTES,
+        )
+    }
+
+    pub(crate) fn with_threshold(
+        concurrency: usize,
+        aging_threshold: Duration,
+        quantum_bytes: usize,
+    ) -> Self {
+        Self {
+            inner: Mutex::new(LoadSchedulerInner::default()),
+            loaded_bytes: AtomicU64::new(0),
+            concurrency: usize::max(concurrency, 1),
+            aging_threshold,
+            quantum_bytes,
+            paused: AtomicBool::new(false),
+            paused_for_reclaim: AtomicBool::new(false),
+        }
+    }
+
+    /// Stop handing out pending loads until [`LoadScheduler::resume`] is
+    /// called. In-flight loads are not touched; they are bounded by the
+    /// concurrency limit and cancelable through eviction.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed) || self.paused_for_reclaim.load(Ordering::Relaxed)
+    }
+
+    /// Stop handing out pending loads while the epoch reclaim backlog is
+    /// above the threshold, see `reclaim_lag_threshold`.
+    pub fn pause_for_reclaim(&self) {
+        self.paused_for_reclaim.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume_from_reclaim(&self) {
+        self.paused_for_reclaim.store(false, Ordering::Relaxed);
+    }
+
+    /// Enqueue a load of `range`. Returns false if the range overlaps a
+    /// pending or in-flight load, in which case the load is deduplicated and
+    /// the caller should not schedule it again.
+    pub fn enqueue(&self, range: CacheRange, priority: LoadPriority) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.pending.iter().any(|l| l.range.overlaps(&range))
+            || inner.in_flight.iter().any(|r| r.overlaps(&range))
+        {
+            return false;
+        }
+        inner.pending.push_back(PendingLoad {
+            range,
+            priority,
+            enqueued_at: Instant::now_coarse(),
+        });
+        true
+    }
+
+    /// Claim the next load to run. Returns `None` if the scheduler is paused,
+    /// the concurrency limit has been reached, or there is no pending load.
+    pub fn claim(&self) -> Option<CacheRange> {
+        if self.is_paused() {
+            return None;
+        }
+        let mut inner = self.inner.lock();
+        if inner.in_flight.len() >= self.concurrency {
+            return None;
+        }
+        let idx = inner
+            .pending
+            .iter()
+            .enumerate()
+            .max_by_key(|(idx, l)| {
+                // Pick the highest effective priority; for equal priorities the
+                // earliest enqueued (smallest index) one wins.
+                (
+                    l.effective_priority(self.aging_threshold),
+                    std::cmp::Reverse(*idx),
+                )
+            })
+            .map(|(idx, _)| idx)?;
+        let load = inner.pending.remove(idx).unwrap();
+        inner.in_flight.push(load.range.clone());
+        Some(load.range)
+    }
+
+    /// Called when an in-flight load completes, fails, or is canceled to
+    /// release its concurrency slot.
+    pub fn finish(&self, range: &CacheRange) {
+        let mut inner = self.inner.lock();
+        inner.in_flight.retain(|r| r != range);
+        inner.snapshots.retain(|(r, ..)| r != range);
+    }
+
+    /// Records the disk snapshot backing the load of `range`: its creation
+    /// time and sequence number. Called when the load is scheduled and again
+    /// each time the load refreshes its snapshot.
+    pub fn record_snapshot(&self, range: &CacheRange, seq: u64) {
+        let mut inner = self.inner.lock();
+        inner.snapshots.retain(|(r, ..)| r != range);
+        inner
+            .snapshots
+            .push((range.clone(), Instant::now_coarse(), seq));
+    }
+
+    /// Returns how long the disk snapshot backing the load of `range` has
+    /// been held, and its sequence number.
+    pub fn snapshot_age(&self, range: &CacheRange) -> Option<(Duration, u64)> {
+        self.inner
+            .lock()
+            .snapshots
+            .iter()
+            .find(|(r, ..)| r == range)
+            .map(|(_, created_at, seq)| (created_at.saturating_elapsed(), *seq))
+    }
+
+    /// Drop pending loads overlapping `range`. In-flight loads are not
+    /// touched here; they observe cancellation through the canceled flag in
+    /// `pending_ranges_loading_data` which is checked every scheduling
+    /// quantum.
+    pub fn cancel_overlapped(&self, range: &CacheRange) {
+        let mut inner = self.inner.lock();
+        inner.pending.retain(|l| !l.range.overlaps(range));
+    }
+
+    pub fn record_loaded_bytes(&self, bytes: u64) {
+        self.loaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn take_loaded_bytes(&self) -> u64 {
+        self.loaded_bytes.swap(0, Ordering::Relaxed)
+    }
+
+    /// Returns true when a load that has written `bytes_since_check` bytes
+    /// since the last check should yield its quantum and re-check
+    /// cancellation.
+    pub fn should_yield(&self, bytes_since_check: usize) -> bool {
+        bytes_since_check >= self.quantum_bytes
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.inner.lock().pending.len()
+    }
+
+    pub fn in_flight_count(&self) -> usize {
+        self.inner.lock().in_flight.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: &[u8], end: &[u8]) -> CacheRange {
+        CacheRange::new(start.to_vec(), end.to_vec())
+    }
+
+    #[test]
+    fn test_concurrency_limit() {
+        let scheduler = LoadScheduler::new(3);
+        for i in 0..10u8 {
+            assert!(scheduler.enqueue(range(&[i * 2], &[i * 2 + 1]), LoadPriority::Normal));
+        }
+        let mut claimed = vec![];
+        while let Some(r) = scheduler.claim() {
+            claimed.push(r);
+        }
+        // At most `concurrency` loads may run simultaneously.
+        assert_eq!(claimed.len(), 3);
+        assert_eq!(scheduler.in_flight_count(), 3);
+        assert_eq!(scheduler.pending_count(), 7);
+
+        // Finishing one load frees a slot for the next pending load.
+        scheduler.finish(&claimed[0]);
+        assert!(scheduler.claim().is_some());
+        assert!(scheduler.claim().is_none());
+    }
+
+    #[test]
+    fn test_priority_and_aging() {
+        // With a zero aging threshold every `Normal` load is immediately
+        // promoted, so the oldest request must overtake newer high priority
+        // ones.
+        let scheduler =
+            LoadScheduler::with_threshold(1, Duration::ZERO, DEFAULT_LOAD_QUANTUM_BYTES);
+        let aged = range(b"k00", b"k10");
+        let newer = range(b"k10", b"k20");
+        assert!(scheduler.enqueue(aged.clone(), LoadPriority::Normal));
+        assert!(scheduler.enqueue(newer, LoadPriority::High));
+        assert_eq!(scheduler.claim().unwrap(), aged);
+
+        // Without aging, `High` runs before an earlier `Normal`.
+        let scheduler = LoadScheduler::with_threshold(
+            1,
+            DEFAULT_AGING_THRESHOLD,
+            DEFAULT_LOAD_QUANTUM_BYTES,
+        );
+        let normal = range(b"k00", b"k10");
+        let high = range(b"k10", b"k20");
+        assert!(scheduler.enqueue(normal, LoadPriority::Normal));
+        assert!(scheduler.enqueue(high.clone(), LoadPriority::High));
+        assert_eq!(scheduler.claim().unwrap(), high);
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let scheduler = LoadScheduler::new(2);
+        let r1 = range(b"k00", b"k10");
+        assert!(scheduler.enqueue(r1.clone(), LoadPriority::Normal));
+
+        // A paused scheduler hands out nothing but keeps the load queued.
+        scheduler.pause();
+        assert!(scheduler.is_paused());
+        assert!(scheduler.claim().is_none());
+        assert_eq!(scheduler.pending_count(), 1);
+
+        // Enqueue and cancel still work while paused.
+        let r2 = range(b"k20", b"k30");
+        assert!(scheduler.enqueue(r2.clone(), LoadPriority::High));
+        scheduler.cancel_overlapped(&r2);
+        assert_eq!(scheduler.pending_count(), 1);
+
+        scheduler.resume();
+        assert!(!scheduler.is_paused());
+        assert_eq!(scheduler.claim().unwrap(), r1);
+    }
+
+    #[test]
+    fn test_dedup_and_cancel() {
+        let scheduler = LoadScheduler::new(2);
+        let r1 = range(b"k00", b"k10");
+        assert!(scheduler.enqueue(r1.clone(), LoadPriority::Normal));
+        // Overlapping and duplicate loads are deduplicated.
+        assert!(!scheduler.enqueue(r1.clone(), LoadPriority::Normal));
+        assert!(!scheduler.enqueue(range(b"k05", b"k15"), LoadPriority::High));
+
+        assert_eq!(scheduler.claim().unwrap(), r1);
+        // Overlaps with in-flight loads are deduplicated as well.
+        assert!(!scheduler.enqueue(range(b"k00", b"k05"), LoadPriority::Normal));
+
+        let r2 = range(b"k20", b"k30");
+        assert!(scheduler.enqueue(r2.clone(), LoadPriority::Normal));
+        scheduler.cancel_overlapped(&range(b"k25", b"k35"));
+        assert_eq!(scheduler.pending_count(), 0);
+        assert!(scheduler.claim().is_none());
+
+        scheduler.finish(&r1);
+        assert_eq!(scheduler.in_flight_count(), 0);
+    }
+
+    #[test]
+    fn test_snapshot_tracking() {
+        let scheduler = LoadScheduler::new(1);
+        let r1 = range(b"k00", b"k10");
+        assert!(scheduler.snapshot_age(&r1).is_none());
+        scheduler.record_snapshot(&r1, 5);
+        assert_eq!(scheduler.snapshot_age(&r1).unwrap().1, 5);
+        // A refresh replaces the record instead of stacking a second one.
+        scheduler.record_snapshot(&r1, 8);
+        assert_eq!(scheduler.snapshot_age(&r1).unwrap().1, 8);
+        // Finishing the load drops the record.
+        scheduler.finish(&r1);
+        assert!(scheduler.snapshot_age(&r1).is_none());
+    }
+}
@@ -14,6 +14,16 @@ use crate::{
     engine::SkiplistEngine, write_batch::NODE_OVERHEAD_SIZE_EXPECTATION, RangeCacheEngineConfig,
 };
 
+/// The write path backpressure level derived from the memory usage and the
+/// write pressure watermarks, see
+/// [`RangeCacheEngineConfig::write_pressure_soft_watermark`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum WritePressure {
+    None,
+    Soft,
+    Hard,
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum MemoryUsage {
     NormalUsage(usize),
@@ -32,6 +42,12 @@ pub struct MemoryController {
     // Allocated memory for keys and values (node overhead is not included)
     // The number of writes that are buffered but not yet written.
     allocated: AtomicUsize,
+    // An estimate of the bytes logically freed by range deletions whose
+    // deferred destruction the crossbeam epoch collector has not executed
+    // yet. Incremented by the delete worker when entries are unlinked and
+    // decremented through a deferred callback once the epoch has advanced
+    // past the deletion, see `DeleteRangeRunner`.
+    pending_reclaim: AtomicUsize,
     config: Arc<VersionTrack<RangeCacheEngineConfig>>,
     memory_checking: AtomicBool,
     skiplist_engine: SkiplistEngine,
@@ -41,6 +57,7 @@ impl fmt::Debug for MemoryController {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MemoryController")
             .field("allocated", &self.allocated)
+            .field("pending_reclaim", &self.pending_reclaim)
             .field("soft_limit", &self.config.value().soft_limit_threshold())
             .field("hard_limit", &self.config.value().hard_limit_threshold())
             .field("memory_checking", &self.memory_checking)
@@ -55,6 +72,7 @@ impl MemoryController {
     ) -> Self {
         Self {
             allocated: AtomicUsize::new(0),
+            pending_reclaim: AtomicUsize::new(0),
             config,
             memory_checking: AtomicBool::new(false),
             skiplist_engine,
@@ -85,6 +103,18 @@ impl MemoryController {
         self.allocated.fetch_sub(n, Ordering::Relaxed);
     }
 
+    pub(crate) fn write_pressure(&self) -> WritePressure {
+        let usage = self.mem_usage();
+        let config = self.config.value();
+        if usage >= config.write_pressure_hard_watermark() {
+            WritePressure::Hard
+        } else if usage >= config.write_pressure_soft_watermark() {
+            WritePressure::Soft
+        } else {
+            WritePressure::None
+        }
+    }
+
     #[inline]
     pub(crate) fn reached_soft_limit(&self) -> bool {
         self.mem_usage() >= self.config.value().soft_limit_threshold()
@@ -105,6 +135,28 @@ impl MemoryController {
         self.memory_checking.load(Ordering::Relaxed)
     }
 
+    #[inline]
+    pub(crate) fn add_pending_reclaim(&self, n: usize) {
+        self.pending_reclaim.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn sub_pending_reclaim(&self, n: usize) {
+        self.pending_reclaim.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn pending_reclaim(&self) -> usize {
+        self.pending_reclaim.load(Ordering::Relaxed)
+    }
+
+    /// Whether the reclaim backlog is large enough that the delete worker
+    /// should drive epoch advancement and pause new range loads.
+    #[inline]
+    pub(crate) fn reclaim_lag_exceeded(&self) -> bool {
+        self.pending_reclaim() >= self.config.value().reclaim_lag_threshold()
+    }
+
     #[inline]
     pub(crate) fn mem_usage(&self) -> usize {
         self.allocated.load(Ordering::Relaxed)
@@ -125,11 +177,9 @@ mod tests {
         let skiplist_engine = SkiplistEngine::new();
         let config = Arc::new(VersionTrack::new(RangeCacheEngineConfig {
             enabled: true,
-            gc_interval: Default::default(),
-            load_evict_interval: Default::default(),
             soft_limit_threshold: Some(ReadableSize(300)),
             hard_limit_threshold: Some(ReadableSize(500)),
-            expected_region_size: Default::default(),
+            ..Default::default()
         }));
         let mc = MemoryController::new(config, skiplist_engine.clone());
         assert_eq!(mc.acquire(100), MemoryUsage::NormalUsage(100));
@@ -7,7 +7,7 @@ use prometheus::*;
 use prometheus_static_metric::*;
 
 use crate::{
-    statistics::{Tickers, ENGINE_TICKER_TYPES},
+    statistics::{take_comparator_calls, Tickers, ENGINE_TICKER_TYPES},
     RangeCacheMemoryEngineStatistics,
 };
 
@@ -28,6 +28,11 @@ make_auto_flush_static_metric! {
         number_db_next_found,
         number_db_prev,
         number_db_prev_found,
+        iter_direction_switch_fast,
+        iter_direction_switch_slow,
+        bytes_written_compressed,
+        bytes_written_uncompressed,
+        number_comparator_calls,
     }
 
     pub struct GcFilteredCountVec: LocalIntCounter {
@@ -57,12 +62,70 @@ lazy_static! {
         exponential_buckets(0.001, 2.0, 20).unwrap()
     )
     .unwrap();
+    pub static ref RANGE_LOAD_BYTES: IntCounter = register_int_counter!(
+        "tikv_range_load_bytes",
+        "Total bytes written into the range cache engine by background range loads.",
+    )
+    .unwrap();
+    pub static ref RANGE_LOAD_SKIPPED_ENTRIES: IntCounter = register_int_counter!(
+        "tikv_range_load_skipped_entries",
+        "Total number of entries not cached during range loads because gc would filter them.",
+    )
+    .unwrap();
+    pub static ref RANGE_LOAD_SKIPPED_BYTES: IntCounter = register_int_counter!(
+        "tikv_range_load_skipped_bytes",
+        "Total bytes not cached during range loads because gc would filter them.",
+    )
+    .unwrap();
+    pub static ref RANGE_LOAD_SNAPSHOT_REFRESHES: IntCounter = register_int_counter!(
+        "tikv_range_load_snapshot_refreshes",
+        "Total number of disk snapshots dropped and re-acquired mid-load because they got older \
+         than load-snapshot-max-age.",
+    )
+    .unwrap();
+    pub static ref RANGE_LOAD_CHUNKED_LOADS: IntCounter = register_int_counter!(
+        "tikv_range_load_chunked_loads",
+        "Total number of range loads that completed in multiple chunks across refreshed disk \
+         snapshots.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_PREWARM_BYTES: IntCounter = register_int_counter!(
+        "tikv_range_cache_evict_prewarm_bytes",
+        "Total bytes read back from the disk engine to pre-warm its block cache after evictions.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_PREWARM_SKIPPED: IntCounterVec = register_int_counter_vec!(
+        "tikv_range_cache_evict_prewarm_skipped",
+        "Evictions whose block cache pre-warm pass was skipped or aborted, by reason.",
+        &["reason"]
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_DENIED_ADMISSIONS: IntCounter = register_int_counter!(
+        "tikv_range_cache_denied_admissions",
+        "Total number of loads and snapshots refused because the range is in the deny list.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_SAFE_POINT_REGRESSIONS: IntCounter = register_int_counter!(
+        "tikv_range_cache_safe_point_regressions",
+        "Total number of rejected attempts to regress the safe point of a cached range.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_CHECKSUM_MISMATCHES: IntCounter = register_int_counter!(
+        "tikv_range_cache_checksum_mismatches",
+        "Total number of cached ranges whose incremental cache and disk checksums diverged.",
+    )
+    .unwrap();
     pub static ref RANGE_GC_TIME_HISTOGRAM: Histogram = register_histogram!(
         "tikv_range_gc_duration_secs",
         "Bucketed histogram of range gc time duration.",
         exponential_buckets(0.001, 2.0, 20).unwrap()
     )
     .unwrap();
+    pub static ref RANGE_GC_FREED_BYTES: IntCounter = register_int_counter!(
+        "tikv_range_gc_freed_bytes",
+        "Estimated total bytes freed from the range cache engine by gc.",
+    )
+    .unwrap();
     pub static ref WRITE_DURATION_HISTOGRAM: Histogram = register_histogram!(
         "tikv_range_cache_engine_write_duration_seconds",
         "Bucketed histogram of write duration in range cache engine.",
@@ -75,6 +138,101 @@ lazy_static! {
         exponential_buckets(0.00001, 2.0, 20).unwrap()
     )
     .unwrap();
+    pub static ref WRITE_BATCH_ENTRIES_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_range_cache_engine_write_batch_entries",
+        "Bucketed histogram of entries per write batch applied to the range cache engine.",
+        exponential_buckets(1.0, 2.0, 20).unwrap()
+    )
+    .unwrap();
+    pub static ref WRITE_BATCH_BYTES_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_range_cache_engine_write_batch_bytes",
+        "Bucketed histogram of bytes per write batch applied to the range cache engine.",
+        exponential_buckets(16.0, 2.0, 24).unwrap()
+    )
+    .unwrap();
+    pub static ref WRITE_BATCH_SKIPPED_UNCACHED: IntCounter = register_int_counter!(
+        "tikv_range_cache_engine_write_batch_skipped_uncached",
+        "Total number of write batches not buffered because the target range was not cached.",
+    )
+    .unwrap();
+    pub static ref WRITE_BATCH_SKIPPED_BY_PRESSURE: IntCounter = register_int_counter!(
+        "tikv_range_cache_engine_write_batch_skipped_by_pressure",
+        "Total number of write batch entries not buffered because the memory usage was above \
+         the write pressure watermarks.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_WRITE_PRESSURE: IntGauge = register_int_gauge!(
+        "tikv_range_cache_engine_write_pressure",
+        "The write path backpressure level of the range cache engine: 0 none, 1 soft, 2 hard.",
+    )
+    .unwrap();
+    pub static ref WRITE_BATCH_SEQNO_MISUSE: IntCounter = register_int_counter!(
+        "tikv_range_cache_engine_write_batch_seqno_misuse",
+        "Total number of write batches rejected because their sequence numbers were already \
+         committed to the range cache engine.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_ITERATOR_COUNT: IntGauge = register_int_gauge!(
+        "tikv_range_cache_iterator_count",
+        "The number of live range cache engine iterators.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_STUCK_EVICTIONS: IntGauge = register_int_gauge!(
+        "tikv_range_cache_stuck_evictions",
+        "The number of evicted ranges whose deletion has been blocked by undropped snapshots \
+         longer than the configured threshold.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_ITERATOR_BUFFER_BYTES: IntGauge = register_int_gauge!(
+        "tikv_range_cache_iterator_buffer_bytes",
+        "Total bytes held in saved key/value buffers by live range cache engine iterators.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_PENDING_RECLAIM: IntGauge = register_int_gauge!(
+        "tikv_range_cache_pending_reclaim_bytes",
+        "Estimated bytes logically freed by range deletions but not yet reclaimed by the \
+         crossbeam epoch collector.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_DELETE_BATCH_BUDGET: IntGauge = register_int_gauge!(
+        "tikv_range_cache_delete_batch_budget",
+        "The number of skiplist entries the background delete worker currently removes per \
+         batch, as decided by the deletion pacer.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_DELETE_BATCH_SLEEP: IntGauge = register_int_gauge!(
+        "tikv_range_cache_delete_batch_sleep_ms",
+        "The sleep in milliseconds the background delete worker currently inserts between \
+         deletion batches, as decided by the deletion pacer.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_DELETE_PACER_SATURATED: IntGauge = register_int_gauge!(
+        "tikv_range_cache_delete_pacer_saturated",
+        "Whether the deletion pacer is pinned at its bounds (minimum batch budget and maximum \
+         sleep) while foreground read latency still exceeds the target: 0 no, 1 yes.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_SEQNO_GAP: IntGauge = register_int_gauge!(
+        "tikv_range_cache_engine_seqno_gap",
+        "Gap between the latest sequence number of the disk engine and the latest sequence \
+         number applied to the range cache engine.",
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_SKIPLIST_NODE_COUNT: IntGaugeVec = register_int_gauge_vec!(
+        "tikv_range_cache_skiplist_node_count",
+        "The number of nodes (internal key versions) held by each skiplist of the range cache \
+         engine.",
+        &["cf"]
+    )
+    .unwrap();
+    pub static ref RANGE_CACHE_SKIPLIST_SEARCH_DEPTH: HistogramVec = register_histogram_vec!(
+        "tikv_range_cache_skiplist_search_depth",
+        "Bucketed histogram of the comparator invocations of sampled skiplist seeks, a proxy \
+         for the search depth. Refreshed by the periodic structural sampling pass.",
+        &["cf"],
+        exponential_buckets(1.0, 2.0, 16).unwrap()
+    )
+    .unwrap();
     pub static ref RANGE_CACHE_COUNT: IntGaugeVec = register_int_gauge_vec!(
         "tikv_range_cache_count",
         "The count of each type on range cache.",
@@ -115,6 +273,12 @@ pub fn flush_range_cache_engine_statistics(statistics: &Arc<RangeCacheMemoryEngi
         let v = statistics.get_and_reset_ticker_count(*t);
         flush_engine_ticker_metrics(*t, v);
     }
+    // Not part of `Statistics`: the comparator cannot reach the engine it
+    // serves, so its invocation count is kept process-global, see
+    // `statistics::record_comparator_call`.
+    IN_MEMORY_ENGINE_LOCATE_STATIC
+        .number_comparator_calls
+        .inc_by(take_comparator_calls());
 }
 
 fn flush_engine_ticker_metrics(t: Tickers, value: u64) {
@@ -149,6 +313,26 @@ fn flush_engine_ticker_metrics(t: Tickers, value: u64) {
                 .number_db_prev_found
                 .inc_by(value);
         }
+        Tickers::IterDirectionSwitchFast => {
+            IN_MEMORY_ENGINE_LOCATE_STATIC
+                .iter_direction_switch_fast
+                .inc_by(value);
+        }
+        Tickers::IterDirectionSwitchSlow => {
+            IN_MEMORY_ENGINE_LOCATE_STATIC
+                .iter_direction_switch_slow
+                .inc_by(value);
+        }
+        Tickers::BytesWrittenCompressed => {
+            IN_MEMORY_ENGINE_FLOW_STATIC
+                .bytes_written_compressed
+                .inc_by(value);
+        }
+        Tickers::BytesWrittenUncompressed => {
+            IN_MEMORY_ENGINE_FLOW_STATIC
+                .bytes_written_uncompressed
+                .inc_by(value);
+        }
         _ => {
             unreachable!()
         }
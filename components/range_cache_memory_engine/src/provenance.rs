@@ -0,0 +1,144 @@
+// Copyright 2025 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Apply provenance of cached entries for debugging.
+//!
+//! The skiplists only store the RocksDB sequence number of each entry, so
+//! when diagnosing a divergence there is no way to tell which raft apply
+//! produced a suspicious entry. When `provenance_tracking` is enabled, every
+//! consumed write batch records, per cached range, the sequence span its
+//! applied entries took together with the (apply index, term) the apply path
+//! attached to the batch. The mapping is a sidecar structure so the entries
+//! themselves are not inflated, and it is bounded: each range keeps the last
+//! [`MAX_PROVENANCE_RECORDS_PER_RANGE`] records, so old sequence spans age
+//! out of the ring as new writes arrive. The records of an evicted range are
+//! dropped when its data is deleted.
+//!
+//! Only the entries applied to the skiplists are tracked; entries buffered
+//! for a range that is still loading get their provenance once the range is
+//! active and new batches arrive.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use engine_traits::CacheRange;
+use parking_lot::Mutex;
+
+/// The number of sequence-span records kept per range. With three `u64`
+/// spans per record this bounds the sidecar to a few hundred KiB per range.
+pub(crate) const MAX_PROVENANCE_RECORDS_PER_RANGE: usize = 4096;
+
+/// One consumed write batch: the entries taking sequences
+/// `[start_seq, end_seq)` of the range were produced by the apply at
+/// `(apply_index, term)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProvenanceRecord {
+    pub start_seq: u64,
+    pub end_seq: u64,
+    pub apply_index: u64,
+    pub term: u64,
+}
+
+/// The per-range provenance records of one engine instance.
+#[derive(Default)]
+pub struct RangeProvenance {
+    records: Mutex<BTreeMap<CacheRange, VecDeque<ProvenanceRecord>>>,
+}
+
+impl RangeProvenance {
+    /// Records the sequence span one consumed write batch took for `range`.
+    /// The oldest record of the range is dropped once the ring is full.
+    pub(crate) fn record(
+        &self,
+        range: CacheRange,
+        start_seq: u64,
+        end_seq: u64,
+        apply_index: u64,
+        term: u64,
+    ) {
+        let mut records = self.records.lock();
+        let ring = records.entry(range).or_default();
+        ring.push_back(ProvenanceRecord {
+            start_seq,
+            end_seq,
+            apply_index,
+            term,
+        });
+        if ring.len() > MAX_PROVENANCE_RECORDS_PER_RANGE {
+            ring.pop_front();
+        }
+    }
+
+    /// The (apply index, term) that produced sequence `seq` of `range`, or
+    /// `None` when the range is not tracked or the record already aged out
+    /// of the ring.
+    pub fn lookup(&self, range: &CacheRange, seq: u64) -> Option<(u64, u64)> {
+        self.records
+            .lock()
+            .get(range)?
+            .iter()
+            .rev()
+            .find(|r| r.start_seq <= seq && seq < r.end_seq)
+            .map(|r| (r.apply_index, r.term))
+    }
+
+    /// The most recent record of `range`, used to annotate mismatch logs.
+    pub fn latest(&self, range: &CacheRange) -> Option<ProvenanceRecord> {
+        self.records.lock().get(range)?.back().copied()
+    }
+
+    /// Drops the records of `range`, called when the data of an evicted
+    /// range is deleted.
+    pub(crate) fn remove_range(&self, range: &CacheRange) {
+        self.records.lock().remove(range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let range = CacheRange::new(b"a".to_vec(), b"z".to_vec());
+        let provenance = RangeProvenance::default();
+        assert_eq!(provenance.lookup(&range, 5), None);
+
+        provenance.record(range.clone(), 5, 8, 100, 6);
+        provenance.record(range.clone(), 8, 9, 101, 6);
+        assert_eq!(provenance.lookup(&range, 5), Some((100, 6)));
+        assert_eq!(provenance.lookup(&range, 7), Some((100, 6)));
+        assert_eq!(provenance.lookup(&range, 8), Some((101, 6)));
+        // Sequences outside any span are not attributed.
+        assert_eq!(provenance.lookup(&range, 9), None);
+        assert_eq!(
+            provenance.latest(&range),
+            Some(ProvenanceRecord {
+                start_seq: 8,
+                end_seq: 9,
+                apply_index: 101,
+                term: 6,
+            })
+        );
+
+        // Other ranges are tracked independently.
+        let other = CacheRange::new(b"z".to_vec(), b"zz".to_vec());
+        assert_eq!(provenance.lookup(&other, 5), None);
+
+        provenance.remove_range(&range);
+        assert_eq!(provenance.lookup(&range, 5), None);
+    }
+
+    #[test]
+    fn test_old_records_age_out() {
+        let range = CacheRange::new(b"a".to_vec(), b"z".to_vec());
+        let provenance = RangeProvenance::default();
+        for i in 0..MAX_PROVENANCE_RECORDS_PER_RANGE as u64 + 10 {
+            provenance.record(range.clone(), i, i + 1, 100 + i, 6);
+        }
+        // The oldest records fell off the ring, the newest are retained.
+        assert_eq!(provenance.lookup(&range, 0), None);
+        assert_eq!(provenance.lookup(&range, 9), None);
+        assert_eq!(provenance.lookup(&range, 10), Some((110, 6)));
+        let last = MAX_PROVENANCE_RECORDS_PER_RANGE as u64 + 9;
+        assert_eq!(provenance.lookup(&range, last), Some((100 + last, 6)));
+    }
+}
@@ -4,17 +4,32 @@ use std::{
     collections::{BTreeMap, BTreeSet, VecDeque},
     result,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
 };
 
 use collections::HashMap;
 use engine_rocks::RocksSnapshot;
-use engine_traits::{CacheRange, FailedReason};
-use tikv_util::info;
+use engine_traits::{CacheRange, CachedCfs, FailedReason};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use tikv_util::{info, warn};
+
+use crate::{
+    events::{EventHistory, RangeEventKind},
+    health::EvictionRecord,
+    metrics::{RANGE_CACHE_DENIED_ADMISSIONS, RANGE_CACHE_SAFE_POINT_REGRESSIONS},
+    read::RangeCacheSnapshotMeta,
+};
+
+// The number of evictions kept in `recent_evictions` for diagnosis.
+const EVICTION_HISTORY_CAP: usize = 16;
 
-use crate::read::RangeCacheSnapshotMeta;
+// The number of snapshot failures with `FailedReason::EpochNotMatch` for the
+// same requested range after which the stale cached ranges overlapping it are
+// evicted, so that they can be reloaded with the current region boundaries.
+const EPOCH_MISMATCH_EVICT_THRESHOLD: u32 = 3;
 
 // read_ts -> ref_count
 #[derive(Default, Debug)]
@@ -47,6 +62,14 @@ impl SnapshotList {
     }
 }
 
+// Returns the current time as milliseconds since the unix epoch, used to
+// record the last access time of a range.
+pub(crate) fn now_unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
 #[derive(Debug, Default)]
 pub struct RangeMeta {
     // start_key and end_key cannot uniquely identify a range as range can split and merge, so we
@@ -54,6 +77,29 @@ pub struct RangeMeta {
     id: u64,
     range_snapshot_list: SnapshotList,
     safe_point: u64,
+    // The unix time in milliseconds when the range was last read. It is an
+    // atomic shared between a range and its derived sub ranges so that it can
+    // be refreshed without requiring mutable access to the meta.
+    last_access: Arc<AtomicU64>,
+    // A pinned range is never removed by the automatic eviction policies
+    // (ttl, memory pressure, load-evict); explicit evictions and region
+    // destroys still remove it. Derived sub ranges inherit the pin, so a
+    // split pinned range stays pinned.
+    pinned: bool,
+    // The tombstone flag shared with all snapshots acquired on the range.
+    // When the eviction watchdog force-completes an eviction blocked by
+    // leaked snapshots, the flag is set so that reads through them fail
+    // instead of observing the deleted data. Checking it is a single atomic
+    // load on the read path.
+    tombstone: Arc<AtomicBool>,
+    // The unix time in milliseconds when the range was evicted and moved into
+    // `historical_ranges` because of undropped snapshots, 0 before that. Used
+    // by the eviction watchdog to tell how long the deletion has been stuck.
+    evicted_at: u64,
+    // Which data cfs of the range hold data. All cfs unless the range was
+    // loaded through the partial load API; reads of the uncached cfs must be
+    // served by the disk engine.
+    cached_cfs: CachedCfs,
 }
 
 impl RangeMeta {
@@ -62,6 +108,11 @@ impl RangeMeta {
             id,
             range_snapshot_list: SnapshotList::default(),
             safe_point: 0,
+            last_access: Arc::new(AtomicU64::new(now_unix_millis())),
+            pinned: false,
+            tombstone: Arc::new(AtomicBool::new(false)),
+            evicted_at: 0,
+            cached_cfs: CachedCfs::all(),
         }
     }
 
@@ -79,12 +130,51 @@ impl RangeMeta {
             id,
             range_snapshot_list: SnapshotList::default(),
             safe_point: r.safe_point,
+            last_access: r.last_access.clone(),
+            pinned: r.pinned,
+            // Derived sub ranges serve their own snapshots, so they get a
+            // fresh tombstone flag.
+            tombstone: Arc::new(AtomicBool::new(false)),
+            evicted_at: 0,
+            cached_cfs: r.cached_cfs,
         }
     }
 
+    pub(crate) fn cached_cfs(&self) -> CachedCfs {
+        self.cached_cfs
+    }
+
+    pub(crate) fn set_cached_cfs(&mut self, cached_cfs: CachedCfs) {
+        self.cached_cfs = cached_cfs;
+    }
+
     pub(crate) fn range_snapshot_list(&self) -> &SnapshotList {
         &self.range_snapshot_list
     }
+
+    // Refreshes the last access time of the range to now.
+    pub(crate) fn touch(&self) {
+        self.last_access.store(now_unix_millis(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn last_access(&self) -> u64 {
+        self.last_access.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    // Marks all snapshots still registered on the range as poisoned, see the
+    // `tombstone` field.
+    pub(crate) fn poison_snapshots(&self) {
+        self.tombstone.store(true, Ordering::Release);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_last_access(&self, millis: u64) {
+        self.last_access.store(millis, Ordering::Relaxed);
+    }
 }
 
 #[derive(Default)]
@@ -119,6 +209,14 @@ pub struct RangeManager {
     // `ranges_being_deleted` contains ranges that are evicted but not finished the delete (or even
     // not start to delete due to ongoing snapshot)
     pub(crate) ranges_being_deleted: BTreeSet<CacheRange>,
+    // Evicted ranges whose eviction can still be rolled back, mapped to the
+    // meta to restore. An entry exists from the eviction until the delete
+    // worker takes its first batch of the range (`start_deletion`), which
+    // drops it and makes the eviction definitive. See `cancel_eviction`.
+    cancelable_evictions: BTreeMap<CacheRange, RangeMeta>,
+    // Canceled evictions whose `DeleteRange` task may still be queued (or
+    // delayed). The worker consumes an entry instead of deleting the range.
+    canceled_evictions: BTreeSet<CacheRange>,
     // ranges that are cached now
     ranges: BTreeMap<CacheRange, RangeMeta>,
 
@@ -162,6 +260,41 @@ pub struct RangeManager {
     // all ranges of it are cleared from `ranges_being_written`.
     ranges_being_written: HashMap<u64, Vec<CacheRange>>,
     range_evictions: AtomicU64,
+    // A bounded history of evictions with reasons and timestamps, newest
+    // last. It is only read by the health report.
+    recent_evictions: VecDeque<EvictionRecord>,
+    // Ranges for which caching is denied. Ranges overlapping with them are
+    // refused by `load_range` and `range_snapshot` until they are removed
+    // again, so reads of them fall back to the disk engine. The ranges are
+    // kept sorted and non-overlapping so the check on the snapshot path is a
+    // binary search.
+    denied_ranges: Vec<CacheRange>,
+    // In-flight bulk evictions, each pairing the requested range with the
+    // completion flag its handle waits on. An entry is resolved, and its
+    // admission block in `denied_ranges` lifted, once nothing overlapping
+    // the range is cached, loading, or awaiting physical deletion.
+    bulk_evictions: Vec<(CacheRange, Arc<(Mutex<bool>, Condvar)>)>,
+    // Counts of snapshot failures with `FailedReason::EpochNotMatch`, keyed by
+    // the requested range. An entry reaching `EPOCH_MISMATCH_EVICT_THRESHOLD`
+    // triggers the eviction of the stale cached ranges and is removed, so the
+    // map stays small.
+    epoch_mismatches: BTreeMap<CacheRange, u32>,
+    // The cf subsets requested by partial loads, keyed by the range the load
+    // was requested for. Entries exist only for loads of a proper cf subset
+    // and only while the load is pending or running: the flags move into the
+    // `RangeMeta` when the load completes. Lookups are containment based, so
+    // a pending range split by `prepare_for_apply` keeps its requested cfs.
+    requested_cfs: BTreeMap<CacheRange, CachedCfs>,
+    // The freshness watermark of the cached data, keyed by the range the write
+    // batch prepared for. The value is the max sequence number and the max
+    // apply index that have been written into the cache for the range. It is
+    // consulted by follower reads to decide whether the cache has caught up to
+    // their required apply index. Entries of evicted ranges are removed.
+    cached_watermarks: BTreeMap<CacheRange, (u64, u64)>,
+    // The engine-wide event history. The manager owns the original; the
+    // engine and the background workers record and query through clones, see
+    // the `events` module.
+    events: Arc<EventHistory>,
 }
 
 impl RangeManager {
@@ -170,8 +303,17 @@ impl RangeManager {
     }
 
     pub fn new_range(&mut self, range: CacheRange) {
+        self.new_range_with_safe_point(range, 0);
+    }
+
+    // Installs a newly cached range with its safe point already set, used by
+    // the load path to carry over the disk engine's gc safe point so that a
+    // freshly loaded range does not accept reads the disk gc may already have
+    // pruned.
+    pub(crate) fn new_range_with_safe_point(&mut self, range: CacheRange, safe_point: u64) {
         assert!(!self.overlap_with_range(&range));
-        let range_meta = RangeMeta::new(self.id_allocator.allocate_id());
+        let mut range_meta = RangeMeta::new(self.id_allocator.allocate_id());
+        range_meta.set_safe_point(safe_point);
         self.ranges.insert(range, range_meta);
     }
 
@@ -179,9 +321,20 @@ impl RangeManager {
         self.ranges.get_mut(range)
     }
 
+    /// Advances the safe point of the cached range. The safe point is
+    /// strictly non-decreasing: regressing it would make reads below it
+    /// succeed again and observe the holes left by the in-memory gc, so
+    /// regression attempts are rejected and counted.
     pub fn set_safe_point(&mut self, range: &CacheRange, safe_ts: u64) -> bool {
         if let Some(meta) = self.ranges.get_mut(range) {
             if meta.safe_point > safe_ts {
+                RANGE_CACHE_SAFE_POINT_REGRESSIONS.inc();
+                warn!(
+                    "reject regressing the safe point of a cached range";
+                    "range" => ?range,
+                    "current" => meta.safe_point,
+                    "attempted" => safe_ts,
+                );
                 return false;
             }
             meta.safe_point = safe_ts;
@@ -191,6 +344,12 @@ impl RangeManager {
         }
     }
 
+    /// Returns the safe point of the cached range exactly matching `range`,
+    /// for diagnostics.
+    pub fn safe_point(&self, range: &CacheRange) -> Option<u64> {
+        self.ranges.get(range).map(|meta| meta.safe_point())
+    }
+
     pub fn contains(&self, key: &[u8]) -> bool {
         self.ranges.keys().any(|r| r.contains_key(key))
     }
@@ -209,6 +368,22 @@ impl RangeManager {
         self.ranges.keys().any(|r| r.contains_range(range))
     }
 
+    // Removes the pending loading range that equals `range`. Loads may finish
+    // out of order when they run concurrently, so the entry is located by
+    // range rather than popped from the front.
+    pub(crate) fn remove_pending_loading_range(
+        &mut self,
+        range: &CacheRange,
+    ) -> Option<(CacheRange, Arc<RocksSnapshot>, bool)> {
+        let idx = self
+            .pending_ranges_loading_data
+            .iter()
+            .position(|(r, ..)| r == range)?;
+        let removed = self.pending_ranges_loading_data.remove(idx);
+        self.gc_requested_cfs();
+        removed
+    }
+
     pub fn pending_ranges_in_loading_contains(&self, range: &CacheRange) -> bool {
         self.pending_ranges_loading_data
             .iter()
@@ -235,29 +410,103 @@ impl RangeManager {
                 .any(|(r, ..)| r.overlaps(range))
     }
 
+    // Whether eviction of the range has fully taken effect: no overlapping
+    // range is cached, pending to be loaded, or being loaded without having
+    // been canceled. Parts waiting for physical deletion
+    // (`ranges_being_deleted`, `historical_ranges`) cannot serve new snapshots,
+    // so they do not count.
+    pub(crate) fn range_evicted(&self, range: &CacheRange) -> bool {
+        !self.overlap_with_range(range)
+            && !self.pending_ranges.iter().any(|r| r.overlaps(range))
+            && !self
+                .pending_ranges_loading_data
+                .iter()
+                .any(|(r, _, canceled)| r.overlaps(range) && !*canceled)
+    }
+
     // Acquire a snapshot of the `range` with `read_ts`. If the range is not
-    // accessable, None will be returned. Otherwise, the range id will be returned.
+    // accessable, None will be returned. Otherwise, the range id and the
+    // shared tombstone flag of the range will be returned.
     pub(crate) fn range_snapshot(
         &mut self,
         range: &CacheRange,
         read_ts: u64,
-    ) -> result::Result<u64, FailedReason> {
+    ) -> result::Result<(u64, Arc<AtomicBool>), FailedReason> {
+        if self.is_denied(range) {
+            RANGE_CACHE_DENIED_ADMISSIONS.inc();
+            self.events
+                .record_snapshot_failed(range, FailedReason::NotCached);
+            return Err(FailedReason::NotCached);
+        }
         let Some(range_key) = self
             .ranges
             .keys()
             .find(|&r| r.contains_range(range))
             .cloned()
         else {
+            // A cached range that overlaps the requested one without
+            // containing it means the cached boundaries are stale relative to
+            // the region epoch the read was routed with.
+            if self.overlap_with_range(range) {
+                self.events
+                    .record_snapshot_failed(range, FailedReason::EpochNotMatch);
+                return Err(FailedReason::EpochNotMatch);
+            }
+            self.events
+                .record_snapshot_failed(range, FailedReason::NotCached);
             return Err(FailedReason::NotCached);
         };
         let meta = self.ranges.get_mut(&range_key).unwrap();
 
         if read_ts <= meta.safe_point {
+            self.events
+                .record_snapshot_failed(range, FailedReason::TooOldRead);
             return Err(FailedReason::TooOldRead);
         }
 
+        meta.touch();
         meta.range_snapshot_list.new_snapshot(read_ts);
-        Ok(meta.id)
+        Ok((meta.id, meta.tombstone.clone()))
+    }
+
+    // Records that a snapshot of `range` failed with
+    // `FailedReason::EpochNotMatch`. Returns true when the mismatch has
+    // repeated often enough that the stale cached ranges should be evicted,
+    // which heals ranges left behind by missed region events.
+    pub(crate) fn record_epoch_mismatch(&mut self, range: &CacheRange) -> bool {
+        let count = self.epoch_mismatches.entry(range.clone()).or_insert(0);
+        *count += 1;
+        if *count < EPOCH_MISMATCH_EVICT_THRESHOLD {
+            return false;
+        }
+        self.epoch_mismatches.remove(range);
+        true
+    }
+
+    // Advances the freshness watermark of `range` to `seq` and `apply_index`.
+    // The watermark only moves forward as writes of one range are applied in
+    // order.
+    pub(crate) fn record_cached_watermark(
+        &mut self,
+        range: CacheRange,
+        seq: u64,
+        apply_index: u64,
+    ) {
+        let watermark = self.cached_watermarks.entry(range).or_insert((0, 0));
+        watermark.0 = u64::max(watermark.0, seq);
+        watermark.1 = u64::max(watermark.1, apply_index);
+    }
+
+    pub fn cached_watermark(&self, range: &CacheRange) -> Option<(u64, u64)> {
+        if let Some(watermark) = self.cached_watermarks.get(range) {
+            return Some(*watermark);
+        }
+        // The cached range may have been derived from a larger one (e.g. by a
+        // partial eviction), so fall back to a containment scan.
+        self.cached_watermarks
+            .iter()
+            .find(|(r, _)| r.contains_range(range))
+            .map(|(_, watermark)| *watermark)
     }
 
     // If the snapshot is the last one in the snapshot list of one cache range in
@@ -298,14 +547,20 @@ impl RangeManager {
         }
 
         // It must belong to the `self.ranges` if not found in `self.historical_ranges`
-        let range_key = self
+        let Some(range_key) = self
             .ranges
             .iter()
             .find(|&(range, meta)| {
                 range.contains_range(&snapshot_meta.range) && meta.id == snapshot_meta.range_id
             })
             .map(|(r, _)| r.clone())
-            .unwrap();
+        else {
+            // The only way a registered snapshot can miss its meta is a
+            // force-completed eviction having dropped it; the poisoned
+            // snapshot then has nothing to unregister.
+            assert!(snapshot_meta.is_poisoned());
+            return vec![];
+        };
         let meta = self.ranges.get_mut(&range_key).unwrap();
         meta.range_snapshot_list
             .remove_snapshot(snapshot_meta.snapshot_ts);
@@ -326,10 +581,15 @@ impl RangeManager {
     //
     // For 2, this is caused by some special operations such as merge and delete
     // range. So, conservatively, we evict all ranges overlap with it.
-    pub(crate) fn evict_range(&mut self, evict_range: &CacheRange) -> Vec<CacheRange> {
+    pub(crate) fn evict_range(
+        &mut self,
+        evict_range: &CacheRange,
+        reason: &'static str,
+    ) -> Vec<CacheRange> {
         info!(
             "try to evict range";
             "evict_range" => ?evict_range,
+            "reason" => reason,
         );
 
         // cancel loading ranges overlapped with `evict_range`
@@ -349,7 +609,7 @@ impl RangeManager {
         let mut overlapped_ranges = vec![];
         for r in self.ranges.keys() {
             if r.contains_range(evict_range) {
-                if self.evict_within_range(evict_range, &r.clone()) {
+                if self.evict_within_range(evict_range, &r.clone(), reason) {
                     return vec![evict_range.clone()];
                 } else {
                     return vec![];
@@ -369,13 +629,18 @@ impl RangeManager {
 
         overlapped_ranges
             .into_iter()
-            .filter(|r| self.evict_within_range(r, r))
+            .filter(|r| self.evict_within_range(r, r, reason))
             .collect()
     }
 
     // Return true means there is no ongoing snapshot, the evicted_range can be
     // deleted now.
-    fn evict_within_range(&mut self, evict_range: &CacheRange, cached_range: &CacheRange) -> bool {
+    fn evict_within_range(
+        &mut self,
+        evict_range: &CacheRange,
+        cached_range: &CacheRange,
+        reason: &'static str,
+    ) -> bool {
         assert!(cached_range.contains_range(evict_range));
         info!(
             "evict range in cache range engine";
@@ -383,7 +648,23 @@ impl RangeManager {
             "cached_range" => ?cached_range,
         );
         self.range_evictions.fetch_add(1, Ordering::Relaxed);
-        let meta = self.ranges.remove(cached_range).unwrap();
+        // The data of the range is going away, so its freshness watermark must
+        // not be consulted anymore.
+        self.cached_watermarks
+            .retain(|r, _| !r.overlaps(evict_range));
+        if self.recent_evictions.len() >= EVICTION_HISTORY_CAP {
+            self.recent_evictions.pop_front();
+        }
+        self.recent_evictions.push_back(EvictionRecord::new(
+            &evict_range.start,
+            &evict_range.end,
+            reason,
+        ));
+        self.events.record(
+            evict_range.clone(),
+            RangeEventKind::EvictRequested { reason },
+        );
+        let mut meta = self.ranges.remove(cached_range).unwrap();
         let (left_range, right_range) = cached_range.split_off(evict_range);
         assert!((left_range.is_some() || right_range.is_some()) || evict_range == cached_range);
 
@@ -398,8 +679,18 @@ impl RangeManager {
         }
 
         self.ranges_being_deleted.insert(evict_range.clone());
+        // Nothing of the range has been deleted yet, so the eviction can
+        // still be rolled back; keep a restorable meta until the delete
+        // worker takes its first batch. A marker left by a canceled earlier
+        // eviction of the same range must not skip this one.
+        self.canceled_evictions.remove(evict_range);
+        self.cancelable_evictions.insert(
+            evict_range.clone(),
+            RangeMeta::derive_from(self.id_allocator.allocate_id(), &meta),
+        );
 
         if !meta.range_snapshot_list.is_empty() {
+            meta.evicted_at = now_unix_millis();
             self.historical_ranges.insert(cached_range.clone(), meta);
             return false;
         }
@@ -418,7 +709,112 @@ impl RangeManager {
     pub fn on_delete_ranges(&mut self, ranges: &[CacheRange]) {
         for r in ranges {
             self.ranges_being_deleted.remove(r);
+            self.events
+                .record(r.clone(), RangeEventKind::EvictCompleted);
+        }
+        self.check_bulk_evictions();
+    }
+
+    // Rolls back the eviction of `range`, which must be the exact range an
+    // earlier `evict_range` returned. Only possible while nothing of the
+    // range has been deleted: once the delete worker has taken its first
+    // batch the restorable meta is gone and the cancellation is refused.
+    pub(crate) fn cancel_eviction(&mut self, range: &CacheRange) -> Result<(), CancelError> {
+        // A denied range must stay out of the cache, so its eviction is not
+        // cancelable until the denial is lifted.
+        if !self.ranges_being_deleted.contains(range) || self.is_denied(range) {
+            return Err(CancelError::NotEvicting);
+        }
+        let Some(meta) = self.cancelable_evictions.remove(range) else {
+            return Err(CancelError::DeletionStarted);
+        };
+        self.ranges_being_deleted.remove(range);
+        // The queued `DeleteRange` task of the range, if any, must consume
+        // this marker instead of deleting the restored data.
+        self.canceled_evictions.insert(range.clone());
+        // Loads overlapping an evicting range are refused, so the slot of
+        // the range is necessarily still free.
+        assert!(!self.overlap_with_range(range));
+        self.ranges.insert(range.clone(), meta);
+        self.events
+            .record(range.clone(), RangeEventKind::EvictCanceled);
+        info!(
+            "cancel eviction of range";
+            "range" => ?range,
+        );
+        Ok(())
+    }
+
+    // Called by the delete worker right before it takes the first batch of
+    // `range`. Returns false if the eviction of the range was canceled, in
+    // which case nothing of it may be deleted. Otherwise the restorable meta
+    // is dropped, which is what makes later cancellations fail definitively.
+    pub(crate) fn start_deletion(&mut self, range: &CacheRange) -> bool {
+        if self.canceled_evictions.remove(range) {
+            return false;
+        }
+        self.cancelable_evictions.remove(range);
+        true
+    }
+
+    // Whether the eviction of `range` has been canceled and the cancellation
+    // has not been consumed by the delete worker yet.
+    pub(crate) fn eviction_canceled(&self, range: &CacheRange) -> bool {
+        self.canceled_evictions.contains(range)
+    }
+
+    // The evicted ranges overlapping `range` whose eviction can still be
+    // rolled back.
+    pub(crate) fn cancelable_evictions_overlapping(&self, range: &CacheRange) -> Vec<CacheRange> {
+        self.cancelable_evictions
+            .keys()
+            .filter(|r| r.overlaps(range))
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn register_bulk_eviction(
+        &mut self,
+        range: CacheRange,
+        done: Arc<(Mutex<bool>, Condvar)>,
+    ) {
+        self.bulk_evictions.push((range, done));
+        // Resolve right away when nothing overlapping the range was cached.
+        self.check_bulk_evictions();
+    }
+
+    // Resolves the bulk evictions whose range holds no cached data anymore
+    // and lifts their admission blocks.
+    fn check_bulk_evictions(&mut self) {
+        if self.bulk_evictions.is_empty() {
+            return;
         }
+        let bulk = std::mem::take(&mut self.bulk_evictions);
+        let (resolved, pending): (Vec<_>, Vec<_>) = bulk
+            .into_iter()
+            .partition(|(range, _)| self.bulk_eviction_complete(range));
+        self.bulk_evictions = pending;
+        for (range, done) in resolved {
+            info!(
+                "bulk eviction completed";
+                "range" => ?range,
+            );
+            self.remove_denied_range(&range);
+            let (lock, cvar) = &*done;
+            *lock.lock() = true;
+            cvar.notify_all();
+        }
+    }
+
+    // Whether all data overlapping `range` has been physically removed: no
+    // overlapping range is cached, pending, loading (even canceled, as its
+    // data is removed only once the loader drops it), or awaiting deletion
+    // behind an undropped snapshot.
+    fn bulk_eviction_complete(&self, range: &CacheRange) -> bool {
+        !self.overlap_with_range(range)
+            && !self.overlap_with_pending_range(range)
+            && !self.overlap_with_evicting_range(range)
+            && !self.historical_ranges.keys().any(|r| r.overlaps(range))
     }
 
     pub fn set_ranges_in_gc(&mut self, ranges_in_gc: BTreeSet<CacheRange>) {
@@ -459,26 +855,404 @@ impl RangeManager {
         assert_eq!(range, std::mem::take(&mut self.ranges_in_gc));
     }
 
+    /// Adds `range` to the deny list. Overlapping or adjacent entries are
+    /// merged so that the deny list stays sorted and non-overlapping.
+    ///
+    /// The caller is responsible for evicting cached ranges that overlap with
+    /// it; pending loads that have not started yet are dropped here.
+    pub(crate) fn add_denied_range(&mut self, mut range: CacheRange) {
+        self.pending_ranges.retain(|r| !r.overlaps(&range));
+        self.gc_requested_cfs();
+        self.denied_ranges.retain(|r| {
+            if r.overlaps(&range) || r.start == range.end || r.end == range.start {
+                if r.start < range.start {
+                    range.start = r.start.clone();
+                }
+                if r.end > range.end {
+                    range.end = r.end.clone();
+                }
+                false
+            } else {
+                true
+            }
+        });
+        let idx = self.denied_ranges.partition_point(|r| r.end <= range.start);
+        self.denied_ranges.insert(idx, range);
+    }
+
+    /// Removes entries overlapping with `range` from the deny list, so that
+    /// ranges within them can be admitted again.
+    pub(crate) fn remove_denied_range(&mut self, range: &CacheRange) {
+        self.denied_ranges.retain(|r| !r.overlaps(range));
+    }
+
+    /// Returns true if caching for `range` is denied.
+    pub(crate) fn is_denied(&self, range: &CacheRange) -> bool {
+        // The entries are sorted and non-overlapping, so only the first entry
+        // that ends after the start of `range` can overlap with it.
+        let idx = self.denied_ranges.partition_point(|r| r.end <= range.start);
+        self.denied_ranges
+            .get(idx)
+            .map_or(false, |r| r.overlaps(range))
+    }
+
     pub fn load_range(&mut self, cache_range: CacheRange) -> Result<(), LoadFailedReason> {
-        if self.overlap_with_range(&cache_range) {
+        self.load_range_with_cfs(cache_range, CachedCfs::all())
+    }
+
+    /// Like [`Self::load_range`], but restricts caching to the given data
+    /// cfs. Caching the write cf only still serves point reads of short
+    /// values, which are inlined in the write records, entirely from the
+    /// cache; reads of the uncached cfs fall back to the disk engine.
+    pub fn load_range_with_cfs(
+        &mut self,
+        cache_range: CacheRange,
+        cfs: CachedCfs,
+    ) -> Result<(), LoadFailedReason> {
+        if let Err(reason) = self.check_load_admission(&cache_range) {
+            self.events.record(
+                cache_range,
+                RangeEventKind::LoadRejected {
+                    reason: reason.as_str(),
+                },
+            );
+            return Err(reason);
+        }
+        self.events
+            .record(cache_range.clone(), RangeEventKind::LoadScheduled);
+        if !cfs.is_all() {
+            self.requested_cfs.insert(cache_range.clone(), cfs);
+        }
+        self.pending_ranges.push(cache_range);
+        Ok(())
+    }
+
+    /// Which data cfs of the cached range containing `range` hold data. None
+    /// if no cached range contains it.
+    pub fn cached_cfs(&self, range: &CacheRange) -> Option<CachedCfs> {
+        self.ranges
+            .iter()
+            .find(|(r, _)| r.contains_range(range))
+            .map(|(_, meta)| meta.cached_cfs())
+    }
+
+    /// The cf subset requested for the pending or loading range. All cfs
+    /// unless a partial load requested otherwise.
+    pub(crate) fn requested_cfs(&self, range: &CacheRange) -> CachedCfs {
+        self.requested_cfs
+            .iter()
+            .find(|(r, _)| r.contains_range(range) || r.overlaps(range))
+            .map_or_else(CachedCfs::all, |(_, cfs)| *cfs)
+    }
+
+    // Drops requested-cf entries whose load is neither pending nor running
+    // anymore, i.e. it completed, was canceled, or was evicted.
+    pub(crate) fn gc_requested_cfs(&mut self) {
+        let pending = &self.pending_ranges;
+        let loading = &self.pending_ranges_loading_data;
+        self.requested_cfs.retain(|r, _| {
+            pending.iter().any(|p| p.overlaps(r)) || loading.iter().any(|(l, ..)| l.overlaps(r))
+        });
+    }
+
+    fn check_load_admission(&self, cache_range: &CacheRange) -> Result<(), LoadFailedReason> {
+        if self.is_denied(cache_range) {
+            RANGE_CACHE_DENIED_ADMISSIONS.inc();
+            return Err(LoadFailedReason::Denied);
+        }
+        if self.overlap_with_range(cache_range) {
             return Err(LoadFailedReason::Overlapped);
         };
-        if self.overlap_with_pending_range(&cache_range) {
+        if self.overlap_with_pending_range(cache_range) {
             return Err(LoadFailedReason::PendingRange);
         }
-        if self.overlap_with_range_in_gc(&cache_range) {
+        if self.overlap_with_range_in_gc(cache_range) {
             return Err(LoadFailedReason::InGc);
         }
-        if self.overlap_with_evicting_range(&cache_range) {
+        if self.overlap_with_evicting_range(cache_range) {
             return Err(LoadFailedReason::Evicting);
         }
-        self.pending_ranges.push(cache_range);
         Ok(())
     }
 
+    /// Pins or unpins the cached range containing `range`, so the automatic
+    /// eviction policies skip it. The memory a pinned range may occupy is
+    /// estimated as one expected range size per pin, and a new pin is refused
+    /// with `PinFailedReason::CapacityExceeded` when the estimate of all pins
+    /// would exceed `pinned_bytes_budget`, so that the memory pressure
+    /// eviction always keeps enough unpinned ranges to reclaim from.
+    pub fn pin_range(
+        &mut self,
+        range: &CacheRange,
+        pinned: bool,
+        pinned_bytes_budget: usize,
+        expected_range_size: usize,
+    ) -> Result<(), PinFailedReason> {
+        let Some(range_key) = self
+            .ranges
+            .keys()
+            .find(|&r| r.contains_range(range))
+            .cloned()
+        else {
+            return Err(PinFailedReason::NotCached);
+        };
+        let already_pinned = self.ranges.get(&range_key).unwrap().pinned;
+        if pinned && !already_pinned {
+            let estimated = (self.pinned_ranges_count() + 1) * expected_range_size;
+            if estimated > pinned_bytes_budget {
+                return Err(PinFailedReason::CapacityExceeded);
+            }
+        }
+        self.ranges.get_mut(&range_key).unwrap().pinned = pinned;
+        info!(
+            "update the pin of a cached range";
+            "range" => ?range_key,
+            "pinned" => pinned,
+        );
+        Ok(())
+    }
+
+    /// Returns true if any cached range overlapping with `range` is pinned,
+    /// so eviction policies checking a candidate range err on the side of
+    /// keeping it.
+    pub fn is_pinned(&self, range: &CacheRange) -> bool {
+        self.ranges
+            .iter()
+            .any(|(r, meta)| r.overlaps(range) && meta.pinned)
+    }
+
+    pub(crate) fn pinned_ranges_count(&self) -> usize {
+        self.ranges.values().filter(|meta| meta.pinned).count()
+    }
+
     pub fn get_and_reset_range_evictions(&self) -> u64 {
         self.range_evictions.swap(0, Ordering::Relaxed)
     }
+
+    pub(crate) fn historical_ranges_count(&self) -> usize {
+        self.historical_ranges.len()
+    }
+
+    pub(crate) fn recent_evictions(&self) -> &VecDeque<EvictionRecord> {
+        &self.recent_evictions
+    }
+
+    pub(crate) fn events(&self) -> &Arc<EventHistory> {
+        &self.events
+    }
+
+    // Returns the historical ranges whose eviction has been blocked by
+    // undropped snapshots for at least `threshold_millis`, together with how
+    // long each has been blocked and the (snapshot_ts, ref_count) pairs still
+    // registered on it. A snapshot blocking deletion for that long is almost
+    // certainly leaked, e.g. held by a wedged request or a reference cycle.
+    pub(crate) fn stuck_historical_ranges(
+        &self,
+        now: u64,
+        threshold_millis: u64,
+    ) -> Vec<(CacheRange, u64, Vec<(u64, u64)>)> {
+        self.historical_ranges
+            .iter()
+            .filter_map(|(range, meta)| {
+                let age = now.saturating_sub(meta.evicted_at);
+                if age < threshold_millis {
+                    return None;
+                }
+                let snapshots = meta
+                    .range_snapshot_list
+                    .0
+                    .iter()
+                    .map(|(ts, count)| (*ts, *count))
+                    .collect();
+                Some((range.clone(), age, snapshots))
+            })
+            .collect()
+    }
+
+    // Force-completes the stuck eviction of the historical `range`: the
+    // snapshots still registered on it are poisoned so that reads and drops
+    // through them fail gracefully instead of observing the deleted data, and
+    // the entry is dropped so it no longer blocks the deletion of the evicted
+    // parts. Returns the ranges that can be deleted now, as
+    // `remove_range_snapshot` does.
+    pub(crate) fn force_complete_eviction(&mut self, range: &CacheRange) -> Vec<CacheRange> {
+        let Some(meta) = self.historical_ranges.remove(range) else {
+            // The last snapshot may have been dropped legitimately between
+            // the stuck report and the force-completion.
+            return vec![];
+        };
+        meta.poison_snapshots();
+        self.ranges_being_deleted
+            .iter()
+            .filter(|evicted_range| {
+                !self
+                    .historical_ranges
+                    .keys()
+                    .any(|r| r.overlaps(evicted_range))
+            })
+            .cloned()
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_historical_evicted_at(&mut self, range: &CacheRange, millis: u64) {
+        self.historical_ranges.get_mut(range).unwrap().evicted_at = millis;
+    }
+
+    // Returns the number of live snapshots and the min/max snapshot ts among
+    // both the cached and the historical ranges.
+    pub(crate) fn snapshot_stats(&self) -> (usize, Option<u64>, Option<u64>) {
+        let mut count = 0;
+        let mut min_ts = None;
+        let mut max_ts = None;
+        for meta in self.ranges.values().chain(self.historical_ranges.values()) {
+            for (ts, ref_count) in &meta.range_snapshot_list().0 {
+                count += *ref_count as usize;
+                min_ts = Some(min_ts.map_or(*ts, |m: u64| m.min(*ts)));
+                max_ts = Some(max_ts.map_or(*ts, |m: u64| m.max(*ts)));
+            }
+        }
+        (count, min_ts, max_ts)
+    }
+
+    /// Collects a point-in-time view of every range the manager tracks, in
+    /// every lifecycle state. The result is sorted by range boundaries and
+    /// state, so repeated calls against the same state produce the same
+    /// order.
+    pub fn ranges_overview(&self) -> Vec<CachedRangeOverview> {
+        let mut overview = Vec::with_capacity(
+            self.ranges.len()
+                + self.historical_ranges.len()
+                + self.ranges_being_deleted.len()
+                + self.pending_ranges.len()
+                + self.pending_ranges_loading_data.len(),
+        );
+        for (range, meta) in &self.ranges {
+            let state = if self.ranges_in_gc.contains(range) {
+                CachedRangeState::InGc
+            } else {
+                CachedRangeState::Active
+            };
+            overview.push(CachedRangeOverview::from_meta(range, state, meta));
+        }
+        for (range, meta) in &self.historical_ranges {
+            overview.push(CachedRangeOverview::from_meta(
+                range,
+                CachedRangeState::Historical,
+                meta,
+            ));
+        }
+        for range in &self.ranges_being_deleted {
+            overview.push(CachedRangeOverview::without_meta(
+                range,
+                CachedRangeState::BeingDeleted,
+            ));
+        }
+        for range in &self.pending_ranges {
+            overview.push(CachedRangeOverview::without_meta(
+                range,
+                CachedRangeState::PendingLoad,
+            ));
+        }
+        for (range, ..) in &self.pending_ranges_loading_data {
+            overview.push(CachedRangeOverview::without_meta(
+                range,
+                CachedRangeState::Loading,
+            ));
+        }
+        // Hex encoding preserves the byte order, so sorting by the encoded
+        // boundaries sorts by the ranges themselves.
+        overview.sort_by(|a, b| (&a.start, &a.end, a.state).cmp(&(&b.start, &b.end, b.state)));
+        overview
+    }
+}
+
+/// The lifecycle state of a range reported by
+/// [`RangeManager::ranges_overview`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CachedRangeState {
+    /// The range is cached and serves reads.
+    Active,
+    /// The range is cached but currently garbage collected.
+    InGc,
+    /// The range is queued for loading.
+    PendingLoad,
+    /// The data of the range is being loaded from a disk snapshot.
+    Loading,
+    /// The range was evicted and waits for its data to be deleted.
+    BeingDeleted,
+    /// The range was evicted but undropped snapshots still pin its data.
+    Historical,
+}
+
+impl CachedRangeState {
+    /// Parses the kebab-case name used in the serialized form, e.g. by the
+    /// state filter of the status server.
+    pub fn from_name(name: &str) -> Option<CachedRangeState> {
+        match name {
+            "active" => Some(CachedRangeState::Active),
+            "in-gc" => Some(CachedRangeState::InGc),
+            "pending-load" => Some(CachedRangeState::PendingLoad),
+            "loading" => Some(CachedRangeState::Loading),
+            "being-deleted" => Some(CachedRangeState::BeingDeleted),
+            "historical" => Some(CachedRangeState::Historical),
+            _ => None,
+        }
+    }
+}
+
+/// A point-in-time view of one range tracked by the engine, see
+/// [`RangeManager::ranges_overview`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedRangeOverview {
+    /// The internal id of the range, 0 for ranges that have no meta yet
+    /// (queued loads and pending deletions).
+    pub id: u64,
+    // Hex-encoded range boundaries so the overview stays readable in JSON.
+    pub start: String,
+    pub end: String,
+    pub state: CachedRangeState,
+    pub safe_point: u64,
+    /// The number of live snapshots on the range.
+    pub snapshots: u64,
+    /// The smallest read ts among the live snapshots.
+    pub min_snapshot_ts: Option<u64>,
+    /// Whether the range is pinned against automatic eviction.
+    pub pinned: bool,
+    /// The unix time in milliseconds when the range was last read, 0 when
+    /// the range has no meta.
+    pub last_access: u64,
+}
+
+impl CachedRangeOverview {
+    fn from_meta(range: &CacheRange, state: CachedRangeState, meta: &RangeMeta) -> Self {
+        CachedRangeOverview {
+            id: meta.id,
+            start: hex::encode_upper(&range.start),
+            end: hex::encode_upper(&range.end),
+            state,
+            safe_point: meta.safe_point,
+            snapshots: meta.range_snapshot_list.0.values().sum(),
+            min_snapshot_ts: meta.range_snapshot_list.min_snapshot_ts(),
+            pinned: meta.pinned,
+            last_access: meta.last_access(),
+        }
+    }
+
+    fn without_meta(range: &CacheRange, state: CachedRangeState) -> Self {
+        CachedRangeOverview {
+            id: 0,
+            start: hex::encode_upper(&range.start),
+            end: hex::encode_upper(&range.end),
+            state,
+            safe_point: 0,
+            snapshots: 0,
+            min_snapshot_ts: None,
+            pinned: false,
+            last_access: 0,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -487,6 +1261,40 @@ pub enum LoadFailedReason {
     PendingRange,
     InGc,
     Evicting,
+    Denied,
+    RawModeKeyspace,
+}
+
+impl LoadFailedReason {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            LoadFailedReason::Overlapped => "overlapped",
+            LoadFailedReason::PendingRange => "pending-range",
+            LoadFailedReason::InGc => "in-gc",
+            LoadFailedReason::Evicting => "evicting",
+            LoadFailedReason::Denied => "denied",
+            LoadFailedReason::RawModeKeyspace => "raw-mode-keyspace",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PinFailedReason {
+    NotCached,
+    CapacityExceeded,
+}
+
+/// Why a [`RangeCacheMemoryEngine::cancel_eviction`] call was refused.
+///
+/// [`RangeCacheMemoryEngine::cancel_eviction`]:
+/// crate::RangeCacheMemoryEngine::cancel_eviction
+#[derive(Debug, PartialEq)]
+pub enum CancelError {
+    /// The range is not awaiting an eviction that could be rolled back.
+    NotEvicting,
+    /// The background deletion has already started removing the data; the
+    /// eviction can only run to completion now.
+    DeletionStarted,
 }
 
 pub enum RangeCacheStatus {
@@ -502,7 +1310,7 @@ mod tests {
     use engine_traits::{CacheRange, FailedReason};
 
     use super::RangeManager;
-    use crate::range_manager::LoadFailedReason;
+    use crate::range_manager::{LoadFailedReason, PinFailedReason};
 
     #[test]
     fn test_range_manager() {
@@ -520,7 +1328,7 @@ mod tests {
         let tmp_r = CacheRange::new(b"k08".to_vec(), b"k15".to_vec());
         assert_eq!(
             range_mgr.range_snapshot(&tmp_r, 8).unwrap_err(),
-            FailedReason::NotCached
+            FailedReason::EpochNotMatch
         );
         let tmp_r = CacheRange::new(b"k10".to_vec(), b"k11".to_vec());
         assert_eq!(
@@ -531,7 +1339,7 @@ mod tests {
         let r_evict = CacheRange::new(b"k03".to_vec(), b"k06".to_vec());
         let r_left = CacheRange::new(b"k00".to_vec(), b"k03".to_vec());
         let r_right = CacheRange::new(b"k06".to_vec(), b"k10".to_vec());
-        range_mgr.evict_range(&r_evict);
+        range_mgr.evict_range(&r_evict, "test");
         let meta1 = range_mgr.historical_ranges.get(&r1).unwrap();
         assert!(range_mgr.ranges_being_deleted.contains(&r_evict));
         assert!(range_mgr.ranges.get(&r1).is_none());
@@ -541,15 +1349,134 @@ mod tests {
 
         // evict a range with accurate match
         let _ = range_mgr.range_snapshot(&r_left, 10);
-        range_mgr.evict_range(&r_left);
+        range_mgr.evict_range(&r_left, "test");
         assert!(range_mgr.historical_ranges.get(&r_left).is_some());
         assert!(range_mgr.ranges_being_deleted.contains(&r_left));
         assert!(range_mgr.ranges.get(&r_left).is_none());
 
-        assert!(range_mgr.evict_range(&r_right).is_empty());
+        assert!(range_mgr.evict_range(&r_right, "test").is_empty());
         assert!(range_mgr.historical_ranges.get(&r_right).is_none());
     }
 
+    #[test]
+    fn test_range_evicted() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        assert!(range_mgr.range_evicted(&r1));
+
+        range_mgr.new_range(r1.clone());
+        assert!(!range_mgr.range_evicted(&r1));
+        // Partial overlap with a cached range also counts.
+        let r_overlap = CacheRange::new(b"k05".to_vec(), b"k15".to_vec());
+        assert!(!range_mgr.range_evicted(&r_overlap));
+
+        // An ongoing snapshot keeps the data around as a historical range, but
+        // new snapshots fail, so the range still counts as evicted.
+        range_mgr.range_snapshot(&r1, 5).unwrap();
+        range_mgr.evict_range(&r1, "test");
+        assert!(range_mgr.historical_ranges.get(&r1).is_some());
+        assert!(range_mgr.range_evicted(&r1));
+        assert_eq!(
+            range_mgr.range_snapshot(&r1, 5).unwrap_err(),
+            FailedReason::NotCached
+        );
+
+        // A pending load may make the range readable later, so it does not
+        // count as evicted until the load is removed or canceled.
+        let r2 = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        range_mgr.load_range(r2.clone()).unwrap();
+        assert!(!range_mgr.range_evicted(&r2));
+    }
+
+    #[test]
+    fn test_safe_point_monotonicity() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        range_mgr.new_range(r1.clone());
+        assert_eq!(range_mgr.safe_point(&r1), Some(0));
+
+        assert!(range_mgr.set_safe_point(&r1, 10));
+        // Setting the same value again is not a regression.
+        assert!(range_mgr.set_safe_point(&r1, 10));
+        // A smaller value is rejected and the safe point is unchanged.
+        assert!(!range_mgr.set_safe_point(&r1, 5));
+        assert_eq!(range_mgr.safe_point(&r1), Some(10));
+        assert_eq!(
+            range_mgr.range_snapshot(&r1, 10).unwrap_err(),
+            FailedReason::TooOldRead
+        );
+        range_mgr.range_snapshot(&r1, 11).unwrap();
+
+        // An uncached range has no safe point.
+        let r_miss = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        assert_eq!(range_mgr.safe_point(&r_miss), None);
+        assert!(!range_mgr.set_safe_point(&r_miss, 10));
+
+        // The load path installs ranges with the disk gc safe point already
+        // applied.
+        let r2 = CacheRange::new(b"k30".to_vec(), b"k40".to_vec());
+        range_mgr.new_range_with_safe_point(r2.clone(), 50);
+        assert_eq!(range_mgr.safe_point(&r2), Some(50));
+        assert!(!range_mgr.set_safe_point(&r2, 40));
+        assert_eq!(
+            range_mgr.range_snapshot(&r2, 50).unwrap_err(),
+            FailedReason::TooOldRead
+        );
+    }
+
+    #[test]
+    fn test_epoch_mismatch() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        range_mgr.new_range(r1.clone());
+        range_mgr.set_safe_point(&r1, 5);
+
+        // Overlapping with a cached range without being contained by it means
+        // the cached boundaries are stale.
+        let r_stale = CacheRange::new(b"k05".to_vec(), b"k15".to_vec());
+        assert_eq!(
+            range_mgr.range_snapshot(&r_stale, 10).unwrap_err(),
+            FailedReason::EpochNotMatch
+        );
+        // A disjoint range is still reported as not cached.
+        let r_miss = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        assert_eq!(
+            range_mgr.range_snapshot(&r_miss, 10).unwrap_err(),
+            FailedReason::NotCached
+        );
+
+        assert!(!range_mgr.record_epoch_mismatch(&r_stale));
+        assert!(!range_mgr.record_epoch_mismatch(&r_stale));
+        assert!(range_mgr.record_epoch_mismatch(&r_stale));
+        // The count is reset once the threshold is reached.
+        assert!(!range_mgr.record_epoch_mismatch(&r_stale));
+    }
+
+    #[test]
+    fn test_cached_watermark() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        range_mgr.new_range(r1.clone());
+
+        assert_eq!(range_mgr.cached_watermark(&r1), None);
+        range_mgr.record_cached_watermark(r1.clone(), 100, 10);
+        assert_eq!(range_mgr.cached_watermark(&r1), Some((100, 10)));
+        // The watermark only moves forward.
+        range_mgr.record_cached_watermark(r1.clone(), 90, 8);
+        assert_eq!(range_mgr.cached_watermark(&r1), Some((100, 10)));
+        range_mgr.record_cached_watermark(r1.clone(), 120, 12);
+        assert_eq!(range_mgr.cached_watermark(&r1), Some((120, 12)));
+
+        // A sub range derived from the recorded one falls back to the
+        // containment scan.
+        let sub = CacheRange::new(b"k02".to_vec(), b"k05".to_vec());
+        assert_eq!(range_mgr.cached_watermark(&sub), Some((120, 12)));
+
+        // Eviction drops the watermark of the range.
+        range_mgr.evict_range(&r1, "test");
+        assert_eq!(range_mgr.cached_watermark(&r1), None);
+    }
+
     #[test]
     fn test_range_load() {
         let mut range_mgr = RangeManager::default();
@@ -559,7 +1486,7 @@ mod tests {
         let r4 = CacheRange::new(b"k25".to_vec(), b"k35".to_vec());
         range_mgr.new_range(r1.clone());
         range_mgr.new_range(r3.clone());
-        range_mgr.evict_range(&r1);
+        range_mgr.evict_range(&r1, "test");
 
         let mut gced = BTreeSet::default();
         gced.insert(r2.clone());
@@ -588,7 +1515,7 @@ mod tests {
         let r2 = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
         let r3 = CacheRange::new(b"k40".to_vec(), b"k50".to_vec());
         range_mgr.new_range(r1.clone());
-        range_mgr.evict_range(&r1);
+        range_mgr.evict_range(&r1, "test");
 
         let mut gced = BTreeSet::default();
         gced.insert(r2);
@@ -640,7 +1567,7 @@ mod tests {
 
             let r4 = CacheRange::new(b"k00".to_vec(), b"k05".to_vec());
             let r5 = CacheRange::new(b"k05".to_vec(), b"k10".to_vec());
-            assert_eq!(range_mgr.evict_range(&r4), vec![r4]);
+            assert_eq!(range_mgr.evict_range(&r4, "test"), vec![r4]);
             assert_eq!(
                 range_mgr.ranges().keys().collect::<Vec<_>>(),
                 vec![&r5, &r2, &r3]
@@ -649,7 +1576,7 @@ mod tests {
             let r6 = CacheRange::new(b"k24".to_vec(), b"k27".to_vec());
             let r7 = CacheRange::new(b"k20".to_vec(), b"k24".to_vec());
             let r8 = CacheRange::new(b"k27".to_vec(), b"k30".to_vec());
-            assert_eq!(range_mgr.evict_range(&r6), vec![r6]);
+            assert_eq!(range_mgr.evict_range(&r6, "test"), vec![r6]);
             assert_eq!(
                 range_mgr.ranges().keys().collect::<Vec<_>>(),
                 vec![&r5, &r7, &r8, &r3]
@@ -669,7 +1596,7 @@ mod tests {
             range_mgr.contains_range(&r3);
 
             let r4 = CacheRange::new(b"k".to_vec(), b"k51".to_vec());
-            assert_eq!(range_mgr.evict_range(&r4), vec![r1, r2, r3]);
+            assert_eq!(range_mgr.evict_range(&r4, "test"), vec![r1, r2, r3]);
             assert!(range_mgr.ranges().is_empty());
         }
 
@@ -683,7 +1610,7 @@ mod tests {
             range_mgr.new_range(r3.clone());
 
             let r4 = CacheRange::new(b"k25".to_vec(), b"k55".to_vec());
-            assert_eq!(range_mgr.evict_range(&r4), vec![r2, r3]);
+            assert_eq!(range_mgr.evict_range(&r4, "test"), vec![r2, r3]);
             assert_eq!(range_mgr.ranges().len(), 1);
         }
 
@@ -697,8 +1624,97 @@ mod tests {
             range_mgr.new_range(r3.clone());
 
             let r4 = CacheRange::new(b"k25".to_vec(), b"k75".to_vec());
-            assert_eq!(range_mgr.evict_range(&r4), vec![r2, r3]);
+            assert_eq!(range_mgr.evict_range(&r4, "test"), vec![r2, r3]);
             assert_eq!(range_mgr.ranges().len(), 1);
         }
     }
+
+    #[test]
+    fn test_pin_range() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        let r2 = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        range_mgr.new_range(r1.clone());
+        range_mgr.new_range(r2.clone());
+
+        // An uncached range cannot be pinned.
+        let r_miss = CacheRange::new(b"k40".to_vec(), b"k50".to_vec());
+        assert_eq!(
+            range_mgr.pin_range(&r_miss, true, 200, 100),
+            Err(PinFailedReason::NotCached)
+        );
+
+        // The budget allows one pin of the expected range size.
+        range_mgr.pin_range(&r1, true, 100, 100).unwrap();
+        assert!(range_mgr.is_pinned(&r1));
+        assert!(!range_mgr.is_pinned(&r2));
+        // Re-pinning an already pinned range does not consume more budget.
+        range_mgr.pin_range(&r1, true, 100, 100).unwrap();
+        // A second pin would exceed the budget.
+        assert_eq!(
+            range_mgr.pin_range(&r2, true, 100, 100),
+            Err(PinFailedReason::CapacityExceeded)
+        );
+
+        // A partial eviction splits the pinned range and the children inherit
+        // the pin, as a region split does.
+        let r_evict = CacheRange::new(b"k03".to_vec(), b"k06".to_vec());
+        let r_left = CacheRange::new(b"k00".to_vec(), b"k03".to_vec());
+        let r_right = CacheRange::new(b"k06".to_vec(), b"k10".to_vec());
+        range_mgr.evict_range(&r_evict, "test");
+        assert!(range_mgr.ranges.get(&r_left).unwrap().is_pinned());
+        assert!(range_mgr.ranges.get(&r_right).unwrap().is_pinned());
+        assert_eq!(range_mgr.pinned_ranges_count(), 2);
+
+        // An explicit eviction still removes a pinned range.
+        assert_eq!(range_mgr.evict_range(&r_left, "test"), vec![r_left.clone()]);
+        assert!(!range_mgr.is_pinned(&r_left));
+
+        // Unpinning frees the budget for other pins.
+        range_mgr.pin_range(&r_right, false, 100, 100).unwrap();
+        assert!(!range_mgr.is_pinned(&r_right));
+        assert_eq!(range_mgr.pinned_ranges_count(), 0);
+        range_mgr.pin_range(&r2, true, 100, 100).unwrap();
+        assert!(range_mgr.is_pinned(&r2));
+    }
+
+    #[test]
+    fn test_denied_ranges() {
+        let mut range_mgr = RangeManager::default();
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        range_mgr.new_range(r1.clone());
+
+        // Loads and snapshots of a denied range are refused.
+        range_mgr.add_denied_range(CacheRange::new(b"k05".to_vec(), b"k15".to_vec()));
+        assert_eq!(
+            range_mgr.range_snapshot(&r1, 10).unwrap_err(),
+            FailedReason::NotCached
+        );
+        assert_eq!(
+            range_mgr.load_range(CacheRange::new(b"k10".to_vec(), b"k12".to_vec())),
+            Err(LoadFailedReason::Denied)
+        );
+        // Ranges disjoint with the deny list are not affected.
+        range_mgr
+            .load_range(CacheRange::new(b"k20".to_vec(), b"k30".to_vec()))
+            .unwrap();
+
+        // Overlapping and adjacent entries are merged.
+        range_mgr.add_denied_range(CacheRange::new(b"k15".to_vec(), b"k18".to_vec()));
+        range_mgr.add_denied_range(CacheRange::new(b"k00".to_vec(), b"k06".to_vec()));
+        assert_eq!(range_mgr.denied_ranges.len(), 1);
+        assert!(range_mgr.is_denied(&CacheRange::new(b"k17".to_vec(), b"k18".to_vec())));
+
+        // Pending loads overlapping with a newly denied range are dropped.
+        range_mgr.add_denied_range(CacheRange::new(b"k25".to_vec(), b"k26".to_vec()));
+        assert!(range_mgr.pending_ranges.is_empty());
+
+        // Removing the entries re-enables admission.
+        range_mgr.remove_denied_range(&CacheRange::new(b"k00".to_vec(), b"k30".to_vec()));
+        assert!(!range_mgr.is_denied(&r1));
+        range_mgr.range_snapshot(&r1, 10).unwrap();
+        range_mgr
+            .load_range(CacheRange::new(b"k10".to_vec(), b"k12".to_vec()))
+            .unwrap();
+    }
 }
@@ -275,12 +275,18 @@ pub mod tests {
     use super::*;
     use crate::RangeCacheEngineConfig;
 
-    struct RegionInfoSimulator {
-        regions: Mutex<TopRegions>,
+    pub struct RegionInfoSimulator {
+        pub regions: Mutex<TopRegions>,
     }
 
     impl RegionInfoSimulator {
-        fn set_top_regions(&self, top_regions: &TopRegions) {
+        pub fn new(regions: TopRegions) -> Self {
+            RegionInfoSimulator {
+                regions: Mutex::new(regions),
+            }
+        }
+
+        pub fn set_top_regions(&self, top_regions: &TopRegions) {
             *self.regions.lock() = top_regions.clone()
         }
     }
@@ -312,7 +318,7 @@ pub mod tests {
         }
     }
 
-    fn new_region(id: u64, start_key: &[u8], end_key: &[u8], version: u64) -> Region {
+    pub fn new_region(id: u64, start_key: &[u8], end_key: &[u8], version: u64) -> Region {
         let mut region = Region::default();
         region.set_id(id);
         region.set_start_key(start_key.to_vec());
@@ -1,15 +1,24 @@
 // Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
 
 use core::slice::SlicePattern;
-use std::{fmt::Debug, ops::Deref, result, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    ops::Deref,
+    result,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use bytes::Bytes;
 use crossbeam::epoch::{self};
 use engine_rocks::{raw::SliceTransform, util::FixedSuffixSliceTransform};
 use engine_traits::{
-    CacheRange, CfNamesExt, DbVector, Error, FailedReason, IterMetricsCollector, IterOptions,
+    CacheRange, CfNamesExt, Code, DbVector, Error, FailedReason, IterMetricsCollector, IterOptions,
     Iterable, Iterator, MetricsExt, Peekable, ReadOptions, Result, Snapshot, SnapshotMiscExt,
-    CF_DEFAULT,
+    Status, CF_DEFAULT, CF_LOCK,
 };
 use prometheus::local::LocalHistogram;
 use skiplist_rs::{base::OwnedIter, SkipList};
@@ -18,15 +27,20 @@ use tikv_util::{box_err, time::Instant};
 
 use crate::{
     background::BackgroundTask,
+    compression::decode_value,
     engine::{cf_to_id, SkiplistEngine},
     keys::{
-        decode_key, encode_seek_for_prev_key, encode_seek_key, InternalBytes, InternalKey,
-        ValueType,
+        decode_key, encode_seek_for_prev_key, encode_seek_key, is_raw_mode_data_key, InternalBytes,
+        InternalKey, ValueType,
+    },
+    memory_controller::{MemoryController, MemoryUsage},
+    metrics::{
+        IN_MEMORY_ENGINE_SEEK_DURATION, RANGE_CACHE_ITERATOR_BUFFER_BYTES,
+        RANGE_CACHE_ITERATOR_COUNT,
     },
-    metrics::IN_MEMORY_ENGINE_SEEK_DURATION,
     perf_context::PERF_CONTEXT,
     perf_counter_add,
-    statistics::{LocalStatistics, Statistics, Tickers},
+    statistics::{record_seek_latency, LocalStatistics, LocalStatsSink, Statistics, Tickers},
     RangeCacheMemoryEngine,
 };
 
@@ -34,6 +48,21 @@ use crate::{
 // for search.
 pub const MAX_SEQUENCE_NUMBER: u64 = (1 << 56) - 1;
 
+// After this many consecutive `next()` calls without a seek or direction
+// change, the iterator starts prefetching raw entries from the skiplist in
+// batches to amortize the per-entry pointer chasing of long scans.
+const PREFETCH_AFTER_SEQUENTIAL_NEXTS: usize = 8;
+
+// Iterator-held buffers at or below this size are considered part of the
+// iterator itself and are not registered with the memory controller, so small
+// point reads stay free of accounting traffic.
+const ITER_BUFFER_ACCOUNT_THRESHOLD: usize = 256;
+
+// When the capacity of `saved_user_key` exceeds this and the key being saved
+// is much smaller, the buffer is shrunk back so that one huge key seen long
+// ago does not pin memory for the rest of a long-lived iterator's life.
+const SAVED_USER_KEY_SHRINK_THRESHOLD: usize = 4096;
+
 #[derive(PartialEq)]
 enum Direction {
     Uninit,
@@ -41,6 +70,24 @@ enum Direction {
     Backward,
 }
 
+// The backward half of the direction-switch cache: everything needed to put
+// the iterator straight back on the entry it exposed while moving backward.
+// The `Bytes` are shallow clones that keep the allocations alive even if a
+// concurrent gc physically removes the nodes, see `restore_backward_position`.
+struct BackwardSwitchCache {
+    // The user key the backward iterator exposed.
+    user_key: Vec<u8>,
+    // The visible value of `user_key` at that point.
+    value: Bytes,
+    // The exact internal key the underlying iterator was parked on, i.e. the
+    // last entry before the entries of `user_key`.
+    parked_internal_key: Bytes,
+    // The user key exposed right after switching to forward. The cache is
+    // only usable by a `prev()` issued while the iterator still exposes this
+    // key; any further movement invalidates it.
+    next_user_key: Vec<u8>,
+}
+
 #[derive(Clone, Debug)]
 pub struct RangeCacheSnapshotMeta {
     pub(crate) range_id: u64,
@@ -49,17 +96,33 @@ pub struct RangeCacheSnapshotMeta {
     // Sequence number is shared between RangeCacheEngine and disk KvEnigne to
     // provide atomic write
     pub(crate) sequence_number: u64,
+    // The tombstone flag shared with the range meta the snapshot was acquired
+    // on. The eviction watchdog sets it when it force-completes an eviction
+    // this snapshot blocks; reads through the snapshot must fail from then on
+    // because the data it pins is being deleted.
+    tombstone: Arc<AtomicBool>,
 }
 
 impl RangeCacheSnapshotMeta {
-    fn new(range_id: u64, range: CacheRange, snapshot_ts: u64, sequence_number: u64) -> Self {
+    fn new(
+        range_id: u64,
+        range: CacheRange,
+        snapshot_ts: u64,
+        sequence_number: u64,
+        tombstone: Arc<AtomicBool>,
+    ) -> Self {
         Self {
             range_id,
             range,
             snapshot_ts,
             sequence_number,
+            tombstone,
         }
     }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.tombstone.load(Ordering::Acquire)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -77,13 +140,42 @@ impl RangeCacheSnapshot {
         seq_num: u64,
     ) -> result::Result<Self, FailedReason> {
         let mut core = engine.core.write();
-        let range_id = core.range_manager.range_snapshot(&range, read_ts)?;
+        let (range_id, tombstone) = match core.range_manager.range_snapshot(&range, read_ts) {
+            Ok(granted) => granted,
+            Err(FailedReason::EpochNotMatch) => {
+                // The cached boundaries no longer match the region the read
+                // was routed with, which indicates region events (split,
+                // merge) were missed. After repeated mismatches evict the
+                // stale ranges so they can be reloaded with the current
+                // boundaries.
+                if core.range_manager.record_epoch_mismatch(&range) {
+                    let ranges_to_delete =
+                        core.range_manager.evict_range(&range, "epoch_not_match");
+                    if !ranges_to_delete.is_empty() {
+                        drop(core);
+                        engine.schedule_evicted_ranges(ranges_to_delete, "epoch_not_match");
+                    }
+                }
+                return Err(FailedReason::EpochNotMatch);
+            }
+            Err(reason) => return Err(reason),
+        };
         Ok(RangeCacheSnapshot {
-            snapshot_meta: RangeCacheSnapshotMeta::new(range_id, range, read_ts, seq_num),
+            snapshot_meta: RangeCacheSnapshotMeta::new(range_id, range, read_ts, seq_num, tombstone),
             skiplist_engine: core.engine.clone(),
             engine: engine.clone(),
         })
     }
+
+    // Returns the poisoned-snapshot error shared by the read paths, see
+    // `RangeCacheSnapshotMeta::tombstone`.
+    fn poisoned_error(&self) -> Error {
+        Error::Other(box_err!(
+            "the snapshot of range [{}, {}] has been poisoned by a force-completed eviction",
+            log_wrappers::Value(&self.snapshot_meta.range.start),
+            log_wrappers::Value(&self.snapshot_meta.range.end)
+        ))
+    }
 }
 
 impl Drop for RangeCacheSnapshot {
@@ -111,13 +203,45 @@ impl Drop for RangeCacheSnapshot {
 
 impl Snapshot for RangeCacheSnapshot {}
 
-impl Iterable for RangeCacheSnapshot {
-    type Iterator = RangeCacheIterator;
+impl RangeCacheSnapshot {
+    /// Same as `iterator_opt`, but the iterator additionally flushes its read
+    /// counters into the given sink when it is dropped, so the caller can
+    /// attribute the reads of one request (e.g. for scan details) while the
+    /// engine-global statistics keep being updated as usual.
+    pub fn iterator_opt_with_stats(
+        &self,
+        cf: &str,
+        opts: IterOptions,
+        sink: Arc<LocalStatsSink>,
+    ) -> Result<RangeCacheIterator> {
+        self.iterator_opt_impl(cf, opts, Some(sink))
+    }
 
-    fn iterator_opt(&self, cf: &str, opts: IterOptions) -> Result<Self::Iterator> {
+    fn iterator_opt_impl(
+        &self,
+        cf: &str,
+        opts: IterOptions,
+        stats_sink: Option<Arc<LocalStatsSink>>,
+    ) -> Result<RangeCacheIterator> {
+        fail::fail_point!("on_range_cache_iterator_opt", |_| {
+            Err(Error::Other(box_err!(
+                "injected range cache engine iterator error"
+            )))
+        });
+        if self.snapshot_meta.is_poisoned() {
+            return Err(self.poisoned_error());
+        }
         let iter = self.skiplist_engine.data[cf_to_id(cf)].owned_iter();
         let prefix_extractor = if opts.prefix_same_as_start() {
-            Some(FixedSuffixSliceTransform::new(8))
+            // Txn-mode keys end with an 8-byte mvcc timestamp, and the API v2
+            // keyspace prefix, if any, is naturally part of the extracted
+            // prefix. Raw-mode keys carry no timestamp suffix, so the whole
+            // key is the prefix.
+            if is_raw_mode_data_key(&self.snapshot_meta.range.start) {
+                Some(FixedSuffixSliceTransform::new(0))
+            } else {
+                Some(FixedSuffixSliceTransform::new(8))
+            }
         } else {
             None
         };
@@ -132,16 +256,19 @@ impl Iterable for RangeCacheSnapshot {
         if lower_bound < self.snapshot_meta.range.start
             || upper_bound > self.snapshot_meta.range.end
         {
-            return Err(Error::Other(box_err!(
-                "the bounderies required [{}, {}] exceeds the range of the snapshot [{}, {}]",
-                log_wrappers::Value(&lower_bound),
-                log_wrappers::Value(&upper_bound),
-                log_wrappers::Value(&self.snapshot_meta.range.start),
-                log_wrappers::Value(&self.snapshot_meta.range.end)
-            )));
+            let key = if lower_bound < self.snapshot_meta.range.start {
+                lower_bound
+            } else {
+                upper_bound
+            };
+            return Err(Error::KeyOutOfSnapshotRange {
+                key,
+                start: self.snapshot_meta.range.start.clone(),
+                end: self.snapshot_meta.range.end.clone(),
+            });
         }
 
-        Ok(RangeCacheIterator {
+        let iter = RangeCacheIterator {
             valid: false,
             prefix: None,
             lower_bound,
@@ -150,12 +277,139 @@ impl Iterable for RangeCacheSnapshot {
             sequence_number: self.sequence_number(),
             saved_user_key: vec![],
             saved_value: None,
+            values_framed: self.engine.config().value().value_compression.is_enabled(),
+            current_value: None,
+            key_only: opts.key_only(),
             direction: Direction::Uninit,
+            prefetch_buffer: VecDeque::new(),
+            current_prefetched: None,
+            forward_switch_cache: None,
+            backward_switch_cache: None,
+            sequential_nexts: 0,
+            prefetch_size: self.engine.config().value().iterator_prefetch_size,
             statistics: self.engine.statistics(),
             prefix_extractor,
+            max_skippable_internal_keys: opts.max_skippable_internal_keys(),
+            internal_keys_skipped: 0,
+            incomplete: false,
             local_stats: LocalStatistics::default(),
+            stats_sink,
             seek_duration: IN_MEMORY_ENGINE_SEEK_DURATION.local(),
-        })
+            memory_controller: self.engine.memory_controller(),
+            tracked_buffer_bytes: 0,
+            snapshot_meta: self.snapshot_meta.clone(),
+        };
+        RANGE_CACHE_ITERATOR_COUNT.inc();
+        Ok(iter)
+    }
+
+    /// Reads the values of several lock cf keys with a single forward pass
+    /// over the skiplist.
+    ///
+    /// Lock cf keys carry no mvcc suffix and at most one visible version, so
+    /// a batch that is sorted in user-key order is also sorted in encoded
+    /// order and can be resolved by one seek followed by `next()`s, instead
+    /// of one seek per key. Batches from the transaction layer are usually
+    /// already sorted; unsorted ones are visited through a sorted index so
+    /// the result still lines up with the input positionally.
+    ///
+    /// Returns one entry per key, in the input order, with `None` marking a
+    /// key that has no lock. A key outside the snapshot range fails the whole
+    /// batch, like `get_value_cf_opt` does for a single key.
+    pub fn multi_get_locks(&self, keys: &[&[u8]]) -> Result<Vec<Option<RangeCacheDbVector>>> {
+        fail::fail_point!("on_range_cache_get_value", |_| {
+            Err(Error::Other(box_err!(
+                "injected range cache engine read error"
+            )))
+        });
+        if self.snapshot_meta.is_poisoned() {
+            return Err(self.poisoned_error());
+        }
+        for key in keys {
+            if !self.snapshot_meta.range.contains_key(key) {
+                return Err(Error::KeyOutOfSnapshotRange {
+                    key: key.to_vec(),
+                    start: self.snapshot_meta.range.start.clone(),
+                    end: self.snapshot_meta.range.end.clone(),
+                });
+            }
+        }
+        let mut results: Vec<Option<RangeCacheDbVector>> = (0..keys.len()).map(|_| None).collect();
+        if keys.is_empty() {
+            return Ok(results);
+        }
+        // The common case is an already sorted batch, for which the index
+        // sort is skipped entirely.
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        if !keys.windows(2).all(|w| w[0] <= w[1]) {
+            order.sort_unstable_by_key(|&i| keys[i]);
+        }
+
+        let seq = self.sequence_number();
+        let framed = self.engine.config().value().value_compression.is_enabled();
+        let mut iter = self.skiplist_engine.data[cf_to_id(CF_LOCK)].owned_iter();
+        let guard = &epoch::pin();
+        let mut nexts = 0u64;
+        let mut bytes_read = 0u64;
+        let mut seeked = false;
+        for &i in &order {
+            let key = keys[i];
+            if !seeked {
+                iter.seek(&encode_seek_key(key, seq), guard);
+                seeked = true;
+            } else {
+                // Advance to the newest visible entry of `key`. The iterator
+                // is never moved past a matched entry, so a duplicate key in
+                // the batch resolves against the same position.
+                while iter.valid() {
+                    let InternalKey {
+                        user_key, sequence, ..
+                    } = decode_key(iter.key().as_slice());
+                    if user_key > key || (user_key == key && sequence <= seq) {
+                        break;
+                    }
+                    iter.next(guard);
+                    nexts += 1;
+                }
+            }
+            if !iter.valid() {
+                continue;
+            }
+            match decode_key(iter.key().as_slice()) {
+                InternalKey {
+                    user_key,
+                    v_type: ValueType::Value,
+                    ..
+                } if user_key == key => {
+                    // Shallow clone, see `get_value_cf_opt` for why this is
+                    // safe against concurrent evictions.
+                    let value = iter.value().clone_bytes();
+                    let value = if framed { decode_value(value)? } else { value };
+                    bytes_read += value.len() as u64;
+                    results[i] = Some(RangeCacheDbVector(value));
+                }
+                _ => {}
+            }
+        }
+
+        let statistics = self.engine.statistics();
+        statistics.record_ticker(Tickers::NumberDbSeek, 1);
+        if nexts > 0 {
+            statistics.record_ticker(Tickers::NumberDbNext, nexts);
+        }
+        if bytes_read > 0 {
+            statistics.record_ticker(Tickers::BytesRead, bytes_read);
+            perf_counter_add!(get_read_bytes, bytes_read);
+        }
+        Ok(results)
+    }
+}
+
+impl Iterable for RangeCacheSnapshot {
+    type Iterator = RangeCacheIterator;
+
+    fn iterator_opt(&self, cf: &str, opts: IterOptions) -> Result<Self::Iterator> {
+        self.iterator_opt_impl(cf, opts, None)
     }
 }
 
@@ -166,20 +420,32 @@ impl Peekable for RangeCacheSnapshot {
         self.get_value_cf_opt(opts, CF_DEFAULT, key)
     }
 
+    // The read options are accepted for API parity with the disk engine but
+    // cannot take effect here: there is no block cache for `fill_cache` to
+    // steer, and the sequence-encoded seek key positions the lookup directly
+    // at the newest visible version of `key`, so unlike an iterator seek a
+    // point lookup never walks invisible versions or tombstones and the
+    // `max_skippable_internal_keys` budget cannot be exceeded.
     fn get_value_cf_opt(
         &self,
-        _: &ReadOptions,
+        _opts: &ReadOptions,
         cf: &str,
         key: &[u8],
     ) -> Result<Option<Self::DbVector>> {
-        fail::fail_point!("on_range_cache_get_value");
+        fail::fail_point!("on_range_cache_get_value", |_| {
+            Err(Error::Other(box_err!(
+                "injected range cache engine read error"
+            )))
+        });
+        if self.snapshot_meta.is_poisoned() {
+            return Err(self.poisoned_error());
+        }
         if !self.snapshot_meta.range.contains_key(key) {
-            return Err(Error::Other(box_err!(
-                "key {} not in range[{}, {}]",
-                log_wrappers::Value(key),
-                log_wrappers::Value(&self.snapshot_meta.range.start),
-                log_wrappers::Value(&self.snapshot_meta.range.end)
-            )));
+            return Err(Error::KeyOutOfSnapshotRange {
+                key: key.to_vec(),
+                start: self.snapshot_meta.range.start.clone(),
+                end: self.snapshot_meta.range.end.clone(),
+            });
         }
         let mut iter = self.skiplist_engine.data[cf_to_id(cf)].owned_iter();
         let seek_key = encode_seek_key(key, self.sequence_number());
@@ -196,7 +462,22 @@ impl Peekable for RangeCacheSnapshot {
                 v_type: ValueType::Value,
                 ..
             } if user_key == key => {
+                // `clone_bytes` is a shallow `Bytes::clone`, i.e. a refcount
+                // bump rather than a copy of the value. It also pins the value
+                // allocation independently of the skiplist node, so a
+                // concurrent eviction that physically removes the node cannot
+                // free the bytes while the returned `RangeCacheDbVector` is
+                // alive.
                 let value = iter.value().clone_bytes();
+                // Framed when value compression is enabled: raw frames are
+                // unwrapped zero-copy, compressed ones are decompressed into
+                // an owned buffer. Corrupted frames fail the read. See the
+                // `compression` module.
+                let value = if self.engine.config().value().value_compression.is_enabled() {
+                    decode_value(value)?
+                } else {
+                    value
+                };
                 self.engine
                     .statistics()
                     .record_ticker(Tickers::BytesRead, value.len() as u64);
@@ -206,6 +487,18 @@ impl Peekable for RangeCacheSnapshot {
             _ => Ok(None),
         }
     }
+
+    // Only the lock cf benefits from a batched read: its keys carry no mvcc
+    // suffix, so the caller's key order matches the skiplist order and one
+    // forward pass serves the whole batch, see `multi_get_locks`. Keys of the
+    // other data cfs are suffixed with timestamps, so a sorted batch of user
+    // keys is not sorted in encoded form and falls back to per-key gets.
+    fn multi_get_value_cf(&self, cf: &str, keys: &[&[u8]]) -> Result<Vec<Option<Self::DbVector>>> {
+        if cf == CF_LOCK {
+            return self.multi_get_locks(keys);
+        }
+        keys.iter().map(|key| self.get_value_cf(cf, key)).collect()
+    }
 }
 
 impl CfNamesExt for RangeCacheSnapshot {
@@ -220,6 +513,168 @@ impl SnapshotMiscExt for RangeCacheSnapshot {
     }
 }
 
+/// One item yielded by [`MultiRangeIterator`].
+#[derive(Debug, PartialEq)]
+pub enum MultiRangeItem {
+    /// The next cached key-value pair, in global key order.
+    Entry(Vec<u8>, Vec<u8>),
+    /// A span of the requested range with no cached data. The caller serves
+    /// it from the disk engine.
+    Gap(Vec<u8>, Vec<u8>),
+}
+
+// The iteration plan of a `MultiRangeIterator`, computed at creation: the
+// cached segments (clamped to the requested span) interleaved with the
+// uncached gaps between them, in key order.
+enum MultiRangeSegment {
+    // Index into `MultiRangeIterator::snapshots` plus the clamped bounds to
+    // iterate within that snapshot.
+    Cached(usize, Vec<u8>, Vec<u8>),
+    Gap(Vec<u8>, Vec<u8>),
+}
+
+/// An iterator over all cached data of one cf within `[start, end)`, stitched
+/// across the cached ranges the span overlaps. See
+/// [`RangeCacheMemoryEngine::multi_range_iterator`].
+///
+/// Yields [`MultiRangeItem`]s in global key order: the cached key-value pairs
+/// of each overlapping range, with a `Gap` item for every uncached part of the
+/// span so the caller knows exactly what to read from the disk engine.
+///
+/// The per-range snapshots are held for the whole life of the iterator and
+/// released when it is dropped, so an eviction requested while the iteration
+/// is in flight only takes effect (i.e. the data is only physically deleted)
+/// after the drop, like for any other undropped snapshot.
+pub struct MultiRangeIterator {
+    // One snapshot per cached range the span overlaps, in key order. Kept
+    // alive until the iterator is dropped; each drop releases the pin on its
+    // range as usual.
+    snapshots: Vec<RangeCacheSnapshot>,
+    segments: VecDeque<MultiRangeSegment>,
+    // The iterator over the current cached segment, if any.
+    current: Option<RangeCacheIterator>,
+    cf: String,
+}
+
+impl MultiRangeIterator {
+    pub(crate) fn new(
+        engine: &RangeCacheMemoryEngine,
+        cf: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Self {
+        let span = CacheRange::new(start.to_vec(), end.to_vec());
+        // All snapshots are granted under one core lock, so they observe the
+        // same committed writes: nothing can commit to one range between two
+        // grants. With `MAX_SEQUENCE_NUMBER` they all expose the newest
+        // committed version, which makes the stitched view consistent across
+        // ranges.
+        let snapshots = if start < end {
+            let mut core = engine.core.write();
+            let overlapping: Vec<_> = core
+                .range_manager
+                .ranges()
+                .keys()
+                .filter(|r| r.overlaps(&span))
+                .cloned()
+                .collect();
+            let mut snapshots = Vec::with_capacity(overlapping.len());
+            for range in overlapping {
+                // A cached range that cannot be snapshot (e.g. it is on the
+                // deny list) is treated as uncached, so it surfaces as part
+                // of a gap and the caller reads it from disk.
+                if let Ok((range_id, tombstone)) =
+                    core.range_manager.range_snapshot(&range, u64::MAX)
+                {
+                    snapshots.push(RangeCacheSnapshot {
+                        snapshot_meta: RangeCacheSnapshotMeta::new(
+                            range_id,
+                            range,
+                            u64::MAX,
+                            MAX_SEQUENCE_NUMBER,
+                            tombstone,
+                        ),
+                        skiplist_engine: core.engine.clone(),
+                        engine: engine.clone(),
+                    });
+                }
+            }
+            snapshots
+        } else {
+            vec![]
+        };
+
+        // The ranges come out of the range manager sorted and non-overlapping,
+        // so walking them in order and filling the holes with gaps yields the
+        // plan already in global key order.
+        let mut segments = VecDeque::with_capacity(snapshots.len() * 2 + 1);
+        let mut cursor = start.to_vec();
+        for (idx, snapshot) in snapshots.iter().enumerate() {
+            let range = &snapshot.snapshot_meta.range;
+            let seg_start = std::cmp::max(range.start.as_slice(), start).to_vec();
+            let seg_end = std::cmp::min(range.end.as_slice(), end).to_vec();
+            if cursor < seg_start {
+                segments.push_back(MultiRangeSegment::Gap(cursor, seg_start.clone()));
+            }
+            cursor = seg_end.clone();
+            segments.push_back(MultiRangeSegment::Cached(idx, seg_start, seg_end));
+        }
+        if start < end && cursor.as_slice() < end {
+            segments.push_back(MultiRangeSegment::Gap(cursor, end.to_vec()));
+        }
+
+        MultiRangeIterator {
+            snapshots,
+            segments,
+            current: None,
+            cf: cf.to_owned(),
+        }
+    }
+}
+
+impl std::iter::Iterator for MultiRangeIterator {
+    type Item = Result<MultiRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(iter) = self.current.as_mut() {
+                match iter.valid() {
+                    Ok(true) => {
+                        let item = MultiRangeItem::Entry(iter.key().to_vec(), iter.value().to_vec());
+                        if let Err(e) = iter.next() {
+                            self.current = None;
+                            return Some(Err(e));
+                        }
+                        return Some(Ok(item));
+                    }
+                    Ok(false) => self.current = None,
+                    Err(e) => {
+                        self.current = None;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            match self.segments.pop_front()? {
+                MultiRangeSegment::Gap(start, end) => {
+                    return Some(Ok(MultiRangeItem::Gap(start, end)));
+                }
+                MultiRangeSegment::Cached(idx, seg_start, seg_end) => {
+                    let mut iter_opt = IterOptions::default();
+                    iter_opt.set_lower_bound(&seg_start, 0);
+                    iter_opt.set_upper_bound(&seg_end, 0);
+                    match self.snapshots[idx].iterator_opt(&self.cf, iter_opt) {
+                        Ok(mut iter) => match iter.seek_to_first() {
+                            Ok(_) => self.current = Some(iter),
+                            Err(e) => return Some(Err(e)),
+                        },
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub struct RangeCacheIterator {
     valid: bool,
     iter: OwnedIter<Arc<SkipList<InternalBytes, InternalBytes>>, InternalBytes, InternalBytes>,
@@ -235,6 +690,18 @@ pub struct RangeCacheIterator {
     // This is only used by backwawrd iteration where the value we want may not be pointed by the
     // `iter`
     saved_value: Option<Bytes>,
+    // Whether the stored values are framed by the value compression, fixed
+    // at creation from the engine config. See the `compression` module.
+    values_framed: bool,
+    // The decoded value at the current position, maintained by
+    // `update_current_value` whenever `values_framed` is set, so `value()`
+    // can keep handing out a plain slice.
+    current_value: Option<Bytes>,
+    // Key-only iteration, see `IterOptions::key_only`. Values are neither
+    // cloned in backward iteration nor counted in the read statistics, and
+    // `value()` returns an empty slice. Entry types are still interpreted so
+    // deletions shadow older versions exactly as in a normal scan.
+    key_only: bool,
 
     // Not None means we are performing prefix seek
     // Note: prefix_seek doesn't support seek_to_first and seek_to_last.
@@ -243,9 +710,68 @@ pub struct RangeCacheIterator {
 
     direction: Direction,
 
+    // Raw internal entries pulled from the skiplist in one batch under a
+    // single epoch pin once a sequential forward scan is detected, so that
+    // subsequent `next()` calls are served without chasing skiplist pointers.
+    // The `Bytes` are shallow clones that keep the allocations alive
+    // independently of the skiplist nodes, so the buffer stays safe to read
+    // after the pin is dropped even if a concurrent gc physically removes the
+    // nodes. Visibility filtering is still applied entry by entry when the
+    // buffer is consumed. Invalidated by seeks and direction changes.
+    prefetch_buffer: VecDeque<(Bytes, Bytes)>,
+    // The buffered entry the iterator currently exposes, present only when
+    // the current position was served from `prefetch_buffer`. The underlying
+    // iterator is then parked at the last prefetched entry.
+    current_prefetched: Option<(Bytes, Bytes)>,
+    // The exact internal key (user key + sequence) the iterator exposed in
+    // forward direction when it reversed to backward. The backward-to-forward
+    // switch of an alternating next()/prev() workload seeks straight back to
+    // it instead of re-seeking the saved user key with `MAX_SEQUENCE_NUMBER`
+    // and walking over all of its versions, see `restore_forward_position`.
+    // Only valid across a single `prev()`; a second one, or any seek, clears
+    // it.
+    forward_switch_cache: Option<Bytes>,
+    // The counterpart for the forward-to-backward switch, captured when the
+    // iterator reverses to forward, see `restore_backward_position`.
+    backward_switch_cache: Option<BackwardSwitchCache>,
+
+    // Consecutive `next()` calls since the last seek or direction change.
+    sequential_nexts: usize,
+    // Max entries prefetched in one batch, 0 disables prefetching.
+    prefetch_size: usize,
+
+    // The per-operation budget for internal keys that may be examined and
+    // passed over (invisible versions, tombstones, older versions of an
+    // already yielded user key) before the operation fails, mirroring the
+    // RocksDB `max_skippable_internal_keys` read option. 0 disables the
+    // check.
+    max_skippable_internal_keys: u64,
+    // Internal keys skipped by the current operation, reset on every seek,
+    // next and prev call.
+    internal_keys_skipped: u64,
+    // Set when the current operation exhausted the skip budget; the
+    // operation then fails with an `Incomplete` engine error.
+    incomplete: bool,
+
     statistics: Arc<Statistics>,
     local_stats: LocalStatistics,
+    // When set, the local counters are also flushed into this caller-supplied
+    // sink on drop, see `RangeCacheSnapshot::iterator_opt_with_stats`.
+    stats_sink: Option<Arc<LocalStatsSink>>,
     seek_duration: LocalHistogram,
+
+    memory_controller: Arc<MemoryController>,
+    // The bytes of `saved_user_key` and `saved_value` currently registered
+    // with the memory controller and the iterator buffer gauge. Coprocessor
+    // sessions can hold thousands of iterators for a long time, so these
+    // buffers are real memory that must show up as cache pressure. Updated
+    // only when the held size changes, see `update_buffer_accounting`.
+    tracked_buffer_bytes: usize,
+
+    // The meta of the snapshot the iterator was created on, used to fail
+    // seeks once the snapshot has been poisoned by a force-completed
+    // eviction, see `RangeCacheSnapshotMeta::tombstone`.
+    snapshot_meta: RangeCacheSnapshotMeta,
 }
 
 impl Drop for RangeCacheIterator {
@@ -270,12 +796,110 @@ impl Drop for RangeCacheIterator {
             Tickers::NumberDbPrevFound,
             self.local_stats.number_db_prev_found,
         );
+        self.statistics.record_ticker(
+            Tickers::IterDirectionSwitchFast,
+            self.local_stats.number_direction_switch_fast,
+        );
+        self.statistics.record_ticker(
+            Tickers::IterDirectionSwitchSlow,
+            self.local_stats.number_direction_switch_slow,
+        );
         perf_counter_add!(iter_read_bytes, self.local_stats.bytes_read);
+        if let Some(sink) = &self.stats_sink {
+            sink.add(&self.local_stats);
+        }
         self.seek_duration.flush();
+        RANGE_CACHE_ITERATOR_COUNT.dec();
+        if self.tracked_buffer_bytes > 0 {
+            self.memory_controller.release(self.tracked_buffer_bytes);
+            RANGE_CACHE_ITERATOR_BUFFER_BYTES.sub(self.tracked_buffer_bytes as i64);
+        }
+    }
+}
+
+// Saves `user_key` into the reusable buffer. A free function rather than a
+// method because callers hold a borrow of the underlying iterator's key.
+// Shrinks an oversized buffer first, see `SAVED_USER_KEY_SHRINK_THRESHOLD`.
+fn save_user_key(buf: &mut Vec<u8>, user_key: &[u8]) {
+    buf.clear();
+    if buf.capacity() > SAVED_USER_KEY_SHRINK_THRESHOLD && user_key.len() <= buf.capacity() / 4 {
+        buf.shrink_to(user_key.len());
     }
+    buf.extend_from_slice(user_key);
 }
 
 impl RangeCacheIterator {
+    /// Returns the number of internal keys the last operation examined and
+    /// passed over, so the storage layer can feed its perf statistics the
+    /// same way it does for RocksDB iterators.
+    pub fn internal_keys_skipped(&self) -> u64 {
+        self.internal_keys_skipped
+    }
+
+    // Closes out the timing of one seek operation: the duration lands in the
+    // histogram and in the process-wide reservoir that paces background
+    // deletions, see `statistics::record_seek_latency`. The failpoint lets
+    // tests inflate the measured latency.
+    fn observe_seek_duration(&mut self, begin: Instant) {
+        fail::fail_point!("range_cache_on_seek");
+        let elapsed = begin.saturating_elapsed();
+        self.seek_duration.observe(elapsed.as_secs_f64());
+        record_seek_latency(elapsed);
+    }
+
+    // Every user-facing operation starts with a fresh skip budget.
+    fn reset_skipped_internal_keys(&mut self) {
+        self.internal_keys_skipped = 0;
+        self.incomplete = false;
+    }
+
+    // Charges one internal key that was examined and passed over against the
+    // per-operation skip budget. Returns false once the budget is exhausted;
+    // the iterator is then invalidated so a key buried under a huge pile of
+    // invisible versions or tombstones stops the operation after bounded work
+    // instead of silently walking all of them.
+    fn charge_skipped_internal_key(&mut self) -> bool {
+        self.internal_keys_skipped += 1;
+        if self.max_skippable_internal_keys > 0
+            && self.internal_keys_skipped > self.max_skippable_internal_keys
+        {
+            self.incomplete = true;
+            self.valid = false;
+            return false;
+        }
+        true
+    }
+
+    // Translates an exhausted skip budget into the same `Incomplete` status
+    // RocksDB returns for `max_skippable_internal_keys`, so the upper layer
+    // can produce its existing "too many versions, please gc" signal.
+    fn check_incomplete(&self) -> Result<()> {
+        if self.incomplete {
+            return Err(Error::Engine(Status::with_error(
+                Code::Incomplete,
+                format!(
+                    "too many internal keys skipped: {}",
+                    self.internal_keys_skipped
+                ),
+            )));
+        }
+        Ok(())
+    }
+
+    // A single atomic load, checked on seeks only: next/prev between two
+    // seeks read nodes the poisoned snapshot's shallow `Bytes` clones still
+    // pin, so they stay safe without paying the check per step.
+    fn check_poisoned(&self) -> Result<()> {
+        if self.snapshot_meta.is_poisoned() {
+            return Err(Error::Other(box_err!(
+                "the snapshot of range [{}, {}] has been poisoned by a force-completed eviction",
+                log_wrappers::Value(&self.snapshot_meta.range.start),
+                log_wrappers::Value(&self.snapshot_meta.range.end)
+            )));
+        }
+        Ok(())
+    }
+
     // If `skipping_saved_key` is true, the function will keep iterating until it
     // finds a user key that is larger than `saved_user_key`.
     // If `prefix` is not None, the iterator needs to stop when all keys for the
@@ -303,9 +927,10 @@ impl RangeCacheIterator {
                 if skip_saved_key && user_key == self.saved_user_key.as_slice() {
                     // the user key has been met before, skip it.
                     perf_counter_add!(internal_key_skipped_count, 1);
+                    self.local_stats.internal_keys_skipped += 1;
                 } else {
-                    self.saved_user_key.clear();
-                    self.saved_user_key.extend_from_slice(user_key);
+                    save_user_key(&mut self.saved_user_key, user_key);
+                    self.update_buffer_accounting();
                     // self.saved_user_key =
                     // Key::from_encoded(user_key.to_vec()).into_raw().unwrap();
 
@@ -313,6 +938,7 @@ impl RangeCacheIterator {
                         ValueType::Deletion => {
                             skip_saved_key = true;
                             perf_counter_add!(internal_delete_skipped_count, 1);
+                            self.local_stats.tombstones_skipped += 1;
                         }
                         ValueType::Value => {
                             self.valid = true;
@@ -325,6 +951,9 @@ impl RangeCacheIterator {
                 skip_saved_key = false;
             }
 
+            if !self.charge_skipped_internal_key() {
+                return;
+            }
             self.iter.next(guard);
         }
 
@@ -335,7 +964,264 @@ impl RangeCacheIterator {
         seq <= self.sequence_number
     }
 
+    // Re-registers the buffer bytes held by this iterator with the memory
+    // controller and the buffer gauge. A no-op unless the held size changed,
+    // so it is cheap to call after every buffer update.
+    fn update_buffer_accounting(&mut self) {
+        let mut held =
+            self.saved_user_key.capacity() + self.saved_value.as_ref().map_or(0, |v| v.len());
+        if held <= ITER_BUFFER_ACCOUNT_THRESHOLD {
+            held = 0;
+        }
+        if held == self.tracked_buffer_bytes {
+            return;
+        }
+        if held > self.tracked_buffer_bytes {
+            let delta = held - self.tracked_buffer_bytes;
+            // Reads must not fail on memory pressure, so a soft limit result
+            // is ignored here; registering the bytes is what lets the
+            // background load/evict logic react to iterator-held memory. At
+            // the hard limit the controller refuses the acquire and the
+            // excess simply stays untracked.
+            if matches!(
+                self.memory_controller.acquire(delta),
+                MemoryUsage::HardLimitReached(_)
+            ) {
+                return;
+            }
+            RANGE_CACHE_ITERATOR_BUFFER_BYTES.add(delta as i64);
+        } else {
+            let delta = self.tracked_buffer_bytes - held;
+            self.memory_controller.release(delta);
+            RANGE_CACHE_ITERATOR_BUFFER_BYTES.sub(delta as i64);
+        }
+        self.tracked_buffer_bytes = held;
+    }
+
+    // Decodes the framed value at the freshly established position into
+    // `current_value`, called at the end of every positioning operation when
+    // values are framed by the value compression. A corrupted frame fails
+    // the operation and invalidates the iterator instead of exposing the
+    // stored bytes. See the `compression` module.
+    fn update_current_value(&mut self) -> Result<()> {
+        if !self.values_framed {
+            return Ok(());
+        }
+        self.current_value = None;
+        if !self.valid || self.key_only {
+            return Ok(());
+        }
+        let stored = if self.direction == Direction::Backward {
+            self.saved_value.as_ref().unwrap().clone()
+        } else if let Some((_, value)) = &self.current_prefetched {
+            value.clone()
+        } else {
+            self.iter.value().clone_bytes()
+        };
+        match decode_value(stored) {
+            Ok(value) => {
+                self.current_value = Some(value);
+                Ok(())
+            }
+            Err(e) => {
+                self.valid = false;
+                Err(e)
+            }
+        }
+    }
+
+    /// Drops prefetched entries and resets the sequential scan detection.
+    /// Called whenever a seek or a direction change invalidates the buffered
+    /// window.
+    fn invalidate_prefetch(&mut self) {
+        self.prefetch_buffer.clear();
+        self.current_prefetched = None;
+        self.sequential_nexts = 0;
+    }
+
+    /// Pulls up to `prefetch_size` raw entries following the current position
+    /// of the underlying iterator into the prefetch buffer, leaving the
+    /// iterator parked at the last pulled entry. The whole batch is read
+    /// under the single given epoch pin; a fresh pin is taken for every
+    /// refill rather than holding one across user calls.
+    fn refill_prefetch(&mut self, guard: &epoch::Guard) {
+        debug_assert!(self.prefetch_buffer.is_empty());
+        for _ in 0..self.prefetch_size {
+            self.iter.next(guard);
+            if !self.iter.valid() {
+                break;
+            }
+            self.prefetch_buffer.push_back((
+                self.iter.key().as_bytes().clone(),
+                self.iter.value().as_bytes().clone(),
+            ));
+        }
+    }
+
+    /// The buffered counterpart of [`Self::find_next_visible_key`]: consumes
+    /// prefetched entries, refilling the buffer from the skiplist whenever it
+    /// runs dry, until a visible value is found or the scan ends.
+    fn find_next_visible_key_prefetched(&mut self, mut skip_saved_key: bool, guard: &epoch::Guard) {
+        while let Some((key, value)) = self.prefetch_buffer.pop_front() {
+            let InternalKey {
+                user_key,
+                sequence,
+                v_type,
+            } = decode_key(key.as_slice());
+
+            if user_key >= self.upper_bound.as_slice() {
+                break;
+            }
+
+            if let Some(ref prefix) = self.prefix {
+                if prefix != self.prefix_extractor.as_mut().unwrap().transform(user_key) {
+                    // stop iterating due to unmatched prefix
+                    break;
+                }
+            }
+
+            if self.is_visible(sequence) {
+                if skip_saved_key && user_key == self.saved_user_key.as_slice() {
+                    // the user key has been met before, skip it.
+                    perf_counter_add!(internal_key_skipped_count, 1);
+                    self.local_stats.internal_keys_skipped += 1;
+                } else {
+                    save_user_key(&mut self.saved_user_key, user_key);
+                    self.update_buffer_accounting();
+
+                    match v_type {
+                        ValueType::Deletion => {
+                            skip_saved_key = true;
+                            perf_counter_add!(internal_delete_skipped_count, 1);
+                            self.local_stats.tombstones_skipped += 1;
+                        }
+                        ValueType::Value => {
+                            self.current_prefetched = Some((key, value));
+                            self.valid = true;
+                            return;
+                        }
+                    }
+                }
+            } else if skip_saved_key && user_key > self.saved_user_key.as_slice() {
+                // user key changed, so no need to skip it
+                skip_saved_key = false;
+            }
+
+            if !self.charge_skipped_internal_key() {
+                self.prefetch_buffer.clear();
+                return;
+            }
+            if self.prefetch_buffer.is_empty() {
+                self.refill_prefetch(guard);
+            }
+        }
+
+        self.prefetch_buffer.clear();
+        self.valid = false;
+    }
+
+    // Returns the exact internal key of the entry the iterator currently
+    // exposes in forward direction, also when it is served from the prefetch
+    // buffer while the underlying iterator is parked elsewhere.
+    fn current_forward_internal_key(&self) -> Option<Bytes> {
+        if let Some((key, _)) = &self.current_prefetched {
+            Some(key.clone())
+        } else if self.iter.valid() {
+            Some(self.iter.key().as_bytes().clone())
+        } else {
+            None
+        }
+    }
+
+    // Both caches describe positions relative to the pre-seek one, so any
+    // seek drops them.
+    fn invalidate_switch_caches(&mut self) {
+        self.forward_switch_cache = None;
+        self.backward_switch_cache = None;
+    }
+
+    /// Attempts to serve a backward-to-forward switch from the cached forward
+    /// position. The cache is only set by the `prev()` that reversed off that
+    /// position and cleared by a second one, so when it is present the cached
+    /// entry is exactly the next visible entry after the currently exposed
+    /// key and a single exact internal-key seek restores it. Returns true
+    /// when the entry has been restored and is exposed to the user; the
+    /// caller then skips the slow re-seek and the walk over the saved key's
+    /// versions entirely. When the remembered node was removed (the seek
+    /// lands elsewhere), the iterator is re-seeked to the position the slow
+    /// path expects and false is returned.
+    fn restore_forward_position(&mut self, guard: &epoch::Guard) -> bool {
+        let Some(cached) = self.forward_switch_cache.take() else {
+            return false;
+        };
+        if self.prefix_extractor.is_some() {
+            return false;
+        }
+        {
+            let InternalKey { user_key, .. } = decode_key(cached.as_slice());
+            if user_key <= self.saved_user_key.as_slice() {
+                return false;
+            }
+        }
+        self.iter.seek(&InternalBytes::from_bytes(cached.clone()), guard);
+        self.local_stats.number_db_seek += 1;
+        if !self.iter.valid() || self.iter.key().as_slice() != cached.as_slice() {
+            // The remembered node is gone. The probe moved the underlying
+            // iterator, so redo the slow re-seek from the saved user key for
+            // `reverse_to_forward`.
+            let seek_key = encode_seek_key(&self.saved_user_key, MAX_SEQUENCE_NUMBER);
+            self.iter.seek(&seek_key, guard);
+            return false;
+        }
+        let InternalKey { user_key, .. } = decode_key(cached.as_slice());
+        save_user_key(&mut self.saved_user_key, user_key);
+        self.update_buffer_accounting();
+        self.direction = Direction::Forward;
+        self.valid = true;
+        true
+    }
+
+    /// The counterpart of [`Self::restore_forward_position`] for the
+    /// forward-to-backward switch: puts the iterator back on the entry it
+    /// exposed while moving backward, provided the forward excursion was the
+    /// single `next()` that reversed off it. Returns true when the backward
+    /// state has been fully restored.
+    fn restore_backward_position(&mut self, guard: &epoch::Guard) -> bool {
+        let Some(cached) = self.backward_switch_cache.take() else {
+            return false;
+        };
+        if self.prefix_extractor.is_some() || cached.next_user_key != self.saved_user_key {
+            return false;
+        }
+        self.iter.seek(
+            &InternalBytes::from_bytes(cached.parked_internal_key.clone()),
+            guard,
+        );
+        self.local_stats.number_db_seek += 1;
+        if !self.iter.valid() || self.iter.key().as_slice() != cached.parked_internal_key.as_slice()
+        {
+            // The remembered node is gone. The probe left the iterator at the
+            // first node after it, from which `reverse_to_backward` recovers
+            // by walking back below the exposed key as usual.
+            if !self.iter.valid() {
+                // Everything from the parked node on was removed; re-anchor
+                // at the exposed key so the backward walk has a start.
+                let seek_key = encode_seek_key(&self.saved_user_key, MAX_SEQUENCE_NUMBER);
+                self.iter.seek(&seek_key, guard);
+            }
+            return false;
+        }
+        save_user_key(&mut self.saved_user_key, &cached.user_key);
+        self.saved_value = Some(cached.value);
+        self.update_buffer_accounting();
+        self.direction = Direction::Backward;
+        self.valid = true;
+        true
+    }
+
     fn seek_internal(&mut self, key: &InternalBytes) {
+        self.invalidate_prefetch();
+        self.invalidate_switch_caches();
         let guard = &epoch::pin();
         self.iter.seek(key, guard);
         self.local_stats.number_db_seek += 1;
@@ -347,6 +1233,8 @@ impl RangeCacheIterator {
     }
 
     fn seek_for_prev_internal(&mut self, key: &InternalBytes) {
+        self.invalidate_prefetch();
+        self.invalidate_switch_caches();
         let guard = &epoch::pin();
         self.iter.seek_for_prev(key, guard);
         self.local_stats.number_db_seek += 1;
@@ -355,9 +1243,12 @@ impl RangeCacheIterator {
 
     fn prev_internal(&mut self, guard: &epoch::Guard) {
         while self.iter.valid() {
+            if self.incomplete {
+                self.valid = false;
+                return;
+            }
             let InternalKey { user_key, .. } = decode_key(self.iter.key().as_slice());
-            self.saved_user_key.clear();
-            self.saved_user_key.extend_from_slice(user_key);
+            save_user_key(&mut self.saved_user_key, user_key);
 
             if user_key < self.lower_bound.as_slice() {
                 break;
@@ -382,6 +1273,7 @@ impl RangeCacheIterator {
         }
 
         // We have not found any key
+        self.update_buffer_accounting();
         self.valid = false;
     }
 
@@ -407,18 +1299,34 @@ impl RangeCacheIterator {
             last_key_entry_type = v_type;
             match v_type {
                 ValueType::Value => {
-                    self.saved_value = Some(self.iter.value().clone_bytes());
+                    if self.key_only {
+                        // The value bytes are never exposed; the entry type
+                        // alone decides validity.
+                        self.saved_value.take();
+                    } else {
+                        self.saved_value = Some(self.iter.value().clone_bytes());
+                    }
                 }
                 ValueType::Deletion => {
                     self.saved_value.take();
                     perf_counter_add!(internal_delete_skipped_count, 1);
+                    self.local_stats.tombstones_skipped += 1;
                 }
             }
 
             perf_counter_add!(internal_key_skipped_count, 1);
+
+            self.local_stats.internal_keys_skipped += 1;
+            if !self.charge_skipped_internal_key() {
+                self.update_buffer_accounting();
+                return false;
+            }
             self.iter.prev(guard);
         }
 
+        // Covers both the key saved by the caller and the value (re)placed
+        // above.
+        self.update_buffer_accounting();
         self.valid = last_key_entry_type == ValueType::Value;
         self.iter.valid()
     }
@@ -435,8 +1343,12 @@ impl RangeCacheIterator {
 
             if self.is_visible(self.sequence_number) {
                 perf_counter_add!(internal_key_skipped_count, 1);
+                self.local_stats.internal_keys_skipped += 1;
             }
 
+            if !self.charge_skipped_internal_key() {
+                return;
+            }
             self.iter.prev(guard);
         }
     }
@@ -471,8 +1383,18 @@ impl Iterator for RangeCacheIterator {
 
     fn value(&self) -> &[u8] {
         assert!(self.valid);
-        if self.direction == Direction::Backward {
+        if self.key_only {
+            // Key-only iterators never materialize values.
+            return &[];
+        }
+        if let Some(value) = &self.current_value {
+            // The decoded form of a framed value, see `update_current_value`.
+            return value.as_slice();
+        }
+        if self.direction == Direction::Backward {
             self.saved_value.as_ref().unwrap().as_slice()
+        } else if let Some((_, value)) = &self.current_prefetched {
+            value.as_slice()
         } else {
             self.iter.value().as_slice()
         }
@@ -480,40 +1402,131 @@ impl Iterator for RangeCacheIterator {
 
     fn next(&mut self) -> Result<bool> {
         assert!(self.valid);
+        self.reset_skipped_internal_keys();
         let guard = &epoch::pin();
 
+        // The backward state being left on a direction switch; it becomes the
+        // backward switch cache once the new forward position is known, so an
+        // immediately following `prev()` can restore it cheaply.
+        let mut leaving_backward = None;
         if self.direction == Direction::Backward {
+            self.invalidate_prefetch();
+            if self.prefix_extractor.is_none() && self.iter.valid() {
+                leaving_backward = self.saved_value.clone().map(|value| {
+                    (
+                        self.saved_user_key.clone(),
+                        value,
+                        self.iter.key().as_bytes().clone(),
+                    )
+                });
+            }
+            if self.restore_forward_position(guard) {
+                if let Some((user_key, value, parked_internal_key)) = leaving_backward {
+                    self.backward_switch_cache = Some(BackwardSwitchCache {
+                        user_key,
+                        value,
+                        parked_internal_key,
+                        next_user_key: self.saved_user_key.clone(),
+                    });
+                }
+                self.update_current_value()?;
+                self.local_stats.number_direction_switch_fast += 1;
+                self.local_stats.number_db_next += 1;
+                self.local_stats.number_db_next_found += 1;
+                self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
+                return Ok(true);
+            }
+            self.local_stats.number_direction_switch_slow += 1;
             self.reverse_to_forward(guard);
         }
 
-        self.iter.next(guard);
+        // Advance off the current entry. If the current position was served
+        // from the prefetch buffer the underlying iterator is already parked
+        // at the last prefetched entry, so refilling continues right behind
+        // the buffered window.
+        let buffered = if self.current_prefetched.take().is_some() {
+            if self.prefetch_buffer.is_empty() {
+                self.refill_prefetch(guard);
+            }
+            true
+        } else if self.prefetch_size > 0 && self.sequential_nexts >= PREFETCH_AFTER_SEQUENTIAL_NEXTS
+        {
+            self.refill_prefetch(guard);
+            true
+        } else {
+            self.iter.next(guard);
+            false
+        };
+        self.sequential_nexts = self.sequential_nexts.saturating_add(1);
 
         perf_counter_add!(internal_key_skipped_count, 1);
+
+        self.local_stats.internal_keys_skipped += 1;
         self.local_stats.number_db_next += 1;
 
-        self.valid = self.iter.valid();
-        if self.valid {
-            // self.valid can be changed after this
-            self.find_next_visible_key(true, guard);
+        if buffered {
+            // self.valid is set inside
+            self.find_next_visible_key_prefetched(true, guard);
+        } else {
+            self.valid = self.iter.valid();
+            if self.valid {
+                // self.valid can be changed after this
+                self.find_next_visible_key(true, guard);
+            }
         }
 
+        self.update_current_value()?;
         if self.valid {
             self.local_stats.number_db_next_found += 1;
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
+            if let Some((user_key, value, parked_internal_key)) = leaving_backward {
+                self.backward_switch_cache = Some(BackwardSwitchCache {
+                    user_key,
+                    value,
+                    parked_internal_key,
+                    next_user_key: self.saved_user_key.clone(),
+                });
+            }
         }
 
+        self.check_incomplete()?;
         Ok(self.valid)
     }
 
     fn prev(&mut self) -> Result<bool> {
         assert!(self.valid);
+        self.reset_skipped_internal_keys();
         let guard = &epoch::pin();
 
         if self.direction == Direction::Forward {
+            // Remember the exposed internal key before leaving the forward
+            // position, so the next backward-to-forward switch can restore it
+            // with a single exact seek.
+            self.forward_switch_cache = self.current_forward_internal_key();
+        } else {
+            // Another `prev()` moves further away from the reversal point, so
+            // the remembered forward position is no longer the next visible
+            // entry.
+            self.forward_switch_cache = None;
+        }
+        // Even without a direction change a `prev()` ends the sequential
+        // forward scan the buffer was serving.
+        self.invalidate_prefetch();
+        if self.direction == Direction::Forward {
+            if self.restore_backward_position(guard) {
+                self.update_current_value()?;
+                self.local_stats.number_direction_switch_fast += 1;
+                self.local_stats.number_db_prev += 1;
+                self.local_stats.number_db_prev_found += 1;
+                self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
+                return Ok(true);
+            }
+            self.local_stats.number_direction_switch_slow += 1;
             self.reverse_to_backward(guard);
         }
 
         self.prev_internal(guard);
+        self.update_current_value()?;
 
         self.local_stats.number_db_prev += 1;
         if self.valid {
@@ -521,11 +1534,14 @@ impl Iterator for RangeCacheIterator {
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
         }
 
+        self.check_incomplete()?;
         Ok(self.valid)
     }
 
     fn seek(&mut self, key: &[u8]) -> Result<bool> {
+        self.check_poisoned()?;
         let begin = Instant::now();
+        self.reset_skipped_internal_keys();
         self.direction = Direction::Forward;
         if let Some(ref mut extractor) = self.prefix_extractor {
             assert!(key.len() >= 8);
@@ -540,17 +1556,21 @@ impl Iterator for RangeCacheIterator {
 
         let seek_key = encode_seek_key(seek_key, self.sequence_number);
         self.seek_internal(&seek_key);
+        self.update_current_value()?;
         if self.valid {
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
             self.local_stats.number_db_seek_found += 1;
         }
-        self.seek_duration.observe(begin.saturating_elapsed_secs());
+        self.observe_seek_duration(begin);
 
+        self.check_incomplete()?;
         Ok(self.valid)
     }
 
     fn seek_for_prev(&mut self, key: &[u8]) -> Result<bool> {
+        self.check_poisoned()?;
         let begin = Instant::now();
+        self.reset_skipped_internal_keys();
         self.direction = Direction::Backward;
         if let Some(ref mut extractor) = self.prefix_extractor {
             assert!(key.len() >= 8);
@@ -564,39 +1584,49 @@ impl Iterator for RangeCacheIterator {
         };
 
         self.seek_for_prev_internal(&seek_key);
+        self.update_current_value()?;
         if self.valid {
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
             self.local_stats.number_db_seek_found += 1;
         }
-        self.seek_duration.observe(begin.saturating_elapsed_secs());
+        self.observe_seek_duration(begin);
 
+        self.check_incomplete()?;
         Ok(self.valid)
     }
 
     fn seek_to_first(&mut self) -> Result<bool> {
+        self.check_poisoned()?;
         let begin = Instant::now();
         assert!(self.prefix_extractor.is_none());
+        self.reset_skipped_internal_keys();
         self.direction = Direction::Forward;
         let seek_key = encode_seek_key(&self.lower_bound, self.sequence_number);
         self.seek_internal(&seek_key);
+        self.update_current_value()?;
 
         if self.valid {
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
             self.local_stats.number_db_seek_found += 1;
         }
-        self.seek_duration.observe(begin.saturating_elapsed_secs());
+        self.observe_seek_duration(begin);
 
+        self.check_incomplete()?;
         Ok(self.valid)
     }
 
     fn seek_to_last(&mut self) -> Result<bool> {
+        self.check_poisoned()?;
         let begin = Instant::now();
         assert!(self.prefix_extractor.is_none());
+        self.reset_skipped_internal_keys();
         self.direction = Direction::Backward;
         let seek_key = encode_seek_for_prev_key(&self.upper_bound, u64::MAX);
         self.seek_for_prev_internal(&seek_key);
+        self.update_current_value()?;
 
         if !self.valid {
+            self.check_incomplete()?;
             return Ok(false);
         }
 
@@ -604,7 +1634,7 @@ impl Iterator for RangeCacheIterator {
             self.local_stats.bytes_read += (self.key().len() + self.value().len()) as u64;
             self.local_stats.number_db_seek_found += 1;
         }
-        self.seek_duration.observe(begin.saturating_elapsed_secs());
+        self.observe_seek_duration(begin);
 
         Ok(self.valid)
     }
@@ -633,6 +1663,11 @@ impl MetricsExt for RangeCacheIterator {
     }
 }
 
+/// A handle to the value bytes stored in the skiplist.
+///
+/// `Bytes` is refcounted, so constructing it does not copy the value and the
+/// bytes stay valid even if the range is evicted and its nodes are physically
+/// deleted while the vector is alive.
 #[derive(Debug)]
 pub struct RangeCacheDbVector(Bytes);
 
@@ -659,7 +1694,6 @@ mod tests {
         iter::{self, StepBy},
         ops::Deref,
         sync::Arc,
-        time::Duration,
     };
 
     use bytes::{BufMut, Bytes};
@@ -668,25 +1702,28 @@ mod tests {
         raw::DBStatisticsTickerType, util::new_engine_opt, RocksDbOptions, RocksStatistics,
     };
     use engine_traits::{
-        CacheRange, FailedReason, IterMetricsCollector, IterOptions, Iterable, Iterator,
-        MetricsExt, Mutable, Peekable, RangeCacheEngine, ReadOptions, WriteBatch, WriteBatchExt,
-        CF_DEFAULT, CF_LOCK, CF_WRITE,
+        CacheRange, Code, Error, FailedReason, IterMetricsCollector, IterOptions, Iterable,
+        Iterator, MetricsExt, Mutable, Peekable, RangeCacheEngine, ReadOptions, WriteBatch,
+        WriteBatchExt, CF_DEFAULT, CF_LOCK, CF_WRITE,
     };
     use skiplist_rs::SkipList;
     use tempfile::Builder;
-    use tikv_util::config::VersionTrack;
+    use tikv_util::config::{ReadableSize, VersionTrack};
 
-    use super::{RangeCacheIterator, RangeCacheSnapshot};
+    use super::{
+        MultiRangeItem, RangeCacheIterator, RangeCacheSnapshot, SAVED_USER_KEY_SHRINK_THRESHOLD,
+    };
     use crate::{
         engine::{cf_to_id, SkiplistEngine},
         keys::{
             construct_key, construct_user_key, construct_value, decode_key, encode_key,
             encode_seek_key, InternalBytes, ValueType,
         },
+        metrics::{RANGE_CACHE_ITERATOR_BUFFER_BYTES, RANGE_CACHE_ITERATOR_COUNT},
         perf_context::PERF_CONTEXT,
-        statistics::Tickers,
+        statistics::{IterReadStats, LocalStatsSink, Tickers},
         RangeCacheEngineConfig, RangeCacheEngineContext, RangeCacheMemoryEngine,
-        RangeCacheWriteBatch,
+        RangeCacheWriteBatch, ValueCompression,
     };
 
     #[test]
@@ -917,6 +1954,105 @@ mod tests {
         assert!(!iter.valid().unwrap());
     }
 
+    #[test]
+    fn test_multi_range_iterator() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        engine.enable_deterministic_background_tasks();
+
+        // A span with no cached data comes out as one gap.
+        let mut iter = engine.multi_range_iterator("write", b"a", b"z");
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Gap(b"a".to_vec(), b"z".to_vec())
+        );
+        assert!(iter.next().is_none());
+
+        // Two non-adjacent cached ranges.
+        let range1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        let range2 = CacheRange::new(b"k20".to_vec(), b"k30".to_vec());
+        engine.new_range(range1.clone());
+        engine.new_range(range2.clone());
+        {
+            let mut core = engine.core.write();
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            put_key_val(&sl, "k02", "v02", 10, 5);
+            put_key_val(&sl, "k05", "v05", 10, 6);
+            put_key_val(&sl, "k22", "v22", 10, 7);
+            put_key_val(&sl, "k25", "v25", 10, 8);
+        }
+
+        // The stitched iteration yields the cached keys of both ranges in
+        // global order, with every uncached part of the span reported as a
+        // gap.
+        let mut iter = engine.multi_range_iterator("write", b"a", b"z");
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Gap(b"a".to_vec(), b"k00".to_vec())
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Entry(construct_mvcc_key("k02", 10), b"v02".to_vec())
+        );
+
+        // An eviction requested mid-iteration takes the range out of the
+        // active set right away but the physical deletion is deferred: the
+        // iterator's snapshot pins the data until the iterator is dropped.
+        engine.evict_range(&range1);
+        engine.run_background_tasks_until_idle();
+        assert_eq!(
+            engine
+                .snapshot(range1.clone(), u64::MAX, u64::MAX)
+                .unwrap_err(),
+            FailedReason::NotCached
+        );
+        assert_eq!(
+            engine.core.read().range_manager().historical_ranges_count(),
+            1
+        );
+
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Entry(construct_mvcc_key("k05", 10), b"v05".to_vec())
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Gap(b"k10".to_vec(), b"k20".to_vec())
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Entry(construct_mvcc_key("k22", 10), b"v22".to_vec())
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Entry(construct_mvcc_key("k25", 10), b"v25".to_vec())
+        );
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Gap(b"k30".to_vec(), b"z".to_vec())
+        );
+        assert!(iter.next().is_none());
+
+        // Dropping the iterator releases the snapshots, which lets the
+        // deferred deletion of the evicted range run.
+        drop(iter);
+        engine.run_background_tasks_until_idle();
+        let core = engine.core.read();
+        assert_eq!(core.range_manager().historical_ranges_count(), 0);
+        assert!(core.range_manager().ranges_being_deleted.is_empty());
+
+        // A span clipped to the middle of the remaining cached range clamps
+        // both the cached segment and the surrounding gaps.
+        drop(core);
+        let mut iter = engine.multi_range_iterator("write", b"k21", b"k24");
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            MultiRangeItem::Entry(construct_mvcc_key("k22", 10), b"v22".to_vec())
+        );
+        assert!(iter.next().is_none());
+    }
+
     #[test]
     fn test_get_value() {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
@@ -997,6 +2133,129 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_max_skippable_internal_keys() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            // A visible value, then a pile of tombstoned versions a forward
+            // scan has to step over, then the next visible value.
+            put_key_val(&sl, "a", "va", 10, 6);
+            for mvcc in 1..=2000 {
+                delete_key(&sl, "b", mvcc, 6 + mvcc);
+            }
+            put_key_val(&sl, "c", "vc", 10, 3000);
+        }
+        let snapshot = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+
+        let assert_incomplete = |err: Error| match err {
+            Error::Engine(s) => assert_eq!(s.code(), Code::Incomplete),
+            e => panic!("unexpected error: {:?}", e),
+        };
+
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+
+        // A small budget fails the seek quickly once the pile is hit instead
+        // of walking all of it.
+        let mut small = iter_opt.clone();
+        small.set_max_skippable_internal_keys(100);
+        let mut iter = snapshot.iterator_opt("write", small.clone()).unwrap();
+        let seek_key = construct_mvcc_key("b", u64::MAX);
+        assert_incomplete(iter.seek(&seek_key).unwrap_err());
+        assert!(!iter.valid().unwrap());
+        assert_eq!(iter.internal_keys_skipped(), 101);
+
+        // Crossing the pile with `next` is bounded the same way.
+        let mut iter = snapshot.iterator_opt("write", small.clone()).unwrap();
+        assert!(iter.seek(&construct_mvcc_key("a", 10)).unwrap());
+        assert_eq!(iter.value(), b"va");
+        assert_incomplete(iter.next().unwrap_err());
+        assert!(!iter.valid().unwrap());
+
+        // And so is a backward scan.
+        let mut iter = snapshot.iterator_opt("write", small).unwrap();
+        assert_incomplete(iter.seek_for_prev(&construct_mvcc_key("b", 1)).unwrap_err());
+        assert!(!iter.valid().unwrap());
+
+        // A generous budget still succeeds and reports the work done.
+        let mut generous = iter_opt.clone();
+        generous.set_max_skippable_internal_keys(10_000);
+        let mut iter = snapshot.iterator_opt("write", generous).unwrap();
+        assert!(iter.seek(&seek_key).unwrap());
+        assert_eq!(iter.value(), b"vc");
+        assert_eq!(iter.internal_keys_skipped(), 2000);
+
+        // The default of 0 disables the check entirely.
+        let mut iter = snapshot.iterator_opt("write", iter_opt).unwrap();
+        assert!(iter.seek(&seek_key).unwrap());
+        assert_eq!(iter.value(), b"vc");
+
+        // Point lookups position directly at the newest visible version, so
+        // even a tiny budget cannot fail them.
+        let mut read_opts = ReadOptions::default();
+        read_opts.set_max_skippable_internal_keys(1);
+        let k = construct_mvcc_key("a", 10);
+        let v = snapshot
+            .get_value_cf_opt(&read_opts, "write", &k)
+            .unwrap()
+            .unwrap();
+        assert_eq!(v, b"va".as_slice());
+    }
+
+    #[test]
+    fn test_value_pinned_across_eviction() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        let big_val = "v".repeat(4096);
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            put_key_val(&sl, "b", &big_val, 10, 5);
+        }
+
+        let opts = ReadOptions::default();
+        let k = construct_mvcc_key("b", 10);
+        let v = {
+            let snapshot = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+            snapshot
+                .get_value_cf_opt(&opts, "write", &k)
+                .unwrap()
+                .unwrap()
+        };
+
+        // The snapshot is dropped, so the eviction can delete the range
+        // physically right away. The DbVector alone must keep the value bytes
+        // alive.
+        {
+            let mut core = engine.core.write();
+            let ranges_to_delete = core.range_manager.evict_range(&range, "test");
+            assert!(!ranges_to_delete.is_empty());
+            for r in &ranges_to_delete {
+                core.engine.delete_range(r);
+            }
+            core.range_manager.on_delete_ranges(&ranges_to_delete);
+        }
+        assert_eq!(
+            engine.snapshot(range.clone(), 10, u64::MAX).unwrap_err(),
+            FailedReason::NotCached
+        );
+        assert_eq!(v, big_val.as_bytes());
+    }
+
     #[test]
     fn test_iterator_forawrd() {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
@@ -1748,21 +3007,17 @@ mod tests {
     }
 
     fn verify_evict_range_deleted(engine: &RangeCacheMemoryEngine, range: &CacheRange) {
-        let mut wait = 0;
-        while wait < 10 {
-            wait += 1;
-            if !engine
+        // The deterministic mode has parked the deletions scheduled so far;
+        // run them now instead of polling for a background worker.
+        engine.run_background_tasks_until_idle();
+        assert!(
+            !engine
                 .core
                 .read()
                 .range_manager()
                 .ranges_being_deleted
-                .is_empty()
-            {
-                std::thread::sleep(Duration::from_millis(200));
-            } else {
-                break;
-            }
-        }
+                .contains(range)
+        );
         let write_handle = engine.core.read().engine.cf_handle("write");
         let start_key = encode_seek_key(&range.start, u64::MAX);
         let mut iter = write_handle.iterator();
@@ -1778,6 +3033,7 @@ mod tests {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
             VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
         )));
+        engine.enable_deterministic_background_tasks();
         let range = CacheRange::new(construct_user_key(0), construct_user_key(30));
         let evict_range = CacheRange::new(construct_user_key(10), construct_user_key(20));
         engine.new_range(range.clone());
@@ -1797,9 +3053,11 @@ mod tests {
         }
 
         engine.evict_range(&evict_range);
+        // The range is splitted by the eviction, so the remaining cached
+        // ranges overlap the requested one without containing it.
         assert_eq!(
             engine.snapshot(range.clone(), 10, 200).unwrap_err(),
-            FailedReason::NotCached
+            FailedReason::EpochNotMatch
         );
         assert_eq!(
             engine.snapshot(evict_range.clone(), 10, 200).unwrap_err(),
@@ -1837,6 +3095,7 @@ mod tests {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
             VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
         )));
+        engine.enable_deterministic_background_tasks();
         let range = CacheRange::new(construct_user_key(0), construct_user_key(30));
         let evict_range = CacheRange::new(construct_user_key(10), construct_user_key(20));
         engine.new_range(range.clone());
@@ -1948,6 +3207,99 @@ mod tests {
         assert_eq!(10, collector.internal_key_skipped_count());
     }
 
+    #[test]
+    fn test_key_only_iteration() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+
+            put_key_val(&sl, "a", "val-a", 10, 6);
+            put_key_val(&sl, "b", "val-b", 10, 6);
+            // The deletion shadows the value written above.
+            delete_key(&sl, "b", 10, 7);
+            put_key_val(&sl, "c", "val-c", 10, 6);
+            put_key_val(&sl, "c", "val-cc", 12, 8);
+            put_key_val(&sl, "d", "val-d", 10, 6);
+        }
+
+        let statistics = engine.statistics();
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, 100).unwrap();
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+        let mut key_only_opt = iter_opt.clone();
+        key_only_opt.set_key_only(true);
+
+        // Forward key-only scan: deletions are honored and no value is
+        // exposed.
+        let mut iter = snapshot
+            .iterator_opt("write", key_only_opt.clone())
+            .unwrap();
+        let mut keys = vec![];
+        let mut key_bytes = 0;
+        iter.seek_to_first().unwrap();
+        while iter.valid().unwrap() {
+            assert!(iter.value().is_empty());
+            keys.push(iter.key().to_vec());
+            key_bytes += iter.key().len() as u64;
+            iter.next().unwrap();
+        }
+        assert_eq!(
+            keys,
+            vec![
+                construct_mvcc_key("a", 10),
+                construct_mvcc_key("c", 12),
+                construct_mvcc_key("c", 10),
+                construct_mvcc_key("d", 10),
+            ]
+        );
+        drop(iter);
+        // Only key bytes are counted for a key-only scan.
+        assert_eq!(
+            statistics.get_and_reset_ticker_count(Tickers::IterBytesRead),
+            key_bytes
+        );
+
+        // Backward key-only scan yields the same keys in reverse and does not
+        // materialize the values it walks over.
+        let mut iter = snapshot.iterator_opt("write", key_only_opt).unwrap();
+        let mut rev_keys = vec![];
+        iter.seek_to_last().unwrap();
+        while iter.valid().unwrap() {
+            assert!(iter.value().is_empty());
+            rev_keys.push(iter.key().to_vec());
+            iter.prev().unwrap();
+        }
+        keys.reverse();
+        assert_eq!(rev_keys, keys);
+        drop(iter);
+        assert_eq!(
+            statistics.get_and_reset_ticker_count(Tickers::IterBytesRead),
+            key_bytes
+        );
+
+        // A normal iterator on the same snapshot still returns values and
+        // counts them.
+        let mut iter = snapshot.iterator_opt("write", iter_opt).unwrap();
+        iter.seek_to_first().unwrap();
+        assert_eq!(iter.value(), b"val-a");
+        iter.seek_to_last().unwrap();
+        assert_eq!(iter.value(), b"val-d");
+        drop(iter);
+        // Two entries with 9-byte mvcc keys and 5-byte values.
+        assert_eq!(
+            statistics.get_and_reset_ticker_count(Tickers::IterBytesRead),
+            28
+        );
+    }
+
     #[test]
     fn test_read_flow_metrics() {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
@@ -2062,6 +3414,116 @@ mod tests {
         assert_eq!(3, statistics.get_ticker_count(Tickers::NumberDbPrevFound));
     }
 
+    #[test]
+    fn test_iterator_stats_sink() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.put(b"a", b"val-a").unwrap();
+        wb.put(b"b", b"val-b").unwrap();
+        wb.put(b"c", b"val-c").unwrap();
+        wb.put(b"d", b"val-d").unwrap();
+        wb.set_sequence_number(10).unwrap();
+        wb.write().unwrap();
+        // Bury "c" under a tombstone so the scan has internal keys to skip.
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.delete(b"c").unwrap();
+        wb.set_sequence_number(20).unwrap();
+        wb.write().unwrap();
+
+        let statistics = engine.statistics();
+        let pc_keys_before = PERF_CONTEXT.with(|c| c.borrow().internal_key_skipped_count);
+        let pc_deletes_before = PERF_CONTEXT.with(|c| c.borrow().internal_delete_skipped_count);
+
+        let snap = engine.snapshot(range.clone(), 100, 100).unwrap();
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+
+        let sink = Arc::new(LocalStatsSink::default());
+        let mut iter = snap
+            .iterator_opt_with_stats("default", iter_opt.clone(), sink.clone())
+            .unwrap();
+        assert!(iter.seek_to_first().unwrap());
+        let mut count = 0;
+        while iter.valid().unwrap() {
+            count += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(count, 3);
+        // The counters are flushed into the sink in one batch on drop.
+        assert_eq!(sink.get(), IterReadStats::default());
+        drop(iter);
+
+        let stats = sink.get();
+        assert_eq!(stats.seek, 1);
+        assert_eq!(stats.seek_found, 1);
+        assert_eq!(stats.next, 3);
+        assert_eq!(stats.next_found, 2);
+        assert_eq!(
+            stats.tombstones_skipped,
+            PERF_CONTEXT.with(|c| c.borrow().internal_delete_skipped_count) - pc_deletes_before
+        );
+        assert_eq!(
+            stats.internal_keys_skipped,
+            PERF_CONTEXT.with(|c| c.borrow().internal_key_skipped_count) - pc_keys_before
+        );
+        assert!(stats.tombstones_skipped > 0);
+        // The global statistics observed exactly the same deltas.
+        assert_eq!(
+            stats.seek,
+            statistics.get_and_reset_ticker_count(Tickers::NumberDbSeek)
+        );
+        assert_eq!(
+            stats.seek_found,
+            statistics.get_and_reset_ticker_count(Tickers::NumberDbSeekFound)
+        );
+        assert_eq!(
+            stats.next,
+            statistics.get_and_reset_ticker_count(Tickers::NumberDbNext)
+        );
+        assert_eq!(
+            stats.next_found,
+            statistics.get_and_reset_ticker_count(Tickers::NumberDbNextFound)
+        );
+        assert_eq!(
+            stats.bytes_read,
+            statistics.get_and_reset_ticker_count(Tickers::IterBytesRead)
+        );
+        assert!(stats.bytes_read > 0);
+
+        // Two live iterators with separate sinks don't cross-contaminate.
+        let sink1 = Arc::new(LocalStatsSink::default());
+        let sink2 = Arc::new(LocalStatsSink::default());
+        let mut iter1 = snap
+            .iterator_opt_with_stats("default", iter_opt.clone(), sink1.clone())
+            .unwrap();
+        let mut iter2 = snap
+            .iterator_opt_with_stats("default", iter_opt, sink2.clone())
+            .unwrap();
+        assert!(iter1.seek_to_first().unwrap());
+        while iter1.valid().unwrap() {
+            iter1.next().unwrap();
+        }
+        assert!(iter2.seek(b"d").unwrap());
+        drop(iter1);
+        drop(iter2);
+        let stats1 = sink1.take();
+        let stats2 = sink2.take();
+        assert_eq!(stats1.next, 3);
+        assert_eq!(stats2.next, 0);
+        assert_eq!(stats2.seek, 1);
+        assert_eq!(stats2.seek_found, 1);
+        // `take` resets the sink.
+        assert_eq!(sink1.get(), IterReadStats::default());
+    }
+
     fn set_up_for_iteator<F>(
         wb_sequence: u64,
         snap_sequence: u64,
@@ -2323,4 +3785,540 @@ mod tests {
         iter.next().unwrap();
         assert!(!iter.valid().unwrap());
     }
+
+    // An alternating next()/prev() workload must serve every direction switch
+    // after the first pair from the cached positions, without walking the
+    // versions of the saved key again, and breaking the alternation must fall
+    // back to the slow path.
+    #[test]
+    fn test_direction_switch_cache() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            // Three keys with a pile of versions each, plus a fully deleted
+            // key sitting between the two the alternation bounces across.
+            for (key, base_seq) in [("a", 10), ("c", 110), ("e", 210)] {
+                for i in 0..30 {
+                    put_key_val(&sl, key, &format!("{}{}", key, i), 10, base_seq + i);
+                }
+            }
+            put_key_val(&sl, "d", "stale", 10, 150);
+            delete_key(&sl, "d", 10, 151);
+        }
+
+        let statistics = engine.statistics();
+        let snapshot = engine.snapshot(range.clone(), 10, 1000).unwrap();
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+        let mut iter = snapshot.iterator_opt("write", iter_opt).unwrap();
+
+        // The first reversal has nothing cached and must take the slow path;
+        // from then on the alternation ping-pongs on the cached positions.
+        iter.seek(&construct_mvcc_key("e", 10)).unwrap();
+        assert_eq!(iter.value(), b"e29");
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), b"c29");
+        iter.next().unwrap();
+        assert_eq!(iter.value(), b"e29");
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), b"c29");
+        iter.next().unwrap();
+        assert_eq!(iter.value(), b"e29");
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), b"c29");
+
+        // A second consecutive prev() leaves the reversal point, so the next
+        // two switches cannot use the caches.
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), b"a29");
+        iter.next().unwrap();
+        assert_eq!(iter.value(), b"c29");
+        iter.next().unwrap();
+        assert_eq!(iter.value(), b"e29");
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), b"c29");
+        iter.next().unwrap();
+        assert_eq!(iter.value(), b"e29");
+
+        drop(iter);
+        assert_eq!(
+            5,
+            statistics.get_ticker_count(Tickers::IterDirectionSwitchFast)
+        );
+        assert_eq!(
+            3,
+            statistics.get_ticker_count(Tickers::IterDirectionSwitchSlow)
+        );
+    }
+
+    // Alternating next()/prev() across keys with many versions, deletions and
+    // re-puts must match a forward-only scan of the same snapshot, whichever
+    // mix of fast restores and slow re-seeks serves the direction switches.
+    #[test]
+    fn test_alternating_direction_oracle() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            let mut seq = 10;
+            for i in 0..20u64 {
+                let key = format!("k{:02}", i);
+                for v in 0..10 {
+                    put_key_val(&sl, &key, &format!("v{}_{}", i, v), 10, seq);
+                    seq += 1;
+                }
+                match i % 5 {
+                    // fully deleted
+                    1 => {
+                        delete_key(&sl, &key, 10, seq);
+                        seq += 1;
+                    }
+                    // deleted and then re-put
+                    3 => {
+                        delete_key(&sl, &key, 10, seq);
+                        seq += 1;
+                        put_key_val(&sl, &key, &format!("r{}", i), 10, seq);
+                        seq += 1;
+                    }
+                    _ => {}
+                }
+            }
+            assert!(seq < 1000);
+            // Newer versions the snapshot must not see.
+            for i in 0..20u64 {
+                put_key_val(&sl, &format!("k{:02}", i), "invisible", 10, 2000 + i);
+            }
+        }
+
+        let snapshot = engine.snapshot(range.clone(), 10, 1000).unwrap();
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+
+        // The brute-force oracle: a forward-only scan never switches
+        // direction, so it is unaffected by the switch caches.
+        let mut expected = vec![];
+        let mut oracle = snapshot.iterator_opt("write", iter_opt.clone()).unwrap();
+        oracle.seek_to_first().unwrap();
+        while oracle.valid().unwrap() {
+            expected.push((oracle.key().to_vec(), oracle.value().to_vec()));
+            oracle.next().unwrap();
+        }
+        // 20 keys minus the four fully deleted ones (i % 5 == 1).
+        assert_eq!(expected.len(), 16);
+
+        let mut iter = snapshot.iterator_opt("write", iter_opt).unwrap();
+        let mut idx = 0;
+        assert!(iter.seek_to_first().unwrap());
+        let mut x: u64 = 0xdead_beef_cafe_f00d;
+        for _ in 0..1000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            if x % 8 == 0 {
+                idx = (x >> 3) as usize % expected.len();
+                assert!(iter.seek(&expected[idx].0).unwrap());
+            } else if x % 2 == 0 {
+                if idx + 1 >= expected.len() {
+                    continue;
+                }
+                idx += 1;
+                assert!(iter.next().unwrap());
+            } else {
+                if idx == 0 {
+                    continue;
+                }
+                idx -= 1;
+                assert!(iter.prev().unwrap());
+            }
+            assert_eq!(iter.key(), expected[idx].0.as_slice());
+            assert_eq!(iter.value(), expected[idx].1.as_slice());
+        }
+    }
+
+    // Long forward scans switch to serving `next()` from the prefetch buffer.
+    // The results must be indistinguishable from the unbatched scan: deletions
+    // interleaved in the buffered window are filtered, and reversals and seeks
+    // in the middle of a buffered window drop the stale entries.
+    #[test]
+    fn test_prefetch_sequential_scan() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        {
+            let mut core = engine.core.write();
+            core.range_manager.set_safe_point(&range, 5);
+            let sl = core.engine.data[cf_to_id("write")].clone();
+            fill_data_in_skiplist(sl.clone(), (1..500).step_by(1), 1..2, 1);
+            // delete every 7th key of [100, 200) so that deletions land in
+            // the middle of buffered windows
+            delete_data_in_skiplist(sl, (100..200).step_by(7), 1..2, 1000);
+        }
+
+        let snapshot = engine.snapshot(range.clone(), 10, u64::MAX).unwrap();
+        let mut iter_opt = IterOptions::default();
+        let lower_bound = construct_user_key(0);
+        let upper_bound = construct_user_key(1000);
+        iter_opt.set_upper_bound(&upper_bound, 0);
+        iter_opt.set_lower_bound(&lower_bound, 0);
+        let mut iter = snapshot.iterator_opt("write", iter_opt).unwrap();
+
+        // Scan the whole range, far more `next()` calls than the trigger
+        // threshold and the batch size.
+        assert!(iter.seek_to_first().unwrap());
+        for i in 1..500u64 {
+            if (100..200).contains(&i) && (i - 100) % 7 == 0 {
+                continue;
+            }
+            verify_key_value(iter.key(), iter.value(), i, 1);
+            iter.next().unwrap();
+        }
+        assert!(!iter.valid().unwrap());
+
+        // Direction reversal in the middle of a buffered window.
+        let seek_key = construct_key(1, u64::MAX);
+        assert!(iter.seek(&seek_key).unwrap());
+        for i in 1..=20u64 {
+            verify_key_value(iter.key(), iter.value(), i, 1);
+            assert!(iter.next().unwrap());
+        }
+        verify_key_value(iter.key(), iter.value(), 21, 1);
+        assert!(iter.prev().unwrap());
+        verify_key_value(iter.key(), iter.value(), 20, 1);
+        assert!(iter.prev().unwrap());
+        verify_key_value(iter.key(), iter.value(), 19, 1);
+        assert!(iter.next().unwrap());
+        verify_key_value(iter.key(), iter.value(), 20, 1);
+
+        // A seek in the middle of a buffered window drops the stale entries,
+        // both forwards and backwards over the window.
+        for i in 21..=35u64 {
+            assert!(iter.next().unwrap());
+            verify_key_value(iter.key(), iter.value(), i, 1);
+        }
+        let seek_key = construct_key(300, u64::MAX);
+        assert!(iter.seek(&seek_key).unwrap());
+        verify_key_value(iter.key(), iter.value(), 300, 1);
+        let seek_key = construct_key(5, u64::MAX);
+        assert!(iter.seek(&seek_key).unwrap());
+        verify_key_value(iter.key(), iter.value(), 5, 1);
+        // deleted keys stay invisible after the seek re-enters prefetching
+        let seek_key = construct_key(100, u64::MAX);
+        assert!(iter.seek(&seek_key).unwrap());
+        verify_key_value(iter.key(), iter.value(), 101, 1);
+    }
+
+    #[test]
+    fn test_iterator_buffer_memory_accounting() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        // A soft limit small enough that iterator-held buffers alone can
+        // reach it.
+        config.soft_limit_threshold = Some(ReadableSize::kb(64));
+        config.hard_limit_threshold = Some(ReadableSize::mb(4));
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        let sl = engine.core.read().engine().data[cf_to_id(CF_DEFAULT)].clone();
+        let guard = &epoch::pin();
+        let big_key = [b"k1".as_slice(), vec![b'a'; 16 * 1024].as_slice()].concat();
+        sl.insert(
+            encode_key(&big_key, 5, ValueType::Value),
+            InternalBytes::from_vec(b"v".to_vec()),
+            guard,
+        )
+        .release(guard);
+        let big_value = vec![b'v'; 20 * 1024];
+        sl.insert(
+            encode_key(b"k2", 5, ValueType::Value),
+            InternalBytes::from_vec(big_value.clone()),
+            guard,
+        )
+        .release(guard);
+
+        let controller = engine.memory_controller();
+        let usage_before = controller.mem_usage();
+        let count_before = RANGE_CACHE_ITERATOR_COUNT.get();
+        let bytes_before = RANGE_CACHE_ITERATOR_BUFFER_BYTES.get();
+
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, 100).unwrap();
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_lower_bound(&range.start, 0);
+        iter_opt.set_upper_bound(&range.end, 0);
+        let mut iters = Vec::new();
+        for _ in 0..10 {
+            let mut iter = snapshot.iterator_opt(CF_DEFAULT, iter_opt.clone()).unwrap();
+            assert!(iter.seek_to_first().unwrap());
+            assert_eq!(iter.key(), big_key.as_slice());
+            assert!(iter.tracked_buffer_bytes >= big_key.len());
+            iters.push(iter);
+        }
+        // Other tests may hold iterators concurrently, so the count is only
+        // checked as a lower bound; the byte assertions are exact as only
+        // this test holds oversized buffers.
+        assert!(RANGE_CACHE_ITERATOR_COUNT.get() >= count_before + 10);
+        assert!(
+            RANGE_CACHE_ITERATOR_BUFFER_BYTES.get()
+                >= bytes_before + (10 * big_key.len()) as i64
+        );
+        assert!(controller.mem_usage() >= usage_before + 10 * big_key.len());
+        // Iterator-held memory is real pressure: the check the background
+        // snapshot-load path uses to cancel loads now fires.
+        assert!(controller.reached_soft_limit());
+
+        // Moving to a small key shrinks the oversized key buffer and
+        // releases the difference.
+        let iter = iters.last_mut().unwrap();
+        assert!(iter.next().unwrap());
+        assert_eq!(iter.key(), b"k2");
+        assert!(iter.saved_user_key.capacity() <= SAVED_USER_KEY_SHRINK_THRESHOLD);
+        assert!(iter.tracked_buffer_bytes < big_key.len());
+
+        // Backward iteration pins the value as well, which must also be
+        // accounted.
+        let mut back_iter = snapshot.iterator_opt(CF_DEFAULT, iter_opt).unwrap();
+        assert!(back_iter.seek_to_last().unwrap());
+        assert_eq!(back_iter.value(), big_value.as_slice());
+        assert!(back_iter.tracked_buffer_bytes >= big_value.len());
+
+        // Dropping the iterators releases everything they held.
+        drop(back_iter);
+        drop(iters);
+        assert_eq!(RANGE_CACHE_ITERATOR_BUFFER_BYTES.get(), bytes_before);
+        assert_eq!(controller.mem_usage(), usage_before);
+    }
+
+    #[test]
+    fn test_value_compression_round_trip() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.value_compression = ValueCompression::Lz4;
+        config.value_compression_threshold = ReadableSize::kb(1);
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        // Mix values below the threshold, compressible values above it, and
+        // random values above it that lz4 cannot shrink, so the scan crosses
+        // all three frame outcomes (and the prefetched read path after a few
+        // sequential nexts).
+        let values: Vec<Vec<u8>> = (0..30)
+            .map(|i| match i % 3 {
+                0 => b"short".to_vec(),
+                1 => vec![b'a'; 4096],
+                _ => (0..4096).map(|_| rand::random::<u8>()).collect(),
+            })
+            .collect();
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        for (i, v) in values.iter().enumerate() {
+            wb.put(format!("k{:02}", i).as_bytes(), v).unwrap();
+        }
+        wb.set_sequence_number(10).unwrap();
+        wb.write().unwrap();
+
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        for (i, v) in values.iter().enumerate() {
+            let res = snapshot
+                .get_value(format!("k{:02}", i).as_bytes())
+                .unwrap()
+                .unwrap();
+            assert_eq!(res, v.as_slice());
+        }
+
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+        let mut iter = snapshot.iterator_opt(CF_DEFAULT, iter_opt.clone()).unwrap();
+        assert!(iter.seek_to_first().unwrap());
+        for (i, v) in values.iter().enumerate() {
+            assert!(iter.valid().unwrap());
+            assert_eq!(iter.key(), format!("k{:02}", i).as_bytes());
+            assert_eq!(iter.value(), v.as_slice());
+            iter.next().unwrap();
+        }
+        assert!(!iter.valid().unwrap());
+
+        // Backward iteration decodes through the saved-value path.
+        assert!(iter.seek_to_last().unwrap());
+        for (i, v) in values.iter().enumerate().rev() {
+            assert!(iter.valid().unwrap());
+            assert_eq!(iter.key(), format!("k{:02}", i).as_bytes());
+            assert_eq!(iter.value(), v.as_slice());
+            iter.prev().unwrap();
+        }
+        assert!(!iter.valid().unwrap());
+    }
+
+    #[test]
+    fn test_corrupted_value_frame_surfaces_error() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.value_compression = ValueCompression::Lz4;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        {
+            // Plant an entry whose frame header is neither raw nor lz4
+            // directly in the skiplist, bypassing the write path.
+            let core = engine.core.read();
+            let sl = core.engine.data[cf_to_id(CF_DEFAULT)].clone();
+            let guard = &epoch::pin();
+            sl.insert(
+                encode_key(b"bad", 10, ValueType::Value),
+                InternalBytes::from_vec(vec![0xff, 1, 2, 3]),
+                guard,
+            )
+            .release(guard);
+        }
+
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        snapshot.get_value(b"bad").unwrap_err();
+
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_upper_bound(&range.end, 0);
+        iter_opt.set_lower_bound(&range.start, 0);
+        let mut iter = snapshot.iterator_opt(CF_DEFAULT, iter_opt).unwrap();
+        // The positioning call surfaces the corruption and invalidates the
+        // iterator rather than panicking.
+        iter.seek_to_first().unwrap_err();
+        assert!(!iter.valid().unwrap());
+    }
+
+    #[test]
+    fn test_multi_get_locks() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+
+        // k02, k06, k08 hold locks; k04's lock is written and then deleted,
+        // so it leaves a visible tombstone in the skiplist; the rest of the
+        // probed keys were never written.
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        for k in [b"k02", b"k04", b"k06", b"k08"] {
+            wb.put_cf(CF_LOCK, k, format!("lock-{}", String::from_utf8_lossy(k)).as_bytes())
+                .unwrap();
+        }
+        wb.set_sequence_number(10).unwrap();
+        wb.write().unwrap();
+        let mut wb = engine.write_batch();
+        wb.prepare_for_range(range.clone());
+        wb.delete_cf(CF_LOCK, b"k04").unwrap();
+        wb.set_sequence_number(20).unwrap();
+        wb.write().unwrap();
+
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+        let statistics = engine.statistics();
+        let check_against_gets = |keys: &[&[u8]]| {
+            let batched = snapshot.multi_get_locks(keys).unwrap();
+            assert_eq!(batched.len(), keys.len());
+            for (key, res) in keys.iter().zip(&batched) {
+                let single = snapshot.get_value_cf(CF_LOCK, key).unwrap();
+                assert_eq!(res.as_deref(), single.as_deref());
+            }
+            batched
+        };
+
+        // A sorted batch mixing present, deleted and never-written keys is
+        // resolved by one seek plus nexts.
+        statistics.get_and_reset_ticker_count(Tickers::NumberDbSeek);
+        statistics.get_and_reset_ticker_count(Tickers::NumberDbNext);
+        let sorted: &[&[u8]] = &[b"k01", b"k02", b"k04", b"k06", b"k08", b"k09"];
+        let batched = check_against_gets(sorted);
+        assert_eq!(
+            batched.iter().filter(|res| res.is_some()).count(),
+            3 // k02, k06, k08
+        );
+        assert_eq!(
+            statistics.get_and_reset_ticker_count(Tickers::NumberDbSeek),
+            1
+        );
+        assert!(statistics.get_and_reset_ticker_count(Tickers::NumberDbNext) > 0);
+
+        // An unsorted batch with a duplicate still lines up positionally.
+        let unsorted: &[&[u8]] = &[b"k08", b"k02", b"k06", b"k02", b"k04", b"k10"];
+        check_against_gets(unsorted);
+
+        // The Peekable entry point routes lock cf batches to the same path.
+        let via_peekable = snapshot.multi_get_value_cf(CF_LOCK, sorted).unwrap();
+        for (key, res) in sorted.iter().zip(&via_peekable) {
+            let single = snapshot.get_value_cf(CF_LOCK, key).unwrap();
+            assert_eq!(res.as_deref(), single.as_deref());
+        }
+
+        assert!(snapshot.multi_get_locks(&[]).unwrap().is_empty());
+        // An out-of-range key fails the whole batch, like a single get does.
+        let out_of_range: &[&[u8]] = &[b"k02", b"zz"];
+        snapshot.multi_get_locks(out_of_range).unwrap_err();
+    }
+
+    #[test]
+    fn test_out_of_range_error_classification() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let range = CacheRange::new(b"b".to_vec(), b"m".to_vec());
+        engine.new_range(range.clone());
+        let snapshot = engine.snapshot(range.clone(), u64::MAX, u64::MAX).unwrap();
+
+        // Point reads outside the snapshot range report the structured
+        // variant, not an opaque `Other` error.
+        assert!(matches!(
+            snapshot.get_value(b"zz").unwrap_err(),
+            Error::KeyOutOfSnapshotRange { ref key, .. } if key == b"zz"
+        ));
+        assert!(matches!(
+            snapshot.multi_get_locks(&[b"c", b"a"]).unwrap_err(),
+            Error::KeyOutOfSnapshotRange { ref key, .. } if key == b"a"
+        ));
+
+        // Iterator bounds exceeding the range are classified the same way,
+        // with the offending bound as the key.
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_lower_bound(b"a", 0);
+        iter_opt.set_upper_bound(b"m", 0);
+        assert!(matches!(
+            snapshot.iterator_opt(CF_DEFAULT, iter_opt).unwrap_err(),
+            Error::KeyOutOfSnapshotRange { ref key, .. } if key == b"a"
+        ));
+        let mut iter_opt = IterOptions::default();
+        iter_opt.set_lower_bound(b"b", 0);
+        iter_opt.set_upper_bound(b"z", 0);
+        assert!(matches!(
+            snapshot.iterator_opt(CF_DEFAULT, iter_opt).unwrap_err(),
+            Error::KeyOutOfSnapshotRange { ref key, .. } if key == b"z"
+        ));
+
+        // Missing bounds stay a distinct condition.
+        assert!(matches!(
+            snapshot
+                .iterator_opt(CF_DEFAULT, IterOptions::default())
+                .unwrap_err(),
+            Error::BoundaryNotSet
+        ));
+    }
 }
@@ -0,0 +1,736 @@
+// Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! An opt-in capture/replay facility for chasing cache divergence bugs.
+//!
+//! When a [`ReplayRecorder`] is attached to a [`RangeCacheMemoryEngine`], the
+//! engine appends every consumed write batch entry (cf, key, value or delete
+//! mark, and the sequence number it was applied with) and every range event
+//! (load, evict, safe point update) to a compact binary log. Recording is
+//! buffered through a bounded channel and a dedicated writer thread, so the
+//! apply path only pays for the channel send; if the writer falls behind,
+//! whole batches are dropped and counted rather than blocking apply.
+//!
+//! The log can later be read back with [`read_replay_log`] and replayed into
+//! a fresh engine with [`replay_records`], which reproduces the exact
+//! skiplist contents including the per-entry sequence numbers. From there
+//! [`find_first_divergence`] compares the visible contents of the cache at a
+//! chosen sequence number against a disk engine, reporting the first key
+//! where they disagree. [`replay_and_compare`] bundles the three steps for
+//! offline use against a copied RocksDB directory.
+
+use std::{
+    cmp,
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use crossbeam::{
+    channel::{self, Receiver, Sender},
+    epoch,
+};
+use engine_traits::{CacheRange, Error, Iterable, Result, DATA_CFS};
+use tikv_util::{info, warn};
+
+use crate::{
+    compression::decode_value,
+    engine::id_to_cf,
+    keys::{decode_key, encode_key_for_boundary_without_mvcc, InternalKey, ValueType},
+    write_batch::RangeCacheWriteBatchEntry,
+    RangeCacheMemoryEngine,
+};
+
+// Record tags in the binary log.
+const TAG_PUT: u8 = 1;
+const TAG_DELETE: u8 = 2;
+const TAG_LOAD: u8 = 3;
+const TAG_EVICT: u8 = 4;
+const TAG_SAFE_POINT: u8 = 5;
+
+// The number of pending batches the recorder buffers before it starts
+// dropping them. Dropping keeps the apply path non-blocking; the drops are
+// counted so a replay that diverges due to lost batches can be told apart
+// from a real bug.
+const RECORDER_CHANNEL_CAP: usize = 4096;
+
+/// One event in the replay log. `Put` and `Delete` carry the sequence number
+/// the entry was applied to the skiplist with, so a replay reproduces the
+/// internal keys byte for byte.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayRecord {
+    Put {
+        cf: usize,
+        seq: u64,
+        key: Bytes,
+        value: Bytes,
+    },
+    Delete {
+        cf: usize,
+        seq: u64,
+        key: Bytes,
+    },
+    Load(CacheRange),
+    Evict(CacheRange),
+    SafePoint(CacheRange, u64),
+}
+
+impl ReplayRecord {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            ReplayRecord::Put {
+                cf,
+                seq,
+                key,
+                value,
+            } => {
+                buf.push(TAG_PUT);
+                buf.push(*cf as u8);
+                buf.extend_from_slice(&seq.to_le_bytes());
+                encode_slice(buf, key);
+                encode_slice(buf, value);
+            }
+            ReplayRecord::Delete { cf, seq, key } => {
+                buf.push(TAG_DELETE);
+                buf.push(*cf as u8);
+                buf.extend_from_slice(&seq.to_le_bytes());
+                encode_slice(buf, key);
+            }
+            ReplayRecord::Load(range) => {
+                buf.push(TAG_LOAD);
+                encode_slice(buf, &range.start);
+                encode_slice(buf, &range.end);
+            }
+            ReplayRecord::Evict(range) => {
+                buf.push(TAG_EVICT);
+                encode_slice(buf, &range.start);
+                encode_slice(buf, &range.end);
+            }
+            ReplayRecord::SafePoint(range, safe_point) => {
+                buf.push(TAG_SAFE_POINT);
+                encode_slice(buf, &range.start);
+                encode_slice(buf, &range.end);
+                buf.extend_from_slice(&safe_point.to_le_bytes());
+            }
+        }
+    }
+
+    /// Decodes the next record from `reader`. Returns `None` on a clean end
+    /// of file; a record cut short mid-way surfaces as `UnexpectedEof`.
+    fn decode_from(reader: &mut impl Read) -> io::Result<Option<ReplayRecord>> {
+        let mut tag = [0; 1];
+        // Distinguish a clean EOF (no more records) from a truncated record.
+        if reader.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let record = match tag[0] {
+            TAG_PUT => ReplayRecord::Put {
+                cf: decode_u8(reader)? as usize,
+                seq: decode_u64(reader)?,
+                key: Bytes::from(decode_slice(reader)?),
+                value: Bytes::from(decode_slice(reader)?),
+            },
+            TAG_DELETE => ReplayRecord::Delete {
+                cf: decode_u8(reader)? as usize,
+                seq: decode_u64(reader)?,
+                key: Bytes::from(decode_slice(reader)?),
+            },
+            TAG_LOAD => ReplayRecord::Load(decode_range(reader)?),
+            TAG_EVICT => ReplayRecord::Evict(decode_range(reader)?),
+            TAG_SAFE_POINT => {
+                let range = decode_range(reader)?;
+                ReplayRecord::SafePoint(range, decode_u64(reader)?)
+            }
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown replay record tag {}", tag),
+                ));
+            }
+        };
+        Ok(Some(record))
+    }
+}
+
+fn encode_slice(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s);
+}
+
+fn decode_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn decode_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn decode_slice(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut buf = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn decode_range(reader: &mut impl Read) -> io::Result<CacheRange> {
+    let start = decode_slice(reader)?;
+    let end = decode_slice(reader)?;
+    Ok(CacheRange::new(start, end))
+}
+
+enum RecorderMsg {
+    Records(Vec<ReplayRecord>),
+    Flush(Sender<()>),
+}
+
+/// Appends [`ReplayRecord`]s to a log file from a dedicated thread. See the
+/// module documentation for the overall workflow.
+pub struct ReplayRecorder {
+    sender: Sender<RecorderMsg>,
+    dropped_batches: Arc<AtomicU64>,
+}
+
+impl ReplayRecorder {
+    /// Creates a recorder appending to the file at `path`. When the file
+    /// grows past `rotate_size` bytes it is renamed aside with an `.old`
+    /// suffix (replacing the previous rotated file, so at most two files
+    /// exist) and a fresh one is started.
+    pub fn new(path: impl Into<PathBuf>, rotate_size: u64) -> io::Result<ReplayRecorder> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        let (sender, receiver) = channel::bounded(RECORDER_CHANNEL_CAP);
+        let dropped_batches = Arc::new(AtomicU64::new(0));
+        info!(
+            "start ime replay recorder";
+            "path" => %path.display(),
+            "rotate_size" => rotate_size,
+        );
+        thread::Builder::new()
+            .name("ime-replay-recorder".to_string())
+            .spawn(move || write_loop(path, file, written, rotate_size, receiver))?;
+        Ok(ReplayRecorder {
+            sender,
+            dropped_batches,
+        })
+    }
+
+    /// Queues `records` for appending. Never blocks: if the writer thread
+    /// has fallen `RECORDER_CHANNEL_CAP` batches behind, the batch is
+    /// dropped and counted instead.
+    pub fn record(&self, records: Vec<ReplayRecord>) {
+        if records.is_empty() {
+            return;
+        }
+        if self.sender.try_send(RecorderMsg::Records(records)).is_err() {
+            self.dropped_batches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Blocks until all batches queued so far have reached the file.
+    pub fn flush(&self) {
+        let (ack_tx, ack_rx) = channel::bounded(1);
+        if self.sender.send(RecorderMsg::Flush(ack_tx)).is_ok() {
+            let _ = ack_rx.recv();
+        }
+    }
+
+    /// The number of batches dropped because the writer could not keep up.
+    /// A non-zero count means a replay of the log is not trustworthy.
+    pub fn dropped_batches(&self) -> u64 {
+        self.dropped_batches.load(Ordering::Relaxed)
+    }
+}
+
+fn write_loop(
+    path: PathBuf,
+    file: File,
+    mut written: u64,
+    rotate_size: u64,
+    receiver: Receiver<RecorderMsg>,
+) {
+    let mut writer = BufWriter::new(file);
+    let mut buf = Vec::new();
+    while let Ok(msg) = receiver.recv() {
+        match msg {
+            RecorderMsg::Records(records) => {
+                buf.clear();
+                for record in &records {
+                    record.encode_to(&mut buf);
+                }
+                if let Err(e) = writer.write_all(&buf) {
+                    warn!(
+                        "ime replay recorder failed to write, stop recording";
+                        "path" => %path.display(),
+                        "err" => %e,
+                    );
+                    return;
+                }
+                written += buf.len() as u64;
+                if written > rotate_size {
+                    match rotate(&path, &mut writer) {
+                        Ok(file) => {
+                            writer = BufWriter::new(file);
+                            written = 0;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "ime replay recorder failed to rotate, stop recording";
+                                "path" => %path.display(),
+                                "err" => %e,
+                            );
+                            return;
+                        }
+                    }
+                }
+            }
+            RecorderMsg::Flush(ack) => {
+                if let Err(e) = writer.flush() {
+                    warn!(
+                        "ime replay recorder failed to flush";
+                        "path" => %path.display(),
+                        "err" => %e,
+                    );
+                }
+                let _ = ack.send(());
+            }
+        }
+    }
+    // All senders are gone, persist what is buffered before exiting.
+    if let Err(e) = writer.flush() {
+        warn!(
+            "ime replay recorder failed to flush on exit";
+            "path" => %path.display(),
+            "err" => %e,
+        );
+    }
+}
+
+fn rotate(path: &Path, writer: &mut BufWriter<File>) -> io::Result<File> {
+    writer.flush()?;
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".old");
+    std::fs::rename(path, rotated)?;
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Reads all records from the replay log at `path`. A record truncated by a
+/// crash mid-append terminates the read without an error, as everything
+/// before it is still usable.
+pub fn read_replay_log(path: &Path) -> io::Result<Vec<ReplayRecord>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    loop {
+        match ReplayRecord::decode_from(&mut reader) {
+            Ok(Some(record)) => records.push(record),
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!(
+                    "replay log ends with a truncated record, ignore it";
+                    "path" => %path.display(),
+                    "records" => records.len(),
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(records)
+}
+
+/// Replays `records` into `engine`, which should be freshly created. Write
+/// records are applied with their recorded sequence numbers, so the
+/// resulting skiplists are byte-identical to the captured ones. Returns the
+/// largest sequence number seen, suitable as the comparison point for
+/// [`find_first_divergence`].
+pub fn replay_records(
+    records: impl IntoIterator<Item = ReplayRecord>,
+    engine: &RangeCacheMemoryEngine,
+) -> u64 {
+    let skiplist_engine = engine.core.read().engine();
+    let memory_controller = engine.memory_controller();
+    let dedup_limit = engine.config().value().value_dedup_table_limit;
+    let guard = &epoch::pin();
+    let mut max_seq = 0;
+    for record in records {
+        match record {
+            ReplayRecord::Put {
+                cf,
+                seq,
+                key,
+                value,
+            } => {
+                max_seq = cmp::max(max_seq, seq);
+                RangeCacheWriteBatchEntry::put_value(id_to_cf(cf), &key, &value)
+                    .write_to_memory(
+                        seq,
+                        &skiplist_engine,
+                        memory_controller.clone(),
+                        dedup_limit,
+                        guard,
+                    )
+                    .unwrap();
+            }
+            ReplayRecord::Delete { cf, seq, key } => {
+                max_seq = cmp::max(max_seq, seq);
+                RangeCacheWriteBatchEntry::deletion(id_to_cf(cf), &key)
+                    .write_to_memory(
+                        seq,
+                        &skiplist_engine,
+                        memory_controller.clone(),
+                        dedup_limit,
+                        guard,
+                    )
+                    .unwrap();
+            }
+            ReplayRecord::Load(range) => engine.new_range(range),
+            ReplayRecord::Evict(range) => engine.evict_range(&range),
+            ReplayRecord::SafePoint(range, safe_point) => {
+                engine
+                    .core
+                    .write()
+                    .mut_range_manager()
+                    .set_safe_point(&range, safe_point);
+            }
+        }
+    }
+    max_seq
+}
+
+/// A key where the cache and the disk engine disagree. `None` means the key
+/// is absent, either entirely or behind a delete mark in the cache.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub cf: &'static str,
+    pub key: Vec<u8>,
+    pub cache_value: Option<Vec<u8>>,
+    pub disk_value: Option<Vec<u8>>,
+}
+
+/// Compares the contents of `cache` visible at sequence number `seq` against
+/// `disk` over all cached ranges, cf by cf. Ranges are visited in key order
+/// and cfs in [`DATA_CFS`] order, and the first differing key is returned,
+/// so repeated runs on the same data report the same divergence.
+pub fn find_first_divergence(
+    cache: &RangeCacheMemoryEngine,
+    disk: &impl Iterable,
+    seq: u64,
+) -> Result<Option<Divergence>> {
+    let (ranges, skiplist_engine) = {
+        let core = cache.core.read();
+        let ranges: Vec<_> = core.range_manager().ranges().keys().cloned().collect();
+        (ranges, core.engine())
+    };
+    let values_framed = cache.config().value().value_compression.is_enabled();
+    let guard = &epoch::pin();
+    for range in &ranges {
+        for &cf in DATA_CFS {
+            // The newest version of a user key within `seq` is the visible
+            // one; versions of the same key are ordered newest first, so the
+            // first version at or below `seq` wins.
+            let mut cache_view: BTreeMap<Vec<u8>, Option<Vec<u8>>> = BTreeMap::new();
+            let (start, end) = encode_key_for_boundary_without_mvcc(range);
+            let mut iter = skiplist_engine.cf_handle(cf).iterator();
+            iter.seek(&start, guard);
+            while iter.valid() && iter.key() < &end {
+                let InternalKey {
+                    user_key,
+                    v_type,
+                    sequence,
+                } = decode_key(iter.key().as_slice());
+                if sequence <= seq && !cache_view.contains_key(user_key) {
+                    // The cached values are framed when value compression is
+                    // enabled while the disk values are not, so compare the
+                    // decoded bytes. See the `compression` module.
+                    let value = if matches!(v_type, ValueType::Value) {
+                        Some(if values_framed {
+                            decode_value(iter.value().clone_bytes())?.to_vec()
+                        } else {
+                            iter.value().as_slice().to_vec()
+                        })
+                    } else {
+                        None
+                    };
+                    cache_view.insert(user_key.to_vec(), value);
+                }
+                iter.next(guard);
+            }
+
+            let mut disk_view: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+            disk.scan(cf, &range.start, &range.end, false, |k, v| {
+                disk_view.insert(k.to_vec(), v.to_vec());
+                Ok(true)
+            })?;
+
+            let mut keys: BTreeSet<&Vec<u8>> = cache_view.keys().collect();
+            keys.extend(disk_view.keys());
+            for key in keys {
+                let cache_value = cache_view.get(key).cloned().flatten();
+                let disk_value = disk_view.get(key).cloned();
+                if cache_value != disk_value {
+                    return Ok(Some(Divergence {
+                        cf,
+                        key: key.clone(),
+                        cache_value,
+                        disk_value,
+                    }));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Replays the log at `log_path` into `engine` (which should be freshly
+/// created) and compares it against the RocksDB at `rocks_dir`, at sequence
+/// number `seq` or, if `None`, at the largest sequence number in the log.
+pub fn replay_and_compare(
+    engine: &RangeCacheMemoryEngine,
+    log_path: &Path,
+    rocks_dir: &str,
+    seq: Option<u64>,
+) -> Result<Option<Divergence>> {
+    let records = read_replay_log(log_path).map_err(|e| Error::Other(Box::new(e)))?;
+    let max_seq = replay_records(records, engine);
+    let disk = engine_rocks::util::new_engine(rocks_dir, DATA_CFS)?;
+    find_first_divergence(engine, &disk, seq.unwrap_or(max_seq))
+}
+
+/// Waits until `range` has applied all writes up to the disk sequence number
+/// `seq`, then compares the cached contents against `disk` as of that
+/// sequence. This is the barrier a consistency check needs before diffing the
+/// cache against a disk scan taken at `seq`: without it, batches that are
+/// still in flight show up as spurious divergences.
+pub fn wait_and_find_divergence(
+    cache: &RangeCacheMemoryEngine,
+    disk: &impl Iterable,
+    range: &CacheRange,
+    seq: u64,
+    timeout: Duration,
+) -> Result<Option<Divergence>> {
+    cache
+        .wait_for_sequence(range, seq, timeout)
+        .map_err(|e| Error::Other(format!("wait for sequence {} failed: {:?}", seq, e).into()))?;
+    find_first_divergence(cache, disk, seq)
+}
+
+impl RangeCacheMemoryEngine {
+    /// Attaches `recorder` so that all future writes and range events are
+    /// appended to its log. Must be called before the engine is cloned or
+    /// shared, typically right after creation.
+    pub fn set_replay_recorder(&mut self, recorder: Arc<ReplayRecorder>) {
+        self.replay_recorder = Some(recorder);
+    }
+
+    pub(crate) fn record_replay(&self, records: Vec<ReplayRecord>) {
+        if let Some(recorder) = &self.replay_recorder {
+            recorder.record(records);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engine_rocks::util::new_engine;
+    use engine_traits::{Mutable, SyncMutable, WriteBatch, CF_DEFAULT, CF_LOCK, CF_WRITE};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use tempfile::Builder;
+    use tikv_util::config::VersionTrack;
+
+    use super::*;
+    use crate::{
+        engine::cf_to_id, write_batch::RangeCacheWriteBatch, RangeCacheEngineConfig,
+        RangeCacheEngineContext,
+    };
+
+    fn new_test_engine() -> RangeCacheMemoryEngine {
+        RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )))
+    }
+
+    fn skiplist_contents(engine: &RangeCacheMemoryEngine) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+        let skiplist_engine = engine.core.read().engine();
+        let guard = &epoch::pin();
+        let mut contents = Vec::new();
+        for &cf in DATA_CFS {
+            let mut entries = Vec::new();
+            let mut iter = skiplist_engine.cf_handle(cf).iterator();
+            iter.seek_to_first(guard);
+            while iter.valid() {
+                entries.push((
+                    iter.key().as_slice().to_vec(),
+                    iter.value().as_slice().to_vec(),
+                ));
+                iter.next(guard);
+            }
+            contents.push(entries);
+        }
+        contents
+    }
+
+    #[test]
+    fn test_record_encode_roundtrip() {
+        let records = vec![
+            ReplayRecord::Put {
+                cf: cf_to_id(CF_WRITE),
+                seq: 100,
+                key: Bytes::from_static(b"k1"),
+                value: Bytes::from_static(b"v1"),
+            },
+            ReplayRecord::Delete {
+                cf: cf_to_id(CF_LOCK),
+                seq: 101,
+                key: Bytes::from_static(b"k2"),
+            },
+            ReplayRecord::Load(CacheRange::new(b"a".to_vec(), b"b".to_vec())),
+            ReplayRecord::Evict(CacheRange::new(b"b".to_vec(), b"c".to_vec())),
+            ReplayRecord::SafePoint(CacheRange::new(b"a".to_vec(), b"b".to_vec()), 42),
+        ];
+        let mut buf = Vec::new();
+        let mut last_record_offset = 0;
+        for record in &records {
+            last_record_offset = buf.len();
+            record.encode_to(&mut buf);
+        }
+        let mut reader = &buf[..];
+        let mut decoded = Vec::new();
+        while let Some(record) = ReplayRecord::decode_from(&mut reader).unwrap() {
+            decoded.push(record);
+        }
+        assert_eq!(records, decoded);
+        // A truncated tail surfaces as UnexpectedEof so that read_replay_log
+        // can keep the complete prefix.
+        let mut truncated = &buf[last_record_offset..buf.len() - 1];
+        assert_eq!(
+            ReplayRecord::decode_from(&mut truncated)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay_roundtrip() {
+        let path = Builder::new()
+            .prefix("test_record_and_replay_roundtrip")
+            .tempdir()
+            .unwrap();
+        let log_path = path.path().join("ime-replay.log");
+        let recorder = Arc::new(ReplayRecorder::new(&log_path, u64::MAX).unwrap());
+
+        let mut engine = new_test_engine();
+        engine.set_replay_recorder(recorder.clone());
+        let range = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(range.clone());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().set_safe_point(&range, 10);
+        }
+
+        // A randomized workload of puts and deletes over a small key pool so
+        // that keys accumulate multiple versions.
+        let mut rng = StdRng::seed_from_u64(0xcafe);
+        let mut seq = 1;
+        for _ in 0..20 {
+            let mut wb = RangeCacheWriteBatch::from(&engine);
+            wb.prepare_for_range(range.clone());
+            let mut count = 0;
+            for _ in 0..rng.gen_range(1..20) {
+                let key = format!("k{:02}", rng.gen_range(0..30)).into_bytes();
+                let cf = [CF_DEFAULT, CF_WRITE][rng.gen_range(0..2)];
+                if rng.gen_bool(0.2) {
+                    wb.delete_cf(cf, &key).unwrap();
+                } else {
+                    wb.put_cf(cf, &key, format!("v{}", seq + count).as_bytes())
+                        .unwrap();
+                }
+                count += 1;
+            }
+            wb.set_sequence_number(seq).unwrap();
+            wb.write().unwrap();
+            seq += count;
+        }
+        recorder.flush();
+        assert_eq!(recorder.dropped_batches(), 0);
+
+        let replayed = new_test_engine();
+        let records = read_replay_log(&log_path).unwrap();
+        replay_records(records, &replayed);
+        // The replayed skiplists must be byte-identical, internal keys with
+        // sequence numbers included.
+        assert_eq!(skiplist_contents(&engine), skiplist_contents(&replayed));
+    }
+
+    #[test]
+    fn test_divergence_pinpoints_dropped_batch() {
+        let path = Builder::new()
+            .prefix("test_divergence_pinpoints_dropped_batch")
+            .tempdir()
+            .unwrap();
+        let log_path = path.path().join("ime-replay.log");
+        let recorder = Arc::new(ReplayRecorder::new(&log_path, u64::MAX).unwrap());
+        let rocks_path = path.path().join("rocks");
+        let disk = new_engine(rocks_path.to_str().unwrap(), DATA_CFS).unwrap();
+
+        let mut engine = new_test_engine();
+        engine.set_replay_recorder(recorder.clone());
+        let range = CacheRange::new(b"k".to_vec(), b"l".to_vec());
+        engine.new_range(range.clone());
+
+        // Mirror every write to the disk engine, the way the kv engine sees
+        // them in production.
+        let mut seq = 1;
+        for i in 0..5 {
+            let mut wb = RangeCacheWriteBatch::from(&engine);
+            wb.prepare_for_range(range.clone());
+            let key = format!("k{}", i).into_bytes();
+            let value = format!("v{}", i).into_bytes();
+            wb.put(&key, &value).unwrap();
+            disk.put(&key, &value).unwrap();
+            wb.set_sequence_number(seq).unwrap();
+            wb.write().unwrap();
+            seq += 1;
+        }
+        recorder.flush();
+
+        // Drop the batch that wrote k3 from the log, as if the recorder had
+        // been overloaded, and replay the rest.
+        let mut records = read_replay_log(&log_path).unwrap();
+        records.retain(|r| !matches!(r, ReplayRecord::Put { key, .. } if key.as_ref() == b"k3"));
+        let replayed = new_test_engine();
+        let max_seq = replay_records(records, &replayed);
+
+        let divergence = find_first_divergence(&replayed, &disk, max_seq)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            divergence,
+            Divergence {
+                cf: CF_DEFAULT,
+                key: b"k3".to_vec(),
+                cache_value: None,
+                disk_value: Some(b"v3".to_vec()),
+            }
+        );
+
+        // With the complete log there is no divergence.
+        let complete = new_test_engine();
+        assert_eq!(
+            replay_and_compare(&complete, &log_path, rocks_path.to_str().unwrap(), None).unwrap(),
+            None
+        );
+    }
+}
@@ -1,11 +1,15 @@
 // Copyright 2024 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Mutex,
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
 };
 
 use crossbeam::utils::CachePadded;
+use lazy_static::lazy_static;
 use rand::Rng;
 
 // An array of core-local values from RocksDB. Ideally the generic type, T, is
@@ -81,6 +85,10 @@ pub const ENGINE_TICKER_TYPES: &[Tickers] = &[
     Tickers::NumberDbNextFound,
     Tickers::NumberDbPrev,
     Tickers::NumberDbPrevFound,
+    Tickers::IterDirectionSwitchFast,
+    Tickers::IterDirectionSwitchSlow,
+    Tickers::BytesWrittenCompressed,
+    Tickers::BytesWrittenUncompressed,
 ];
 
 #[repr(u32)]
@@ -94,6 +102,13 @@ pub enum Tickers {
     NumberDbNextFound,
     NumberDbPrev,
     NumberDbPrevFound,
+    IterDirectionSwitchFast,
+    IterDirectionSwitchSlow,
+    // The stored bytes of the values the write path kept in compressed and
+    // in raw form respectively, see the `compression` module. Both stay at
+    // zero while value compression is disabled.
+    BytesWrittenCompressed,
+    BytesWrittenUncompressed,
     TickerEnumMax,
 }
 
@@ -156,6 +171,106 @@ impl Statistics {
     }
 }
 
+lazy_static! {
+    // The skiplist comparator (`Ord` of `InternalBytes`) has no handle on the
+    // engine it serves, so its invocation count cannot live in the per-engine
+    // `Statistics` and is kept process-global instead. Core-local like the
+    // ticker counters, see `CoreLocalArray`.
+    static ref COMPARATOR_CALLS: CoreLocalArray<CachePadded<AtomicU64>> =
+        CoreLocalArray::default();
+}
+
+thread_local! {
+    // A thread-local mirror of `COMPARATOR_CALLS`. A delta of the global
+    // counter around an operation would be polluted by concurrent readers and
+    // writers, so single-threaded callers that need exact per-operation
+    // counts (the skiplist depth sampler) take deltas of this one.
+    static THREAD_COMPARATOR_CALLS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Records one comparator invocation. Called from `Ord` of `InternalBytes`,
+/// i.e. once per key comparison the skiplists perform on any path.
+#[inline]
+pub(crate) fn record_comparator_call() {
+    COMPARATOR_CALLS.access().fetch_add(1, Ordering::Relaxed);
+    THREAD_COMPARATOR_CALLS.with(|c| c.set(c.get() + 1));
+}
+
+/// Returns the number of comparator invocations performed by the current
+/// thread so far, for taking deltas around single-threaded operations.
+pub(crate) fn thread_comparator_calls() -> u64 {
+    THREAD_COMPARATOR_CALLS.with(|c| c.get())
+}
+
+/// Returns the number of comparator invocations process-wide since the last
+/// call and resets the counter, mirroring `get_and_reset_ticker_count`.
+pub(crate) fn take_comparator_calls() -> u64 {
+    COMPARATOR_CALLS
+        .data
+        .iter()
+        .fold(0, |acc, c| acc + c.swap(0, Ordering::Relaxed))
+}
+
+/// The number of recent seek durations the reservoir keeps. Large enough
+/// that a p99 over it is meaningful, small enough that a quantile query
+/// (copy and sort) stays cheap.
+const SEEK_LATENCY_RESERVOIR_SLOTS: usize = 256;
+
+/// A fixed-size ring of the most recent seek durations, in nanoseconds.
+/// Recording is one `fetch_add` plus one relaxed store, cheap enough to run
+/// on every foreground seek; querying sorts a copy, so it is reserved for
+/// the background deletion pacer which runs a few times per second at most.
+struct SeekLatencyReservoir {
+    cursor: AtomicU64,
+    // 0 marks a slot that has not been written yet.
+    slots: Vec<AtomicU64>,
+}
+
+impl Default for SeekLatencyReservoir {
+    fn default() -> Self {
+        let mut slots = Vec::with_capacity(SEEK_LATENCY_RESERVOIR_SLOTS);
+        slots.resize_with(SEEK_LATENCY_RESERVOIR_SLOTS, AtomicU64::default);
+        SeekLatencyReservoir {
+            cursor: AtomicU64::new(0),
+            slots,
+        }
+    }
+}
+
+lazy_static! {
+    // Like `COMPARATOR_CALLS`, kept process-global: the delete worker that
+    // consumes the quantiles has no handle on the per-engine `Statistics`,
+    // and one process hosts at most one range cache engine.
+    static ref SEEK_LATENCIES: SeekLatencyReservoir = SeekLatencyReservoir::default();
+}
+
+/// Records the duration of one foreground seek into the reservoir. Called
+/// from every seek of a range cache iterator, see
+/// `RangeCacheIterator::observe_seek_duration`.
+pub(crate) fn record_seek_latency(duration: std::time::Duration) {
+    let idx = SEEK_LATENCIES.cursor.fetch_add(1, Ordering::Relaxed) as usize
+        % SEEK_LATENCY_RESERVOIR_SLOTS;
+    // Store at least 1ns so the slot does not read as unwritten.
+    SEEK_LATENCIES.slots[idx].store(u64::max(duration.as_nanos() as u64, 1), Ordering::Relaxed);
+}
+
+/// Returns quantile `q` (in `[0.0, 1.0]`) of the recent seek durations, or
+/// `None` when no seek has been recorded yet.
+pub(crate) fn recent_seek_latency_quantile(q: f64) -> Option<std::time::Duration> {
+    let mut samples: Vec<u64> = SEEK_LATENCIES
+        .slots
+        .iter()
+        .map(|s| s.load(Ordering::Relaxed))
+        .filter(|&nanos| nanos > 0)
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable();
+    let idx = ((samples.len() - 1) as f64 * q) as usize;
+    Some(std::time::Duration::from_nanos(samples[idx]))
+}
+
 // LocalStatistics contain Statistics counters that will be aggregated per
 // each iterator instance and then will be sent to the global statistics when
 // the iterator is destroyed.
@@ -178,6 +293,74 @@ pub(crate) struct LocalStatistics {
     pub(crate) number_db_prev: u64,
     // Map to Tickers::NumberDbPrevFound
     pub(crate) number_db_prev_found: u64,
+    // Map to Tickers::IterDirectionSwitchFast
+    pub(crate) number_direction_switch_fast: u64,
+    // Map to Tickers::IterDirectionSwitchSlow
+    pub(crate) number_direction_switch_slow: u64,
+    // Not mapped to tickers: mirrors of the perf context counters, only
+    // reported through a per-iterator sink, see `LocalStatsSink`.
+    pub(crate) internal_keys_skipped: u64,
+    pub(crate) tombstones_skipped: u64,
+}
+
+/// The read counters of one or more range cache iterators, see
+/// [`LocalStatsSink`]. The skipped counters mirror the perf context ones
+/// (`internal_key_skipped_count` and `internal_delete_skipped_count`), so the
+/// storage layer sees the same numbers it harvests for RocksDB iterators.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct IterReadStats {
+    pub bytes_read: u64,
+    pub seek: u64,
+    pub seek_found: u64,
+    pub next: u64,
+    pub next_found: u64,
+    pub prev: u64,
+    pub prev_found: u64,
+    pub direction_switch_fast: u64,
+    pub direction_switch_slow: u64,
+    pub internal_keys_skipped: u64,
+    pub tombstones_skipped: u64,
+}
+
+/// A caller-supplied statistics sink that receives the local counters of the
+/// iterators created through it (see
+/// `RangeCacheSnapshot::iterator_opt_with_stats`), so per-request read stats
+/// can be attributed to individual queries. The engine-global [`Statistics`]
+/// keep being updated as before; the sink is an additional copy.
+///
+/// An iterator flushes its counters into the sink when it is dropped, in one
+/// batch just like the flush into the global statistics, so a sink shared by
+/// several iterators never observes a partial update.
+#[derive(Default)]
+pub struct LocalStatsSink {
+    stats: Mutex<IterReadStats>,
+}
+
+impl LocalStatsSink {
+    pub(crate) fn add(&self, local: &LocalStatistics) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.bytes_read += local.bytes_read;
+        stats.seek += local.number_db_seek;
+        stats.seek_found += local.number_db_seek_found;
+        stats.next += local.number_db_next;
+        stats.next_found += local.number_db_next_found;
+        stats.prev += local.number_db_prev;
+        stats.prev_found += local.number_db_prev_found;
+        stats.direction_switch_fast += local.number_direction_switch_fast;
+        stats.direction_switch_slow += local.number_direction_switch_slow;
+        stats.internal_keys_skipped += local.internal_keys_skipped;
+        stats.tombstones_skipped += local.tombstones_skipped;
+    }
+
+    /// Returns the accumulated counters and resets the sink.
+    pub fn take(&self) -> IterReadStats {
+        std::mem::take(&mut *self.stats.lock().unwrap())
+    }
+
+    /// Returns the accumulated counters without resetting them.
+    pub fn get(&self) -> IterReadStats {
+        *self.stats.lock().unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     sync::{atomic::Ordering, Arc},
     time::Duration,
 };
@@ -7,18 +7,26 @@ use std::{
 use bytes::Bytes;
 use crossbeam::epoch;
 use engine_traits::{
-    CacheRange, MiscExt, Mutable, RangeCacheEngine, Result, WriteBatch, WriteBatchExt,
+    CacheRange, CachedCfs, MiscExt, Mutable, RangeCacheEngine, Result, WriteBatch, WriteBatchExt,
     WriteOptions, CF_DEFAULT,
 };
 use tikv_util::{box_err, config::ReadableSize, error, info, time::Instant, warn};
 
 use crate::{
-    background::BackgroundTask,
+    background::{note_prewarm_skipped, BackgroundTask},
+    checksum::entry_hash,
+    compression::{maybe_compress_value, ValueCompression},
     engine::{cf_to_id, id_to_cf, is_lock_cf, SkiplistEngine},
     keys::{encode_key, InternalBytes, ValueType, ENC_KEY_SEQ_LENGTH},
-    memory_controller::{MemoryController, MemoryUsage},
-    metrics::{RANGE_PREPARE_FOR_WRITE_DURATION_HISTOGRAM, WRITE_DURATION_HISTOGRAM},
+    memory_controller::{MemoryController, MemoryUsage, WritePressure},
+    metrics::{
+        RANGE_PREPARE_FOR_WRITE_DURATION_HISTOGRAM, WRITE_BATCH_BYTES_HISTOGRAM,
+        WRITE_BATCH_ENTRIES_HISTOGRAM, WRITE_BATCH_SEQNO_MISUSE, WRITE_BATCH_SKIPPED_BY_PRESSURE,
+        WRITE_BATCH_SKIPPED_UNCACHED, WRITE_DURATION_HISTOGRAM,
+    },
     range_manager::{RangeCacheStatus, RangeManager},
+    replay::ReplayRecord,
+    statistics::Tickers,
     RangeCacheMemoryEngine,
 };
 
@@ -54,6 +62,10 @@ pub struct RangeCacheWriteBatch {
     // `pending_range_in_loading_buffer` which is cached in the memory engine and will be consumed
     // after the snapshot has been loaded.
     range_cache_status: RangeCacheStatus,
+    // Which data cfs are cached for the current range. Writes of the uncached
+    // cfs of a partially loaded range are not mirrored, as reads of those cfs
+    // are always served by the disk engine.
+    current_cached_cfs: CachedCfs,
     buffer: Vec<RangeCacheWriteBatchEntry>,
     pending_range_in_loading_buffer: Vec<RangeCacheWriteBatchEntry>,
     engine: RangeCacheMemoryEngine,
@@ -61,10 +73,32 @@ pub struct RangeCacheWriteBatch {
     sequence_number: Option<u64>,
     memory_controller: Arc<MemoryController>,
     memory_usage_reach_hard_limit: bool,
+    // The value compression settings the engine was started with, cached
+    // here so the hot put path does not read the versioned config per key.
+    // See the `compression` module.
+    value_compression: ValueCompression,
+    value_compression_threshold: usize,
+    // The value dedup table cap, 0 when deduplication is disabled. Cached
+    // like the compression settings. See the `dedup` module.
+    value_dedup_table_limit: usize,
 
     current_range: Option<CacheRange>,
     // the ranges that reaches the hard limit and need to be evicted
     ranges_to_evict: BTreeSet<CacheRange>,
+    // The apply indexes recorded by `set_range_applied_index`, keyed by the
+    // range they were recorded for. They are consumed when the batch is
+    // written to advance the per-range freshness watermarks.
+    range_applied_indexes: BTreeMap<CacheRange, u64>,
+    // The apply terms recorded by `set_range_applied_term`, keyed like
+    // `range_applied_indexes`. Only consumed for the provenance records when
+    // `provenance_tracking` is enabled.
+    range_apply_terms: BTreeMap<CacheRange, u64>,
+    // The shadow disk checksum updates recorded by
+    // `record_disk_shadow_checksums`, applied together with the cache side
+    // when the batch is consumed. `None` when the hybrid layer did not record
+    // them (or the verification is disabled), in which case the batch is not
+    // tracked.
+    disk_shadow_checksums: Option<Vec<(CacheRange, u64)>>,
 
     // record the total durations of the prepare work for write in the write batch
     prepare_for_write_duration: Duration,
@@ -82,9 +116,11 @@ impl std::fmt::Debug for RangeCacheWriteBatch {
 
 impl From<&RangeCacheMemoryEngine> for RangeCacheWriteBatch {
     fn from(engine: &RangeCacheMemoryEngine) -> Self {
+        let config = engine.config().value();
         Self {
             id: engine.alloc_write_batch_id(),
             range_cache_status: RangeCacheStatus::NotInCache,
+            current_cached_cfs: CachedCfs::all(),
             buffer: Vec::new(),
             pending_range_in_loading_buffer: Vec::new(),
             engine: engine.clone(),
@@ -92,8 +128,14 @@ impl From<&RangeCacheMemoryEngine> for RangeCacheWriteBatch {
             sequence_number: None,
             memory_controller: engine.memory_controller(),
             memory_usage_reach_hard_limit: false,
+            value_compression: config.value_compression,
+            value_compression_threshold: config.value_compression_threshold.0 as usize,
+            value_dedup_table_limit: config.value_dedup_table_limit,
             current_range: None,
             ranges_to_evict: BTreeSet::default(),
+            range_applied_indexes: BTreeMap::default(),
+            range_apply_terms: BTreeMap::default(),
+            disk_shadow_checksums: None,
             prepare_for_write_duration: Duration::default(),
         }
     }
@@ -101,9 +143,11 @@ impl From<&RangeCacheMemoryEngine> for RangeCacheWriteBatch {
 
 impl RangeCacheWriteBatch {
     pub fn with_capacity(engine: &RangeCacheMemoryEngine, cap: usize) -> Self {
+        let config = engine.config().value();
         Self {
             id: engine.alloc_write_batch_id(),
             range_cache_status: RangeCacheStatus::NotInCache,
+            current_cached_cfs: CachedCfs::all(),
             buffer: Vec::with_capacity(cap),
             // cache_buffer should need small capacity
             pending_range_in_loading_buffer: Vec::new(),
@@ -112,8 +156,14 @@ impl RangeCacheWriteBatch {
             sequence_number: None,
             memory_controller: engine.memory_controller(),
             memory_usage_reach_hard_limit: false,
+            value_compression: config.value_compression,
+            value_compression_threshold: config.value_compression_threshold.0 as usize,
+            value_dedup_table_limit: config.value_dedup_table_limit,
             current_range: None,
             ranges_to_evict: BTreeSet::default(),
+            range_applied_indexes: BTreeMap::default(),
+            range_apply_terms: BTreeMap::default(),
+            disk_shadow_checksums: None,
             prepare_for_write_duration: Duration::default(),
         }
     }
@@ -161,19 +211,134 @@ impl RangeCacheWriteBatch {
 
     /// Sets the sequence number for this batch. This should only be called
     /// prior to writing the batch.
+    ///
+    /// `seq` must be the first sequence number RocksDB assigned to the paired
+    /// disk batch: the buffered entries take `[seq, seq + count)` in insertion
+    /// order and visibility is purely `seq <= snapshot_seq`, so a stale or
+    /// reused value would create entries that are invisible to correct
+    /// snapshots or that shadow newer data. Since cache batches are consumed
+    /// in RocksDB commit order, rejecting any `seq` not beyond the engine's
+    /// highest committed sequence rejects exactly those values.
     pub fn set_sequence_number(&mut self, seq: u64) -> Result<()> {
         if let Some(seqno) = self.sequence_number {
             return Err(box_err!("Sequence number {} already set", seqno));
         };
+        if self.entry_count() != 0 {
+            let committed = self.engine.core.read().max_applied_seqno();
+            if seq <= committed {
+                WRITE_BATCH_SEQNO_MISUSE.inc();
+                return Err(box_err!(
+                    "stale sequence number {}, sequences up to {} are already committed",
+                    seq,
+                    committed
+                ));
+            }
+        }
         self.sequence_number = Some(seq);
         Ok(())
     }
 
+    /// The sequence numbers `[start, end)` the buffered entries will consume
+    /// when the batch is written, derived from the entry order. `None` until
+    /// `set_sequence_number` is called. The hybrid layer cross-checks the
+    /// start against the RocksDB write result.
+    pub fn sequence_range(&self) -> Option<(u64, u64)> {
+        self.sequence_number
+            .map(|seq| (seq, seq + self.entry_count() as u64))
+    }
+
+    /// Records the shadow disk checksum updates for the buffered entries,
+    /// see the `checksum` module. Called by the hybrid engine write path,
+    /// whose disk batch carries the identical entries for the cached ranges.
+    /// The updates are buffered and applied together with the cache side
+    /// when the batch is consumed, so the background comparison never
+    /// observes one side of a batch without the other. Entries buffered for
+    /// ranges that are still loading are not tracked; their checksums start
+    /// once the range is active.
+    pub fn record_disk_shadow_checksums(&mut self) {
+        if !self.engine.config().value().checksum_verification || self.buffer.is_empty() {
+            return;
+        }
+        let core = self.engine.core.read();
+        let range_manager = core.range_manager();
+        let mut updates: Vec<(CacheRange, u64)> = Vec::new();
+        for e in &self.buffer {
+            if let Some(r) = range_manager.get_range_for_key(&e.key) {
+                match updates.iter_mut().find(|(ur, _)| *ur == r) {
+                    Some((_, h)) => *h = h.wrapping_add(e.checksum_hash()),
+                    None => updates.push((r, e.checksum_hash())),
+                }
+            }
+        }
+        self.disk_shadow_checksums = Some(updates);
+    }
+
+    // Every buffered entry consumes one sequence number when the batch is
+    // written, whether it is applied directly or cached for a loading range.
+    fn entry_count(&self) -> usize {
+        self.buffer.len() + self.pending_range_in_loading_buffer.len()
+    }
+
+    // The cached or loading ranges the buffered entries fall in.
+    fn touched_ranges(&self) -> Vec<CacheRange> {
+        let core = self.engine.core.read();
+        let range_manager = core.range_manager();
+        let mut ranges = BTreeSet::new();
+        for e in self
+            .buffer
+            .iter()
+            .chain(&self.pending_range_in_loading_buffer)
+        {
+            if let Some(r) = range_manager.get_range_for_key(&e.key) {
+                ranges.insert(r);
+            } else if let Some((r, ..)) = range_manager
+                .pending_ranges_loading_data
+                .iter()
+                .find(|(r, ..)| r.contains_key(&e.key))
+            {
+                ranges.insert(r.clone());
+            }
+        }
+        ranges.into_iter().collect()
+    }
+
     // Note: `seq` is the sequence number of the first key in this write batch in
     // the RocksDB, which will be incremented automatically for each key, so
     // that all keys have unique sequence numbers.
     fn write_impl(&mut self, mut seq: u64) -> Result<()> {
         fail::fail_point!("on_write_impl");
+        if self.entry_count() != 0 {
+            let committed = self.engine.core.read().max_applied_seqno();
+            if seq <= committed {
+                // The validation in `set_sequence_number` was bypassed.
+                // Writing the entries would corrupt visibility, so refuse the
+                // batch and evict the touched ranges as a safety net: the
+                // disk engine may have applied the mutations, and serving the
+                // now-stale cached data would be incorrect.
+                WRITE_BATCH_SEQNO_MISUSE.inc();
+                let ranges = self.touched_ranges();
+                error!(
+                    "stale sequence number for range cache write batch, evicting touched ranges";
+                    "seq" => seq,
+                    "committed" => committed,
+                    "ranges" => ?ranges,
+                );
+                self.buffer.clear();
+                self.pending_range_in_loading_buffer.clear();
+                self.disk_shadow_checksums = None;
+                for r in &ranges {
+                    self.engine.evict_range(r);
+                }
+                let mut core = self.engine.core.write();
+                core.mut_range_manager()
+                    .clear_ranges_in_being_written(self.id, false);
+                return Err(box_err!(
+                    "stale sequence number {}, sequences up to {} are already committed",
+                    seq,
+                    committed
+                ));
+            }
+        }
         let ranges_to_delete = self.handle_ranges_to_evict();
         let (entries_to_write, engine) = self.engine.handle_pending_range_in_loading_buffer(
             &mut seq,
@@ -183,6 +348,25 @@ impl RangeCacheWriteBatch {
         let start = Instant::now();
         let mut lock_modification: u64 = 0;
         let mut have_entry_applied = false;
+        let mut entry_count: u64 = 0;
+        let mut entry_bytes: u64 = 0;
+        // The cache-side checksum contributions of the applied buffer
+        // entries, only collected when the hybrid layer recorded the disk
+        // shadow side. The entries diverted from the loading buffer are
+        // excluded to mirror `record_disk_shadow_checksums`.
+        let track_checksums = self.disk_shadow_checksums.is_some();
+        let pending_entry_count = entries_to_write.len() as u64;
+        let mut cache_checksum_updates: Vec<(Bytes, u64)> = Vec::new();
+        // The (key, sequence) pairs of the applied buffer entries, only
+        // collected when provenance tracking is enabled and the apply path
+        // attached an apply index. Like the checksums, entries diverted from
+        // the loading buffer are excluded.
+        let track_provenance = !self.range_applied_indexes.is_empty()
+            && self.engine.config().value().provenance_tracking;
+        let mut provenance_keys: Vec<(Bytes, u64)> = Vec::new();
+        // Only collected when a replay recorder is attached, see the `replay`
+        // module.
+        let mut replay_records = self.engine.replay_recorder.as_ref().map(|_| Vec::new());
         // Some entries whose ranges may be marked as evicted above, but it does not
         // matter, they will be deleted later.
         let res = entries_to_write
@@ -193,20 +377,113 @@ impl RangeCacheWriteBatch {
                 if is_lock_cf(e.cf) {
                     lock_modification += e.data_size() as u64;
                 }
+                entry_count += 1;
+                entry_bytes += e.data_size() as u64;
                 seq += 1;
-                e.write_to_memory(seq - 1, &engine, self.memory_controller.clone(), guard)
+                if let Some(records) = replay_records.as_mut() {
+                    records.push(e.to_replay_record(seq - 1));
+                }
+                if track_checksums && entry_count > pending_entry_count {
+                    cache_checksum_updates.push((e.key.clone(), e.checksum_hash()));
+                }
+                if track_provenance && entry_count > pending_entry_count {
+                    provenance_keys.push((e.key.clone(), seq - 1));
+                }
+                e.write_to_memory(
+                    seq - 1,
+                    &engine,
+                    self.memory_controller.clone(),
+                    self.value_dedup_table_limit,
+                    guard,
+                )
             });
+        if let Some(disk_updates) = self.disk_shadow_checksums.take() {
+            let (checksums, cache_updates) = {
+                let core = self.engine.core.read();
+                let range_manager = core.range_manager();
+                let mut grouped: Vec<(CacheRange, u64)> = Vec::new();
+                for (key, hash) in cache_checksum_updates {
+                    // An entry whose range was evicted in between is skipped;
+                    // the pair of the evicted range is dropped by the next
+                    // comparison pass anyway.
+                    if let Some(r) = range_manager.get_range_for_key(&key) {
+                        match grouped.iter_mut().find(|(ur, _)| *ur == r) {
+                            Some((_, h)) => *h = h.wrapping_add(hash),
+                            None => grouped.push((r, hash)),
+                        }
+                    }
+                }
+                (core.checksums(), grouped)
+            };
+            checksums.apply_batch(cache_updates, disk_updates);
+        }
+        if !provenance_keys.is_empty() {
+            // Group the applied entries into per-range sequence spans and
+            // record them against the apply the batch carried. Entries whose
+            // range was evicted in between are skipped; their records would
+            // be dropped with the range anyway.
+            let (provenance, spans) = {
+                let core = self.engine.core.read();
+                let range_manager = core.range_manager();
+                let mut spans: Vec<(CacheRange, u64, u64)> = Vec::new();
+                for (key, entry_seq) in provenance_keys {
+                    if let Some(r) = range_manager.get_range_for_key(&key) {
+                        match spans.iter_mut().find(|(ur, ..)| *ur == r) {
+                            Some((.., end)) => *end = entry_seq + 1,
+                            None => spans.push((r, entry_seq, entry_seq + 1)),
+                        }
+                    }
+                }
+                (core.provenance(), spans)
+            };
+            for (r, start, end) in spans {
+                if let Some(&apply_index) = self.range_applied_indexes.get(&r) {
+                    let term = self.range_apply_terms.get(&r).copied().unwrap_or(0);
+                    provenance.record(r, start, end, apply_index, term);
+                }
+            }
+        }
+        self.range_apply_terms.clear();
+        if let Some(records) = replay_records {
+            self.engine.record_replay(records);
+        }
         let duration = start.saturating_elapsed_secs();
         WRITE_DURATION_HISTOGRAM.observe(duration);
+        if have_entry_applied {
+            WRITE_BATCH_ENTRIES_HISTOGRAM.observe(entry_count as f64);
+            WRITE_BATCH_BYTES_HISTOGRAM.observe(entry_bytes as f64);
+        }
 
         fail::fail_point!("in_memory_engine_write_batch_consumed");
         fail::fail_point!("before_clear_ranges_in_being_written");
 
-        self.engine
-            .core
-            .write()
-            .mut_range_manager()
-            .clear_ranges_in_being_written(self.id, have_entry_applied);
+        {
+            let mut core = self.engine.core.write();
+            core.mut_range_manager()
+                .clear_ranges_in_being_written(self.id, have_entry_applied);
+            if have_entry_applied {
+                // `seq` now points one past the last sequence number consumed
+                // by this batch.
+                core.max_applied_seqno.fetch_max(seq - 1, Ordering::Relaxed);
+            }
+            for (range, apply_index) in std::mem::take(&mut self.range_applied_indexes) {
+                core.mut_range_manager().record_cached_watermark(
+                    range,
+                    seq.saturating_sub(1),
+                    apply_index,
+                );
+            }
+        }
+
+        if have_entry_applied {
+            // Wake up `wait_for_sequence` callers now that the watermarks
+            // advanced. Locking the notifier mutex first makes sure a waiter
+            // that has just checked the watermark is parked before the
+            // notification, so the wakeup cannot be lost.
+            let (lock, cvar) = &*self.engine.watermark_notifier;
+            let _notified = lock.lock();
+            cvar.notify_all();
+        }
 
         self.engine
             .lock_modification_bytes
@@ -241,8 +518,10 @@ impl RangeCacheWriteBatch {
         let mut ranges = vec![];
         let range_manager = core.mut_range_manager();
         for r in std::mem::take(&mut self.ranges_to_evict) {
-            let mut ranges_to_delete = range_manager.evict_range(&r);
+            let mut ranges_to_delete = range_manager.evict_range(&r, "write-hard-limit");
             if !ranges_to_delete.is_empty() {
+                // A memory pressure eviction, so no block cache pre-warm.
+                note_prewarm_skipped(&self.engine.config().value(), "write-hard-limit");
                 ranges.append(&mut ranges_to_delete);
                 continue;
             }
@@ -252,6 +531,9 @@ impl RangeCacheWriteBatch {
 
     #[inline]
     pub fn set_range_cache_status(&mut self, range_cache_status: RangeCacheStatus) {
+        if matches!(range_cache_status, RangeCacheStatus::NotInCache) {
+            WRITE_BATCH_SKIPPED_UNCACHED.inc();
+        }
         self.range_cache_status = range_cache_status;
     }
 
@@ -278,6 +560,34 @@ impl RangeCacheWriteBatch {
             self.ranges_to_evict.insert(range);
             return;
         }
+
+        // Write path backpressure: above the soft watermark, new entries for
+        // unpinned ranges are not buffered anymore and the ranges are evicted
+        // so that reads cannot miss the skipped writes; pinning is the
+        // priority notion that exempts a range. Pending loads are paused as
+        // well so they stop growing the usage. Above the hard watermark, an
+        // eviction selection pass is additionally scheduled right away; it is
+        // only enqueued here to keep the apply latency bounded.
+        let pressure = self.memory_controller.write_pressure();
+        if pressure != WritePressure::None {
+            self.engine.bg_worker_manager().load_scheduler().pause();
+            if pressure == WritePressure::Hard {
+                self.schedule_memory_check();
+            }
+            let range = self.current_range.clone().unwrap();
+            if !self.engine.core.read().range_manager().is_pinned(&range) {
+                WRITE_BATCH_SKIPPED_BY_PRESSURE.inc();
+                if self.ranges_to_evict.insert(range.clone()) {
+                    info!(
+                        "write backpressure, stop caching writes and evict the range";
+                        "range_start" => log_wrappers::Value(&range.start),
+                        "range_end" => log_wrappers::Value(&range.end),
+                    );
+                }
+                return;
+            }
+        }
+
         let memory_expect = entry_size();
         if !self.memory_acquire(memory_expect) {
             let range = self.current_range.clone().unwrap();
@@ -378,10 +688,17 @@ pub(crate) struct RangeCacheWriteBatchEntry {
 
 impl RangeCacheWriteBatchEntry {
     pub fn put_value(cf: &str, key: &[u8], value: &[u8]) -> Self {
+        Self::put_stored_value(cf, key, Bytes::copy_from_slice(value))
+    }
+
+    /// Like [`Self::put_value`], but takes the bytes exactly as they are to
+    /// be stored, avoiding a copy when the caller already framed the value
+    /// for compression.
+    pub fn put_stored_value(cf: &str, key: &[u8], value: Bytes) -> Self {
         Self {
             cf: cf_to_id(cf),
             key: Bytes::copy_from_slice(key),
-            inner: WriteBatchEntryInternal::PutValue(Bytes::copy_from_slice(value)),
+            inner: WriteBatchEntryInternal::PutValue(value),
         }
     }
 
@@ -418,18 +735,63 @@ impl RangeCacheWriteBatchEntry {
         self.key.len() + ENC_KEY_SEQ_LENGTH + self.inner.data_size()
     }
 
+    /// Converts the entry into a replay log record carrying `seq`, the
+    /// sequence number the entry is written to the skiplist with.
+    pub fn to_replay_record(&self, seq: u64) -> ReplayRecord {
+        match &self.inner {
+            WriteBatchEntryInternal::PutValue(value) => ReplayRecord::Put {
+                cf: self.cf,
+                seq,
+                key: self.key.clone(),
+                value: value.clone(),
+            },
+            WriteBatchEntryInternal::Deletion => ReplayRecord::Delete {
+                cf: self.cf,
+                seq,
+                key: self.key.clone(),
+            },
+        }
+    }
+
+    /// The order-independent checksum contribution of this entry, see the
+    /// `checksum` module.
+    #[inline]
+    pub fn checksum_hash(&self) -> u64 {
+        match &self.inner {
+            WriteBatchEntryInternal::PutValue(value) => {
+                entry_hash(self.cf, &self.key, Some(value))
+            }
+            WriteBatchEntryInternal::Deletion => entry_hash(self.cf, &self.key, None),
+        }
+    }
+
     #[inline]
     pub fn write_to_memory(
         &self,
         seq: u64,
         skiplist_engine: &SkiplistEngine,
         memory_controller: Arc<MemoryController>,
+        dedup_limit: usize,
         guard: &epoch::Guard,
     ) -> Result<()> {
         let handle = skiplist_engine.cf_handle(id_to_cf(self.cf));
 
         let (mut key, mut value) = self.encode(seq);
         key.set_memory_controller(memory_controller.clone());
+        if dedup_limit > 0
+            && let WriteBatchEntryInternal::PutValue(stored) = &self.inner
+            && let Some(interned) = skiplist_engine.value_dedup.intern(stored, dedup_limit)
+        {
+            if interned.shared {
+                // The value bytes are already accounted for by the tracked
+                // copy; give the buffered duplicate's share back and keep
+                // only this handle's overhead acquired. See the `dedup`
+                // module.
+                memory_controller.release(stored.len());
+                value = InternalBytes::from_bytes(interned.bytes);
+            }
+            value.set_dedup_handle(skiplist_engine.value_dedup.clone(), interned.hash);
+        }
         value.set_memory_controller(memory_controller);
         handle.insert(key, value, guard);
 
@@ -545,7 +907,10 @@ impl WriteBatch for RangeCacheWriteBatch {
     fn clear(&mut self) {
         self.buffer.clear();
         self.save_points.clear();
+        self.range_applied_indexes.clear();
+        self.range_apply_terms.clear();
         _ = self.sequence_number.take();
+        _ = self.disk_shadow_checksums.take();
     }
 
     fn set_save_point(&mut self) {
@@ -575,11 +940,34 @@ impl WriteBatch for RangeCacheWriteBatch {
 
     fn prepare_for_range(&mut self, range: CacheRange) {
         let time = Instant::now();
-        self.set_range_cache_status(self.engine.prepare_for_apply(self.id, &range));
+        let (status, cached_cfs) = self.engine.prepare_for_apply(self.id, &range);
+        self.set_range_cache_status(status);
+        self.current_cached_cfs = cached_cfs;
         self.memory_usage_reach_hard_limit = false;
         self.current_range = Some(range);
         self.prepare_for_write_duration += time.saturating_elapsed();
     }
+
+    fn set_range_applied_index(&mut self, apply_index: u64) {
+        // Only ranges whose writes are actually buffered can claim freshness.
+        if !matches!(self.range_cache_status, RangeCacheStatus::Cached) {
+            return;
+        }
+        if let Some(range) = &self.current_range {
+            let idx = self.range_applied_indexes.entry(range.clone()).or_insert(0);
+            *idx = u64::max(*idx, apply_index);
+        }
+    }
+
+    fn set_range_applied_term(&mut self, term: u64) {
+        if !matches!(self.range_cache_status, RangeCacheStatus::Cached) {
+            return;
+        }
+        if let Some(range) = &self.current_range {
+            let t = self.range_apply_terms.entry(range.clone()).or_insert(0);
+            *t = u64::max(*t, term);
+        }
+    }
 }
 
 impl Mutable for RangeCacheWriteBatch {
@@ -588,10 +976,38 @@ impl Mutable for RangeCacheWriteBatch {
     }
 
     fn put_cf(&mut self, cf: &str, key: &[u8], val: &[u8]) -> Result<()> {
-        self.process_cf_operation(
-            || RangeCacheWriteBatchEntry::calc_put_entry_size(key, val),
-            || RangeCacheWriteBatchEntry::put_value(cf, key, val),
-        );
+        if !self.current_cached_cfs.contains(cf) {
+            return Ok(());
+        }
+        if self.value_compression.is_enabled() {
+            // Frame (and maybe compress) the value before the entry and its
+            // memory footprint are formed, so the controller, the checksums
+            // and the replay log all see the stored bytes. See the
+            // `compression` module.
+            let (stored, compressed) = maybe_compress_value(val, self.value_compression_threshold);
+            let statistics = self.engine.statistics();
+            self.process_cf_operation(
+                || RangeCacheWriteBatchEntry::calc_put_entry_size(key, &stored),
+                || {
+                    // Recorded here so only entries that are actually
+                    // buffered count into the flow.
+                    statistics.record_ticker(
+                        if compressed {
+                            Tickers::BytesWrittenCompressed
+                        } else {
+                            Tickers::BytesWrittenUncompressed
+                        },
+                        stored.len() as u64,
+                    );
+                    RangeCacheWriteBatchEntry::put_stored_value(cf, key, stored.clone())
+                },
+            );
+        } else {
+            self.process_cf_operation(
+                || RangeCacheWriteBatchEntry::calc_put_entry_size(key, val),
+                || RangeCacheWriteBatchEntry::put_value(cf, key, val),
+            );
+        }
         Ok(())
     }
 
@@ -600,6 +1016,9 @@ impl Mutable for RangeCacheWriteBatch {
     }
 
     fn delete_cf(&mut self, cf: &str, key: &[u8]) -> Result<()> {
+        if !self.current_cached_cfs.contains(cf) {
+            return Ok(());
+        }
         self.process_cf_operation(
             || RangeCacheWriteBatchEntry::calc_delete_entry_size(key),
             || RangeCacheWriteBatchEntry::deletion(cf, key),
@@ -708,6 +1127,101 @@ mod tests {
         assert!(get_value(&sl, &encode_key(b"ccc", 1, ValueType::Value), guard).is_none())
     }
 
+    #[test]
+    fn test_incremental_checksums() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.checksum_verification = true;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.range_cache_status = RangeCacheStatus::Cached;
+        wb.prepare_for_range(r.clone());
+        wb.put(b"aaa", b"v1").unwrap();
+        wb.put(b"bbb", b"v2").unwrap();
+        wb.set_sequence_number(1).unwrap();
+        wb.record_disk_shadow_checksums();
+        wb.write().unwrap();
+
+        // A second batch, so the checksums accumulate across batch
+        // boundaries, with a deletion in the mix.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.range_cache_status = RangeCacheStatus::Cached;
+        wb.prepare_for_range(r.clone());
+        wb.delete(b"aaa").unwrap();
+        wb.set_sequence_number(3).unwrap();
+        wb.record_disk_shadow_checksums();
+        wb.write().unwrap();
+
+        // Identical entries went through both sides, so the checksums match.
+        let checksums = engine.core.read().checksums();
+        let (cache, disk) = checksums.pair(&r).unwrap();
+        assert_eq!(cache, disk);
+        assert_ne!(cache, 0);
+        assert!(checksums.check(&[r.clone()]).is_empty());
+
+        // An entry the cache missed diverges the pair and is flagged by the
+        // next comparison.
+        checksums.update_disk(&r, 42);
+        assert_eq!(checksums.check(&[r.clone()]), vec![r.clone()]);
+    }
+
+    #[test]
+    fn test_apply_provenance() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.provenance_tracking = true;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+
+        // First batch: two entries applied at (index 10, term 5), taking
+        // sequences 1 and 2.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.range_cache_status = RangeCacheStatus::Cached;
+        wb.prepare_for_range(r.clone());
+        wb.put(b"aaa", b"v1").unwrap();
+        wb.put(b"bbb", b"v2").unwrap();
+        wb.set_range_applied_index(10);
+        wb.set_range_applied_term(5);
+        wb.set_sequence_number(1).unwrap();
+        wb.write().unwrap();
+
+        // Second batch at (index 11, term 6), taking sequence 3.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.range_cache_status = RangeCacheStatus::Cached;
+        wb.prepare_for_range(r.clone());
+        wb.delete(b"aaa").unwrap();
+        wb.set_range_applied_index(11);
+        wb.set_range_applied_term(6);
+        wb.set_sequence_number(3).unwrap();
+        wb.write().unwrap();
+
+        // Every sequence is attributed to the batch that produced it.
+        assert_eq!(engine.provenance(&r, 1), Some((10, 5)));
+        assert_eq!(engine.provenance(&r, 2), Some((10, 5)));
+        assert_eq!(engine.provenance(&r, 3), Some((11, 6)));
+        // A sequence no batch produced is not attributed, and neither is a
+        // range that was never written.
+        assert_eq!(engine.provenance(&r, 4), None);
+        let other = CacheRange::new(b"z".to_vec(), b"zz".to_vec());
+        assert_eq!(engine.provenance(&other, 1), None);
+
+        // The records are dropped when the evicted range's data is deleted.
+        engine.evict_range(&r);
+        for _ in 0..20 {
+            if engine.provenance(&r, 1).is_none() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(engine.provenance(&r, 1), None);
+    }
+
     #[test]
     fn test_put_write_clear_delete_put_write() {
         let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
@@ -739,6 +1253,156 @@ mod tests {
         assert!(snapshot.get_value(&b"aaa"[..]).unwrap().is_none())
     }
 
+    #[test]
+    fn test_write_batch_metrics() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().set_safe_point(&r, 10);
+        }
+
+        // The metrics are process-global, so other tests running in parallel
+        // may also contribute to them. Only assert the lower bounds of the
+        // deltas here.
+        let entries_count = WRITE_BATCH_ENTRIES_HISTOGRAM.get_sample_count();
+        let entries_sum = WRITE_BATCH_ENTRIES_HISTOGRAM.get_sample_sum();
+        let bytes_sum = WRITE_BATCH_BYTES_HISTOGRAM.get_sample_sum();
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.range_cache_status = RangeCacheStatus::Cached;
+        wb.prepare_for_range(r.clone());
+        wb.put(b"aaa", b"bbb").unwrap();
+        wb.put(b"ccc", b"ddd").unwrap();
+        wb.put(b"eee", b"fff").unwrap();
+        wb.set_sequence_number(1).unwrap();
+        assert_eq!(wb.write().unwrap(), 1);
+        assert!(WRITE_BATCH_ENTRIES_HISTOGRAM.get_sample_count() >= entries_count + 1);
+        assert!(WRITE_BATCH_ENTRIES_HISTOGRAM.get_sample_sum() - entries_sum >= 3.0);
+        // Each entry accounts for the key, the 8-byte sequence suffix, and the
+        // value.
+        let expected_bytes = (3 * (3 + ENC_KEY_SEQ_LENGTH + 3)) as f64;
+        assert!(WRITE_BATCH_BYTES_HISTOGRAM.get_sample_sum() - bytes_sum >= expected_bytes);
+        // Sequence numbers 1..=3 have been consumed by the three entries.
+        assert_eq!(engine.core.read().max_applied_seqno(), 3);
+
+        // Writes against an uncached range are dropped: they only bump the
+        // skip counter and must not touch the skiplists.
+        let skipped = WRITE_BATCH_SKIPPED_UNCACHED.get();
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(CacheRange::new(b"z".to_vec(), b"z1".to_vec()));
+        wb.put(b"z0", b"val").unwrap();
+        wb.set_sequence_number(4).unwrap();
+        assert_eq!(wb.write().unwrap(), 4);
+        assert!(WRITE_BATCH_SKIPPED_UNCACHED.get() >= skipped + 1);
+        let sl = engine.core.read().engine().data[cf_to_id(CF_DEFAULT)].clone();
+        let guard = &crossbeam::epoch::pin();
+        assert!(get_value(&sl, &encode_key(b"z0", u64::MAX, ValueType::Value), guard).is_none());
+        assert_eq!(engine.core.read().max_applied_seqno(), 3);
+    }
+
+    #[test]
+    fn test_stale_sequence_number_rejected() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().set_safe_point(&r, 10);
+        }
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"aaa", b"bbb").unwrap();
+        assert_eq!(wb.sequence_range(), None);
+        wb.set_sequence_number(5).unwrap();
+        assert_eq!(wb.sequence_range(), Some((5, 6)));
+        assert_eq!(wb.write().unwrap(), 5);
+
+        // A batch with entries cannot reuse or precede committed sequences.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"bbb", b"ccc").unwrap();
+        assert!(wb.set_sequence_number(5).is_err());
+        assert!(wb.set_sequence_number(3).is_err());
+        wb.set_sequence_number(6).unwrap();
+        assert_eq!(wb.write().unwrap(), 6);
+
+        // An empty batch consumes no sequence numbers, so no value can be
+        // stale.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.set_sequence_number(1).unwrap();
+        assert_eq!(wb.write().unwrap(), 1);
+
+        // A stale sequence number bypassing `set_sequence_number` is refused
+        // by the write itself, which evicts the touched range as a safety
+        // net.
+        let misuse = WRITE_BATCH_SEQNO_MISUSE.get();
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"ccc", b"ddd").unwrap();
+        wb.write_impl(2).unwrap_err();
+        assert_eq!(WRITE_BATCH_SEQNO_MISUSE.get(), misuse + 1);
+        assert_eq!(
+            engine.snapshot(r, u64::MAX, 100).unwrap_err(),
+            FailedReason::NotCached
+        );
+    }
+
+    #[test]
+    fn test_sequence_visibility_boundaries() {
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().set_safe_point(&r, 10);
+        }
+
+        // Each entry takes the next sequence in insertion order: k1 at 10, k2
+        // at 11.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"k1", b"v1").unwrap();
+        wb.put(b"k2", b"v1").unwrap();
+        wb.set_sequence_number(10).unwrap();
+        assert_eq!(wb.sequence_range(), Some((10, 12)));
+        assert_eq!(wb.write().unwrap(), 10);
+
+        // k1 at 12, the delete of k2 at 13.
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"k1", b"v2").unwrap();
+        wb.delete(b"k2").unwrap();
+        wb.set_sequence_number(12).unwrap();
+        assert_eq!(wb.sequence_range(), Some((12, 14)));
+        assert_eq!(wb.write().unwrap(), 12);
+
+        let get = |seq: u64, key: &[u8]| {
+            engine
+                .snapshot(r.clone(), u64::MAX, seq)
+                .unwrap()
+                .get_value(key)
+                .unwrap()
+                .map(|v| v.to_vec())
+        };
+        // The visibility boundary at each sequence number is exact.
+        assert_eq!(get(9, b"k1"), None);
+        assert_eq!(get(10, b"k1"), Some(b"v1".to_vec()));
+        assert_eq!(get(10, b"k2"), None);
+        assert_eq!(get(11, b"k1"), Some(b"v1".to_vec()));
+        assert_eq!(get(11, b"k2"), Some(b"v1".to_vec()));
+        assert_eq!(get(12, b"k1"), Some(b"v2".to_vec()));
+        assert_eq!(get(12, b"k2"), Some(b"v1".to_vec()));
+        assert_eq!(get(13, b"k1"), Some(b"v2".to_vec()));
+        assert_eq!(get(13, b"k2"), None);
+    }
+
     #[test]
     fn test_prepare_for_apply() {
         let path = Builder::new()
@@ -963,10 +1627,122 @@ mod tests {
     }
 
     #[test]
-    fn test_write_batch_with_config_change() {
+    fn test_write_batch_with_write_pressure() {
         let mut config = RangeCacheEngineConfig::default();
+        config.enabled = true;
+        // The reactive hard limit must not interfere here, only the proactive
+        // watermarks should.
         config.soft_limit_threshold = Some(ReadableSize(u64::MAX));
         config.hard_limit_threshold = Some(ReadableSize(u64::MAX));
+        config.write_pressure_soft_watermark = Some(ReadableSize(500));
+        config.write_pressure_hard_watermark = Some(ReadableSize(1000));
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        // Park background tasks so that the scheduled eviction selection stays
+        // observable and deletions run when we say so.
+        engine.enable_deterministic_background_tasks();
+        let range1 = CacheRange::new(b"kk00".to_vec(), b"kk10".to_vec());
+        let range2 = CacheRange::new(b"kk10".to_vec(), b"kk20".to_vec());
+        let range3 = CacheRange::new(b"kk20".to_vec(), b"kk30".to_vec());
+        for r in [&range1, &range2, &range3] {
+            engine.new_range(r.clone());
+            {
+                let mut core = engine.core.write();
+                core.mut_range_manager().set_safe_point(r, 10);
+            }
+            let _ = engine.snapshot(r.clone(), 1000, 1000).unwrap();
+        }
+        // The pinned range keeps caching writes under pressure.
+        engine.pin_range(&range3, true).unwrap();
+
+        let memory_controller = engine.memory_controller();
+        let skipped_before = WRITE_BATCH_SKIPPED_BY_PRESSURE.get();
+        let val1: Vec<u8> = (0..150).map(|_| 0).collect();
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(range1.clone());
+        // Each of these entries requires
+        // 4(key) + 8(sequence number) + 150(value) + 16(2 Arc<MemoryController>) = 178
+        wb.put(b"kk01", &val1).unwrap();
+        wb.put(b"kk02", &val1).unwrap();
+        wb.prepare_for_range(range2.clone());
+        // Still below the soft watermark (356 < 500), so this one is buffered
+        // and brings the usage to 534.
+        wb.put(b"kk11", &val1).unwrap();
+        // Now above the soft watermark: the entry is not buffered and range2
+        // must be evicted, while the pending loads are paused.
+        wb.put(b"kk12", &val1).unwrap();
+        assert_eq!(skipped_before + 1, WRITE_BATCH_SKIPPED_BY_PRESSURE.get());
+        assert_eq!(534, memory_controller.mem_usage());
+        assert_eq!(WritePressure::Soft, memory_controller.write_pressure());
+        assert!(engine.bg_worker_manager().load_scheduler().is_paused());
+
+        // The pinned range is exempted and keeps buffering. 712 after the
+        // first put, then 712 + (4 + 8 + 500 + 16) = 1240, above the hard
+        // watermark.
+        let val2: Vec<u8> = (0..500).map(|_| 2).collect();
+        wb.prepare_for_range(range3.clone());
+        wb.put(b"kk21", &val1).unwrap();
+        wb.put(b"kk22", &val2).unwrap();
+        assert_eq!(WritePressure::Hard, memory_controller.write_pressure());
+
+        // Above the hard watermark an eviction selection pass is scheduled
+        // (parked by the deterministic mode) and unpinned ranges are dropped,
+        // but the overshoot stays bounded: skipped entries acquire nothing.
+        wb.prepare_for_range(range1.clone());
+        wb.put(b"kk03", &val1).unwrap();
+        assert_eq!(skipped_before + 2, WRITE_BATCH_SKIPPED_BY_PRESSURE.get());
+        assert!(memory_controller.memory_checking());
+        assert_eq!(1240, memory_controller.mem_usage());
+
+        wb.write_impl(1000).unwrap();
+        // Both unpinned ranges that had writes skipped are evicted, the
+        // pinned one still serves reads.
+        assert_eq!(
+            engine.snapshot(range1.clone(), 1000, 1010).unwrap_err(),
+            FailedReason::NotCached
+        );
+        assert_eq!(
+            engine.snapshot(range2.clone(), 1000, 1010).unwrap_err(),
+            FailedReason::NotCached
+        );
+        let snap3 = engine.snapshot(range3.clone(), 1000, 1010).unwrap();
+        assert_eq!(snap3.get_value(b"kk21").unwrap().unwrap(), &val1);
+        assert_eq!(snap3.get_value(b"kk22").unwrap().unwrap(), &val2);
+
+        // Free the memory: run the parked deletions and drop the pinned range
+        // as well, the pressure must fall back to `None`.
+        drop(snap3);
+        engine.pin_range(&range3, false).unwrap();
+        engine.evict_range(&range3);
+        engine.run_background_tasks_until_idle();
+        flush_epoch();
+        assert_eq!(0, memory_controller.mem_usage());
+        assert_eq!(WritePressure::None, memory_controller.write_pressure());
+
+        // The write path recovers: new writes are cached again.
+        let range4 = CacheRange::new(b"kk30".to_vec(), b"kk40".to_vec());
+        engine.new_range(range4.clone());
+        {
+            let mut core = engine.core.write();
+            core.mut_range_manager().set_safe_point(&range4, 10);
+        }
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(range4.clone());
+        wb.put(b"kk31", &val1).unwrap();
+        assert_eq!(skipped_before + 2, WRITE_BATCH_SKIPPED_BY_PRESSURE.get());
+        wb.write_impl(1010).unwrap();
+        let snap4 = engine.snapshot(range4, 1000, 1020).unwrap();
+        assert_eq!(snap4.get_value(b"kk31").unwrap().unwrap(), &val1);
+    }
+
+    #[test]
+    fn test_write_batch_with_config_change() {
+        let mut config = RangeCacheEngineConfig::default();
+        // Effectively unlimited, but the soft limit must stay below the hard
+        // one for the online updates below to pass validation.
+        config.soft_limit_threshold = Some(ReadableSize(u64::MAX - 1));
+        config.hard_limit_threshold = Some(ReadableSize(u64::MAX));
         config.enabled = true;
         let config = Arc::new(VersionTrack::new(config));
         let engine =
@@ -1023,4 +1799,106 @@ mod tests {
         let snap2 = engine.snapshot(r2.clone(), 1000, 1000).unwrap();
         assert_eq!(snap2.get_value(b"kk11").unwrap().unwrap(), &val1);
     }
+
+    #[test]
+    fn test_value_compression_accounting() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.value_compression = ValueCompression::Lz4;
+        config.value_compression_threshold = ReadableSize::kb(1);
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+
+        let big: Vec<u8> = vec![0; 8192];
+        let mut wb = RangeCacheWriteBatch::from(&engine);
+        wb.prepare_for_range(r.clone());
+        wb.put(b"big", &big).unwrap();
+        wb.put(b"small", b"v").unwrap();
+        wb.set_sequence_number(10).unwrap();
+        wb.write().unwrap();
+
+        let statistics = engine.statistics();
+        let compressed = statistics.get_ticker_count(Tickers::BytesWrittenCompressed);
+        let uncompressed = statistics.get_ticker_count(Tickers::BytesWrittenUncompressed);
+        // 8192 zero bytes compress to a small fraction of their raw size.
+        assert!(compressed > 0 && compressed < big.len() as u64);
+        // "v" is below the threshold and stays raw behind a one-byte header.
+        assert_eq!(uncompressed, 2);
+        // The memory controller accounts the stored (compressed) size, so the
+        // usage stays far below the raw value size.
+        assert!(engine.memory_controller().mem_usage() < big.len());
+
+        let snapshot = engine.snapshot(r.clone(), u64::MAX, u64::MAX).unwrap();
+        assert_eq!(
+            snapshot.get_value(b"big").unwrap().unwrap(),
+            big.as_slice()
+        );
+        assert_eq!(snapshot.get_value(b"small").unwrap().unwrap(), b"v".as_slice());
+    }
+
+    #[test]
+    fn test_value_dedup_accounting() {
+        let mut config = RangeCacheEngineConfig::config_for_test();
+        config.value_dedup_table_limit = 1024;
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(config),
+        )));
+        let r = CacheRange::new(b"".to_vec(), b"z".to_vec());
+        engine.new_range(r.clone());
+
+        // Forty versions flipping between two 512-byte payloads, as a row
+        // whose status flag is rewritten back and forth would produce.
+        let payload_a: Vec<u8> = vec![1; 512];
+        let payload_b: Vec<u8> = vec![2; 512];
+        let versions: u64 = 40;
+        for i in 0..versions {
+            let mut wb = RangeCacheWriteBatch::from(&engine);
+            wb.prepare_for_range(r.clone());
+            let val = if i % 2 == 0 { &payload_a } else { &payload_b };
+            wb.put(format!("k{:02}", i).as_bytes(), val).unwrap();
+            wb.set_sequence_number(i + 1).unwrap();
+            wb.write().unwrap();
+        }
+
+        // Only the two distinct payloads are kept; the usage stays near
+        // single-copy instead of the 20KB of raw value bytes the versions
+        // carried.
+        let skiplist_engine = engine.core.read().engine();
+        assert_eq!(skiplist_engine.value_dedup.len(), 2);
+        let usage = engine.memory_controller().mem_usage();
+        assert!(
+            usage < 4 * payload_a.len(),
+            "dedup left {} bytes accounted",
+            usage
+        );
+
+        // Reads are unaffected: every version is served correctly, and the
+        // sequence visibility boundaries still hold.
+        let snapshot = engine.snapshot(r.clone(), u64::MAX, versions).unwrap();
+        for i in 0..versions {
+            let expected = if i % 2 == 0 { &payload_a } else { &payload_b };
+            assert_eq!(
+                snapshot
+                    .get_value(format!("k{:02}", i).as_bytes())
+                    .unwrap()
+                    .unwrap(),
+                expected.as_slice()
+            );
+        }
+        let half = engine.snapshot(r.clone(), u64::MAX, versions / 2).unwrap();
+        assert!(half.get_value(b"k19").unwrap().is_some());
+        assert!(half.get_value(b"k20").unwrap().is_none());
+        drop(half);
+        drop(snapshot);
+
+        // Evicting the range drops every reference: the table empties and
+        // all the accounted memory is released.
+        engine.evict_range(&r);
+        flush_epoch();
+        wait_evict_done(&engine);
+        assert_eq!(engine.memory_controller().mem_usage(), 0);
+        assert_eq!(skiplist_engine.value_dedup.len(), 0);
+    }
 }
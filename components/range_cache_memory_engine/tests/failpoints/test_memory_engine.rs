@@ -8,13 +8,13 @@ use std::{
 use crossbeam::epoch;
 use engine_rocks::util::new_engine;
 use engine_traits::{
-    CacheRange, Mutable, RangeCacheEngine, WriteBatch, WriteBatchExt, CF_DEFAULT, CF_LOCK,
-    CF_WRITE, DATA_CFS,
+    CacheRange, Mutable, RangeCacheEngine, SyncMutable, WriteBatch, WriteBatchExt, CF_DEFAULT,
+    CF_LOCK, CF_WRITE, DATA_CFS,
 };
 use range_cache_memory_engine::{
     decode_key, encode_key_for_boundary_without_mvcc, encoding_for_filter, test_util::put_data,
-    BackgroundTask, InternalBytes, InternalKey, RangeCacheEngineConfig, RangeCacheEngineContext,
-    RangeCacheMemoryEngine, SkiplistHandle, ValueType,
+    wait_and_find_divergence, BackgroundTask, InternalBytes, InternalKey, RangeCacheEngineConfig,
+    RangeCacheEngineContext, RangeCacheMemoryEngine, SkiplistHandle, ValueType, WaitError,
 };
 use tempfile::Builder;
 use tikv_util::config::{ReadableDuration, ReadableSize, VersionTrack};
@@ -497,3 +497,51 @@ fn test_concurrency_between_delete_range_and_write_to_memory() {
 
     let _ = handle.join();
 }
+
+#[test]
+fn test_wait_for_sequence_barrier() {
+    let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+        VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+    )));
+    let range = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+    engine.new_range(range.clone());
+
+    // Mirror the write to a disk engine, as an apply would.
+    let path = Builder::new()
+        .prefix("test_wait_for_sequence_barrier")
+        .tempdir()
+        .unwrap();
+    let rocks_engine = new_engine(path.path().to_str().unwrap(), DATA_CFS).unwrap();
+    rocks_engine.put(b"k01", b"val").unwrap();
+
+    // Hold the cache batch back right before it is consumed.
+    fail::cfg("on_write_impl", "pause").unwrap();
+    let mut wb = engine.write_batch();
+    wb.prepare_for_range(range.clone());
+    wb.put(b"k01", b"val").unwrap();
+    wb.set_range_applied_index(1);
+    wb.set_sequence_number(5).unwrap();
+    let writer = std::thread::spawn(move || {
+        wb.write().unwrap();
+    });
+
+    // With the batch still in flight the barrier must time out.
+    assert_eq!(
+        engine.wait_for_sequence(&range, 5, Duration::from_millis(200)),
+        Err(WaitError::TimedOut)
+    );
+
+    // Release the batch: the barrier completes and the cache matches the disk
+    // contents at that sequence.
+    fail::remove("on_write_impl");
+    writer.join().unwrap();
+    assert_eq!(
+        engine.wait_for_sequence(&range, 5, Duration::from_secs(5)),
+        Ok(())
+    );
+    assert!(
+        wait_and_find_divergence(&engine, &rocks_engine, &range, 5, Duration::from_secs(5))
+            .unwrap()
+            .is_none()
+    );
+}
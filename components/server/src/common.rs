@@ -710,6 +710,10 @@ pub trait KvEngineBuilder: KvEngine {
         pd_client: Option<Arc<RpcClient>>,
         region_info_provider: Option<Arc<dyn RegionInfoProvider>>,
     ) -> Self;
+
+    /// Returns the range cache engine when the built engine has one, so
+    /// that e.g. the status server can report its cached ranges.
+    fn range_cache_engine_handle(&self) -> Option<RangeCacheMemoryEngine>;
 }
 
 impl KvEngineBuilder for RocksEngine {
@@ -721,6 +725,10 @@ impl KvEngineBuilder for RocksEngine {
     ) -> Self {
         disk_engine
     }
+
+    fn range_cache_engine_handle(&self) -> Option<RangeCacheMemoryEngine> {
+        None
+    }
 }
 
 impl KvEngineBuilder for HybridEngine<RocksEngine, RangeCacheMemoryEngine> {
@@ -745,6 +753,10 @@ impl KvEngineBuilder for HybridEngine<RocksEngine, RangeCacheMemoryEngine> {
         }
         HybridEngine::new(disk_engine, memory_engine)
     }
+
+    fn range_cache_engine_handle(&self) -> Option<RangeCacheMemoryEngine> {
+        Some(self.range_cache_engine().clone())
+    }
 }
 
 pub trait ConfiguredRaftEngine: RaftEngine {
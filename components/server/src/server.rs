@@ -280,6 +280,8 @@ where
     engines: Option<TikvEngines<EK, ER>>,
     kv_statistics: Option<Arc<RocksStatistics>>,
     range_cache_engine_statistics: Option<Arc<RangeCacheMemoryEngineStatistics>>,
+    // `Some` iff the kv engine is a hybrid engine, see `KvEngineBuilder`.
+    range_cache_engine: Option<RangeCacheMemoryEngine>,
     raft_statistics: Option<Arc<RocksStatistics>>,
     servers: Option<Servers<EK, ER, F>>,
     region_info_accessor: RegionInfoAccessor,
@@ -490,6 +492,7 @@ where
             engines: None,
             kv_statistics: None,
             range_cache_engine_statistics: None,
+            range_cache_engine: None,
             raft_statistics: None,
             servers: None,
             region_info_accessor,
@@ -759,6 +762,7 @@ where
             .enable_receive_tablet_snapshot(
                 self.core.config.raft_store.enable_v2_compatible_learner,
             )
+            .ingest_temp_dir(&self.core.config.raft_store.cleanup_ingest_temp_dir)
             .build(snap_path);
 
         // Create coprocessor endpoint.
@@ -1577,6 +1581,9 @@ where
                     return;
                 }
             };
+            if let Some(range_cache_engine) = self.range_cache_engine.clone() {
+                status_server.set_range_cache_engine(range_cache_engine);
+            }
             // Start the status server.
             if let Err(e) = status_server.start(self.core.config.server.status_addr.clone()) {
                 error_unknown!(%e; "failed to bind addr for status service");
@@ -1700,6 +1707,7 @@ where
         let range_cache_config_manager = RangeCacheConfigManager(range_cache_engine_config);
         self.kv_statistics = Some(factory.rocks_statistics());
         self.range_cache_engine_statistics = Some(range_cache_engine_statistics);
+        self.range_cache_engine = kv_engine.range_cache_engine_handle();
         let engines = Engines::new(kv_engine, raft_engine);
 
         let cfg_controller = self.cfg_controller.as_mut().unwrap();
@@ -1023,6 +1023,7 @@ where
                     DATA_MIN_KEY.to_vec(),
                     DATA_MAX_KEY.to_vec(),
                 )),
+                required_apply_index: None,
             };
             self.get_cf_with_snap_ctx(CF_DEFAULT, key, true, ctx)
         }
@@ -1038,6 +1039,7 @@ where
                     DATA_MIN_KEY.to_vec(),
                     DATA_MAX_KEY.to_vec(),
                 )),
+                required_apply_index: None,
             };
             self.get_cf_with_snap_ctx(cf, key, true, ctx)
         }
@@ -1053,6 +1055,7 @@ where
                     DATA_MIN_KEY.to_vec(),
                     DATA_MAX_KEY.to_vec(),
                 )),
+                required_apply_index: None,
             };
             self.get_cf_with_snap_ctx(CF_DEFAULT, key, true, ctx)
         }
@@ -278,6 +278,7 @@ impl<EK: KvEngine> Simulator<EK> for NodeCluster<EK> {
                 .max_per_file_size(cfg.raft_store.max_snapshot_file_raw_size.0)
                 .enable_multi_snapshot_files(true)
                 .enable_receive_tablet_snapshot(cfg.raft_store.enable_v2_compatible_learner)
+                .ingest_temp_dir(&cfg.raft_store.cleanup_ingest_temp_dir)
                 .build(tmp.path().to_str().unwrap());
             (snap_mgr, Some(tmp))
         } else {
@@ -477,6 +477,7 @@ impl<EK: KvEngineWithRocks> ServerCluster<EK> {
             .max_per_file_size(cfg.raft_store.max_snapshot_file_raw_size.0)
             .enable_multi_snapshot_files(true)
             .enable_receive_tablet_snapshot(cfg.raft_store.enable_v2_compatible_learner)
+            .ingest_temp_dir(&cfg.raft_store.cleanup_ingest_temp_dir)
             .build(tmp_str);
         self.snap_mgrs.insert(node_id, snap_mgr.clone());
         let server_cfg = Arc::new(VersionTrack::new(cfg.server.clone()));
@@ -481,6 +481,14 @@ pub trait Snapshot: Sync + Send + Clone {
     /// in `opts`
     fn get_cf_opt(&self, opts: ReadOptions, cf: CfName, key: &Key) -> Result<Option<Value>>;
 
+    /// Get the values associated with `keys` in `cf` column family. The
+    /// result holds one entry per key, in the same order. The default
+    /// implementation issues one `get_cf` per key; snapshots backed by an
+    /// engine with a batched read should override it.
+    fn multi_get_cf(&self, cf: CfName, keys: &[&Key]) -> Result<Vec<Option<Value>>> {
+        keys.iter().map(|key| self.get_cf(cf, key)).collect()
+    }
+
     fn iter(&self, cf: CfName, iter_opt: IterOptions) -> Result<Self::Iter>;
 
     // The minimum key this snapshot can retrieve.
@@ -11,7 +11,7 @@ use kvproto::{
     raft_serverpb::RaftMessage,
 };
 use raft::SnapshotStatus;
-use raftstore::store::region_meta::RegionMeta;
+use raftstore::store::{region_meta::RegionMeta, SnapApplyHistoryEntry};
 
 use crate::Result;
 
@@ -59,6 +59,15 @@ pub trait RaftExtension: Clone + Send {
         Box::pin(async move { Err(box_err!("query region is not supported")) })
     }
 
+    /// Get the recent snapshot-apply history of the given region, newest
+    /// entry last.
+    fn query_snap_apply_history(
+        &self,
+        _region_id: u64,
+    ) -> BoxFuture<'static, Result<Vec<SnapApplyHistoryEntry>>> {
+        Box::pin(async move { Err(box_err!("query snap apply history is not supported")) })
+    }
+
     /// Ask the raft group to do a consistency check.
     fn check_consistency(&self, _region_id: u64) -> BoxFuture<'static, Result<()>> {
         Box::pin(async move { Err(box_err!("consistency check is not supported")) })
@@ -91,6 +91,15 @@ impl<S: Snapshot> EngineSnapshot for RegionSnapshot<S> {
         Ok(v.map(|v| v.to_vec()))
     }
 
+    fn multi_get_cf(&self, cf: CfName, keys: &[&Key]) -> kv::Result<Vec<Option<Value>>> {
+        fail_point!("raftkv_snapshot_get_cf", |_| Err(box_err!(
+            "injected error for get_cf"
+        )));
+        let encoded: Vec<&[u8]> = keys.iter().map(|key| key.as_encoded().as_slice()).collect();
+        let values = box_try!(self.multi_get_value_cf(cf, &encoded));
+        Ok(values.into_iter().map(|v| v.map(|v| v.to_vec())).collect())
+    }
+
     fn iter(&self, cf: CfName, iter_opt: IterOptions) -> kv::Result<Self::Iter> {
         fail_point!("raftkv_snapshot_iter", |_| Err(box_err!(
             "injected error for iter_cf"
@@ -141,6 +141,10 @@ pub struct RequestMetrics {
     pub read_index_confirm_wait_nanos: u64,
     pub read_pool_schedule_wait_nanos: u64,
     pub local_read: bool,
+    // Why the range cache engine snapshot was not used for this read, if it
+    // was not. Surfaced in the slow log to explain reads falling back to the
+    // disk engine.
+    pub range_cache_snapshot_failed_reason: Option<&'static str>,
     pub block_cache_hit_count: u64,
     pub block_read_count: u64,
     pub block_read_byte: u64,
@@ -589,6 +589,14 @@ bitflags! {
         const FLASHBACK = 0b00001000;
         /// Indicates the relevant tablet has been flushed, and we can propose split now.
         const PRE_FLUSH_FINISHED = 0b00010000;
+        /// Indicates the `flag_data` field carries a trace id (a `u64` encoded
+        /// with `NumberEncoder`) that should be attached to the logs of the
+        /// command. Only set for admin commands, whose `flag_data` is unused
+        /// otherwise.
+        const TRACE_ID_IN_FLAG_DATA = 0b00100000;
+        /// Indicates a BatchSplit request only previews the derived regions
+        /// and must not be proposed.
+        const SPLIT_DRY_RUN = 0b01000000;
     }
 }
 
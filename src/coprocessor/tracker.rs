@@ -304,6 +304,8 @@ impl<E: Engine> Tracker<E> {
                         => tracker.metrics.internal_key_skipped_count,
                     "perf_stats.internal_delete_skipped_count"
                         => tracker.metrics.deleted_key_skipped_count,
+                    "range_cache_snapshot_failed_reason"
+                        => tracker.metrics.range_cache_snapshot_failed_reason,
                 )
             });
         }
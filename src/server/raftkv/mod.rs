@@ -652,6 +652,7 @@ where
             ctx.start_ts.map(|ts| SnapshotContext {
                 read_ts: ts.into_inner(),
                 range: None,
+                required_apply_index: None,
             })
         } else {
             None
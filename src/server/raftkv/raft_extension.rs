@@ -16,7 +16,7 @@ use raftstore::{
     router::RaftStoreRouter,
     store::{
         region_meta::{RaftStateRole, RegionMeta},
-        CasualMessage,
+        ApplyHistoryCallback, CasualMessage, SnapApplyHistoryEntry, StoreMsg,
     },
 };
 use tikv_util::future::paired_future_callback;
@@ -152,6 +152,21 @@ where
         })
     }
 
+    fn query_snap_apply_history(
+        &self,
+        region_id: u64,
+    ) -> BoxFuture<'static, kv::Result<Vec<SnapApplyHistoryEntry>>> {
+        let (cb, rx) = paired_future_callback();
+        let res = self.router.send_store_msg(StoreMsg::QuerySnapApplyHistory {
+            region_id,
+            cb: ApplyHistoryCallback(cb),
+        });
+        Box::pin(async move {
+            res?;
+            Ok(box_try!(rx.await))
+        })
+    }
+
     /// Ask the raft group to do a consistency check.
     fn check_consistency(&self, region_id: u64) -> BoxFuture<'static, kv::Result<()>> {
         let region = self.query_region(region_id);
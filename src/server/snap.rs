@@ -326,6 +326,12 @@ impl RecvSnapContext {
                 info!("snapshot file already exists, skip receiving"; "snap_key" => %key, "file" => p);
                 None
             } else {
+                // Reject the stream up front if the disk cannot hold the
+                // snapshot, so the sender backs off instead of failing the
+                // transfer halfway through.
+                if let Err(e) = snap_mgr.reserve_space(&key, s.total_size()) {
+                    return Err(box_err!("{} failed to reserve disk space: {:?}", key, e));
+                }
                 Some(s)
             }
         };
@@ -44,6 +44,7 @@ use openssl::{
 use pin_project::pin_project;
 use profile::*;
 use prometheus::TEXT_FORMAT;
+use range_cache_memory_engine::{CachedRangeState, RangeCacheMemoryEngine};
 use regex::Regex;
 use resource_control::ResourceGroupManager;
 use security::{self, SecurityConfig};
@@ -95,6 +96,7 @@ pub struct StatusServer<R> {
     security_config: Arc<SecurityConfig>,
     resource_manager: Option<Arc<ResourceGroupManager>>,
     grpc_service_mgr: GrpcServiceManager,
+    range_cache_engine: Option<RangeCacheMemoryEngine>,
 }
 
 impl<R> StatusServer<R>
@@ -130,9 +132,17 @@ where
             security_config,
             resource_manager,
             grpc_service_mgr,
+            range_cache_engine: None,
         })
     }
 
+    /// Sets the range cache engine whose cached ranges are served on
+    /// `/in_memory_engine/regions` and inlined into the region page. Without
+    /// it the endpoints report that the engine is not enabled.
+    pub fn set_range_cache_engine(&mut self, range_cache_engine: RangeCacheMemoryEngine) {
+        self.range_cache_engine = Some(range_cache_engine);
+    }
+
     fn dump_heap_prof_to_resp(req: Request<Body>) -> hyper::Result<Response<Body>> {
         let query = req.uri().query().unwrap_or("");
         let query_pairs: HashMap<_, _> = url::form_urlencoded::parse(query.as_bytes()).collect();
@@ -500,7 +510,87 @@ where
         ))
     }
 
-    pub async fn dump_region_meta(req: Request<Body>, router: R) -> hyper::Result<Response<Body>> {
+    /// Lists the ranges the in-memory engine tracks, in a deterministic
+    /// order. The optional query parameters `state` (a kebab-case
+    /// [`CachedRangeState`] name), `offset` and `limit` filter and paginate
+    /// the result.
+    fn dump_in_memory_engine_regions(
+        req: Request<Body>,
+        range_cache_engine: Option<&RangeCacheMemoryEngine>,
+    ) -> hyper::Result<Response<Body>> {
+        let Some(engine) = range_cache_engine else {
+            return Ok(make_response(
+                StatusCode::NOT_FOUND,
+                "in-memory engine is not enabled",
+            ));
+        };
+        let query = req.uri().query().unwrap_or("");
+        let query_pairs: HashMap<_, _> = url::form_urlencoded::parse(query.as_bytes()).collect();
+        let state = match query_pairs.get("state") {
+            Some(name) => match CachedRangeState::from_name(name) {
+                Some(state) => Some(state),
+                None => {
+                    return Ok(make_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("invalid state filter: {}", name),
+                    ));
+                }
+            },
+            None => None,
+        };
+        let offset = match query_pairs.get("offset").map(|v| v.parse::<usize>()) {
+            Some(Ok(offset)) => offset,
+            Some(Err(err)) => {
+                return Ok(make_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid offset: {}", err),
+                ));
+            }
+            None => 0,
+        };
+        let limit = match query_pairs.get("limit").map(|v| v.parse::<usize>()) {
+            Some(Ok(limit)) => limit,
+            Some(Err(err)) => {
+                return Ok(make_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid limit: {}", err),
+                ));
+            }
+            None => usize::MAX,
+        };
+        let ranges: Vec<_> = engine
+            .ranges_overview()
+            .into_iter()
+            .filter(|r| state.map_or(true, |s| r.state == s))
+            .skip(offset)
+            .take(limit)
+            .collect();
+        let body = match serde_json::to_vec(&ranges) {
+            Ok(body) => body,
+            Err(err) => {
+                return Ok(make_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("fails to json: {}", err),
+                ));
+            }
+        };
+        match Response::builder()
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(body))
+        {
+            Ok(resp) => Ok(resp),
+            Err(err) => Ok(make_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("fails to build response: {}", err),
+            )),
+        }
+    }
+
+    pub async fn dump_region_meta(
+        req: Request<Body>,
+        router: R,
+        range_cache_engine: Option<&RangeCacheMemoryEngine>,
+    ) -> hyper::Result<Response<Body>> {
         lazy_static! {
             static ref REGION: Regex = Regex::new(r"/region/(?P<id>\d+)").unwrap();
         }
@@ -539,6 +629,48 @@ where
             }
         };
 
+        // The raw region boundaries, kept before `meta` is turned into a
+        // json value below.
+        let region_start = meta.region_state.start_key.clone();
+        let region_end = meta.region_state.end_key.clone();
+        let mut meta = match serde_json::to_value(&meta) {
+            Ok(meta) => meta,
+            Err(err) => {
+                return Ok(make_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("fails to json: {}", err),
+                ));
+            }
+        };
+        // The apply history is diagnostic detail, failing to fetch it (e.g.
+        // on engines that do not support it) should not fail the region dump.
+        if let Ok(history) = router.query_snap_apply_history(id).await {
+            if let Value::Object(map) = &mut meta {
+                map.insert(
+                    "snap_apply_history".to_owned(),
+                    serde_json::to_value(&history).unwrap_or(Value::Null),
+                );
+            }
+        }
+        // On servers with the in-memory engine enabled, inline the cached
+        // ranges overlapping the region. The cached ranges use data-encoded
+        // keys, and hex encoding preserves the byte order, so the overlap
+        // check can work on the encoded boundaries directly.
+        if let Some(engine) = range_cache_engine {
+            let start = hex::encode_upper(keys::data_key(&region_start));
+            let end = hex::encode_upper(keys::data_end_key(&region_end));
+            let ranges: Vec<_> = engine
+                .ranges_overview()
+                .into_iter()
+                .filter(|r| r.start < end && r.end > start)
+                .collect();
+            if let Value::Object(map) = &mut meta {
+                map.insert(
+                    "in_memory_engine".to_owned(),
+                    serde_json::to_value(&ranges).unwrap_or(Value::Null),
+                );
+            }
+        }
         let body = match serde_json::to_vec(&meta) {
             Ok(body) => body,
             Err(err) => {
@@ -613,6 +745,7 @@ where
         let router = self.router.clone();
         let resource_manager = self.resource_manager.clone();
         let grpc_service_mgr = self.grpc_service_mgr.clone();
+        let range_cache_engine = self.range_cache_engine.clone();
         // Start to serve.
         let server = builder.serve(make_service_fn(move |conn: &C| {
             let x509 = conn.get_x509();
@@ -621,6 +754,7 @@ where
             let router = router.clone();
             let resource_manager = resource_manager.clone();
             let grpc_service_mgr = grpc_service_mgr.clone();
+            let range_cache_engine = range_cache_engine.clone();
             async move {
                 // Create a status service.
                 Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
@@ -630,6 +764,7 @@ where
                     let router = router.clone();
                     let resource_manager = resource_manager.clone();
                     let grpc_service_mgr = grpc_service_mgr.clone();
+                    let range_cache_engine = range_cache_engine.clone();
                     async move {
                         let path = req.uri().path().to_owned();
                         let method = req.method().to_owned();
@@ -718,8 +853,15 @@ where
                                 info!("debug fail point API finish");
                                 Ok(Response::default())
                             }
+                            (Method::GET, "/in_memory_engine/regions") => {
+                                Self::dump_in_memory_engine_regions(
+                                    req,
+                                    range_cache_engine.as_ref(),
+                                )
+                            }
                             (Method::GET, path) if path.starts_with("/region") => {
-                                Self::dump_region_meta(req, router).await
+                                Self::dump_region_meta(req, router, range_cache_engine.as_ref())
+                                    .await
                             }
                             (Method::PUT, path) if path.starts_with("/log-level") => {
                                 Self::change_log_level(req).await
@@ -1886,4 +2028,70 @@ mod tests {
             status_server.stop();
         }
     }
+
+    #[test]
+    fn test_dump_in_memory_engine_regions() {
+        use engine_traits::{CacheRange, RangeCacheEngine};
+        use range_cache_memory_engine::{
+            CachedRangeOverview, CachedRangeState, RangeCacheEngineConfig,
+            RangeCacheEngineContext, RangeCacheMemoryEngine,
+        };
+        use tikv_util::config::VersionTrack;
+
+        fn get(
+            engine: Option<&RangeCacheMemoryEngine>,
+            path_and_query: &str,
+        ) -> (StatusCode, Vec<u8>) {
+            let req = Request::builder()
+                .method(Method::GET)
+                .uri(path_and_query)
+                .body(Body::empty())
+                .unwrap();
+            let resp =
+                StatusServer::<MockRouter>::dump_in_memory_engine_regions(req, engine).unwrap();
+            let status = resp.status();
+            let body = block_on(hyper::body::to_bytes(resp.into_body())).unwrap();
+            (status, body.to_vec())
+        }
+
+        // Without the engine the endpoint reports that it is not enabled.
+        let (status, _) = get(None, "/in_memory_engine/regions");
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let engine = RangeCacheMemoryEngine::new(RangeCacheEngineContext::new_for_tests(Arc::new(
+            VersionTrack::new(RangeCacheEngineConfig::config_for_test()),
+        )));
+        let r1 = CacheRange::new(b"k00".to_vec(), b"k10".to_vec());
+        let r2 = CacheRange::new(b"k10".to_vec(), b"k20".to_vec());
+        engine.new_range(r1);
+        engine.new_range(r2.clone());
+        // The snapshot pins the evicted range in the historical state so
+        // that the background deletion cannot race with the assertions.
+        let snap = engine.snapshot(r2.clone(), 10, u64::MAX).unwrap();
+        engine.evict_range(&r2);
+
+        let (status, body) = get(Some(&engine), "/in_memory_engine/regions");
+        assert_eq!(status, StatusCode::OK);
+        let all: Vec<CachedRangeOverview> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].state, CachedRangeState::Active);
+
+        let (status, body) = get(Some(&engine), "/in_memory_engine/regions?state=historical");
+        assert_eq!(status, StatusCode::OK);
+        let historical: Vec<CachedRangeOverview> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(historical.len(), 1);
+        assert_eq!(historical[0].snapshots, 1);
+
+        let (status, body) = get(Some(&engine), "/in_memory_engine/regions?offset=1&limit=1");
+        assert_eq!(status, StatusCode::OK);
+        let page: Vec<CachedRangeOverview> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].state, CachedRangeState::BeingDeleted);
+
+        let (status, _) = get(Some(&engine), "/in_memory_engine/regions?state=nope");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get(Some(&engine), "/in_memory_engine/regions?limit=x");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        drop(snap);
+    }
 }
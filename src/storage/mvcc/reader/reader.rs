@@ -1,7 +1,7 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
 // #[PerformanceCriticalPath]
-use std::ops::Bound;
+use std::{collections::HashMap, ops::Bound};
 
 use engine_traits::{CF_DEFAULT, CF_LOCK, CF_WRITE};
 use kvproto::{
@@ -68,6 +68,11 @@ impl<S: EngineSnapshot> SnapshotReader<S> {
         self.reader.load_lock(key)
     }
 
+    #[inline(always)]
+    pub fn prefetch_locks(&mut self, keys: &[&Key]) -> Result<()> {
+        self.reader.prefetch_locks(keys)
+    }
+
     #[inline(always)]
     pub fn key_exist(&mut self, key: &Key, ts: TimeStamp) -> Result<bool> {
         Ok(self
@@ -132,6 +137,11 @@ pub struct MvccReader<S: EngineSnapshot> {
     lock_cursor: Option<Cursor<S::Iter>>,
     write_cursor: Option<Cursor<S::Iter>>,
 
+    // Locks batch-loaded by `prefetch_locks`, consulted by `load_lock` before
+    // falling back to a point get. `None` until a prefetch happens, so
+    // single-key commands pay nothing for it.
+    prefetched_locks: Option<HashMap<Key, Option<Lock>>>,
+
     lower_bound: Option<Key>,
     upper_bound: Option<Key>,
 
@@ -164,6 +174,7 @@ impl<S: EngineSnapshot> MvccReader<S> {
             data_cursor: None,
             lock_cursor: None,
             write_cursor: None,
+            prefetched_locks: None,
             lower_bound: None,
             upper_bound: None,
             hint_min_ts: None,
@@ -183,6 +194,7 @@ impl<S: EngineSnapshot> MvccReader<S> {
             data_cursor: None,
             lock_cursor: None,
             write_cursor: None,
+            prefetched_locks: None,
             lower_bound: None,
             upper_bound: None,
             hint_min_ts: None,
@@ -231,11 +243,42 @@ impl<S: EngineSnapshot> MvccReader<S> {
         }
     }
 
+    /// Batch-loads the locks of `keys` from the lock cf with one engine-level
+    /// multi-get, so that later `load_lock` calls on those keys are answered
+    /// from memory instead of issuing one point get each. The snapshot is
+    /// immutable, so the prefetched results cannot go stale within a command,
+    /// and `load_lock` still consults the in-memory pessimistic lock table
+    /// first, so prefetching never changes what a read observes.
+    pub fn prefetch_locks(&mut self, keys: &[&Key]) -> Result<()> {
+        if keys.len() <= 1 {
+            return Ok(());
+        }
+        self.statistics.lock.get += keys.len();
+        let values = self.snapshot.multi_get_cf(CF_LOCK, keys)?;
+        let prefetched = self.prefetched_locks.get_or_insert_with(Default::default);
+        for (key, value) in keys.iter().zip(values) {
+            let lock = match value {
+                Some(v) => Some(Lock::parse(&v)?),
+                None => None,
+            };
+            prefetched.insert((*key).clone(), lock);
+        }
+        Ok(())
+    }
+
     pub fn load_lock(&mut self, key: &Key) -> Result<Option<Lock>> {
         if let Some(pessimistic_lock) = self.load_in_memory_pessimistic_lock(key)? {
             return Ok(Some(pessimistic_lock));
         }
 
+        if let Some(prefetched) = self.prefetched_locks.as_ref()
+            && let Some(lock) = prefetched.get(key)
+        {
+            // The get of this key was already accounted when it was
+            // prefetched.
+            return Ok(lock.clone());
+        }
+
         if self.scan_mode.is_some() {
             self.create_lock_cursor_if_not_exist()?;
         }
@@ -1939,6 +1982,78 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_prefetch_locks() {
+        let path = tempfile::Builder::new()
+            .prefix("_test_storage_mvcc_reader_prefetch_locks")
+            .tempdir()
+            .unwrap();
+        let path = path.path().to_str().unwrap();
+        let region = make_region(1, vec![], vec![]);
+        let db = open_db(path, true);
+        let mut engine = RegionEngine::new(&db, &region);
+
+        engine.prewrite(
+            Mutation::make_put(Key::from_raw(b"k1"), b"v1".to_vec()),
+            b"k1",
+            5,
+        );
+        engine.prewrite(
+            Mutation::make_put(Key::from_raw(b"k3"), b"v3".to_vec()),
+            b"k1",
+            5,
+        );
+        // k5's lock is written and rolled back, so it leaves a deletion in
+        // the lock cf; k0 and k9 were never locked at all.
+        engine.prewrite(
+            Mutation::make_put(Key::from_raw(b"k5"), b"v5".to_vec()),
+            b"k5",
+            7,
+        );
+        engine.rollback(b"k5", 7);
+
+        let keys: Vec<Key> = [b"k0", b"k1", b"k3", b"k5", b"k9"]
+            .into_iter()
+            .map(|k| Key::from_raw(k))
+            .collect();
+        let key_refs: Vec<&Key> = keys.iter().collect();
+
+        // Collect the expected locks with plain per-key reads.
+        let mut reader = MvccReader::new(engine.snapshot(), None, false);
+        let expected: Vec<_> = keys.iter().map(|k| reader.load_lock(k).unwrap()).collect();
+        assert_eq!(reader.statistics.lock.get, keys.len());
+
+        // A prefetched reader returns the same locks while accounting each
+        // key exactly once, at prefetch time.
+        let mut reader = MvccReader::new(engine.snapshot(), None, false);
+        reader.prefetch_locks(&key_refs).unwrap();
+        assert_eq!(reader.statistics.lock.get, keys.len());
+        for (k, expected) in keys.iter().zip(&expected) {
+            assert_eq!(&reader.load_lock(k).unwrap(), expected);
+        }
+        assert_eq!(reader.statistics.lock.get, keys.len());
+
+        // Prefetching an unsorted batch resolves the same way.
+        let mut reader = MvccReader::new(engine.snapshot(), None, false);
+        let mut unsorted = key_refs.clone();
+        unsorted.reverse();
+        reader.prefetch_locks(&unsorted).unwrap();
+        for (k, expected) in keys.iter().zip(&expected) {
+            assert_eq!(&reader.load_lock(k).unwrap(), expected);
+        }
+
+        // Keys outside the prefetched batch still fall back to a point get.
+        engine.prewrite(
+            Mutation::make_put(Key::from_raw(b"k7"), b"v7".to_vec()),
+            b"k7",
+            9,
+        );
+        let mut reader = MvccReader::new(engine.snapshot(), None, false);
+        reader.prefetch_locks(&key_refs).unwrap();
+        assert!(reader.load_lock(&Key::from_raw(b"k7")).unwrap().is_some());
+        assert_eq!(reader.statistics.lock.get, keys.len() + 1);
+    }
+
     #[test]
     fn test_scan_latest_user_keys() {
         let path = tempfile::Builder::new()
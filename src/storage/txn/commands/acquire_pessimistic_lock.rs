@@ -98,6 +98,14 @@ impl<S: Snapshot, L: LockManager> WriteCommand<S, L> for AcquirePessimisticLock
         let mut encountered_locks = vec![];
         let need_old_value = context.extra_op == ExtraOp::ReadOldValue;
         let mut old_values = OldValues::default();
+        // Batch-load the existing locks of all keys up front, so the per-key
+        // conflict checks inside `acquire_pessimistic_lock` are answered from
+        // the prefetched map instead of issuing one lock cf point get each.
+        // See `MvccReader::prefetch_locks`.
+        if keys.len() > 1 {
+            let lock_keys: Vec<&Key> = keys.iter().map(|(k, _)| k).collect();
+            reader.prefetch_locks(&lock_keys)?;
+        }
         for (k, should_not_exist) in keys {
             match acquire_pessimistic_lock(
                 &mut txn,
@@ -32,6 +32,7 @@ fn test_basic_put_get() {
     let snap_ctx = SnapshotContext {
         read_ts: 1001,
         range: None,
+        required_apply_index: None,
     };
     let (tx, rx) = sync_channel(1);
     fail::cfg_callback("on_range_cache_get_value", move || {
@@ -126,6 +127,7 @@ fn test_load() {
         let snap_ctx = SnapshotContext {
             read_ts: 20,
             range: None,
+            required_apply_index: None,
         };
 
         for i in 0..30 {
@@ -207,6 +209,7 @@ fn test_write_batch_cache_during_load() {
     let snap_ctx = SnapshotContext {
         read_ts: 20,
         range: None,
+        required_apply_index: None,
     };
 
     for i in 20..30 {
@@ -325,6 +328,7 @@ fn test_load_with_split() {
     let snap_ctx = SnapshotContext {
         read_ts: 20,
         range: None,
+        required_apply_index: None,
     };
 
     for i in 0..30 {
@@ -415,6 +419,7 @@ fn test_load_with_split2() {
     let snap_ctx = SnapshotContext {
         read_ts: 20,
         range: None,
+        required_apply_index: None,
     };
 
     let _ = cluster
@@ -523,6 +528,7 @@ fn test_load_with_eviction() {
     let snap_ctx = SnapshotContext {
         read_ts: u64::MAX,
         range: None,
+        required_apply_index: None,
     };
     let val = cluster
         .get_cf_with_snap_ctx(CF_DEFAULT, b"k01", false, snap_ctx.clone())
@@ -16,6 +16,9 @@ use grpcio::{ChannelBuilder, Environment};
 use kvproto::{kvrpcpb::*, tikvpb::TikvClient};
 use pd_client::PdClient;
 use raft::eraftpb::MessageType;
+use raftstore::store::metrics::{
+    PRE_TRANSFER_LEADER_INFLIGHT_GAUGE, PRE_TRANSFER_LEADER_THROTTLE_COUNTER,
+};
 use test_raftstore::*;
 use test_raftstore_macro::test_case;
 use tikv::storage::Snapshot;
@@ -581,6 +584,60 @@ fn test_when_warmup_fail_and_its_timeout_is_short() {
     cluster.must_transfer_leader(1, new_peer(2, 2));
 }
 
+/// Transfer leader requests beyond `max_inflight_pre_transfer_leader` should
+/// be rejected with a busy error instead of queueing more warmups, and should
+/// proceed again once the in-flight pre-transfer is gone.
+#[test_case(test_raftstore::new_node_cluster)]
+fn test_pre_transfer_leader_cap() {
+    let mut cluster = new_cluster(0, 3);
+    cluster.cfg.raft_store.max_entry_cache_warmup_duration = ReadableDuration::secs(1000);
+    cluster.cfg.raft_store.max_inflight_pre_transfer_leader = 1;
+    prevent_from_gc_raft_log(&mut cluster.cfg);
+    run_cluster_for_test_warmup_entry_cache!(cluster);
+
+    // Create a second region whose leader is also on store 1.
+    let region = cluster.get_region(b"0");
+    cluster.must_split(&region, b"0");
+    let left = cluster.get_region(b"");
+    let r2 = left.get_id();
+    let left_peer_on_store1 = find_peer(&left, 1).unwrap().clone();
+    cluster.must_transfer_leader(r2, left_peer_on_store1);
+
+    // Park the warmup so the pre-transfer of region 1 stays in flight and
+    // holds the only slot.
+    fail::cfg("worker_async_fetch_raft_log", "pause").unwrap();
+    cluster.transfer_leader(1, new_peer(2, 2));
+    sleep_ms(50);
+    assert_eq!(cluster.leader_of_region(1).unwrap().get_id(), 1);
+
+    // A transfer of another region on the same store must be rejected with a
+    // busy error, and the in-flight gauge must not exceed the cap.
+    let rejected = PRE_TRANSFER_LEADER_THROTTLE_COUNTER.get();
+    let left_peer_on_store2 = find_peer(&left, 2).unwrap().clone();
+    let epoch = cluster.get_region_epoch(r2);
+    let req = new_admin_request(
+        r2,
+        &epoch,
+        new_transfer_leader_cmd(left_peer_on_store2.clone()),
+    );
+    let resp = cluster
+        .call_command_on_leader(req, Duration::from_secs(3))
+        .unwrap();
+    assert!(
+        resp.get_header().get_error().has_server_is_busy(),
+        "{:?}",
+        resp
+    );
+    assert_eq!(PRE_TRANSFER_LEADER_THROTTLE_COUNTER.get(), rejected + 1);
+    assert_eq!(PRE_TRANSFER_LEADER_INFLIGHT_GAUGE.get(), 1);
+
+    // Unpark the warmup. The pending transfer of region 1 finishes and frees
+    // the slot, after which new transfers proceed.
+    fail::remove("worker_async_fetch_raft_log");
+    cluster.must_transfer_leader(1, new_peer(2, 2));
+    cluster.must_transfer_leader(r2, left_peer_on_store2);
+}
+
 /// The follower should ack the msg when the cache is warmed up.
 /// Besides, the cache should be kept for a period of time.
 #[test_case(test_raftstore::new_node_cluster)]
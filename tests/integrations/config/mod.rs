@@ -217,7 +217,15 @@ fn test_serde_custom_tikv_config() {
         gc_peer_check_interval: ReadableDuration::days(1),
         leader_transfer_max_log_lag: 123,
         snap_apply_batch_size: ReadableSize::mb(12),
+        snap_apply_batch_size_lock: Some(ReadableSize::mb(24)),
         snap_apply_copy_symlink: true,
+        snap_apply_ingest_concurrency: 2,
+        snap_apply_pipelined_verification: false,
+        snap_applied_notify_batch: 16,
+        snap_apply_time_budget: ReadableDuration::secs(1),
+        snap_receiver_backlog_threshold: ReadableSize::mb(512),
+        snap_apply_urgent_bypass_engine_check: true,
+        snap_apply_pressure_high_watermark: ReadableSize::gb(10),
         region_worker_tick_interval: ReadableDuration::millis(1000),
         clean_stale_ranges_tick: 10,
         lock_cf_compact_interval: ReadableDuration::minutes(12),
@@ -230,7 +238,10 @@ fn test_serde_custom_tikv_config() {
         allow_remove_leader: true,
         merge_max_log_gap: 3,
         merge_check_tick_interval: ReadableDuration::secs(11),
+        max_concurrent_merge_catch_up: 3,
         use_delete_range: true,
+        cleanup_ingest_temp_dir: "/var/ingest-temp".to_owned(),
+        cleanup_ingest_min_free_space: ReadableSize::gb(2),
         snap_generator_pool_size: 2,
         cleanup_import_sst_interval: ReadableDuration::minutes(12),
         local_read_batch_size: 33,
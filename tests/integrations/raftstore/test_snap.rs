@@ -24,7 +24,10 @@ use kvproto::{
 use protobuf::Message as M1;
 use raft::eraftpb::{Message, MessageType, Snapshot};
 use raftstore::{
-    coprocessor::{ApplySnapshotObserver, BoxApplySnapshotObserver, Coprocessor, CoprocessorHost},
+    coprocessor::{
+        ApplyCleanupInfo, ApplySnapshotObserver, BoxApplySnapshotObserver, Coprocessor,
+        CoprocessorHost,
+    },
     store::{snap::TABLET_SNAPSHOT_VERSION, *},
     Result,
 };
@@ -867,6 +870,7 @@ impl ApplySnapshotObserver for MockApplySnapshotObserver {
         peer_id: u64,
         _: &raftstore::store::SnapKey,
         snap: Option<&raftstore::store::Snapshot>,
+        _: Option<&ApplyCleanupInfo>,
     ) {
         let tablet_path = snap.unwrap().tablet_snap_path().as_ref().unwrap().clone();
         self.tablet_snap_paths
@@ -881,6 +885,7 @@ impl ApplySnapshotObserver for MockApplySnapshotObserver {
         peer_id: u64,
         _: &raftstore::store::SnapKey,
         snap: Option<&raftstore::store::Snapshot>,
+        _: Option<&ApplyCleanupInfo>,
     ) {
         let tablet_path = snap.unwrap().tablet_snap_path().as_ref().unwrap().clone();
         match self.tablet_snap_paths.lock().unwrap().entry(peer_id) {